@@ -102,7 +102,7 @@ check_python_toolchain
 
 # Check if the correct number of arguments is provided
 if [ "$#" -lt 1 ]; then
-  echo "Usage: $0 {run|build|pkg|pub-test} [debug|trace]"
+  echo "Usage: $0 {run|build|pkg|stubs|pub-test} [debug|trace]"
   exit 1
 fi
 
@@ -153,15 +153,20 @@ case $command in
     ;;
   "pkg")
     echo "Packaging..."
+    cargo run -p stubgen -- --check || exit 1
     maturin build --release
     ;;
+  "stubs")
+    echo "Generating the Python type stubs..."
+    cargo run -p stubgen
+    ;;
   "pub-test")
     echo "Publishing to test.pypi.org..."
     maturin publish --repository testpypi
     ;;
   *)
     echo "Invalid command: $command"
-    echo "Usage: $0 {run|build|pkg} [debug|trace]"
+    echo "Usage: $0 {run|build|pkg|stubs} [debug|trace]"
     exit 1
     ;;
 esac
@@ -1,9 +1,9 @@
-__all__ = ['App', 'Window', 'LightType', 'Color', 'IllumModel']
+__all__ = ['App', 'AppConfig', 'Window', 'LightType', 'Color', 'IllumModel']
 
 
 import enum
 import inspect
-from bk7084.bkfw import Window, Color, IllumModel
+from bk7084.bkfw import AppConfig, Window, Color, IllumModel
 from bk7084.bkfw import PyAppState
 from bk7084.bkfw import run_main_loop
 
@@ -17,9 +17,16 @@ class LightType(enum.Enum):
 
 
 class App(PyAppState):
-    """The main application class."""
-    def __new__(cls):
-        return super().__new__(cls)
+    """The main application class.
+
+    Usage:
+
+    config = AppConfig().with_shadows(True).with_msaa(4)
+    with App(config) as app:
+        app.run()
+    """
+    def __new__(cls, config: AppConfig = None):
+        return super().__new__(cls, config)
 
     def event(self, *args):
         """Decorator for attaching event handlers to the window.
@@ -69,6 +76,7 @@ class App(PyAppState):
             return light, sphere
         return light
 
-    def run(self, builder: Window):
-        """Starts the main loop."""
+    def run(self, builder: Window = None):
+        """Starts the main loop, opening the window of the configuration of
+        the application if no `builder` is given."""
         run_main_loop(self, builder)
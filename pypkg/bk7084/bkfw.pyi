@@ -0,0 +1,2211 @@
+# Type stubs of the bkfw module, generated by stubgen. Do not edit.
+
+from typing import Any, Callable, ClassVar
+
+import numpy as np
+import numpy.typing as npt
+
+
+def run_main_loop(app: PyAppState, builder: Window | None = ...) -> None:
+    """Runs the main loop of the application, opening a window configured by
+    `builder` or by the configuration of the application if `None`.
+    """
+
+
+class Window:
+    def __new__(cls) -> Window:
+        ...
+    def set_size(self, width: int = ..., height: int = ...) -> None:
+        """Set the size of the window."""
+    def set_position(self, x: int = ..., y: int = ...) -> None:
+        """Set the position of the window."""
+    def set_resizable(self, resizable: bool) -> None:
+        """Set whether the window is resizable."""
+    def set_title(self, title: str) -> None:
+        """Set the title of the window."""
+    def set_fullscreen(self, fullscreen: bool) -> None:
+        """Set whether the window is fullscreen."""
+    def set_maximized(self, maximized: bool) -> None:
+        """Set whether the window is maximized."""
+    def set_transparent(self, transparent: bool) -> None:
+        """Set whether the window is transparent."""
+    def set_decorations(self, decorations: bool) -> None:
+        """Set whether the window has decorations."""
+
+
+class PyAppState:
+    def __new__(cls, config: AppConfig | None = ...) -> PyAppState:
+        ...
+    def __enter__(self) -> PyAppState:
+        ...
+    def __exit__(self, _exc_type: Any | None, _exc_value: Any | None, _traceback: Any | None) -> bool:
+        """Shuts the application down when leaving the `with` block, without
+        suppressing the exception raised in it if any.
+        """
+    def register_event_type(self, event_type: str) -> None:
+        """Register an event type."""
+    def register_event_types(self, event_types: list[str]) -> None:
+        """Register multiple event types."""
+    def attach_event_handler(self, event_type: str, listener: Any) -> None:
+        """Attach a handler to an event type."""
+    def detach_event_handler(self, event_type: str, listener: Any) -> None:
+        """Detach a handler from an event type."""
+    def post_event(self, name: str, payload: Any | None = ...) -> None:
+        """Posts an event to be dispatched to its handlers on the main thread,
+        with the payload as argument if any, e.g. for a background thread to
+        signal the application that an asset is loaded.
+
+        The event wakes up the main loop; events posted while it is not
+        running are dispatched at its next frame.
+        """
+    def request_exit(self) -> None:
+        """Asks the main loop to exit after the current frame. The "on_exit"
+        event is dispatched before the resources of the application are
+        released.
+        """
+    def enable_text_input(self, enabled: bool) -> None:
+        """Allows the input method editor (IME) of the window, to compose the
+        text of the languages needing it. The composed text is dispatched to
+        the "on_text_input" handlers like the text typed on the keyboard,
+        and the text being composed to the "on_text_preedit" handlers.
+
+        The IME is disallowed by default, as it may take the keys used to
+        control the application.
+        """
+    def get_clipboard_text(self) -> str | None:
+        """Returns the text of the system clipboard, `None` if it holds no
+        text.
+        """
+    def set_clipboard_text(self, text: str) -> None:
+        """Sets the text of the system clipboard."""
+    def run_async(self, builder: Window | None = ...) -> None:
+        """Opens the window and starts the main loop without blocking, so that
+        the scene can still be modified from a notebook.
+
+        The main loop is stepped by `poll`, which is called regularly on the
+        running asyncio event loop (as in Jupyter) if any. Otherwise `poll`
+        must be called by the user.
+        """
+    def poll(self, timeout: float | None = ...) -> bool:
+        """Processes the pending events of the main loop started by `run_async`
+        and renders a frame if one is due, waiting at most `timeout` seconds
+        for new events. Returns false once the main loop exited.
+        """
+    def _poll_scheduled(self) -> None:
+        """Polls the main loop and schedules the next poll while it runs."""
+    def delta_time(self) -> float:
+        """Get the real frame time in seconds, not affected by `set_time_scale`."""
+    def set_time_scale(self, scale: float) -> None:
+        """Slow down (below 1) or speed up (above 1) the time passed to the
+        update handlers, the physics and the animations of the scene. The
+        camera controls keep following the real time.
+        """
+    @property
+    def time_scale(self) -> float:
+        """Factor applied to the frame time passed to the update handlers."""
+    def pause(self) -> None:
+        """Pause the time passed to the update handlers, the physics and the
+        animations of the scene, which then receive a frame time of zero.
+        """
+    def resume(self) -> None:
+        """Resume the time paused by `pause` or `step_frame`."""
+    @property
+    def is_paused(self) -> bool:
+        """Whether the time is paused."""
+    def step_frame(self, frames: int = ..., duration: float | None = ...) -> None:
+        """Pause the time and advance it by the given number of frames, one per
+        rendered frame, each lasting `duration` seconds (1/60 by default)
+        whatever the time scale.
+        """
+    def get_adapter_info(self) -> AdapterInfo:
+        """Get the name, the backend and the limits of the GPU adapter the
+        application renders with.
+        """
+    def get_transform(self, entity: PyEntity) -> npt.NDArray[np.float32]:
+        """Get the transform of an entity."""
+    def update_materials(self, entity: PyEntity, materials: list[Material]) -> bool:
+        """Re-uploads the materials of the mesh of an entity after they have
+        been modified. All entities sharing the same materials are affected.
+
+        Returns false if the entity has no mesh or if the number of materials
+        differs from the number of materials of the mesh.
+        """
+    def pin_mesh(self, entity: PyEntity, pinned: bool = ...) -> bool:
+        """Keep the mesh of an entity uploaded once no entity uses it anymore,
+        e.g. to spawn it again later, or let it be removed once unused. The
+        meshes are otherwise removed with the last entity using them.
+
+        Returns false if the entity has no mesh.
+        """
+    def capture_prefab(self, entity: PyEntity) -> Prefab | None:
+        """Capture an entity and its descendants, with their meshes, materials,
+        lights and transforms, into a prefab to be instantiated with
+        `instantiate`.
+
+        The meshes of the prefab stay uploaded even once no entity uses them,
+        see `pin_mesh`. Returns `None` if the entity doesn't exist.
+        """
+    def instantiate(self, prefab: Prefab, transform: npt.NDArray[np.float32] | None = ..., parent: PyEntity | None = ...) -> PyEntity:
+        """Spawn a copy of the entities of the prefab, sharing their meshes and
+        materials with the other copies. The root of the copy is placed at the
+        given 4x4 transform relative to its parent, or at the transform of the
+        captured entity if `None`.
+
+        Returns the root entity of the copy.
+        """
+    def enable_journal(self, enabled: bool = ...) -> None:
+        """Start or stop recording the changes of the transforms, visibility,
+        materials, lights, names and tags of the entities of the active scene,
+        and the spawned and despawned entities, to undo them with `undo`. The
+        recorded changes are forgotten when stopping.
+        """
+    def begin_transaction(self) -> None:
+        """Group the following changes of the entities into a single transaction,
+        undone and redone at once, until `end_transaction` is called.
+        Transactions may be nested, the outermost one grouping all the
+        changes.
+        """
+    def end_transaction(self) -> None:
+        """End the transaction started by the matching `begin_transaction`."""
+    def undo(self) -> bool:
+        """Revert the last recorded transaction of the active scene, see
+        `enable_journal`. Returns false if there is nothing to undo.
+        """
+    def redo(self) -> bool:
+        """Apply again the last undone transaction of the active scene. Returns
+        false if there is nothing to redo.
+        """
+    def create_scene(self) -> Scene:
+        """Create a new empty scene sharing the meshes, materials and textures of
+        the application with the other scenes. The scene is inactive until
+        `set_active_scene` is called with it.
+        """
+    def active_scene(self) -> Scene:
+        """Get the scene the application renders and spawns entities in."""
+    def set_active_scene(self, scene: Scene) -> bool:
+        """Make the scene the one the application renders and spawns entities
+        in. The entities of the previously active scene are kept, without
+        uploading their meshes again when it is activated later.
+
+        Returns false if the scene doesn't exist.
+        """
+    def find_entity(self, name: str) -> PyEntity | None:
+        """Find the entity with the given name.
+
+        Returns `None` if no entity has been given this name. If several
+        entities share the same name, the first one named is returned.
+        """
+    def find_entities(self, prefix: str) -> list[PyEntity]:
+        """Find all entities whose name starts with the given prefix."""
+    def get_entities_by_tag(self, tag: str) -> list[PyEntity]:
+        """Get all entities with the given tag."""
+    def hide_by_tag(self, tag: str) -> None:
+        """Hide all entities with the given tag."""
+    def show_by_tag(self, tag: str) -> None:
+        """Show all entities with the given tag."""
+    def delete_by_tag(self, tag: str) -> None:
+        """Delete all entities with the given tag, together with their children."""
+    def enable_backface_culling(self, enabled: bool) -> None:
+        """Set the backface culling state."""
+    def enable_shadows(self, enabled: bool) -> None:
+        """Set the shadows rendering state."""
+    def enable_wireframe(self, enabled: bool) -> None:
+        """Set the wireframe rendering state."""
+    def show_debug_gizmos(self, shown: bool) -> None:
+        """Shows or hides the debug gizmos: an arrow per directional light, a
+        cross per point light, the frustum of the cameras other than the main
+        one and the volume covered by each shadow map.
+        """
+    def enable_lighting(self, enabled: bool) -> None:
+        ...
+    def enable_occlusion_culling(self, enabled: bool) -> None:
+        """Set whether the objects hidden behind other objects are skipped. The
+        visibility is determined from the previous frames, so that hidden
+        objects may appear a few frames late when uncovered.
+        """
+    def enable_gpu_culling(self, enabled: bool) -> None:
+        """Set whether the objects outside the view are skipped by a compute
+        pass on the GPU, instead of being drawn and clipped. Only the objects
+        drawn with the default shader are culled, and only if the GPU supports
+        indirect draws.
+        """
+    def enable_depth_prepass(self, enabled: bool) -> None:
+        """Set whether the depth of the objects is drawn before shading them, so
+        that each pixel is shaded only once. This speeds up dense scenes, but
+        transparent objects hide the objects behind them.
+        """
+    def enable_hot_reload(self, enabled: bool) -> None:
+        """Set whether mesh and texture files modified on disk are reloaded."""
+    def set_vsync(self, enabled: bool) -> None:
+        """Set whether the frames are presented in sync with the display refresh
+        rate. When disabled, frames are presented as soon as they are ready.
+        """
+    def set_target_fps(self, fps: float | None = ...) -> None:
+        """Set the maximum number of frames rendered per second, the frame rate
+        is uncapped if `None` or not positive. Rates below 0.01 are raised to
+        it.
+        """
+    def set_shader_directory(self, path: str | None = ...) -> None:
+        """Set the directory from which `blph.wgsl` and `shadow.wgsl` are loaded,
+        or restore the embedded shaders if `None`. The shaders are reloaded
+        when modified, falling back to the embedded ones if they fail to
+        compile.
+        """
+    def set_environment_map(self, faces: list[str] | None = ...) -> None:
+        """Set the cube map reflected by the materials from the image files of
+        its 6 faces, in the order of +X, -X, +Y, -Y, +Z, -Z, or remove it if
+        `None`.
+        """
+    def register_sampler(self, name: str, config: SamplerConfig) -> None:
+        """Register the settings of a sampler under a name, which textures
+        request with `Texture.set_sampler`. Replaces the settings previously
+        registered under this name, including the ones described by names
+        like `clamp_nearest`.
+        """
+    def set_texture_budget(self, megabytes: float | None = ...) -> None:
+        """Limit the GPU memory of the textures to the given number of
+        megabytes, or remove the limit if `None`. The least recently used
+        textures of the entities which are not visible are evicted beyond it,
+        and loaded again from their files once visible.
+        """
+    def texture_memory_stats(self) -> TextureMemoryStats:
+        """Returns the GPU memory used by the textures and the evictions of the
+        last frame.
+        """
+    def update_shadow_map_ortho_proj(self, max_dist: float) -> None:
+        ...
+    def show_sunlight_overlay(self, position_index: int | None = ...) -> list[tuple[PyEntity, float]]:
+        """Tints the visible mesh entities by their exposure to the sun at the
+        given position of the sunlight scores, from blue in the shadow to
+        yellow in the sun, or removes the tints if `None`.
+
+        The exposure of an entity is the fraction of the area of its sampled
+        triangles lit by the sun. Returns the exposure of each tinted entity.
+        """
+    def set_sunlight_ground(self, up: npt.NDArray[np.float32], height: float = ...) -> None:
+        """Sets the ground plane the sun travels over when computing the
+        sunlight scores.
+
+        * `up` - The normal of the ground, pointing to the sky.
+        * `height` - The height of the ground along `up`.
+        """
+    def sunlight_coverage(self) -> tuple[npt.NDArray[np.float32], npt.NDArray[np.float32], float]:
+        """Returns the region covered by the last sunlight scores, as the min
+        and max corners of the bounds of the scene, and the largest size in
+        world units of a pixel of the occlusion maps.
+        """
+    def compute_viewshed(self, observer: npt.NDArray[np.float32], targets: list[PyEntity], resolution: int = ..., up: npt.NDArray[np.float32] | None = ...) -> list[tuple[PyEntity, float, float]]:
+        """Computes how much of each target is seen from the observer, by
+        casting a ray through each texel of a cube map around it.
+
+        * `observer` - The position the targets are seen from.
+        * `targets` - The entities seen, including their descendants.
+        * `resolution` - The number of texels along a face of the cube map.
+        * `up` - If given, only the hemisphere above the observer is seen.
+
+        Returns for each target the fraction of it not hidden by the other
+        meshes, and the solid angle in steradians of its visible part.
+        """
+    def compute_sunlight_scores(self) -> list[float]:
+        ...
+    def compute_sunlight_scores_async(self) -> int:
+        """Starts computing the sunlight scores without blocking, see
+        `compute_sunlight_scores`.
+
+        Returns the id of the computation. The `on_scores_ready` event is
+        dispatched with the scores and this id once they are computed.
+        """
+    def create_camera(self, pos: npt.NDArray[np.float32], look_at: npt.NDArray[np.float32], fov_v: float, near: float = ..., far: float = ..., background: Color = ...) -> PyEntity:
+        """Create a camera
+
+        # Arguments
+
+        * `pos` - The position of the camera.
+        * `target` - The target of the camera.
+        * `fov` - The field of view of the camera in degrees.
+        """
+    def set_fixed_aspect(self, aspect: float | None = ...) -> None:
+        """Fixes the aspect ratio of the image of the main camera, letterboxing
+        it in the window, or makes it follow the window if `None`.
+        """
+    def render_camera_to_texture(self, camera: PyEntity, name: str, width: int = ..., height: int = ...) -> None:
+        """Makes the camera render the scene into a texture each frame, e.g. to
+        show it on a screen in the scene or on a quad in front of the main
+        camera.
+
+        Materials show the image of the camera by using `name` as the path of
+        one of their textures; the texture must be created before the
+        materials using it are uploaded. Calling it again with the same name
+        resizes the texture, possibly moving it to another camera.
+        """
+    def stop_camera_render_to_texture(self, camera: PyEntity) -> None:
+        """Stops the camera rendering into its texture, which keeps showing the
+        last image.
+        """
+    def show_minimap(self, size: int = ..., world_extent: float = ...) -> None:
+        """Shows a top-down map of the scene in the top right corner of the
+        window, `size` pixels wide and covering `world_extent` units around
+        the main camera, whose position and heading are marked at its center.
+
+        The map is rendered by an orthographic camera into the texture named
+        `"minimap"`; calling it again resizes the map.
+        """
+    def hide_minimap(self) -> None:
+        """Hides the map shown by `show_minimap`."""
+    def set_gravity(self, gravity: npt.NDArray[np.float32]) -> None:
+        """Sets the gravity of the physics simulation."""
+    def host_session(self, port: int) -> None:
+        """Hosts a shared session on the port, e.g. for a classroom: the other
+        instances joining it with `join_session` see the meshes added with
+        `add_mesh` by all the instances, their transforms, visibility and
+        material overrides. The host relays the changes of each instance to
+        the other ones; the meshes are exchanged as obj files, their textures
+        must be found at the same paths on every machine.
+        """
+    def join_session(self, addr: str) -> None:
+        """Joins the shared session hosted at the address, e.g.
+        `"192.168.1.10:7084"`, see `host_session`.
+        """
+    def leave_session(self) -> None:
+        """Leaves the shared session, or stops hosting it. The replicated
+        entities stay in the scene.
+        """
+    @property
+    def in_session(self) -> bool:
+        """Whether the application hosts or joined a shared session."""
+    def listen_osc(self, port: int) -> None:
+        """Listens for the OSC messages sent to the UDP port, e.g. by a control
+        surface or a live-coding environment. Each message is dispatched as
+        an `on_osc` event with its address and the list of its arguments,
+        after updating the entities bound to its address with `bind_osc`.
+        """
+    def stop_osc(self) -> None:
+        """Stops listening for OSC messages, the bindings are dropped."""
+    def bind_osc(self, address: str, entity: PyEntity, target: OscTarget, scale: float = ..., offset: float = ...) -> None:
+        """Binds the OSC address, e.g. `"/fader/1"`, to a property of the
+        entity: the first numeric argument of each message sent to the
+        address, multiplied by `scale` and added to `offset`, sets the
+        property. Several properties may be bound to the same address.
+        """
+    def unbind_osc(self, address: str | None = ...) -> None:
+        """Removes the bindings of the OSC address, or all of them if `None`."""
+    def enable_debug_ui(self, enabled: bool) -> None:
+        """Shows or hides the debug UI drawn over the window: an inspector of
+        the scene and of the rendering parameters, and the controls added
+        with `add_slider`, `add_checkbox` and `add_button`.
+        """
+    def add_slider(self, label: str, value: float, min: float, max: float, callback: Any) -> None:
+        """Adds a slider to the debug UI, calling `callback(value)` when it is
+        moved.
+        """
+    def add_checkbox(self, label: str, value: bool, callback: Any) -> None:
+        """Adds a checkbox to the debug UI, calling `callback(value)` when it is
+        toggled.
+        """
+    def add_button(self, label: str, callback: Any) -> None:
+        """Adds a button to the debug UI, calling `callback()` when it is
+        clicked.
+        """
+    def clear_debug_controls(self) -> None:
+        """Removes the controls added to the debug UI."""
+    def raycast(self, origin: npt.NDArray[np.float32], direction: npt.NDArray[np.float32], max_dist: float = ...) -> tuple[PyEntity, npt.NDArray[np.float32], npt.NDArray[np.float32]] | None:
+        """Casts a ray from `origin` along `direction` against the triangles of
+        the visible meshes, up to `max_dist`.
+
+        Returns the entity hit first, the hit point and the normal of the
+        surface there, or `None` if nothing is hit.
+        """
+    def screen_to_world_ray(self, x: float, y: float) -> tuple[npt.NDArray[np.float32], npt.NDArray[np.float32]] | None:
+        """Returns the ray from the main camera through a position of the
+        window, in pixels from its top-left corner like
+        `Input.cursor_position`, as its origin on the near plane and its unit
+        direction.
+
+        Returns `None` if there is no main camera or before the first frame.
+        """
+    def world_to_screen(self, point: npt.NDArray[np.float32]) -> npt.NDArray[np.float32] | None:
+        """Returns the position in pixels from the top-left corner of the window
+        where a point in world space is seen by the main camera. The position
+        may be outside the window.
+
+        Returns `None` if the point is behind the camera, if there is no main
+        camera or before the first frame.
+        """
+    def screen_to_ground(self, x: float, y: float, plane_height: float = ...) -> npt.NDArray[np.float32] | None:
+        """Returns the point of the horizontal plane at `plane_height` seen by
+        the main camera at a position of the window, in pixels from its
+        top-left corner, e.g. to place objects on the ground under the
+        cursor.
+
+        Returns `None` if the plane is not seen there, if there is no main
+        camera or before the first frame.
+        """
+    def begin_placement(self, mesh: Mesh, snap: float = ..., height: float = ...) -> None:
+        """Starts placing a copy of the mesh on the ground with the mouse,
+        cancelling the ongoing placement if any.
+
+        A translucent ghost of the mesh follows the point of the ground under
+        the cursor, snapped to a grid. A left click places the object there
+        and dispatches the "on_object_placed" event with the new entity and
+        its position.
+
+        # Arguments
+
+        * `snap` - Spacing of the grid, no snapping if zero.
+        * `height` - Height of the ground plane.
+        """
+    def cancel_placement(self) -> None:
+        """Stops the ongoing placement without placing the object."""
+    @property
+    def is_placing(self) -> bool:
+        """Returns true while a placement is ongoing."""
+    def select(self, entity: PyEntity | None, mode: GizmoMode | None = ...) -> None:
+        """Selects the entity manipulated by the gizmo, or removes the gizmo if
+        `None`.
+
+        The handles of the gizmo are dragged with the left mouse button. The
+        "on_transform_changed" event is dispatched at the end of each drag
+        with the entity and its local transform before and after, as 4x4
+        matrices.
+
+        # Arguments
+
+        * `mode` - Transformation applied by the handles, defaults to the
+          mode of the previous selection or to translation.
+        """
+    @property
+    def selected(self) -> PyEntity | None:
+        """Returns the entity manipulated by the gizmo, if any."""
+    def set_gizmo_mode(self, mode: GizmoMode) -> None:
+        """Sets the transformation applied by the handles of the gizmo."""
+    def set_background(self, color: Color) -> None:
+        """Sets the background color of the main camera and removes its gradient
+        or image background.
+        """
+    def set_sky(self, turbidity: float = ..., exposure: float = ..., ground: Color | None = ...) -> None:
+        """Draws an analytical sky behind the scene seen by the main camera, with
+        the sun disk and the ground below the horizon. The sun follows the
+        day/night cycle if enabled, and the sky fades into the background
+        color at night.
+
+        # Arguments
+
+        * `turbidity` - Haziness of the atmosphere, from 2 for a clear sky to
+          10 for a hazy one.
+        * `exposure` - Scale of the brightness of the sky.
+        * `ground` - Color of the ground below the horizon.
+        """
+    def render_ground_truth(self, width: int, height: int, spp: int, path: str, progress: Any | None = ...) -> None:
+        """Renders the scene from the main camera with a CPU path tracer and
+        writes the image to `path`, as a reference for the rasterized frames.
+
+        Materials are approximated by their diffuse and specular colors, and
+        the rays escaping the scene take the background color of the camera.
+        The scene is rendered as it was at the last frame.
+
+        # Arguments
+
+        * `spp` - The number of samples per pixel.
+        * `progress` - Called after each sample of all the pixels with the
+          number of samples done and `spp`.
+        """
+    def start_recording(self, path: str, fps: float = ...) -> None:
+        """Starts recording the frames of the window.
+
+        If the path has a video extension (`mp4`, `mkv`, `webm`, `mov` or
+        `avi`) and ffmpeg is installed, a video is written. Otherwise, the
+        frames are written as numbered PNG images in the directory `path`.
+        """
+    def stop_recording(self) -> None:
+        """Stops recording the frames of the window, waits for the recorded
+        frames to be written.
+        """
+    def move_camera_to(self, pos: npt.NDArray[np.float32], look_at: npt.NDArray[np.float32], duration: float = ..., easing: Easing = ...) -> None:
+        """Moves the main camera smoothly to a new viewpoint.
+
+        Moves are queued: the camera moves to this viewpoint once the previous
+        ones have been reached. The `on_camera_arrived` event is dispatched
+        each time a viewpoint is reached.
+
+        # Arguments
+
+        * `pos` - The position of the camera.
+        * `look_at` - The position the camera looks at.
+        * `duration` - The duration of the move in seconds.
+        * `easing` - The easing of the move.
+        """
+    def stop_camera_animation(self) -> None:
+        """Stops the camera animation and discards the queued viewpoints."""
+    def is_camera_animating(self) -> bool:
+        """Returns true if the camera is moving to a viewpoint."""
+    def enable_day_cycle(self, day_duration: float = ..., latitude: float = ..., light: PyEntity | None = ...) -> None:
+        """Enables the day/night cycle: the directional light moves along the
+        path of the sun, its color warms up at dawn and dusk, and the
+        background of the main camera follows the sky.
+
+        # Arguments
+
+        * `day_duration` - The duration of a whole day in seconds.
+        * `latitude` - The latitude of the site in degrees.
+        * `light` - The directional light moved as the sun, the first
+          directional light of the scene if `None`.
+        """
+    def disable_day_cycle(self) -> None:
+        """Stops the day/night cycle, leaving the light as it is."""
+    def set_time_of_day(self, hours: float) -> None:
+        """Sets the time of the day in hours of the day/night cycle."""
+    def add_mesh(self, mesh: Mesh, parent: PyEntity | None = ...) -> PyEntity:
+        """Adds a mesh to the scene."""
+    def add_mesh_lod(self, meshes: list[Mesh], distances: list[float], parent: PyEntity | None = ...) -> PyEntity:
+        """Adds a mesh with several levels of detail to the scene.
+
+        The entity draws the first mesh whose distance is greater than its
+        distance to the main camera, or the last mesh beyond all distances.
+        Simplified meshes can be generated with `Mesh.simplify`.
+
+        # Arguments
+
+        * `meshes` - The meshes, from the most to the least detailed.
+        * `distances` - The distance up to which each mesh is drawn.
+        """
+    def add_mesh_streamed(self, path: str, parent: PyEntity | None = ...) -> PyEntity:
+        """Adds the objects of a wavefront obj file to the scene progressively.
+
+        The file is read in the background; each object is added as a child
+        of the returned entity as soon as it has been read, so that large
+        files are displayed without blocking the application.
+        """
+    def create_spline(self, points: list[list[float]], kind: SplineKind = ...) -> Spline:
+        """Creates a spline through the points, e.g. to sweep a road along it
+        with `Mesh.sweep_along_spline`. See `SplineKind` for the meaning of
+        the points.
+        """
+    def scatter(self, mesh: Mesh, surface: PyEntity, density: float, seed: int = ..., align_to_normal: bool = ..., scale_jitter: float = ...) -> PyEntity:
+        """Scatters instances of the mesh at random over the surface of a mesh
+        entity, e.g. grass or trees over a terrain. The instances share the
+        uploaded mesh and are drawn together; they are children of the
+        returned entity.
+
+        # Arguments
+
+        * `mesh` - The scattered mesh, its up axis being Y.
+        * `surface` - The entity whose mesh receives the instances.
+        * `density` - The average number of instances per unit of area.
+        * `seed` - The seed of the random placement.
+        * `align_to_normal` - Whether the instances are tilted along the normal
+          of the surface, otherwise they stay upright.
+        * `scale_jitter` - The largest relative change of the scale of the
+          instances.
+        """
+    def bake_static_geometry(self) -> int:
+        """Merges the static meshes sharing the same materials into single
+        meshes with their world transforms applied, so that they are drawn
+        with a few draw calls, e.g. for the final render of a large scene.
+
+        The visible mesh entities are baked, except those with levels of
+        detail, custom shaders, material overrides or tints. Moving or hiding
+        the baked entities has no visible effect until the geometry is
+        unbaked; ray casts and analyses still see the baked entities. Baking
+        again unbakes the previous batches first.
+
+        Returns the number of batches.
+        """
+    def unbake_static_geometry(self) -> None:
+        """Removes the meshes merged by `bake_static_geometry`, the entities
+        being drawn again on their own with their current transforms.
+        """
+    def add_sprite(self, texture: str, size: list[float], position: npt.NDArray[np.float32], tint: Color = ...) -> PyEntity:
+        """Adds a sprite, a camera-facing textured quad, to the scene.
+
+        # Arguments
+
+        * `texture` - Path to the image of the sprite.
+        * `size` - Width and height of the sprite in world units.
+        * `position` - Center of the sprite in world space.
+        * `tint` - Color multiplied with the image.
+        """
+    def add_particle_emitter(self, emitter: ParticleEmitter, parent: PyEntity | None = ...) -> PyEntity:
+        """Adds a particle emitter to the scene. Particles are spawned at the
+        position of the returned entity.
+        """
+    def add_water(self, size: float, level: float, water: Water | None = ...) -> PyEntity:
+        """Adds a square water surface to the scene.
+
+        # Arguments
+
+        * `size` - Side length of the surface in world units.
+        * `level` - Height of the surface.
+        * `water` - Appearance of the water, defaults to [`Water::default`].
+        """
+    def spawn_building(self) -> PyEntity:
+        ...
+    def add_point_light_py(self, pos: npt.NDArray[np.float32], color: Color = ...) -> PyEntity:
+        ...
+    def add_directional_light(self, dir: npt.NDArray[np.float32], color: Color = ...) -> PyEntity:
+        ...
+
+
+class AppConfig:
+    """Configuration of the application, applied at once when the application
+    is created.
+
+    All the setters return the configuration so that they can be chained.
+    """
+    def __new__(cls) -> AppConfig:
+        ...
+    def with_window(self, window: Window) -> AppConfig:
+        """Sets the settings of the window."""
+    def with_camera(self, pos: npt.NDArray[np.float32], look_at: npt.NDArray[np.float32], fov_v: float = ..., near: float = ..., far: float = ..., background: Color = ...) -> AppConfig:
+        """Creates the main camera with the application.
+
+        * `pos` - The position of the camera.
+        * `look_at` - The target of the camera.
+        * `fov_v` - The vertical field of view of the camera in degrees.
+        """
+    def with_shadows(self, enabled: bool) -> AppConfig:
+        """Enables or disables shadows."""
+    def with_lighting(self, enabled: bool) -> AppConfig:
+        """Enables or disables the lighting."""
+    def with_backface_culling(self, enabled: bool) -> AppConfig:
+        """Enables or disables backface culling."""
+    def with_wireframe(self, enabled: bool) -> AppConfig:
+        """Enables or disables wireframe rendering."""
+    def with_vsync(self, enabled: bool) -> AppConfig:
+        """Enables or disables the synchronization of the frames with the
+        display refresh rate.
+        """
+    def with_target_fps(self, fps: float | None = ...) -> AppConfig:
+        """Caps the number of frames rendered per second, or uncaps it if
+        `None`.
+        """
+    def with_texture_budget(self, megabytes: float | None = ...) -> AppConfig:
+        """Limits the GPU memory of the textures to the given number of
+        megabytes, or removes the limit if `None`. The least recently used
+        textures of the entities which are not visible are evicted beyond it.
+        """
+    def with_occlusion_culling(self, enabled: bool) -> AppConfig:
+        """Enables or disables the occlusion culling."""
+    def with_gpu_culling(self, enabled: bool) -> AppConfig:
+        """Enables or disables the culling of the instances on the GPU."""
+    def with_depth_prepass(self, enabled: bool) -> AppConfig:
+        """Enables or disables the depth pre-pass."""
+    def with_depth_format(self, format: DepthFormat) -> AppConfig:
+        """Sets the format of the depth buffer."""
+    def with_reversed_z(self, enabled: bool) -> AppConfig:
+        """Enables or disables the reversed depth, going from 1 at the near
+        plane to 0 at the far plane, which avoids z-fighting far away in
+        large scenes, especially with an infinite far plane.
+        """
+    def with_msaa(self, samples: int) -> AppConfig:
+        """Sets the number of samples per pixel of the scene, smoothing the
+        edges of the geometry. 1 disables the multisampling; counts not
+        supported by the adapter are lowered to the closest supported one.
+        """
+    def with_hot_reload(self, enabled: bool) -> AppConfig:
+        """Enables or disables the reloading of files modified on disk."""
+    def with_backend(self, backend: Backend | None = ...) -> AppConfig:
+        """Selects the graphics API to render with, or the default one of the
+        platform if `None`. The `BKFW_BACKEND` environment variable takes
+        precedence.
+        """
+    def with_power_preference(self, preference: PowerPreference) -> AppConfig:
+        """Sets the kind of GPU preferred when several are available. The
+        `BKFW_POWER_PREFERENCE` environment variable takes precedence.
+        """
+    def with_fallback_adapter(self, enabled: bool) -> AppConfig:
+        """Forces the use of a software adapter rendering on the CPU. The
+        `BKFW_FORCE_FALLBACK_ADAPTER` environment variable takes precedence.
+        """
+
+
+class Input:
+    """Struct holding the input state of the current frame.
+    This is passed to the user's update function.
+    """
+    def cursor_position(self) -> list[float]:
+        """Returns the position of the cursor in pixels from the top left
+        corner of the window.
+        """
+    @property
+    def cursor_delta(self) -> list[float]:
+        ...
+    @property
+    def scroll_delta(self) -> float:
+        ...
+    def is_shift_pressed(self) -> bool:
+        ...
+    def is_left_shift_pressed(self) -> bool:
+        ...
+    def is_right_shift_pressed(self) -> bool:
+        ...
+    def is_ctrl_pressed(self) -> bool:
+        ...
+    def is_left_ctrl_pressed(self) -> bool:
+        ...
+    def is_right_ctrl_pressed(self) -> bool:
+        ...
+    def is_alt_pressed(self) -> bool:
+        ...
+    def is_left_alt_pressed(self) -> bool:
+        ...
+    def is_right_alt_pressed(self) -> bool:
+        ...
+    def is_super_pressed(self) -> bool:
+        ...
+    def is_key_pressed(self, key_code: KeyCode) -> bool:
+        ...
+    def is_key_released(self, key_code: KeyCode) -> bool:
+        ...
+    def is_mouse_pressed(self, button: MouseButton) -> bool:
+        ...
+    def is_mouse_released(self, button: MouseButton) -> bool:
+        ...
+    def was_key_pressed(self, key_code: KeyCode) -> bool:
+        """Returns true if the key went down since the last frame."""
+    def was_key_released(self, key_code: KeyCode) -> bool:
+        """Returns true if the key went up since the last frame."""
+    def was_key_repeated(self, key_code: KeyCode) -> bool:
+        """Returns true if the key has been repeated by the system since the
+        last frame while it is held.
+        """
+    def was_mouse_pressed(self, button: MouseButton) -> bool:
+        """Returns true if the mouse button went down since the last frame."""
+    def was_mouse_released(self, button: MouseButton) -> bool:
+        """Returns true if the mouse button went up since the last frame."""
+    def is_double_click(self, button: MouseButton = ...) -> bool:
+        """Returns true if the mouse button has been pressed a second time in
+        a row at about the same place since the last frame.
+        """
+    def release_key(self, key_code: KeyCode) -> None:
+        ...
+    def release_mouse_button(self, button: MouseButton) -> None:
+        ...
+
+
+class MouseButton:
+    Left: ClassVar[MouseButton]
+    Right: ClassVar[MouseButton]
+    Middle: ClassVar[MouseButton]
+
+
+class KeyCode:
+    Key1: ClassVar[KeyCode]
+    """The '1' key over the letters."""
+    Key2: ClassVar[KeyCode]
+    """The '2' key over the letters."""
+    Key3: ClassVar[KeyCode]
+    """The '3' key over the letters."""
+    Key4: ClassVar[KeyCode]
+    """The '4' key over the letters."""
+    Key5: ClassVar[KeyCode]
+    """The '5' key over the letters."""
+    Key6: ClassVar[KeyCode]
+    """The '6' key over the letters."""
+    Key7: ClassVar[KeyCode]
+    """The '7' key over the letters."""
+    Key8: ClassVar[KeyCode]
+    """The '8' key over the letters."""
+    Key9: ClassVar[KeyCode]
+    """The '9' key over the letters."""
+    Key0: ClassVar[KeyCode]
+    """The '0' key over the 'O' and 'P' keys."""
+    A: ClassVar[KeyCode]
+    B: ClassVar[KeyCode]
+    C: ClassVar[KeyCode]
+    D: ClassVar[KeyCode]
+    E: ClassVar[KeyCode]
+    F: ClassVar[KeyCode]
+    G: ClassVar[KeyCode]
+    H: ClassVar[KeyCode]
+    I: ClassVar[KeyCode]
+    J: ClassVar[KeyCode]
+    K: ClassVar[KeyCode]
+    L: ClassVar[KeyCode]
+    M: ClassVar[KeyCode]
+    N: ClassVar[KeyCode]
+    O: ClassVar[KeyCode]
+    P: ClassVar[KeyCode]
+    Q: ClassVar[KeyCode]
+    R: ClassVar[KeyCode]
+    S: ClassVar[KeyCode]
+    T: ClassVar[KeyCode]
+    U: ClassVar[KeyCode]
+    V: ClassVar[KeyCode]
+    W: ClassVar[KeyCode]
+    X: ClassVar[KeyCode]
+    Y: ClassVar[KeyCode]
+    Z: ClassVar[KeyCode]
+    Escape: ClassVar[KeyCode]
+    """The Escape key, next to F1."""
+    F1: ClassVar[KeyCode]
+    F2: ClassVar[KeyCode]
+    F3: ClassVar[KeyCode]
+    F4: ClassVar[KeyCode]
+    F5: ClassVar[KeyCode]
+    F6: ClassVar[KeyCode]
+    F7: ClassVar[KeyCode]
+    F8: ClassVar[KeyCode]
+    F9: ClassVar[KeyCode]
+    F10: ClassVar[KeyCode]
+    F11: ClassVar[KeyCode]
+    F12: ClassVar[KeyCode]
+    F13: ClassVar[KeyCode]
+    F14: ClassVar[KeyCode]
+    F15: ClassVar[KeyCode]
+    F16: ClassVar[KeyCode]
+    F17: ClassVar[KeyCode]
+    F18: ClassVar[KeyCode]
+    F19: ClassVar[KeyCode]
+    F20: ClassVar[KeyCode]
+    F21: ClassVar[KeyCode]
+    F22: ClassVar[KeyCode]
+    F23: ClassVar[KeyCode]
+    F24: ClassVar[KeyCode]
+    PrintScreen: ClassVar[KeyCode]
+    ScrollLock: ClassVar[KeyCode]
+    Pause: ClassVar[KeyCode]
+    Insert: ClassVar[KeyCode]
+    Home: ClassVar[KeyCode]
+    Delete: ClassVar[KeyCode]
+    End: ClassVar[KeyCode]
+    PageDown: ClassVar[KeyCode]
+    PageUp: ClassVar[KeyCode]
+    Left: ClassVar[KeyCode]
+    Up: ClassVar[KeyCode]
+    Right: ClassVar[KeyCode]
+    Down: ClassVar[KeyCode]
+    Backspace: ClassVar[KeyCode]
+    Backquote: ClassVar[KeyCode]
+    Enter: ClassVar[KeyCode]
+    Space: ClassVar[KeyCode]
+    NumLock: ClassVar[KeyCode]
+    Numpad0: ClassVar[KeyCode]
+    Numpad1: ClassVar[KeyCode]
+    Numpad2: ClassVar[KeyCode]
+    Numpad3: ClassVar[KeyCode]
+    Numpad4: ClassVar[KeyCode]
+    Numpad5: ClassVar[KeyCode]
+    Numpad6: ClassVar[KeyCode]
+    Numpad7: ClassVar[KeyCode]
+    Numpad8: ClassVar[KeyCode]
+    Numpad9: ClassVar[KeyCode]
+    NumpadAdd: ClassVar[KeyCode]
+    NumpadDivide: ClassVar[KeyCode]
+    NumpadDecimal: ClassVar[KeyCode]
+    NumpadComma: ClassVar[KeyCode]
+    NumpadEnter: ClassVar[KeyCode]
+    NumpadEqual: ClassVar[KeyCode]
+    NumpadMultiply: ClassVar[KeyCode]
+    NumpadSubtract: ClassVar[KeyCode]
+    NumpadBackspace: ClassVar[KeyCode]
+    NumpadClear: ClassVar[KeyCode]
+    NumpadClearEntry: ClassVar[KeyCode]
+    NumpadHash: ClassVar[KeyCode]
+    NumpadMemoryAdd: ClassVar[KeyCode]
+    NumpadMemoryClear: ClassVar[KeyCode]
+    NumpadMemoryRecall: ClassVar[KeyCode]
+    NumpadMemoryStore: ClassVar[KeyCode]
+    NumpadMemorySubtract: ClassVar[KeyCode]
+    NumpadParenLeft: ClassVar[KeyCode]
+    NumpadParenRight: ClassVar[KeyCode]
+    NumpadStar: ClassVar[KeyCode]
+    Backslash: ClassVar[KeyCode]
+    CapsLock: ClassVar[KeyCode]
+    Comma: ClassVar[KeyCode]
+    Convert: ClassVar[KeyCode]
+    Equal: ClassVar[KeyCode]
+    AltLeft: ClassVar[KeyCode]
+    BracketLeft: ClassVar[KeyCode]
+    ControlLeft: ClassVar[KeyCode]
+    ShiftLeft: ClassVar[KeyCode]
+    SuperLeft: ClassVar[KeyCode]
+    SuperRight: ClassVar[KeyCode]
+    LaunchMail: ClassVar[KeyCode]
+    MediaSelect: ClassVar[KeyCode]
+    MediaStop: ClassVar[KeyCode]
+    Minus: ClassVar[KeyCode]
+    AudioVolumeMute: ClassVar[KeyCode]
+    MediaTrackNext: ClassVar[KeyCode]
+    NonConvert: ClassVar[KeyCode]
+    Period: ClassVar[KeyCode]
+    MediaPlayPause: ClassVar[KeyCode]
+    Power: ClassVar[KeyCode]
+    MediaTrackPrevious: ClassVar[KeyCode]
+    AltRight: ClassVar[KeyCode]
+    BracketRight: ClassVar[KeyCode]
+    ControlRight: ClassVar[KeyCode]
+    ShiftRight: ClassVar[KeyCode]
+    Semicolon: ClassVar[KeyCode]
+    Slash: ClassVar[KeyCode]
+    Sleep: ClassVar[KeyCode]
+    Tab: ClassVar[KeyCode]
+    AudioVolumeDown: ClassVar[KeyCode]
+    AudioVolumeUp: ClassVar[KeyCode]
+    WakeUp: ClassVar[KeyCode]
+    BrowserBack: ClassVar[KeyCode]
+    BrowserFavorites: ClassVar[KeyCode]
+    BrowserForward: ClassVar[KeyCode]
+    BrowserHome: ClassVar[KeyCode]
+    BrowserRefresh: ClassVar[KeyCode]
+    BrowserSearch: ClassVar[KeyCode]
+    BrowserStop: ClassVar[KeyCode]
+    Copy: ClassVar[KeyCode]
+    Paste: ClassVar[KeyCode]
+    Cut: ClassVar[KeyCode]
+    IntlBackslash: ClassVar[KeyCode]
+    IntlRo: ClassVar[KeyCode]
+    IntlYen: ClassVar[KeyCode]
+    Quote: ClassVar[KeyCode]
+    ContextMenu: ClassVar[KeyCode]
+    KanaMode: ClassVar[KeyCode]
+    Lang1: ClassVar[KeyCode]
+    Lang2: ClassVar[KeyCode]
+    Lang3: ClassVar[KeyCode]
+    Lang4: ClassVar[KeyCode]
+    Lang5: ClassVar[KeyCode]
+    Help: ClassVar[KeyCode]
+    Fn: ClassVar[KeyCode]
+    FnLock: ClassVar[KeyCode]
+    Eject: ClassVar[KeyCode]
+    LaunchApp1: ClassVar[KeyCode]
+    LaunchApp2: ClassVar[KeyCode]
+    Meta: ClassVar[KeyCode]
+    Hyper: ClassVar[KeyCode]
+    Turbo: ClassVar[KeyCode]
+    Abort: ClassVar[KeyCode]
+    Resume: ClassVar[KeyCode]
+    Suspend: ClassVar[KeyCode]
+    Again: ClassVar[KeyCode]
+    Find: ClassVar[KeyCode]
+    Open: ClassVar[KeyCode]
+    Props: ClassVar[KeyCode]
+    Select: ClassVar[KeyCode]
+    Undo: ClassVar[KeyCode]
+    Hiragana: ClassVar[KeyCode]
+    Katakana: ClassVar[KeyCode]
+    F25: ClassVar[KeyCode]
+    F26: ClassVar[KeyCode]
+    F27: ClassVar[KeyCode]
+    F28: ClassVar[KeyCode]
+    F29: ClassVar[KeyCode]
+    F30: ClassVar[KeyCode]
+    F31: ClassVar[KeyCode]
+    F32: ClassVar[KeyCode]
+    F33: ClassVar[KeyCode]
+    F34: ClassVar[KeyCode]
+    F35: ClassVar[KeyCode]
+
+
+class AdapterInfo:
+    """Description of the adapter the application renders with, to be included
+    in bug reports.
+    """
+    @property
+    def name(self) -> str:
+        """Name of the adapter."""
+    @property
+    def backend(self) -> str:
+        """Graphics API used, e.g. `Vulkan`."""
+    @property
+    def device_type(self) -> str:
+        """Kind of adapter, e.g. `DiscreteGpu`."""
+    @property
+    def vendor(self) -> int:
+        """PCI id of the vendor of the adapter."""
+    @property
+    def device(self) -> int:
+        """PCI id of the adapter."""
+    @property
+    def driver(self) -> str:
+        """Name of the driver."""
+    @property
+    def driver_info(self) -> str:
+        """Version of the driver."""
+    @property
+    def features(self) -> list[str]:
+        """Features enabled on the device."""
+    @property
+    def limits(self) -> list[tuple[str, int]]:
+        """Limits of the device, by name."""
+
+
+class Backend:
+    """Graphics API used to render."""
+    Vulkan: ClassVar[Backend]
+    Metal: ClassVar[Backend]
+    Dx12: ClassVar[Backend]
+    Gl: ClassVar[Backend]
+    """OpenGL or OpenGL ES, the most widely available but the slowest."""
+
+
+class PowerPreference:
+    """Kind of adapter preferred when several are available."""
+    LowPower: ClassVar[PowerPreference]
+    """Prefers integrated GPUs, e.g. to save the battery of a laptop."""
+    HighPerformance: ClassVar[PowerPreference]
+    """Prefers discrete GPUs."""
+
+
+class DepthFormat:
+    """Format of the depth buffer of the rendering passes."""
+    Depth24Plus: ClassVar[DepthFormat]
+    """At least 24 bits of depth, fixed or floating point depending on the
+    platform.
+    """
+    Depth32Float: ClassVar[DepthFormat]
+    """32-bit floating point depth."""
+
+
+class ShadingMode:
+    """Shading mode."""
+    Flat: ClassVar[ShadingMode]
+    """Flat shading."""
+    Gouraud: ClassVar[ShadingMode]
+    """Gouraud shading."""
+    BlinnPhong: ClassVar[ShadingMode]
+    """Blinn-Phong shading."""
+
+
+class SamplerConfig:
+    """Settings of a sampler, registered under a name in the [`SamplerRegistry`]."""
+    wrap_u: WrapMode
+    """Wrapping along U."""
+    wrap_v: WrapMode
+    """Wrapping along V."""
+    filter: FilterMode
+    """Filtering of the magnified and minified texels."""
+    mipmap_filter: FilterMode
+    """Filtering between the mip levels."""
+    anisotropy: int
+    """Maximum anisotropy, from 1 (disabled) to 16. Only applied if all the
+    filters are linear.
+    """
+    lod_min: float
+    """Lowest mip level sampled."""
+    lod_max: float
+    """Highest mip level sampled."""
+    def __new__(cls, wrap: WrapMode = ..., filter: FilterMode = ..., mipmap_filter: FilterMode | None = ..., anisotropy: int = ..., lod_min: float = ..., lod_max: float = ...) -> SamplerConfig:
+        ...
+    @staticmethod
+    def from_name(name: str) -> SamplerConfig | None:
+        """Returns the settings described by a sampler name, e.g.
+        `clamp_nearest` or `linear_aniso8`, `None` if the name is not valid.
+        """
+    def __repr__(self) -> str:
+        ...
+
+
+class WrapMode:
+    """Wrapping of the texture coordinates outside of the `[0, 1]` range."""
+    Repeat: ClassVar[WrapMode]
+    """The texture repeats."""
+    Clamp: ClassVar[WrapMode]
+    """The texels at the edges are stretched."""
+    Mirror: ClassVar[WrapMode]
+    """The texture repeats, mirrored every other time."""
+
+
+class FilterMode:
+    """Filtering of the texels."""
+    Nearest: ClassVar[FilterMode]
+    """The nearest texel is sampled."""
+    Linear: ClassVar[FilterMode]
+    """The nearest texels are interpolated."""
+
+
+class Projection:
+    """Describes the projection settings for a camera."""
+    @staticmethod
+    def orthographic(height: float, z_near: float, z_far: float) -> Projection:
+        """Creates a new perspective projection."""
+    @staticmethod
+    def perspective(fov: float, z_near: float, z_far: float) -> Projection:
+        """Creates a new perspective projection."""
+
+
+class ProjectionKind:
+    """The type of projection for a camera."""
+    Orthographic: ClassVar[ProjectionKind]
+    """An orthographic projection."""
+    Perspective: ClassVar[ProjectionKind]
+    """A perspective projection."""
+
+
+class Easing:
+    """Easing function of an animation."""
+    Linear: ClassVar[Easing]
+    """Constant speed."""
+    EaseIn: ClassVar[Easing]
+    """Starts slowly and accelerates."""
+    EaseOut: ClassVar[Easing]
+    """Starts fast and decelerates."""
+    EaseInOut: ClassVar[Easing]
+    """Accelerates then decelerates."""
+
+
+class GizmoMode:
+    """Transformation applied by the handles of the gizmo."""
+    Translate: ClassVar[GizmoMode]
+    """Arrows moving the entity along the world axes."""
+    Rotate: ClassVar[GizmoMode]
+    """Circles rotating the entity around the world axes."""
+    Scale: ClassVar[GizmoMode]
+    """Boxes scaling the entity along its local axes."""
+
+
+class HalfEdgeMesh:
+    """Polygon mesh connected by half-edges.
+
+    Vertices, half-edges and faces are referred to by their index, which
+    stays valid across edits: removed elements are only marked as such.
+    Edges shared by more than two faces, or by faces of opposite
+    orientations, can't be represented and are left as boundaries.
+    """
+    def __new__(cls, mesh: Mesh) -> HalfEdgeMesh:
+        """Creates the half-edge mesh of a triangle mesh."""
+    @property
+    def vertex_count(self) -> int:
+        """Number of vertex indices, removed vertices included."""
+    @property
+    def face_count(self) -> int:
+        ...
+    @property
+    def edge_count(self) -> int:
+        ...
+    def position(self, vertex: int) -> list[float]:
+        ...
+    def vertex_neighbors(self, vertex: int) -> list[int]:
+        ...
+    def vertex_faces(self, vertex: int) -> list[int]:
+        ...
+    def valence(self, vertex: int) -> int:
+        ...
+    def is_boundary_vertex(self, vertex: int) -> bool:
+        ...
+    def face_vertices(self, face: int) -> list[int]:
+        ...
+    def face_neighbors(self, face: int) -> list[int]:
+        ...
+    def boundary_loops(self) -> list[list[int]]:
+        ...
+    def is_closed(self) -> bool:
+        ...
+    def to_mesh(self) -> Mesh:
+        ...
+
+
+class Mesh:
+    """A mesh is a collection of vertices with optional indices and materials.
+    Vertices can have different attributes such as position, normal, uv, etc.
+    """
+    def __new__(cls, name: str | None = ..., topology: Topology = ...) -> Mesh:
+        ...
+    @staticmethod
+    def create_cube(length: float) -> Mesh:
+        ...
+    @staticmethod
+    def create_quad(length: float, align: Alignment) -> Mesh:
+        ...
+    @staticmethod
+    def create_sphere(radius: float, segments: int, rings: int) -> Mesh:
+        ...
+    @staticmethod
+    def create_water_plane(size: float) -> Mesh:
+        ...
+    @staticmethod
+    def create_grid(width: float, height: float, spacing: tuple[float, float], align: Alignment, color: Color) -> Mesh:
+        ...
+    @staticmethod
+    def create_triangle(p0: npt.NDArray[np.float32], p1: npt.NDArray[np.float32], p2: npt.NDArray[np.float32]) -> Mesh:
+        ...
+    @staticmethod
+    def load_from(path: str) -> Mesh:
+        ...
+    @staticmethod
+    def create_building(footprint: list[list[float]], params: BuildingParams) -> Mesh:
+        """Generates a building standing on the footprint, a polygon given as
+        `(x, z)` points on the ground. The walls, the roof and the ground
+        floor are sub-meshes with the materials of the parameters.
+        """
+    @staticmethod
+    def sweep_along_spline(profile: list[list[float]], spline: Spline, uv_scale: float = ...) -> Mesh:
+        """Creates the surface swept by the profile along the spline, e.g. a
+        road, a wall or a river bed.
+
+        The profile points are `(x, y)` offsets to the right of and above
+        the path, ordered clockwise as seen looking along the path (e.g. from
+        left to right for a road). The texture is tiled every `uv_scale`
+        units along the profile and the path.
+        """
+    @staticmethod
+    def merge(meshes: list[Mesh]) -> Mesh:
+        """Merges meshes into a single mesh drawn at once, each mesh becoming
+        one or more sub-meshes sharing the identical materials.
+        """
+    @staticmethod
+    def from_arrays(positions: Any, indices: Any, normals: Any | None = ..., uvs: Any | None = ..., colors: Any | None = ...) -> Mesh:
+        """Creates a triangle mesh from numpy arrays of `n` positions of shape
+        `(n, 3)` and of indices of shape `(m,)` or `(m, 3)`, with optional
+        normals of shape `(n, 3)`, UVs of shape `(n, 2)` and colors of shape
+        `(n, 3)` or `(n, 4)`. Contiguous float32 and uint32 arrays are copied
+        once, arrays of other float and integer types are converted. The
+        normals are computed if they are not given.
+        """
+    def simplify(self, target_ratio: float) -> Mesh:
+        """Returns a simplified copy of the mesh with about `target_ratio` of its
+        triangles, e.g. to be used as a level of detail.
+        """
+    def subdivide(self, levels: int = ..., scheme: SubdivisionScheme = ...) -> Mesh:
+        """Returns a smoothed copy of the mesh, subdivided `levels` times with
+        the given scheme.
+        """
+    def save_obj(self, path: str) -> None:
+        """Writes the mesh to a wavefront obj file, the materials are written to
+        a material library next to it.
+        """
+    def save_gltf(self, path: str) -> None:
+        """Writes the mesh to a glTF file, the vertex data is written to a binary
+        buffer next to it.
+        """
+    def generate_uvs(self, mode: UvProjection) -> None:
+        """Replaces the texture coordinates of the mesh by a projection of its
+        vertices, see `UvProjection`.
+        """
+    def triangle_count(self) -> int:
+        """Returns the number of triangles of the mesh."""
+    def surface_area(self) -> float:
+        """Returns the total area of the triangles of the mesh."""
+    def volume(self) -> float | None:
+        """Returns the volume enclosed by the mesh, or `None` if the mesh is not
+        watertight.
+        """
+    def is_watertight(self) -> bool:
+        """Returns whether the mesh is closed and consistently oriented."""
+    def bounding_sphere(self) -> tuple[npt.NDArray[np.float32], float] | None:
+        """Returns a sphere enclosing the vertices of the mesh as (center,
+        radius), or `None` if the mesh has no vertices.
+        """
+    def apply_material(self, material: Material) -> None:
+        ...
+    def set_material(self, material: Material) -> None:
+        """Applies a single material to the whole mesh."""
+    @materials.setter
+    def materials(self, materials: list[Material] | None) -> None:
+        """Sets the materials of the mesh."""
+    @property
+    def materials(self) -> list[Material] | None:
+        """Returns the materials of the mesh."""
+    def append_material(self, material: Material) -> int:
+        """Appends a material to the current list of materials of the mesh."""
+    def append_materials(self, materials: list[Material]) -> list[int]:
+        """Appends a list of materials to the current list of materials of the
+        mesh.
+        """
+    @sub_meshes.setter
+    def sub_meshes(self, sub_meshes: list[SubMesh] | None) -> None:
+        """Sets the submeshes of the mesh."""
+    @property
+    def positions(self) -> npt.NDArray[np.float32] | None:
+        """Returns a read-only numpy view of the positions of the vertices, of
+        shape `(n, 3)`, `None` if the mesh has no positions.
+        """
+    @positions.setter
+    def positions(self, vertices: list[list[float]] | None) -> None:
+        ...
+    @property
+    def normals(self) -> npt.NDArray[np.float32] | None:
+        """Returns a read-only numpy view of the normals of the vertices, of
+        shape `(n, 3)`, `None` if the mesh has no normals.
+        """
+    @normals.setter
+    def normals(self, normals: list[list[float]]) -> None:
+        ...
+    @property
+    def texcoords(self) -> npt.NDArray[np.float32] | None:
+        """Returns a read-only numpy view of the UVs of the vertices, of shape
+        `(n, 2)`, `None` if the mesh has no UVs.
+        """
+    @property
+    def colors(self) -> npt.NDArray[np.float32] | None:
+        """Returns a read-only numpy view of the colors of the vertices, of
+        shape `(n, 4)`, `None` if the mesh has no colors.
+        """
+    @texcoords.setter
+    def texcoords(self, uvs: list[list[float]] | None) -> None:
+        ...
+    @triangles.setter
+    def triangles(self, triangles: list[list[int]] | None) -> None:
+        ...
+    def set_attribute(self, name: str, values: npt.NDArray[np.float32], location: int | None = ...) -> None:
+        """Sets a custom vertex attribute read by the custom shaders, with 1 to
+        4 values per vertex. The attribute is read at the given location, by
+        default the location of the attribute of the same name or the first
+        free one.
+        """
+    @property
+    def indices(self) -> npt.NDArray[np.uint32] | None:
+        """Returns a copy of the indices of the primitives of the mesh, `None`
+        if the mesh is not indexed.
+        """
+    @indices.setter
+    def indices(self, indices: list[int] | None) -> None:
+        """Sets the indices of the primitives of the mesh, whatever its
+        topology.
+        """
+    @property
+    def topology(self) -> Topology:
+        """Returns the topology of the mesh primitives."""
+    @topology.setter
+    def topology(self, topology: Topology) -> None:
+        ...
+    @property
+    def point_size(self) -> float:
+        """Returns the size of the points of a point list, in world units."""
+    @point_size.setter
+    def point_size(self, size: float) -> None:
+        ...
+    @property
+    def name(self) -> str:
+        ...
+    @name.setter
+    def name(self, name: str) -> None:
+        ...
+    def __repr__(self) -> str:
+        ...
+    def __eq__(self, other: Mesh) -> bool:
+        """Meshes are equal if they have the same name, which is unique unless
+        set explicitly.
+        """
+    def __hash__(self) -> int:
+        ...
+    def compute_normals(self) -> None:
+        """Computes per vertex normals for the mesh from the UVs."""
+    def compute_tangents(self) -> None:
+        """Computes per vertex tangents for the mesh from the UVs."""
+    def colorize_by_height(self, gradient: list[tuple[float, Color]]) -> None:
+        """Generates the vertex colors from the height of the vertices. The
+        gradient is a list of `(t, color)` stops, `t` going from 0 at the lowest
+        vertex to 1 at the highest one.
+        """
+    def colorize_by_normal_slope(self, gradient: list[tuple[float, Color]]) -> None:
+        """Generates the vertex colors from the slope of the surface. The
+        gradient is a list of `(t, color)` stops, `t` going from 0 for flat
+        surfaces to 1 for vertical ones.
+        """
+    def bake_ambient_occlusion(self, samples: int = ..., radius: float = ...) -> None:
+        """Bakes the ambient occlusion onto the vertex colors, casting `samples`
+        rays per vertex against the mesh within `radius`.
+        """
+
+
+class SubMesh:
+    """A submesh is a range of indices, it specifies a range of indices to be
+    rendered with a specific material.
+    """
+    def __new__(cls, start: int, end: int, index: int) -> SubMesh:
+        """Creates a new submesh from a range of indices of triangles."""
+
+
+class Topology:
+    """Topology of a mesh primitive."""
+    PointList: ClassVar[Topology]
+    """Vertex data is a list of points. Each vertex is a new point."""
+    LineList: ClassVar[Topology]
+    """Vertex data is a list of lines. Each pair of vertices composes a new
+    line.
+
+    Vertices `0 1 2 3` create two lines `0 1` and `2 3`
+    """
+    LineStrip: ClassVar[Topology]
+    """Vertex data is a strip of lines. Each set of two adjacent vertices form
+    a line.
+
+    Vertices `0 1 2 3` create three lines `0 1`, `1 2`, and `2 3`.
+    """
+    TriangleList: ClassVar[Topology]
+    """Vertex data is a list of triangles. Each set of 3 vertices composes a
+    new triangle.
+
+    Vertices `0 1 2 3 4 5` create two triangles `0 1 2` and `3 4 5`
+    """
+    TriangleStrip: ClassVar[Topology]
+    """Vertex data is a triangle strip. Each set of three adjacent vertices
+    form a triangle.
+
+    Vertices `0 1 2 3 4 5` create four triangles `0 1 2`, `2 1 3`, `2 3 4`,
+    and `4 3 5`
+    """
+
+
+class UvProjection:
+    """Projection used to generate the texture coordinates of a mesh."""
+    Planar: ClassVar[UvProjection]
+    """Projection onto the plane of the two largest dimensions of the mesh."""
+    Box: ClassVar[UvProjection]
+    """Projection of each triangle onto the side of the bounding box it
+    faces the most.
+    """
+    Spherical: ClassVar[UvProjection]
+    """Projection onto a sphere around the center of the mesh, by longitude
+    and latitude.
+    """
+    Cylindrical: ClassVar[UvProjection]
+    """Projection onto a cylinder around the Y axis through the center of the
+    mesh.
+    """
+    Auto: ClassVar[UvProjection]
+    """Unwrapping into charts of connected triangles facing the same side,
+    each projected flat and packed without overlaps into the unit square.
+    """
+
+
+class SubdivisionScheme:
+    """Subdivision scheme of [`Mesh::subdivide`]."""
+    Loop: ClassVar[SubdivisionScheme]
+    """Loop subdivision, splitting each triangle into 4 triangles."""
+    CatmullClark: ClassVar[SubdivisionScheme]
+    """Catmull-Clark subdivision, splitting each face into quads, one per
+    corner. The quads are kept between the levels and only triangulated
+    at the end.
+    """
+
+
+class Noise:
+    """Fractal noise: the sum of `octaves` layers of the basis noise, each
+    `lacunarity` times the frequency and `gain` times the amplitude of the
+    previous one, normalized to the range of the basis noise.
+    """
+    kind: NoiseKind
+    """Basis function."""
+    seed: int
+    """Seed of the noise, different seeds giving unrelated values."""
+    frequency: float
+    """Frequency of the first octave, i.e. number of lattice cells per unit."""
+    octaves: int
+    """Number of layers summed, 1 giving the basis noise."""
+    lacunarity: float
+    """Frequency multiplier between successive octaves."""
+    gain: float
+    """Amplitude multiplier between successive octaves."""
+    def __new__(cls, kind: NoiseKind = ..., seed: int = ..., frequency: float = ..., octaves: int = ..., lacunarity: float = ..., gain: float = ...) -> Noise:
+        ...
+    def sample(self, x: npt.NDArray[np.float32], y: npt.NDArray[np.float32], z: npt.NDArray[np.float32] | None = ...) -> npt.NDArray[np.float32]:
+        """Samples the noise at the coordinates given by arrays of the same
+        shape, e.g. from `numpy.meshgrid`. The noise is 2D unless `z` is
+        given. Returns an array of that shape.
+        """
+    def grid(self, width: int, height: int, origin: list[float] = ..., spacing: float = ...) -> npt.NDArray[np.float32]:
+        """Samples the 2D noise over a grid of `height` rows and `width` columns
+        starting at `origin` with the given spacing, e.g. a heightmap.
+        """
+
+
+class NoiseKind:
+    """Basis function of a [`Noise`]."""
+    Perlin: ClassVar[NoiseKind]
+    """Gradient noise on a square lattice, roughly in `[-1, 1]`."""
+    Simplex: ClassVar[NoiseKind]
+    """Gradient noise on a simplex lattice, roughly in `[-1, 1]`, with fewer
+    directional artifacts than Perlin noise.
+    """
+    Worley: ClassVar[NoiseKind]
+    """Distance to the nearest of randomly scattered feature points, one per
+    unit cell, clamped to `[0, 1]`. Gives cell-like patterns.
+    """
+
+
+class BuildingParams:
+    """Parameters of a generated building, see [`building`]."""
+    floors: int
+    """Number of floors."""
+    floor_height: float
+    """Height of each floor."""
+    roof: RoofStyle
+    """Shape of the roof."""
+    roof_height: float
+    """Height of the apex or the ridge of a sloping roof above the last
+    floor.
+    """
+    tile_width: float
+    """Width of the facade covered by the texture of a floor, the texture
+    being tiled along the walls.
+    """
+    facade: Material
+    """Material of the walls."""
+    ground_floor: Material | None
+    """Material of the walls of the ground floor, the facade material if
+    `None`.
+    """
+    roof_material: Material
+    """Material of the roof."""
+    def __new__(cls, floors: int = ..., floor_height: float = ..., roof: RoofStyle = ..., roof_height: float = ..., tile_width: float = ...) -> BuildingParams:
+        ...
+
+
+class RoofStyle:
+    """Shape of the roof of a generated building."""
+    Flat: ClassVar[RoofStyle]
+    """Horizontal roof covering the footprint."""
+    Pyramid: ClassVar[RoofStyle]
+    """Faces sloping from each wall to a single apex above the center of
+    the footprint, which should be convex.
+    """
+    Gabled: ClassVar[RoofStyle]
+    """Two faces sloping from the longer walls to a ridge, the shorter walls
+    being extended by triangular gables. Only for footprints of 4 points.
+    """
+
+
+class Spline:
+    """Path defined by points, see [`SplineKind`]."""
+    def __new__(cls, points: list[list[float]], kind: SplineKind = ...) -> Spline:
+        ...
+    @property
+    def points(self) -> list[list[float]]:
+        """Returns the points defining the spline."""
+    @property
+    def kind(self) -> SplineKind:
+        """Returns the kind of the spline."""
+    def length(self) -> float:
+        """Returns the length of the path."""
+    def sample(self) -> list[list[float]]:
+        """Returns the positions sampled along the path."""
+
+
+class SplineKind:
+    """Interpretation of the points of a [`Spline`]."""
+    Polyline: ClassVar[SplineKind]
+    """Straight segments between the points."""
+    Bezier: ClassVar[SplineKind]
+    """Cubic Bézier curves joined end to end: the points are the start of
+    the path followed by two control points and the end of each curve,
+    i.e. `3 * n + 1` points for `n` curves.
+    """
+
+
+class Material:
+    """Material description derived from a `MTL` file.
+
+    Material is a collection of parameters that describe how the surface of an
+    object reflects light. The parameters are used by the shading algorithm to
+    determine the color of a surface at a given point.
+
+    Materials are identified by a it's name, the name should be unique.
+    """
+    def __new__(cls, name: str | None = ...) -> Material:
+        ...
+    @name.setter
+    def name(self, name: str) -> None:
+        ...
+    @property
+    def name(self) -> str:
+        ...
+    @diffuse.setter
+    def diffuse(self, kd: Color) -> None:
+        ...
+    @kd.setter
+    def kd(self, kd: list[float]) -> None:
+        ...
+    @property
+    def diffuse(self) -> list[float] | None:
+        ...
+    @ambient.setter
+    def ambient(self, ka: Color) -> None:
+        ...
+    @property
+    def ambient(self) -> list[float] | None:
+        ...
+    @specular.setter
+    def specular(self, ks: Color) -> None:
+        ...
+    @property
+    def specular(self) -> list[float] | None:
+        ...
+    @shininess.setter
+    def shininess(self, ns: float) -> None:
+        ...
+    @property
+    def shininess(self) -> float | None:
+        ...
+    @illum_model.setter
+    def illum_model(self, illum: IllumModel) -> None:
+        ...
+    @property
+    def illum_model(self) -> IllumModel | None:
+        ...
+    @reflectivity.setter
+    def reflectivity(self, reflectivity: float) -> None:
+        ...
+    @property
+    def reflectivity(self) -> float | None:
+        ...
+    @emissive.setter
+    def emissive(self, ke: Color) -> None:
+        ...
+    @property
+    def emissive(self) -> list[float] | None:
+        ...
+    @roughness.setter
+    def roughness(self, roughness: float) -> None:
+        ...
+    @property
+    def roughness(self) -> float | None:
+        ...
+    @metallic.setter
+    def metallic(self, metallic: float) -> None:
+        ...
+    @property
+    def metallic(self) -> float | None:
+        ...
+    @sheen.setter
+    def sheen(self, sheen: float) -> None:
+        ...
+    @property
+    def sheen(self) -> float | None:
+        ...
+    @double_sided.setter
+    def double_sided(self, double_sided: bool) -> None:
+        """Sets whether both sides of the surfaces cast shadows."""
+    @property
+    def double_sided(self) -> bool:
+        ...
+    @uv_offset.setter
+    def uv_offset(self, offset: list[float]) -> None:
+        """Sets the offset added to the texture coordinates."""
+    @property
+    def uv_offset(self) -> list[float]:
+        ...
+    @uv_scale.setter
+    def uv_scale(self, scale: list[float]) -> None:
+        """Sets how many times the textures repeat over the UV square, along U
+        and V.
+        """
+    @property
+    def uv_scale(self) -> list[float]:
+        ...
+    @uv_rotation.setter
+    def uv_rotation(self, rotation: float) -> None:
+        """Sets the counter-clockwise rotation in radians of the texture
+        coordinates.
+        """
+    @property
+    def uv_rotation(self) -> float:
+        ...
+    @textures.setter
+    def textures(self, textures: dict[Any, Any]) -> None:
+        """Sets the textures for the material.
+
+        The textures are passed as a dictionary where the key is the texture
+        type and the value is the path to the texture, or a `Texture` to
+        sample it with a specific sampler.
+        """
+
+
+class ConcatOrder:
+    """The order in which transforms are concatenated. The transformation
+    result is in the reverse order of concatenation.
+    """
+    Pre: ClassVar[ConcatOrder]
+    """The transform is concatenated before the current one."""
+    Post: ClassVar[ConcatOrder]
+    """The transform is concatenated after the current one."""
+
+
+class Alignment:
+    """The alignment of a plane."""
+    XY: ClassVar[Alignment]
+    """The XY plane."""
+    XZ: ClassVar[Alignment]
+    """The XZ plane."""
+    YZ: ClassVar[Alignment]
+    """The YZ plane."""
+
+
+class Color:
+    """Linear color representation."""
+    DARK_GREY: ClassVar[Color]
+    PURPLISH_GREY: ClassVar[Color]
+    VERY_LIGHT_PINK: ClassVar[Color]
+    PEACHY_PINK: ClassVar[Color]
+    LIGHT_PERIWINKLE: ClassVar[Color]
+    CLOUDY_BLUE: ClassVar[Color]
+    ICE_BLUE: ClassVar[Color]
+    LIGHT_LAVENDER: ClassVar[Color]
+    GREENISH_GREY: ClassVar[Color]
+    WASHED_OUT_GREEN: ClassVar[Color]
+    LIGHT_KHAKI: ClassVar[Color]
+    PALE: ClassVar[Color]
+    PINKISH_TAN: ClassVar[Color]
+    VERY_LIGHT_BROWN: ClassVar[Color]
+    BUFF: ClassVar[Color]
+    OFF_WHITE: ClassVar[Color]
+    WHITE: ClassVar[Color]
+    BLACK: ClassVar[Color]
+    RED: ClassVar[Color]
+    GREEN: ClassVar[Color]
+    BLUE: ClassVar[Color]
+    YELLOW: ClassVar[Color]
+    CYAN: ClassVar[Color]
+    MAGENTA: ClassVar[Color]
+    ORANGE: ClassVar[Color]
+    PURPLE: ClassVar[Color]
+    PINK: ClassVar[Color]
+    LIME: ClassVar[Color]
+    TEAL: ClassVar[Color]
+    LAVENDER: ClassVar[Color]
+    BROWN: ClassVar[Color]
+    BEIGE: ClassVar[Color]
+    MAROON: ClassVar[Color]
+    MINT: ClassVar[Color]
+    def __new__(cls, r: float, g: float, b: float) -> Color:
+        ...
+    @staticmethod
+    def from_srgb(r: float, g: float, b: float, a: float = ...) -> Color:
+        ...
+    @staticmethod
+    def from_hsv(h: float, s: float, v: float) -> Color:
+        ...
+    @staticmethod
+    def from_hsl(h: float, s: float, l: float) -> Color:
+        ...
+    @staticmethod
+    def from_hex(hex: str) -> Color:
+        ...
+    def to_srgb(self) -> list[float]:
+        ...
+    def to_hsv(self) -> tuple[float, float, float]:
+        ...
+    def lerp(self, other: Color, t: float) -> Color:
+        ...
+
+
+class Gradient:
+    """Colors evaluated along `[0, 1]` by interpolating between color stops,
+    e.g. to map data such as sunlight scores to colors.
+    """
+    def __new__(cls, stops: Any) -> Gradient:
+        """Creates a gradient from a list of colors evenly spaced over `[0, 1]`
+        or of `(position, color)` stops.
+        """
+    @staticmethod
+    def viridis() -> Gradient:
+        ...
+    @staticmethod
+    def inferno() -> Gradient:
+        ...
+    def evaluate(self, t: float) -> Color:
+        ...
+    def evaluate_array(self, values: npt.NDArray[np.float32]) -> npt.NDArray[np.float32]:
+        """Evaluates the gradient at each value of the array, returning an array
+        of the same shape with an extra axis for the linear RGBA components.
+        """
+
+
+class IllumModel:
+    ColorOnAmbientOff: ClassVar[IllumModel]
+    ColorOnAmbientOn: ClassVar[IllumModel]
+    HighlightOn: ClassVar[IllumModel]
+    ReflectionOnRayTraceOn: ClassVar[IllumModel]
+    TransparencyGlassOnReflectionRayTraceOn: ClassVar[IllumModel]
+    ReflectionFresnelOnRayTraceOn: ClassVar[IllumModel]
+    TransparencyRefractionOnReflectionFresnelOffRayTraceOn: ClassVar[IllumModel]
+    TransparencyRefractionOnReflectionFresnelOnRayTraceOn: ClassVar[IllumModel]
+    ReflectionOnRayTraceOff: ClassVar[IllumModel]
+    TransparencyGlassOnReflectionRayTraceOff: ClassVar[IllumModel]
+    CastsShadowsOntoInvisibleSurfaces: ClassVar[IllumModel]
+    DiffuseNoShading: ClassVar[IllumModel]
+    SpecularNoShading: ClassVar[IllumModel]
+    TextureCoordinates: ClassVar[IllumModel]
+    NormalInViewSpace: ClassVar[IllumModel]
+
+
+class TextureType:
+    """Texture type."""
+    MapKa: ClassVar[TextureType]
+    MapKd: ClassVar[TextureType]
+    MapKs: ClassVar[TextureType]
+    MapNs: ClassVar[TextureType]
+    MapD: ClassVar[TextureType]
+    MapBump: ClassVar[TextureType]
+    MapDisp: ClassVar[TextureType]
+    MapDecal: ClassVar[TextureType]
+    MapNorm: ClassVar[TextureType]
+    MapKe: ClassVar[TextureType]
+    MapPr: ClassVar[TextureType]
+    MapPm: ClassVar[TextureType]
+    MapPs: ClassVar[TextureType]
+    Unknown: ClassVar[TextureType]
+
+
+class Texture:
+    """Texture of a material, read from an image file and sampled with the
+    sampler of the given name, see `App.register_sampler`.
+    """
+    def __new__(cls, path: str, sampler: str | None = ...) -> Texture:
+        ...
+    @property
+    def path(self) -> str:
+        ...
+    @property
+    def sampler(self) -> str | None:
+        ...
+    def set_sampler(self, name: str) -> None:
+        """Sets the name of the sampler the texture is sampled with, e.g.
+        `clamp_nearest`, `linear_aniso8` or a name registered with
+        `App.register_sampler`.
+        """
+    def __repr__(self) -> str:
+        ...
+
+
+class TextureMemoryStats:
+    """Memory statistics of the textures kept on the GPU."""
+    @property
+    def budget(self) -> int | None:
+        """Maximum number of bytes of the textures, unlimited if `None`."""
+    @property
+    def resident(self) -> int:
+        """Number of bytes of the textures on the GPU."""
+    @property
+    def resident_count(self) -> int:
+        """Number of textures on the GPU."""
+    @property
+    def evicted_count(self) -> int:
+        """Number of textures evicted from the GPU, uploaded again when used."""
+    @property
+    def evictions(self) -> int:
+        """Number of textures evicted during the last frame."""
+    @property
+    def reloads(self) -> int:
+        """Number of evicted textures uploaded again during the last frame."""
+
+
+class LightKind:
+    """Kind of a light source, see [`Light`]."""
+    Directional: ClassVar[LightKind]
+    """A directional light, e.g. the sun."""
+    Point: ClassVar[LightKind]
+    """A point light."""
+
+
+class Billboard:
+    """Billboard component making an entity always face the main camera.
+
+    The entity is rotated so that its local +Z axis points towards the camera.
+    """
+    Spherical: ClassVar[Billboard]
+    """The entity rotates freely to face the camera."""
+    Cylindrical: ClassVar[Billboard]
+    """The entity only rotates around the world Y axis."""
+
+
+class PyEntity:
+    """Entity with a command sender."""
+    def draw(self) -> None:
+        ...
+    def set_visible(self, visible: bool) -> None:
+        ...
+    def set_cast_shadows(self, cast_shadows: bool) -> None:
+        ...
+    def set_transform(self, mat4: npt.NDArray[np.float32]) -> None:
+        ...
+    def get_position(self) -> npt.NDArray[np.float32]:
+        """Returns the position of the entity relative to its parent."""
+    def get_rotation_quat(self) -> npt.NDArray[np.float32]:
+        """Returns the rotation of the entity relative to its parent as a
+        quaternion (w, x, y, z).
+        """
+    def get_scale(self) -> npt.NDArray[np.float32]:
+        """Returns the scale of the entity relative to its parent."""
+    def get_world_position(self) -> npt.NDArray[np.float32]:
+        """Returns the position of the entity in world space."""
+    def get_world_rotation_quat(self) -> npt.NDArray[np.float32]:
+        """Returns the rotation of the entity in world space as a quaternion
+        (w, x, y, z).
+        """
+    def get_world_scale(self) -> npt.NDArray[np.float32]:
+        """Returns the scale of the entity in world space."""
+    def set_position(self, position: npt.NDArray[np.float32]) -> None:
+        """Sets the position of the entity relative to its parent."""
+    def set_world_position(self, position: npt.NDArray[np.float32]) -> None:
+        """Sets the position of the entity in world space."""
+    def set_rotation_quat(self, quat: list[float]) -> None:
+        """Sets the rotation of the entity relative to its parent from a
+        quaternion (w, x, y, z).
+        """
+    def set_world_rotation_quat(self, quat: list[float]) -> None:
+        """Sets the rotation of the entity in world space from a quaternion
+        (w, x, y, z).
+        """
+    def look_at(self, target: npt.NDArray[np.float32], up: npt.NDArray[np.float32] | None = ...) -> None:
+        """Rotates the entity so that its -Z axis points to the target position
+        given in world space.
+        """
+    def set_billboard(self, billboard: Billboard | None) -> None:
+        """Makes the entity always face the main camera. Passing `None` restores
+        the normal behaviour.
+        """
+    def set_scale(self, scale: npt.NDArray[np.float32]) -> None:
+        """Sets the scale of the entity relative to its parent."""
+    def rotate(self, rotation: npt.NDArray[np.float32], order: ConcatOrder) -> None:
+        ...
+    def translate(self, translation: npt.NDArray[np.float32], order: ConcatOrder) -> None:
+        ...
+    def scale(self, scale: npt.NDArray[np.float32], order: ConcatOrder) -> None:
+        ...
+    def use_material(self, material: int) -> None:
+        """Sets the material to use. This will override the material set by the
+        submesh. If the material index is out of bounds of all the materials
+        of the entity, the command will set the material to the last material
+        of the entity.
+        """
+    def set_as_main_camera(self) -> None:
+        """Sets the entity as the main camera only if the entity has a camera
+        component. Otherwise, this function does nothing.
+        """
+    def set_name(self, name: str) -> None:
+        """Sets the name of the entity. The name can be used later to look up
+        the entity with `find_entity` or `find_entities`.
+        """
+    def set_layer(self, layer: int) -> None:
+        """Sets the render layer of the entity. Entities on higher layers are
+        drawn after entities on lower layers. Layers range from 0 to 31.
+        """
+    def set_shader(self, path: str | None) -> None:
+        """Draws the entity with a custom material shader loaded from the given
+        WGSL file, or with the default shader if `None`. The shader is
+        reloaded when the file is modified.
+        """
+    def set_layer_mask(self, mask: int) -> None:
+        """Sets the layers visible to the camera. Bit `i` of the mask enables
+        layer `i`. Does nothing if the entity is not a camera.
+        """
+    def add_rigid_body(self, mass: float = ...) -> None:
+        """Adds a rigid body to the entity, simulated from its current world
+        transform. The body is fixed if the mass is not positive.
+
+        Colliders added afterwards are attached to the body.
+        """
+    def add_collider(self, shape: ColliderShape = ...) -> None:
+        """Adds a collider enclosing the mesh of the entity."""
+    def set_fixed_aspect(self, aspect: float | None = ...) -> None:
+        """Fixes the aspect ratio of the image of the camera, letterboxing it in
+        the window, or makes it follow the window if `None`. Does nothing if
+        the entity is not a camera.
+        """
+    def set_fov(self, fov: float) -> None:
+        """Sets the vertical field of view of the camera in degrees, from the
+        next frame on. Does nothing if the entity is not a camera or if its
+        projection is orthographic.
+        """
+    def set_clip_planes(self, near: float, far: float) -> None:
+        """Sets the distances of the near and far clipping planes of the camera,
+        from the next frame on. The far plane may be infinitely far for
+        perspective projections. Does nothing if the entity is not a camera.
+        """
+    def set_background(self, color: Color) -> None:
+        """Sets the background color of the camera and removes its gradient or
+        image background. Does nothing if the entity is not a camera.
+        """
+    def set_background_gradient(self, top: Color, bottom: Color) -> None:
+        """Draws a vertical gradient behind the scene seen by the camera. Does
+        nothing if the entity is not a camera.
+        """
+    def set_background_image(self, path: str) -> None:
+        """Draws an image covering the screen behind the scene seen by the
+        camera. Does nothing if the entity is not a camera.
+        """
+    def set_background_sky(self, turbidity: float = ..., exposure: float = ..., sun_direction: npt.NDArray[np.float32] | None = ..., ground: Color | None = ...) -> None:
+        """Draws an analytical sky behind the scene seen by the camera, with the
+        sun disk and the ground below the horizon. Does nothing if the entity
+        is not a camera.
+
+        # Arguments
+
+        * `turbidity` - Haziness of the atmosphere, from 2 for a clear sky to
+          10 for a hazy one.
+        * `exposure` - Scale of the brightness of the sky.
+        * `sun_direction` - Direction towards the sun; follows the day/night
+          cycle of the main camera if enabled.
+        * `ground` - Color of the ground below the horizon.
+        """
+    def set_exposure(self, ev: float) -> None:
+        """Sets the exposure of the camera in EV: the lit colors are scaled by
+        `2^ev`. With auto-exposure, it compensates the adapted exposure. Does
+        nothing if the entity is not a camera.
+        """
+    def set_auto_exposure(self, enabled: bool = ..., key: float = ..., speed: float = ..., min_ev: float = ..., max_ev: float = ...) -> None:
+        """Adapts the exposure of the camera over time to the average luminance
+        of the previous frames, only for the main window. Does nothing if the
+        entity is not a camera.
+
+        # Arguments
+
+        * `enabled` - Disables the auto-exposure if false.
+        * `key` - Average luminance the frames are brought to.
+        * `speed` - Rate of the adaptation per second.
+        * `min_ev` - Lowest adapted exposure in EV.
+        * `max_ev` - Highest adapted exposure in EV.
+        """
+    def add_tag(self, tag: str) -> None:
+        """Adds a tag to the entity. An entity can have multiple tags."""
+    def remove_tag(self, tag: str) -> None:
+        """Removes a tag from the entity."""
+    def attach_script(self, script: Any) -> None:
+        """Attaches a script to the entity: an object whose method
+        `update(entity, input, dt)` is called each frame with the entity,
+        after the camera controls and before the `on_update` event.
+
+        The scripts run by node order, the ones of a parent before the ones
+        of its children, and the scripts of an entity in the order they were
+        attached. They stop with the entity.
+        """
+    def detach_script(self, script: Any) -> None:
+        """Detaches a script attached with `attach_script`."""
+    def get_scripts(self) -> list[Any]:
+        """Returns the scripts attached to the entity, in the order they run."""
+    def despawn(self) -> None:
+        """Removes the entity and all its descendants from the scene."""
+    def clear_material_override(self) -> None:
+        ...
+    def set_directional_light(self, direction: npt.NDArray[np.float32]) -> None:
+        ...
+    def set_light_color(self, color: Color) -> None:
+        """Sets the color of the light attached to the entity."""
+    def set_casts_shadows(self, casts_shadows: bool) -> None:
+        """Sets whether the light attached to the entity casts shadows. Only
+        directional lights cast shadows, each into its own shadow map.
+        """
+    def get_light_kind(self) -> LightKind | None:
+        """Returns the kind of the light attached to the entity, `None` if the
+        entity has no light.
+        """
+    def __repr__(self) -> str:
+        ...
+    def __eq__(self, other: PyEntity) -> bool:
+        """Entities are equal if they refer to the same entity of the scene."""
+    def __hash__(self) -> int:
+        ...
+
+
+class Scene:
+    """Handle to one of the scenes of the application, see `App.create_scene`.
+
+    The entities of a scene can only be accessed while it is the active
+    scene, the commands sent to them in the meantime are applied once it is
+    active again.
+    """
+    @property
+    def id(self) -> int:
+        """Identifier of the scene, the first scene of the application is 0."""
+    def __eq__(self, other: Scene) -> bool:
+        ...
+    def __hash__(self) -> int:
+        ...
+    def __repr__(self) -> str:
+        ...
+
+
+class Prefab:
+    """Template of an entity hierarchy captured from a scene, see
+    `App.capture_prefab`.
+
+    The meshes and materials of the entities are shared by all the instances
+    of the prefab, only the nodes and the components are duplicated.
+    """
+    def __len__(self) -> int:
+        """Number of entities instantiated by the prefab."""
+    def __repr__(self) -> str:
+        ...
+
+
+class ParticleEmitter:
+    """Particle emitter component.
+
+    Particles are spawned at the position of the node of the emitter, then
+    simulated and drawn on the GPU as camera-facing quads. The color of a
+    particle is interpolated from `start_color` to `end_color` over its life.
+    """
+    rate: float
+    """Number of particles spawned per second."""
+    lifetime: float
+    """Life time of a particle in seconds."""
+    velocity: list[float]
+    """Mean initial velocity of the particles."""
+    velocity_spread: float
+    """Random variation added to each component of the initial velocity."""
+    gravity: list[float]
+    """Constant acceleration applied to the particles."""
+    size: float
+    """Size of a particle in world units."""
+    start_color: Color
+    """Color of a particle when spawned."""
+    end_color: Color
+    """Color of a particle when it dies."""
+    @property
+    def max_particles(self) -> int:
+        """Maximum number of particles alive at the same time."""
+    def __new__(cls, rate: float = ..., lifetime: float = ..., max_particles: int = ...) -> ParticleEmitter:
+        ...
+
+
+class Water:
+    """Water surface component.
+
+    The surface is a horizontal plane through the node of the entity, scaled
+    by the node's scale. Waves are animated in the shader by perturbing the
+    vertices and the normals with a sum of directional sine waves; the color
+    blends between `color` and `sky_color` following Fresnel's law.
+
+    With `reflection`, the scene is rendered mirrored below the surface into
+    an offscreen texture which replaces `sky_color`. Only the first visible
+    reflective surface is mirrored, the others keep their sky color.
+    """
+    color: Color
+    """Color of the water seen from above."""
+    sky_color: Color
+    """Color reflected by the water at grazing angles."""
+    wave_amplitude: float
+    """Height of the waves in world units."""
+    wave_length: float
+    """Length of the longest wave in world units."""
+    wave_speed: float
+    """Speed of the waves in world units per second."""
+    reflection: bool
+    """Whether the surface reflects the scene, rendered a second time from
+    the main camera mirrored below it.
+    """
+    def __new__(cls) -> Water:
+        ...
+
+
+class ColliderShape:
+    """Shape of the collider created from the mesh of an entity."""
+    Aabb: ClassVar[ColliderShape]
+    """Axis-aligned bounding box of the mesh."""
+    ConvexHull: ClassVar[ColliderShape]
+    """Convex hull of the vertices of the mesh."""
+
+
+class OscTarget:
+    """Property of an entity driven by an OSC address, see `App.bind_osc`."""
+    PositionX: ClassVar[OscTarget]
+    """Local position along the X axis."""
+    PositionY: ClassVar[OscTarget]
+    """Local position along the Y axis."""
+    PositionZ: ClassVar[OscTarget]
+    """Local position along the Z axis."""
+    RotationX: ClassVar[OscTarget]
+    """Local rotation in radians around the X axis."""
+    RotationY: ClassVar[OscTarget]
+    """Local rotation in radians around the Y axis."""
+    RotationZ: ClassVar[OscTarget]
+    """Local rotation in radians around the Z axis."""
+    Scale: ClassVar[OscTarget]
+    """Uniform local scale."""
+    Visible: ClassVar[OscTarget]
+    """Visibility, visible if the value is greater than 0.5."""
+    Material: ClassVar[OscTarget]
+    """Material override, the value being rounded to the index of the
+    material.
+    """
@@ -0,0 +1,218 @@
+use crate::core::{camera::Easing, Transform};
+use glam::{Quat, Vec3};
+use std::collections::VecDeque;
+
+/// Viewpoint the camera moves to.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraWaypoint {
+    /// Position of the camera.
+    pub position: Vec3,
+    /// Position the camera looks at.
+    pub look_at: Vec3,
+    /// Duration of the move in seconds.
+    pub duration: f32,
+    /// Easing of the move.
+    pub easing: Easing,
+}
+
+/// Move of the camera in progress.
+#[derive(Clone, Copy, Debug)]
+struct CameraMove {
+    from: (Vec3, Quat),
+    to: (Vec3, Quat),
+    duration: f32,
+    easing: Easing,
+    elapsed: f32,
+}
+
+/// Animates the camera through a queue of waypoints.
+#[derive(Clone, Debug, Default)]
+pub struct CameraAnimator {
+    /// Waypoints waiting to be reached, in order.
+    waypoints: VecDeque<CameraWaypoint>,
+    /// Move towards the current waypoint.
+    current: Option<CameraMove>,
+}
+
+impl CameraAnimator {
+    /// Queues a waypoint, reached after the already queued ones.
+    pub fn push(&mut self, waypoint: CameraWaypoint) {
+        self.waypoints.push_back(waypoint);
+    }
+
+    /// Stops the animation and removes all the waypoints.
+    pub fn clear(&mut self) {
+        self.waypoints.clear();
+        self.current = None;
+    }
+
+    /// Returns true if the camera is moving or has waypoints to reach.
+    pub fn is_animating(&self) -> bool {
+        self.current.is_some() || !self.waypoints.is_empty()
+    }
+
+    /// Advances the animation by `dt` seconds, starting from the current
+    /// transform of the camera.
+    ///
+    /// Returns the new position and rotation of the camera, and whether a
+    /// waypoint has been reached, or `None` if the camera is not animated.
+    pub fn advance(&mut self, dt: f32, camera: &Transform) -> Option<(Vec3, Quat, bool)> {
+        if self.current.is_none() {
+            let waypoint = self.waypoints.pop_front()?;
+            let mut target = Transform::from_translation(waypoint.position);
+            // Avoid gimbal lock.
+            let forward = (waypoint.look_at - waypoint.position).normalize_or_zero();
+            let up = if forward.y.abs() > 0.999 {
+                Vec3::Z
+            } else {
+                Vec3::Y
+            };
+            target.looking_at(waypoint.look_at, up);
+            self.current = Some(CameraMove {
+                from: (camera.translation, camera.rotation),
+                to: (target.translation, target.rotation),
+                duration: waypoint.duration,
+                easing: waypoint.easing,
+                elapsed: 0.0,
+            });
+        }
+
+        let current = self.current.as_mut()?;
+        current.elapsed += dt;
+        let t = if current.duration > 0.0 {
+            current.elapsed / current.duration
+        } else {
+            1.0
+        };
+        let s = current.easing.apply(t);
+        let position = current.from.0.lerp(current.to.0, s);
+        let rotation = current.from.1.slerp(current.to.1, s);
+        let arrived = t >= 1.0;
+        if arrived {
+            self.current = None;
+        }
+        Some((position, rotation, arrived))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-5;
+
+    fn waypoint(position: Vec3, look_at: Vec3, duration: f32, easing: Easing) -> CameraWaypoint {
+        CameraWaypoint {
+            position,
+            look_at,
+            duration,
+            easing,
+        }
+    }
+
+    /// Returns the direction the rotation makes the camera look at.
+    fn forward(rotation: Quat) -> Vec3 {
+        rotation * Vec3::NEG_Z
+    }
+
+    #[test]
+    fn interpolates_from_the_camera_to_the_waypoint() {
+        let camera = Transform::identity();
+        let mut animator = CameraAnimator::default();
+        animator.push(waypoint(
+            Vec3::new(10.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            2.0,
+            Easing::Linear,
+        ));
+
+        let (position, rotation, arrived) = animator.advance(0.0, &camera).unwrap();
+        assert!(position.abs_diff_eq(Vec3::ZERO, EPSILON));
+        assert!(forward(rotation).abs_diff_eq(Vec3::NEG_Z, EPSILON));
+        assert!(!arrived);
+
+        let (position, rotation, arrived) = animator.advance(1.0, &camera).unwrap();
+        assert!(position.abs_diff_eq(Vec3::new(5.0, 0.0, 0.0), EPSILON));
+        let halfway = Vec3::new(-1.0, 0.0, -1.0).normalize();
+        assert!(forward(rotation).abs_diff_eq(halfway, EPSILON));
+        assert!(!arrived);
+
+        let (position, rotation, arrived) = animator.advance(1.0, &camera).unwrap();
+        assert!(position.abs_diff_eq(Vec3::new(10.0, 0.0, 0.0), EPSILON));
+        assert!(forward(rotation).abs_diff_eq(Vec3::NEG_X, EPSILON));
+        assert!(arrived);
+        assert!(!animator.is_animating());
+        assert!(animator.advance(1.0, &camera).is_none());
+    }
+
+    #[test]
+    fn easing_shapes_the_move() {
+        let camera = Transform::identity();
+        let mut animator = CameraAnimator::default();
+        animator.push(waypoint(
+            Vec3::new(0.0, 0.0, 8.0),
+            Vec3::new(0.0, 0.0, 0.0),
+            4.0,
+            Easing::EaseInOut,
+        ));
+        let (position, _, _) = animator.advance(1.0, &camera).unwrap();
+        assert!((position.z - 8.0 * Easing::EaseInOut.apply(0.25)).abs() < EPSILON);
+        let (position, _, _) = animator.advance(1.0, &camera).unwrap();
+        assert!((position.z - 4.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn overshooting_steps_stop_at_the_waypoint() {
+        let camera = Transform::identity();
+        let mut animator = CameraAnimator::default();
+        let target = Vec3::new(0.0, 3.0, 0.0);
+        animator.push(waypoint(target, Vec3::ZERO, 1.0, Easing::Linear));
+        let (position, _, arrived) = animator.advance(5.0, &camera).unwrap();
+        assert!(position.abs_diff_eq(target, EPSILON));
+        assert!(arrived);
+
+        // Moves without duration complete at once.
+        animator.push(waypoint(Vec3::ONE, Vec3::ZERO, 0.0, Easing::Linear));
+        let (position, _, arrived) = animator.advance(0.0, &camera).unwrap();
+        assert!(position.abs_diff_eq(Vec3::ONE, EPSILON));
+        assert!(arrived);
+    }
+
+    #[test]
+    fn waypoints_are_reached_in_order() {
+        let mut camera = Transform::identity();
+        let mut animator = CameraAnimator::default();
+        let first = Vec3::new(2.0, 0.0, 0.0);
+        let second = Vec3::new(2.0, 0.0, 4.0);
+        animator.push(waypoint(first, Vec3::ZERO, 1.0, Easing::Linear));
+        animator.push(waypoint(second, Vec3::ZERO, 1.0, Easing::Linear));
+
+        let (position, rotation, arrived) = animator.advance(1.0, &camera).unwrap();
+        assert!(position.abs_diff_eq(first, EPSILON));
+        assert!(arrived);
+        assert!(animator.is_animating());
+        camera.translation = position;
+        camera.rotation = rotation;
+
+        // The next move starts from where the camera is.
+        let (position, _, arrived) = animator.advance(0.5, &camera).unwrap();
+        assert!(position.abs_diff_eq((first + second) * 0.5, EPSILON));
+        assert!(!arrived);
+        let (position, _, arrived) = animator.advance(0.5, &camera).unwrap();
+        assert!(position.abs_diff_eq(second, EPSILON));
+        assert!(arrived);
+        assert!(!animator.is_animating());
+    }
+
+    #[test]
+    fn clear_stops_the_animation() {
+        let camera = Transform::identity();
+        let mut animator = CameraAnimator::default();
+        animator.push(waypoint(Vec3::X, Vec3::ZERO, 1.0, Easing::Linear));
+        animator.push(waypoint(Vec3::Y, Vec3::ZERO, 1.0, Easing::Linear));
+        animator.advance(0.5, &camera).unwrap();
+        animator.clear();
+        assert!(!animator.is_animating());
+        assert!(animator.advance(0.5, &camera).is_none());
+    }
+}
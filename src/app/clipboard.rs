@@ -0,0 +1,37 @@
+//! Access to the text of the system clipboard.
+
+use std::sync::Mutex;
+
+/// Clipboard of the system, kept open as on some platforms (e.g. X11) the
+/// text set by the application is only served while it is alive.
+static CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+
+/// Calls `f` on the clipboard, opened the first time it is used.
+fn with_clipboard<R>(
+    f: impl FnOnce(&mut arboard::Clipboard) -> Result<R, arboard::Error>,
+) -> Result<R, arboard::Error> {
+    let mut clipboard = CLIPBOARD.lock().unwrap();
+    if clipboard.is_none() {
+        *clipboard = Some(arboard::Clipboard::new()?);
+    }
+    f(clipboard.as_mut().unwrap())
+}
+
+/// Returns the text of the clipboard, `None` if it holds no text or can't
+/// be read.
+pub fn clipboard_text() -> Option<String> {
+    match with_clipboard(|clipboard| clipboard.get_text()) {
+        Ok(text) => Some(text),
+        Err(arboard::Error::ContentNotAvailable) => None,
+        Err(err) => {
+            log::warn!("Failed to read the clipboard: {}", err);
+            None
+        }
+    }
+}
+
+/// Sets the text of the clipboard.
+pub fn set_clipboard_text(text: &str) -> Result<(), String> {
+    with_clipboard(|clipboard| clipboard.set_text(text))
+        .map_err(|err| format!("Failed to set the clipboard: {}", err))
+}
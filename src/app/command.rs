@@ -1,5 +1,16 @@
-use crate::{core::ConcatOrder, scene::Entity};
+#[cfg(feature = "physics")]
+use crate::physics::ColliderShape;
+use crate::{
+    core::{
+        camera::{AutoExposure, Backdrop},
+        Color, ConcatOrder, SmlString,
+    },
+    render::{RenderParams, SamplerConfig},
+    scene::{Billboard, Entity},
+};
 use glam::{Quat, Vec3};
+use pyo3::PyObject;
+use std::path::PathBuf;
 
 /// Possible commands that can be executed.
 #[derive(Debug, Clone)]
@@ -42,6 +53,34 @@ pub enum Command {
         rotation: Quat,
         scale: Vec3,
     },
+    /// Sets the position of the entity, either in the local space or in the
+    /// world space.
+    SetPosition {
+        entity: Entity,
+        position: Vec3,
+        world: bool,
+    },
+    /// Sets the rotation of the entity, either in the local space or in the
+    /// world space.
+    SetRotation {
+        entity: Entity,
+        rotation: Quat,
+        world: bool,
+    },
+    /// Rotates the entity so that its -Z axis points to the target position
+    /// in world space.
+    LookAt {
+        entity: Entity,
+        target: Vec3,
+        up: Vec3,
+    },
+    /// Makes the entity always face the main camera, or stops it if `None`.
+    SetBillboard {
+        entity: Entity,
+        billboard: Option<Billboard>,
+    },
+    /// Sets the scale of the entity in the local space.
+    SetScale { entity: Entity, scale: Vec3 },
     /// Sets if the entity is active or not.
     SetActive { entity: Entity, active: bool },
     /// Sets if the entity is visible or not.
@@ -57,18 +96,133 @@ pub enum Command {
     SetAsMainCamera { entity: Entity },
     /// Sets the direction of the directional light.
     SetDirectionalLight { entity: Entity, direction: Vec3 },
+    /// Sets the color of a light.
+    SetLightColor { entity: Entity, color: Color },
     /// Clears the material override.
     ClearMaterialOverride { entity: Entity },
+    /// Sets the name of the entity.
+    SetName { entity: Entity, name: SmlString },
+    /// Adds a tag to the entity.
+    AddTag { entity: Entity, tag: SmlString },
+    /// Removes a tag from the entity.
+    RemoveTag { entity: Entity, tag: SmlString },
+    /// Attaches a Python script to the entity.
+    AttachScript { entity: Entity, script: PyObject },
+    /// Detaches a Python script from the entity.
+    DetachScript { entity: Entity, script: PyObject },
+    /// Sets the visibility of all entities with the given tag.
+    SetVisibleByTag { tag: SmlString, visible: bool },
+    /// Sets the render layer of the entity.
+    SetRenderLayer { entity: Entity, layer: u8 },
+    /// Sets the custom material shader of the entity, or restores the default
+    /// shader if `None`.
+    SetCustomShader {
+        entity: Entity,
+        path: Option<PathBuf>,
+    },
+    /// Sets the layers visible to the camera entity.
+    SetLayerMask { entity: Entity, mask: u32 },
+    /// Sets the background color of the camera entity.
+    SetBackground { entity: Entity, color: Color },
+    /// Fixes the aspect ratio of the camera entity, or makes it follow the
+    /// window if `None`.
+    SetFixedAspect { entity: Entity, aspect: Option<f32> },
+    /// Sets the vertical field of view in degrees and the clipping planes of
+    /// the camera entity, keeping the current ones where `None`.
+    SetCameraParams {
+        entity: Entity,
+        fov: Option<f32>,
+        near: Option<f32>,
+        far: Option<f32>,
+    },
+    /// Sets the size in pixels of the window the cameras render to.
+    ResizeViewport { width: u32, height: u32 },
+    /// Adds a rigid body of the given mass to the entity, fixed if the mass
+    /// is not positive.
+    #[cfg(feature = "physics")]
+    AddRigidBody { entity: Entity, mass: f32 },
+    /// Adds a collider enclosing the mesh of the entity.
+    #[cfg(feature = "physics")]
+    AddCollider {
+        entity: Entity,
+        shape: ColliderShape,
+    },
+    /// Sets the gravity of the physics simulation.
+    #[cfg(feature = "physics")]
+    SetGravity(Vec3),
+    /// Sets the backdrop of the camera entity.
+    SetBackdrop {
+        entity: Entity,
+        backdrop: Option<Backdrop>,
+    },
+    /// Sets the exposure in EV of the camera entity.
+    SetExposure { entity: Entity, ev: f32 },
+    /// Enables the auto-exposure of the camera entity, or disables it if
+    /// `None`.
+    SetAutoExposure {
+        entity: Entity,
+        auto: Option<AutoExposure>,
+    },
+    /// Moves the sun of the sky backdrop of the camera entity, if it has
+    /// one, `sun` pointing towards the sun.
+    SetSkySun { entity: Entity, sun: Vec3 },
+    /// Removes the entity and all its descendants from the scene.
+    Despawn { entity: Entity },
+    /// Removes all entities with the given tag (and their descendants) from
+    /// the scene.
+    DespawnByTag { tag: SmlString },
+    /// Records the entity spawned in the journal of the scene, in order with
+    /// the commands sent before. Sent by the scene itself when spawning.
+    Spawned { entity: Entity },
+    /// Groups the following changes of the entities into a single
+    /// transaction of the journal of the scene, until `EndTransaction`.
+    BeginTransaction,
+    /// Ends the transaction started by the matching `BeginTransaction`.
+    EndTransaction,
     /// Enables or disables backface culling.
     EnableBackfaceCulling(bool),
     /// Enables or disables wireframe rendering.
     EnableWireframe(bool),
+    /// Shows or hides the debug gizmos of the lights and cameras.
+    ShowDebugGizmos(bool),
     /// Enables or disables shadwos.
     EnableShadows(bool),
     /// Updates manually the shadow map orthographic projection.
     UpdateShadowMapOrthoProj(f32),
     /// Enables or disables the lighting.
     EnableLighting(bool),
+    /// Enables or disables the occlusion culling.
+    EnableOcclusionCulling(bool),
+    /// Enables or disables the culling of the instances on the GPU.
+    EnableGpuCulling(bool),
+    /// Enables or disables the depth pre-pass.
+    EnableDepthPrepass(bool),
+    /// Enables or disables the reloading of mesh and texture files modified
+    /// on disk.
+    EnableHotReload(bool),
+    /// Replaces all the rendering parameters at once.
+    SetRenderParams(Box<RenderParams>),
+    /// Enables or disables the synchronization of the frames with the
+    /// display refresh rate.
+    SetVsync(bool),
+    /// Caps the number of frames rendered per second, or uncaps it if
+    /// `None`.
+    SetTargetFps(Option<f32>),
+    /// Sets the directory from which the shaders are loaded, or restores the
+    /// embedded shaders if `None`.
+    SetShaderDirectory(Option<PathBuf>),
+    /// Sets the environment map reflected by the materials from the images
+    /// of its faces (+X, -X, +Y, -Y, +Z, -Z), or removes it.
+    SetEnvironmentMap(Option<[PathBuf; 6]>),
+    /// Registers the settings of the sampler of the given name, used by the
+    /// textures requesting it.
+    RegisterSampler {
+        name: SmlString,
+        config: SamplerConfig,
+    },
+    /// Sets the maximum number of bytes of the textures kept on the GPU, or
+    /// removes the limit if `None`.
+    SetTextureBudget(Option<u64>),
 }
 
 /// Receiver of commands.
@@ -76,3 +230,500 @@ pub type CommandReceiver = crossbeam_channel::Receiver<Command>;
 
 /// Sender of commands.
 pub type CommandSender = crossbeam_channel::Sender<Command>;
+
+/// Typed interface sending commands to the scene and to the renderer, so
+/// that the application can be driven from Rust without the Python layer.
+#[derive(Debug, Clone)]
+pub struct Commands {
+    scene: CommandSender,
+    renderer: CommandSender,
+}
+
+impl Commands {
+    /// Creates the interface from the senders of the scene and renderer
+    /// command channels.
+    pub fn new(scene: CommandSender, renderer: CommandSender) -> Self {
+        Self { scene, renderer }
+    }
+
+    /// Sends a command to the scene, executed when the scene is prepared.
+    pub fn send_to_scene(&self, cmd: Command) {
+        self.scene.send(cmd).unwrap();
+    }
+
+    /// Sends a command to the renderer, executed when the renderer is
+    /// prepared.
+    pub fn send_to_renderer(&self, cmd: Command) {
+        self.renderer.send(cmd).unwrap();
+    }
+
+    /// Translates the entity in its local space.
+    pub fn translate(&self, entity: Entity, translation: Vec3) {
+        self.send_to_scene(Command::Translate {
+            entity,
+            translation,
+            order: ConcatOrder::Post,
+        });
+    }
+
+    /// Rotates the entity in its local space.
+    pub fn rotate(&self, entity: Entity, rotation: Quat) {
+        self.send_to_scene(Command::Rotate {
+            entity,
+            rotation,
+            order: ConcatOrder::Post,
+        });
+    }
+
+    /// Scales the entity in its local space.
+    pub fn scale(&self, entity: Entity, scale: Vec3) {
+        self.send_to_scene(Command::Scale {
+            entity,
+            scale,
+            order: ConcatOrder::Post,
+        });
+    }
+
+    /// Sets the transform of the entity.
+    pub fn set_transform(&self, entity: Entity, translation: Vec3, rotation: Quat, scale: Vec3) {
+        self.send_to_scene(Command::SetTransform {
+            entity,
+            translation,
+            rotation,
+            scale,
+        });
+    }
+
+    /// Sets the position of the entity in world space.
+    pub fn set_position(&self, entity: Entity, position: Vec3) {
+        self.send_to_scene(Command::SetPosition {
+            entity,
+            position,
+            world: true,
+        });
+    }
+
+    /// Sets the rotation of the entity in world space.
+    pub fn set_rotation(&self, entity: Entity, rotation: Quat) {
+        self.send_to_scene(Command::SetRotation {
+            entity,
+            rotation,
+            world: true,
+        });
+    }
+
+    /// Sets the scale of the entity in its local space.
+    pub fn set_scale(&self, entity: Entity, scale: Vec3) {
+        self.send_to_scene(Command::SetScale { entity, scale });
+    }
+
+    /// Rotates the entity so that its -Z axis points to the target position.
+    pub fn look_at(&self, entity: Entity, target: Vec3, up: Vec3) {
+        self.send_to_scene(Command::LookAt { entity, target, up });
+    }
+
+    /// Makes the entity always face the main camera, or stops it if `None`.
+    pub fn set_billboard(&self, entity: Entity, billboard: Option<Billboard>) {
+        self.send_to_scene(Command::SetBillboard { entity, billboard });
+    }
+
+    /// Rotates the camera entity around the center of the scene, by angles
+    /// in radians around its X axis and around the Y axis.
+    pub fn camera_orbit(&self, entity: Entity, rotation_x: f32, rotation_y: f32) {
+        self.send_to_scene(Command::CameraOrbit {
+            entity,
+            rotation_x,
+            rotation_y,
+        });
+    }
+
+    /// Pans the camera entity along its X and Y axes.
+    pub fn camera_pan(&self, entity: Entity, delta_x: f32, delta_y: f32) {
+        self.send_to_scene(Command::CameraPan {
+            entity,
+            delta_x,
+            delta_y,
+        });
+    }
+
+    /// Sets if the entity is active or not.
+    pub fn set_active(&self, entity: Entity, active: bool) {
+        self.send_to_scene(Command::SetActive { entity, active });
+    }
+
+    /// Sets if the entity is visible or not.
+    pub fn set_visible(&self, entity: Entity, visible: bool) {
+        self.send_to_scene(Command::SetVisible { entity, visible });
+    }
+
+    /// Sets if the entity casts shadows or not.
+    pub fn set_cast_shadows(&self, entity: Entity, cast_shadows: bool) {
+        self.send_to_scene(Command::SetCastShadows {
+            entity,
+            cast_shadows,
+        });
+    }
+
+    /// Draws the entity with the material of the given index, overriding the
+    /// materials of its submeshes.
+    pub fn use_material(&self, entity: Entity, material: u32) {
+        self.send_to_scene(Command::UseMaterial { entity, material });
+    }
+
+    /// Clears the material override of the entity.
+    pub fn clear_material_override(&self, entity: Entity) {
+        self.send_to_scene(Command::ClearMaterialOverride { entity });
+    }
+
+    /// Sets the name of the entity.
+    pub fn set_name(&self, entity: Entity, name: &str) {
+        self.send_to_scene(Command::SetName {
+            entity,
+            name: SmlString::from(name),
+        });
+    }
+
+    /// Adds a tag to the entity.
+    pub fn add_tag(&self, entity: Entity, tag: &str) {
+        self.send_to_scene(Command::AddTag {
+            entity,
+            tag: SmlString::from(tag),
+        });
+    }
+
+    /// Removes a tag from the entity.
+    pub fn remove_tag(&self, entity: Entity, tag: &str) {
+        self.send_to_scene(Command::RemoveTag {
+            entity,
+            tag: SmlString::from(tag),
+        });
+    }
+
+    /// Attaches a Python script to the entity.
+    pub fn attach_script(&self, entity: Entity, script: PyObject) {
+        self.send_to_scene(Command::AttachScript { entity, script });
+    }
+
+    /// Detaches a Python script from the entity.
+    pub fn detach_script(&self, entity: Entity, script: PyObject) {
+        self.send_to_scene(Command::DetachScript { entity, script });
+    }
+
+    /// Sets the render layer of the entity.
+    pub fn set_render_layer(&self, entity: Entity, layer: u8) {
+        self.send_to_scene(Command::SetRenderLayer { entity, layer });
+    }
+
+    /// Sets the custom material shader of the entity, or restores the
+    /// default shader if `None`.
+    pub fn set_custom_shader(&self, entity: Entity, path: Option<PathBuf>) {
+        self.send_to_scene(Command::SetCustomShader { entity, path });
+    }
+
+    /// Sets the camera entity as the main camera.
+    pub fn set_main_camera(&self, entity: Entity) {
+        self.send_to_scene(Command::SetAsMainCamera { entity });
+    }
+
+    /// Sets the direction of the directional light entity.
+    pub fn set_directional_light(&self, entity: Entity, direction: Vec3) {
+        self.send_to_scene(Command::SetDirectionalLight { entity, direction });
+    }
+
+    /// Sets the color of the light entity.
+    pub fn set_light_color(&self, entity: Entity, color: Color) {
+        self.send_to_scene(Command::SetLightColor { entity, color });
+    }
+
+    /// Sets the background color of the camera entity.
+    pub fn set_background(&self, entity: Entity, color: Color) {
+        self.send_to_scene(Command::SetBackground { entity, color });
+    }
+
+    /// Sets the layers visible to the camera entity.
+    pub fn set_layer_mask(&self, entity: Entity, mask: u32) {
+        self.send_to_scene(Command::SetLayerMask { entity, mask });
+    }
+
+    /// Sets the backdrop of the camera entity, or removes it if `None`.
+    pub fn set_backdrop(&self, entity: Entity, backdrop: Option<Backdrop>) {
+        self.send_to_scene(Command::SetBackdrop { entity, backdrop });
+    }
+
+    /// Sets the exposure in EV of the camera entity.
+    pub fn set_exposure(&self, entity: Entity, ev: f32) {
+        self.send_to_scene(Command::SetExposure { entity, ev });
+    }
+
+    /// Enables the auto-exposure of the camera entity, or disables it if
+    /// `None`.
+    pub fn set_auto_exposure(&self, entity: Entity, auto: Option<AutoExposure>) {
+        self.send_to_scene(Command::SetAutoExposure { entity, auto });
+    }
+
+    /// Moves the sun of the sky backdrop of the camera entity, if it has
+    /// one, `sun` pointing towards the sun.
+    pub fn set_sky_sun(&self, entity: Entity, sun: Vec3) {
+        self.send_to_scene(Command::SetSkySun { entity, sun });
+    }
+
+    /// Fixes the aspect ratio of the camera entity, or makes it follow the
+    /// window if `None`.
+    pub fn set_fixed_aspect(&self, entity: Entity, aspect: Option<f32>) {
+        self.send_to_scene(Command::SetFixedAspect { entity, aspect });
+    }
+
+    /// Sets the vertical field of view in degrees and the clipping planes of
+    /// the camera entity, keeping the current ones where `None`.
+    pub fn set_camera_params(
+        &self,
+        entity: Entity,
+        fov: Option<f32>,
+        near: Option<f32>,
+        far: Option<f32>,
+    ) {
+        self.send_to_scene(Command::SetCameraParams {
+            entity,
+            fov,
+            near,
+            far,
+        });
+    }
+
+    /// Sets the size in pixels of the window the cameras render to.
+    pub fn resize_viewport(&self, width: u32, height: u32) {
+        self.send_to_scene(Command::ResizeViewport { width, height });
+    }
+
+    /// Adds a rigid body of the given mass to the entity, fixed if the mass
+    /// is not positive.
+    #[cfg(feature = "physics")]
+    pub fn add_rigid_body(&self, entity: Entity, mass: f32) {
+        self.send_to_scene(Command::AddRigidBody { entity, mass });
+    }
+
+    /// Adds a collider enclosing the mesh of the entity.
+    #[cfg(feature = "physics")]
+    pub fn add_collider(&self, entity: Entity, shape: ColliderShape) {
+        self.send_to_scene(Command::AddCollider { entity, shape });
+    }
+
+    /// Sets the gravity of the physics simulation.
+    #[cfg(feature = "physics")]
+    pub fn set_gravity(&self, gravity: Vec3) {
+        self.send_to_scene(Command::SetGravity(gravity));
+    }
+
+    /// Sets the visibility of all entities with the given tag.
+    pub fn set_visible_by_tag(&self, tag: &str, visible: bool) {
+        self.send_to_scene(Command::SetVisibleByTag {
+            tag: SmlString::from(tag),
+            visible,
+        });
+    }
+
+    /// Removes the entity and all its descendants from the scene.
+    pub fn despawn(&self, entity: Entity) {
+        self.send_to_scene(Command::Despawn { entity });
+    }
+
+    /// Removes all entities with the given tag and their descendants from
+    /// the scene.
+    pub fn despawn_by_tag(&self, tag: &str) {
+        self.send_to_scene(Command::DespawnByTag {
+            tag: SmlString::from(tag),
+        });
+    }
+
+    /// Groups the following changes of the entities into a single
+    /// transaction, undone at once.
+    pub fn begin_transaction(&self) {
+        self.send_to_scene(Command::BeginTransaction);
+    }
+
+    /// Ends the transaction started by the matching `begin_transaction`.
+    pub fn end_transaction(&self) {
+        self.send_to_scene(Command::EndTransaction);
+    }
+
+    /// Enables or disables backface culling.
+    pub fn enable_backface_culling(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableBackfaceCulling(enabled));
+    }
+
+    /// Enables or disables wireframe rendering.
+    pub fn enable_wireframe(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableWireframe(enabled));
+    }
+
+    /// Shows or hides the debug gizmos of the lights and cameras.
+    pub fn show_debug_gizmos(&self, shown: bool) {
+        self.send_to_renderer(Command::ShowDebugGizmos(shown));
+    }
+
+    /// Enables or disables shadows.
+    pub fn enable_shadows(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableShadows(enabled));
+    }
+
+    /// Enables or disables the lighting.
+    pub fn enable_lighting(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableLighting(enabled));
+    }
+
+    /// Enables or disables the occlusion culling.
+    pub fn enable_occlusion_culling(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableOcclusionCulling(enabled));
+    }
+
+    /// Enables or disables the culling of the instances on the GPU.
+    pub fn enable_gpu_culling(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableGpuCulling(enabled));
+    }
+
+    /// Enables or disables the depth pre-pass.
+    pub fn enable_depth_prepass(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableDepthPrepass(enabled));
+    }
+
+    /// Enables or disables the reloading of files modified on disk.
+    pub fn enable_hot_reload(&self, enabled: bool) {
+        self.send_to_renderer(Command::EnableHotReload(enabled));
+    }
+
+    /// Replaces all the rendering parameters at once.
+    pub fn set_render_params(&self, params: RenderParams) {
+        self.send_to_renderer(Command::SetRenderParams(Box::new(params)));
+    }
+
+    /// Enables or disables the synchronization of the frames with the
+    /// display refresh rate.
+    pub fn set_vsync(&self, enabled: bool) {
+        self.send_to_renderer(Command::SetVsync(enabled));
+    }
+
+    /// Caps the number of frames rendered per second, or uncaps it if
+    /// `None`.
+    pub fn set_target_fps(&self, fps: Option<f32>) {
+        self.send_to_renderer(Command::SetTargetFps(fps));
+    }
+
+    /// Sets the directory from which the shaders are loaded, or restores the
+    /// embedded shaders if `None`.
+    pub fn set_shader_directory(&self, dir: Option<PathBuf>) {
+        self.send_to_renderer(Command::SetShaderDirectory(dir));
+    }
+
+    /// Sets the environment map reflected by the materials, or removes it.
+    pub fn set_environment_map(&self, faces: Option<[PathBuf; 6]>) {
+        self.send_to_renderer(Command::SetEnvironmentMap(faces));
+    }
+
+    /// Sets the maximum number of bytes of the textures kept on the GPU, or
+    /// removes the limit if `None`.
+    pub fn set_texture_budget(&self, bytes: Option<u64>) {
+        self.send_to_renderer(Command::SetTextureBudget(bytes));
+    }
+
+    /// Registers the settings of the sampler of the given name.
+    pub fn register_sampler(&self, name: &str, config: SamplerConfig) {
+        self.send_to_renderer(Command::RegisterSampler {
+            name: SmlString::from(name),
+            config,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{
+            camera::{Camera, Projection},
+            Light,
+        },
+        scene::{NodeIdx, RenderLayer, Scene},
+    };
+
+    #[test]
+    fn commands_drive_the_scene_without_python() {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut scene = Scene::new(sender.clone(), receiver);
+        let (renderer_sender, renderer_receiver) = crossbeam_channel::unbounded();
+        let commands = Commands::new(sender, renderer_sender);
+
+        let node = scene.spawn(NodeIdx::root(), ());
+        let camera = scene.spawn(
+            NodeIdx::root(),
+            (Camera::new(
+                Projection::perspective(60.0, 0.1, 100.0),
+                Color::WHITE,
+                false,
+            ),),
+        );
+        let light = scene.spawn(
+            NodeIdx::root(),
+            (Light::Directional {
+                direction: Vec3::X,
+                color: Color::WHITE,
+            },),
+        );
+
+        commands.set_position(node, Vec3::new(1.0, 2.0, 3.0));
+        commands.set_scale(node, Vec3::splat(2.0));
+        commands.set_name(node, "tree");
+        commands.add_tag(node, "forest");
+        commands.set_render_layer(node, 3);
+        commands.set_visible_by_tag("forest", false);
+        commands.set_main_camera(camera);
+        commands.set_layer_mask(camera, 0b1000);
+        commands.set_fixed_aspect(camera, Some(2.0));
+        commands.set_camera_params(camera, None, Some(0.5), None);
+        commands.set_light_color(light, Color::RED);
+        commands.set_directional_light(light, Vec3::NEG_Y);
+        commands.enable_shadows(true);
+
+        let mut main_camera = None;
+        scene.prepare(&mut main_camera);
+
+        let transform = scene.nodes[node.node].transform();
+        assert_eq!(transform.translation, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(transform.scale, Vec3::splat(2.0));
+        assert_eq!(scene.find_entity("tree").map(|e| e.raw), Some(node.raw));
+        assert_eq!(scene.entities_with_tag("forest").len(), 1);
+        assert!(!scene.nodes[node.node].is_visible());
+        let entry = scene.world.entry_ref(node.raw).unwrap();
+        assert_eq!(
+            entry.get_component::<RenderLayer>().ok(),
+            Some(&RenderLayer(3))
+        );
+
+        assert_eq!(main_camera.map(|e| e.raw), Some(camera.raw));
+        let entry = scene.world.entry_ref(camera.raw).unwrap();
+        let component = entry.get_component::<Camera>().unwrap();
+        assert!(component.is_main);
+        assert_eq!(component.layer_mask, 0b1000);
+        assert_eq!(component.aspect, 2.0);
+        assert_eq!(component.proj.min_depth, 0.5);
+
+        let entry = scene.world.entry_ref(light.raw).unwrap();
+        assert_eq!(
+            entry.get_component::<Light>().ok(),
+            Some(&Light::Directional {
+                direction: Vec3::NEG_Y,
+                color: Color::RED,
+            })
+        );
+
+        // The rendering settings go to the renderer, not to the scene.
+        assert!(matches!(
+            renderer_receiver.try_recv(),
+            Ok(Command::EnableShadows(true))
+        ));
+
+        commands.despawn_by_tag("forest");
+        scene.prepare(&mut main_camera);
+        assert!(scene.world.entry_ref(node.raw).is_err());
+        assert_eq!(main_camera.map(|e| e.raw), Some(camera.raw));
+    }
+}
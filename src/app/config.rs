@@ -0,0 +1,228 @@
+use crate::{
+    app::PyWindowBuilder,
+    core::{camera::Projection, Color},
+    render::{AdapterOptions, Backend, DepthFormat, DepthSettings, PowerPreference, RenderParams},
+};
+use glam::Vec3;
+use numpy as np;
+use pyo3::prelude::*;
+
+/// Initial camera of the application.
+#[derive(Clone, Copy)]
+pub struct CameraConfig {
+    pub proj: Projection,
+    pub pos: Vec3,
+    pub look_at: Vec3,
+    pub background: Color,
+}
+
+/// Configuration of the application, applied at once when the application
+/// is created.
+///
+/// All the setters return the configuration so that they can be chained.
+#[pyclass]
+#[pyo3(name = "AppConfig")]
+#[derive(Clone)]
+pub struct PyAppConfig {
+    /// Settings of the window opened by the main loop.
+    pub window: PyWindowBuilder,
+    /// Main camera created with the application.
+    pub camera: Option<CameraConfig>,
+    /// Initial rendering parameters.
+    pub params: RenderParams,
+    /// Whether to reload the files modified on disk.
+    pub hot_reload: bool,
+    /// Selection of the GPU adapter, overridden by the environment
+    /// variables of [`AdapterOptions`].
+    pub adapter: AdapterOptions,
+    /// Depth buffer and multisampling of the rendering passes.
+    pub depth: DepthSettings,
+}
+
+impl Default for PyAppConfig {
+    fn default() -> Self {
+        Self {
+            window: PyWindowBuilder::default(),
+            camera: None,
+            params: RenderParams::new(),
+            hot_reload: false,
+            adapter: AdapterOptions::default(),
+            depth: DepthSettings::default(),
+        }
+    }
+}
+
+#[pymethods]
+impl PyAppConfig {
+    #[new]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the settings of the window.
+    pub fn with_window(mut slf: PyRefMut<'_, Self>, window: PyWindowBuilder) -> PyRefMut<'_, Self> {
+        slf.window = window;
+        slf
+    }
+
+    /// Creates the main camera with the application.
+    ///
+    /// * `pos` - The position of the camera.
+    /// * `look_at` - The target of the camera.
+    /// * `fov_v` - The vertical field of view of the camera in degrees.
+    #[pyo3(signature = (pos, look_at, fov_v=60.0, near=0.1, far=200.0, background=Color::DARK_GREY))]
+    pub fn with_camera<'a>(
+        mut slf: PyRefMut<'a, Self>,
+        pos: &np::PyArray2<f32>,
+        look_at: &np::PyArray2<f32>,
+        fov_v: f32,
+        near: f32,
+        far: f32,
+        background: Color,
+    ) -> PyRefMut<'a, Self> {
+        slf.camera = Some(CameraConfig {
+            proj: Projection::perspective(fov_v, near, far),
+            pos: Vec3::from_slice(pos.readonly().as_slice().unwrap()),
+            look_at: Vec3::from_slice(look_at.readonly().as_slice().unwrap()),
+            background,
+        });
+        slf
+    }
+
+    /// Enables or disables shadows.
+    pub fn with_shadows(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.enable_shadows = enabled;
+        slf
+    }
+
+    /// Enables or disables the lighting.
+    pub fn with_lighting(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.enable_lighting = enabled;
+        slf
+    }
+
+    /// Enables or disables backface culling.
+    pub fn with_backface_culling(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.enable_back_face_culling = enabled;
+        slf
+    }
+
+    /// Enables or disables wireframe rendering.
+    pub fn with_wireframe(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.enable_wireframe = enabled;
+        slf
+    }
+
+    /// Enables or disables the synchronization of the frames with the
+    /// display refresh rate.
+    pub fn with_vsync(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.vsync = enabled;
+        slf
+    }
+
+    /// Caps the number of frames rendered per second, or uncaps it if
+    /// `None`.
+    #[pyo3(signature = (fps=None))]
+    pub fn with_target_fps(mut slf: PyRefMut<'_, Self>, fps: Option<f32>) -> PyRefMut<'_, Self> {
+        slf.params.target_fps = RenderParams::clamp_target_fps(fps);
+        slf
+    }
+
+    /// Limits the GPU memory of the textures to the given number of
+    /// megabytes, or removes the limit if `None`. The least recently used
+    /// textures of the entities which are not visible are evicted beyond it.
+    #[pyo3(signature = (megabytes=None))]
+    pub fn with_texture_budget(
+        mut slf: PyRefMut<'_, Self>,
+        megabytes: Option<f32>,
+    ) -> PyRefMut<'_, Self> {
+        slf.params.texture_budget = megabytes.map(megabytes_to_bytes);
+        slf
+    }
+
+    /// Enables or disables the occlusion culling.
+    pub fn with_occlusion_culling(
+        mut slf: PyRefMut<'_, Self>,
+        enabled: bool,
+    ) -> PyRefMut<'_, Self> {
+        slf.params.enable_occlusion_culling = enabled;
+        slf
+    }
+
+    /// Enables or disables the culling of the instances on the GPU.
+    pub fn with_gpu_culling(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.enable_gpu_culling = enabled;
+        slf
+    }
+
+    /// Enables or disables the depth pre-pass.
+    pub fn with_depth_prepass(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.params.enable_depth_prepass = enabled;
+        slf
+    }
+
+    /// Sets the format of the depth buffer.
+    pub fn with_depth_format(
+        mut slf: PyRefMut<'_, Self>,
+        format: DepthFormat,
+    ) -> PyRefMut<'_, Self> {
+        slf.depth.format = format;
+        slf
+    }
+
+    /// Enables or disables the reversed depth, going from 1 at the near
+    /// plane to 0 at the far plane, which avoids z-fighting far away in
+    /// large scenes, especially with an infinite far plane.
+    pub fn with_reversed_z(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.depth.reversed_z = enabled;
+        slf
+    }
+
+    /// Sets the number of samples per pixel of the scene, smoothing the
+    /// edges of the geometry. 1 disables the multisampling; counts not
+    /// supported by the adapter are lowered to the closest supported one.
+    pub fn with_msaa(mut slf: PyRefMut<'_, Self>, samples: u32) -> PyRefMut<'_, Self> {
+        slf.depth.samples = samples.max(1);
+        slf
+    }
+
+    /// Enables or disables the reloading of files modified on disk.
+    pub fn with_hot_reload(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.hot_reload = enabled;
+        slf
+    }
+
+    /// Selects the graphics API to render with, or the default one of the
+    /// platform if `None`. The `BKFW_BACKEND` environment variable takes
+    /// precedence.
+    #[pyo3(signature = (backend=None))]
+    pub fn with_backend(
+        mut slf: PyRefMut<'_, Self>,
+        backend: Option<Backend>,
+    ) -> PyRefMut<'_, Self> {
+        slf.adapter.backend = backend;
+        slf
+    }
+
+    /// Sets the kind of GPU preferred when several are available. The
+    /// `BKFW_POWER_PREFERENCE` environment variable takes precedence.
+    pub fn with_power_preference(
+        mut slf: PyRefMut<'_, Self>,
+        preference: PowerPreference,
+    ) -> PyRefMut<'_, Self> {
+        slf.adapter.power_preference = preference;
+        slf
+    }
+
+    /// Forces the use of a software adapter rendering on the CPU. The
+    /// `BKFW_FORCE_FALLBACK_ADAPTER` environment variable takes precedence.
+    pub fn with_fallback_adapter(mut slf: PyRefMut<'_, Self>, enabled: bool) -> PyRefMut<'_, Self> {
+        slf.adapter.force_fallback_adapter = enabled;
+        slf
+    }
+}
+
+/// Converts a size in megabytes, as given from Python, to bytes.
+pub(crate) fn megabytes_to_bytes(megabytes: f32) -> u64 {
+    (megabytes.max(0.0) as f64 * 1024.0 * 1024.0) as u64
+}
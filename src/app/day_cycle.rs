@@ -0,0 +1,134 @@
+use crate::{compute::sun_position, core::Color, scene::Entity};
+use glam::Vec3;
+
+/// Color of the sky at night.
+const NIGHT_SKY: Color = Color::new(0.005, 0.007, 0.02, 1.0);
+/// Color of the sky during the day.
+const DAY_SKY: Color = Color::new(0.25, 0.5, 0.85, 1.0);
+/// Color of the sky at dawn and dusk.
+const TWILIGHT_SKY: Color = Color::new(0.8, 0.3, 0.12, 1.0);
+/// Color of the moonlight lighting the scene at night.
+const MOONLIGHT: Color = Color::new(0.04, 0.05, 0.09, 1.0);
+
+/// Lighting of the scene at a time of the day.
+#[derive(Clone, Copy, Debug)]
+pub struct SunState {
+    /// Unit vector pointing towards the sun, below the horizon at night.
+    pub sun: Vec3,
+    /// Direction of the directional light (from the sun or the moon towards
+    /// the origin).
+    pub direction: Vec3,
+    /// Color of the directional light.
+    pub light_color: Color,
+    /// Clear color of the main camera.
+    pub sky_color: Color,
+}
+
+/// Drives a directional light along the path of the sun over a day.
+#[derive(Clone, Copy, Debug)]
+pub struct SunAnimator {
+    /// The directional light moved as the sun.
+    pub light: Entity,
+    /// Duration of a whole day in seconds.
+    pub day_duration: f32,
+    /// Latitude of the site in radians.
+    pub latitude: f32,
+    /// Time of the day in hours, in [0, 24).
+    pub time_of_day: f32,
+}
+
+impl SunAnimator {
+    /// Creates a new animator starting in the morning, `latitude` being in
+    /// degrees.
+    pub fn new(light: Entity, day_duration: f32, latitude: f32) -> Self {
+        Self {
+            light,
+            day_duration: day_duration.max(f32::EPSILON),
+            latitude: latitude.to_radians(),
+            time_of_day: 8.0,
+        }
+    }
+
+    /// Sets the time of the day in hours.
+    pub fn set_time_of_day(&mut self, hours: f32) {
+        self.time_of_day = hours.rem_euclid(24.0);
+    }
+
+    /// Advances the time by `dt` seconds and returns the new lighting.
+    pub fn advance(&mut self, dt: f32) -> SunState {
+        self.set_time_of_day(self.time_of_day + dt / self.day_duration * 24.0);
+        self.state()
+    }
+
+    /// Returns the lighting at the current time of the day.
+    pub fn state(&self) -> SunState {
+        let hour_angle = (self.time_of_day / 24.0 - 0.5) * std::f32::consts::TAU;
+        let sun = sun_position(hour_angle, self.latitude);
+        let elevation = sun.y;
+
+        // Both lights fade out at the horizon, where the moon takes over.
+        let day = smoothstep(0.0, 0.1, elevation);
+        let night = smoothstep(0.0, 0.1, -elevation);
+        let (direction, light_color) = if elevation >= 0.0 {
+            // Warmer light when the sun is low.
+            let kelvin = 2000.0 + 4500.0 * smoothstep(0.0, 0.5, elevation);
+            (-sun, scale(color_temperature(kelvin), day))
+        } else {
+            (sun, scale(MOONLIGHT, night))
+        };
+
+        let sky = mix(NIGHT_SKY, DAY_SKY, smoothstep(-0.1, 0.3, elevation));
+        let twilight = 1.0 - smoothstep(0.0, 0.25, elevation.abs());
+        SunState {
+            sun,
+            direction,
+            light_color,
+            sky_color: mix(sky, TWILIGHT_SKY, 0.6 * twilight),
+        }
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn mix(a: Color, b: Color, t: f32) -> Color {
+    let t = t as f64;
+    Color::new(
+        a.r + (b.r - a.r) * t,
+        a.g + (b.g - a.g) * t,
+        a.b + (b.b - a.b) * t,
+        1.0,
+    )
+}
+
+fn scale(color: Color, factor: f32) -> Color {
+    let factor = factor as f64;
+    Color::new(color.r * factor, color.g * factor, color.b * factor, 1.0)
+}
+
+/// Approximates the linear color of a black body at the given temperature in
+/// kelvins (Tanner Helland's fit).
+fn color_temperature(kelvin: f32) -> Color {
+    let t = (kelvin / 100.0) as f64;
+    let r = if t <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (t - 60.0).powf(-0.1332047592)
+    };
+    let g = if t <= 66.0 {
+        99.4708025861 * t.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (t - 60.0).powf(-0.0755148492)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (t - 10.0).ln() - 305.0447927307
+    };
+    let linear = |c: f64| (c / 255.0).clamp(0.0, 1.0).powf(2.2);
+    Color::new(linear(r), linear(g), linear(b), 1.0)
+}
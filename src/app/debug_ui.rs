@@ -0,0 +1,576 @@
+//! Debug user interface drawn over the window with egui: an inspector of the
+//! scene and of the rendering parameters, and the controls added from
+//! Python.
+
+use crate::{
+    app::{command::Command, log_history, PyAppState},
+    core::{mesh::MeshBundle, FxHashMap, Material},
+    render::RenderTarget,
+    scene::{Entity, Name, NodeIdx, Scene},
+};
+use glam::{EulerRot, Quat, Vec3};
+use legion::IntoQuery;
+use pyo3::{prelude::*, types::PyTuple};
+use std::{ops::RangeInclusive, time::Instant};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
+    keyboard::{Key, NamedKey},
+};
+
+/// Control added from Python to the debug UI, calling back with its new
+/// value when changed.
+pub enum DebugControl {
+    /// Slider over a range of numbers.
+    Slider {
+        label: String,
+        value: f32,
+        range: RangeInclusive<f32>,
+        callback: PyObject,
+    },
+    /// Checkbox.
+    Checkbox {
+        label: String,
+        value: bool,
+        callback: PyObject,
+    },
+    /// Button, calling back without arguments when clicked.
+    Button { label: String, callback: PyObject },
+}
+
+/// State of the debug UI shared by the copies of the application.
+#[derive(Default)]
+pub struct DebugControls {
+    /// Whether the debug UI is shown.
+    pub enabled: bool,
+    /// Controls added from Python, in order.
+    pub controls: Vec<DebugControl>,
+}
+
+/// Debug UI drawn by the main loop over the frames of the window.
+pub(crate) struct DebugUi {
+    context: egui::Context,
+    renderer: egui_wgpu::Renderer,
+    /// Input events received since the last frame.
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    /// Position of the cursor in points.
+    cursor: egui::Pos2,
+    start: Instant,
+    /// Node shown in the inspector.
+    inspected: Option<NodeIdx>,
+    /// Materials edited in the inspector, per mesh.
+    materials: FxHashMap<MeshBundle, Vec<Material>>,
+    /// Least severe level of the messages shown in the console.
+    console_level: log::LevelFilter,
+}
+
+impl DebugUi {
+    /// Creates the debug UI drawing to targets of the given format.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        Self {
+            context: egui::Context::default(),
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1, false),
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            cursor: egui::Pos2::ZERO,
+            start: Instant::now(),
+            inspected: None,
+            materials: FxHashMap::default(),
+            console_level: log::LevelFilter::Trace,
+        }
+    }
+
+    /// Passes the window event to the UI. Returns true if the UI uses it,
+    /// in which case it is not passed to the application.
+    ///
+    /// Releases of buttons and keys are always passed to the application,
+    /// which would otherwise see them pressed.
+    pub fn handle_event(&mut self, event: &WindowEvent, scale_factor: f32) -> bool {
+        match event {
+            WindowEvent::ModifiersChanged(modifiers) => {
+                let state = modifiers.state();
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt_key(),
+                    ctrl: state.control_key(),
+                    shift: state.shift_key(),
+                    mac_cmd: cfg!(target_os = "macos") && state.super_key(),
+                    command: if cfg!(target_os = "macos") {
+                        state.super_key()
+                    } else {
+                        state.control_key()
+                    },
+                };
+                false
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = egui::pos2(position.x as f32, position.y as f32) / scale_factor;
+                self.events.push(egui::Event::PointerMoved(self.cursor));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    _ => return false,
+                };
+                let pressed = *state == ElementState::Pressed;
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.cursor,
+                    button,
+                    pressed,
+                    modifiers: self.modifiers,
+                });
+                pressed && self.context.is_pointer_over_area()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (unit, delta) = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        (egui::MouseWheelUnit::Line, egui::vec2(*x, *y))
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => (
+                        egui::MouseWheelUnit::Point,
+                        egui::vec2(delta.x as f32, delta.y as f32) / scale_factor,
+                    ),
+                };
+                self.events.push(egui::Event::MouseWheel {
+                    unit,
+                    delta,
+                    modifiers: self.modifiers,
+                });
+                self.context.is_pointer_over_area()
+            }
+            WindowEvent::KeyboardInput { event, .. } => {
+                let pressed = event.state == ElementState::Pressed;
+                if let Some(key) = egui_key(&event.logical_key) {
+                    self.events.push(egui::Event::Key {
+                        key,
+                        physical_key: None,
+                        pressed,
+                        repeat: event.repeat,
+                        modifiers: self.modifiers,
+                    });
+                }
+                if let Some(text) = event.text.as_ref().filter(|_| pressed) {
+                    if text.chars().all(|c| !c.is_control()) {
+                        self.events.push(egui::Event::Text(text.to_string()));
+                    }
+                }
+                pressed && self.context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    /// Runs the UI over the state of the application and draws it to the
+    /// target, then calls back the Python controls which changed.
+    pub fn render(
+        &mut self,
+        app: &PyAppState,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        target: &RenderTarget,
+        scale_factor: f32,
+    ) {
+        profiling::scope!("DebugUi::render");
+        let size = [target.size.width, target.size.height];
+        let mut raw_input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(size[0] as f32, size[1] as f32) / scale_factor,
+            )),
+            time: Some(self.start.elapsed().as_secs_f64()),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        };
+        raw_input
+            .viewports
+            .entry(egui::ViewportId::ROOT)
+            .or_default()
+            .native_pixels_per_point = Some(scale_factor);
+
+        let mut changed = Vec::new();
+        let context = self.context.clone();
+        let output = context.run(raw_input, |ctx| {
+            self.show_inspector(ctx, app);
+            self.show_console(ctx);
+            changed = Self::show_controls(ctx, app);
+        });
+
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: size,
+            pixels_per_point: output.pixels_per_point,
+        };
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let primitives = self
+            .context
+            .tessellate(output.shapes, output.pixels_per_point);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("debug_ui_encoder"),
+        });
+        let callbacks =
+            self.renderer
+                .update_buffers(device, queue, &mut encoder, &primitives, &screen);
+        {
+            let mut render_pass = encoder
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("debug_ui_render_pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &target.view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                })
+                .forget_lifetime();
+            self.renderer.render(&mut render_pass, &primitives, &screen);
+        }
+        queue.submit(callbacks.into_iter().chain(Some(encoder.finish())));
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+
+        // The callbacks may use the application, which is not locked anymore.
+        if !changed.is_empty() {
+            Python::with_gil(|py| {
+                for (callback, args) in changed {
+                    if let Err(err) = callback.call1(py, args) {
+                        err.print(py);
+                    }
+                }
+            });
+        }
+    }
+
+    /// Shows the scene graph, the node selected in it and the rendering
+    /// parameters. Edits are sent as commands to the scene and the renderer.
+    fn show_inspector(&mut self, ctx: &egui::Context, app: &PyAppState) {
+        let scene = app.scene.read().unwrap();
+        let mut renderer = app.renderer.write().unwrap();
+        let entities = <(legion::Entity, &NodeIdx, Option<&Name>, Option<&MeshBundle>)>::query()
+            .iter(&scene.world)
+            .map(|(raw, node, name, mesh)| {
+                let name = name.and_then(|name| name.label.as_ref());
+                (*node, (*raw, name.map(|n| n.to_string()), mesh.copied()))
+            })
+            .collect::<FxHashMap<_, _>>();
+
+        egui::Window::new("Inspector")
+            .default_width(280.0)
+            .show(ctx, |ui| {
+                egui::CollapsingHeader::new("Render parameters").show(ui, |ui| {
+                    let mut params = renderer.params().clone();
+                    let changed = [
+                        ui.checkbox(&mut params.enable_lighting, "Lighting"),
+                        ui.checkbox(&mut params.enable_shadows, "Shadows"),
+                        ui.checkbox(&mut params.enable_wireframe, "Wireframe"),
+                        ui.checkbox(&mut params.enable_back_face_culling, "Back-face culling"),
+                        ui.checkbox(&mut params.enable_occlusion_culling, "Occlusion culling"),
+                        ui.checkbox(&mut params.enable_gpu_culling, "GPU culling"),
+                        ui.checkbox(&mut params.enable_depth_prepass, "Depth pre-pass"),
+                        ui.checkbox(&mut params.show_debug_gizmos, "Debug gizmos"),
+                        ui.checkbox(&mut params.vsync, "VSync"),
+                    ]
+                    .iter()
+                    .any(egui::Response::changed);
+                    if changed {
+                        app.commands().set_render_params(params);
+                    }
+                });
+
+                egui::CollapsingHeader::new("Memory").show(ui, |ui| {
+                    const MB: f64 = 1024.0 * 1024.0;
+                    let meshes = renderer.mesh_buffer_stats();
+                    ui.label(format!(
+                        "Meshes: {} ({:.1} / {:.1} MB)",
+                        meshes.mesh_count,
+                        meshes.used as f64 / MB,
+                        meshes.capacity as f64 / MB
+                    ));
+                    let textures = renderer.texture_memory_stats();
+                    let budget = match textures.budget {
+                        Some(budget) => format!("{:.1} MB", budget as f64 / MB),
+                        None => "unlimited".to_string(),
+                    };
+                    ui.label(format!(
+                        "Textures: {} ({:.1} MB, budget {})",
+                        textures.resident_count,
+                        textures.resident as f64 / MB,
+                        budget
+                    ));
+                    ui.label(format!(
+                        "Evicted textures: {} ({} evicted, {} reloaded last frame)",
+                        textures.evicted_count, textures.evictions, textures.reloads
+                    ));
+                });
+
+                egui::CollapsingHeader::new("Scene graph")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::ScrollArea::vertical()
+                            .max_height(240.0)
+                            .show(ui, |ui| {
+                                for child in scene.nodes.children(NodeIdx::root()) {
+                                    self.show_node_tree(ui, &scene, &entities, child);
+                                }
+                            });
+                    });
+
+                let Some(node) = self.inspected.filter(|node| entities.contains_key(node)) else {
+                    return;
+                };
+                let (raw, _, mesh) = &entities[&node];
+                let entity = Entity { raw: *raw, node };
+                egui::CollapsingHeader::new("Transform")
+                    .default_open(true)
+                    .show(ui, |ui| Self::show_transform(ui, &scene, app, entity));
+                if let Some(mesh) = mesh {
+                    egui::CollapsingHeader::new("Materials").show(ui, |ui| {
+                        let Some(source) = renderer.mesh_source(mesh.mesh) else {
+                            return;
+                        };
+                        let materials = self
+                            .materials
+                            .entry(*mesh)
+                            .or_insert_with(|| source.materials.clone().unwrap_or_default());
+                        if materials.is_empty() {
+                            ui.label("Default material");
+                        }
+                        let mut changed = false;
+                        for (i, material) in materials.iter_mut().enumerate() {
+                            changed |= Self::show_material(ui, i, material);
+                        }
+                        if changed && !renderer.update_materials(mesh.aesthetic, materials) {
+                            log::warn!("Failed to update the materials of {:?}", entity);
+                        }
+                    });
+                }
+            });
+    }
+
+    /// Shows the node and its descendants as a tree, the node clicked being
+    /// inspected.
+    fn show_node_tree(
+        &mut self,
+        ui: &mut egui::Ui,
+        scene: &Scene,
+        entities: &FxHashMap<NodeIdx, (legion::Entity, Option<String>, Option<MeshBundle>)>,
+        node: NodeIdx,
+    ) {
+        let Some((_, name, _)) = entities.get(&node) else {
+            return;
+        };
+        let label = name.clone().unwrap_or_else(|| format!("Node {}", node.0));
+        let selected = self.inspected == Some(node);
+        let mut children = scene.nodes.children(node).peekable();
+        if children.peek().is_none() {
+            if ui.selectable_label(selected, label).clicked() {
+                self.inspected = Some(node);
+            }
+            return;
+        }
+        let id = ui.make_persistent_id(("scene_graph", node.0));
+        egui::collapsing_header::CollapsingState::load_with_default_open(ui.ctx(), id, false)
+            .show_header(ui, |ui| {
+                if ui.selectable_label(selected, label).clicked() {
+                    self.inspected = Some(node);
+                }
+            })
+            .body(|ui| {
+                for child in children {
+                    self.show_node_tree(ui, scene, entities, child);
+                }
+            });
+    }
+
+    /// Shows the local transform of the entity, its rotation as Euler angles
+    /// in degrees, and its visibility.
+    fn show_transform(ui: &mut egui::Ui, scene: &Scene, app: &PyAppState, entity: Entity) {
+        let node = &scene.nodes[entity.node];
+        let transform = *node.transform();
+        let mut translation = transform.translation.to_array();
+        let (y, x, z) = transform.rotation.to_euler(EulerRot::YXZ);
+        let mut rotation = [x, y, z].map(f32::to_degrees);
+        let mut scale = transform.scale.to_array();
+        let mut changed = false;
+        for (label, values, speed) in [
+            ("Translation", &mut translation, 0.05),
+            ("Rotation", &mut rotation, 1.0),
+            ("Scale", &mut scale, 0.01),
+        ] {
+            ui.horizontal(|ui| {
+                ui.label(label);
+                for value in values.iter_mut() {
+                    changed |= ui.add(egui::DragValue::new(value).speed(speed)).changed();
+                }
+            });
+        }
+        if changed {
+            let [x, y, z] = rotation.map(f32::to_radians);
+            app.commands().send_to_scene(Command::SetTransform {
+                entity,
+                translation: Vec3::from(translation),
+                rotation: Quat::from_euler(EulerRot::YXZ, y, x, z),
+                scale: Vec3::from(scale),
+            });
+        }
+        let mut visible = node.is_visible();
+        if ui.checkbox(&mut visible, "Visible").changed() {
+            app.commands().set_visible(entity, visible);
+        }
+    }
+
+    /// Shows the colors and coefficients of the material. Returns true if
+    /// one of them changed.
+    fn show_material(ui: &mut egui::Ui, index: usize, material: &mut Material) -> bool {
+        let mut changed = false;
+        egui::CollapsingHeader::new(format!("{}: {}", index, material.name))
+            .id_salt(index)
+            .show(ui, |ui| {
+                for (label, color) in [
+                    ("Ambient", &mut material.ambient),
+                    ("Diffuse", &mut material.diffuse),
+                    ("Specular", &mut material.specular),
+                    ("Emissive", &mut material.emissive),
+                ] {
+                    if let Some(color) = color {
+                        ui.horizontal(|ui| {
+                            ui.label(label);
+                            changed |= ui.color_edit_button_rgb(color).changed();
+                        });
+                    }
+                }
+                let shininess = material.shininess.get_or_insert(1.0);
+                changed |= ui
+                    .add(egui::Slider::new(shininess, 0.0..=1000.0).text("Shininess"))
+                    .changed();
+                let opacity = material.opacity.get_or_insert(1.0);
+                changed |= ui
+                    .add(egui::Slider::new(opacity, 0.0..=1.0).text("Opacity"))
+                    .changed();
+                changed |= ui
+                    .checkbox(&mut material.double_sided, "Double-sided")
+                    .changed();
+                let reflectivity = material.reflectivity.get_or_insert(0.0);
+                changed |= ui
+                    .add(egui::Slider::new(reflectivity, 0.0..=1.0).text("Reflectivity"))
+                    .changed();
+            });
+        changed
+    }
+
+    /// Shows the last logged messages, filtered by level.
+    fn show_console(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Console")
+            .default_open(false)
+            .default_width(480.0)
+            .show(ctx, |ui| {
+                egui::ComboBox::from_label("Level")
+                    .selected_text(self.console_level.as_str())
+                    .show_ui(ui, |ui| {
+                        for level in log::LevelFilter::iter().skip(1) {
+                            ui.selectable_value(&mut self.console_level, level, level.as_str());
+                        }
+                    });
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .max_height(240.0)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for record in log_history()
+                            .iter()
+                            .filter(|record| record.level <= self.console_level)
+                        {
+                            let color = match record.level {
+                                log::Level::Error => egui::Color32::LIGHT_RED,
+                                log::Level::Warn => egui::Color32::YELLOW,
+                                _ => ui.visuals().text_color(),
+                            };
+                            ui.colored_label(
+                                color,
+                                format!("[{} {}] {}", record.level, record.target, record.message),
+                            );
+                        }
+                    });
+            });
+    }
+
+    /// Shows the controls added from Python. Returns the callbacks of the
+    /// controls which changed with their arguments.
+    fn show_controls(ctx: &egui::Context, app: &PyAppState) -> Vec<(PyObject, Py<PyTuple>)> {
+        let mut debug_ui = app.debug_ui.lock().unwrap();
+        let mut changed = Vec::new();
+        if debug_ui.controls.is_empty() {
+            return changed;
+        }
+        egui::Window::new("Controls").show(ctx, |ui| {
+            Python::with_gil(|py| {
+                for control in debug_ui.controls.iter_mut() {
+                    match control {
+                        DebugControl::Slider {
+                            label,
+                            value,
+                            range,
+                            callback,
+                        } => {
+                            if ui
+                                .add(egui::Slider::new(value, range.clone()).text(label.as_str()))
+                                .changed()
+                            {
+                                changed.push((callback.clone_ref(py), (*value,).into_py(py)));
+                            }
+                        }
+                        DebugControl::Checkbox {
+                            label,
+                            value,
+                            callback,
+                        } => {
+                            if ui.checkbox(value, label.as_str()).changed() {
+                                changed.push((callback.clone_ref(py), (*value,).into_py(py)));
+                            }
+                        }
+                        DebugControl::Button { label, callback } => {
+                            if ui.button(label.as_str()).clicked() {
+                                changed.push((callback.clone_ref(py), ().into_py(py)));
+                            }
+                        }
+                    }
+                }
+            });
+        });
+        changed
+    }
+}
+
+/// Returns the egui key of the keys used to edit text, if any.
+fn egui_key(key: &Key) -> Option<egui::Key> {
+    let Key::Named(key) = key else {
+        return None;
+    };
+    Some(match key {
+        NamedKey::Backspace => egui::Key::Backspace,
+        NamedKey::Delete => egui::Key::Delete,
+        NamedKey::Enter => egui::Key::Enter,
+        NamedKey::Tab => egui::Key::Tab,
+        NamedKey::Escape => egui::Key::Escape,
+        NamedKey::Home => egui::Key::Home,
+        NamedKey::End => egui::Key::End,
+        NamedKey::ArrowLeft => egui::Key::ArrowLeft,
+        NamedKey::ArrowRight => egui::Key::ArrowRight,
+        NamedKey::ArrowUp => egui::Key::ArrowUp,
+        NamedKey::ArrowDown => egui::Key::ArrowDown,
+        _ => return None,
+    })
+}
@@ -1,4 +1,5 @@
 use crate::core::FxHashMap;
+use std::time::{Duration, Instant};
 
 use winit::event::Modifiers;
 use winit::{
@@ -652,6 +653,20 @@ impl From<MouseButton> for WinitMouseButton {
     }
 }
 
+/// The key or button went down since the last frame.
+const EDGE_PRESSED: u8 = 1 << 0;
+/// The key or button went up since the last frame.
+const EDGE_RELEASED: u8 = 1 << 1;
+/// The key has been repeated by the system since the last frame.
+const EDGE_REPEATED: u8 = 1 << 2;
+/// The button has been pressed twice in a row since the last frame.
+const EDGE_DOUBLE_CLICK: u8 = 1 << 3;
+
+/// Maximum time between the two presses of a double click.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+/// Maximum distance in pixels between the two presses of a double click.
+const DOUBLE_CLICK_DISTANCE: f32 = 4.0;
+
 /// Struct holding the state of the keyboard and mouse.
 #[derive(Debug, Clone)]
 pub struct InputState {
@@ -660,7 +675,18 @@ pub struct InputState {
     pub mods: ModifiersState,
     pub scroll_delta: f32,
     pub cursor_delta: [f32; 2],
+    /// Position of the cursor in pixels from the top left corner of the
+    /// window.
     pub cursor_pos: [f32; 2],
+    /// Transitions of the keys since the last frame.
+    pub key_edges: FxHashMap<WinitKeyCode, u8>,
+    /// Transitions of the mouse buttons since the last frame.
+    pub btn_edges: FxHashMap<WinitMouseButton, u8>,
+    /// Time and cursor position of the last press of the mouse buttons
+    /// which may start a double click.
+    pub last_clicks: FxHashMap<WinitMouseButton, (Instant, [f32; 2])>,
+    /// Whether the input method editor is composing the typed text.
+    pub ime_active: bool,
 }
 
 impl Default for InputState {
@@ -672,6 +698,10 @@ impl Default for InputState {
             scroll_delta: 0.0,
             cursor_delta: [0.0, 0.0],
             cursor_pos: [0.0, 0.0],
+            key_edges: Default::default(),
+            btn_edges: Default::default(),
+            last_clicks: Default::default(),
+            ime_active: false,
         }
     }
 }
@@ -700,7 +730,10 @@ impl InputState {
     pub fn take(&mut self) -> Input {
         let mut input = Input {
             keys: [None; 16],
+            key_edges: [None; 16],
+            key_edge_flags: [0; 16],
             btns: 0,
+            btn_edges: 0,
             scroll_delta: self.scroll_delta,
             cursor_delta: self.cursor_delta,
             cursor_pos: self.cursor_pos,
@@ -712,6 +745,24 @@ impl InputState {
                 i += 1;
             }
         });
+        for (i, (key, flags)) in self
+            .key_edges
+            .drain()
+            .take(input.key_edges.len())
+            .enumerate()
+        {
+            input.key_edges[i] = Some(KeyCode::from(key));
+            input.key_edge_flags[i] = flags;
+        }
+        for (button, flags) in self.btn_edges.drain() {
+            let shift = match button {
+                WinitMouseButton::Left => MouseButton::Left as u32,
+                WinitMouseButton::Right => MouseButton::Right as u32,
+                WinitMouseButton::Middle => MouseButton::Middle as u32,
+                _ => continue,
+            } * 8;
+            input.btn_edges |= (flags as u32) << shift;
+        }
         if *self.btns.get(&WinitMouseButton::Left).unwrap_or(&false) {
             input.btns = 1 << 0;
         }
@@ -726,14 +777,54 @@ impl InputState {
         input
     }
 
-    pub fn update_key_states(&mut self, key_code: WinitKeyCode, state: ElementState) {
-        log::trace!("update_key_states: {:?} {:?}", key_code, state);
-        *self.keys.entry(key_code).or_insert(false) = state == ElementState::Pressed;
+    /// Updates the state of a key, `repeat` being true if the event is a
+    /// repetition of the system while the key is held.
+    pub fn update_key_states(&mut self, key_code: WinitKeyCode, state: ElementState, repeat: bool) {
+        log::trace!("update_key_states: {:?} {:?} {}", key_code, state, repeat);
+        let pressed = state == ElementState::Pressed;
+        let was_pressed = std::mem::replace(self.keys.entry(key_code).or_insert(false), pressed);
+        let edge = match (was_pressed, pressed) {
+            (_, true) if repeat => EDGE_REPEATED,
+            (false, true) => EDGE_PRESSED,
+            (true, false) => EDGE_RELEASED,
+            _ => return,
+        };
+        *self.key_edges.entry(key_code).or_insert(0) |= edge;
     }
 
     pub fn update_mouse_button_states(&mut self, button: WinitMouseButton, state: ElementState) {
+        self.update_mouse_button_states_at(button, state, Instant::now());
+    }
+
+    /// Updates the state of a mouse button changed at the given time.
+    fn update_mouse_button_states_at(
+        &mut self,
+        button: WinitMouseButton,
+        state: ElementState,
+        now: Instant,
+    ) {
         log::trace!("update_mouse_button_states: {:?} {:?}", button, state);
-        *self.btns.entry(button).or_insert(false) = state == ElementState::Pressed;
+        let pressed = state == ElementState::Pressed;
+        let was_pressed = std::mem::replace(self.btns.entry(button).or_insert(false), pressed);
+        let edge = match (was_pressed, pressed) {
+            (false, true) => {
+                // The second press of a double click doesn't start another.
+                let double_click = self.last_clicks.remove(&button).is_some_and(|(time, pos)| {
+                    let distance = (self.cursor_pos[0] - pos[0]).hypot(self.cursor_pos[1] - pos[1]);
+                    now.duration_since(time) <= DOUBLE_CLICK_TIME
+                        && distance <= DOUBLE_CLICK_DISTANCE
+                });
+                if double_click {
+                    EDGE_PRESSED | EDGE_DOUBLE_CLICK
+                } else {
+                    self.last_clicks.insert(button, (now, self.cursor_pos));
+                    EDGE_PRESSED
+                }
+            }
+            (true, false) => EDGE_RELEASED,
+            _ => return,
+        };
+        *self.btn_edges.entry(button).or_insert(0) |= edge;
     }
 
     pub fn update_modifier_states(&mut self, modifiers: &Modifiers) {
@@ -766,10 +857,17 @@ impl InputState {
 #[pyo3::pyclass]
 #[derive(Debug, Copy, Clone)]
 pub struct Input {
-    /// The keys that were pressed this frame, 8 at most.
+    /// The keys that were pressed this frame, 16 at most.
     keys: [Option<KeyCode>; 16],
+    /// The keys that changed since the last frame, 16 at most.
+    key_edges: [Option<KeyCode>; 16],
+    /// The transitions of the keys of `key_edges`.
+    key_edge_flags: [u8; 16],
     /// The mouse buttons that were pressed this frame.
     btns: u32,
+    /// The transitions of the mouse buttons since the last frame, 8 bits
+    /// per button.
+    btn_edges: u32,
     /// The scroll delta of the mouse wheel.
     scroll_delta: f32,
     /// The delta of the cursor position since the last frame.
@@ -778,11 +876,12 @@ pub struct Input {
     cursor_pos: [f32; 2],
 }
 
-static_assertions::assert_eq_size!(Input, [u32; 22]);
+static_assertions::assert_eq_size!(Input, [u32; 43]);
 
 #[pyo3::pymethods]
 impl Input {
-    #[getter]
+    /// Returns the position of the cursor in pixels from the top left
+    /// corner of the window.
     pub fn cursor_position(&self) -> [f32; 2] {
         self.cursor_pos
     }
@@ -853,6 +952,39 @@ impl Input {
         !self.is_mouse_pressed(button)
     }
 
+    /// Returns true if the key went down since the last frame.
+    pub fn was_key_pressed(&self, key_code: KeyCode) -> bool {
+        self.key_edge(key_code) & EDGE_PRESSED != 0
+    }
+
+    /// Returns true if the key went up since the last frame.
+    pub fn was_key_released(&self, key_code: KeyCode) -> bool {
+        self.key_edge(key_code) & EDGE_RELEASED != 0
+    }
+
+    /// Returns true if the key has been repeated by the system since the
+    /// last frame while it is held.
+    pub fn was_key_repeated(&self, key_code: KeyCode) -> bool {
+        self.key_edge(key_code) & EDGE_REPEATED != 0
+    }
+
+    /// Returns true if the mouse button went down since the last frame.
+    pub fn was_mouse_pressed(&self, button: MouseButton) -> bool {
+        self.button_edge(button) & EDGE_PRESSED != 0
+    }
+
+    /// Returns true if the mouse button went up since the last frame.
+    pub fn was_mouse_released(&self, button: MouseButton) -> bool {
+        self.button_edge(button) & EDGE_RELEASED != 0
+    }
+
+    /// Returns true if the mouse button has been pressed a second time in
+    /// a row at about the same place since the last frame.
+    #[pyo3(signature = (button=MouseButton::Left))]
+    pub fn is_double_click(&self, button: MouseButton) -> bool {
+        self.button_edge(button) & EDGE_DOUBLE_CLICK != 0
+    }
+
     pub fn release_key(&mut self, key_code: KeyCode) {
         self.keys.iter_mut().for_each(|k| {
             if *k == Some(key_code) {
@@ -865,3 +997,74 @@ impl Input {
         self.btns &= !(1 << button as u32);
     }
 }
+
+impl Input {
+    /// Returns the transitions of the key since the last frame.
+    fn key_edge(&self, key_code: KeyCode) -> u8 {
+        self.key_edges
+            .iter()
+            .position(|k| *k == Some(key_code))
+            .map_or(0, |i| self.key_edge_flags[i])
+    }
+
+    /// Returns the transitions of the mouse button since the last frame.
+    fn button_edge(&self, button: MouseButton) -> u8 {
+        (self.btn_edges >> (button as u32 * 8)) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_transitions_last_one_frame() {
+        let mut state = InputState::default();
+        state.update_key_states(WinitKeyCode::KeyW, ElementState::Pressed, false);
+        state.update_key_states(WinitKeyCode::KeyW, ElementState::Pressed, true);
+        let input = state.take();
+        assert!(input.was_key_pressed(KeyCode::W));
+        assert!(input.was_key_repeated(KeyCode::W));
+        assert!(!input.was_key_released(KeyCode::W));
+        assert!(input.is_key_pressed(KeyCode::W));
+
+        let input = state.take();
+        assert!(!input.was_key_pressed(KeyCode::W));
+        assert!(input.is_key_pressed(KeyCode::W));
+
+        state.update_key_states(WinitKeyCode::KeyW, ElementState::Released, false);
+        let input = state.take();
+        assert!(input.was_key_released(KeyCode::W));
+        assert!(!input.is_key_pressed(KeyCode::W));
+    }
+
+    #[test]
+    fn double_clicks_are_close_in_time_and_space() {
+        let mut state = InputState::default();
+        let t0 = Instant::now();
+        let click = |state: &mut InputState, t: Instant| {
+            state.update_mouse_button_states_at(WinitMouseButton::Left, ElementState::Pressed, t);
+            state.update_mouse_button_states_at(WinitMouseButton::Left, ElementState::Released, t);
+        };
+        click(&mut state, t0);
+        let input = state.take();
+        assert!(input.was_mouse_pressed(MouseButton::Left));
+        assert!(input.was_mouse_released(MouseButton::Left));
+        assert!(!input.is_double_click(MouseButton::Left));
+
+        click(&mut state, t0 + Duration::from_millis(200));
+        assert!(state.take().is_double_click(MouseButton::Left));
+
+        // Too late after the previous click.
+        click(&mut state, t0 + Duration::from_millis(400));
+        click(&mut state, t0 + Duration::from_millis(1000));
+        assert!(!state.take().is_double_click(MouseButton::Left));
+
+        // Too far from the previous click.
+        state.cursor_pos = [100.0, 0.0];
+        click(&mut state, t0 + Duration::from_millis(1100));
+        let input = state.take();
+        assert!(input.was_mouse_pressed(MouseButton::Left));
+        assert!(!input.is_double_click(MouseButton::Left));
+    }
+}
@@ -0,0 +1,146 @@
+//! Logger of the application. Besides writing to the standard error like
+//! `env_logger`, it keeps the last messages for the console of the debug UI
+//! and forwards them to the `logging` module of Python, where they show up in
+//! notebooks.
+
+use pyo3::prelude::*;
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, OnceLock},
+    thread::ThreadId,
+};
+
+/// Message logged by the application.
+#[derive(Clone, Debug)]
+pub struct LogRecord {
+    pub level: log::Level,
+    /// Module which logged the message.
+    pub target: String,
+    pub message: String,
+}
+
+/// Logger writing to the standard error, keeping the last messages and
+/// forwarding them to Python.
+struct Logger {
+    env: env_logger::Logger,
+    /// Thread on which the Python interpreter runs the application. The
+    /// messages of the other threads are forwarded from this one, as
+    /// waiting for the GIL there could block the application.
+    main_thread: ThreadId,
+    /// Last messages, the oldest first.
+    history: Mutex<VecDeque<LogRecord>>,
+    /// Messages of the other threads not yet forwarded to Python.
+    pending: Mutex<Vec<LogRecord>>,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+/// Number of messages kept for the console.
+pub const LOG_HISTORY_LEN: usize = 256;
+
+/// Least severe level kept and forwarded whatever the filter of
+/// `RUST_LOG`, which only applies to the standard error.
+const MIN_FORWARDED_LEVEL: log::LevelFilter = log::LevelFilter::Warn;
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.level() <= MIN_FORWARDED_LEVEL || self.env.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        if self.env.matches(record) {
+            self.env.log(record);
+        }
+        let record = LogRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        };
+        {
+            let mut history = self.history.lock().unwrap();
+            if history.len() == LOG_HISTORY_LEN {
+                history.pop_front();
+            }
+            history.push_back(record.clone());
+        }
+        if std::thread::current().id() == self.main_thread {
+            forward_pending();
+            Python::with_gil(|py| forward(py, &record));
+        } else {
+            self.pending.lock().unwrap().push(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.env.flush();
+    }
+}
+
+/// Installs the logger, unless another logger is already set. The
+/// standard error is filtered by the `RUST_LOG` environment variable.
+pub fn init() {
+    let mut installed = false;
+    let logger = LOGGER.get_or_init(|| {
+        installed = true;
+        Logger {
+            env: env_logger::Builder::from_default_env().build(),
+            main_thread: std::thread::current().id(),
+            history: Mutex::new(VecDeque::with_capacity(LOG_HISTORY_LEN)),
+            pending: Mutex::new(Vec::new()),
+        }
+    });
+    if installed && log::set_logger(logger).is_ok() {
+        log::set_max_level(logger.env.filter().max(MIN_FORWARDED_LEVEL));
+    }
+}
+
+/// Returns the last logged messages, the oldest first.
+pub fn log_history() -> Vec<LogRecord> {
+    LOGGER
+        .get()
+        .map(|logger| logger.history.lock().unwrap().iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Forwards to Python the messages logged by the other threads since the
+/// last call. Must be called from the thread running the application.
+pub fn forward_pending() {
+    let Some(logger) = LOGGER.get() else {
+        return;
+    };
+    let pending = std::mem::take(&mut *logger.pending.lock().unwrap());
+    if pending.is_empty() {
+        return;
+    }
+    Python::with_gil(|py| {
+        for record in &pending {
+            forward(py, record);
+        }
+    });
+}
+
+/// Logs the message with the logger of Python named after its module, e.g.
+/// `bkfw.render.rpass` for `bkfw::render::rpass`.
+fn forward(py: Python, record: &LogRecord) {
+    let level = match record.level {
+        log::Level::Error => 40,
+        log::Level::Warn => 30,
+        log::Level::Info => 20,
+        log::Level::Debug => 10,
+        log::Level::Trace => 5,
+    };
+    let name = record.target.replace("::", ".");
+    let result = py.import("logging").and_then(|logging| {
+        logging
+            .call_method1("getLogger", (name,))?
+            .call_method1("log", (level, record.message.as_str()))
+            .map(|_| ())
+    });
+    // Logging the failure would forward it again.
+    if let Err(err) = result {
+        err.print(py);
+    }
+}
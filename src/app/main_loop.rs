@@ -0,0 +1,380 @@
+#[cfg(feature = "debug-ui")]
+use crate::app::DebugUi;
+use crate::{
+    app::{AppEvent, PyAppState, PyWindowBuilder, UserEvent},
+    core::FxHashMap,
+    render::{rpass::BlinnPhongRenderPass, surface::Surface, GpuContext, RenderTarget},
+};
+use std::{
+    cell::RefCell,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use winit::{
+    event::{Event, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopWindowTarget},
+    platform::pump_events::{EventLoopExtPumpEvents, PumpStatus},
+    window::Window,
+};
+
+use super::KeyCode;
+
+thread_local! {
+    /// Event loop of the main thread. It can only be created once, so it is
+    /// kept to run the main loop of the applications created afterwards.
+    static EVENT_LOOP: RefCell<Option<EventLoop<UserEvent<AppEvent>>>> = RefCell::new(None);
+    /// Main loop started without blocking, stepped by [`poll`].
+    static ASYNC_MAIN_LOOP: RefCell<Option<AsyncMainLoop>> = RefCell::new(None);
+}
+
+/// Main loop running without blocking the Python thread.
+struct AsyncMainLoop {
+    event_loop: EventLoop<UserEvent<AppEvent>>,
+    main_loop: MainLoop,
+    /// Copy of the application driven by the main loop. It shares the scene,
+    /// the renderer and the event handlers with the Python application.
+    app: PyAppState,
+}
+
+/// Takes the event loop of the main thread, creating it the first time.
+pub(crate) fn take_event_loop() -> EventLoop<UserEvent<AppEvent>> {
+    EVENT_LOOP
+        .with(|event_loop| event_loop.borrow_mut().take())
+        .unwrap_or_else(|| {
+            EventLoopBuilder::<UserEvent<AppEvent>>::with_user_event()
+                .build()
+                .unwrap()
+        })
+}
+
+/// Gives the event loop back once the main loop exited.
+pub(crate) fn return_event_loop(event_loop: EventLoop<UserEvent<AppEvent>>) {
+    EVENT_LOOP.with(|slot| *slot.borrow_mut() = Some(event_loop));
+}
+
+/// Opens the window of the application and starts its main loop without
+/// running it, returns false if a main loop is already started.
+pub(crate) fn start_async(mut app: PyAppState, builder: PyWindowBuilder) -> bool {
+    if ASYNC_MAIN_LOOP.with(|running| running.borrow().is_some()) {
+        return false;
+    }
+    let event_loop = take_event_loop();
+    let main_loop = MainLoop::new(&mut app, &event_loop, builder);
+    ASYNC_MAIN_LOOP.with(|running| {
+        *running.borrow_mut() = Some(AsyncMainLoop {
+            event_loop,
+            main_loop,
+            app,
+        })
+    });
+    true
+}
+
+/// Processes the pending events of the main loop started by [`start_async`],
+/// waiting at most `timeout` for new events. Returns false once the main
+/// loop exited.
+pub(crate) fn poll(timeout: Duration) -> bool {
+    let Some(mut running) = ASYNC_MAIN_LOOP.with(|running| running.borrow_mut().take()) else {
+        return false;
+    };
+    let AsyncMainLoop {
+        event_loop,
+        main_loop,
+        app,
+    } = &mut running;
+    let status = event_loop.pump_events(Some(timeout), |event, evlp| {
+        main_loop.handle_event(app, event, evlp)
+    });
+    match status {
+        PumpStatus::Continue => {
+            ASYNC_MAIN_LOOP.with(|slot| *slot.borrow_mut() = Some(running));
+            true
+        }
+        PumpStatus::Exit(_) => {
+            let AsyncMainLoop {
+                event_loop,
+                main_loop,
+                ..
+            } = running;
+            drop(main_loop);
+            return_event_loop(event_loop);
+            false
+        }
+    }
+}
+
+/// Window of the application and the state needed to render to it, driven
+/// by the events of the event loop.
+pub(crate) struct MainLoop {
+    window: Arc<Window>,
+    surface: Surface<'static>,
+    context: Arc<GpuContext>,
+    render_pass: BlinnPhongRenderPass,
+    /// Passes of the cameras rendering into textures.
+    camera_passes: FxHashMap<legion::Entity, BlinnPhongRenderPass>,
+    /// Pass rendering the reflection of the water, if a surface reflects.
+    reflection_pass: Option<BlinnPhongRenderPass>,
+    /// Debug UI drawn over the frames when enabled.
+    #[cfg(feature = "debug-ui")]
+    debug_ui: DebugUi,
+    /// Time at which the next frame is due when the frame rate is capped.
+    next_frame: Instant,
+}
+
+impl MainLoop {
+    /// Opens the window of the application and creates the surface to
+    /// render to.
+    pub fn new(
+        app: &mut PyAppState,
+        event_loop: &EventLoop<UserEvent<AppEvent>>,
+        builder: PyWindowBuilder,
+    ) -> Self {
+        let window = Arc::new(app.create_window(event_loop, builder));
+        let context = app.context.clone();
+        let surface = Surface::new(&context, window.clone());
+        let depth = app.renderer.read().unwrap().depth();
+        let render_pass = BlinnPhongRenderPass::new(&context, surface.format(), depth);
+        #[cfg(feature = "debug-ui")]
+        let debug_ui = DebugUi::new(&context.device, surface.format());
+        app.commands()
+            .resize_viewport(surface.width(), surface.height());
+        // Ready to present the window.
+        window.set_visible(true);
+        // ControlFlow::Poll continuously runs the event loop, even if the OS
+        // hasn't dispatched any events.
+        event_loop.set_control_flow(ControlFlow::Poll);
+        Self {
+            window,
+            surface,
+            context,
+            render_pass,
+            camera_passes: FxHashMap::default(),
+            reflection_pass: None,
+            #[cfg(feature = "debug-ui")]
+            debug_ui,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Handles an event of the event loop.
+    pub fn handle_event(
+        &mut self,
+        app: &mut PyAppState,
+        event: Event<UserEvent<AppEvent>>,
+        evlp: &EventLoopWindowTarget<UserEvent<AppEvent>>,
+    ) {
+        match event {
+            Event::UserEvent(UserEvent::Event(AppEvent::Exit)) => {
+                evlp.exit();
+            }
+            Event::UserEvent(UserEvent::Event(AppEvent::SetTextInput(enabled))) => {
+                self.window.set_ime_allowed(enabled);
+            }
+            Event::UserEvent(UserEvent::Event(AppEvent::Posted)) => {
+                app.dispatch_posted_events();
+            }
+            Event::UserEvent(UserEvent::Empty) => {}
+            Event::WindowEvent {
+                ref event,
+                window_id,
+            } if window_id == self.window.id() => {
+                // Events used by the debug UI are not seen by the
+                // application.
+                #[cfg(feature = "debug-ui")]
+                if app.debug_ui.lock().unwrap().enabled
+                    && self
+                        .debug_ui
+                        .handle_event(event, self.window.scale_factor() as f32)
+                {
+                    return;
+                }
+                if !app.process_input(event) {
+                    match event {
+                        WindowEvent::CloseRequested => {
+                            evlp.exit();
+                        }
+                        WindowEvent::Resized(sz) => {
+                            if self
+                                .surface
+                                .resize(&self.context.device, sz.width, sz.height)
+                            {
+                                app.commands().resize_viewport(sz.width, sz.height);
+                                // Dispatch the resize event.
+                                app.dispatch_resize_event(sz.width, sz.height);
+                            }
+                        }
+                        WindowEvent::ScaleFactorChanged { .. } => {
+                            if self.surface.resize(
+                                &self.context.device,
+                                self.window.inner_size().width,
+                                self.window.inner_size().height,
+                            ) {
+                                app.commands().resize_viewport(
+                                    self.window.inner_size().width,
+                                    self.window.inner_size().height,
+                                );
+                                // Dispatch the resize event.
+                                app.dispatch_resize_event(
+                                    self.window.inner_size().width,
+                                    self.window.inner_size().height,
+                                );
+                            }
+                        }
+                        WindowEvent::RedrawRequested => {
+                            // Recreate the device and everything living on
+                            // it if it was lost, then skip the frame.
+                            if self.context.is_lost() {
+                                self.context = app.restore_device();
+                                self.surface = Surface::new(&self.context, self.window.clone());
+                                let depth = app.renderer.read().unwrap().depth();
+                                self.render_pass = BlinnPhongRenderPass::new(
+                                    &self.context,
+                                    self.surface.format(),
+                                    depth,
+                                );
+                                self.camera_passes.clear();
+                                self.reflection_pass = None;
+                                #[cfg(feature = "debug-ui")]
+                                {
+                                    self.debug_ui =
+                                        DebugUi::new(&self.context.device, self.surface.format());
+                                }
+                                app.dispatch_device_restored_event();
+                                return;
+                            }
+
+                            // Grab a frame from the surface.
+                            let frame = match self.surface.get_current_texture() {
+                                Ok(frame) => frame,
+                                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                    self.surface.reconfigure(&self.context.device);
+                                    return;
+                                }
+                                Err(wgpu::SurfaceError::OutOfMemory) => {
+                                    log::error!("Out of GPU memory, recreate the device");
+                                    self.context.mark_lost();
+                                    return;
+                                }
+                                Err(err) => {
+                                    log::warn!("Failed to get a frame: {:?}", err);
+                                    return;
+                                }
+                            };
+                            let target = RenderTarget {
+                                size: frame.texture.size(),
+                                view: frame.texture.create_view(&Default::default()),
+                                format: self.surface.format(),
+                            };
+
+                            let scene = app.scene.read().unwrap();
+                            // The textures of the secondary cameras are
+                            // rendered first, to be sampled by the frame.
+                            let depth = app.renderer.read().unwrap().depth();
+                            app.renderer.read().unwrap().render_camera_targets(
+                                &scene,
+                                &mut self.camera_passes,
+                                |camera, target| {
+                                    BlinnPhongRenderPass::new(&self.context, target.format, depth)
+                                        .with_camera(camera)
+                                },
+                            );
+                            app.renderer.write().unwrap().render_water_reflection(
+                                &scene,
+                                target.size,
+                                &mut self.reflection_pass,
+                                |target| {
+                                    BlinnPhongRenderPass::new(&self.context, target.format, depth)
+                                        .with_reflection()
+                                },
+                            );
+                            match app.renderer.write().unwrap().render(
+                                &scene,
+                                &target,
+                                &mut self.render_pass,
+                            ) {
+                                Ok(_) => {}
+                                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                                    self.surface.reconfigure(&self.context.device);
+                                }
+                                Err(wgpu::SurfaceError::OutOfMemory) => {
+                                    log::error!("Out of GPU memory, recreate the device");
+                                    self.context.mark_lost();
+                                }
+                                Err(e) => log::warn!("Failed to render a frame: {:?}", e),
+                            }
+                            drop(scene);
+                            self.render_pass.exposure.measure(
+                                &self.context.device,
+                                &self.context.queue,
+                                &frame.texture,
+                            );
+
+                            app.recorder
+                                .write()
+                                .unwrap()
+                                .capture(&self.context.queue, &frame.texture);
+                            // Drawn after the capture to be left out of the
+                            // recordings.
+                            #[cfg(feature = "debug-ui")]
+                            if app.debug_ui.lock().unwrap().enabled {
+                                self.debug_ui.render(
+                                    app,
+                                    &self.context.device,
+                                    &self.context.queue,
+                                    &target,
+                                    self.window.scale_factor() as f32,
+                                );
+                            }
+                            frame.present();
+                        }
+                        _ => {}
+                    }
+                    if app.input.is_key_pressed(KeyCode::Escape) {
+                        evlp.exit();
+                    }
+                }
+            }
+            // The main event loop has been cleared and will not be processed
+            // again until the next event needs to be handled.
+            Event::AboutToWait => {
+                // Wait until the next frame is due.
+                let now = Instant::now();
+                if now < self.next_frame {
+                    evlp.set_control_flow(ControlFlow::WaitUntil(self.next_frame));
+                    return;
+                }
+                evlp.set_control_flow(ControlFlow::Poll);
+
+                app.curr_time = Instant::now();
+                let dt = app.delta_time();
+                app.prev_time = app.curr_time;
+                app.update(self.surface.size(), dt);
+                app.prepare();
+
+                let (vsync, target_fps) = {
+                    let renderer = app.renderer.read().unwrap();
+                    (renderer.params().vsync, renderer.params().target_fps)
+                };
+                let present_mode = if vsync {
+                    wgpu::PresentMode::AutoVsync
+                } else {
+                    wgpu::PresentMode::AutoNoVsync
+                };
+                self.surface
+                    .set_present_mode(&self.context.device, present_mode);
+                self.next_frame = match target_fps {
+                    Some(fps) => (self.next_frame + Duration::from_secs_f32(1.0 / fps)).max(now),
+                    None => now,
+                };
+                self.window.request_redraw();
+            }
+            Event::LoopExiting => {
+                app.dispatch_exit_event();
+                *app.event_loop.lock().unwrap() = None;
+                app.shutdown();
+            }
+            // Otherwise, just let the event pass through.
+            _ => {}
+        }
+    }
+}
@@ -1,41 +1,90 @@
+mod camera_anim;
+mod clipboard;
+mod config;
+mod day_cycle;
+#[cfg(feature = "debug-ui")]
+mod debug_ui;
 mod input;
+mod logger;
+mod main_loop;
+#[cfg(feature = "osc")]
+mod osc;
+mod placement;
+mod scenes;
+#[cfg(feature = "network")]
+mod session;
+mod time;
+pub use camera_anim::*;
+pub use clipboard::*;
+pub use config::*;
+pub use day_cycle::*;
+#[cfg(feature = "debug-ui")]
+pub use debug_ui::*;
 pub use input::*;
+pub use logger::*;
+#[cfg(feature = "osc")]
+pub use osc::*;
+pub use placement::*;
+pub use scenes::*;
+#[cfg(feature = "network")]
+pub use session::*;
+pub use time::*;
 pub mod command;
 
 mod window;
 
 pub use window::*;
 
-use crate::render::rpass::BlinnPhongRenderPass;
-use crate::render::surface::Surface;
-use crate::render::RenderTarget;
+#[cfg(feature = "physics")]
+use crate::physics::mesh_collider_shape;
 use crate::{
-    app::command::Command,
-    compute::SunlightScore,
+    app::command::{Command, Commands},
+    compute::{SunlightGround, SunlightScore, Viewshed, SUN_POSITIONS_NUM},
     core::{
-        camera::{Camera, Projection},
-        mesh::{Mesh, MeshBundle},
-        Color, ConcatOrder, FxHashMap, Light, SmlString,
+        assets::{decode_images, Handle, TextureMemoryStats},
+        bvh::{Aabb, Bvh, Ray},
+        camera::{Backdrop, Camera, Easing, Projection},
+        gizmo::{Gizmo, GizmoMode},
+        mesh::{scatter, GpuMesh, LodGroup, LodLevel, Mesh, MeshBundle, ObjStream, ScatterParams},
+        particle::ParticleEmitter,
+        sky::Sky,
+        spline::{Spline, SplineKind},
+        sprite::Sprite,
+        water::Water,
+        Color, ConcatOrder, FxHashMap, Light, Material, SmlString,
+    },
+    render::{
+        AdapterInfo, AdapterOptions, DepthSettings, FrameRecorder, GpuContext, PathTracer,
+        Renderer, SamplerConfig, StaticBatch, Viewport,
+    },
+    scene::{
+        mat4_to_py, vec3_to_py, Baked, CustomShader, Entity, NodeIdx, Prefab, PyEntity, PyScene,
+        RenderLayer, Scene, Transform, Transient,
     },
-    render::{GpuContext, Renderer},
-    scene::{Entity, NodeIdx, PyEntity, Scene},
 };
-use crossbeam_channel::Sender;
-use glam::{Mat4, Quat, Vec3};
-use legion::IntoQuery;
+use crossbeam_channel::{Receiver, Sender, TryRecvError};
+use glam::{Mat4, Quat, Vec2, Vec3};
+use legion::{component, IntoQuery};
+use main_loop::{return_event_loop, take_event_loop, MainLoop};
 use numpy as np;
-use numpy::array;
 use pyo3::{
     prelude::*,
     types::{PyDict, PyTuple},
 };
-use std::sync::{Arc, RwLock};
-use winit::event::{Event, KeyEvent};
-use winit::event_loop::{ControlFlow, EventLoopBuilder};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+use winit::event::KeyEvent;
 use winit::keyboard::PhysicalKey;
+use winit::platform::run_on_demand::EventLoopExtRunOnDemand;
 use winit::{
     dpi::PhysicalSize,
-    event::WindowEvent,
+    event::{ElementState, Ime, WindowEvent},
     event_loop::{EventLoop, EventLoopProxy},
     window::Window,
 };
@@ -51,64 +100,194 @@ pub enum UserEvent<E: 'static> {
 
 unsafe impl<E: 'static> Send for UserEvent<E> {}
 
+/// Payload of the user events sent to the event loop by the application.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AppEvent {
+    /// Exits the main loop.
+    Exit,
+    /// Allows or disallows the input method editor of the window.
+    SetTextInput(bool),
+    /// Dispatches the events posted with `PyAppState::post_event`.
+    Posted,
+}
+
+/// Intersection of a ray with the meshes of the scene.
+#[derive(Clone, Copy, Debug)]
+pub struct RaycastHit {
+    /// Entity whose mesh is hit.
+    pub entity: Entity,
+    /// Distance from the origin of the ray.
+    pub distance: f32,
+    /// Hit point in world space.
+    pub point: Vec3,
+    /// Normal of the surface at the hit point, facing the ray.
+    pub normal: Vec3,
+}
+
+/// Mesh entity in the BVH the raycasts go through.
+#[derive(Clone, Copy, Debug)]
+struct MeshInstance {
+    entity: Entity,
+    mesh: Handle<GpuMesh>,
+}
+
+/// Meshes read in the background from a file, waiting to be added to the
+/// scene.
+#[derive(Clone)]
+struct MeshStream {
+    /// Node under which the meshes are spawned.
+    parent: NodeIdx,
+    /// Receives the meshes as soon as they are read.
+    receiver: Receiver<Mesh>,
+    /// Stops the reading thread when set.
+    cancelled: Arc<AtomicBool>,
+}
+
 #[pyclass(subclass)]
 #[derive(Clone)]
 pub struct PyAppState {
     pub input: InputState,
-    event_loop: Option<EventLoopProxy<UserEvent<()>>>,
-    event_listeners: FxHashMap<SmlString, Vec<PyObject>>,
-    start_time: std::time::Instant,
+    /// Proxy of the running event loop, shared with the copies of the
+    /// application.
+    event_loop: Arc<Mutex<Option<EventLoopProxy<UserEvent<AppEvent>>>>>,
+    /// Event handlers, shared with the copies of the application.
+    event_listeners: Arc<RwLock<FxHashMap<SmlString, Vec<PyObject>>>>,
+    /// Events posted from any thread with their payload, waiting to be
+    /// dispatched on the main thread.
+    posted_events: Arc<Mutex<Vec<(SmlString, Option<PyObject>)>>>,
     prev_time: std::time::Instant,
     curr_time: std::time::Instant,
+    /// Scaling of the time of the animations, shared with the copies of the
+    /// application.
+    time: Arc<Mutex<TimeControl>>,
     context: Arc<GpuContext>,
+    /// Selection of the adapter, kept to recreate the context on the same
+    /// one.
+    adapter_options: AdapterOptions,
+    /// Active scene, exchanged with the other scenes when switching scenes.
     scene: Arc<RwLock<Scene>>,
+    /// Scenes other than the active one, shared with the copies of the
+    /// application.
+    scenes: Arc<Mutex<SceneStore>>,
     renderer: Arc<RwLock<Renderer>>,
-    scene_cmd_sender: Sender<Command>,
+    /// Command sender of the active scene, shared with the copies of the
+    /// application.
+    scene_cmd_sender: Arc<RwLock<Sender<Command>>>,
     renderer_cmd_sender: Sender<Command>,
     sunlight_score: Arc<RwLock<SunlightScore>>,
+    recorder: Arc<RwLock<FrameRecorder>>,
     main_camera: Option<Entity>,
+    camera_animator: CameraAnimator,
+    day_cycle: Option<SunAnimator>,
+    mesh_streams: Vec<MeshStream>,
+    /// Ongoing interactive placement of a mesh, if any.
+    placement: Option<Placement>,
+    /// BVH over the world bounds of the mesh entities, rebuilt when they
+    /// change and refitted when they move.
+    mesh_instances: Arc<Mutex<Option<Bvh<MeshInstance>>>>,
+    /// Visibility of the debug UI and the controls added to it.
+    #[cfg(feature = "debug-ui")]
+    debug_ui: Arc<Mutex<DebugControls>>,
+    /// Shared session hosted or joined, if any.
+    #[cfg(feature = "network")]
+    session: Arc<Mutex<Option<Session>>>,
+    /// Listener of the OSC messages with its bindings, if any.
+    #[cfg(feature = "osc")]
+    osc: Arc<Mutex<Option<OscListener>>>,
+    /// Settings of the window opened by the main loop if none is given.
+    window: PyWindowBuilder,
+    /// Whether the input method editor of the window is allowed, shared with
+    /// the copies of the application.
+    text_input: Arc<AtomicBool>,
+    /// Size in pixels of the window at the last frame, shared with the
+    /// copies of the application.
+    window_size: Arc<Mutex<(u32, u32)>>,
 }
 
 /// Python interface for AppState
 #[pymethods]
 impl PyAppState {
     #[new]
-    pub fn new() -> PyResult<Self> {
-        env_logger::init();
+    #[pyo3(signature = (config=None))]
+    pub fn new(config: Option<PyAppConfig>) -> PyResult<Self> {
+        // The logger is already set if an application was created before.
+        logger::init();
         let now = std::time::Instant::now();
-        let context = Arc::new(GpuContext::new(Some(
-            wgpu::Features::POLYGON_MODE_LINE
-                | wgpu::Features::PUSH_CONSTANTS
-                | wgpu::Features::TEXTURE_BINDING_ARRAY
-                | wgpu::Features::STORAGE_RESOURCE_BINDING_ARRAY,
-        )));
+        let adapter_options = config
+            .as_ref()
+            .map_or_else(AdapterOptions::default, |config| config.adapter)
+            .overridden_by_env();
+        let context = Arc::new(GpuContext::new(None, &adapter_options));
         let (scene_cmd_sender, scene_cmd_receiver) = crossbeam_channel::unbounded::<Command>();
         let scene = Scene::new(scene_cmd_sender.clone(), scene_cmd_receiver);
         let (renderer_cmd_sender, renderer_cmd_receiver) =
             crossbeam_channel::unbounded::<Command>();
-        let renderer = Renderer::new(&context, renderer_cmd_receiver);
+        let depth = config
+            .as_ref()
+            .map_or_else(DepthSettings::default, |config| config.depth);
+        let renderer = Renderer::new(&context, renderer_cmd_receiver, depth);
         let sunlight_score = SunlightScore::new(&context.device);
-        Ok(Self {
+        let mut app = Self {
             context,
+            adapter_options,
             input: InputState::default(),
-            event_loop: None,
+            event_loop: Arc::new(Mutex::new(None)),
             event_listeners: Default::default(),
-            start_time: now,
+            posted_events: Default::default(),
             prev_time: now,
             curr_time: now,
+            time: Default::default(),
             scene: Arc::new(RwLock::new(scene)),
+            scenes: Arc::new(Mutex::new(SceneStore::default())),
             renderer: Arc::new(RwLock::new(renderer)),
-            scene_cmd_sender,
+            scene_cmd_sender: Arc::new(RwLock::new(scene_cmd_sender)),
             renderer_cmd_sender,
             main_camera: None,
+            camera_animator: CameraAnimator::default(),
+            day_cycle: None,
+            mesh_streams: Vec::new(),
+            placement: None,
+            mesh_instances: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "debug-ui")]
+            debug_ui: Arc::new(Mutex::new(DebugControls::default())),
+            #[cfg(feature = "network")]
+            session: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "osc")]
+            osc: Arc::new(Mutex::new(None)),
             sunlight_score: Arc::new(RwLock::new(sunlight_score)),
-        })
+            recorder: Arc::new(RwLock::new(FrameRecorder::default())),
+            window: PyWindowBuilder::default(),
+            text_input: Arc::new(AtomicBool::new(false)),
+            window_size: Arc::new(Mutex::new((0, 0))),
+        };
+        if let Some(config) = config {
+            app.apply_config(config);
+        }
+        Ok(app)
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Shuts the application down when leaving the `with` block, without
+    /// suppressing the exception raised in it if any.
+    fn __exit__(
+        &mut self,
+        _exc_type: Option<&PyAny>,
+        _exc_value: Option<&PyAny>,
+        _traceback: Option<&PyAny>,
+    ) -> bool {
+        self.shutdown();
+        false
     }
 
     /// Register an event type.
     #[pyo3(text_signature = "($self, event_name)")]
     pub fn register_event_type(&mut self, event_type: String) {
         self.event_listeners
+            .write()
+            .unwrap()
             .entry(SmlString::from(event_type))
             .or_default();
     }
@@ -124,6 +303,8 @@ impl PyAppState {
     /// Attach a handler to an event type.
     pub fn attach_event_handler(&mut self, event_type: String, listener: PyObject) {
         self.event_listeners
+            .write()
+            .unwrap()
             .entry(SmlString::from(event_type))
             .or_default()
             .push(listener);
@@ -131,54 +312,545 @@ impl PyAppState {
 
     /// Detach a handler from an event type.
     pub fn detach_event_handler(&mut self, event_type: String, listener: PyObject) {
-        if let Some(listeners) = self.event_listeners.get_mut(event_type.as_str()) {
+        if let Some(listeners) = self
+            .event_listeners
+            .write()
+            .unwrap()
+            .get_mut(event_type.as_str())
+        {
             listeners.retain(|l| !l.is(&listener));
         }
     }
 
-    /// Get the frame time in seconds.
+    /// Posts an event to be dispatched to its handlers on the main thread,
+    /// with the payload as argument if any, e.g. for a background thread to
+    /// signal the application that an asset is loaded.
+    ///
+    /// The event wakes up the main loop; events posted while it is not
+    /// running are dispatched at its next frame.
+    #[pyo3(signature = (name, payload=None))]
+    pub fn post_event(&self, name: &str, payload: Option<PyObject>) {
+        self.posted_events
+            .lock()
+            .unwrap()
+            .push((SmlString::from(name), payload));
+        if let Some(proxy) = self.event_loop.lock().unwrap().as_ref() {
+            let _ = proxy.send_event(UserEvent::Event(AppEvent::Posted));
+        }
+    }
+
+    /// Asks the main loop to exit after the current frame. The "on_exit"
+    /// event is dispatched before the resources of the application are
+    /// released.
+    pub fn request_exit(&self) {
+        match self.event_loop.lock().unwrap().as_ref() {
+            Some(proxy) => {
+                if proxy.send_event(UserEvent::Event(AppEvent::Exit)).is_err() {
+                    log::warn!("The main loop already exited.");
+                }
+            }
+            None => log::warn!("The main loop is not running."),
+        }
+    }
+
+    /// Allows the input method editor (IME) of the window, to compose the
+    /// text of the languages needing it. The composed text is dispatched to
+    /// the "on_text_input" handlers like the text typed on the keyboard,
+    /// and the text being composed to the "on_text_preedit" handlers.
+    ///
+    /// The IME is disallowed by default, as it may take the keys used to
+    /// control the application.
+    pub fn enable_text_input(&self, enabled: bool) {
+        self.text_input.store(enabled, Ordering::Relaxed);
+        if let Some(proxy) = self.event_loop.lock().unwrap().as_ref() {
+            let _ = proxy.send_event(UserEvent::Event(AppEvent::SetTextInput(enabled)));
+        }
+    }
+
+    /// Returns the text of the system clipboard, `None` if it holds no
+    /// text.
+    pub fn get_clipboard_text(&self) -> Option<String> {
+        clipboard_text()
+    }
+
+    /// Sets the text of the system clipboard.
+    pub fn set_clipboard_text(&self, text: &str) -> PyResult<()> {
+        set_clipboard_text(text).map_err(pyo3::exceptions::PyRuntimeError::new_err)
+    }
+
+    /// Opens the window and starts the main loop without blocking, so that
+    /// the scene can still be modified from a notebook.
+    ///
+    /// The main loop is stepped by `poll`, which is called regularly on the
+    /// running asyncio event loop (as in Jupyter) if any. Otherwise `poll`
+    /// must be called by the user.
+    #[pyo3(signature = (builder=None))]
+    pub fn run_async(slf: &PyCell<Self>, builder: Option<PyWindowBuilder>) -> PyResult<()> {
+        let app = slf.borrow().clone();
+        let builder = builder.unwrap_or_else(|| app.window.clone());
+        if !main_loop::start_async(app, builder) {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "The main loop is already running",
+            ));
+        }
+        Self::schedule_poll(slf)
+    }
+
+    /// Processes the pending events of the main loop started by `run_async`
+    /// and renders a frame if one is due, waiting at most `timeout` seconds
+    /// for new events. Returns false once the main loop exited.
+    #[pyo3(signature = (timeout=None))]
+    pub fn poll(_slf: &PyCell<Self>, timeout: Option<f32>) -> PyResult<bool> {
+        let timeout = match timeout {
+            None => Duration::ZERO,
+            Some(secs) => Duration::try_from_secs_f32(secs).map_err(|_| {
+                pyo3::exceptions::PyValueError::new_err(
+                    "The timeout must be a finite and non-negative number of seconds",
+                )
+            })?,
+        };
+        Ok(main_loop::poll(timeout))
+    }
+
+    /// Polls the main loop and schedules the next poll while it runs.
+    fn _poll_scheduled(slf: &PyCell<Self>) -> PyResult<()> {
+        if Self::poll(slf, None)? {
+            Self::schedule_poll(slf)?;
+        }
+        Ok(())
+    }
+
+    /// Get the real frame time in seconds, not affected by `set_time_scale`.
     pub fn delta_time(&self) -> f32 {
         self.curr_time.duration_since(self.prev_time).as_secs_f32()
     }
 
+    /// Slow down (below 1) or speed up (above 1) the time passed to the
+    /// update handlers, the physics and the animations of the scene. The
+    /// camera controls keep following the real time.
+    pub fn set_time_scale(&self, scale: f32) {
+        self.time.lock().unwrap().set_scale(scale);
+    }
+
+    /// Factor applied to the frame time passed to the update handlers.
+    #[getter]
+    pub fn get_time_scale(&self) -> f32 {
+        self.time.lock().unwrap().scale()
+    }
+
+    /// Pause the time passed to the update handlers, the physics and the
+    /// animations of the scene, which then receive a frame time of zero.
+    pub fn pause(&self) {
+        self.time.lock().unwrap().set_paused(true);
+    }
+
+    /// Resume the time paused by `pause` or `step_frame`.
+    pub fn resume(&self) {
+        self.time.lock().unwrap().set_paused(false);
+    }
+
+    /// Whether the time is paused.
+    #[getter]
+    pub fn is_paused(&self) -> bool {
+        self.time.lock().unwrap().is_paused()
+    }
+
+    /// Pause the time and advance it by the given number of frames, one per
+    /// rendered frame, each lasting `duration` seconds (1/60 by default)
+    /// whatever the time scale.
+    #[pyo3(signature = (frames=1, duration=None))]
+    pub fn step_frame(&self, frames: u32, duration: Option<f32>) {
+        self.time
+            .lock()
+            .unwrap()
+            .step(frames, duration.unwrap_or(TimeControl::DEFAULT_STEP));
+    }
+
+    /// Get the name, the backend and the limits of the GPU adapter the
+    /// application renders with.
+    pub fn get_adapter_info(&self) -> AdapterInfo {
+        self.context.adapter_info()
+    }
+
     /// Get the transform of an entity.
     #[pyo3(name = "get_transform")]
     pub fn get_transform_py(&self, entity: &PyEntity) -> Py<np::PyArray2<f32>> {
-        Python::with_gil(|py| {
-            let mat = self.scene.read().unwrap().nodes[entity.entity.node]
+        mat4_to_py(
+            self.scene.read().unwrap().nodes[entity.entity.node]
                 .transform()
-                .to_mat4()
-                .transpose();
-            let [x, y, z, w] = mat.to_cols_array_2d();
-            np::PyArray2::<f32>::from_array(py, &array![x, y, z, w]).to_owned()
+                .to_mat4(),
+        )
+    }
+
+    /// Re-uploads the materials of the mesh of an entity after they have
+    /// been modified. All entities sharing the same materials are affected.
+    ///
+    /// Returns false if the entity has no mesh or if the number of materials
+    /// differs from the number of materials of the mesh.
+    pub fn update_materials(&mut self, entity: &PyEntity, materials: Vec<Material>) -> bool {
+        let mesh = {
+            let mut scene = self.scene.write().unwrap();
+            scene.prepare(&mut self.main_camera);
+            scene
+                .world
+                .entry_ref(entity.entity.raw)
+                .ok()
+                .and_then(|entry| entry.get_component::<MeshBundle>().ok().copied())
+        };
+        match mesh {
+            None => {
+                log::error!("Entity {:?} has no mesh!", entity.entity);
+                false
+            }
+            Some(mesh) => self
+                .renderer
+                .write()
+                .unwrap()
+                .update_materials(mesh.aesthetic, &materials),
+        }
+    }
+
+    /// Keep the mesh of an entity uploaded once no entity uses it anymore,
+    /// e.g. to spawn it again later, or let it be removed once unused. The
+    /// meshes are otherwise removed with the last entity using them.
+    ///
+    /// Returns false if the entity has no mesh.
+    #[pyo3(signature = (entity, pinned=true))]
+    pub fn pin_mesh(&mut self, entity: &PyEntity, pinned: bool) -> bool {
+        let meshes = self.scene.read().unwrap().meshes_of(entity.entity);
+        let mut renderer = self.renderer.write().unwrap();
+        for mesh in &meshes {
+            renderer.pin_mesh(*mesh, pinned);
+        }
+        !meshes.is_empty()
+    }
+
+    /// Capture an entity and its descendants, with their meshes, materials,
+    /// lights and transforms, into a prefab to be instantiated with
+    /// `instantiate`.
+    ///
+    /// The meshes of the prefab stay uploaded even once no entity uses them,
+    /// see `pin_mesh`. Returns `None` if the entity doesn't exist.
+    pub fn capture_prefab(&mut self, entity: &PyEntity) -> Option<Prefab> {
+        let prefab = {
+            let mut scene = self.scene.write().unwrap();
+            scene.prepare(&mut self.main_camera);
+            scene.capture_prefab(entity.entity)?
+        };
+        let mut renderer = self.renderer.write().unwrap();
+        for mesh in prefab.meshes() {
+            renderer.pin_mesh(mesh, true);
+        }
+        Some(prefab)
+    }
+
+    /// Spawn a copy of the entities of the prefab, sharing their meshes and
+    /// materials with the other copies. The root of the copy is placed at the
+    /// given 4x4 transform relative to its parent, or at the transform of the
+    /// captured entity if `None`.
+    ///
+    /// Returns the root entity of the copy.
+    #[pyo3(signature = (prefab, transform=None, parent=None))]
+    pub fn instantiate(
+        &mut self,
+        prefab: &Prefab,
+        transform: Option<&np::PyArray2<f32>>,
+        parent: Option<&PyEntity>,
+    ) -> PyEntity {
+        let parent = parent.map(|p| p.entity.node).unwrap_or(NodeIdx::root());
+        let transform = transform.map(|mat4| {
+            let mat = Mat4::from_cols_slice(mat4.readonly().as_slice().unwrap()).transpose();
+            Transform::from_mat4(mat)
+        });
+        let entity = {
+            let mut scene = self.scene.write().unwrap();
+            let mut renderer = self.renderer.write().unwrap();
+            let (entity, instances) = scene.instantiate(prefab, parent, transform);
+            for (mesh, node) in instances {
+                renderer.add_instancing(mesh, &[node]);
+            }
+            entity
+        };
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+
+    /// Start or stop recording the changes of the transforms, visibility,
+    /// materials, lights, names and tags of the entities of the active scene,
+    /// and the spawned and despawned entities, to undo them with `undo`. The
+    /// recorded changes are forgotten when stopping.
+    #[pyo3(signature = (enabled=true))]
+    pub fn enable_journal(&mut self, enabled: bool) {
+        let mut scene = self.scene.write().unwrap();
+        // The changes requested before are not recorded.
+        scene.prepare(&mut self.main_camera);
+        scene.set_journal_enabled(enabled);
+    }
+
+    /// Group the following changes of the entities into a single transaction,
+    /// undone and redone at once, until `end_transaction` is called.
+    /// Transactions may be nested, the outermost one grouping all the
+    /// changes.
+    pub fn begin_transaction(&self) {
+        self.commands().begin_transaction();
+    }
+
+    /// End the transaction started by the matching `begin_transaction`.
+    pub fn end_transaction(&self) {
+        self.commands().end_transaction();
+    }
+
+    /// Revert the last recorded transaction of the active scene, see
+    /// `enable_journal`. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        let mut scene = self.scene.write().unwrap();
+        scene.prepare(&mut self.main_camera);
+        scene.undo()
+    }
+
+    /// Apply again the last undone transaction of the active scene. Returns
+    /// false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        let mut scene = self.scene.write().unwrap();
+        scene.prepare(&mut self.main_camera);
+        scene.redo()
+    }
+
+    /// Create a new empty scene sharing the meshes, materials and textures of
+    /// the application with the other scenes. The scene is inactive until
+    /// `set_active_scene` is called with it.
+    pub fn create_scene(&self) -> PyScene {
+        self.scenes.lock().unwrap().create()
+    }
+
+    /// Get the scene the application renders and spawns entities in.
+    pub fn active_scene(&self) -> PyScene {
+        self.scenes.lock().unwrap().active()
+    }
+
+    /// Make the scene the one the application renders and spawns entities
+    /// in. The entities of the previously active scene are kept, without
+    /// uploading their meshes again when it is activated later.
+    ///
+    /// Returns false if the scene doesn't exist.
+    pub fn set_active_scene(&mut self, scene: PyScene) -> bool {
+        let scenes = self.scenes.clone();
+        let mut scenes = scenes.lock().unwrap();
+        if !scenes.contains(scene) {
+            log::error!("{:?} doesn't exist!", scene);
+            return false;
+        }
+        if scenes.active() == scene {
+            return true;
+        }
+        // The pending changes belong to the current scene.
+        self.cancel_placement();
+        self.flush_scene();
+        self.camera_animator = CameraAnimator::default();
+        *self.mesh_instances.lock().unwrap() = None;
+        let mut active = self.scene.write().unwrap();
+        scenes.activate(scene, &mut active, &mut self.renderer.write().unwrap());
+        *self.scene_cmd_sender.write().unwrap() = active.cmd_sender().clone();
+        self.main_camera = active.main_camera();
+        true
+    }
+
+    /// Find the entity with the given name.
+    ///
+    /// Returns `None` if no entity has been given this name. If several
+    /// entities share the same name, the first one named is returned.
+    pub fn find_entity(&mut self, name: &str) -> Option<PyEntity> {
+        let mut scene = self.scene.write().unwrap();
+        // Apply pending commands so that names set in the same frame are found.
+        scene.prepare(&mut self.main_camera);
+        scene.find_entity(name).map(|entity| PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
         })
     }
 
+    /// Find all entities whose name starts with the given prefix.
+    pub fn find_entities(&mut self, prefix: &str) -> Vec<PyEntity> {
+        let mut scene = self.scene.write().unwrap();
+        scene.prepare(&mut self.main_camera);
+        scene
+            .find_entities(prefix)
+            .map(|entity| PyEntity {
+                entity,
+                cmd_sender: self.scene_cmd_sender(),
+                scene: self.scene.clone(),
+            })
+            .collect()
+    }
+
+    /// Get all entities with the given tag.
+    pub fn get_entities_by_tag(&mut self, tag: &str) -> Vec<PyEntity> {
+        let mut scene = self.scene.write().unwrap();
+        scene.prepare(&mut self.main_camera);
+        scene
+            .entities_with_tag(tag)
+            .iter()
+            .map(|entity| PyEntity {
+                entity: *entity,
+                cmd_sender: self.scene_cmd_sender(),
+                scene: self.scene.clone(),
+            })
+            .collect()
+    }
+
+    /// Hide all entities with the given tag.
+    pub fn hide_by_tag(&mut self, tag: &str) {
+        self.scene_cmd_sender()
+            .send(Command::SetVisibleByTag {
+                tag: SmlString::from(tag),
+                visible: false,
+            })
+            .unwrap();
+    }
+
+    /// Show all entities with the given tag.
+    pub fn show_by_tag(&mut self, tag: &str) {
+        self.scene_cmd_sender()
+            .send(Command::SetVisibleByTag {
+                tag: SmlString::from(tag),
+                visible: true,
+            })
+            .unwrap();
+    }
+
+    /// Delete all entities with the given tag, together with their children.
+    pub fn delete_by_tag(&mut self, tag: &str) {
+        self.scene_cmd_sender()
+            .send(Command::DespawnByTag {
+                tag: SmlString::from(tag),
+            })
+            .unwrap();
+    }
+
     /// Set the backface culling state.
     pub fn enable_backface_culling(&mut self, enabled: bool) {
-        self.renderer_cmd_sender
-            .send(Command::EnableBackfaceCulling(enabled))
-            .unwrap();
+        self.commands().enable_backface_culling(enabled);
     }
 
     /// Set the shadows rendering state.
     pub fn enable_shadows(&mut self, enabled: bool) {
-        self.renderer_cmd_sender
-            .send(Command::EnableShadows(enabled))
-            .unwrap();
+        self.commands().enable_shadows(enabled);
     }
 
     /// Set the wireframe rendering state.
     pub fn enable_wireframe(&mut self, enabled: bool) {
-        self.renderer_cmd_sender
-            .send(Command::EnableWireframe(enabled))
-            .unwrap();
+        self.commands().enable_wireframe(enabled);
+    }
+
+    /// Shows or hides the debug gizmos: an arrow per directional light, a
+    /// cross per point light, the frustum of the cameras other than the main
+    /// one and the volume covered by each shadow map.
+    pub fn show_debug_gizmos(&mut self, shown: bool) {
+        self.commands().show_debug_gizmos(shown);
     }
 
     pub fn enable_lighting(&mut self, enabled: bool) {
-        self.renderer_cmd_sender
-            .send(Command::EnableLighting(enabled))
-            .unwrap();
+        self.commands().enable_lighting(enabled);
+    }
+
+    /// Set whether the objects hidden behind other objects are skipped. The
+    /// visibility is determined from the previous frames, so that hidden
+    /// objects may appear a few frames late when uncovered.
+    pub fn enable_occlusion_culling(&mut self, enabled: bool) {
+        self.commands().enable_occlusion_culling(enabled);
+    }
+
+    /// Set whether the objects outside the view are skipped by a compute
+    /// pass on the GPU, instead of being drawn and clipped. Only the objects
+    /// drawn with the default shader are culled, and only if the GPU supports
+    /// indirect draws.
+    pub fn enable_gpu_culling(&mut self, enabled: bool) {
+        self.commands().enable_gpu_culling(enabled);
+    }
+
+    /// Set whether the depth of the objects is drawn before shading them, so
+    /// that each pixel is shaded only once. This speeds up dense scenes, but
+    /// transparent objects hide the objects behind them.
+    pub fn enable_depth_prepass(&mut self, enabled: bool) {
+        self.commands().enable_depth_prepass(enabled);
+    }
+
+    /// Set whether mesh and texture files modified on disk are reloaded.
+    pub fn enable_hot_reload(&mut self, enabled: bool) {
+        self.commands().enable_hot_reload(enabled);
+    }
+
+    /// Set whether the frames are presented in sync with the display refresh
+    /// rate. When disabled, frames are presented as soon as they are ready.
+    pub fn set_vsync(&mut self, enabled: bool) {
+        self.commands().set_vsync(enabled);
+    }
+
+    /// Set the maximum number of frames rendered per second, the frame rate
+    /// is uncapped if `None` or not positive. Rates below 0.01 are raised to
+    /// it.
+    #[pyo3(signature = (fps=None))]
+    pub fn set_target_fps(&mut self, fps: Option<f32>) {
+        self.commands().set_target_fps(fps);
+    }
+
+    /// Set the directory from which `blph.wgsl` and `shadow.wgsl` are loaded,
+    /// or restore the embedded shaders if `None`. The shaders are reloaded
+    /// when modified, falling back to the embedded ones if they fail to
+    /// compile.
+    #[pyo3(signature = (path=None))]
+    pub fn set_shader_directory(&mut self, path: Option<&str>) {
+        self.commands()
+            .set_shader_directory(path.map(PathBuf::from));
+    }
+
+    /// Set the cube map reflected by the materials from the image files of
+    /// its 6 faces, in the order of +X, -X, +Y, -Y, +Z, -Z, or remove it if
+    /// `None`.
+    #[pyo3(signature = (faces=None))]
+    pub fn set_environment_map(&mut self, faces: Option<Vec<String>>) -> PyResult<()> {
+        let faces = match faces {
+            Some(faces) => Some(
+                <[String; 6]>::try_from(faces)
+                    .map_err(|_| {
+                        pyo3::exceptions::PyValueError::new_err(
+                            "An environment map requires exactly 6 faces",
+                        )
+                    })?
+                    .map(PathBuf::from),
+            ),
+            None => None,
+        };
+        self.commands().set_environment_map(faces);
+        Ok(())
+    }
+
+    /// Register the settings of a sampler under a name, which textures
+    /// request with `Texture.set_sampler`. Replaces the settings previously
+    /// registered under this name, including the ones described by names
+    /// like `clamp_nearest`.
+    pub fn register_sampler(&mut self, name: &str, config: SamplerConfig) {
+        self.commands().register_sampler(name, config);
+    }
+
+    /// Limit the GPU memory of the textures to the given number of
+    /// megabytes, or remove the limit if `None`. The least recently used
+    /// textures of the entities which are not visible are evicted beyond it,
+    /// and loaded again from their files once visible.
+    #[pyo3(signature = (megabytes=None))]
+    pub fn set_texture_budget(&mut self, megabytes: Option<f32>) {
+        self.commands()
+            .set_texture_budget(megabytes.map(megabytes_to_bytes));
+    }
+
+    /// Returns the GPU memory used by the textures and the evictions of the
+    /// last frame.
+    pub fn texture_memory_stats(&self) -> TextureMemoryStats {
+        self.renderer.read().unwrap().texture_memory_stats()
     }
 
     #[deprecated(note = "Should be automatically updated by the renderer.")]
@@ -188,6 +860,128 @@ impl PyAppState {
             .unwrap();
     }
 
+    /// Tints the visible mesh entities by their exposure to the sun at the
+    /// given position of the sunlight scores, from blue in the shadow to
+    /// yellow in the sun, or removes the tints if `None`.
+    ///
+    /// The exposure of an entity is the fraction of the area of its sampled
+    /// triangles lit by the sun. Returns the exposure of each tinted entity.
+    #[pyo3(signature = (position_index=None))]
+    pub fn show_sunlight_overlay(
+        &mut self,
+        position_index: Option<usize>,
+    ) -> PyResult<Vec<(PyEntity, f32)>> {
+        for node in self.scene.write().unwrap().nodes.iter_mut() {
+            node.tint = None;
+        }
+        let Some(index) = position_index else {
+            return Ok(Vec::new());
+        };
+        if index >= SUN_POSITIONS_NUM {
+            return Err(pyo3::exceptions::PyIndexError::new_err(format!(
+                "Sun position {} out of range 0..{}",
+                index, SUN_POSITIONS_NUM
+            )));
+        }
+        let sun_dir = self
+            .sunlight_score
+            .read()
+            .unwrap()
+            .ground()
+            .sun_direction(index);
+        let exposures = self.sun_exposures(sun_dir);
+        let shadow = Vec3::new(0.02, 0.05, 0.6);
+        let sun = Vec3::new(1.0, 0.85, 0.1);
+        let mut scene = self.scene.write().unwrap();
+        for (entity, exposure) in &exposures {
+            let color = shadow.lerp(sun, *exposure);
+            scene.nodes[entity.node].tint = Some(Color::new(
+                color.x as f64,
+                color.y as f64,
+                color.z as f64,
+                0.75,
+            ));
+        }
+        Ok(exposures
+            .into_iter()
+            .map(|(entity, exposure)| {
+                (
+                    PyEntity {
+                        entity,
+                        cmd_sender: self.scene_cmd_sender(),
+                        scene: self.scene.clone(),
+                    },
+                    exposure,
+                )
+            })
+            .collect())
+    }
+
+    /// Sets the ground plane the sun travels over when computing the
+    /// sunlight scores.
+    ///
+    /// * `up` - The normal of the ground, pointing to the sky.
+    /// * `height` - The height of the ground along `up`.
+    #[pyo3(signature = (up, height=0.0))]
+    pub fn set_sunlight_ground(&mut self, up: &np::PyArray2<f32>, height: f32) {
+        let up = Vec3::from_slice(up.readonly().as_slice().unwrap());
+        self.sunlight_score
+            .write()
+            .unwrap()
+            .set_ground(SunlightGround { up, height });
+    }
+
+    /// Returns the region covered by the last sunlight scores, as the min
+    /// and max corners of the bounds of the scene, and the largest size in
+    /// world units of a pixel of the occlusion maps.
+    pub fn sunlight_coverage(&self) -> (Py<np::PyArray2<f32>>, Py<np::PyArray2<f32>>, f32) {
+        let coverage = self.sunlight_score.read().unwrap().coverage();
+        (
+            vec3_to_py(coverage.bounds.min),
+            vec3_to_py(coverage.bounds.max),
+            coverage.texel_size,
+        )
+    }
+
+    /// Computes how much of each target is seen from the observer, by
+    /// casting a ray through each texel of a cube map around it.
+    ///
+    /// * `observer` - The position the targets are seen from.
+    /// * `targets` - The entities seen, including their descendants.
+    /// * `resolution` - The number of texels along a face of the cube map.
+    /// * `up` - If given, only the hemisphere above the observer is seen.
+    ///
+    /// Returns for each target the fraction of it not hidden by the other
+    /// meshes, and the solid angle in steradians of its visible part.
+    #[pyo3(signature = (observer, targets, resolution=128, up=None))]
+    pub fn compute_viewshed(
+        &mut self,
+        py: Python,
+        observer: &np::PyArray2<f32>,
+        targets: Vec<PyEntity>,
+        resolution: u32,
+        up: Option<&np::PyArray2<f32>>,
+    ) -> Vec<(PyEntity, f32, f32)> {
+        profiling::scope!("compute_viewshed");
+        let observer = Vec3::from_slice(observer.readonly().as_slice().unwrap());
+        let up = up.map(|up| Vec3::from_slice(up.readonly().as_slice().unwrap()));
+        let entities = targets
+            .iter()
+            .map(|target| target.entity)
+            .collect::<Vec<_>>();
+        let viewshed = {
+            let scene = self.scene.read().unwrap();
+            let mut renderer = self.renderer.write().unwrap();
+            Viewshed::new(&scene, &mut renderer, &entities)
+        };
+        let visibilities = py.allow_threads(|| viewshed.compute(observer, resolution, up));
+        targets
+            .into_iter()
+            .zip(visibilities)
+            .map(|(target, visibility)| (target, visibility.visibility, visibility.solid_angle))
+            .collect()
+    }
+
     pub fn compute_sunlight_scores(&mut self) -> Vec<f32> {
         profiling::scope!("compute_sunlight_score");
         self.sunlight_score
@@ -210,6 +1004,29 @@ impl PyAppState {
             .unwrap()
     }
 
+    /// Starts computing the sunlight scores without blocking, see
+    /// `compute_sunlight_scores`.
+    ///
+    /// Returns the id of the computation. The `on_scores_ready` event is
+    /// dispatched with the scores and this id once they are computed.
+    pub fn compute_sunlight_scores_async(&mut self) -> u64 {
+        profiling::scope!("compute_sunlight_score_async");
+        let mut score = self.sunlight_score.write().unwrap();
+        let scene = self.scene.read().unwrap();
+        let mut mesh_bundle_query = <(&MeshBundle, &NodeIdx)>::query();
+        let meshes = mesh_bundle_query.iter(&scene.world).filter(|(_, node)| {
+            scene.nodes[**node].is_visible() && scene.nodes[**node].cast_shadows()
+        });
+        let renderer = self.renderer.read().unwrap();
+        score.compute_async(
+            &self.context.device,
+            &self.context.queue,
+            &scene,
+            &renderer,
+            meshes,
+        )
+    }
+
     /// Create a camera
     ///
     /// # Arguments
@@ -241,64 +1058,1067 @@ impl PyAppState {
             let entity = self.create_camera(proj, pos, target, background);
             PyEntity {
                 entity,
-                cmd_sender: self.scene_cmd_sender.clone(),
+                cmd_sender: self.scene_cmd_sender(),
+                scene: self.scene.clone(),
             }
         })
     }
 
-    /// Adds a mesh to the scene.
-    // TODO: pass transform as an argument.
-    #[pyo3(name = "add_mesh")]
-    #[pyo3(signature = (mesh, parent=None))]
-    pub fn add_mesh_py(&mut self, mesh: &mut Mesh, parent: Option<&PyEntity>) -> PyEntity {
-        let parent = parent.map(|p| p.entity.node).unwrap_or(NodeIdx::root());
-        let entity = self.spawn_object_with_mesh(parent, mesh);
-        PyEntity {
-            entity,
-            cmd_sender: self.scene_cmd_sender.clone(),
+    /// Fixes the aspect ratio of the image of the main camera, letterboxing
+    /// it in the window, or makes it follow the window if `None`.
+    #[pyo3(signature = (aspect=None))]
+    pub fn set_fixed_aspect(&mut self, aspect: Option<f32>) {
+        match self.main_camera {
+            Some(entity) => self.commands().set_fixed_aspect(entity, aspect),
+            None => log::warn!("No main camera, can't set the aspect ratio."),
         }
     }
 
-    #[pyo3(name = "spawn_building")]
-    pub fn spawn_empty_py(&mut self) -> PyEntity {
-        let entity = self.spawn_empty(NodeIdx::root());
-        PyEntity {
-            entity,
-            cmd_sender: self.scene_cmd_sender.clone(),
+    /// Makes the camera render the scene into a texture each frame, e.g. to
+    /// show it on a screen in the scene or on a quad in front of the main
+    /// camera.
+    ///
+    /// Materials show the image of the camera by using `name` as the path of
+    /// one of their textures; the texture must be created before the
+    /// materials using it are uploaded. Calling it again with the same name
+    /// resizes the texture, possibly moving it to another camera.
+    #[pyo3(signature = (camera, name, width=512, height=512))]
+    pub fn render_camera_to_texture(
+        &mut self,
+        camera: &PyEntity,
+        name: &str,
+        width: u32,
+        height: u32,
+    ) -> PyResult<()> {
+        let is_camera = self
+            .scene
+            .read()
+            .unwrap()
+            .world
+            .entry_ref(camera.entity.raw)
+            .is_ok_and(|entry| entry.get_component::<Camera>().is_ok());
+        if !is_camera {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The entity is not a camera",
+            ));
         }
+        self.renderer
+            .write()
+            .unwrap()
+            .add_camera_target(camera.entity.raw, name, width, height);
+        Ok(())
     }
 
-    #[pyo3(signature = (pos, color=Color::WHITE))]
-    pub fn add_point_light_py(&mut self, pos: &np::PyArray2<f32>, color: Color) -> PyEntity {
-        let position = Vec3::from_slice(pos.readonly().as_slice().unwrap());
-        let entity = self.spawn_light(NodeIdx::root(), Light::Point { color }, Some(position));
-        PyEntity {
-            entity,
-            cmd_sender: self.scene_cmd_sender.clone(),
-        }
+    /// Stops the camera rendering into its texture, which keeps showing the
+    /// last image.
+    pub fn stop_camera_render_to_texture(&mut self, camera: &PyEntity) {
+        self.renderer
+            .write()
+            .unwrap()
+            .remove_camera_target(camera.entity.raw);
     }
 
-    #[pyo3(name = "add_directional_light")]
-    #[pyo3(signature = (dir, color=Color::WHITE))]
-    pub fn add_directional_light_py(&mut self, dir: &np::PyArray2<f32>, color: Color) -> PyEntity {
-        let direction = Vec3::from_slice(dir.readonly().as_slice().unwrap());
-        let entity = self.spawn_light(
-            NodeIdx::root(),
-            Light::Directional { direction, color },
-            None,
-        );
-        PyEntity {
-            entity,
-            cmd_sender: self.scene_cmd_sender.clone(),
+    /// Shows a top-down map of the scene in the top right corner of the
+    /// window, `size` pixels wide and covering `world_extent` units around
+    /// the main camera, whose position and heading are marked at its center.
+    ///
+    /// The map is rendered by an orthographic camera into the texture named
+    /// `"minimap"`; calling it again resizes the map.
+    #[pyo3(signature = (size=200, world_extent=100.0))]
+    pub fn show_minimap(&mut self, size: u32, world_extent: f32) {
+        let mut scene = self.scene.write().unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        let proj = Projection::orthographic(world_extent, 0.1, 2.0 * Self::MINIMAP_HEIGHT);
+        let existing = renderer
+            .minimap()
+            .map(|minimap| minimap.camera)
+            .filter(|camera| scene.world.contains(*camera));
+        let camera = match existing {
+            Some(camera) => {
+                if let Ok(mut entry) = scene.world.entry_mut(camera) {
+                    if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                        camera.proj = proj;
+                    }
+                }
+                camera
+            }
+            None => {
+                let background = self
+                    .main_camera
+                    .and_then(|main| {
+                        let entry = scene.world.entry_ref(main.raw).ok()?;
+                        entry.get_component::<Camera>().ok().map(|c| c.background)
+                    })
+                    .unwrap_or(Renderer::CLEAR_COLOR);
+                let camera = Camera::new(proj, background, false);
+                scene.spawn(NodeIdx::root(), (camera, Transient)).raw
+            }
+        };
+        renderer.show_minimap(camera, size);
+    }
+
+    /// Hides the map shown by `show_minimap`.
+    pub fn hide_minimap(&mut self) {
+        let Some(camera) = self.renderer.write().unwrap().hide_minimap() else {
+            return;
+        };
+        let node = self
+            .scene
+            .read()
+            .unwrap()
+            .world
+            .entry_ref(camera)
+            .ok()
+            .and_then(|entry| entry.get_component::<NodeIdx>().ok().copied());
+        if let Some(node) = node {
+            self.commands().despawn(Entity { raw: camera, node });
         }
     }
-}
 
-/// Implementation of the methods only available to Rust.
+    /// Sets the gravity of the physics simulation.
+    #[cfg(feature = "physics")]
+    pub fn set_gravity(&mut self, gravity: &np::PyArray2<f32>) {
+        let gravity = Vec3::from_slice(gravity.readonly().as_slice().unwrap());
+        self.commands().set_gravity(gravity);
+    }
+
+    /// Hosts a shared session on the port, e.g. for a classroom: the other
+    /// instances joining it with `join_session` see the meshes added with
+    /// `add_mesh` by all the instances, their transforms, visibility and
+    /// material overrides. The host relays the changes of each instance to
+    /// the other ones; the meshes are exchanged as obj files, their textures
+    /// must be found at the same paths on every machine.
+    #[cfg(feature = "network")]
+    pub fn host_session(&mut self, port: u16) -> PyResult<()> {
+        let session = Session::host(port).map_err(|err| {
+            pyo3::exceptions::PyIOError::new_err(format!("Failed to host on {}: {}", port, err))
+        })?;
+        self.start_session(session);
+        Ok(())
+    }
+
+    /// Joins the shared session hosted at the address, e.g.
+    /// `"192.168.1.10:7084"`, see `host_session`.
+    #[cfg(feature = "network")]
+    pub fn join_session(&mut self, addr: &str) -> PyResult<()> {
+        let session = Session::join(addr).map_err(|err| {
+            pyo3::exceptions::PyIOError::new_err(format!("Failed to join {}: {}", addr, err))
+        })?;
+        self.start_session(session);
+        Ok(())
+    }
+
+    /// Leaves the shared session, or stops hosting it. The replicated
+    /// entities stay in the scene.
+    #[cfg(feature = "network")]
+    pub fn leave_session(&mut self) {
+        *self.session.lock().unwrap() = None;
+    }
+
+    /// Whether the application hosts or joined a shared session.
+    #[cfg(feature = "network")]
+    #[getter]
+    pub fn get_in_session(&self) -> bool {
+        self.session.lock().unwrap().is_some()
+    }
+
+    /// Listens for the OSC messages sent to the UDP port, e.g. by a control
+    /// surface or a live-coding environment. Each message is dispatched as
+    /// an `on_osc` event with its address and the list of its arguments,
+    /// after updating the entities bound to its address with `bind_osc`.
+    #[cfg(feature = "osc")]
+    pub fn listen_osc(&mut self, port: u16) -> PyResult<()> {
+        let mut osc = self.osc.lock().unwrap();
+        if osc.as_ref().is_some_and(|osc| osc.port() == port) {
+            return Ok(());
+        }
+        let mut listener = OscListener::bind(port).map_err(|err| {
+            pyo3::exceptions::PyIOError::new_err(format!("Failed to listen on {}: {}", port, err))
+        })?;
+        // The bindings survive a change of port.
+        if let Some(previous) = osc.take() {
+            listener.bindings = previous.bindings.clone();
+        }
+        log::info!("Listening for OSC messages on port {}.", listener.port());
+        *osc = Some(listener);
+        Ok(())
+    }
+
+    /// Stops listening for OSC messages, the bindings are dropped.
+    #[cfg(feature = "osc")]
+    pub fn stop_osc(&mut self) {
+        self.osc.lock().unwrap().take();
+    }
+
+    /// Binds the OSC address, e.g. `"/fader/1"`, to a property of the
+    /// entity: the first numeric argument of each message sent to the
+    /// address, multiplied by `scale` and added to `offset`, sets the
+    /// property. Several properties may be bound to the same address.
+    #[cfg(feature = "osc")]
+    #[pyo3(signature = (address, entity, target, scale=1.0, offset=0.0))]
+    pub fn bind_osc(
+        &mut self,
+        address: &str,
+        entity: &PyEntity,
+        target: OscTarget,
+        scale: f32,
+        offset: f32,
+    ) -> PyResult<()> {
+        match self.osc.lock().unwrap().as_mut() {
+            Some(osc) => {
+                osc.bindings.push(OscBinding {
+                    address: address.to_string(),
+                    entity: entity.entity,
+                    target,
+                    scale,
+                    offset,
+                });
+                Ok(())
+            }
+            None => Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "Not listening for OSC messages, see `listen_osc`.",
+            )),
+        }
+    }
+
+    /// Removes the bindings of the OSC address, or all of them if `None`.
+    #[cfg(feature = "osc")]
+    #[pyo3(signature = (address=None))]
+    pub fn unbind_osc(&mut self, address: Option<&str>) {
+        if let Some(osc) = self.osc.lock().unwrap().as_mut() {
+            osc.bindings
+                .retain(|binding| address.is_some_and(|a| a != binding.address));
+        }
+    }
+
+    /// Shows or hides the debug UI drawn over the window: an inspector of
+    /// the scene and of the rendering parameters, and the controls added
+    /// with `add_slider`, `add_checkbox` and `add_button`.
+    #[cfg(feature = "debug-ui")]
+    pub fn enable_debug_ui(&mut self, enabled: bool) {
+        self.debug_ui.lock().unwrap().enabled = enabled;
+    }
+
+    /// Adds a slider to the debug UI, calling `callback(value)` when it is
+    /// moved.
+    #[cfg(feature = "debug-ui")]
+    pub fn add_slider(
+        &mut self,
+        label: &str,
+        value: f32,
+        min: f32,
+        max: f32,
+        callback: PyObject,
+    ) -> PyResult<()> {
+        if min > max {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The minimum of the slider must not be greater than its maximum",
+            ));
+        }
+        self.debug_ui
+            .lock()
+            .unwrap()
+            .controls
+            .push(DebugControl::Slider {
+                label: label.to_string(),
+                value: value.clamp(min, max),
+                range: min..=max,
+                callback,
+            });
+        Ok(())
+    }
+
+    /// Adds a checkbox to the debug UI, calling `callback(value)` when it is
+    /// toggled.
+    #[cfg(feature = "debug-ui")]
+    pub fn add_checkbox(&mut self, label: &str, value: bool, callback: PyObject) {
+        self.debug_ui
+            .lock()
+            .unwrap()
+            .controls
+            .push(DebugControl::Checkbox {
+                label: label.to_string(),
+                value,
+                callback,
+            });
+    }
+
+    /// Adds a button to the debug UI, calling `callback()` when it is
+    /// clicked.
+    #[cfg(feature = "debug-ui")]
+    pub fn add_button(&mut self, label: &str, callback: PyObject) {
+        self.debug_ui
+            .lock()
+            .unwrap()
+            .controls
+            .push(DebugControl::Button {
+                label: label.to_string(),
+                callback,
+            });
+    }
+
+    /// Removes the controls added to the debug UI.
+    #[cfg(feature = "debug-ui")]
+    pub fn clear_debug_controls(&mut self) {
+        self.debug_ui.lock().unwrap().controls.clear();
+    }
+
+    /// Casts a ray from `origin` along `direction` against the triangles of
+    /// the visible meshes, up to `max_dist`.
+    ///
+    /// Returns the entity hit first, the hit point and the normal of the
+    /// surface there, or `None` if nothing is hit.
+    #[pyo3(name = "raycast")]
+    #[pyo3(signature = (origin, direction, max_dist=f32::INFINITY))]
+    pub fn raycast_py(
+        &mut self,
+        origin: &np::PyArray2<f32>,
+        direction: &np::PyArray2<f32>,
+        max_dist: f32,
+    ) -> Option<(PyEntity, Py<np::PyArray2<f32>>, Py<np::PyArray2<f32>>)> {
+        let origin = Vec3::from_slice(origin.readonly().as_slice().unwrap());
+        let direction = Vec3::from_slice(direction.readonly().as_slice().unwrap());
+        let hit = self.raycast(Ray::new(origin, direction), max_dist)?;
+        Some((
+            PyEntity {
+                entity: hit.entity,
+                cmd_sender: self.scene_cmd_sender(),
+                scene: self.scene.clone(),
+            },
+            vec3_to_py(hit.point),
+            vec3_to_py(hit.normal),
+        ))
+    }
+
+    /// Returns the ray from the main camera through a position of the
+    /// window, in pixels from its top-left corner like
+    /// `Input.cursor_position`, as its origin on the near plane and its unit
+    /// direction.
+    ///
+    /// Returns `None` if there is no main camera or before the first frame.
+    pub fn screen_to_world_ray(
+        &self,
+        x: f32,
+        y: f32,
+    ) -> Option<(Py<np::PyArray2<f32>>, Py<np::PyArray2<f32>>)> {
+        let win_size = *self.window_size.lock().unwrap();
+        let ray = self.main_camera_ray([x, y], win_size)?;
+        Some((vec3_to_py(ray.origin), vec3_to_py(ray.direction)))
+    }
+
+    /// Returns the position in pixels from the top-left corner of the window
+    /// where a point in world space is seen by the main camera. The position
+    /// may be outside the window.
+    ///
+    /// Returns `None` if the point is behind the camera, if there is no main
+    /// camera or before the first frame.
+    pub fn world_to_screen(
+        &self,
+        py: Python,
+        point: &np::PyArray2<f32>,
+    ) -> Option<Py<np::PyArray1<f32>>> {
+        let point = Vec3::from_slice(point.readonly().as_slice().unwrap());
+        let win_size = *self.window_size.lock().unwrap();
+        if win_size.0 == 0 || win_size.1 == 0 {
+            return None;
+        }
+        let (view_proj, viewport) = self.main_camera_view(win_size)?;
+        let screen = screen_point(point, &viewport, view_proj)?;
+        Some(np::PyArray1::from_slice(py, &screen.to_array()).to_owned())
+    }
+
+    /// Returns the point of the horizontal plane at `plane_height` seen by
+    /// the main camera at a position of the window, in pixels from its
+    /// top-left corner, e.g. to place objects on the ground under the
+    /// cursor.
+    ///
+    /// Returns `None` if the plane is not seen there, if there is no main
+    /// camera or before the first frame.
+    #[pyo3(signature = (x, y, plane_height=0.0))]
+    pub fn screen_to_ground(
+        &self,
+        x: f32,
+        y: f32,
+        plane_height: f32,
+    ) -> Option<Py<np::PyArray2<f32>>> {
+        let win_size = *self.window_size.lock().unwrap();
+        let ray = self.main_camera_ray([x, y], win_size)?;
+        ground_point(&ray, plane_height).map(vec3_to_py)
+    }
+
+    /// Starts placing a copy of the mesh on the ground with the mouse,
+    /// cancelling the ongoing placement if any.
+    ///
+    /// A translucent ghost of the mesh follows the point of the ground under
+    /// the cursor, snapped to a grid. A left click places the object there
+    /// and dispatches the "on_object_placed" event with the new entity and
+    /// its position.
+    ///
+    /// # Arguments
+    ///
+    /// * `snap` - Spacing of the grid, no snapping if zero.
+    /// * `height` - Height of the ground plane.
+    #[pyo3(signature = (mesh, snap=1.0, height=0.0))]
+    pub fn begin_placement(&mut self, mesh: &mut Mesh, snap: f32, height: f32) {
+        self.cancel_placement();
+        self.prepare_mesh(mesh);
+        let bundle = self.renderer.write().unwrap().upload_mesh(mesh);
+        let mut ghost_mesh = mesh.translucent(Placement::GHOST_OPACITY);
+        let ghost = self.spawn_object_with_mesh(NodeIdx::root(), &mut ghost_mesh);
+        self.scene.write().unwrap().mark_transient(ghost);
+        // The ghost is shown once the cursor is over the ground.
+        self.commands().set_visible(ghost, false);
+        self.commands().set_cast_shadows(ghost, false);
+        self.placement = Some(Placement::new(bundle, ghost, snap, height));
+    }
+
+    /// Stops the ongoing placement without placing the object.
+    pub fn cancel_placement(&mut self) {
+        if let Some(placement) = self.placement.take() {
+            self.commands().despawn(placement.ghost);
+        }
+    }
+
+    /// Returns true while a placement is ongoing.
+    #[getter]
+    pub fn is_placing(&self) -> bool {
+        self.placement.is_some()
+    }
+
+    /// Selects the entity manipulated by the gizmo, or removes the gizmo if
+    /// `None`.
+    ///
+    /// The handles of the gizmo are dragged with the left mouse button. The
+    /// "on_transform_changed" event is dispatched at the end of each drag
+    /// with the entity and its local transform before and after, as 4x4
+    /// matrices.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Transformation applied by the handles, defaults to the
+    ///   mode of the previous selection or to translation.
+    #[pyo3(signature = (entity, mode=None))]
+    pub fn select(&mut self, entity: Option<&PyEntity>, mode: Option<GizmoMode>) {
+        let mut scene = self.scene.write().unwrap();
+        let mode = mode
+            .or(scene.gizmo.map(|gizmo| gizmo.mode))
+            .unwrap_or_default();
+        scene.gizmo = entity.map(|entity| Gizmo::new(entity.entity, mode));
+    }
+
+    /// Returns the entity manipulated by the gizmo, if any.
+    #[getter]
+    pub fn selected(&self) -> Option<PyEntity> {
+        let gizmo = self.scene.read().unwrap().gizmo?;
+        Some(PyEntity {
+            entity: gizmo.target,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        })
+    }
+
+    /// Sets the transformation applied by the handles of the gizmo.
+    pub fn set_gizmo_mode(&mut self, mode: GizmoMode) {
+        match self.scene.write().unwrap().gizmo.as_mut() {
+            Some(gizmo) => {
+                gizmo.mode = mode;
+                gizmo.hovered = None;
+                if gizmo.drag.take().is_some() {
+                    self.commands().end_transaction();
+                }
+            }
+            None => log::warn!("No entity selected, can't set the gizmo mode."),
+        }
+    }
+
+    /// Sets the background color of the main camera and removes its gradient
+    /// or image background.
+    pub fn set_background(&mut self, color: Color) {
+        match self.main_camera {
+            Some(entity) => {
+                self.scene_cmd_sender()
+                    .send(Command::SetBackground { entity, color })
+                    .unwrap();
+                self.scene_cmd_sender()
+                    .send(Command::SetBackdrop {
+                        entity,
+                        backdrop: None,
+                    })
+                    .unwrap();
+            }
+            None => log::warn!("No main camera, can't set the background."),
+        }
+    }
+
+    /// Draws an analytical sky behind the scene seen by the main camera, with
+    /// the sun disk and the ground below the horizon. The sun follows the
+    /// day/night cycle if enabled, and the sky fades into the background
+    /// color at night.
+    ///
+    /// # Arguments
+    ///
+    /// * `turbidity` - Haziness of the atmosphere, from 2 for a clear sky to
+    ///   10 for a hazy one.
+    /// * `exposure` - Scale of the brightness of the sky.
+    /// * `ground` - Color of the ground below the horizon.
+    #[pyo3(signature = (turbidity=3.0, exposure=1.0, ground=None))]
+    pub fn set_sky(&mut self, turbidity: f32, exposure: f32, ground: Option<Color>) {
+        let Some(entity) = self.main_camera else {
+            log::warn!("No main camera, can't set the sky.");
+            return;
+        };
+        let mut sky = Sky {
+            turbidity,
+            exposure,
+            ..Default::default()
+        };
+        if let Some(cycle) = self.day_cycle.as_ref() {
+            sky.sun = cycle.state().sun;
+        }
+        if let Some(ground) = ground {
+            sky.ground = ground;
+        }
+        self.scene_cmd_sender()
+            .send(Command::SetBackdrop {
+                entity,
+                backdrop: Some(Backdrop::Sky(sky)),
+            })
+            .unwrap();
+    }
+
+    /// Renders the scene from the main camera with a CPU path tracer and
+    /// writes the image to `path`, as a reference for the rasterized frames.
+    ///
+    /// Materials are approximated by their diffuse and specular colors, and
+    /// the rays escaping the scene take the background color of the camera.
+    /// The scene is rendered as it was at the last frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `spp` - The number of samples per pixel.
+    /// * `progress` - Called after each sample of all the pixels with the
+    ///   number of samples done and `spp`.
+    #[pyo3(signature = (width, height, spp, path, progress=None))]
+    pub fn render_ground_truth(
+        &mut self,
+        py: Python<'_>,
+        width: u32,
+        height: u32,
+        spp: u32,
+        path: &str,
+        progress: Option<PyObject>,
+    ) -> PyResult<()> {
+        if width == 0 || height == 0 || spp == 0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The image size and the number of samples must be positive.",
+            ));
+        }
+        let tracer = {
+            let scene = self.scene.read().unwrap();
+            let mut renderer = self.renderer.write().unwrap();
+            PathTracer::new(&scene, &mut renderer, width, height)
+        }
+        .ok_or_else(|| pyo3::exceptions::PyRuntimeError::new_err("No camera to render from."))?;
+        let mut accum = vec![Vec3::ZERO; width as usize * height as usize];
+        for sample in 0..spp {
+            py.allow_threads(|| tracer.render_sample(sample, &mut accum));
+            if let Some(progress) = &progress {
+                progress.call1(py, (sample + 1, spp))?;
+            }
+        }
+        tracer.save(&accum, spp, path).map_err(|err| {
+            pyo3::exceptions::PyIOError::new_err(format!("Failed to write {}: {}", path, err))
+        })
+    }
+
+    /// Starts recording the frames of the window.
+    ///
+    /// If the path has a video extension (`mp4`, `mkv`, `webm`, `mov` or
+    /// `avi`) and ffmpeg is installed, a video is written. Otherwise, the
+    /// frames are written as numbered PNG images in the directory `path`.
+    #[pyo3(signature = (path, fps=30.0))]
+    pub fn start_recording(&mut self, path: &str, fps: f32) -> PyResult<()> {
+        self.recorder
+            .write()
+            .unwrap()
+            .start(self.context.device.clone(), Path::new(path), fps)
+            .map_err(|err| {
+                pyo3::exceptions::PyIOError::new_err(format!(
+                    "Failed to record to {}: {}",
+                    path, err
+                ))
+            })
+    }
+
+    /// Stops recording the frames of the window, waits for the recorded
+    /// frames to be written.
+    pub fn stop_recording(&mut self) {
+        self.recorder.write().unwrap().stop();
+    }
+
+    /// Moves the main camera smoothly to a new viewpoint.
+    ///
+    /// Moves are queued: the camera moves to this viewpoint once the previous
+    /// ones have been reached. The `on_camera_arrived` event is dispatched
+    /// each time a viewpoint is reached.
+    ///
+    /// # Arguments
+    ///
+    /// * `pos` - The position of the camera.
+    /// * `look_at` - The position the camera looks at.
+    /// * `duration` - The duration of the move in seconds.
+    /// * `easing` - The easing of the move.
+    #[pyo3(signature = (pos, look_at, duration=1.0, easing=Easing::EaseInOut))]
+    pub fn move_camera_to(
+        &mut self,
+        pos: &np::PyArray2<f32>,
+        look_at: &np::PyArray2<f32>,
+        duration: f32,
+        easing: Easing,
+    ) {
+        Python::with_gil(|_py| {
+            self.camera_animator.push(CameraWaypoint {
+                position: Vec3::from_slice(pos.readonly().as_slice().unwrap()),
+                look_at: Vec3::from_slice(look_at.readonly().as_slice().unwrap()),
+                duration: duration.max(0.0),
+                easing,
+            });
+        });
+    }
+
+    /// Stops the camera animation and discards the queued viewpoints.
+    pub fn stop_camera_animation(&mut self) {
+        self.camera_animator.clear();
+    }
+
+    /// Returns true if the camera is moving to a viewpoint.
+    pub fn is_camera_animating(&self) -> bool {
+        self.camera_animator.is_animating()
+    }
+
+    /// Enables the day/night cycle: the directional light moves along the
+    /// path of the sun, its color warms up at dawn and dusk, and the
+    /// background of the main camera follows the sky.
+    ///
+    /// # Arguments
+    ///
+    /// * `day_duration` - The duration of a whole day in seconds.
+    /// * `latitude` - The latitude of the site in degrees.
+    /// * `light` - The directional light moved as the sun, the first
+    ///   directional light of the scene if `None`.
+    #[pyo3(signature = (day_duration=60.0, latitude=52.0, light=None))]
+    pub fn enable_day_cycle(&mut self, day_duration: f32, latitude: f32, light: Option<&PyEntity>) {
+        let light = match light {
+            Some(light) => light.entity,
+            None => {
+                let existing = {
+                    let scene = self.scene.read().unwrap();
+                    <(legion::Entity, &Light, &NodeIdx)>::query()
+                        .iter(&scene.world)
+                        .find(|(_, light, _)| light.is_directional())
+                        .map(|(raw, _, node)| Entity {
+                            raw: *raw,
+                            node: *node,
+                        })
+                };
+                existing.unwrap_or_else(|| {
+                    self.spawn_light(
+                        NodeIdx::root(),
+                        Light::Directional {
+                            direction: Vec3::NEG_Y,
+                            color: Color::WHITE,
+                        },
+                        None,
+                    )
+                })
+            }
+        };
+        let time_of_day = self.day_cycle.map(|cycle| cycle.time_of_day);
+        let mut day_cycle = SunAnimator::new(light, day_duration, latitude);
+        if let Some(hours) = time_of_day {
+            day_cycle.set_time_of_day(hours);
+        }
+        self.day_cycle = Some(day_cycle);
+    }
+
+    /// Stops the day/night cycle, leaving the light as it is.
+    pub fn disable_day_cycle(&mut self) {
+        self.day_cycle = None;
+    }
+
+    /// Sets the time of the day in hours of the day/night cycle.
+    pub fn set_time_of_day(&mut self, hours: f32) {
+        match self.day_cycle.as_mut() {
+            Some(cycle) => cycle.set_time_of_day(hours),
+            None => log::warn!("The day/night cycle is not enabled."),
+        }
+    }
+
+    /// Adds a mesh to the scene.
+    // TODO: pass transform as an argument.
+    #[pyo3(name = "add_mesh")]
+    #[pyo3(signature = (mesh, parent=None))]
+    pub fn add_mesh_py(&mut self, mesh: &mut Mesh, parent: Option<&PyEntity>) -> PyEntity {
+        let parent = parent.map(|p| p.entity.node).unwrap_or(NodeIdx::root());
+        let entity = self.spawn_object_with_mesh(parent, mesh);
+        #[cfg(feature = "network")]
+        if let Some(session) = self.session.lock().unwrap().as_mut() {
+            session.share_spawn(entity, parent, mesh);
+        }
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+
+    /// Adds a mesh with several levels of detail to the scene.
+    ///
+    /// The entity draws the first mesh whose distance is greater than its
+    /// distance to the main camera, or the last mesh beyond all distances.
+    /// Simplified meshes can be generated with `Mesh.simplify`.
+    ///
+    /// # Arguments
+    ///
+    /// * `meshes` - The meshes, from the most to the least detailed.
+    /// * `distances` - The distance up to which each mesh is drawn.
+    #[pyo3(name = "add_mesh_lod")]
+    #[pyo3(signature = (meshes, distances, parent=None))]
+    pub fn add_mesh_lod_py(
+        &mut self,
+        mut meshes: Vec<Mesh>,
+        distances: Vec<f32>,
+        parent: Option<&PyEntity>,
+    ) -> PyResult<PyEntity> {
+        if meshes.is_empty() || meshes.len() != distances.len() {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "A distance is required for each of the meshes (at least one).",
+            ));
+        }
+        let parent = parent.map(|p| p.entity.node).unwrap_or(NodeIdx::root());
+        let entity = self.spawn_object_with_lods(parent, &mut meshes, &distances);
+        Ok(PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        })
+    }
+
+    /// Adds the objects of a wavefront obj file to the scene progressively.
+    ///
+    /// The file is read in the background; each object is added as a child
+    /// of the returned entity as soon as it has been read, so that large
+    /// files are displayed without blocking the application.
+    #[pyo3(name = "add_mesh_streamed")]
+    #[pyo3(signature = (path, parent=None))]
+    pub fn add_mesh_streamed_py(
+        &mut self,
+        path: &str,
+        parent: Option<&PyEntity>,
+    ) -> PyResult<PyEntity> {
+        let parent = parent.map(|p| p.entity.node).unwrap_or(NodeIdx::root());
+        let entity = self
+            .spawn_streamed_obj(parent, Path::new(path))
+            .map_err(|err| {
+                pyo3::exceptions::PyIOError::new_err(format!("Failed to open {}: {}", path, err))
+            })?;
+        Ok(PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        })
+    }
+
+    /// Creates a spline through the points, e.g. to sweep a road along it
+    /// with `Mesh.sweep_along_spline`. See `SplineKind` for the meaning of
+    /// the points.
+    #[pyo3(signature = (points, kind=SplineKind::Polyline))]
+    pub fn create_spline(&self, points: Vec<[f32; 3]>, kind: SplineKind) -> PyResult<Spline> {
+        Spline::new_py(points, kind)
+    }
+
+    /// Scatters instances of the mesh at random over the surface of a mesh
+    /// entity, e.g. grass or trees over a terrain. The instances share the
+    /// uploaded mesh and are drawn together; they are children of the
+    /// returned entity.
+    ///
+    /// # Arguments
+    ///
+    /// * `mesh` - The scattered mesh, its up axis being Y.
+    /// * `surface` - The entity whose mesh receives the instances.
+    /// * `density` - The average number of instances per unit of area.
+    /// * `seed` - The seed of the random placement.
+    /// * `align_to_normal` - Whether the instances are tilted along the normal
+    ///   of the surface, otherwise they stay upright.
+    /// * `scale_jitter` - The largest relative change of the scale of the
+    ///   instances.
+    #[pyo3(signature = (mesh, surface, density, seed=0, align_to_normal=true, scale_jitter=0.2))]
+    pub fn scatter(
+        &mut self,
+        mesh: &mut Mesh,
+        surface: &PyEntity,
+        density: f32,
+        seed: u64,
+        align_to_normal: bool,
+        scale_jitter: f32,
+    ) -> PyResult<PyEntity> {
+        if !density.is_finite() || density <= 0.0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The density must be positive.",
+            ));
+        }
+        if !(0.0..1.0).contains(&scale_jitter) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The scale jitter must be in [0, 1).",
+            ));
+        }
+        let params = ScatterParams {
+            density,
+            seed,
+            align_to_normal,
+            scale_jitter,
+        };
+        let entity = self
+            .spawn_scattered(surface.entity, mesh, &params)
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        Ok(PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        })
+    }
+
+    /// Merges the static meshes sharing the same materials into single
+    /// meshes with their world transforms applied, so that they are drawn
+    /// with a few draw calls, e.g. for the final render of a large scene.
+    ///
+    /// The visible mesh entities are baked, except those with levels of
+    /// detail, custom shaders, material overrides or tints. Moving or hiding
+    /// the baked entities has no visible effect until the geometry is
+    /// unbaked; ray casts and analyses still see the baked entities. Baking
+    /// again unbakes the previous batches first.
+    ///
+    /// Returns the number of batches.
+    pub fn bake_static_geometry(&mut self) -> usize {
+        profiling::scope!("bake_static_geometry");
+        self.unbake_static_geometry();
+        let mut scene = self.scene.write().unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        // Instances sharing their materials, layer and shadow casting.
+        let mut groups = FxHashMap::default();
+        let mut query = <(legion::Entity, &MeshBundle, &NodeIdx, Option<&RenderLayer>)>::query()
+            .filter(
+                !component::<LodGroup>() & !component::<CustomShader>() & !component::<Baked>(),
+            );
+        for (raw, mesh, node, layer) in query.iter(&scene.world) {
+            let node_data = &scene.nodes[*node];
+            if !node_data.is_visible()
+                || node_data.material_override.is_some()
+                || node_data.tint.is_some()
+            {
+                continue;
+            }
+            let key = (
+                mesh.aesthetic,
+                layer.copied().unwrap_or_default(),
+                node_data.cast_shadows(),
+            );
+            groups.entry(key).or_insert_with(Vec::new).push((
+                Entity {
+                    raw: *raw,
+                    node: *node,
+                },
+                *mesh,
+            ));
+        }
+
+        let mut n_batches = 0;
+        for ((_, layer, cast_shadows), instances) in groups {
+            if instances.len() < 2 {
+                continue;
+            }
+            let transformed = instances
+                .iter()
+                .map(|(entity, mesh)| {
+                    (*mesh, entity.node, scene.nodes.world(entity.node).to_mat4())
+                })
+                .collect::<Vec<_>>();
+            let Some(mesh) = renderer.bake_static_batch(&transformed) else {
+                continue;
+            };
+            let entity = scene.spawn(NodeIdx::root(), (mesh, layer, Baked, Transient));
+            scene.nodes[entity.node].set_cast_shadows(cast_shadows);
+            renderer.add_static_batch(StaticBatch {
+                entity,
+                mesh,
+                instances,
+            });
+            n_batches += 1;
+        }
+        log::info!("Baked the static geometry into {} batches.", n_batches);
+        n_batches
+    }
+
+    /// Removes the meshes merged by `bake_static_geometry`, the entities
+    /// being drawn again on their own with their current transforms.
+    pub fn unbake_static_geometry(&mut self) {
+        let mut scene = self.scene.write().unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        for batch in renderer.take_static_batches() {
+            scene.despawn(batch.entity);
+            for (entity, mesh) in batch.instances {
+                let current = scene
+                    .world
+                    .entry_ref(entity.raw)
+                    .ok()
+                    .and_then(|entry| entry.get_component::<MeshBundle>().ok().copied());
+                if current == Some(mesh) {
+                    renderer.add_instancing(mesh, &[entity.node]);
+                }
+            }
+        }
+    }
+
+    /// Adds a sprite, a camera-facing textured quad, to the scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `texture` - Path to the image of the sprite.
+    /// * `size` - Width and height of the sprite in world units.
+    /// * `position` - Center of the sprite in world space.
+    /// * `tint` - Color multiplied with the image.
+    #[pyo3(name = "add_sprite")]
+    #[pyo3(signature = (texture, size, position, tint=Color::WHITE))]
+    pub fn add_sprite_py(
+        &mut self,
+        texture: &str,
+        size: [f32; 2],
+        position: &np::PyArray2<f32>,
+        tint: Color,
+    ) -> PyResult<PyEntity> {
+        let position = Vec3::from_slice(position.readonly().as_slice().unwrap());
+        let region = self
+            .renderer
+            .write()
+            .unwrap()
+            .add_sprite_image(std::path::Path::new(texture))
+            .ok_or_else(|| {
+                pyo3::exceptions::PyIOError::new_err(format!(
+                    "Failed to add sprite image {}",
+                    texture
+                ))
+            })?;
+        let sprite = Sprite {
+            region,
+            size: Vec2::from(size),
+            tint,
+        };
+        let entity = self.spawn_sprite(NodeIdx::root(), sprite, position);
+        Ok(PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        })
+    }
+
+    /// Adds a particle emitter to the scene. Particles are spawned at the
+    /// position of the returned entity.
+    #[pyo3(name = "add_particle_emitter")]
+    #[pyo3(signature = (emitter, parent=None))]
+    pub fn add_particle_emitter_py(
+        &mut self,
+        emitter: ParticleEmitter,
+        parent: Option<&PyEntity>,
+    ) -> PyEntity {
+        let parent = parent.map(|p| p.entity.node).unwrap_or(NodeIdx::root());
+        let entity = self.spawn_particle_emitter(parent, emitter);
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+
+    /// Adds a square water surface to the scene.
+    ///
+    /// # Arguments
+    ///
+    /// * `size` - Side length of the surface in world units.
+    /// * `level` - Height of the surface.
+    /// * `water` - Appearance of the water, defaults to [`Water::default`].
+    #[pyo3(name = "add_water")]
+    #[pyo3(signature = (size, level, water=None))]
+    pub fn add_water_py(&mut self, size: f32, level: f32, water: Option<Water>) -> PyEntity {
+        let entity = self.spawn_water(NodeIdx::root(), water.unwrap_or_default(), size, level);
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+
+    #[pyo3(name = "spawn_building")]
+    pub fn spawn_empty_py(&mut self) -> PyEntity {
+        let entity = self.spawn_empty(NodeIdx::root());
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+
+    #[pyo3(signature = (pos, color=Color::WHITE))]
+    pub fn add_point_light_py(&mut self, pos: &np::PyArray2<f32>, color: Color) -> PyEntity {
+        let position = Vec3::from_slice(pos.readonly().as_slice().unwrap());
+        let entity = self.spawn_light(NodeIdx::root(), Light::Point { color }, Some(position));
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+
+    #[pyo3(name = "add_directional_light")]
+    #[pyo3(signature = (dir, color=Color::WHITE))]
+    pub fn add_directional_light_py(&mut self, dir: &np::PyArray2<f32>, color: Color) -> PyEntity {
+        let direction = Vec3::from_slice(dir.readonly().as_slice().unwrap());
+        let entity = self.spawn_light(
+            NodeIdx::root(),
+            Light::Directional { direction, color },
+            None,
+        );
+        PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        }
+    }
+}
+
+/// Implementation of the methods only available to Rust.
 impl PyAppState {
+    /// Returns the command sender of the active scene.
+    fn scene_cmd_sender(&self) -> Sender<Command> {
+        self.scene_cmd_sender.read().unwrap().clone()
+    }
+
+    /// Returns the typed interface sending commands to the scene and the
+    /// renderer.
+    pub fn commands(&self) -> Commands {
+        Commands::new(self.scene_cmd_sender(), self.renderer_cmd_sender.clone())
+    }
+
+    /// Maximum number of meshes read in advance by a mesh stream.
+    const MESH_STREAM_CAPACITY: usize = 16;
+    /// Maximum number of streamed meshes uploaded per frame.
+    const MAX_STREAMED_MESHES_PER_FRAME: usize = 8;
+    /// Height of the camera of the minimap, which sees the scene below and
+    /// up to the same height above the ground.
+    const MINIMAP_HEIGHT: f32 = 500.0;
+
     pub fn create_window(
         &mut self,
-        event_loop: &EventLoop<UserEvent<()>>,
+        event_loop: &EventLoop<UserEvent<AppEvent>>,
         builder: PyWindowBuilder,
     ) -> Window {
         let inner_size = builder.size.unwrap_or([800, 600]);
@@ -318,36 +2138,335 @@ impl PyAppState {
             .with_visible(false)
             .build(event_loop)
             .unwrap();
-        self.event_loop = Some(event_loop.create_proxy());
+        window.set_ime_allowed(self.text_input.load(Ordering::Relaxed));
+        *self.event_loop.lock().unwrap() = Some(event_loop.create_proxy());
         window
     }
 
-    /// Spawn an object with the given mesh and parent.
-    ///
-    /// Returns the entity ID of the spawned object.
-    pub fn spawn_object_with_mesh(&mut self, parent: NodeIdx, mesh: &mut Mesh) -> Entity {
-        log::debug!("Spawning object with mesh#{}", mesh.name);
-        mesh.validate();
-        self.renderer
+    /// Interval between the polls of the main loop run without blocking.
+    const ASYNC_POLL_INTERVAL: f32 = 1.0 / 240.0;
+
+    /// Schedules the next poll of the main loop on the running asyncio event
+    /// loop, if any.
+    fn schedule_poll(slf: &PyCell<Self>) -> PyResult<()> {
+        let py = slf.py();
+        let Ok(event_loop) = py.import("asyncio")?.call_method0("get_running_loop") else {
+            return Ok(());
+        };
+        event_loop.call_method1(
+            "call_later",
+            (Self::ASYNC_POLL_INTERVAL, slf.getattr("_poll_scheduled")?),
+        )?;
+        Ok(())
+    }
+
+    /// Applies the configuration of the application: the rendering
+    /// parameters are replaced at once and the main camera is created.
+    pub fn apply_config(&mut self, config: PyAppConfig) {
+        self.window = config.window;
+        self.commands().set_render_params(config.params);
+        self.commands().enable_hot_reload(config.hot_reload);
+        if let Some(camera) = config.camera {
+            self.create_camera(camera.proj, camera.pos, camera.look_at, camera.background);
+        }
+    }
+
+    /// Finishes the pending work of the application, stops its worker
+    /// threads and releases the GPU resources of the scene. The application
+    /// is left empty.
+    pub fn shutdown(&mut self) {
+        self.recorder.write().unwrap().stop();
+        for stream in self.mesh_streams.drain(..) {
+            stream.cancelled.store(true, Ordering::Relaxed);
+            // Unblocks the reading thread until it stops.
+            for _ in stream.receiver.iter() {}
+        }
+        self.event_listeners.write().unwrap().clear();
+        self.posted_events.lock().unwrap().clear();
+        self.camera_animator = CameraAnimator::default();
+        self.day_cycle = None;
+        self.placement = None;
+        self.main_camera = None;
+        *self.mesh_instances.lock().unwrap() = None;
+        #[cfg(feature = "network")]
+        self.session.lock().unwrap().take();
+        #[cfg(feature = "osc")]
+        self.osc.lock().unwrap().take();
+        self.scenes.lock().unwrap().clear();
+        self.scene.write().unwrap().clear();
+        self.renderer.write().unwrap().release();
+    }
+
+    /// Spawn an object with the given mesh and parent.
+    ///
+    /// Returns the entity ID of the spawned object.
+    pub fn spawn_object_with_mesh(&mut self, parent: NodeIdx, mesh: &mut Mesh) -> Entity {
+        log::debug!("Spawning object with mesh#{}", mesh.name);
+        self.prepare_mesh(mesh);
+        self.renderer
+            .write()
+            .map(|mut renderer| {
+                let mesh_bundle = renderer.upload_mesh(mesh);
+                let entity = self
+                    .scene
+                    .write()
+                    .map(|mut scene| scene.spawn(parent, (mesh_bundle,)))
+                    .unwrap();
+                renderer.add_instancing(mesh_bundle, &[entity.node]);
+                entity
+            })
+            .expect("Failed to spawn object with mesh!")
+    }
+
+    /// Validates the mesh while the textures of its materials are decoded
+    /// on multiple threads, each texture being uploaded as soon as it is
+    /// decoded.
+    ///
+    /// The `on_load_progress` event is dispatched after each texture with
+    /// the percentage of the textures loaded and the path of the texture.
+    fn prepare_mesh(&self, mesh: &mut Mesh) {
+        let missing = self
+            .renderer
+            .read()
+            .unwrap()
+            .missing_textures(mesh.materials.as_deref().unwrap_or_default());
+        if missing.is_empty() {
+            mesh.validate();
+            return;
+        }
+        let paths = missing
+            .iter()
+            .map(|(path, _)| path.as_path())
+            .collect::<Vec<_>>();
+        std::thread::scope(|s| {
+            s.spawn(|| mesh.validate());
+            let mut loaded = 0;
+            decode_images(&paths, |i, image| {
+                let (path, format) = &missing[i];
+                match image {
+                    Ok(image) => {
+                        self.renderer
+                            .write()
+                            .unwrap()
+                            .add_decoded_texture(path, *format, &image);
+                    }
+                    Err(err) => log::error!("Failed to decode texture {:?}: {}", path, err),
+                }
+                loaded += 1;
+                let percentage = loaded as f32 * 100.0 / missing.len() as f32;
+                Python::with_gil(|py| {
+                    self.dispatch_event(
+                        py,
+                        "on_load_progress",
+                        PyTuple::new(
+                            py,
+                            &[
+                                percentage.into_py(py),
+                                path.display().to_string().into_py(py),
+                            ],
+                        ),
+                        None,
+                    )
+                })
+                .unwrap();
+            });
+        });
+    }
+
+    /// Spawn an object with levels of detail, `distances` being the distance
+    /// to the camera up to which each mesh is drawn.
+    ///
+    /// Returns the entity ID of the spawned object.
+    pub fn spawn_object_with_lods(
+        &mut self,
+        parent: NodeIdx,
+        meshes: &mut [Mesh],
+        distances: &[f32],
+    ) -> Entity {
+        for mesh in meshes.iter_mut() {
+            self.prepare_mesh(mesh);
+        }
+        let mut renderer = self.renderer.write().unwrap();
+        let levels = meshes
+            .iter()
+            .zip(distances)
+            .map(|(mesh, max_distance)| LodLevel {
+                mesh: renderer.upload_mesh(mesh),
+                max_distance: *max_distance,
+            })
+            .collect::<Vec<_>>();
+        let lod = LodGroup::new(levels);
+        let mesh_bundle = lod.current();
+        let entity = self
+            .scene
+            .write()
+            .map(|mut scene| scene.spawn(parent, (mesh_bundle, lod)))
+            .unwrap();
+        renderer.add_instancing(mesh_bundle, &[entity.node]);
+        entity
+    }
+
+    /// Spawn an empty object at the root whose children are instances of
+    /// the mesh scattered over the surface of the mesh entity, the mesh
+    /// being uploaded once for all of them.
+    ///
+    /// Returns an error if the surface entity has no triangle mesh.
+    pub fn spawn_scattered(
+        &mut self,
+        surface: Entity,
+        mesh: &mut Mesh,
+        params: &ScatterParams,
+    ) -> Result<Entity, String> {
+        let transforms = {
+            let scene = self.scene.read().unwrap();
+            let bundle = scene
+                .world
+                .entry_ref(surface.raw)
+                .ok()
+                .and_then(|entry| entry.get_component::<MeshBundle>().ok().copied())
+                .ok_or_else(|| "The surface entity has no mesh.".to_string())?;
+            let bvh = self
+                .renderer
+                .write()
+                .unwrap()
+                .mesh_bvh(bundle.mesh)
+                .ok_or_else(|| "The mesh of the surface entity has no triangles.".to_string())?;
+            let model = scene.nodes.world(surface.node).to_mat4();
+            scatter(
+                bvh.triangles()
+                    .map(|(_, triangle)| triangle.map(|v| model.transform_point3(v))),
+                params,
+            )
+        };
+        log::debug!(
+            "Scattering {} instances of mesh#{}",
+            transforms.len(),
+            mesh.name
+        );
+        self.prepare_mesh(mesh);
+        let mut renderer = self.renderer.write().unwrap();
+        let mesh_bundle = renderer.upload_mesh(mesh);
+        let mut scene = self.scene.write().unwrap();
+        let root = scene.spawn(NodeIdx::root(), ());
+        let nodes = transforms
+            .into_iter()
+            .map(|transform| {
+                let entity = scene.spawn(root.node, (mesh_bundle,));
+                scene.nodes[entity.node].set_transform(transform);
+                entity.node
+            })
+            .collect::<Vec<_>>();
+        renderer.add_instancing(mesh_bundle, &nodes);
+        Ok(root)
+    }
+
+    /// Spawn an empty object whose children are the objects of the given obj
+    /// file. The file is read in a background thread and the objects are
+    /// spawned by [`Self::prepare`] as they arrive.
+    pub fn spawn_streamed_obj(&mut self, parent: NodeIdx, path: &Path) -> std::io::Result<Entity> {
+        let stream = ObjStream::open(path)?;
+        let (sender, receiver) = crossbeam_channel::bounded(Self::MESH_STREAM_CAPACITY);
+        let path = path.to_path_buf();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let cancelled_flag = cancelled.clone();
+        std::thread::spawn(move || {
+            for mesh in stream {
+                if cancelled_flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                match mesh {
+                    Ok(mesh) => {
+                        if sender.send(mesh).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => {
+                        log::error!("Failed to read {:?}: {}", path, err);
+                        break;
+                    }
+                }
+            }
+        });
+        let entity = self.spawn_empty(parent);
+        self.mesh_streams.push(MeshStream {
+            parent: entity.node,
+            receiver,
+            cancelled,
+        });
+        Ok(entity)
+    }
+
+    /// Spawns the meshes read by the mesh streams since the last frame, at
+    /// most [`Self::MAX_STREAMED_MESHES_PER_FRAME`] of them.
+    fn spawn_streamed_meshes(&mut self) {
+        let mut budget = Self::MAX_STREAMED_MESHES_PER_FRAME;
+        let mut streams = std::mem::take(&mut self.mesh_streams);
+        streams.retain(|stream| loop {
+            if budget == 0 {
+                return true;
+            }
+            match stream.receiver.try_recv() {
+                Ok(mut mesh) => {
+                    self.spawn_object_with_mesh(stream.parent, &mut mesh);
+                    budget -= 1;
+                }
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return false,
+            }
+        });
+        self.mesh_streams = streams;
+    }
+
+    /// Spawn an empty object with the given parent.
+    pub fn spawn_empty(&mut self, parent: NodeIdx) -> Entity {
+        self.scene
+            .write()
+            .map(|mut scene| scene.spawn(parent, ()))
+            .unwrap()
+    }
+
+    /// Spawn a visible sprite at the given position.
+    pub fn spawn_sprite(&mut self, parent: NodeIdx, sprite: Sprite, position: Vec3) -> Entity {
+        self.scene
             .write()
-            .map(|mut renderer| {
-                let mesh_bundle = renderer.upload_mesh(mesh);
-                let entity = self
-                    .scene
-                    .write()
-                    .map(|mut scene| scene.spawn(parent, (mesh_bundle,)))
-                    .unwrap();
-                renderer.add_instancing(mesh_bundle, &[entity.node]);
+            .map(|mut scene| {
+                let entity = scene.spawn(parent, (sprite,));
+                let node = &mut scene.nodes[entity.node];
+                node.transform_mut().translation = position;
+                node.set_visible(true);
+                node.set_cast_shadows(false);
                 entity
             })
-            .expect("Failed to spawn object with mesh!")
+            .unwrap()
     }
 
-    /// Spawn an empty object with the given parent.
-    pub fn spawn_empty(&mut self, parent: NodeIdx) -> Entity {
+    /// Spawn a visible particle emitter with the given parent.
+    pub fn spawn_particle_emitter(&mut self, parent: NodeIdx, emitter: ParticleEmitter) -> Entity {
         self.scene
             .write()
-            .map(|mut scene| scene.spawn(parent, ()))
+            .map(|mut scene| {
+                let entity = scene.spawn(parent, (emitter,));
+                let node = &mut scene.nodes[entity.node];
+                node.set_visible(true);
+                node.set_cast_shadows(false);
+                entity
+            })
+            .unwrap()
+    }
+
+    /// Spawn a visible water surface of the given size at the given height.
+    pub fn spawn_water(&mut self, parent: NodeIdx, water: Water, size: f32, level: f32) -> Entity {
+        self.scene
+            .write()
+            .map(|mut scene| {
+                let entity = scene.spawn(parent, (water,));
+                let node = &mut scene.nodes[entity.node];
+                node.transform_mut().translation = Vec3::new(0.0, level, 0.0);
+                node.transform_mut().scale = Vec3::new(size, 1.0, size);
+                node.set_visible(true);
+                node.set_cast_shadows(false);
+                entity
+            })
             .unwrap()
     }
 
@@ -368,9 +2487,10 @@ impl PyAppState {
 
     /// Prepare the scene and renderer for rendering.
     pub fn prepare(&mut self) {
+        self.spawn_streamed_meshes();
         let has_light = self.scene.read().unwrap().has_light();
         if !has_light {
-            self.spawn_light(
+            let light = self.spawn_light(
                 NodeIdx::root(),
                 Light::Directional {
                     direction: Vec3::new(1.0, -1.0, -1.0),
@@ -378,9 +2498,238 @@ impl PyAppState {
                 },
                 None,
             );
+            self.scene.write().unwrap().mark_transient(light);
+        }
+        self.flush_scene();
+        #[cfg(feature = "physics")]
+        self.shape_colliders();
+        self.follow_minimap();
+        let scene = self.scene.read().unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        // Evicted textures used again are loaded before the texture bind
+        // group is updated.
+        renderer.update_texture_residency(&scene);
+        renderer.prepare();
+    }
+
+    /// Applies the pending commands of the active scene and passes the
+    /// changes of its mesh instances on to the renderer.
+    fn flush_scene(&mut self) {
+        let (despawned, respawned, lod_switches, custom_shaders, mesh_refs) = self
+            .scene
+            .write()
+            .map(|mut scene| {
+                scene.prepare(&mut self.main_camera);
+                (
+                    scene.take_despawned(),
+                    scene.take_respawned(),
+                    scene.take_lod_switches(),
+                    scene.take_custom_shaders(),
+                    scene.take_mesh_refs(),
+                )
+            })
+            .unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        for (mesh, node) in despawned {
+            renderer.remove_instancing(mesh, node);
+        }
+        for (mesh, node) in respawned {
+            renderer.add_instancing(mesh, &[node]);
+        }
+        for (node, previous, current) in lod_switches {
+            renderer.remove_instancing(previous, node);
+            renderer.add_instancing(current, &[node]);
+        }
+        renderer.add_custom_shaders(&custom_shaders);
+        renderer.update_mesh_refs(&mesh_refs);
+    }
+
+    /// Casts the ray against the triangles of the visible meshes, up to
+    /// `max_dist` from its origin, and returns the closest hit.
+    pub fn raycast(&mut self, ray: Ray, max_dist: f32) -> Option<RaycastHit> {
+        profiling::scope!("PyAppState::raycast");
+        let scene = self.scene.read().unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        let mut instances = self.mesh_instances.lock().unwrap();
+        let bvh = Self::update_mesh_instances(&mut instances, &scene, &renderer);
+        Self::intersect_mesh_instances(bvh, &scene, &mut renderer, ray, max_dist)
+    }
+
+    /// Returns the closest intersection of the ray with the visible mesh
+    /// entities of the BVH.
+    fn intersect_mesh_instances(
+        bvh: &Bvh<MeshInstance>,
+        scene: &Scene,
+        renderer: &mut Renderer,
+        ray: Ray,
+        max_dist: f32,
+    ) -> Option<RaycastHit> {
+        let direction = ray.direction.try_normalize()?;
+        let ray = Ray::new(ray.origin, direction);
+        let (distance, (entity, normal)) =
+            bvh.intersect(&ray, max_dist, |instance, max_dist| {
+                if !scene.nodes[instance.entity.node].is_visible() {
+                    return None;
+                }
+                let bvh = renderer.mesh_bvh(instance.mesh)?;
+                // The local direction is not normalized so that distances along
+                // the local ray are the same as in world space.
+                let inv_model = scene.nodes.world(instance.entity.node).to_mat4().inverse();
+                let local_ray = Ray::new(
+                    inv_model.transform_point3(ray.origin),
+                    inv_model.transform_vector3(direction),
+                );
+                let hit = bvh.intersect(&local_ray, max_dist)?;
+                let normal = inv_model
+                    .transpose()
+                    .transform_vector3(hit.normal)
+                    .normalize_or_zero();
+                Some((hit.t, (instance.entity, normal)))
+            })?;
+        Some(RaycastHit {
+            entity,
+            distance,
+            point: ray.at(distance),
+            normal: if normal.dot(direction) > 0.0 {
+                -normal
+            } else {
+                normal
+            },
+        })
+    }
+
+    /// Returns the BVH over the mesh entities, refitted to their current
+    /// transforms, or rebuilt if they changed.
+    fn update_mesh_instances<'a>(
+        instances: &'a mut Option<Bvh<MeshInstance>>,
+        scene: &Scene,
+        renderer: &Renderer,
+    ) -> &'a Bvh<MeshInstance> {
+        if !instances
+            .as_mut()
+            .is_some_and(|bvh| Self::refit_mesh_instances(bvh, scene, renderer))
+        {
+            *instances = None;
+        }
+        instances.get_or_insert_with(|| Self::build_mesh_instances(scene, renderer))
+    }
+
+    /// Returns the exposure of the visible mesh entities to the sun coming
+    /// from `sun_dir`, as the area-weighted fraction of the sampled triangles
+    /// facing the sun and not shadowed by the meshes.
+    fn sun_exposures(&self, sun_dir: Vec3) -> Vec<(Entity, f32)> {
+        profiling::scope!("PyAppState::sun_exposures");
+        // Maximum number of triangles sampled per entity.
+        const MAX_SAMPLES: usize = 64;
+        let scene = self.scene.read().unwrap();
+        let mut renderer = self.renderer.write().unwrap();
+        let mut instances = self.mesh_instances.lock().unwrap();
+        let bvh = Self::update_mesh_instances(&mut instances, &scene, &renderer);
+        let mut exposures = Vec::new();
+        for instance in bvh.items() {
+            let node = instance.entity.node;
+            if !scene.nodes[node].is_visible() {
+                continue;
+            }
+            let Some(mesh_bvh) = renderer.mesh_bvh(instance.mesh) else {
+                continue;
+            };
+            let model = scene.nodes.world(node).to_mat4();
+            let step = mesh_bvh.len().div_ceil(MAX_SAMPLES).max(1);
+            let (mut lit, mut total) = (0.0, 0.0);
+            for (_, triangle) in mesh_bvh.triangles().step_by(step) {
+                let [a, b, c] = triangle.map(|v| model.transform_point3(v));
+                let cross = (b - a).cross(c - a);
+                let area = cross.length() * 0.5;
+                if area <= 0.0 {
+                    continue;
+                }
+                total += area;
+                let normal = cross.normalize();
+                let center = (a + b + c) / 3.0;
+                // Triangles are lit from both sides, closed meshes shadow
+                // the inner side of their triangles.
+                let normal = if normal.dot(sun_dir) < 0.0 {
+                    -normal
+                } else {
+                    normal
+                };
+                let origin = center + normal * 1e-3 * center.abs().max_element().max(1.0);
+                let shadow_ray = Ray::new(origin, sun_dir);
+                if normal.dot(sun_dir) > 0.0
+                    && Self::intersect_mesh_instances(
+                        bvh,
+                        &scene,
+                        &mut renderer,
+                        shadow_ray,
+                        f32::INFINITY,
+                    )
+                    .is_none()
+                {
+                    lit += area;
+                }
+            }
+            if total > 0.0 {
+                exposures.push((instance.entity, lit / total));
+            }
+        }
+        exposures
+    }
+
+    /// Returns the bounds of the mesh instance in world space, empty if the
+    /// mesh is not uploaded yet.
+    fn mesh_instance_bounds(instance: &MeshInstance, scene: &Scene, renderer: &Renderer) -> Aabb {
+        renderer
+            .meshes
+            .get(instance.mesh)
+            .map_or(Aabb::EMPTY, |mesh| {
+                Aabb::from(mesh.bounds)
+                    .transform(&scene.nodes.world(instance.entity.node).to_mat4())
+            })
+    }
+
+    /// Builds the BVH over the world bounds of the mesh entities.
+    fn build_mesh_instances(scene: &Scene, renderer: &Renderer) -> Bvh<MeshInstance> {
+        let mut query =
+            <(legion::Entity, &MeshBundle, &NodeIdx)>::query().filter(!component::<Baked>());
+        Bvh::new(query.iter(&scene.world).map(|(raw, mesh, node)| {
+            let instance = MeshInstance {
+                entity: Entity {
+                    raw: *raw,
+                    node: *node,
+                },
+                mesh: mesh.mesh,
+            };
+            let bounds = Self::mesh_instance_bounds(&instance, scene, renderer);
+            (instance, bounds)
+        }))
+    }
+
+    /// Refits the BVH over the mesh entities to their world transforms.
+    /// Returns `false` if mesh entities were added, removed or changed their
+    /// mesh since the BVH was built, in which case it must be rebuilt.
+    fn refit_mesh_instances(
+        bvh: &mut Bvh<MeshInstance>,
+        scene: &Scene,
+        renderer: &Renderer,
+    ) -> bool {
+        let count = <&MeshBundle>::query()
+            .filter(!component::<Baked>())
+            .iter(&scene.world)
+            .count();
+        let unchanged = count == bvh.len()
+            && bvh.items().iter().all(|instance| {
+                scene
+                    .world
+                    .entry_ref(instance.entity.raw)
+                    .ok()
+                    .and_then(|entry| entry.get_component::<MeshBundle>().ok().copied())
+                    .is_some_and(|mesh| mesh.mesh == instance.mesh)
+            });
+        if unchanged {
+            bvh.refit(|instance| Self::mesh_instance_bounds(instance, scene, renderer));
         }
-        self.scene.write().unwrap().prepare(&mut self.main_camera);
-        self.renderer.write().unwrap().prepare();
+        unchanged
     }
 
     /// Creates the main camera.
@@ -427,11 +2776,33 @@ impl PyAppState {
                     KeyEvent {
                         physical_key: PhysicalKey::Code(keycode),
                         state,
+                        repeat,
+                        text,
                         ..
                     },
                 ..
             } => {
-                self.input.update_key_states(*keycode, *state);
+                self.input.update_key_states(*keycode, *state, *repeat);
+                // The text composed by the IME is received once committed.
+                if *state == ElementState::Pressed && !self.input.ime_active {
+                    let text = text
+                        .iter()
+                        .flat_map(|text| text.chars())
+                        .filter(|c| !c.is_control())
+                        .collect::<String>();
+                    if !text.is_empty() {
+                        self.dispatch_text_input_event(&text);
+                    }
+                }
+                true
+            }
+            WindowEvent::Ime(ime) => {
+                match ime {
+                    Ime::Enabled => self.input.ime_active = true,
+                    Ime::Disabled => self.input.ime_active = false,
+                    Ime::Preedit(text, cursor) => self.dispatch_text_preedit_event(text, *cursor),
+                    Ime::Commit(text) => self.dispatch_text_input_event(text),
+                }
                 true
             }
 
@@ -459,7 +2830,19 @@ impl PyAppState {
         args: &PyTuple,
         kwargs: Option<&PyDict>,
     ) -> PyResult<()> {
-        if let Some(listeners) = self.event_listeners.get(event_name) {
+        // The handlers may attach or detach handlers.
+        let listeners = self
+            .event_listeners
+            .read()
+            .unwrap()
+            .get(event_name)
+            .map(|listeners| {
+                listeners
+                    .iter()
+                    .map(|listener| listener.clone_ref(py))
+                    .collect::<Vec<_>>()
+            });
+        if let Some(listeners) = listeners {
             for listener in listeners {
                 let _ = listener.call(py, args, kwargs).map_err(|e| {
                     log::error!("Failed to dispatch event: {}", e);
@@ -469,6 +2852,35 @@ impl PyAppState {
         Ok(())
     }
 
+    /// Recreates the GPU context after the device was lost and restores the
+    /// GPU resources of the renderer on it.
+    fn restore_device(&mut self) -> Arc<GpuContext> {
+        log::warn!("GPU device lost, recreating it");
+        // The frames in flight belong to the lost device.
+        self.recorder.write().unwrap().stop();
+        self.context = Arc::new(GpuContext::new(None, &self.adapter_options));
+        self.renderer.write().unwrap().restore(&self.context);
+        {
+            let mut sunlight_score = self.sunlight_score.write().unwrap();
+            let ground = sunlight_score.ground();
+            *sunlight_score = SunlightScore::new(&self.context.device);
+            sunlight_score.set_ground(ground);
+        }
+        self.context.clone()
+    }
+
+    fn dispatch_exit_event(&self) {
+        Python::with_gil(|py| self.dispatch_event(py, "on_exit", PyTuple::empty(py), None))
+            .unwrap();
+    }
+
+    fn dispatch_device_restored_event(&self) {
+        Python::with_gil(|py| {
+            self.dispatch_event(py, "on_device_restored", PyTuple::empty(py), None)
+        })
+        .unwrap();
+    }
+
     fn dispatch_resize_event(&self, width: u32, height: u32) {
         Python::with_gil(|py| {
             self.dispatch_event(
@@ -481,6 +2893,73 @@ impl PyAppState {
         .unwrap();
     }
 
+    /// Dispatches the text typed on the keyboard or committed by the IME to
+    /// the "on_text_input" handlers.
+    fn dispatch_text_input_event(&self, text: &str) {
+        Python::with_gil(|py| {
+            self.dispatch_event(py, "on_text_input", PyTuple::new(py, [text]), None)
+        })
+        .unwrap();
+    }
+
+    /// Dispatches the text being composed by the IME to the
+    /// "on_text_preedit" handlers, with the byte range of the cursor in the
+    /// text if it is shown. The text is empty once the composition ends.
+    fn dispatch_text_preedit_event(&self, text: &str, cursor: Option<(usize, usize)>) {
+        Python::with_gil(|py| {
+            self.dispatch_event(
+                py,
+                "on_text_preedit",
+                PyTuple::new(py, &[text.into_py(py), cursor.into_py(py)]),
+                None,
+            )
+        })
+        .unwrap();
+    }
+
+    /// Dispatches the events posted with `post_event`, in the order they
+    /// were posted.
+    fn dispatch_posted_events(&self) {
+        let events = std::mem::take(&mut *self.posted_events.lock().unwrap());
+        if events.is_empty() {
+            return;
+        }
+        Python::with_gil(|py| {
+            for (name, payload) in events {
+                let args = match payload {
+                    Some(payload) => PyTuple::new(py, &[payload]),
+                    None => PyTuple::empty(py),
+                };
+                self.dispatch_event(py, &name, args, None).unwrap();
+            }
+        });
+    }
+
+    /// Calls the `update` method of the scripts attached to the entities of
+    /// the scene, see `PyEntity.attach_script`.
+    fn run_scripts(&self, input: Input, dt: f32) {
+        Python::with_gil(|py| {
+            // The scripts may change the scene, it is not locked while they
+            // run.
+            let scripts = self.scene.read().unwrap().scripts(py);
+            if scripts.is_empty() {
+                return;
+            }
+            let input = input.into_py(py);
+            for (entity, script) in scripts {
+                let entity = PyEntity {
+                    entity,
+                    cmd_sender: self.scene_cmd_sender(),
+                    scene: self.scene.clone(),
+                };
+                let args = (entity, input.clone_ref(py), dt);
+                if let Err(err) = script.call_method1(py, "update", args) {
+                    log::error!("Failed to run a script: {}", err);
+                }
+            }
+        });
+    }
+
     fn dispatch_update_event(&self, input: Input, dt: f32, t: f32) {
         Python::with_gil(|py| {
             self.dispatch_event(
@@ -493,12 +2972,494 @@ impl PyAppState {
         });
     }
 
-    fn update(&mut self, win_size: (u32, u32), dt: f32, t: f32) {
+    /// Shapes the colliders added since the last frame from the meshes of
+    /// their entities.
+    #[cfg(feature = "physics")]
+    fn shape_colliders(&mut self) {
+        let mut scene = self.scene.write().unwrap();
+        let pending = scene.take_pending_colliders();
+        if pending.is_empty() {
+            return;
+        }
+        let renderer = self.renderer.read().unwrap();
+        for (entity, shape) in pending {
+            let mesh = scene
+                .world
+                .entry_ref(entity.raw)
+                .ok()
+                .and_then(|entry| entry.get_component::<MeshBundle>().ok().copied());
+            let Some(mesh) = mesh else {
+                log::warn!("Entity {:?} has no mesh, can't add a collider.", entity);
+                continue;
+            };
+            let scale = scene.nodes.world(entity.node).scale;
+            match renderer
+                .mesh_source(mesh.mesh)
+                .and_then(|mesh| mesh_collider_shape(shape, mesh, scale))
+            {
+                Some((shape, offset)) => scene.add_collider(entity, shape, offset),
+                None => log::warn!("Failed to create the collider of {:?}", entity),
+            }
+        }
+    }
+
+    /// Advances the physics simulation and dispatches the collisions to the
+    /// "on_collision" handlers, as (entity, other entity, started).
+    #[cfg(feature = "physics")]
+    fn step_physics(&mut self, dt: f32) {
+        let collisions = self.scene.write().unwrap().step_physics(dt);
+        if collisions.is_empty() {
+            return;
+        }
+        Python::with_gil(|py| {
+            for collision in collisions {
+                let (a, b) = collision.entities;
+                let [a, b] = [a, b].map(|entity| PyEntity {
+                    entity,
+                    cmd_sender: self.scene_cmd_sender(),
+                    scene: self.scene.clone(),
+                });
+                self.dispatch_event(
+                    py,
+                    "on_collision",
+                    PyTuple::new(
+                        py,
+                        &[a.into_py(py), b.into_py(py), collision.started.into_py(py)],
+                    ),
+                    None,
+                )
+                .unwrap();
+            }
+        });
+    }
+
+    /// Dispatches the sunlight scores read back from the GPU to the
+    /// "on_scores_ready" handlers, as (scores, id of the computation).
+    fn poll_sunlight_scores(&mut self) {
+        let ready = self
+            .sunlight_score
+            .write()
+            .unwrap()
+            .poll_scores(&self.context.device);
+        if ready.is_empty() {
+            return;
+        }
+        Python::with_gil(|py| {
+            for (id, scores) in ready {
+                self.dispatch_event(
+                    py,
+                    "on_scores_ready",
+                    PyTuple::new(py, &[scores.into_py(py), id.into_py(py)]),
+                    None,
+                )
+                .unwrap();
+            }
+        });
+    }
+
+    /// Moves the main camera towards its next viewpoint, if any.
+    fn animate_camera(&mut self, dt: f32) {
+        let Some(camera) = self.main_camera else {
+            return;
+        };
+        let transform = *self.scene.read().unwrap().nodes[camera.node].transform();
+        if let Some((translation, rotation, arrived)) = self.camera_animator.advance(dt, &transform)
+        {
+            self.scene_cmd_sender()
+                .send(Command::SetTransform {
+                    entity: camera,
+                    translation,
+                    rotation,
+                    scale: transform.scale,
+                })
+                .unwrap();
+            if arrived {
+                Python::with_gil(|py| {
+                    self.dispatch_event(py, "on_camera_arrived", PyTuple::empty(py), None)
+                })
+                .unwrap();
+            }
+        }
+    }
+
+    /// Returns the view-projection matrix of the main camera, if any, and
+    /// the viewport it renders to in the window of the given size,
+    /// letterboxed if the camera has a fixed aspect ratio.
+    fn main_camera_view(&self, win_size: (u32, u32)) -> Option<(Mat4, Viewport)> {
+        let camera = self.main_camera?;
+        let scene = self.scene.read().unwrap();
+        let entry = scene.world.entry_ref(camera.raw).ok()?;
+        let component = entry.get_component::<Camera>().ok()?;
+        let viewport = Viewport::for_camera(
+            component,
+            wgpu::Extent3d {
+                width: win_size.0,
+                height: win_size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+        let proj = component.current_proj_matrix();
+        let view = scene.nodes.inverse_world(camera.node).to_mat4();
+        Some((proj * view, viewport))
+    }
+
+    /// Returns the ray through the cursor from the main camera, if any.
+    fn main_camera_ray(&self, cursor: [f32; 2], win_size: (u32, u32)) -> Option<Ray> {
+        let (view_proj, viewport) = self.main_camera_view(win_size)?;
+        cursor_ray(cursor, &viewport, view_proj)
+    }
+
+    /// Highlights the handle of the gizmo under the cursor and drags it while
+    /// the left mouse button is pressed, dispatching the transform of the
+    /// entity to the "on_transform_changed" handlers when released.
+    fn update_gizmo(&mut self, input: &Input, win_size: (u32, u32)) {
+        let (Some(camera), Some(ray)) = (
+            self.main_camera,
+            self.main_camera_ray(input.cursor_position(), win_size),
+        ) else {
+            return;
+        };
+        let mut scene = self.scene.write().unwrap();
+        let Some(mut gizmo) = scene.gizmo else {
+            return;
+        };
+        let world = scene.nodes.world(gizmo.target.node);
+        let local = *scene.nodes[gizmo.target.node].transform();
+        let size = Gizmo::size(
+            world.translation,
+            scene.nodes.world(camera.node).translation,
+        );
+        // Clicks orbiting the camera do not grab the handles.
+        let pressed = input.is_mouse_pressed(MouseButton::Left) && !input.is_alt_pressed();
+        let mut changed = None;
+        match gizmo.drag {
+            Some(drag) if !pressed => {
+                gizmo.drag = None;
+                changed = Some((drag.start_local, local));
+                self.commands().end_transaction();
+            }
+            Some(_) => {
+                if let Some(transform) = gizmo.drag_to(&ray) {
+                    let entity = gizmo.target;
+                    self.scene_cmd_sender()
+                        .send(match gizmo.mode {
+                            GizmoMode::Translate => Command::SetPosition {
+                                entity,
+                                position: transform.translation,
+                                world: true,
+                            },
+                            GizmoMode::Rotate => Command::SetRotation {
+                                entity,
+                                rotation: transform.rotation,
+                                world: true,
+                            },
+                            GizmoMode::Scale => Command::SetScale {
+                                entity,
+                                scale: transform.scale,
+                            },
+                        })
+                        .unwrap();
+                }
+            }
+            None => {
+                gizmo.hovered = gizmo.pick(&world, size, &ray);
+                if let (Some(axis), true) = (gizmo.hovered, pressed && !gizmo.pressed) {
+                    gizmo.begin_drag(axis, (world, local), size, &ray);
+                    // The whole drag is undone at once.
+                    self.commands().begin_transaction();
+                }
+            }
+        }
+        gizmo.pressed = pressed;
+        scene.gizmo = Some(gizmo);
+        drop(scene);
+
+        if let Some((before, after)) = changed.filter(|(before, after)| before != after) {
+            let entity = PyEntity {
+                entity: gizmo.target,
+                cmd_sender: self.scene_cmd_sender(),
+                scene: self.scene.clone(),
+            };
+            Python::with_gil(|py| {
+                self.dispatch_event(
+                    py,
+                    "on_transform_changed",
+                    PyTuple::new(
+                        py,
+                        &[
+                            entity.into_py(py),
+                            mat4_to_py(before.to_mat4()).into_py(py),
+                            mat4_to_py(after.to_mat4()).into_py(py),
+                        ],
+                    ),
+                    None,
+                )
+            })
+            .unwrap();
+        }
+    }
+
+    /// Moves the ghost of the ongoing placement, if any, to the point of the
+    /// ground under the cursor, and places the object on click.
+    fn update_placement(&mut self, input: &Input, win_size: (u32, u32)) {
+        let Some(mut placement) = self.placement else {
+            return;
+        };
+        let position = self
+            .main_camera_ray(input.cursor_position(), win_size)
+            .and_then(|ray| placement.ground_point(&ray));
+        if position != placement.position {
+            if let Some(position) = position {
+                self.commands().set_position(placement.ghost, position);
+            }
+            if position.is_some() != placement.position.is_some() {
+                self.commands()
+                    .set_visible(placement.ghost, position.is_some());
+            }
+            placement.position = position;
+        }
+
+        // Clicks orbiting the camera do not place the object.
+        let pressed = input.is_mouse_pressed(MouseButton::Left) && !input.is_alt_pressed();
+        let clicked = pressed && !placement.pressed;
+        placement.pressed = pressed;
+        self.placement = Some(placement);
+        if let (true, Some(position)) = (clicked, position) {
+            self.place_object(position);
+        }
+    }
+
+    /// Ends the ongoing placement with the object at `position`, dispatched
+    /// to the "on_object_placed" handlers as (entity, position).
+    fn place_object(&mut self, position: Vec3) {
+        let Some(placement) = self.placement.take() else {
+            return;
+        };
+        self.commands().despawn(placement.ghost);
+        let entity = {
+            let mut renderer = self.renderer.write().unwrap();
+            let mut scene = self.scene.write().unwrap();
+            let entity = scene.spawn(NodeIdx::root(), (placement.mesh,));
+            scene.nodes[entity.node].transform_mut().translation = position;
+            renderer.add_instancing(placement.mesh, &[entity.node]);
+            entity
+        };
+        let entity = PyEntity {
+            entity,
+            cmd_sender: self.scene_cmd_sender(),
+            scene: self.scene.clone(),
+        };
+        Python::with_gil(|py| {
+            self.dispatch_event(
+                py,
+                "on_object_placed",
+                PyTuple::new(py, &[entity.into_py(py), vec3_to_py(position).into_py(py)]),
+                None,
+            )
+        })
+        .unwrap();
+    }
+
+    /// Moves the sun of the day/night cycle, if enabled.
+    fn animate_sun(&mut self, dt: f32) {
+        let Some(cycle) = self.day_cycle.as_mut() else {
+            return;
+        };
+        let state = cycle.advance(dt);
+        self.scene_cmd_sender()
+            .send(Command::SetDirectionalLight {
+                entity: cycle.light,
+                direction: state.direction,
+            })
+            .unwrap();
+        self.scene_cmd_sender()
+            .send(Command::SetLightColor {
+                entity: cycle.light,
+                color: state.light_color,
+            })
+            .unwrap();
+        if let Some(entity) = self.main_camera {
+            self.scene_cmd_sender()
+                .send(Command::SetBackground {
+                    entity,
+                    color: state.sky_color,
+                })
+                .unwrap();
+            self.scene_cmd_sender()
+                .send(Command::SetSkySun {
+                    entity,
+                    sun: state.sun,
+                })
+                .unwrap();
+        }
+    }
+
+    /// Replaces the shared session by the new one. The meshes added to the
+    /// scene before are not replicated.
+    #[cfg(feature = "network")]
+    fn start_session(&mut self, session: Session) {
+        log::info!(
+            "{} the session as instance {}.",
+            if session.is_host() {
+                "Hosting"
+            } else {
+                "Joined"
+            },
+            session.instance()
+        );
+        *self.session.lock().unwrap() = Some(session);
+    }
+
+    /// Moves the camera of the minimap above the main camera, once the
+    /// commands of the frame have moved it, and turns its marker.
+    fn follow_minimap(&self) {
+        let Some(main) = self.main_camera else {
+            return;
+        };
+        let Some(camera) = self.renderer.read().unwrap().minimap().map(|m| m.camera) else {
+            return;
+        };
+        let mut scene = self.scene.write().unwrap();
+        let Some(node) = scene
+            .world
+            .entry_ref(camera)
+            .ok()
+            .and_then(|entry| entry.get_component::<NodeIdx>().ok().copied())
+        else {
+            return;
+        };
+        let main = scene.nodes.world(main.node);
+        // North, -Z, is up on the map.
+        let transform = scene.nodes[node].transform_mut();
+        transform.translation =
+            Vec3::new(main.translation.x, Self::MINIMAP_HEIGHT, main.translation.z);
+        transform.rotation = Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2);
+        transform.scale = Vec3::ONE;
+        let forward = main.rotation * Vec3::NEG_Z;
+        let heading = Vec2::new(forward.x, -forward.z)
+            .try_normalize()
+            .unwrap_or(Vec2::Y);
+        drop(scene);
+        self.renderer.write().unwrap().set_minimap_heading(heading);
+    }
+
+    /// Applies the changes received from the other instances of the shared
+    /// session, then sends the changes of the replicated entities made since
+    /// the last frame.
+    #[cfg(feature = "network")]
+    fn sync_session(&mut self) {
+        let session = self.session.clone();
+        let mut session = session.lock().unwrap();
+        let Some(session) = session.as_mut() else {
+            return;
+        };
+        for (from, message) in session.receive() {
+            match message {
+                Message::Spawn {
+                    id,
+                    parent,
+                    ref name,
+                    ref obj,
+                    ref mtl,
+                    ref textures,
+                } => {
+                    let loaded =
+                        session
+                            .write_mesh_files(id, obj, mtl, textures)
+                            .and_then(|path| {
+                                Mesh::try_load_from_obj(&path).map_err(std::io::Error::other)
+                            });
+                    match loaded {
+                        Ok(mut mesh) => {
+                            mesh.name = name.clone();
+                            let entity =
+                                self.spawn_object_with_mesh(session.parent_node(parent), &mut mesh);
+                            session.spawned(from, entity, message);
+                        }
+                        Err(err) => log::error!("Failed to load the shared mesh {}: {}", name, err),
+                    }
+                }
+                Message::Update { id, state } => {
+                    if let Some(entity) = session.updated(from, id, state) {
+                        state.apply(&mut self.scene.write().unwrap(), entity);
+                    }
+                }
+                Message::Despawn { id } => {
+                    if let Some(entity) = session.despawned(from, id) {
+                        self.commands().despawn(entity);
+                    }
+                }
+                Message::Welcome { .. } => {}
+            }
+        }
+        session.send_changes(&self.scene.read().unwrap());
+    }
+
+    /// Applies the OSC messages received since the last frame to the bound
+    /// entities and dispatches them as `on_osc` events.
+    #[cfg(feature = "osc")]
+    fn dispatch_osc_events(&self) {
+        let messages = match self.osc.lock().unwrap().as_ref() {
+            Some(osc) => {
+                let messages = osc.receive();
+                if messages
+                    .iter()
+                    .any(|m| osc.bindings.iter().any(|b| b.address == m.address))
+                {
+                    let mut scene = self.scene.write().unwrap();
+                    for message in &messages {
+                        for binding in osc.bindings.iter().filter(|b| b.address == message.address)
+                        {
+                            binding.apply(message, &mut scene);
+                        }
+                    }
+                }
+                messages
+            }
+            None => return,
+        };
+        if messages.is_empty() {
+            return;
+        }
+        Python::with_gil(|py| {
+            for message in messages {
+                let args = pyo3::types::PyList::new(
+                    py,
+                    message.args.into_iter().map(|arg| arg.into_py(py)),
+                );
+                self.dispatch_event(
+                    py,
+                    "on_osc",
+                    PyTuple::new(py, &[message.address.into_py(py), args.into_py(py)]),
+                    None,
+                )
+                .unwrap();
+            }
+        });
+    }
+
+    /// Updates the application after a frame of `dt` real seconds.
+    fn update(&mut self, win_size: (u32, u32), dt: f32) {
+        *self.window_size.lock().unwrap() = win_size;
         let input = self.input.take();
+        // The scene follows the scaled time, the camera controls the real one.
+        let time = self.time.lock().unwrap().advance(dt);
+        self.renderer.write().unwrap().set_time(time);
+        self.animate_sun(time.delta);
+        self.poll_sunlight_scores();
+        #[cfg(feature = "network")]
+        self.sync_session();
+        #[cfg(feature = "osc")]
+        self.dispatch_osc_events();
+
+        // The animation takes over the camera controls.
+        let animating = self.camera_animator.is_animating();
+        self.animate_camera(dt);
 
         // Rotate the camera with the middle mouse button.
-        if input.is_mouse_pressed(MouseButton::Middle)
-            || (input.is_mouse_pressed(MouseButton::Left) && input.is_alt_pressed())
+        if !animating
+            && (input.is_mouse_pressed(MouseButton::Middle)
+                || (input.is_mouse_pressed(MouseButton::Left) && input.is_alt_pressed()))
         {
             let delta = input.cursor_delta();
             // Make the rotation the same direction as the mouse movement.
@@ -515,7 +3476,7 @@ impl PyAppState {
                         let rot = Quat::from_mat4(
                             &(Mat4::from_rotation_y(horiz) * Mat4::from_rotation_x(vert)),
                         );
-                        self.scene_cmd_sender
+                        self.scene_cmd_sender()
                             .send(Command::Rotate {
                                 entity: self.main_camera.unwrap(),
                                 rotation: rot,
@@ -525,7 +3486,7 @@ impl PyAppState {
                     }
                     (true, false) => {
                         // Pan the camera.
-                        self.scene_cmd_sender
+                        self.scene_cmd_sender()
                             .send(Command::CameraPan {
                                 entity: self.main_camera.unwrap(),
                                 delta_x: horiz,
@@ -535,7 +3496,7 @@ impl PyAppState {
                     }
                     (false, _) => {
                         // Orbit the camera around the target.
-                        self.scene_cmd_sender
+                        self.scene_cmd_sender()
                             .send(Command::CameraOrbit {
                                 entity: self.main_camera.unwrap(),
                                 rotation_x: vert,
@@ -548,13 +3509,13 @@ impl PyAppState {
         }
 
         // Zoom in/out with the mouse wheel.
-        if input.scroll_delta().is_normal() {
+        if !animating && input.scroll_delta().is_normal() {
             let scale = if input.is_key_pressed(KeyCode::ControlLeft) {
                 10.0
             } else {
                 1.0
             };
-            self.scene_cmd_sender
+            self.scene_cmd_sender()
                 .send(Command::Translate {
                     entity: self.main_camera.unwrap(),
                     translation: Vec3::new(0.0, 0.0, input.scroll_delta() * dt * scale),
@@ -563,138 +3524,36 @@ impl PyAppState {
                 .unwrap();
         }
 
-        // Dispatch the update event, potentially run the user's update function.
-        self.dispatch_update_event(input, dt, t);
-    }
-}
-
-#[pyfunction]
-pub fn run_main_loop(mut app: PyAppState, builder: PyWindowBuilder) {
-    let event_loop = EventLoopBuilder::<UserEvent<()>>::with_user_event()
-        .build()
-        .unwrap();
+        self.update_gizmo(&input, win_size);
+        self.update_placement(&input, win_size);
 
-    // A helper struct to make sure the window and surface are all
-    // moved together.
-    struct WinSurf<'a> {
-        pub window: &'a Window,
-        pub surface: Surface<'a>,
-    }
+        #[cfg(feature = "physics")]
+        if time.delta > 0.0 {
+            self.step_physics(time.delta);
+        }
 
-    // Create the displaying window.
-    let window = app.create_window(&event_loop, builder);
-    let win_id = window.id();
-    let context = app.context.clone();
+        // Show the messages of the loading and rendering threads in Python.
+        forward_pending();
 
-    // Create the surface to render to.
-    let surface = Surface::new(&context, &window);
-    let mut blph_render_pass = BlinnPhongRenderPass::new(&context, surface.format());
-    // Ready to present the window.
-    window.set_visible(true);
+        self.dispatch_posted_events();
+        self.run_scripts(input, time.delta);
 
-    let mut win_surf = WinSurf {
-        window: &window,
-        surface,
-    };
-    // ControlFlow::Poll continuously runs the event loop, even if the OS hasn't
-    // dispatched any events.
-    event_loop.set_control_flow(ControlFlow::Poll);
+        // Dispatch the update event, potentially run the user's update function.
+        self.dispatch_update_event(input, time.delta, time.elapsed);
+    }
+}
 
+/// Runs the main loop of the application, opening a window configured by
+/// `builder` or by the configuration of the application if `None`.
+#[pyfunction]
+#[pyo3(signature = (app, builder=None))]
+pub fn run_main_loop(mut app: PyAppState, builder: Option<PyWindowBuilder>) {
+    let mut event_loop = take_event_loop();
+    let builder = builder.unwrap_or_else(|| app.window.clone());
+    let mut main_loop = MainLoop::new(&mut app, &event_loop, builder);
     event_loop
-        .run(move |event, evlp| {
-            match event {
-                Event::UserEvent(_) => {
-                    // todo
-                }
-                Event::WindowEvent {
-                    ref event,
-                    window_id,
-                } if window_id == win_id => {
-                    if !app.process_input(event) {
-                        match event {
-                            WindowEvent::CloseRequested => {
-                                evlp.exit();
-                            }
-                            WindowEvent::Resized(sz) => {
-                                if win_surf
-                                    .surface
-                                    .resize(&context.device, sz.width, sz.height)
-                                {
-                                    // Dispatch the resize event.
-                                    app.dispatch_resize_event(sz.width, sz.height);
-                                    // TODO: update camera aspect ratio
-                                }
-                            }
-                            WindowEvent::ScaleFactorChanged { .. } => {
-                                if win_surf.surface.resize(
-                                    &context.device,
-                                    win_surf.window.inner_size().width,
-                                    win_surf.window.inner_size().height,
-                                ) {
-                                    // Dispatch the resize event.
-                                    app.dispatch_resize_event(
-                                        win_surf.window.inner_size().width,
-                                        win_surf.window.inner_size().height,
-                                    );
-                                }
-                            }
-                            WindowEvent::RedrawRequested => {
-                                // Grab a frame from the surface.
-                                let frame = win_surf
-                                    .surface
-                                    .get_current_texture()
-                                    .expect("Failed to get a frame from the surface");
-                                let target = RenderTarget {
-                                    size: frame.texture.size(),
-                                    view: frame.texture.create_view(&Default::default()),
-                                    format: win_surf.surface.format(),
-                                };
-
-                                let scene = app.scene.read().unwrap();
-                                match app.renderer.write().unwrap().render(
-                                    &scene,
-                                    &target,
-                                    &mut blph_render_pass,
-                                ) {
-                                    Ok(_) => {}
-                                    Err(
-                                        wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated,
-                                    ) => {
-                                        win_surf.surface.resize(
-                                            &context.device,
-                                            win_surf.surface.width(),
-                                            win_surf.surface.height(),
-                                        );
-                                    }
-                                    Err(wgpu::SurfaceError::OutOfMemory) => {
-                                        evlp.exit();
-                                    }
-                                    Err(e) => eprintln!("{:?}", e),
-                                }
-
-                                frame.present();
-                            }
-                            _ => {}
-                        }
-                        if app.input.is_key_pressed(KeyCode::Escape) {
-                            evlp.exit();
-                        }
-                    }
-                }
-                // The main event loop has been cleared and will not be processed
-                // again until the next event needs to be handled.
-                Event::AboutToWait => {
-                    app.curr_time = std::time::Instant::now();
-                    let dt = app.delta_time();
-                    app.prev_time = app.curr_time;
-                    let t = app.start_time.elapsed().as_secs_f32();
-                    app.update(win_surf.surface.size(), dt, t);
-                    app.prepare();
-                    win_surf.window.request_redraw();
-                }
-                // Otherwise, just let the event pass through.
-                _ => {}
-            }
-        })
+        .run_on_demand(|event, evlp| main_loop.handle_event(&mut app, event, evlp))
         .expect("Failed to run the main loop");
+    drop(main_loop);
+    return_event_loop(event_loop);
 }
@@ -0,0 +1,335 @@
+//! Open Sound Control input, see `PyAppState::listen_osc`.
+//!
+//! The messages received over UDP are dispatched to Python as `on_osc`
+//! events, or drive the nodes of entities bound to their address.
+
+use crate::scene::{Entity, Scene};
+use crossbeam_channel::Receiver;
+use glam::{EulerRot, Quat};
+use pyo3::prelude::*;
+use std::{
+    io,
+    net::UdpSocket,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Argument of an OSC message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum OscArg {
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    String(String),
+    Blob(Vec<u8>),
+    Bool(bool),
+    Nil,
+}
+
+impl OscArg {
+    /// Returns the value of a numeric or boolean argument.
+    pub fn as_f32(&self) -> Option<f32> {
+        match self {
+            OscArg::Int(x) => Some(*x as f32),
+            OscArg::Long(x) => Some(*x as f32),
+            OscArg::Float(x) => Some(*x),
+            OscArg::Double(x) => Some(*x as f32),
+            OscArg::Bool(x) => Some(*x as u8 as f32),
+            OscArg::String(_) | OscArg::Blob(_) | OscArg::Nil => None,
+        }
+    }
+}
+
+impl IntoPy<PyObject> for OscArg {
+    fn into_py(self, py: Python<'_>) -> PyObject {
+        match self {
+            OscArg::Int(x) => x.into_py(py),
+            OscArg::Long(x) => x.into_py(py),
+            OscArg::Float(x) => x.into_py(py),
+            OscArg::Double(x) => x.into_py(py),
+            OscArg::String(x) => x.into_py(py),
+            OscArg::Blob(x) => pyo3::types::PyBytes::new(py, &x).into_py(py),
+            OscArg::Bool(x) => x.into_py(py),
+            OscArg::Nil => py.None(),
+        }
+    }
+}
+
+/// OSC message with its address pattern, e.g. `/slider/1`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OscMessage {
+    pub address: String,
+    pub args: Vec<OscArg>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads the values of an OSC packet in order, each one padded to 4 bytes.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid("truncated packet"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_be_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_be_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// Reads a string terminated by a null byte.
+    fn string(&mut self) -> io::Result<String> {
+        let len = self
+            .0
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| invalid("unterminated string"))?;
+        let string = String::from_utf8_lossy(&self.0[..len]).into_owned();
+        self.take((len + 4) & !3)?;
+        Ok(string)
+    }
+
+    fn blob(&mut self) -> io::Result<Vec<u8>> {
+        let len = usize::try_from(self.i32()?).map_err(|_| invalid("negative blob size"))?;
+        let blob = self.take(len)?.to_vec();
+        self.take((4 - len % 4) % 4)?;
+        Ok(blob)
+    }
+}
+
+/// Decodes an OSC packet, a message or a bundle of packets, appending its
+/// messages. The time tags of the bundles are ignored, the messages are
+/// handled as soon as they are received.
+pub fn decode_packet(bytes: &[u8], messages: &mut Vec<OscMessage>) -> io::Result<()> {
+    let mut reader = Reader(bytes);
+    if bytes.starts_with(b"#bundle\0") {
+        reader.take(16)?;
+        while !reader.0.is_empty() {
+            let len = usize::try_from(reader.i32()?).map_err(|_| invalid("negative size"))?;
+            decode_packet(reader.take(len)?, messages)?;
+        }
+        return Ok(());
+    }
+
+    let address = reader.string()?;
+    if !address.starts_with('/') {
+        return Err(invalid("invalid address"));
+    }
+    // Old implementations may omit the type tags of messages without
+    // arguments.
+    let tags = if reader.0.is_empty() {
+        String::from(",")
+    } else {
+        reader.string()?
+    };
+    let Some(tags) = tags.strip_prefix(',') else {
+        return Err(invalid("invalid type tags"));
+    };
+    let mut args = Vec::with_capacity(tags.len());
+    for tag in tags.chars() {
+        args.push(match tag {
+            'i' => OscArg::Int(reader.i32()?),
+            'h' => OscArg::Long(reader.i64()?),
+            'f' => OscArg::Float(f32::from_bits(reader.i32()? as u32)),
+            'd' => OscArg::Double(f64::from_bits(reader.i64()? as u64)),
+            's' | 'S' => OscArg::String(reader.string()?),
+            'b' => OscArg::Blob(reader.blob()?),
+            'T' => OscArg::Bool(true),
+            'F' => OscArg::Bool(false),
+            'N' | 'I' => OscArg::Nil,
+            _ => return Err(invalid("unsupported argument type")),
+        });
+    }
+    messages.push(OscMessage { address, args });
+    Ok(())
+}
+
+/// Property of an entity driven by an OSC address, see `App.bind_osc`.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OscTarget {
+    /// Local position along the X axis.
+    PositionX,
+    /// Local position along the Y axis.
+    PositionY,
+    /// Local position along the Z axis.
+    PositionZ,
+    /// Local rotation in radians around the X axis.
+    RotationX,
+    /// Local rotation in radians around the Y axis.
+    RotationY,
+    /// Local rotation in radians around the Z axis.
+    RotationZ,
+    /// Uniform local scale.
+    Scale,
+    /// Visibility, visible if the value is greater than 0.5.
+    Visible,
+    /// Material override, the value being rounded to the index of the
+    /// material.
+    Material,
+}
+
+/// Binding of an OSC address to a property of an entity.
+#[derive(Clone, Debug)]
+pub struct OscBinding {
+    pub address: String,
+    pub entity: Entity,
+    pub target: OscTarget,
+    /// Factor applied to the value of the first argument.
+    pub scale: f32,
+    /// Offset added to the scaled value.
+    pub offset: f32,
+}
+
+impl OscBinding {
+    /// Sets the property of the entity from the first numeric argument of
+    /// the message.
+    pub fn apply(&self, message: &OscMessage, scene: &mut Scene) {
+        let Some(value) = message.args.iter().find_map(OscArg::as_f32) else {
+            return;
+        };
+        if !scene.world.contains(self.entity.raw) {
+            return;
+        }
+        let value = value * self.scale + self.offset;
+        let node = &mut scene.nodes[self.entity.node];
+        let transform = node.transform_mut();
+        let (mut x, mut y, mut z) = transform.rotation.to_euler(EulerRot::XYZ);
+        match self.target {
+            OscTarget::PositionX => transform.translation.x = value,
+            OscTarget::PositionY => transform.translation.y = value,
+            OscTarget::PositionZ => transform.translation.z = value,
+            OscTarget::RotationX | OscTarget::RotationY | OscTarget::RotationZ => {
+                match self.target {
+                    OscTarget::RotationX => x = value,
+                    OscTarget::RotationY => y = value,
+                    _ => z = value,
+                }
+                transform.rotation = Quat::from_euler(EulerRot::XYZ, x, y, z);
+            }
+            OscTarget::Scale => transform.scale = glam::Vec3::splat(value),
+            OscTarget::Visible => node.set_visible(value > 0.5),
+            OscTarget::Material => node.material_override = Some(value.round().max(0.0) as u32),
+        }
+    }
+}
+
+/// Receives the OSC messages sent to a UDP port on a background thread.
+pub struct OscListener {
+    port: u16,
+    receiver: Receiver<OscMessage>,
+    /// Stops the receiving thread when set.
+    closed: Arc<AtomicBool>,
+    /// Properties of entities driven by the messages.
+    pub bindings: Vec<OscBinding>,
+}
+
+impl OscListener {
+    /// Starts listening on the UDP port of all the network interfaces.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        // Polled so that the thread stops once the listener is dropped.
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let closed = Arc::new(AtomicBool::new(false));
+        let stopped = closed.clone();
+        std::thread::spawn(move || {
+            let mut buffer = vec![0u8; 65536];
+            let mut messages = Vec::new();
+            while !stopped.load(Ordering::Relaxed) {
+                let len = match socket.recv(&mut buffer) {
+                    Ok(len) => len,
+                    Err(err)
+                        if matches!(
+                            err.kind(),
+                            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                        ) =>
+                    {
+                        continue
+                    }
+                    Err(err) => {
+                        log::error!("Failed to receive OSC packet: {}", err);
+                        continue;
+                    }
+                };
+                if let Err(err) = decode_packet(&buffer[..len], &mut messages) {
+                    log::warn!("Ignored invalid OSC packet: {}", err);
+                }
+                for message in messages.drain(..) {
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Self {
+            port,
+            receiver,
+            closed,
+            bindings: Vec::new(),
+        })
+    }
+
+    /// Returns the port listened to.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Takes the messages received since the last call.
+    pub fn receive(&self) -> Vec<OscMessage> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for OscListener {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_messages_and_bundles() {
+        let mut message = b"/fader\0\0,ifs\0\0\0\0".to_vec();
+        message.extend_from_slice(&7i32.to_be_bytes());
+        message.extend_from_slice(&0.5f32.to_be_bytes());
+        message.extend_from_slice(b"hi\0\0");
+
+        let mut bundle = b"#bundle\0".to_vec();
+        bundle.extend_from_slice(&1u64.to_be_bytes());
+        bundle.extend_from_slice(&(message.len() as i32).to_be_bytes());
+        bundle.extend_from_slice(&message);
+
+        let mut messages = Vec::new();
+        decode_packet(&bundle, &mut messages).unwrap();
+        assert_eq!(
+            messages,
+            [OscMessage {
+                address: String::from("/fader"),
+                args: vec![
+                    OscArg::Int(7),
+                    OscArg::Float(0.5),
+                    OscArg::String(String::from("hi"))
+                ],
+            }]
+        );
+        assert!(decode_packet(&message[..12], &mut messages).is_err());
+    }
+}
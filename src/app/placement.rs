@@ -0,0 +1,184 @@
+use crate::{
+    core::{bvh::Ray, mesh::MeshBundle},
+    render::Viewport,
+    scene::Entity,
+};
+use glam::{Mat4, Vec2, Vec3};
+
+/// Interactive placement of a mesh on the ground, see
+/// `PyAppState::begin_placement`.
+#[derive(Clone, Copy, Debug)]
+pub struct Placement {
+    /// Mesh of the placed object.
+    pub mesh: MeshBundle,
+    /// Translucent copy of the object following the cursor.
+    pub ghost: Entity,
+    /// Spacing of the grid the positions snap to, no snapping if zero.
+    pub snap: f32,
+    /// Height of the ground plane.
+    pub height: f32,
+    /// Position of the ghost, `None` while the cursor is not over the
+    /// ground.
+    pub position: Option<Vec3>,
+    /// Whether the left mouse button was pressed at the previous frame, a
+    /// click being a new press.
+    pub pressed: bool,
+}
+
+impl Placement {
+    /// Opacity of the materials of the ghost.
+    pub const GHOST_OPACITY: f32 = 0.5;
+
+    /// Creates a new placement of the mesh, whose ghost is not shown until
+    /// the cursor is over the ground.
+    pub fn new(mesh: MeshBundle, ghost: Entity, snap: f32, height: f32) -> Self {
+        Self {
+            mesh,
+            ghost,
+            snap: snap.max(0.0),
+            height,
+            position: None,
+            // A click which started the placement does not end it.
+            pressed: true,
+        }
+    }
+
+    /// Returns the intersection of the ray with the ground plane snapped to
+    /// the grid, or `None` if the ray does not go towards the ground.
+    pub fn ground_point(&self, ray: &Ray) -> Option<Vec3> {
+        let point = ground_point(ray, self.height)?;
+        if self.snap > 0.0 {
+            let cell = (Vec2::new(point.x, point.z) / self.snap).round() * self.snap;
+            Some(Vec3::new(cell.x, self.height, cell.y))
+        } else {
+            Some(point)
+        }
+    }
+}
+
+/// Returns the intersection of the ray with the horizontal plane at the
+/// given height, or `None` if the ray does not go towards the plane.
+pub fn ground_point(ray: &Ray, height: f32) -> Option<Vec3> {
+    if ray.direction.y.abs() < f32::EPSILON {
+        return None;
+    }
+    let t = (height - ray.origin.y) / ray.direction.y;
+    if t < 0.0 {
+        return None;
+    }
+    let point = ray.at(t);
+    point.is_finite().then_some(point)
+}
+
+/// Returns the ray through the cursor in world space, from the near plane of
+/// the camera.
+///
+/// # Arguments
+///
+/// * `cursor` - Position of the cursor in pixels, from the top-left corner
+///   of the window.
+/// * `viewport` - Region of the window the camera renders to, in pixels.
+/// * `view_proj` - Product of the projection and view matrices of the
+///   camera.
+pub fn cursor_ray(cursor: [f32; 2], viewport: &Viewport, view_proj: Mat4) -> Option<Ray> {
+    if viewport.width <= 0.0 || viewport.height <= 0.0 {
+        return None;
+    }
+    let ndc = Vec2::new(
+        (cursor[0] - viewport.x) / viewport.width * 2.0 - 1.0,
+        1.0 - (cursor[1] - viewport.y) / viewport.height * 2.0,
+    );
+    let inv = view_proj.inverse();
+    let near = inv.project_point3(ndc.extend(0.0));
+    // The far plane may be infinitely far, any point behind the near plane
+    // gives the direction.
+    let far = inv.project_point3(ndc.extend(0.5));
+    let direction = (far - near).try_normalize()?;
+    Some(Ray::new(near, direction))
+}
+
+/// Returns the position in pixels from the top-left corner of the window of
+/// a point in world space, or `None` if the point is behind the camera.
+///
+/// # Arguments
+///
+/// * `point` - Position of the point in world space.
+/// * `viewport` - Region of the window the camera renders to, in pixels.
+/// * `view_proj` - Product of the projection and view matrices of the
+///   camera.
+pub fn screen_point(point: Vec3, viewport: &Viewport, view_proj: Mat4) -> Option<Vec2> {
+    let clip = view_proj * point.extend(1.0);
+    if clip.w <= f32::EPSILON {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    Some(Vec2::new(
+        viewport.x + (ndc.x + 1.0) * 0.5 * viewport.width,
+        viewport.y + (1.0 - ndc.y) * 0.5 * viewport.height,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_points_round_trip_through_the_ground() {
+        let viewport = Viewport::full(wgpu::Extent3d {
+            width: 800,
+            height: 600,
+            depth_or_array_layers: 1,
+        });
+        let proj = Mat4::perspective_infinite_rh(60f32.to_radians(), 800.0 / 600.0, 0.1);
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 10.0, 10.0), Vec3::ZERO, Vec3::Y);
+        let view_proj = proj * view;
+
+        let point = Vec3::new(2.0, 0.0, -3.0);
+        let screen = screen_point(point, &viewport, view_proj).unwrap();
+        let ray = cursor_ray(screen.to_array(), &viewport, view_proj).unwrap();
+        let ground = ground_point(&ray, 0.0).unwrap();
+        assert!(ground.distance(point) < 1e-3, "{ground:?}");
+
+        // The center of the window looks at the target of the camera.
+        let center = screen_point(Vec3::ZERO, &viewport, view_proj).unwrap();
+        assert!(center.distance(Vec2::new(400.0, 300.0)) < 1e-3);
+        // Points behind the camera are not on the screen.
+        assert!(screen_point(Vec3::new(0.0, 20.0, 20.0), &viewport, view_proj).is_none());
+    }
+
+    #[test]
+    fn screen_points_map_through_letterboxed_viewports() {
+        // A square camera in a wide window, with bars on the sides.
+        let viewport = Viewport::letterboxed(
+            wgpu::Extent3d {
+                width: 800,
+                height: 600,
+                depth_or_array_layers: 1,
+            },
+            1.0,
+        );
+        assert_eq!((viewport.x, viewport.width), (100.0, 600.0));
+        let proj = Mat4::perspective_infinite_rh(60f32.to_radians(), 1.0, 0.1);
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 10.0, 10.0), Vec3::ZERO, Vec3::Y);
+        let view_proj = proj * view;
+
+        // The target of the camera is at the center of the viewport, which
+        // is also the center of the window.
+        let center = screen_point(Vec3::ZERO, &viewport, view_proj).unwrap();
+        assert!(
+            center.distance(Vec2::new(400.0, 300.0)) < 1e-3,
+            "{center:?}"
+        );
+        // The left edge of the image is at the left bar, not at the left
+        // edge of the window.
+        let ray = cursor_ray([100.0, 300.0], &viewport, view_proj).unwrap();
+        let ndc = view_proj.project_point3(ray.at(5.0));
+        assert!((ndc.x + 1.0).abs() < 1e-4, "{ndc:?}");
+
+        let point = Vec3::new(2.0, 0.0, -3.0);
+        let screen = screen_point(point, &viewport, view_proj).unwrap();
+        let ray = cursor_ray(screen.to_array(), &viewport, view_proj).unwrap();
+        let ground = ground_point(&ray, 0.0).unwrap();
+        assert!(ground.distance(point) < 1e-3, "{ground:?}");
+    }
+}
@@ -0,0 +1,76 @@
+use crate::{
+    app::command::Command,
+    core::FxHashMap,
+    render::{Renderer, SceneDraws},
+    scene::{PyScene, Scene},
+};
+
+/// Scenes of the application, see `PyAppState::create_scene`.
+///
+/// The active scene lives in the scene shared by the copies of the
+/// application; the other ones are kept here together with their draws until
+/// they are activated, at which point they are exchanged with it.
+pub struct SceneStore {
+    /// Identifier of the active scene.
+    active: u32,
+    /// Identifier of the next created scene.
+    next_id: u32,
+    /// Inactive scenes with the instances of their meshes.
+    inactive: FxHashMap<u32, (Scene, SceneDraws)>,
+}
+
+impl Default for SceneStore {
+    fn default() -> Self {
+        Self {
+            active: 0,
+            next_id: 1,
+            inactive: FxHashMap::default(),
+        }
+    }
+}
+
+impl SceneStore {
+    /// Returns the handle of the active scene.
+    pub fn active(&self) -> PyScene {
+        PyScene { id: self.active }
+    }
+
+    /// Returns whether the scene exists.
+    pub fn contains(&self, scene: PyScene) -> bool {
+        scene.id == self.active || self.inactive.contains_key(&scene.id)
+    }
+
+    /// Adds a new empty scene, inactive until it is activated.
+    pub fn create(&mut self) -> PyScene {
+        let id = self.next_id;
+        self.next_id += 1;
+        let (sender, receiver) = crossbeam_channel::unbounded::<Command>();
+        self.inactive
+            .insert(id, (Scene::new(sender, receiver), SceneDraws::default()));
+        PyScene { id }
+    }
+
+    /// Makes the scene the active one by exchanging it with the active scene,
+    /// and the instances drawn by the renderer with its own. Returns false if
+    /// the scene doesn't exist or is already active.
+    pub fn activate(
+        &mut self,
+        scene: PyScene,
+        active: &mut Scene,
+        renderer: &mut Renderer,
+    ) -> bool {
+        let Some((mut next, mut draws)) = self.inactive.remove(&scene.id) else {
+            return false;
+        };
+        std::mem::swap(active, &mut next);
+        renderer.swap_scene_draws(&mut draws);
+        self.inactive.insert(self.active, (next, draws));
+        self.active = scene.id;
+        true
+    }
+
+    /// Drops the inactive scenes, the active one being kept.
+    pub fn clear(&mut self) {
+        self.inactive.clear();
+    }
+}
@@ -0,0 +1,1024 @@
+//! Shared sessions replicating the scene between instances of the
+//! application over TCP, see `PyAppState::host_session`.
+//!
+//! The host relays the changes of each client to the other ones, so that all
+//! instances receive the changes in the order seen by the host. The host is
+//! the authority of the session: the changes of a client are validated
+//! before being applied and relayed. Each message is a frame prefixed by its
+//! length in bytes, written by a thread per connection so that a slow
+//! instance doesn't stall the others.
+
+use crate::{
+    core::{mesh::Mesh, FxHashMap, SmlString},
+    scene::{Entity, NodeIdx, Scene, Transform},
+};
+use crossbeam_channel::{Receiver, Sender};
+use glam::{Quat, Vec3};
+use std::{
+    io::{self, Read, Write},
+    net::{Shutdown, TcpListener, TcpStream, ToSocketAddrs},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Identifier of a replicated entity, shared by all the instances of the
+/// session: the instance that spawned it in the high bits and a counter of
+/// this instance in the low bits.
+pub type NetId = u64;
+
+/// Largest accepted message, to reject corrupted frames.
+const MAX_MESSAGE_SIZE: u32 = 64 * 1024 * 1024;
+
+/// Number of frames queued for an instance before it is considered unable
+/// to keep up and disconnected.
+const MAX_QUEUED_FRAMES: usize = 1024;
+
+/// Keywords of the texture statements of the material libraries.
+const MTL_TEXTURE_KEYWORDS: [&str; 14] = [
+    "map_Ka", "map_Kd", "map_Ks", "map_Ns", "map_d", "map_bump", "bump", "disp", "decal", "norm",
+    "map_Ke", "map_Pr", "map_Pm", "map_Ps",
+];
+
+/// Replicated state of the node of an entity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeState {
+    pub transform: Transform,
+    pub visible: bool,
+    pub material: Option<u32>,
+}
+
+impl NodeState {
+    /// Reads the state of the node of the entity.
+    fn of(scene: &Scene, entity: Entity) -> Self {
+        let node = &scene.nodes[entity.node];
+        Self {
+            transform: *node.transform(),
+            visible: node.is_visible(),
+            material: node.material_override,
+        }
+    }
+
+    /// Writes the state to the node of the entity.
+    pub fn apply(&self, scene: &mut Scene, entity: Entity) {
+        let node = &mut scene.nodes[entity.node];
+        node.set_transform(self.transform);
+        node.set_visible(self.visible);
+        node.material_override = self.material;
+    }
+}
+
+/// Messages exchanged by the instances of a session.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Message {
+    /// Sent by the host to a client joining the session with the identifier
+    /// of its instance.
+    Welcome { instance: u32 },
+    /// Spawns an entity with a mesh, written as a wavefront obj file and its
+    /// material library, with the textures it references by file name.
+    Spawn {
+        id: NetId,
+        parent: Option<NetId>,
+        name: SmlString,
+        obj: Vec<u8>,
+        mtl: Vec<u8>,
+        textures: Vec<(SmlString, Vec<u8>)>,
+    },
+    /// Sets the state of the node of an entity.
+    Update { id: NetId, state: NodeState },
+    /// Removes an entity and its descendants.
+    Despawn { id: NetId },
+}
+
+impl Message {
+    /// Encodes the message without its length.
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            Message::Welcome { instance } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&instance.to_le_bytes());
+            }
+            Message::Spawn {
+                id,
+                parent,
+                name,
+                obj,
+                mtl,
+                textures,
+            } => {
+                bytes.push(1);
+                bytes.extend_from_slice(&id.to_le_bytes());
+                bytes.extend_from_slice(&parent.unwrap_or(u64::MAX).to_le_bytes());
+                let files = textures
+                    .iter()
+                    .flat_map(|(name, data)| [name.as_bytes(), data.as_slice()]);
+                for data in [name.as_bytes(), obj.as_slice(), mtl.as_slice()] {
+                    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(data);
+                }
+                bytes.extend_from_slice(&(textures.len() as u32).to_le_bytes());
+                for data in files {
+                    bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                    bytes.extend_from_slice(data);
+                }
+            }
+            Message::Update { id, state } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&id.to_le_bytes());
+                let t = &state.transform;
+                for x in t.translation.to_array() {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+                for x in t.rotation.to_array() {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+                for x in t.scale.to_array() {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+                bytes.push(state.visible as u8);
+                bytes.extend_from_slice(&state.material.unwrap_or(u32::MAX).to_le_bytes());
+            }
+            Message::Despawn { id } => {
+                bytes.push(3);
+                bytes.extend_from_slice(&id.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    /// Decodes a message encoded by `encode`.
+    fn decode(bytes: &[u8]) -> io::Result<Self> {
+        let mut reader = Decoder(bytes);
+        let message = match reader.u8()? {
+            0 => Message::Welcome {
+                instance: reader.u32()?,
+            },
+            1 => Message::Spawn {
+                id: reader.u64()?,
+                parent: Some(reader.u64()?).filter(|&p| p != u64::MAX),
+                name: reader.string()?,
+                obj: reader.bytes()?.to_vec(),
+                mtl: reader.bytes()?.to_vec(),
+                textures: (0..reader.u32()?)
+                    .map(|_| Ok((reader.string()?, reader.bytes()?.to_vec())))
+                    .collect::<io::Result<_>>()?,
+            },
+            2 => {
+                let id = reader.u64()?;
+                let translation = Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?);
+                let rotation =
+                    Quat::from_xyzw(reader.f32()?, reader.f32()?, reader.f32()?, reader.f32()?);
+                let scale = Vec3::new(reader.f32()?, reader.f32()?, reader.f32()?);
+                let visible = reader.u8()? != 0;
+                let material = Some(reader.u32()?).filter(|&m| m != u32::MAX);
+                Message::Update {
+                    id,
+                    state: NodeState {
+                        transform: Transform {
+                            translation,
+                            rotation,
+                            scale,
+                        },
+                        visible,
+                        material,
+                    },
+                }
+            }
+            3 => Message::Despawn { id: reader.u64()? },
+            tag => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("unknown message {}", tag),
+                ))
+            }
+        };
+        Ok(message)
+    }
+}
+
+/// Reads the values of an encoded message in order.
+struct Decoder<'a>(&'a [u8]);
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> io::Result<f32> {
+        Ok(f32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> io::Result<SmlString> {
+        Ok(SmlString::from(
+            String::from_utf8_lossy(self.bytes()?).as_ref(),
+        ))
+    }
+}
+
+/// Encodes the message prefixed by its length.
+fn frame(message: &Message) -> Vec<u8> {
+    let bytes = message.encode();
+    let mut frame = Vec::with_capacity(bytes.len() + 4);
+    frame.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&bytes);
+    frame
+}
+
+fn read_message(mut stream: &TcpStream) -> io::Result<Message> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message of {} bytes", len),
+        ));
+    }
+    // The buffer grows as the bytes arrive, a corrupted length doesn't
+    // allocate the whole message upfront.
+    let mut bytes = Vec::new();
+    stream.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Message::decode(&bytes)
+}
+
+/// Returns whether the name is the name of a file in the directory of the
+/// session, without any other path component.
+fn is_file_name(name: &str) -> bool {
+    !name.is_empty() && Path::new(name).file_name().is_some_and(|n| n == name)
+}
+
+/// Reads the textures referenced by the material library written in `dir`,
+/// and returns the library referencing them by the names they are sent with,
+/// prefixed by `prefix` to keep apart the textures of different meshes.
+/// Textures which can't be read are left out.
+fn pack_textures(mtl: &str, dir: &Path, prefix: &str) -> (String, Vec<(SmlString, Vec<u8>)>) {
+    let mut textures = Vec::new();
+    let mut names = FxHashMap::<PathBuf, SmlString>::default();
+    let mut packed = String::with_capacity(mtl.len());
+    for line in mtl.lines() {
+        let statement = line
+            .trim()
+            .split_once(char::is_whitespace)
+            .filter(|(keyword, _)| MTL_TEXTURE_KEYWORDS.contains(keyword));
+        let Some((keyword, path)) = statement else {
+            packed.push_str(line);
+            packed.push('\n');
+            continue;
+        };
+        let path = dir.join(path.trim());
+        let name = match names.get(&path) {
+            Some(name) => Some(name.clone()),
+            None => match std::fs::read(&path) {
+                Ok(data) => {
+                    let file_name = path
+                        .file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    let name =
+                        SmlString::from(format!("{}_{}_{}", prefix, textures.len(), file_name));
+                    names.insert(path.clone(), name.clone());
+                    textures.push((name.clone(), data));
+                    Some(name)
+                }
+                Err(err) => {
+                    log::warn!("Failed to share the texture {:?}: {}", path, err);
+                    None
+                }
+            },
+        };
+        match name {
+            Some(name) => packed.push_str(&format!("{} {}\n", keyword, name)),
+            None => {
+                packed.push_str(line);
+                packed.push('\n');
+            }
+        }
+    }
+    (packed, textures)
+}
+
+/// Events of the network threads, handled by `Session::receive`.
+enum Event {
+    /// A client connected to the host.
+    Joined(Peer),
+    /// A message was received from the instance.
+    Received(u32, Message),
+    /// The connection with the instance was closed.
+    Left(u32),
+}
+
+/// Connection to another instance of the session.
+struct Peer {
+    instance: u32,
+    stream: TcpStream,
+    /// Frames written to the stream by the writer thread of the connection.
+    frames: Sender<Arc<[u8]>>,
+}
+
+impl Peer {
+    /// Starts writing the frames queued for the instance on a new thread.
+    fn new(instance: u32, stream: TcpStream) -> io::Result<Self> {
+        let (frames, queue) = crossbeam_channel::bounded::<Arc<[u8]>>(MAX_QUEUED_FRAMES);
+        let mut writer = stream.try_clone()?;
+        std::thread::spawn(move || {
+            for frame in queue {
+                if let Err(err) = writer.write_all(&frame) {
+                    log::debug!("Failed to write to instance {}: {}", instance, err);
+                    // The reader of the connection reports that it is closed.
+                    let _ = writer.shutdown(Shutdown::Both);
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            instance,
+            stream,
+            frames,
+        })
+    }
+
+    /// Queues the frame to be written. Returns false if the connection is
+    /// closed or the instance doesn't keep up.
+    fn send(&self, frame: Arc<[u8]>) -> bool {
+        self.frames.try_send(frame).is_ok()
+    }
+}
+
+/// Reads the messages of the peer on a new thread until the connection is
+/// closed.
+fn spawn_reader(instance: u32, stream: TcpStream, events: Sender<Event>) {
+    std::thread::spawn(move || {
+        loop {
+            match read_message(&stream) {
+                Ok(message) => {
+                    if events.send(Event::Received(instance, message)).is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    if err.kind() != io::ErrorKind::UnexpectedEof {
+                        log::debug!("Connection with instance {} closed: {}", instance, err);
+                    }
+                    break;
+                }
+            }
+        }
+        let _ = events.send(Event::Left(instance));
+    });
+}
+
+/// Entity replicated between the instances of the session.
+struct Replica {
+    id: NetId,
+    entity: Entity,
+    /// Message spawning the entity, sent to the clients joining later.
+    spawn: Message,
+    /// State last sent or received, `None` until the first synchronization.
+    state: Option<NodeState>,
+}
+
+/// Shared session of the application, hosted or joined.
+pub struct Session {
+    host: bool,
+    /// Identifier of this instance, 0 for the host.
+    instance: u32,
+    /// Port the session is hosted on.
+    port: u16,
+    /// Counter of the entities spawned by this instance.
+    next_id: u32,
+    /// Clients of the host, or the host of a client.
+    peers: Vec<Peer>,
+    events: Receiver<Event>,
+    /// Stops accepting clients when set.
+    closed: Arc<AtomicBool>,
+    /// Replicated entities, each one after its parent.
+    replicas: Vec<Replica>,
+    /// Directory of the mesh files exchanged with the other instances.
+    dir: PathBuf,
+}
+
+impl Session {
+    /// Hosts a session accepting clients on the port.
+    pub fn host(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        let port = listener.local_addr()?.port();
+        // Polled so that the thread stops once the session is closed.
+        listener.set_nonblocking(true)?;
+        let (sender, events) = crossbeam_channel::unbounded();
+        let closed = Arc::new(AtomicBool::new(false));
+        let stopped = closed.clone();
+        std::thread::spawn(move || {
+            let mut next_instance = 1;
+            while !stopped.load(Ordering::Relaxed) {
+                let stream = match listener.accept() {
+                    Ok((stream, addr)) => {
+                        log::info!("Instance {} joined from {}.", next_instance, addr);
+                        stream
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                        std::thread::sleep(Duration::from_millis(50));
+                        continue;
+                    }
+                    Err(err) => {
+                        log::error!("Failed to accept a client: {}", err);
+                        continue;
+                    }
+                };
+                let instance = next_instance;
+                next_instance += 1;
+                let joined = stream.set_nonblocking(false).and_then(|_| {
+                    let _ = stream.set_nodelay(true);
+                    let reader = stream.try_clone()?;
+                    let peer = Peer::new(instance, stream)?;
+                    peer.send(frame(&Message::Welcome { instance }).into());
+                    Ok((peer, reader))
+                });
+                match joined {
+                    Ok((peer, reader)) => {
+                        spawn_reader(instance, reader, sender.clone());
+                        if sender.send(Event::Joined(peer)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(err) => log::error!("Failed to welcome instance {}: {}", instance, err),
+                }
+            }
+        });
+        Self::new(true, 0, port, Vec::new(), events, closed)
+    }
+
+    /// Joins the session hosted at the address.
+    pub fn join<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let _ = stream.set_nodelay(true);
+        let instance = match read_message(&stream)? {
+            Message::Welcome { instance } => instance,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "the host didn't welcome the instance",
+                ))
+            }
+        };
+        let (sender, events) = crossbeam_channel::unbounded();
+        spawn_reader(0, stream.try_clone()?, sender);
+        let port = stream.peer_addr()?.port();
+        let host = Peer::new(0, stream)?;
+        Self::new(
+            false,
+            instance,
+            port,
+            vec![host],
+            events,
+            Default::default(),
+        )
+    }
+
+    fn new(
+        host: bool,
+        instance: u32,
+        port: u16,
+        peers: Vec<Peer>,
+        events: Receiver<Event>,
+        closed: Arc<AtomicBool>,
+    ) -> io::Result<Self> {
+        let dir = std::env::temp_dir().join(format!(
+            "bkfw-session-{}-{}-{}",
+            std::process::id(),
+            port,
+            instance
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self {
+            host,
+            instance,
+            port,
+            next_id: 0,
+            peers,
+            events,
+            closed,
+            replicas: Vec::new(),
+            dir,
+        })
+    }
+
+    /// Returns whether this instance hosts the session.
+    pub fn is_host(&self) -> bool {
+        self.host
+    }
+
+    /// Returns the identifier of this instance, 0 for the host.
+    pub fn instance(&self) -> u32 {
+        self.instance
+    }
+
+    /// Returns the port the session is hosted on, e.g. the one chosen by the
+    /// system if hosted on port 0.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Returns the number of other instances connected to this one.
+    pub fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    /// Queues the message for the connected instances but `except`,
+    /// dropping the connections that are closed or don't keep up.
+    fn broadcast(&mut self, message: &Message, except: Option<u32>) {
+        let frame: Arc<[u8]> = frame(message).into();
+        self.peers.retain(|peer| {
+            if Some(peer.instance) == except || peer.send(frame.clone()) {
+                return true;
+            }
+            log::warn!("Lost the connection with instance {}.", peer.instance);
+            let _ = peer.stream.shutdown(Shutdown::Both);
+            false
+        });
+    }
+
+    fn replica(&self, id: NetId) -> Option<&Replica> {
+        self.replicas.iter().find(|r| r.id == id)
+    }
+
+    /// Replicates the entity spawned with the mesh under the parent node to
+    /// the other instances, with the textures of its materials.
+    pub fn share_spawn(&mut self, entity: Entity, parent: NodeIdx, mesh: &Mesh) {
+        let id = (self.instance as NetId) << 32 | self.next_id as NetId;
+        self.next_id += 1;
+        let obj_path = self.dir.join(format!("{:016x}.obj", id));
+        let files = mesh.save_obj(&obj_path).and_then(|_| {
+            let obj = std::fs::read(&obj_path)?;
+            let mtl = std::fs::read_to_string(obj_path.with_extension("mtl")).unwrap_or_default();
+            Ok((obj, mtl))
+        });
+        let (obj, mtl) = match files {
+            Ok(files) => files,
+            Err(err) => {
+                log::warn!("Failed to share the mesh {}: {}", mesh.name, err);
+                return;
+            }
+        };
+        let (packed, textures) = pack_textures(&mtl, &self.dir, &format!("{:016x}", id));
+        let mut spawn = Message::Spawn {
+            id,
+            parent: self
+                .replicas
+                .iter()
+                .find(|r| r.entity.node == parent)
+                .map(|r| r.id),
+            name: mesh.name.clone(),
+            obj,
+            mtl: packed.into_bytes(),
+            textures,
+        };
+        if spawn.encode().len() > MAX_MESSAGE_SIZE as usize {
+            log::warn!(
+                "The mesh {} is too large to be shared with its textures, sharing it without them.",
+                mesh.name
+            );
+            if let Message::Spawn {
+                mtl: packed,
+                textures,
+                ..
+            } = &mut spawn
+            {
+                *packed = mtl.into_bytes();
+                textures.clear();
+            }
+            if spawn.encode().len() > MAX_MESSAGE_SIZE as usize {
+                log::warn!("The mesh {} is too large to be shared.", mesh.name);
+                return;
+            }
+        }
+        self.broadcast(&spawn, None);
+        self.replicas.push(Replica {
+            id,
+            entity,
+            spawn,
+            state: None,
+        });
+    }
+
+    /// Handles the events of the network threads, and returns the messages
+    /// received from the other instances to be applied to the scene, in
+    /// order, with the instance they come from.
+    ///
+    /// The host drops the invalid messages of its clients, see `validate`,
+    /// and relays the others once they are applied with `spawned`, `updated`
+    /// and `despawned`.
+    pub fn receive(&mut self) -> Vec<(u32, Message)> {
+        let mut received = Vec::new();
+        // Entities spawned by the received messages, not applied yet.
+        let mut pending = Vec::new();
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                Event::Joined(peer) => {
+                    // Bring the new client up to date, in a single frame
+                    // not to fill its queue.
+                    let mut catch_up = Vec::new();
+                    for replica in &self.replicas {
+                        catch_up.extend_from_slice(&frame(&replica.spawn));
+                        if let Some(state) = replica.state {
+                            let update = Message::Update {
+                                id: replica.id,
+                                state,
+                            };
+                            catch_up.extend_from_slice(&frame(&update));
+                        }
+                    }
+                    if catch_up.is_empty() || peer.send(catch_up.into()) {
+                        self.peers.push(peer);
+                    } else {
+                        log::warn!("Failed to update instance {}.", peer.instance);
+                        let _ = peer.stream.shutdown(Shutdown::Both);
+                    }
+                }
+                Event::Left(instance) => {
+                    log::info!("Instance {} left the session.", instance);
+                    self.peers.retain(|peer| peer.instance != instance);
+                }
+                Event::Received(from, message) => {
+                    if self.host {
+                        if let Err(err) = self.validate(from, &message, &pending) {
+                            log::warn!("Rejected a message of instance {}: {}", from, err);
+                            continue;
+                        }
+                    }
+                    if let Message::Spawn { id, .. } = message {
+                        pending.push(id);
+                    }
+                    received.push((from, message));
+                }
+            }
+        }
+        received
+    }
+
+    /// Checks a message received by the host from a client. Clients may
+    /// spawn entities with their own identifiers under known parents, and
+    /// update or despawn known entities.
+    fn validate(&self, from: u32, message: &Message, pending: &[NetId]) -> Result<(), String> {
+        let known = |id: NetId| self.replica(id).is_some() || pending.contains(&id);
+        match message {
+            Message::Welcome { .. } => Err("clients can't welcome instances".to_string()),
+            Message::Spawn {
+                id,
+                parent,
+                textures,
+                ..
+            } => {
+                if (id >> 32) as u32 != from {
+                    Err(format!("entity {:016x} isn't identified by the client", id))
+                } else if known(*id) {
+                    Err(format!("entity {:016x} already exists", id))
+                } else if parent.is_some_and(|parent| !known(parent)) {
+                    Err(format!("unknown parent of entity {:016x}", id))
+                } else if let Some((name, _)) = textures.iter().find(|(n, _)| !is_file_name(n)) {
+                    Err(format!("invalid texture name {:?}", name))
+                } else {
+                    Ok(())
+                }
+            }
+            Message::Update { id, state } => {
+                let t = &state.transform;
+                if !known(*id) {
+                    Err(format!("unknown entity {:016x}", id))
+                } else if !(t.translation.is_finite()
+                    && t.rotation.is_finite()
+                    && t.scale.is_finite())
+                {
+                    Err(format!("invalid transform of entity {:016x}", id))
+                } else {
+                    Ok(())
+                }
+            }
+            Message::Despawn { id } if !known(*id) => Err(format!("unknown entity {:016x}", id)),
+            Message::Despawn { .. } => Ok(()),
+        }
+    }
+
+    /// Writes the mesh files of a spawn message to the directory of the
+    /// session, and returns the path of the obj file.
+    pub fn write_mesh_files(
+        &self,
+        id: NetId,
+        obj: &[u8],
+        mtl: &[u8],
+        textures: &[(SmlString, Vec<u8>)],
+    ) -> io::Result<PathBuf> {
+        let obj_path = self.dir.join(format!("{:016x}.obj", id));
+        for (name, data) in textures {
+            if !is_file_name(name) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid texture name {:?}", name),
+                ));
+            }
+            std::fs::write(self.dir.join(name.as_str()), data)?;
+        }
+        if !mtl.is_empty() {
+            std::fs::write(obj_path.with_extension("mtl"), mtl)?;
+        }
+        std::fs::write(&obj_path, obj)?;
+        Ok(obj_path)
+    }
+
+    /// Returns the node of the replicated parent, or the root node.
+    pub fn parent_node(&self, parent: Option<NetId>) -> NodeIdx {
+        parent
+            .and_then(|id| self.replica(id))
+            .map_or(NodeIdx::root(), |r| r.entity.node)
+    }
+
+    /// Binds the entity spawned locally for a spawn message received from
+    /// the instance `from`. The host relays the message to its other
+    /// clients.
+    pub fn spawned(&mut self, from: u32, entity: Entity, spawn: Message) {
+        if let Message::Spawn { id, .. } = spawn {
+            if self.host {
+                self.broadcast(&spawn, Some(from));
+            }
+            self.replicas.push(Replica {
+                id,
+                entity,
+                spawn,
+                state: None,
+            });
+        }
+    }
+
+    /// Returns the entity of an update received from the instance `from`,
+    /// recording its state so that it isn't sent back. The host relays the
+    /// update to its other clients.
+    pub fn updated(&mut self, from: u32, id: NetId, state: NodeState) -> Option<Entity> {
+        let replica = self.replicas.iter_mut().find(|r| r.id == id)?;
+        replica.state = Some(state);
+        let entity = replica.entity;
+        if self.host {
+            self.broadcast(&Message::Update { id, state }, Some(from));
+        }
+        Some(entity)
+    }
+
+    /// Returns the entity of a despawn received from the instance `from`,
+    /// which is no longer replicated. The host relays the despawn to its
+    /// other clients.
+    pub fn despawned(&mut self, from: u32, id: NetId) -> Option<Entity> {
+        let index = self.replicas.iter().position(|r| r.id == id)?;
+        if self.host {
+            self.broadcast(&Message::Despawn { id }, Some(from));
+        }
+        Some(self.replicas.remove(index).entity)
+    }
+
+    /// Sends the changes of the replicated entities since the last call: the
+    /// states of the nodes that changed and the entities that were removed.
+    pub fn send_changes(&mut self, scene: &Scene) {
+        let mut messages = Vec::new();
+        self.replicas.retain_mut(|replica| {
+            if !scene.world.contains(replica.entity.raw) {
+                messages.push(Message::Despawn { id: replica.id });
+                return false;
+            }
+            let state = NodeState::of(scene, replica.entity);
+            if replica.state != Some(state) {
+                replica.state = Some(state);
+                messages.push(Message::Update {
+                    id: replica.id,
+                    state,
+                });
+            }
+            true
+        });
+        for message in &messages {
+            self.broadcast(message, None);
+        }
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        for peer in &self.peers {
+            let _ = peer.stream.shutdown(Shutdown::Both);
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{Material, TextureType},
+        scene::Command,
+    };
+    use std::time::Instant;
+
+    fn scene() -> Scene {
+        let (sender, receiver) = crossbeam_channel::unbounded::<Command>();
+        Scene::new(sender, receiver)
+    }
+
+    /// Receives the messages of the session until `done` returns true.
+    fn receive_until(
+        session: &mut Session,
+        mut done: impl FnMut(&Session, &[(u32, Message)]) -> bool,
+    ) -> Vec<(u32, Message)> {
+        let start = Instant::now();
+        let mut received = Vec::new();
+        while !done(session, &received) {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            received.extend(session.receive());
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        received
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            Message::Welcome { instance: 3 },
+            Message::Spawn {
+                id: 1 << 32 | 7,
+                parent: None,
+                name: SmlString::from("house"),
+                obj: b"v 0 0 0".to_vec(),
+                mtl: b"map_Kd brick.png".to_vec(),
+                textures: vec![(SmlString::from("brick.png"), vec![1, 2, 3])],
+            },
+            Message::Update {
+                id: 2,
+                state: NodeState {
+                    transform: Transform::from_translation(Vec3::new(1.0, 2.0, 3.0)),
+                    visible: true,
+                    material: Some(1),
+                },
+            },
+            Message::Despawn { id: 2 },
+        ];
+        for message in messages {
+            assert_eq!(Message::decode(&message.encode()).unwrap(), message);
+        }
+        assert!(Message::decode(&[2, 0]).is_err());
+    }
+
+    #[test]
+    fn host_rejects_invalid_messages() {
+        let host = Session::host(0).unwrap();
+        let spawn = |id: NetId, parent: Option<NetId>, texture: &str| Message::Spawn {
+            id,
+            parent,
+            name: SmlString::from("house"),
+            obj: Vec::new(),
+            mtl: Vec::new(),
+            textures: vec![(SmlString::from(texture), Vec::new())],
+        };
+        assert!(host
+            .validate(1, &spawn(1 << 32, None, "a.png"), &[])
+            .is_ok());
+        assert!(host
+            .validate(1, &spawn(2 << 32, None, "a.png"), &[])
+            .is_err());
+        assert!(host
+            .validate(1, &spawn(1 << 32, Some(5), "a.png"), &[])
+            .is_err());
+        assert!(host
+            .validate(1, &spawn(1 << 32 | 1, Some(1 << 32), "a.png"), &[1 << 32])
+            .is_ok());
+        assert!(host
+            .validate(1, &spawn(1 << 32, None, "../a.png"), &[])
+            .is_err());
+        assert!(host
+            .validate(1, &Message::Welcome { instance: 2 }, &[])
+            .is_err());
+        assert!(host
+            .validate(1, &Message::Despawn { id: 1 << 32 }, &[])
+            .is_err());
+        let mut state = NodeState {
+            transform: Transform::identity(),
+            visible: true,
+            material: None,
+        };
+        let update = |state| Message::Update { id: 1 << 32, state };
+        assert!(host.validate(1, &update(state), &[1 << 32]).is_ok());
+        state.transform.translation.x = f32::NAN;
+        assert!(host.validate(1, &update(state), &[1 << 32]).is_err());
+    }
+
+    #[test]
+    fn spawns_and_transforms_replicate() {
+        let mut host = Session::host(0).unwrap();
+        let address = ("127.0.0.1", host.port());
+        let mut a = Session::join(address).unwrap();
+        let mut b = Session::join(address).unwrap();
+        receive_until(&mut host, |host, _| host.peers.len() == 2);
+
+        // Spawn a textured cube on the first client.
+        let texture = a.dir.join("source").join("brick.png");
+        std::fs::create_dir_all(texture.parent().unwrap()).unwrap();
+        std::fs::write(&texture, [1, 2, 3]).unwrap();
+        let mut material = Material::default();
+        material.name = "brick".into();
+        material.textures.insert(TextureType::MapKd, texture);
+        let mut cube = Mesh::cube(1.0);
+        cube.set_material(material);
+        let mut scene_a = scene();
+        let entity_a = scene_a.spawn(NodeIdx::root(), ());
+        a.share_spawn(entity_a, NodeIdx::root(), &cube);
+
+        // The host applies the spawn, then relays it to the second client.
+        let mut scene_host = scene();
+        let received = receive_until(&mut host, |_, received| !received.is_empty());
+        let (from, spawn) = received.into_iter().next().unwrap();
+        assert_eq!(from, a.instance());
+        let Message::Spawn { id, textures, .. } = &spawn else {
+            panic!("expected a spawn, received {:?}", spawn);
+        };
+        let id = *id;
+        assert_eq!(textures.len(), 1);
+        assert_eq!(textures[0].1, [1, 2, 3]);
+        host.spawned(from, scene_host.spawn(NodeIdx::root(), ()), spawn);
+
+        let mut scene_b = scene();
+        let received = receive_until(&mut b, |_, received| !received.is_empty());
+        let (from, spawn) = received.into_iter().next().unwrap();
+        let Message::Spawn {
+            id: id_b,
+            ref obj,
+            ref mtl,
+            ref textures,
+            ..
+        } = spawn
+        else {
+            panic!("expected a spawn, received {:?}", spawn);
+        };
+        assert_eq!(id_b, id);
+        let obj_path = b.write_mesh_files(id, obj, mtl, textures).unwrap();
+        let loaded = Mesh::try_load_from_obj(&obj_path).unwrap();
+        assert_eq!(loaded.triangle_count(), cube.triangle_count());
+        let materials = loaded.materials.unwrap();
+        assert_eq!(
+            std::fs::read(&materials[0].textures[&TextureType::MapKd]).unwrap(),
+            [1, 2, 3]
+        );
+        let entity_b = scene_b.spawn(NodeIdx::root(), ());
+        b.spawned(from, entity_b, spawn);
+
+        // Moving the cube on the first client moves it on the second one.
+        let translation = Vec3::new(1.0, 2.0, 3.0);
+        scene_a.nodes[entity_a.node].transform_mut().translation = translation;
+        a.send_changes(&scene_a);
+        let received = receive_until(&mut host, |_, received| !received.is_empty());
+        for (from, message) in received {
+            if let Message::Update { id, state } = message {
+                host.updated(from, id, state).unwrap();
+            }
+        }
+        let received = receive_until(&mut b, |_, received| {
+            received
+                .iter()
+                .any(|(_, message)| matches!(message, Message::Update { .. }))
+        });
+        for (from, message) in received {
+            if let Message::Update { id, state } = message {
+                assert_eq!(b.updated(from, id, state), Some(entity_b));
+                state.apply(&mut scene_b, entity_b);
+            }
+        }
+        assert_eq!(
+            scene_b.nodes[entity_b.node].transform().translation,
+            translation
+        );
+    }
+}
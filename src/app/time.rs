@@ -0,0 +1,113 @@
+use crate::render::SceneTime;
+
+/// Control of the time of the animations of the scene, slowed down, sped up,
+/// paused or advanced frame by frame, see `PyAppState::set_time_scale`.
+///
+/// The camera controls are not affected and always follow the real time.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeControl {
+    /// Factor applied to the real frame time.
+    scale: f32,
+    paused: bool,
+    /// Frames still to advance while paused, see `step`.
+    steps: u32,
+    /// Duration of the frames advanced while paused.
+    step_duration: f32,
+    /// Time of the scene at the last frame.
+    time: SceneTime,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+            steps: 0,
+            step_duration: Self::DEFAULT_STEP,
+            time: SceneTime::default(),
+        }
+    }
+}
+
+impl TimeControl {
+    /// Default duration in seconds of the frames advanced while paused.
+    pub const DEFAULT_STEP: f32 = 1.0 / 60.0;
+
+    /// Returns the factor applied to the real frame time.
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the factor applied to the real frame time, negative factors
+    /// being clamped to zero.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    /// Returns whether the time is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pauses or resumes the time, dropping the frames still to advance
+    /// when resuming.
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        if !paused {
+            self.steps = 0;
+        }
+    }
+
+    /// Pauses the time and advances it by `frames` frames of `duration`
+    /// seconds, one per frame, regardless of the scale.
+    pub fn step(&mut self, frames: u32, duration: f32) {
+        self.paused = true;
+        self.steps += frames;
+        self.step_duration = duration.max(0.0);
+    }
+
+    /// Returns the time of the scene at the last frame.
+    pub fn time(&self) -> SceneTime {
+        self.time
+    }
+
+    /// Advances the time of the scene by a frame of `dt` real seconds, and
+    /// returns it.
+    pub fn advance(&mut self, dt: f32) -> SceneTime {
+        let delta = if !self.paused {
+            dt * self.scale
+        } else if self.steps > 0 {
+            self.steps -= 1;
+            self.step_duration
+        } else {
+            0.0
+        };
+        self.time = SceneTime {
+            elapsed: self.time.elapsed + delta,
+            delta,
+        };
+        self.time
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paused_time_advances_by_steps() {
+        let mut time = TimeControl::default();
+        time.set_scale(0.5);
+        assert_eq!(time.advance(0.5).delta, 0.25);
+
+        time.step(2, 0.125);
+        assert!(time.is_paused());
+        assert_eq!(time.advance(0.5).delta, 0.125);
+        assert_eq!(time.advance(0.5).delta, 0.125);
+        assert_eq!(time.advance(0.5).delta, 0.0);
+        assert_eq!(time.time().elapsed, 0.5);
+
+        time.set_paused(false);
+        assert_eq!(time.advance(0.5).delta, 0.25);
+    }
+}
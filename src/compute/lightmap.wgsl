@@ -21,7 +21,12 @@ var<storage, read> light_matrices: array<mat4x4<f32>>;
 @group(2) @binding(0)
 var<storage, read> instances: array<Locals>;
 
+// Without push constants, the constants are read from a uniform buffer.
+// #if push_constants
 var<push_constant> pconsts: PConsts;
+// #else
+@group(3) @binding(0) var<uniform> pconsts: PConsts;
+// #fi
 
 @vertex
 fn vs_main(vin: VSInput) -> @builtin(position) vec4<f32> {
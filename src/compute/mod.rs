@@ -1,22 +1,88 @@
-use std::num::NonZeroU64;
+use std::{collections::VecDeque, num::NonZeroU64};
 
-use glam::{Mat3, Mat4, Vec3};
+use glam::{Mat3, Mat4, Quat, Vec3};
 use wgpu::{util::DeviceExt, BindGroupLayoutEntry};
 
 use crate::{
     core::{
+        bvh::Aabb,
         mesh::{MeshBundle, VertexAttribute},
-        FxHashSet,
+        FxHashMap,
     },
     render::{
-        rpass::{LocalsBindGroup, PConstsShadowPass, ShadowPassLocals},
+        rpass::{DrawConstants, LocalsBindGroup, PConstsShadowPass, ShadowPassLocals},
+        util::preprocess_wgsl,
         Renderer,
     },
     scene::{NodeIdx, Scene},
 };
 
+mod viewshed;
+pub use viewshed::*;
+
 pub const MAX_SUN_POSITIONS_NUM: usize = 16;
 
+/// Returns the unit vector pointing from the origin towards the sun.
+///
+/// The sun travels around the Z axis: `hour_angle` is zero at noon and
+/// increases by `TAU` over a day, `inclination` tilts its path away from the
+/// zenith (the latitude of the site).
+pub fn sun_position(hour_angle: f32, inclination: f32) -> Vec3 {
+    let center_pos = Vec3::new(0.0, inclination.cos(), inclination.sin());
+    Mat3::from_rotation_z(hour_angle) * center_pos
+}
+
+/// Number of sun positions the scores are computed for.
+pub const SUN_POSITIONS_NUM: usize = 11;
+
+/// Ground the sun travels over, as the plane of normal `up` at distance
+/// `height` from the origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SunlightGround {
+    pub up: Vec3,
+    pub height: f32,
+}
+
+impl SunlightGround {
+    /// Returns the unit vector pointing towards the sun at the given
+    /// position, in `0..SUN_POSITIONS_NUM` from morning to evening.
+    pub fn sun_direction(&self, index: usize) -> Vec3 {
+        // Sun positions are defined around the Y axis.
+        let rotation = Quat::from_rotation_arc(Vec3::Y, self.up);
+        let angle = (index as f32 - 5.0) * std::f32::consts::FRAC_PI_6 * 0.5;
+        rotation * sun_position(angle, std::f32::consts::FRAC_PI_8)
+    }
+}
+
+impl Default for SunlightGround {
+    fn default() -> Self {
+        Self {
+            up: Vec3::Y,
+            height: 0.0,
+        }
+    }
+}
+
+/// Sunlight scores computed on the GPU, waiting to be read back.
+struct PendingScores {
+    /// Id of the computation.
+    id: u64,
+    /// Buffer the scores are copied to.
+    readback: wgpu::Buffer,
+    /// Receives the result of the mapping of the buffer.
+    mapped: flume::Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+/// Region of the scene covered by the occlusion maps of the last scores.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SunlightCoverage {
+    /// Bounds of the meshes, down to the ground.
+    pub bounds: Aabb,
+    /// Largest size in world units of a pixel of the occlusion maps, over
+    /// all the sun positions.
+    pub texel_size: f32,
+}
+
 pub struct SunlightScore {
     /// The occlusion map for each of the 11 sun positions.
     light_maps: wgpu::Texture,
@@ -24,6 +90,9 @@ pub struct SunlightScore {
     rpass_output: wgpu::Texture,
     /// Pipeline generating the occlusion map.
     rpass_pipeline: wgpu::RenderPipeline,
+    /// Instance base index and sun position index of the occlusion map
+    /// draws.
+    rpass_constants: DrawConstants,
     /// The bind group containing the occlusion map used for rendering.
     rpass_light_maps_bind_group: wgpu::BindGroup,
     /// The buffer containing the light space matrices.
@@ -42,6 +111,14 @@ pub struct SunlightScore {
     cpass_light_maps_bind_group: wgpu::BindGroup,
     /// Scores for each sun position.
     scores: [f32; MAX_SUN_POSITIONS_NUM],
+    /// Ground the sun travels over.
+    ground: SunlightGround,
+    /// Region covered by the occlusion maps.
+    coverage: SunlightCoverage,
+    /// Computations waiting for their scores to be read back, oldest first.
+    pending: VecDeque<PendingScores>,
+    /// Id of the last computation started.
+    last_id: u64,
     #[cfg(all(debug_assertions, feature = "debug-sunlight-map"))]
     pub storage_buffer: wgpu::Buffer,
     #[cfg(all(debug_assertions, feature = "debug-sunlight-map"))]
@@ -54,6 +131,11 @@ impl SunlightScore {
     pub const LIGHT_MAP_LAYER_PIXEL_COUNT: u32 =
         Self::LIGHT_MAP_LAYER_COLS * Self::LIGHT_MAP_LAYER_ROWS;
     pub const LIGHT_MAP_LAYER_SIZE: u32 = Self::LIGHT_MAP_LAYER_PIXEL_COUNT * 4;
+    /// Region covered when there are no meshes.
+    const DEFAULT_BOUNDS: Aabb = Aabb {
+        min: Vec3::splat(-40.0),
+        max: Vec3::splat(40.0),
+    };
 
     /// Creates a new sunlight score compute.
     pub fn new(device: &wgpu::Device) -> Self {
@@ -88,26 +170,14 @@ impl SunlightScore {
             }],
         });
 
-        const ORTHO_NEAR: f32 = -80.0;
-        const ORTHO_FAR: f32 = 80.0;
-        const ORTHO_H: f32 = 40.0;
-        const ORTHO_W: f32 = 40.0;
-        // Sun's light space matrices at each of the 11 positions.
-        let mut light_matrices = [[0f32; 16]; 16];
-        let inclination = std::f32::consts::FRAC_PI_8;
-        let center_pos = Vec3::new(0.0, inclination.cos(), inclination.sin());
-        for i in 0..11 {
-            let angle = (i as f32 - 5.0) * std::f32::consts::FRAC_PI_6 * 0.5;
-            let pos = Mat3::from_rotation_z(angle) * center_pos;
-            light_matrices[i] = (Mat4::orthographic_rh(
-                -ORTHO_W, ORTHO_W, -ORTHO_H, ORTHO_H, ORTHO_NEAR, ORTHO_FAR,
-            ) * Mat4::look_at_rh(pos, Vec3::ZERO, Vec3::Y))
-            .to_cols_array();
-        }
+        let ground = SunlightGround::default();
+        let (light_matrices, coverage) = Self::fit_light_matrices(&ground, Self::DEFAULT_BOUNDS);
         let rpass_light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("light_matrices_buffer"),
             contents: bytemuck::cast_slice(&light_matrices),
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST,
         });
         let rpass_light_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -259,23 +329,33 @@ impl SunlightScore {
             cache: None,
         });
 
+        let rpass_constants = DrawConstants::new(
+            device,
+            "sunlight_score_rpass_constants",
+            wgpu::ShaderStages::VERTEX_FRAGMENT,
+            PConstsShadowPass::SIZE as u32,
+        );
+        let mut conditions = FxHashMap::default();
+        conditions.insert("push_constants", rpass_constants.is_push_constants());
         let rpass_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("sunlight_score_rpass_shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("lightmap.wgsl").into()),
+            source: wgpu::ShaderSource::Wgsl(
+                preprocess_wgsl(include_str!("lightmap.wgsl"), &conditions).into(),
+            ),
         });
 
+        let mut bind_group_layouts = vec![
+            &rpass_light_maps_bg_layout,
+            &rpass_light_bind_group_layout,
+            &rpass_locals_bind_group.layout,
+        ];
+        bind_group_layouts.extend(rpass_constants.bind_group_layout());
         let rpass_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("render_pipeline_layout"),
-                bind_group_layouts: &[
-                    &rpass_light_maps_bg_layout,
-                    &rpass_light_bind_group_layout,
-                    &rpass_locals_bind_group.layout,
-                ],
-                push_constant_ranges: &[wgpu::PushConstantRange {
-                    stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    range: 0..PConstsShadowPass::SIZE as u32,
-                }],
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &rpass_constants
+                    .push_constant_ranges(wgpu::ShaderStages::VERTEX_FRAGMENT),
             });
 
         let rpass_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
@@ -325,6 +405,7 @@ impl SunlightScore {
         Self {
             light_maps,
             rpass_pipeline,
+            rpass_constants,
             rpass_light_maps_bind_group,
             cpass_scores_buffer,
             cpass_scores_bind_group,
@@ -339,7 +420,70 @@ impl SunlightScore {
             cpass_light_maps_bind_group,
             cpass_pipeline,
             scores: [0.0; MAX_SUN_POSITIONS_NUM],
+            ground,
+            coverage,
+            pending: VecDeque::new(),
+            last_id: 0,
+        }
+    }
+
+    /// Returns the ground the sun travels over.
+    pub fn ground(&self) -> SunlightGround {
+        self.ground
+    }
+
+    /// Sets the ground the sun travels over, used by the next scores.
+    pub fn set_ground(&mut self, ground: SunlightGround) {
+        self.ground = SunlightGround {
+            up: ground.up.try_normalize().unwrap_or(Vec3::Y),
+            height: ground.height,
+        };
+    }
+
+    /// Returns the region of the scene covered by the last scores.
+    pub fn coverage(&self) -> SunlightCoverage {
+        self.coverage
+    }
+
+    /// Computes the light space matrices of the sun positions, each fitting
+    /// the orthographic projection to the bounds of the scene seen from the
+    /// sun.
+    ///
+    /// The bounds are extended down to the ground so that the sun travels
+    /// above the middle of the ground under the scene.
+    fn fit_light_matrices(
+        ground: &SunlightGround,
+        bounds: Aabb,
+    ) -> ([[f32; 16]; MAX_SUN_POSITIONS_NUM], SunlightCoverage) {
+        let center = bounds.center();
+        let bounds = bounds.grow(center - ground.up * (ground.up.dot(center) - ground.height));
+        let center = bounds.center();
+        let radius = (bounds.extent().length() * 0.5).max(f32::EPSILON);
+        let mut light_matrices = [[0f32; 16]; MAX_SUN_POSITIONS_NUM];
+        let mut texel_size = 0.0f32;
+        for (i, matrix) in light_matrices
+            .iter_mut()
+            .enumerate()
+            .take(SUN_POSITIONS_NUM)
+        {
+            let dir = ground.sun_direction(i);
+            let up = if dir.dot(ground.up).abs() > 0.999 {
+                ground.up.any_orthonormal_vector()
+            } else {
+                ground.up
+            };
+            let view = Mat4::look_at_rh(center + dir * radius, center, up);
+            // The camera looks down -Z in view space.
+            let fit = bounds.transform(&view);
+            let proj = Mat4::orthographic_rh(
+                fit.min.x, fit.max.x, fit.min.y, fit.max.y, -fit.max.z, -fit.min.z,
+            );
+            *matrix = (proj * view).to_cols_array();
+            texel_size = texel_size
+                .max(fit.extent().x / Self::LIGHT_MAP_LAYER_COLS as f32)
+                .max(fit.extent().y / Self::LIGHT_MAP_LAYER_ROWS as f32);
         }
+        (light_matrices, SunlightCoverage { bounds, texel_size })
     }
 
     #[cfg(all(debug_assertions, feature = "debug-sunlight-map"))]
@@ -413,12 +557,16 @@ impl SunlightScore {
     {
         profiling::scope!("render_occlusion_maps");
         log::debug!("Render sunlight occlusion maps");
-        let mut unique_bundles = FxHashSet::default();
+        // The meshes are sorted, so that they are drawn in the same order in
+        // every run.
+        let mut unique_bundles = Vec::new();
         let mut n_inst = 0;
         for (bundle, _) in mesh_bundles {
-            unique_bundles.insert(bundle);
+            unique_bundles.push(bundle);
             n_inst += 1;
         }
+        unique_bundles.sort_unstable();
+        unique_bundles.dedup();
 
         log::debug!(
             "Rendering occlusion maps of {} instances of {} visible meshes",
@@ -442,11 +590,12 @@ impl SunlightScore {
                 .get(bundle)
                 .expect("Unreachable: the bundle is in the unique bundles list");
             offsets_and_inst_counts[i].0 = offset;
-            for (j, node_idx) in instances.iter().enumerate() {
+            for node_idx in instances {
                 let node = &scene.nodes[*node_idx];
                 if !node.is_visible() {
                     continue;
                 }
+                let j = offsets_and_inst_counts[i].1 as usize;
                 offsets_and_inst_counts[i].1 += 1;
                 locals[offset as usize + j] = ShadowPassLocals {
                     model: scene.nodes.world(*node_idx).to_mat4().to_cols_array(),
@@ -505,13 +654,8 @@ impl SunlightScore {
             rpass.set_bind_group(2, &self.rpass_locals_bind_group, &[]);
 
             // Rendering the occlusion maps for each sun position.
-            (0..11).into_iter().for_each(|i| {
+            (0..SUN_POSITIONS_NUM).for_each(|i| {
                 profiling::scope!("render_occlusion_map_rpass");
-                rpass.set_push_constants(
-                    wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    4,
-                    bytemuck::bytes_of(&(i as u32)),
-                );
 
                 for (bundle, (offset, inst_count)) in
                     unique_bundles.iter().zip(offsets_and_inst_counts.iter())
@@ -524,11 +668,15 @@ impl SunlightScore {
                             {
                                 rpass.set_vertex_buffer(0, mesh_buffer.slice(pos_range.clone()));
                             }
-                            // Set push constants - instance base index.
-                            rpass.set_push_constants(
+                            // Set the constants - instance base index and
+                            // sun position index.
+                            self.rpass_constants.set(
+                                device,
+                                queue,
+                                &mut rpass,
+                                3,
                                 wgpu::ShaderStages::VERTEX_FRAGMENT,
-                                0,
-                                bytemuck::bytes_of(offset),
+                                &[*offset, i as u32],
                             );
 
                             match mesh.index_format {
@@ -656,6 +804,71 @@ impl SunlightScore {
         self.cpass_scores_buffer.unmap();
     }
 
+    /// Fits the projections of the sun to the meshes, then submits the passes
+    /// rendering their occlusion maps and computing the scores.
+    ///
+    /// If given, the scores are copied to `readback` once computed.
+    fn submit<'a, M>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        renderer: &Renderer,
+        meshes: M,
+        readback: Option<&wgpu::Buffer>,
+    ) where
+        M: Iterator<Item = (&'a MeshBundle, &'a NodeIdx)>,
+    {
+        let meshes = meshes.collect::<Vec<_>>();
+        let bounds = meshes
+            .iter()
+            .filter_map(|(bundle, node)| {
+                let mesh = renderer.meshes.get(bundle.mesh)?;
+                Some(Aabb::from(mesh.bounds).transform(&scene.nodes.world(**node).to_mat4()))
+            })
+            .fold(Aabb::EMPTY, |bounds, b| bounds.union(&b));
+        let bounds = if bounds.is_empty() {
+            Self::DEFAULT_BOUNDS
+        } else {
+            bounds
+        };
+        let (light_matrices, coverage) = Self::fit_light_matrices(&self.ground, bounds);
+        self.coverage = coverage;
+        queue.write_buffer(
+            &self.rpass_light_buffer,
+            0,
+            bytemuck::cast_slice(&light_matrices),
+        );
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("occlusion_map_encoder"),
+        });
+        self.render_occlusion_maps(
+            device,
+            queue,
+            &mut encoder,
+            scene,
+            renderer,
+            meshes.into_iter(),
+        );
+        self.compute_sunlight_scores(&mut encoder);
+        if let Some(readback) = readback {
+            encoder.copy_buffer_to_buffer(
+                &self.cpass_scores_buffer,
+                0,
+                readback,
+                0,
+                readback.size(),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Computes the sunlight scores of the meshes, for each sun position.
+    /// Blocks until the scores are read back from the GPU.
+    ///
+    /// The orthographic projections of the sun are fitted to the bounds of
+    /// the meshes, or cover 40 units around the origin if there is none.
     pub fn compute<'a, M>(
         &mut self,
         device: &wgpu::Device,
@@ -667,14 +880,7 @@ impl SunlightScore {
     where
         M: Iterator<Item = (&'a MeshBundle, &'a NodeIdx)>,
     {
-        {
-            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("occlusion_map_encoder"),
-            });
-            self.render_occlusion_maps(device, queue, &mut encoder, scene, renderer, meshes);
-            self.compute_sunlight_scores(&mut encoder);
-            queue.submit(std::iter::once(encoder.finish()));
-        }
+        self.submit(device, queue, scene, renderer, meshes, None);
 
         #[cfg(all(debug_assertions, feature = "debug-sunlight-map"))]
         self.write_sunlight_maps(device);
@@ -682,4 +888,76 @@ impl SunlightScore {
 
         return self.scores.to_vec();
     }
+
+    /// Starts computing the sunlight scores of the meshes without waiting
+    /// for the GPU, see [`SunlightScore::compute`].
+    ///
+    /// Returns the id of the computation. Its scores are returned by
+    /// [`SunlightScore::poll_scores`] once read back from the GPU.
+    pub fn compute_async<'a, M>(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        scene: &Scene,
+        renderer: &Renderer,
+        meshes: M,
+    ) -> u64
+    where
+        M: Iterator<Item = (&'a MeshBundle, &'a NodeIdx)>,
+    {
+        // Each computation reads back its scores from its own buffer, so
+        // that the scores buffer can be written while others are mapped.
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sunlight_scores_readback"),
+            size: std::mem::size_of::<[f32; MAX_SUN_POSITIONS_NUM]>() as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        self.submit(device, queue, scene, renderer, meshes, Some(&readback));
+        let (sender, mapped) = flume::bounded(1);
+        readback.slice(..).map_async(wgpu::MapMode::Read, move |r| {
+            let _ = sender.send(r);
+        });
+        self.last_id += 1;
+        self.pending.push_back(PendingScores {
+            id: self.last_id,
+            readback,
+            mapped,
+        });
+        self.last_id
+    }
+
+    /// Returns the id and the scores of the computations started by
+    /// [`SunlightScore::compute_async`] which have been read back from the
+    /// GPU since the last call, in the order they were started. Doesn't
+    /// block.
+    pub fn poll_scores(&mut self, device: &wgpu::Device) -> Vec<(u64, Vec<f32>)> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        device.poll(wgpu::Maintain::Poll);
+        let mut ready = Vec::new();
+        while let Some(result) = self
+            .pending
+            .front()
+            .and_then(|pending| pending.mapped.try_recv().ok())
+        {
+            let pending = self.pending.pop_front().unwrap();
+            match result {
+                Ok(()) => {
+                    {
+                        let buffer_view = pending.readback.slice(..).get_mapped_range();
+                        self.scores
+                            .copy_from_slice(bytemuck::cast_slice(&buffer_view));
+                    }
+                    pending.readback.unmap();
+                    ready.push((pending.id, self.scores.to_vec()));
+                }
+                Err(err) => {
+                    log::error!("Failed to read back the sunlight scores: {}", err);
+                }
+            }
+        }
+        ready
+    }
 }
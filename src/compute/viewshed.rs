@@ -0,0 +1,247 @@
+//! Viewshed analysis: visibility of target meshes from an observer.
+
+use crate::{
+    core::{
+        bvh::{Bvh, Ray, TriangleBvh},
+        mesh::MeshBundle,
+        FxHashMap,
+    },
+    render::Renderer,
+    scene::{Baked, Entity, NodeIdx, Scene},
+};
+use glam::{Mat4, Vec3};
+use legion::{component, IntoQuery};
+use std::sync::Arc;
+
+/// Visibility of a target from the observer of a viewshed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TargetVisibility {
+    pub entity: Entity,
+    /// Fraction of the target seen by the observer, between 0 when it is
+    /// hidden by the other meshes and 1 when nothing is in front of it.
+    pub visibility: f32,
+    /// Solid angle in steradians of the visible part of the target.
+    pub solid_angle: f32,
+}
+
+/// Mesh instance the rays of the viewshed are cast against.
+struct ViewshedInstance {
+    bvh: Arc<TriangleBvh>,
+    inv_model: Mat4,
+    /// Index of the target the instance belongs to, if any.
+    target: Option<usize>,
+}
+
+impl ViewshedInstance {
+    /// Returns the distance along the ray to the instance, if it is hit
+    /// closer than `max_t`. The direction of the ray must be normalized.
+    fn intersect(&self, ray: &Ray, max_t: f32) -> Option<f32> {
+        // The local direction is not normalized so that distances along the
+        // local ray are the same as in world space.
+        let local_ray = Ray::new(
+            self.inv_model.transform_point3(ray.origin),
+            self.inv_model.transform_vector3(ray.direction),
+        );
+        self.bvh.intersect(&local_ray, max_t).map(|hit| hit.t)
+    }
+}
+
+/// Viewshed of an observer: the meshes are seen through the texels of a
+/// cube map around the observer, each texel casting a ray through its
+/// center.
+///
+/// The visibility of a target is the solid angle of the texels seeing it
+/// first, divided by the solid angle of the texels which would see it if
+/// there were no other meshes.
+pub struct Viewshed {
+    /// All the visible meshes, occluding each other.
+    instances: Bvh<ViewshedInstance>,
+    /// The meshes of the targets only.
+    targets: Bvh<ViewshedInstance>,
+    entities: Vec<Entity>,
+}
+
+impl Viewshed {
+    /// Takes a snapshot of the visible meshes of the scene. The meshes of the
+    /// targets are the meshes of their entities and of their descendants.
+    pub fn new(scene: &Scene, renderer: &mut Renderer, targets: &[Entity]) -> Self {
+        profiling::scope!("Viewshed::new");
+        let target_nodes = targets
+            .iter()
+            .enumerate()
+            .map(|(i, entity)| (entity.node, i))
+            .collect::<FxHashMap<_, _>>();
+        let target_of = |mut node: NodeIdx| loop {
+            if let Some(target) = target_nodes.get(&node) {
+                return Some(*target);
+            }
+            node = scene.nodes[node].parent?;
+        };
+
+        let mut instances = Vec::new();
+        let mut target_instances = Vec::new();
+        let mut query = <(&MeshBundle, &NodeIdx)>::query().filter(!component::<Baked>());
+        for (mesh, node) in query.iter(&scene.world) {
+            if !scene.nodes[*node].is_visible() {
+                continue;
+            }
+            let Some(bvh) = renderer.mesh_bvh(mesh.mesh) else {
+                continue;
+            };
+            let model = scene.nodes.world(*node).to_mat4();
+            let bounds = bvh.bounds().transform(&model);
+            let target = target_of(*node);
+            let instance = || ViewshedInstance {
+                bvh: bvh.clone(),
+                inv_model: model.inverse(),
+                target,
+            };
+            if target.is_some() {
+                target_instances.push((instance(), bounds));
+            }
+            instances.push((instance(), bounds));
+        }
+
+        Self {
+            instances: Bvh::new(instances),
+            targets: Bvh::new(target_instances),
+            entities: targets.to_vec(),
+        }
+    }
+
+    /// Computes the visibility of the targets from `observer`, through a
+    /// cube map of `resolution` x `resolution` texels per face.
+    ///
+    /// If `up` is given, only the hemisphere above the observer is seen, as
+    /// through a hemicube.
+    pub fn compute(
+        &self,
+        observer: Vec3,
+        resolution: u32,
+        up: Option<Vec3>,
+    ) -> Vec<TargetVisibility> {
+        profiling::scope!("Viewshed::compute");
+        let n_targets = self.entities.len();
+        let resolution = resolution.max(1);
+        let up = up.and_then(|up| up.try_normalize());
+        // Rows of the six faces, traced in parallel.
+        let rows = (0..6u32)
+            .flat_map(|face| (0..resolution).map(move |row| (face, row)))
+            .collect::<Vec<_>>();
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = rows.len().div_ceil(threads).max(1);
+        // Solid angles seeing each target, as (first, unoccluded).
+        let solid_angles = std::thread::scope(|s| {
+            let handles = rows
+                .chunks(rows_per_thread)
+                .map(|chunk| {
+                    s.spawn(move || {
+                        let mut solid_angles = vec![(0.0f32, 0.0f32); n_targets];
+                        for (face, row) in chunk {
+                            for col in 0..resolution {
+                                let (direction, solid_angle) =
+                                    cube_texel(*face, (col, *row), resolution);
+                                if up.is_some_and(|up| direction.dot(up) < 0.0) {
+                                    continue;
+                                }
+                                self.trace(
+                                    &Ray::new(observer, direction),
+                                    solid_angle,
+                                    &mut solid_angles,
+                                );
+                            }
+                        }
+                        solid_angles
+                    })
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .fold(vec![(0.0f32, 0.0f32); n_targets], |mut acc, angles| {
+                    for (acc, (first, unoccluded)) in acc.iter_mut().zip(angles) {
+                        acc.0 += first;
+                        acc.1 += unoccluded;
+                    }
+                    acc
+                })
+        });
+
+        self.entities
+            .iter()
+            .zip(solid_angles)
+            .map(|(entity, (first, unoccluded))| TargetVisibility {
+                entity: *entity,
+                visibility: if unoccluded > 0.0 {
+                    first / unoccluded
+                } else {
+                    0.0
+                },
+                solid_angle: first,
+            })
+            .collect()
+    }
+
+    /// Adds the solid angle of the ray to the target it sees first, and to
+    /// every target it goes through.
+    fn trace(&self, ray: &Ray, solid_angle: f32, solid_angles: &mut [(f32, f32)]) {
+        let first = self
+            .instances
+            .intersect(ray, f32::INFINITY, |instance, max_t| {
+                Some((instance.intersect(ray, max_t)?, instance.target))
+            });
+        if let Some((_, Some(target))) = first {
+            solid_angles[target].0 += solid_angle;
+        }
+        // Finds all the targets by never shrinking the search distance.
+        let mut seen = Vec::new();
+        self.targets
+            .intersect(ray, f32::INFINITY, |instance, max_t| {
+                if let Some(target) = instance.target {
+                    if !seen.contains(&target) && instance.intersect(ray, max_t).is_some() {
+                        seen.push(target);
+                    }
+                }
+                None::<(f32, ())>
+            });
+        for target in seen {
+            solid_angles[target].1 += solid_angle;
+        }
+    }
+}
+
+/// Returns the direction through the center of a texel of a cube map face,
+/// and the solid angle of the texel.
+fn cube_texel(face: u32, (col, row): (u32, u32), resolution: u32) -> (Vec3, f32) {
+    let texel = 2.0 / resolution as f32;
+    let u = (col as f32 + 0.5) * texel - 1.0;
+    let v = (row as f32 + 0.5) * texel - 1.0;
+    let direction = match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    };
+    let d2 = 1.0 + u * u + v * v;
+    (direction / d2.sqrt(), texel * texel / (d2 * d2.sqrt()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cube_texels_cover_the_sphere() {
+        let resolution = 32;
+        let total = (0..6)
+            .flat_map(|face| {
+                (0..resolution).flat_map(move |row| {
+                    (0..resolution).map(move |col| cube_texel(face, (col, row), resolution).1)
+                })
+            })
+            .sum::<f32>();
+        assert!((total - 4.0 * std::f32::consts::PI).abs() < 0.01);
+    }
+}
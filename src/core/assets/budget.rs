@@ -0,0 +1,229 @@
+use crate::core::{assets::Handle, texture::Texture, FxHashMap};
+
+/// Memory statistics of the textures kept on the GPU.
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextureMemoryStats {
+    /// Maximum number of bytes of the textures, unlimited if `None`.
+    #[pyo3(get)]
+    pub budget: Option<u64>,
+    /// Number of bytes of the textures on the GPU.
+    #[pyo3(get)]
+    pub resident: u64,
+    /// Number of textures on the GPU.
+    #[pyo3(get)]
+    pub resident_count: usize,
+    /// Number of textures evicted from the GPU, uploaded again when used.
+    #[pyo3(get)]
+    pub evicted_count: usize,
+    /// Number of textures evicted during the last frame.
+    #[pyo3(get)]
+    pub evictions: usize,
+    /// Number of evicted textures uploaded again during the last frame.
+    #[pyo3(get)]
+    pub reloads: usize,
+}
+
+/// Residency of a tracked texture.
+#[derive(Debug, Clone, Copy)]
+struct Residency {
+    /// Size of the texture in bytes.
+    size: u64,
+    /// Frame in which the texture was last used.
+    last_used: u64,
+    /// Whether the texture is on the GPU.
+    resident: bool,
+    /// Whether the texture can be evicted, i.e. it can be loaded again.
+    evictable: bool,
+}
+
+/// Tracks the GPU memory of the textures and chooses the least recently used
+/// ones to evict when their total size exceeds a budget.
+///
+/// Textures used during the current frame are never evicted, so the budget
+/// may be exceeded if the visible textures don't fit in it.
+pub struct TextureBudget {
+    /// Maximum number of bytes of the resident textures, unlimited if `None`.
+    limit: Option<u64>,
+    /// Current frame, incremented by `begin_frame`.
+    frame: u64,
+    /// Number of bytes of the resident textures.
+    resident: u64,
+    entries: FxHashMap<Handle<Texture>, Residency>,
+    evictions: usize,
+    reloads: usize,
+}
+
+impl Default for TextureBudget {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl TextureBudget {
+    /// Creates a budget of the given number of bytes, unlimited if `None`.
+    pub fn new(limit: Option<u64>) -> Self {
+        Self {
+            limit,
+            frame: 0,
+            resident: 0,
+            entries: FxHashMap::default(),
+            evictions: 0,
+            reloads: 0,
+        }
+    }
+
+    /// Sets the maximum number of bytes of the resident textures, unlimited
+    /// if `None`.
+    pub fn set_limit(&mut self, limit: Option<u64>) {
+        self.limit = limit;
+    }
+
+    /// Starts a new frame, resetting the counts of evictions and reloads.
+    pub fn begin_frame(&mut self) {
+        self.frame += 1;
+        self.evictions = 0;
+        self.reloads = 0;
+    }
+
+    /// Tracks a texture uploaded to the GPU, or updates its size if it has
+    /// been uploaded again.
+    pub fn track(&mut self, handle: Handle<Texture>, size: u64, evictable: bool) {
+        let frame = self.frame;
+        let entry = self.entries.entry(handle).or_insert(Residency {
+            size: 0,
+            last_used: frame,
+            resident: false,
+            evictable,
+        });
+        if entry.resident {
+            self.resident -= entry.size;
+        } else if entry.size != 0 {
+            // An evicted texture uploaded again.
+            self.reloads += 1;
+        }
+        self.resident += size;
+        *entry = Residency {
+            size,
+            last_used: frame,
+            resident: true,
+            evictable,
+        };
+    }
+
+    /// Marks the texture as used during the current frame. Returns `false`
+    /// if the texture is evicted and must be uploaded again.
+    pub fn touch(&mut self, handle: Handle<Texture>) -> bool {
+        match self.entries.get_mut(&handle) {
+            Some(entry) => {
+                entry.last_used = self.frame;
+                entry.resident
+            }
+            None => true,
+        }
+    }
+
+    /// Returns whether the texture is on the GPU. Untracked textures are.
+    pub fn is_resident(&self, handle: Handle<Texture>) -> bool {
+        self.entries
+            .get(&handle)
+            .map_or(true, |entry| entry.resident)
+    }
+
+    /// Marks the least recently used textures as evicted until the resident
+    /// ones fit in the budget, and returns them to be released.
+    pub fn evict(&mut self) -> Vec<Handle<Texture>> {
+        let Some(limit) = self.limit else {
+            return Vec::new();
+        };
+        if self.resident <= limit {
+            return Vec::new();
+        }
+        let mut candidates = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.resident && entry.evictable && entry.last_used < self.frame)
+            .map(|(handle, entry)| (entry.last_used, *handle))
+            .collect::<Vec<_>>();
+        candidates.sort_unstable();
+
+        let mut evicted = Vec::new();
+        for (_, handle) in candidates {
+            if self.resident <= limit {
+                break;
+            }
+            let entry = self.entries.get_mut(&handle).unwrap();
+            entry.resident = false;
+            self.resident -= entry.size;
+            evicted.push(handle);
+        }
+        self.evictions += evicted.len();
+        evicted
+    }
+
+    /// Stops tracking all the textures.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.resident = 0;
+    }
+
+    /// Returns the memory statistics of the tracked textures.
+    pub fn stats(&self) -> TextureMemoryStats {
+        let resident_count = self.entries.values().filter(|e| e.resident).count();
+        TextureMemoryStats {
+            budget: self.limit,
+            resident: self.resident,
+            resident_count,
+            evicted_count: self.entries.len() - resident_count,
+            evictions: self.evictions,
+            reloads: self.reloads,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::assets::HandleAllocator;
+
+    #[test]
+    fn least_recently_used_textures_are_evicted() {
+        let allocator = HandleAllocator::<Texture>::new();
+        let handles = (0..3).map(|_| allocator.reserve()).collect::<Vec<_>>();
+        let mut budget = TextureBudget::new(Some(250));
+        for handle in &handles {
+            budget.track(*handle, 100, true);
+        }
+
+        // The first texture is used last, the second one is the oldest.
+        budget.begin_frame();
+        budget.touch(handles[2]);
+        budget.begin_frame();
+        budget.touch(handles[0]);
+        assert_eq!(budget.evict(), vec![handles[1]]);
+        assert!(!budget.is_resident(handles[1]));
+        assert_eq!(budget.stats().resident, 200);
+
+        // Uploading it again evicts the next least recently used one.
+        budget.begin_frame();
+        assert!(!budget.touch(handles[1]));
+        budget.track(handles[1], 100, true);
+        assert_eq!(budget.evict(), vec![handles[2]]);
+        let stats = budget.stats();
+        assert_eq!((stats.resident_count, stats.evicted_count), (2, 1));
+        assert_eq!((stats.evictions, stats.reloads), (1, 1));
+    }
+
+    #[test]
+    fn used_and_pinned_textures_are_kept() {
+        let allocator = HandleAllocator::<Texture>::new();
+        let (pinned, used) = (allocator.reserve(), allocator.reserve());
+        let mut budget = TextureBudget::new(Some(10));
+        budget.track(pinned, 100, false);
+        budget.track(used, 100, true);
+        budget.begin_frame();
+        budget.touch(used);
+        assert!(budget.evict().is_empty());
+        assert_eq!(budget.stats().resident, 200);
+    }
+}
@@ -0,0 +1,455 @@
+//! Loading of block-compressed textures from KTX2 and DDS containers.
+//!
+//! KTX2 levels supercompressed with Zstandard are decompressed, and UASTC
+//! textures of Basis Universal are transcoded to BC7 with the `basis`
+//! feature.
+
+use crate::core::{texture::Texture, SmlString};
+use std::borrow::Cow;
+
+/// Identifier at the start of KTX2 files.
+const KTX2_MAGIC: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+/// Identifier at the start of DDS files.
+const DDS_MAGIC: [u8; 4] = *b"DDS ";
+
+/// Supercompression schemes of the KTX2 levels.
+const KTX2_SUPERCOMPRESSION_NONE: u32 = 0;
+const KTX2_SUPERCOMPRESSION_BASISLZ: u32 = 1;
+const KTX2_SUPERCOMPRESSION_ZSTD: u32 = 2;
+
+/// Color model of the KTX2 data format descriptor of UASTC textures.
+const KDF_MODEL_UASTC: u8 = 166;
+
+/// Block compression formats supported by the loader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BcFormat {
+    Bc1,
+    Bc3,
+    Bc5,
+    Bc7,
+}
+
+impl BcFormat {
+    /// Size in bytes of a block of 4x4 texels.
+    pub fn block_size(self) -> usize {
+        match self {
+            BcFormat::Bc1 => 8,
+            BcFormat::Bc3 | BcFormat::Bc5 | BcFormat::Bc7 => 16,
+        }
+    }
+
+    /// Returns the texture format of the compressed data.
+    pub fn texture_format(self, srgb: bool) -> wgpu::TextureFormat {
+        match (self, srgb) {
+            (BcFormat::Bc1, false) => wgpu::TextureFormat::Bc1RgbaUnorm,
+            (BcFormat::Bc1, true) => wgpu::TextureFormat::Bc1RgbaUnormSrgb,
+            (BcFormat::Bc3, false) => wgpu::TextureFormat::Bc3RgbaUnorm,
+            (BcFormat::Bc3, true) => wgpu::TextureFormat::Bc3RgbaUnormSrgb,
+            (BcFormat::Bc5, _) => wgpu::TextureFormat::Bc5RgUnorm,
+            (BcFormat::Bc7, false) => wgpu::TextureFormat::Bc7RgbaUnorm,
+            (BcFormat::Bc7, true) => wgpu::TextureFormat::Bc7RgbaUnormSrgb,
+        }
+    }
+
+    /// Decodes a compressed image into BGRA8 pixels.
+    fn decode(self, data: &[u8], width: u32, height: u32) -> Result<Vec<u32>, String> {
+        let (w, h) = (width as usize, height as usize);
+        let mut pixels = vec![0u32; w * h];
+        let decoded = match self {
+            BcFormat::Bc1 => texture2ddecoder::decode_bc1(data, w, h, &mut pixels),
+            BcFormat::Bc3 => texture2ddecoder::decode_bc3(data, w, h, &mut pixels),
+            BcFormat::Bc5 => texture2ddecoder::decode_bc5(data, w, h, &mut pixels),
+            BcFormat::Bc7 => texture2ddecoder::decode_bc7(data, w, h, &mut pixels),
+        };
+        decoded.map(|_| pixels).map_err(|err| err.to_string())
+    }
+}
+
+/// Block-compressed image with its mip chain.
+#[derive(Debug)]
+pub struct CompressedImage<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub format: BcFormat,
+    /// Whether the texels are stored in sRGB color space. `None` if the
+    /// container doesn't tell.
+    pub srgb: Option<bool>,
+    /// Data of each mip level, starting from the largest, borrowed from the
+    /// file unless it had to be decompressed or transcoded.
+    pub levels: Vec<Cow<'a, [u8]>>,
+}
+
+impl<'a> CompressedImage<'a> {
+    /// Parses a KTX2 or DDS file, returns `None` if the bytes are not one of
+    /// these containers.
+    pub fn parse(bytes: &'a [u8]) -> Option<Result<Self, String>> {
+        if bytes.starts_with(&KTX2_MAGIC) {
+            Some(Self::parse_ktx2(bytes))
+        } else if bytes.starts_with(&DDS_MAGIC) {
+            Some(Self::parse_dds(bytes))
+        } else {
+            None
+        }
+    }
+
+    fn parse_ktx2(bytes: &'a [u8]) -> Result<Self, String> {
+        let vk_format = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 20)?;
+        let height = read_u32(bytes, 24)?.max(1);
+        let level_count = read_u32(bytes, 40)?.max(1);
+        let supercompression = read_u32(bytes, 44)?;
+        let (format, srgb, uastc) = match vk_format {
+            // Basis Universal textures have no format, the data format
+            // descriptor tells the codec.
+            0 => {
+                let dfd = read_u32(bytes, 48)? as usize;
+                let model = bytes.get(dfd + 12).copied();
+                if supercompression == KTX2_SUPERCOMPRESSION_BASISLZ
+                    || model != Some(KDF_MODEL_UASTC)
+                {
+                    return Err(String::from(
+                        "ETC1S textures of Basis Universal are not supported, encode them with \
+                         UASTC or a BCn format",
+                    ));
+                }
+                // Transfer function 2 is sRGB, channel 3 is RGBA.
+                let srgb = bytes.get(dfd + 14) == Some(&2);
+                let has_alpha = bytes.get(dfd + 31).map(|b| b & 0x0F) == Some(3);
+                (BcFormat::Bc7, srgb, Some(has_alpha))
+            }
+            131 | 133 => (BcFormat::Bc1, false, None),
+            132 | 134 => (BcFormat::Bc1, true, None),
+            137 => (BcFormat::Bc3, false, None),
+            138 => (BcFormat::Bc3, true, None),
+            141 => (BcFormat::Bc5, false, None),
+            145 => (BcFormat::Bc7, false, None),
+            146 => (BcFormat::Bc7, true, None),
+            _ => return Err(format!("unsupported KTX2 format {}", vk_format)),
+        };
+        // The level index follows the 80 bytes of the header. UASTC blocks
+        // have the size of BC7 blocks.
+        let levels = (0..level_count as usize)
+            .map(|level| {
+                let offset = read_u64(bytes, 80 + level * 24)? as usize;
+                let length = read_u64(bytes, 88 + level * 24)? as usize;
+                let expected = level_size(format, width, height, level as u32);
+                let data = bytes
+                    .get(offset..offset + length)
+                    .ok_or_else(|| format!("KTX2 level {} is out of bounds", level))?;
+                let mut data = match supercompression {
+                    KTX2_SUPERCOMPRESSION_NONE => Cow::Borrowed(data),
+                    KTX2_SUPERCOMPRESSION_ZSTD => Cow::Owned(decompress_zstd(data, expected)?),
+                    other => return Err(format!("unsupported KTX2 supercompression {}", other)),
+                };
+                if data.len() < expected {
+                    return Err(format!("KTX2 level {} is truncated", level));
+                }
+                if let Some(has_alpha) = uastc {
+                    let (w, h) = ((width >> level).max(1), (height >> level).max(1));
+                    data = Cow::Owned(transcode_uastc(&data[..expected], w, h, has_alpha)?);
+                }
+                Ok(match data {
+                    Cow::Borrowed(data) => Cow::Borrowed(&data[..expected]),
+                    Cow::Owned(mut data) => {
+                        data.truncate(expected);
+                        Cow::Owned(data)
+                    }
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self {
+            width,
+            height,
+            format,
+            srgb: Some(srgb),
+            levels,
+        })
+    }
+
+    fn parse_dds(bytes: &'a [u8]) -> Result<Self, String> {
+        let height = read_u32(bytes, 12)?;
+        let width = read_u32(bytes, 16)?;
+        let level_count = read_u32(bytes, 28)?.max(1);
+        let four_cc = bytes.get(84..88).ok_or("DDS header is truncated")?;
+        let (format, srgb, mut offset) = match four_cc {
+            b"DXT1" => (BcFormat::Bc1, None, 128),
+            b"DXT5" => (BcFormat::Bc3, None, 128),
+            b"ATI2" | b"BC5U" => (BcFormat::Bc5, Some(false), 128),
+            b"DX10" => {
+                let (format, srgb) = match read_u32(bytes, 128)? {
+                    71 => (BcFormat::Bc1, false),
+                    72 => (BcFormat::Bc1, true),
+                    77 => (BcFormat::Bc3, false),
+                    78 => (BcFormat::Bc3, true),
+                    83 => (BcFormat::Bc5, false),
+                    98 => (BcFormat::Bc7, false),
+                    99 => (BcFormat::Bc7, true),
+                    other => return Err(format!("unsupported DXGI format {}", other)),
+                };
+                (format, Some(srgb), 148)
+            }
+            other => return Err(format!("unsupported DDS format {:?}", other)),
+        };
+        // Only the first layer of texture arrays and cube maps is loaded, its
+        // mip levels are stored contiguously.
+        let levels = (0..level_count)
+            .map(|level| {
+                let size = level_size(format, width, height, level);
+                let data = bytes
+                    .get(offset..offset + size)
+                    .ok_or_else(|| format!("DDS level {} is out of bounds", level))?;
+                offset += size;
+                Ok(Cow::Borrowed(data))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Self {
+            width,
+            height,
+            format,
+            srgb,
+            levels,
+        })
+    }
+
+    /// Returns the image owning the data of its mip levels.
+    pub fn into_owned(self) -> CompressedImage<'static> {
+        CompressedImage {
+            width: self.width,
+            height: self.height,
+            format: self.format,
+            srgb: self.srgb,
+            levels: self
+                .levels
+                .into_iter()
+                .map(|level| Cow::Owned(level.into_owned()))
+                .collect(),
+        }
+    }
+
+    /// Uploads the image to a new texture.
+    ///
+    /// If the device doesn't support block-compressed textures, or if the
+    /// size of the image is not a multiple of the block size, the image is
+    /// decompressed on the CPU.
+    pub fn create_texture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        srgb: bool,
+    ) -> Result<Texture, String> {
+        let srgb = self.srgb.unwrap_or(srgb);
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        let supported = device
+            .features()
+            .contains(wgpu::Features::TEXTURE_COMPRESSION_BC)
+            && self.width % 4 == 0
+            && self.height % 4 == 0;
+        let format = if supported {
+            self.format.texture_format(srgb)
+        } else {
+            log::warn!(
+                "Block-compressed textures not supported, decompressing {:?} texture.",
+                self.format
+            );
+            if srgb {
+                wgpu::TextureFormat::Bgra8UnormSrgb
+            } else {
+                wgpu::TextureFormat::Bgra8Unorm
+            }
+        };
+        let raw = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size,
+            mip_level_count: self.levels.len() as u32,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+
+        for (level, data) in self.levels.iter().enumerate() {
+            let mip_size = size.mip_level_size(level as u32, wgpu::TextureDimension::D2);
+            let copy = wgpu::ImageCopyTexture {
+                texture: &raw,
+                mip_level: level as u32,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            };
+            if supported {
+                let physical_size = mip_size.physical_size(format);
+                let blocks_wide = physical_size.width / 4;
+                let blocks_high = physical_size.height / 4;
+                queue.write_texture(
+                    copy,
+                    data,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(blocks_wide * self.format.block_size() as u32),
+                        rows_per_image: Some(blocks_high),
+                    },
+                    physical_size,
+                );
+            } else {
+                let pixels = self.format.decode(data, mip_size.width, mip_size.height)?;
+                queue.write_texture(
+                    copy,
+                    bytemuck::cast_slice(&pixels),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * mip_size.width),
+                        rows_per_image: Some(mip_size.height),
+                    },
+                    mip_size,
+                );
+            }
+        }
+
+        let view = raw.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(Texture {
+            raw,
+            view,
+            size,
+            sampler: SmlString::from("linear"),
+        })
+    }
+}
+
+/// Returns the size in bytes of a mip level of a block-compressed image.
+fn level_size(format: BcFormat, width: u32, height: u32, level: u32) -> usize {
+    let w = (width >> level).max(1).div_ceil(4) as usize;
+    let h = (height >> level).max(1).div_ceil(4) as usize;
+    w * h * format.block_size()
+}
+
+/// Decompresses a KTX2 level supercompressed with Zstandard.
+fn decompress_zstd(data: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    use std::io::Read;
+    let mut decoder = ruzstd::StreamingDecoder::new(data).map_err(|err| err.to_string())?;
+    let mut decompressed = Vec::with_capacity(size);
+    decoder
+        .read_to_end(&mut decompressed)
+        .map_err(|err| err.to_string())?;
+    Ok(decompressed)
+}
+
+/// Transcodes a mip level of UASTC blocks to BC7 blocks.
+#[cfg(feature = "basis")]
+fn transcode_uastc(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    has_alpha: bool,
+) -> Result<Vec<u8>, String> {
+    use basis_universal::{
+        DecodeFlags, LowLevelUastcTranscoder, SliceParametersUastc, TranscoderBlockFormat,
+    };
+    basis_universal::transcoder_init();
+    LowLevelUastcTranscoder::new()
+        .transcode_slice(
+            data,
+            SliceParametersUastc {
+                num_blocks_x: width.div_ceil(4),
+                num_blocks_y: height.div_ceil(4),
+                has_alpha,
+                original_width: width,
+                original_height: height,
+            },
+            DecodeFlags::HIGH_QUALITY,
+            TranscoderBlockFormat::BC7,
+        )
+        .map_err(|err| format!("failed to transcode UASTC texture: {:?}", err))
+}
+
+#[cfg(not(feature = "basis"))]
+fn transcode_uastc(_: &[u8], _: u32, _: u32, _: bool) -> Result<Vec<u8>, String> {
+    Err(String::from(
+        "UASTC textures of Basis Universal require the `basis` feature",
+    ))
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, String> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| String::from("texture header is truncated"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, String> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or_else(|| String::from("texture header is truncated"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dds_dx10() {
+        // 8x8 BC7 sRGB image with 2 mip levels.
+        let mut bytes = vec![0u8; 148];
+        bytes[..4].copy_from_slice(&DDS_MAGIC);
+        bytes[12..16].copy_from_slice(&8u32.to_le_bytes());
+        bytes[16..20].copy_from_slice(&8u32.to_le_bytes());
+        bytes[28..32].copy_from_slice(&2u32.to_le_bytes());
+        bytes[84..88].copy_from_slice(b"DX10");
+        bytes[128..132].copy_from_slice(&99u32.to_le_bytes());
+        bytes.extend(std::iter::repeat(1u8).take(4 * 16));
+        bytes.extend(std::iter::repeat(2u8).take(16));
+
+        let image = CompressedImage::parse(&bytes).unwrap().unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!(image.format, BcFormat::Bc7);
+        assert_eq!(image.srgb, Some(true));
+        assert_eq!(image.levels.len(), 2);
+        assert!(image.levels[0].len() == 64 && image.levels[0].iter().all(|b| *b == 1));
+        assert!(image.levels[1].len() == 16 && image.levels[1].iter().all(|b| *b == 2));
+
+        // Truncated data.
+        assert!(CompressedImage::parse(&bytes[..150]).unwrap().is_err());
+        assert!(CompressedImage::parse(b"PNG").is_none());
+    }
+
+    #[test]
+    fn parse_ktx2_zstd() {
+        // 4x4 BC7 image with a single level stored in a Zstandard frame made
+        // of a raw block.
+        let mut bytes = vec![0u8; 104];
+        bytes[..12].copy_from_slice(&KTX2_MAGIC);
+        bytes[12..16].copy_from_slice(&145u32.to_le_bytes());
+        bytes[20..24].copy_from_slice(&4u32.to_le_bytes());
+        bytes[24..28].copy_from_slice(&4u32.to_le_bytes());
+        bytes[40..44].copy_from_slice(&1u32.to_le_bytes());
+        bytes[44..48].copy_from_slice(&KTX2_SUPERCOMPRESSION_ZSTD.to_le_bytes());
+        let frame = [
+            &[0x28, 0xB5, 0x2F, 0xFD, 0x20, 16][..],
+            &((16u32 << 3) | 1).to_le_bytes()[..3],
+            &[7u8; 16][..],
+        ]
+        .concat();
+        bytes[80..88].copy_from_slice(&104u64.to_le_bytes());
+        bytes[88..96].copy_from_slice(&(frame.len() as u64).to_le_bytes());
+        bytes[96..104].copy_from_slice(&16u64.to_le_bytes());
+        bytes.extend_from_slice(&frame);
+
+        let image = CompressedImage::parse(&bytes).unwrap().unwrap();
+        assert_eq!(image.format, BcFormat::Bc7);
+        assert_eq!(image.srgb, Some(false));
+        assert_eq!(image.levels.len(), 1);
+        assert_eq!(&*image.levels[0], &[7u8; 16]);
+
+        // BasisLZ supercompression of ETC1S textures.
+        bytes[12..16].copy_from_slice(&0u32.to_le_bytes());
+        bytes[44..48].copy_from_slice(&KTX2_SUPERCOMPRESSION_BASISLZ.to_le_bytes());
+        assert!(CompressedImage::parse(&bytes).unwrap().is_err());
+    }
+}
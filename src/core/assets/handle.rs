@@ -1,4 +1,4 @@
-use crate::core::assets::Asset;
+use crate::core::{assets::Asset, FxHashMap};
 use crossbeam_channel::{Receiver, Sender};
 use std::{
     cmp::Ordering,
@@ -135,3 +135,120 @@ impl<T: Asset> Default for HandleAllocator<T> {
         Self::new()
     }
 }
+
+/// Number of strong references to an asset.
+#[derive(Debug, Clone, Copy, Default)]
+struct RefCount {
+    strong: u32,
+    pinned: bool,
+}
+
+/// Strong references to assets, counted so that the assets which are no
+/// longer referenced are removed.
+///
+/// A [`Handle`] itself is a weak reference: copying it doesn't keep its asset
+/// alive, and it resolves to nothing once the asset is removed even if its
+/// slot is reused, see [`Assets::get`](super::Assets::get). Pinned assets are
+/// kept without strong references.
+pub struct RefCounts<T: Asset> {
+    counts: FxHashMap<Handle<T>, RefCount>,
+}
+
+impl<T: Asset> Default for RefCounts<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Asset> RefCounts<T> {
+    pub fn new() -> Self {
+        Self {
+            counts: FxHashMap::default(),
+        }
+    }
+
+    /// Adds a strong reference to the asset.
+    pub fn retain(&mut self, handle: Handle<T>) {
+        self.counts.entry(handle).or_default().strong += 1;
+    }
+
+    /// Removes a strong reference to the asset. Returns true if the asset is
+    /// no longer referenced nor pinned.
+    pub fn release(&mut self, handle: Handle<T>) -> bool {
+        match self.counts.get_mut(&handle) {
+            Some(count) if count.strong > 0 => {
+                count.strong -= 1;
+                count.strong == 0 && !count.pinned
+            }
+            _ => {
+                log::warn!("Releasing unreferenced asset {:?}", handle);
+                false
+            }
+        }
+    }
+
+    /// Keeps the asset alive while it is not referenced, or stops keeping it
+    /// alive. Returns true if the asset is no longer referenced nor pinned.
+    pub fn pin(&mut self, handle: Handle<T>, pinned: bool) -> bool {
+        let count = self.counts.entry(handle).or_default();
+        count.pinned = pinned;
+        count.strong == 0 && !count.pinned
+    }
+
+    /// Returns the number of strong references to the asset.
+    pub fn strong_count(&self, handle: Handle<T>) -> u32 {
+        self.counts.get(&handle).map_or(0, |count| count.strong)
+    }
+
+    /// Returns whether the asset is pinned.
+    pub fn is_pinned(&self, handle: Handle<T>) -> bool {
+        self.counts.get(&handle).is_some_and(|count| count.pinned)
+    }
+
+    /// Returns whether the asset is no longer referenced nor pinned, and
+    /// stops tracking it if so.
+    pub fn take_unused(&mut self, handle: Handle<T>) -> bool {
+        match self.counts.get(&handle) {
+            Some(count) if count.strong > 0 || count.pinned => false,
+            _ => {
+                self.counts.remove(&handle);
+                true
+            }
+        }
+    }
+
+    /// Stops tracking all the assets.
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Dummy;
+
+    impl Asset for Dummy {}
+
+    #[test]
+    fn assets_are_unused_once_released_and_unpinned() {
+        let allocator = HandleAllocator::<Dummy>::new();
+        let (a, b) = (allocator.reserve(), allocator.reserve());
+        let mut refs = RefCounts::new();
+        refs.retain(a);
+        refs.retain(a);
+        assert!(!refs.release(a));
+        assert_eq!(refs.strong_count(a), 1);
+        assert!(refs.release(a));
+
+        // A pinned asset outlives its references.
+        refs.retain(b);
+        refs.pin(b, true);
+        assert!(!refs.release(b));
+        assert!(!refs.take_unused(b));
+        assert!(refs.pin(b, false));
+        assert!(refs.take_unused(b));
+        assert!(!refs.is_pinned(b));
+    }
+}
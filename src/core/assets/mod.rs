@@ -1,15 +1,20 @@
+mod budget;
+mod compressed;
 mod handle;
 pub mod storage;
+mod watcher;
 
 use crate::core::{
-    assets::storage::GpuMeshStorage,
+    assets::storage::{GpuMeshStorage, MeshBufferStats},
     mesh::{GpuMesh, Mesh},
     texture::Texture,
-    MaterialBundle, SmlString, TextureBundle,
+    FxHashMap, MaterialBundle, SmlString, TextureBundle,
 };
+pub use budget::*;
 pub use handle::*;
 use std::path::Path;
 use tobj::Material;
+pub use watcher::*;
 
 /// Trait for representing an asset.
 pub trait Asset: Send + Sync {}
@@ -23,6 +28,10 @@ pub trait AssetStorage {
 pub struct Assets<A: Asset, S: AssetStorage> {
     storage: S,
     allocator: HandleAllocator<A>,
+    /// Generation of the handle of the asset stored in each slot, so that
+    /// the handles of removed assets don't resolve to the assets reusing
+    /// their slot.
+    generations: Vec<u32>,
 }
 
 impl<A: Asset, S: AssetStorage> Assets<A, S> {
@@ -43,6 +52,7 @@ where
         Self {
             storage: S::default(),
             allocator: HandleAllocator::new(),
+            generations: Vec::new(),
         }
     }
 }
@@ -63,6 +73,7 @@ impl<A: Asset> Assets<A, Vec<Option<A>>> {
         let handle = self.allocator.reserve();
         self.flush();
         self.storage[handle.index as usize] = Some(asset);
+        self.generations[handle.index as usize] = handle.generation;
         log::debug!(
             "add({:?}), len: {}",
             std::any::type_name::<A>(),
@@ -71,22 +82,45 @@ impl<A: Asset> Assets<A, Vec<Option<A>>> {
         handle
     }
 
-    /// Returns the asset with the given handle.
+    /// Returns the asset with the given handle, `None` if it has been
+    /// removed.
     pub fn get(&self, handle: Handle<A>) -> Option<&A> {
+        if !self.is_current(handle) {
+            return None;
+        }
         self.storage[handle.index as usize].as_ref()
     }
 
+    /// Returns the mutable asset with the given handle, `None` if it has
+    /// been removed.
+    pub fn get_mut(&mut self, handle: Handle<A>) -> Option<&mut A> {
+        if !self.is_current(handle) {
+            return None;
+        }
+        self.storage[handle.index as usize].as_mut()
+    }
+
+    /// Returns whether the handle refers to the asset stored in its slot,
+    /// not to a removed asset whose slot has been reused.
+    fn is_current(&self, handle: Handle<A>) -> bool {
+        self.generations.get(handle.index as usize) == Some(&handle.generation)
+    }
+
     /// Inserts a new asset into the storage at the given index.
     ///
     /// Returns true if the asset was inserted.
     pub fn insert(&mut self, handle: Handle<A>, asset: A) -> Option<A> {
         self.flush();
+        self.generations[handle.index as usize] = handle.generation;
         self.storage[handle.index as usize].replace(asset)
     }
 
     /// Removes an asset from the storage at the given index and returns it.
     pub fn remove(&mut self, handle: Handle<A>) -> Option<A> {
         self.flush();
+        if !self.is_current(handle) {
+            return None;
+        }
         match self.storage[handle.index as usize].take() {
             Some(asset) => {
                 self.allocator.recycle(handle);
@@ -105,6 +139,7 @@ impl<A: Asset> Assets<A, Vec<Option<A>>> {
             .load(std::sync::atomic::Ordering::Relaxed) as usize;
         if new_len != self.storage.len() {
             self.storage.resize_with(new_len, || None);
+            self.generations.resize(new_len, 0);
         }
         while let Ok(recycled) = self.allocator.recycle_receiver.try_recv() {
             self.storage[recycled.index as usize] = None;
@@ -134,6 +169,7 @@ impl Assets<GpuMesh, GpuMeshStorage> {
         Self {
             storage: GpuMeshStorage::new(device),
             allocator: HandleAllocator::new(),
+            generations: Vec::new(),
         }
     }
 
@@ -165,16 +201,46 @@ impl Assets<GpuMesh, GpuMeshStorage> {
         handle
     }
 
+    /// Replaces the GPU mesh with the given handle by the given mesh,
+    /// releasing the buffer ranges of the old mesh.
+    pub fn replace(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        handle: Handle<GpuMesh>,
+        mesh: &Mesh,
+    ) {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mesh_replace"),
+        });
+        let gpu_mesh = self.storage.add(device, queue, &mut encoder, mesh);
+        if let Some((_, old)) = self.storage.data[handle.index as usize].replace((handle, gpu_mesh))
+        {
+            self.storage.free(&old);
+        }
+        if self.storage.needs_defragmentation() {
+            self.storage.defragment(device, &mut encoder);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Returns the GPU mesh with the given handle, `None` if it has been
+    /// removed.
     pub fn get(&self, handle: Handle<GpuMesh>) -> Option<&GpuMesh> {
         self.storage.data[handle.index as usize]
             .as_ref()
+            .filter(|(current, _)| *current == handle)
             .map(|(_, mesh)| mesh)
     }
 
+    /// Removes the GPU mesh with the given handle, releasing its buffer
+    /// ranges.
     pub fn remove(&mut self, handle: Handle<GpuMesh>) -> Option<GpuMesh> {
         self.flush();
-        match self.storage.data[handle.index as usize].take() {
+        let slot = &mut self.storage.data[handle.index as usize];
+        match slot.take_if(|(current, _)| *current == handle) {
             Some(mesh) => {
+                self.storage.free(&mesh.1);
                 self.allocator.recycle(handle);
                 Some(mesh.1)
             }
@@ -182,11 +248,63 @@ impl Assets<GpuMesh, GpuMeshStorage> {
         }
     }
 
+    /// Recreates the mesh data buffer on a new device, uploading the meshes
+    /// again from their CPU copies. Meshes without a copy are removed.
+    pub fn restore(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        sources: &FxHashMap<Handle<GpuMesh>, Mesh>,
+    ) {
+        self.flush();
+        let data = std::mem::take(&mut self.storage.data);
+        self.storage = GpuMeshStorage::new(device);
+        self.storage.data.resize_with(data.len(), || None);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mesh_restore"),
+        });
+        for (handle, gpu_mesh) in data.into_iter().flatten() {
+            match sources.get(&handle) {
+                Some(mesh) => {
+                    let gpu_mesh = self.storage.add(device, queue, &mut encoder, mesh);
+                    self.storage.data[handle.index as usize] = Some((handle, gpu_mesh));
+                }
+                None => {
+                    log::error!("Mesh {:?} can't be restored, it is removed", gpu_mesh.name);
+                }
+            }
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
     /// Returns the buffer containing the mesh data.
     pub fn buffer(&self) -> &wgpu::Buffer {
         &self.storage.buffer
     }
 
+    /// Returns the memory statistics of the mesh data buffer.
+    pub fn stats(&self) -> MeshBufferStats {
+        self.storage.stats()
+    }
+
+    /// Compacts the mesh data buffer if the holes left by removed meshes
+    /// exceed [`storage::DEFRAG_THRESHOLD`] of its occupied part.
+    ///
+    /// Returns true if the buffer was compacted, in which case the ranges of
+    /// the meshes changed.
+    pub fn defragment(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        self.flush();
+        if !self.storage.needs_defragmentation() {
+            return false;
+        }
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("mesh_defragment"),
+        });
+        self.storage.defragment(device, &mut encoder);
+        queue.submit(std::iter::once(encoder.finish()));
+        true
+    }
+
     /// Flushes the asset storage, removing those assets of which the handle
     /// is recycled.
     pub fn flush(&mut self) {
@@ -198,7 +316,9 @@ impl Assets<GpuMesh, GpuMeshStorage> {
             self.storage.data.resize_with(new_len, || None);
         }
         while let Ok(recycled) = self.allocator.recycle_receiver.try_recv() {
-            self.storage.data[recycled.index as usize] = None;
+            if let Some((_, mesh)) = self.storage.data[recycled.index as usize].take() {
+                self.storage.free(&mesh);
+            }
         }
     }
 }
@@ -211,6 +331,7 @@ impl Assets<MaterialBundle, Vec<Option<MaterialBundle>>> {
         Self {
             storage: Vec::new(),
             allocator: HandleAllocator::new(),
+            generations: Vec::new(),
         }
     }
 }
@@ -232,18 +353,41 @@ impl Assets<Texture, Vec<Option<Texture>>> {
         let mut assets = Self {
             storage: Vec::new(),
             allocator: HandleAllocator::new(),
+            generations: Vec::new(),
         };
-        let hdl = assets.load_from_bytes(
-            device,
-            queue,
-            include_bytes!("../../../data/textures/checker.png"),
-            None,
-            None,
-        );
+        let hdl = assets.add(create_default_texture(device, queue));
         debug_assert_eq!(hdl.index, 0);
         assets
     }
 
+    /// Recreates the textures on a new device: the default texture is
+    /// created again and the others are reloaded from the given files.
+    /// Textures which can't be reloaded are replaced by the default texture.
+    pub fn restore<'a, F>(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, files: F)
+    where
+        F: Iterator<Item = (Handle<Texture>, &'a Path, Option<wgpu::TextureFormat>)>,
+    {
+        self.flush();
+        let mut restored = vec![false; self.storage.len()];
+        self.insert(
+            self.default_texture(),
+            create_default_texture(device, queue),
+        );
+        restored[0] = true;
+        for (handle, path, format) in files {
+            restored[handle.index as usize] =
+                self.reload_from_file(device, queue, handle, path, format);
+        }
+        for (texture, restored) in self.storage.iter_mut().zip(restored) {
+            if let Some(texture) = texture.as_mut().filter(|_| !restored) {
+                log::warn!("Texture can't be restored, use the default texture instead");
+                let sampler = texture.sampler.clone();
+                *texture = create_default_texture(device, queue);
+                texture.sampler = sampler;
+            }
+        }
+    }
+
     pub fn default_texture(&self) -> Handle<Texture> {
         Handle {
             generation: 0,
@@ -255,6 +399,7 @@ impl Assets<Texture, Vec<Option<Texture>>> {
     /// Loads a texture from bytes.
     ///
     /// If the format is not specified, it defaults to `wgpu::TextureFormat::Rgba8UnormSrgb`.
+    /// KTX2 and DDS files keep their block-compressed format and mip levels.
     /// The sampler is set to `linear`.
     pub fn load_from_bytes(
         &mut self,
@@ -264,50 +409,98 @@ impl Assets<Texture, Vec<Option<Texture>>> {
         path: Option<&Path>,
         format: Option<wgpu::TextureFormat>,
     ) -> Handle<Texture> {
-        let img = image::load_from_memory(bytes)
+        let texture = create_texture(device, queue, bytes, format)
             .map_err(|e| eprintln!("Failed to load texture: {:?} from {:?}", e, path))
-            .unwrap()
-            .to_rgba8();
-        let dims = img.dimensions();
-        let size = wgpu::Extent3d {
-            width: dims.0,
-            height: dims.1,
-            depth_or_array_layers: 1,
+            .unwrap();
+        self.add(texture)
+    }
+
+    /// Creates a new texture from an image decoded beforehand, see
+    /// `decode_images`.
+    pub fn load_from_image(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: &DecodedImage,
+        format: Option<wgpu::TextureFormat>,
+    ) -> Result<Handle<Texture>, String> {
+        let texture = match image {
+            DecodedImage::Compressed(image) => {
+                image.create_texture(device, queue, is_srgb(format))?
+            }
+            DecodedImage::Rgba(img) => create_rgba_texture(device, queue, img, format),
         };
-        let desc = wgpu::TextureDescriptor {
-            label: None,
-            size,
+        Ok(self.add(texture))
+    }
+
+    /// Reloads the texture with the given handle from a file, keeping its
+    /// sampler.
+    ///
+    /// Returns false if the file could not be loaded, in which case the
+    /// texture is left untouched.
+    pub fn reload_from_file(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        handle: Handle<Texture>,
+        filepath: &Path,
+        format: Option<wgpu::TextureFormat>,
+    ) -> bool {
+        let texture = std::fs::read(filepath)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| create_texture(device, queue, &bytes, format));
+        match texture {
+            Ok(mut texture) => {
+                if let Some(old) = self.get(handle) {
+                    texture.sampler = old.sampler.clone();
+                }
+                self.insert(handle, texture);
+                log::info!("Reloaded texture from: {:?}", filepath);
+                true
+            }
+            Err(err) => {
+                log::error!("Failed to reload texture from {:?}: {}", filepath, err);
+                false
+            }
+        }
+    }
+
+    /// Releases the GPU memory of the texture with the given handle, which
+    /// keeps its sampler and can be reloaded with `reload_from_file`.
+    pub fn evict(&mut self, device: &wgpu::Device, handle: Handle<Texture>) {
+        let Some(texture) = self.get(handle) else {
+            return;
+        };
+        let sampler = texture.sampler.clone();
+        // The placeholder keeps the format of the texture so that it is
+        // sampled the same way, e.g. with the same color space. A
+        // block-compressed texture needs at least one block.
+        let format = texture.raw.format();
+        let (width, height) = format.block_dimensions();
+        let raw = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("evicted_texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: format.unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb),
-            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING,
             view_formats: &[],
-        };
-        let raw = device.create_texture(&desc);
+        });
         let view = raw.create_view(&wgpu::TextureViewDescriptor::default());
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &raw,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &img,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dims.0),
-                rows_per_image: Some(dims.1),
+        self.insert(
+            handle,
+            Texture {
+                size: raw.size(),
+                raw,
+                view,
+                sampler,
             },
-            size,
         );
-        let texture = Texture {
-            raw,
-            view,
-            size,
-            sampler: SmlString::from("linear"),
-        };
-        self.add(texture)
     }
 
     /// Creates a new texture by loading it from a file.
@@ -325,6 +518,151 @@ impl Assets<Texture, Vec<Option<Texture>>> {
     }
 }
 
+/// Creates the default checker texture.
+fn create_default_texture(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+    create_texture(
+        device,
+        queue,
+        include_bytes!("../../../data/textures/checker.png"),
+        None,
+    )
+    .unwrap()
+}
+
+/// Creates a texture from the bytes of an encoded image.
+///
+/// Block-compressed images stored in KTX2 or DDS files are uploaded as is,
+/// with their mip chain. Other images are decoded to RGBA8.
+fn create_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    bytes: &[u8],
+    format: Option<wgpu::TextureFormat>,
+) -> Result<Texture, String> {
+    if let Some(image) = compressed::CompressedImage::parse(bytes) {
+        return image?.create_texture(device, queue, is_srgb(format));
+    }
+
+    let img = image::load_from_memory(bytes)
+        .map_err(|err| err.to_string())?
+        .to_rgba8();
+    Ok(create_rgba_texture(device, queue, &img, format))
+}
+
+/// Returns whether textures of the requested format are in sRGB color space.
+/// Containers without color space information follow the requested format,
+/// textures are in sRGB by default.
+fn is_srgb(format: Option<wgpu::TextureFormat>) -> bool {
+    format.map_or(true, |format| format.is_srgb())
+}
+
+/// Creates a texture from an image decoded to RGBA8.
+fn create_rgba_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    img: &image::RgbaImage,
+    format: Option<wgpu::TextureFormat>,
+) -> Texture {
+    let dims = img.dimensions();
+    let size = wgpu::Extent3d {
+        width: dims.0,
+        height: dims.1,
+        depth_or_array_layers: 1,
+    };
+    let desc = wgpu::TextureDescriptor {
+        label: None,
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: format.unwrap_or(wgpu::TextureFormat::Rgba8UnormSrgb),
+        usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+    };
+    let raw = device.create_texture(&desc);
+    let view = raw.create_view(&wgpu::TextureViewDescriptor::default());
+    queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &raw,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        img,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * dims.0),
+            rows_per_image: Some(dims.1),
+        },
+        size,
+    );
+    Texture {
+        raw,
+        view,
+        size,
+        sampler: SmlString::from("linear"),
+    }
+}
+
+/// Image read from a file and decoded on any thread, waiting to be uploaded
+/// to a texture.
+pub enum DecodedImage {
+    /// Block-compressed image, decompressed or transcoded if needed but
+    /// uploaded with its block compression.
+    Compressed(compressed::CompressedImage<'static>),
+    /// Image decoded to RGBA8.
+    Rgba(image::RgbaImage),
+}
+
+impl DecodedImage {
+    /// Reads and decodes an image file.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+        if let Some(image) = compressed::CompressedImage::parse(&bytes) {
+            return Ok(Self::Compressed(image?.into_owned()));
+        }
+        image::load_from_memory(&bytes)
+            .map(|img| Self::Rgba(img.to_rgba8()))
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Reads and decodes image files on multiple threads.
+///
+/// `f` is called on the calling thread with the index of each file and its
+/// image as soon as it is decoded, so that the images can be uploaded while
+/// the next ones are being decoded. Files are not given in order.
+pub fn decode_images<F>(paths: &[&Path], mut f: F)
+where
+    F: FnMut(usize, Result<DecodedImage, String>),
+{
+    let n_threads = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(paths.len());
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let (sender, receiver) = flume::unbounded();
+    std::thread::scope(|s| {
+        for _ in 0..n_threads {
+            let sender = sender.clone();
+            let next = &next;
+            s.spawn(move || loop {
+                let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(path) = paths.get(i) else {
+                    break;
+                };
+                if sender.send((i, DecodedImage::read(path))).is_err() {
+                    break;
+                }
+            });
+        }
+        // The channel disconnects once all the workers are done.
+        drop(sender);
+        for (i, image) in receiver.iter() {
+            f(i, image);
+        }
+    });
+}
+
 /// A collection of texture bundles, including textures and samplers.
 pub type TextureBundleAssets = Assets<TextureBundle, Vec<Option<TextureBundle>>>;
 
@@ -333,6 +671,7 @@ impl Assets<TextureBundle, Vec<Option<TextureBundle>>> {
         Self {
             storage: Vec::new(),
             allocator: HandleAllocator::new(),
+            generations: Vec::new(),
         }
     }
 }
@@ -1,20 +1,91 @@
 use crate::core::{
     assets::{AssetStorage, Handle},
-    mesh::{GpuMesh, Mesh},
+    mesh::{GpuMesh, Indices, Mesh, VertexAttribute},
 };
+use glam::Vec3;
 use range_alloc::RangeAllocator;
+use rustc_hash::FxHashMap;
 use std::{num::NonZeroU64, ops::Range, sync::Arc};
 
 /// Initial size of the mesh data buffer. 32MB.
 pub const INITIAL_MESH_DATA_SIZE: u64 = 1 << 25;
 
+/// Fraction of the occupied part of the mesh data buffer left unused by
+/// removed meshes above which the buffer is compacted.
+pub const DEFRAG_THRESHOLD: f32 = 0.5;
+
+/// Minimum number of bytes left unused by removed meshes before the mesh
+/// data buffer is compacted, to avoid compacting small buffers repeatedly.
+pub const DEFRAG_MIN_WASTED_SIZE: u64 = 1 << 22;
+
+/// Memory statistics of the mesh data buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MeshBufferStats {
+    /// Size of the buffer in bytes.
+    pub capacity: u64,
+    /// Number of bytes occupied by the live meshes.
+    pub used: u64,
+    /// End of the last range occupied by a live mesh; the bytes below it
+    /// which are not used are holes left by removed meshes.
+    pub span: u64,
+    /// Number of live meshes.
+    pub mesh_count: usize,
+}
+
+impl MeshBufferStats {
+    /// Returns the number of bytes of the holes left by removed meshes.
+    pub const fn wasted(&self) -> u64 {
+        self.span - self.used
+    }
+
+    /// Returns the fraction of the occupied part of the buffer left unused
+    /// by removed meshes.
+    pub fn fragmentation(&self) -> f32 {
+        if self.span == 0 {
+            0.0
+        } else {
+            self.wasted() as f32 / self.span as f32
+        }
+    }
+}
+
+/// Identifies the content of an index buffer: its format and a copy of its
+/// data, compared in full so that only identical indices are shared.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct IndicesKey {
+    format: wgpu::IndexFormat,
+    data: Arc<[u8]>,
+}
+
+impl IndicesKey {
+    fn new(format: wgpu::IndexFormat, data: &[u8]) -> Self {
+        Self {
+            format,
+            data: Arc::from(data),
+        }
+    }
+}
+
+/// Index data shared by the meshes with identical indices.
+#[derive(Debug, Clone)]
+struct SharedIndices {
+    range: Range<u64>,
+    /// Number of meshes using the indices.
+    users: u32,
+}
+
 /// Storage for GPU meshes in a megabuffer.
 ///
-/// This manages the allocation of mesh data on the GPU.
+/// This manages the allocation of mesh data on the GPU. Meshes with
+/// identical indices share the same range of the buffer.
 pub struct GpuMeshStorage {
     pub(crate) buffer: Arc<wgpu::Buffer>,
     allocator: RangeAllocator<u64>,
     pub(crate) data: Vec<Option<(Handle<GpuMesh>, GpuMesh)>>,
+    /// Index data of the meshes, looked up by content.
+    indices: FxHashMap<IndicesKey, SharedIndices>,
+    /// Content of the index data starting at each offset of the buffer.
+    indices_keys: FxHashMap<u64, IndicesKey>,
 }
 
 impl GpuMeshStorage {
@@ -27,6 +98,8 @@ impl GpuMeshStorage {
             buffer,
             allocator,
             data: Vec::new(),
+            indices: FxHashMap::default(),
+            indices_keys: FxHashMap::default(),
         }
     }
 
@@ -38,11 +111,21 @@ impl GpuMeshStorage {
         mesh: &Mesh,
     ) -> GpuMesh {
         profiling::scope!("GpuMeshStorage::add");
+        // Points are uploaded as quads, the gpu mesh keeping the topology
+        // of the points so that they are drawn facing the camera.
+        let topology = mesh.topology;
+        let quads;
+        let mesh = if topology == wgpu::PrimitiveTopology::PointList {
+            quads = mesh.point_quads();
+            &quads
+        } else {
+            mesh
+        };
         let index_count = mesh.indices.as_ref().map(|i| i.len()).unwrap_or(0);
         let vertex_count = mesh.attributes.vertex_count();
 
         if index_count == 0 && vertex_count == 0 {
-            return GpuMesh::empty(mesh.topology);
+            return GpuMesh::empty(topology);
         }
 
         let mut vertex_attribute_ranges = Vec::with_capacity(mesh.attributes.0.len());
@@ -62,18 +145,29 @@ impl GpuMeshStorage {
             );
             vertex_attribute_ranges.push((*attrib, range));
         }
+        // The shaders read a color per vertex, meshes without vertex colors
+        // get white ones.
+        if vertex_count > 0 && !mesh.attributes.0.contains_key(&VertexAttribute::COLOR) {
+            let n_bytes = (vertex_count * VertexAttribute::COLOR.size) as u64;
+            let range = self.allocate_range(device, encoder, n_bytes);
+            vertex_attribute_ranges.push((VertexAttribute::COLOR, range));
+        }
 
         // Copy the mesh vertex data into the buffer.
         for (attrib, range) in vertex_attribute_ranges.iter() {
-            let data = mesh.attributes.0.get(attrib).unwrap();
             let mut mapping = queue
                 .write_buffer_with(
                     &self.buffer,
                     range.start,
-                    NonZeroU64::new(data.n_bytes() as u64).unwrap(),
+                    NonZeroU64::new(range.end - range.start).unwrap(),
                 )
                 .unwrap();
-            mapping.copy_from_slice(data.as_bytes());
+            match mesh.attributes.0.get(attrib) {
+                Some(data) => mapping.copy_from_slice(data.as_bytes()),
+                None => {
+                    mapping.copy_from_slice(bytemuck::cast_slice(&vec![[1.0f32; 4]; vertex_count]))
+                }
+            }
         }
 
         let (index_format, index_range) = match mesh.indices.as_ref() {
@@ -82,44 +176,96 @@ impl GpuMeshStorage {
                 (None, 0..0)
             }
             Some(indices) => {
-                // Make sure the size of the indices is aligned to COPY_BUFFER_ALIGNMENT.
-                let n_bytes = (indices.n_bytes() as u64 + wgpu::COPY_BUFFER_ALIGNMENT - 1)
-                    & !(wgpu::COPY_BUFFER_ALIGNMENT - 1);
-                let index_range = self.allocate_range(device, encoder, n_bytes);
-                log::debug!(
-                    "Allocating index buffer with size {} as range {:?} with padding {}",
-                    indices.n_bytes(),
-                    index_range,
-                    n_bytes - indices.n_bytes() as u64
-                );
-                // Copy the mesh index data into the buffer.
-                let mut mapping = queue
-                    .write_buffer_with(
-                        &self.buffer,
-                        index_range.start,
-                        NonZeroU64::new(n_bytes).unwrap(),
-                    )
-                    .unwrap();
-                mapping[..indices.n_bytes()].copy_from_slice(indices.as_bytes());
-                (Some(indices.format()), index_range)
+                // Store the indices as 16-bit indices if possible.
+                let indices = indices.compacted();
+                (
+                    Some(indices.format()),
+                    self.add_indices(device, queue, encoder, &indices),
+                )
             }
         };
 
         GpuMesh {
             name: mesh.name.clone(),
             path: mesh.path.clone(),
-            topology: mesh.topology,
+            topology,
             vertex_attribute_ranges,
             vertex_count: vertex_count as u32,
             index_format,
             index_range,
             index_count: index_count as u32,
             sub_meshes: mesh.sub_meshes.clone(),
+            bounds: mesh.bounds().unwrap_or((Vec3::ZERO, Vec3::ZERO)),
         }
     }
 }
 
 impl GpuMeshStorage {
+    /// Uploads the given indices, or reuses the range of identical indices
+    /// already uploaded.
+    fn add_indices(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        indices: &Indices,
+    ) -> Range<u64> {
+        let key = IndicesKey::new(indices.format(), indices.as_bytes());
+        if let Some(shared) = self.indices.get_mut(&key) {
+            log::debug!("Sharing index buffer at range {:?}", shared.range);
+            shared.users += 1;
+            return shared.range.clone();
+        }
+
+        // Make sure the size of the indices is aligned to COPY_BUFFER_ALIGNMENT.
+        let n_bytes = (indices.n_bytes() as u64 + wgpu::COPY_BUFFER_ALIGNMENT - 1)
+            & !(wgpu::COPY_BUFFER_ALIGNMENT - 1);
+        let index_range = self.allocate_range(device, encoder, n_bytes);
+        log::debug!(
+            "Allocating index buffer with size {} as range {:?} with padding {}",
+            indices.n_bytes(),
+            index_range,
+            n_bytes - indices.n_bytes() as u64
+        );
+        // Copy the mesh index data into the buffer.
+        let mut mapping = queue
+            .write_buffer_with(
+                &self.buffer,
+                index_range.start,
+                NonZeroU64::new(n_bytes).unwrap(),
+            )
+            .unwrap();
+        mapping[..indices.n_bytes()].copy_from_slice(indices.as_bytes());
+        self.indices_keys.insert(index_range.start, key.clone());
+        self.indices.insert(
+            key,
+            SharedIndices {
+                range: index_range.clone(),
+                users: 1,
+            },
+        );
+        index_range
+    }
+
+    /// Releases a mesh's use of the index data at the given range, the range
+    /// is deallocated once no mesh uses it anymore.
+    fn release_indices(&mut self, range: Range<u64>) {
+        if range.is_empty() {
+            return;
+        }
+        let Some(key) = self.indices_keys.get(&range.start).cloned() else {
+            self.deallocate_range(range);
+            return;
+        };
+        let shared = self.indices.get_mut(&key).unwrap();
+        shared.users -= 1;
+        if shared.users == 0 {
+            self.indices.remove(&key);
+            self.indices_keys.remove(&range.start);
+            self.deallocate_range(range);
+        }
+    }
+
     /// Allocates a range of the given size from the buffer.
     ///
     /// If the buffer is too small, it will be grown.
@@ -144,6 +290,93 @@ impl GpuMeshStorage {
         }
     }
 
+    /// Releases the ranges of the buffer occupied by the given mesh.
+    pub fn free(&mut self, mesh: &GpuMesh) {
+        for (_, range) in &mesh.vertex_attribute_ranges {
+            self.deallocate_range(range.clone());
+        }
+        self.release_indices(mesh.index_range.clone());
+    }
+
+    /// Returns the memory statistics of the buffer.
+    pub fn stats(&self) -> MeshBufferStats {
+        let mut stats = MeshBufferStats {
+            capacity: self.allocator.initial_range().end,
+            ..Default::default()
+        };
+        let vertex_ranges = self
+            .data
+            .iter()
+            .flatten()
+            .flat_map(|(_, mesh)| mesh.vertex_attribute_ranges.iter().map(|(_, range)| range));
+        // Shared indices are counted once.
+        let index_ranges = self.indices.values().map(|shared| &shared.range);
+        for range in vertex_ranges.chain(index_ranges) {
+            stats.used += range.end - range.start;
+            stats.span = stats.span.max(range.end);
+        }
+        stats.mesh_count = self.data.iter().flatten().count();
+        stats
+    }
+
+    /// Returns true if the holes left by removed meshes are large enough
+    /// for the buffer to be compacted.
+    pub fn needs_defragmentation(&self) -> bool {
+        let stats = self.stats();
+        stats.wasted() >= DEFRAG_MIN_WASTED_SIZE && stats.fragmentation() > DEFRAG_THRESHOLD
+    }
+
+    /// Compacts the buffer by copying the ranges of the live meshes next to
+    /// each other into a new buffer, then patches the ranges of the meshes.
+    /// Shared indices are copied once.
+    ///
+    /// The copies are recorded into the given encoder, the data written to
+    /// the old buffer through the queue before it is submitted is preserved.
+    pub fn defragment(&mut self, device: &wgpu::Device, encoder: &mut wgpu::CommandEncoder) {
+        profiling::scope!("GpuMeshStorage::defragment");
+        let capacity = self.allocator.initial_range().end;
+        let new_buffer = create_gpu_mesh_storage_buffer(device, capacity);
+        let mut allocator = RangeAllocator::new(0..capacity);
+        let mut relocate = |range: &Range<u64>| {
+            // The live ranges fitted in the old buffer, so they fit in the
+            // new one of the same size.
+            let new_range = allocator.allocate_range(range.end - range.start).unwrap();
+            encoder.copy_buffer_to_buffer(
+                &self.buffer,
+                range.start,
+                &new_buffer,
+                new_range.start,
+                new_range.end - new_range.start,
+            );
+            new_range
+        };
+        // Relocate the shared indices once, then patch the meshes using them.
+        let mut relocated_indices = FxHashMap::default();
+        self.indices_keys.clear();
+        for (key, shared) in self.indices.iter_mut() {
+            let new_range = relocate(&shared.range);
+            relocated_indices.insert(shared.range.start, new_range.clone());
+            self.indices_keys.insert(new_range.start, key.clone());
+            shared.range = new_range;
+        }
+        for (_, mesh) in self.data.iter_mut().flatten() {
+            for (_, range) in mesh.vertex_attribute_ranges.iter_mut() {
+                if !range.is_empty() {
+                    *range = relocate(range);
+                }
+            }
+            if !mesh.index_range.is_empty() {
+                mesh.index_range = relocated_indices[&mesh.index_range.start].clone();
+            }
+        }
+        log::debug!(
+            "Defragmented mesh buffer, {} bytes available",
+            allocator.total_available()
+        );
+        self.buffer = new_buffer;
+        self.allocator = allocator;
+    }
+
     /// Deallocates a range of the given size from the buffer.
     fn deallocate_range(&mut self, range: Range<u64>) {
         if range.is_empty() {
@@ -216,3 +449,41 @@ impl MaterialStorage {
         Self {}
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indices_keys_compare_the_data() {
+        let key = IndicesKey::new(wgpu::IndexFormat::Uint16, &[0, 1, 2, 3]);
+        assert_eq!(
+            key,
+            IndicesKey::new(wgpu::IndexFormat::Uint16, &[0, 1, 2, 3])
+        );
+        // Same format and size, different data.
+        assert_ne!(
+            key,
+            IndicesKey::new(wgpu::IndexFormat::Uint16, &[0, 1, 3, 2])
+        );
+        assert_ne!(
+            key,
+            IndicesKey::new(wgpu::IndexFormat::Uint32, &[0, 1, 2, 3])
+        );
+    }
+
+    #[test]
+    fn mesh_buffer_fragmentation() {
+        let empty = MeshBufferStats::default();
+        assert_eq!(empty.fragmentation(), 0.0);
+
+        let stats = MeshBufferStats {
+            capacity: 1024,
+            used: 256,
+            span: 512,
+            mesh_count: 2,
+        };
+        assert_eq!(stats.wasted(), 256);
+        assert_eq!(stats.fragmentation(), 0.5);
+    }
+}
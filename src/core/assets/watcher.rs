@@ -0,0 +1,78 @@
+use crate::core::FxHashSet;
+use crossbeam_channel::Receiver;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+
+/// Watches asset files for modifications on disk.
+///
+/// The parent directories of the files are watched rather than the files
+/// themselves, as many editors save files by replacing them.
+pub struct FileWatcher {
+    /// The underlying file system watcher.
+    watcher: notify::RecommendedWatcher,
+    /// Receives the paths of the modified files.
+    receiver: Receiver<PathBuf>,
+    /// Watched directories.
+    dirs: FxHashSet<PathBuf>,
+    /// Watched files (canonical paths).
+    files: FxHashSet<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Creates a new file watcher watching no files.
+    pub fn new() -> notify::Result<Self> {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| match res {
+                Ok(event) => {
+                    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                        for path in event.paths {
+                            let _ = sender.send(path);
+                        }
+                    }
+                }
+                Err(err) => log::error!("File watcher error: {}", err),
+            })?;
+        Ok(Self {
+            watcher,
+            receiver,
+            dirs: FxHashSet::default(),
+            files: FxHashSet::default(),
+        })
+    }
+
+    /// Starts watching the given file.
+    pub fn watch(&mut self, path: &Path) {
+        let path = match path.canonicalize() {
+            Ok(path) => path,
+            Err(err) => {
+                log::error!("Can't watch {:?}: {}", path, err);
+                return;
+            }
+        };
+        if let Some(dir) = path.parent() {
+            if !self.dirs.contains(dir) {
+                match self.watcher.watch(dir, RecursiveMode::NonRecursive) {
+                    Ok(_) => {
+                        self.dirs.insert(dir.to_path_buf());
+                    }
+                    Err(err) => {
+                        log::error!("Can't watch {:?}: {}", dir, err);
+                        return;
+                    }
+                }
+            }
+        }
+        self.files.insert(path);
+    }
+
+    /// Returns the canonical paths of the watched files modified since the
+    /// last call. Each file is reported once.
+    pub fn changed_files(&self) -> FxHashSet<PathBuf> {
+        self.receiver
+            .try_iter()
+            .filter_map(|path| path.canonicalize().ok())
+            .filter(|path| self.files.contains(path))
+            .collect()
+    }
+}
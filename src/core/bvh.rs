@@ -0,0 +1,608 @@
+//! Bounding volume hierarchies for ray and nearest-point queries on the CPU.
+//!
+//! [`Bvh`] is built over any primitives given with their bounding boxes, for
+//! example the instances of a scene, and can be refitted when the primitives
+//! move. [`TriangleBvh`] builds one over the triangles of a mesh.
+
+use crate::core::mesh::Mesh;
+use glam::{Mat4, Vec3};
+
+/// A ray starting at `origin` going along `direction`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn new(origin: Vec3, direction: Vec3) -> Self {
+        Self { origin, direction }
+    }
+
+    /// Returns the point at distance `t` along the ray, in units of the
+    /// length of its direction.
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
+/// Axis-aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    /// Box containing nothing, the identity of [`Aabb::union`].
+    pub const EMPTY: Self = Self {
+        min: Vec3::splat(f32::INFINITY),
+        max: Vec3::splat(f32::NEG_INFINITY),
+    };
+
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// Returns the smallest box containing the points.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        points.into_iter().fold(Self::EMPTY, |aabb, p| aabb.grow(p))
+    }
+
+    /// Returns whether the box contains nothing.
+    pub fn is_empty(&self) -> bool {
+        self.min.cmpgt(self.max).any()
+    }
+
+    /// Returns the box grown to contain the point.
+    pub fn grow(&self, p: Vec3) -> Self {
+        Self::new(self.min.min(p), self.max.max(p))
+    }
+
+    /// Returns the smallest box containing both boxes.
+    pub fn union(&self, other: &Self) -> Self {
+        Self::new(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    /// Returns the smallest box containing the box transformed by the
+    /// matrix.
+    pub fn transform(&self, m: &Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+        let center = m.transform_point3(self.center());
+        let half = self.extent() * 0.5;
+        let half = m.x_axis.truncate().abs() * half.x
+            + m.y_axis.truncate().abs() * half.y
+            + m.z_axis.truncate().abs() * half.z;
+        Self::new(center - half, center + half)
+    }
+
+    /// Returns the squared distance from the point to the box, zero if the
+    /// point is inside.
+    pub fn distance_squared(&self, p: Vec3) -> f32 {
+        (self.min - p)
+            .max(p - self.max)
+            .max(Vec3::ZERO)
+            .length_squared()
+    }
+
+    /// Returns the distance along the ray at which it enters the box, if it
+    /// does before `max_t`. `inv_dir` is the inverse of the ray direction.
+    pub fn intersect(&self, ray: &Ray, inv_dir: Vec3, max_t: f32) -> Option<f32> {
+        let t0 = (self.min - ray.origin) * inv_dir;
+        let t1 = (self.max - ray.origin) * inv_dir;
+        let t_near = t0.min(t1).max_element().max(0.0);
+        let t_far = t0.max(t1).min_element().min(max_t);
+        (t_near <= t_far).then_some(t_near)
+    }
+}
+
+impl From<(Vec3, Vec3)> for Aabb {
+    fn from((min, max): (Vec3, Vec3)) -> Self {
+        Self::new(min, max)
+    }
+}
+
+/// Node of a BVH, either a leaf with `count` primitives starting at
+/// `start`, or an inner node whose children are at `start` and `start + 1`.
+#[derive(Clone, Copy, Debug)]
+struct BvhNode {
+    bounds: Aabb,
+    start: u32,
+    count: u32,
+}
+
+impl BvhNode {
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+/// Bounding volume hierarchy over primitives of type `T`.
+///
+/// The hierarchy only knows the bounding boxes of the primitives, the
+/// queries call back to test the primitives themselves.
+#[derive(Clone, Debug)]
+pub struct Bvh<T> {
+    /// Nodes of the hierarchy, the children of a node always come after it.
+    nodes: Vec<BvhNode>,
+    /// Primitives, ordered by leaf.
+    items: Vec<T>,
+    /// Bounding box of each primitive.
+    bounds: Vec<Aabb>,
+}
+
+impl<T> Bvh<T> {
+    /// Maximum number of primitives in a leaf.
+    const MAX_LEAF_SIZE: usize = 4;
+
+    /// Builds the BVH over the primitives given with their bounding boxes.
+    pub fn new(items: impl IntoIterator<Item = (T, Aabb)>) -> Self {
+        profiling::scope!("Bvh::new");
+        let mut prims = items.into_iter().collect::<Vec<_>>();
+        let mut nodes = Vec::with_capacity(prims.len().max(1) * 2);
+        nodes.push(BvhNode {
+            bounds: Aabb::EMPTY,
+            start: 0,
+            count: 0,
+        });
+        Self::split(&mut nodes, 0, &mut prims, 0);
+        let (items, bounds) = prims.into_iter().unzip();
+        Self {
+            nodes,
+            items,
+            bounds,
+        }
+    }
+
+    /// Returns the number of primitives.
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns the bounds of all the primitives.
+    pub fn bounds(&self) -> Aabb {
+        self.nodes[0].bounds
+    }
+
+    /// Returns the primitives, in no particular order.
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Fills the node `idx` with the primitives `prims`, starting at `start`
+    /// in the final order, splitting them at the middle of the longest axis
+    /// of their centers.
+    fn split(nodes: &mut Vec<BvhNode>, idx: usize, prims: &mut [(T, Aabb)], start: u32) {
+        let bounds = prims.iter().fold(Aabb::EMPTY, |aabb, (_, b)| aabb.union(b));
+        nodes[idx].bounds = bounds;
+        if prims.len() <= Self::MAX_LEAF_SIZE {
+            nodes[idx].start = start;
+            nodes[idx].count = prims.len() as u32;
+            return;
+        }
+
+        let centers = Aabb::from_points(prims.iter().map(|(_, b)| b.center()));
+        let extent = centers.extent();
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let mid = centers.center()[axis];
+        let mut n_left = partition(prims, |(_, b)| b.center()[axis] < mid);
+        // Split in halves if all the centers are on the same side.
+        if n_left == 0 || n_left == prims.len() {
+            n_left = prims.len() / 2;
+            prims.select_nth_unstable_by(n_left, |(_, a), (_, b)| {
+                a.center()[axis].total_cmp(&b.center()[axis])
+            });
+        }
+
+        let children = nodes.len();
+        nodes[idx].start = children as u32;
+        nodes[idx].count = 0;
+        for _ in 0..2 {
+            nodes.push(BvhNode {
+                bounds: Aabb::EMPTY,
+                start: 0,
+                count: 0,
+            });
+        }
+        let (left, right) = prims.split_at_mut(n_left);
+        Self::split(nodes, children, left, start);
+        Self::split(nodes, children + 1, right, start + n_left as u32);
+    }
+
+    /// Updates the bounding boxes of the primitives, after they moved,
+    /// without changing the hierarchy.
+    ///
+    /// Queries stay correct however far the primitives move, but get slower
+    /// if they move too much from where the BVH was built.
+    pub fn refit(&mut self, mut bounds: impl FnMut(&T) -> Aabb) {
+        profiling::scope!("Bvh::refit");
+        for (item, aabb) in self.items.iter().zip(self.bounds.iter_mut()) {
+            *aabb = bounds(item);
+        }
+        for idx in (0..self.nodes.len()).rev() {
+            let node = self.nodes[idx];
+            let start = node.start as usize;
+            self.nodes[idx].bounds = if node.is_leaf() {
+                self.bounds[start..start + node.count as usize]
+                    .iter()
+                    .fold(Aabb::EMPTY, |aabb, b| aabb.union(b))
+            } else if idx == 0 && self.items.is_empty() {
+                Aabb::EMPTY
+            } else {
+                self.nodes[start]
+                    .bounds
+                    .union(&self.nodes[start + 1].bounds)
+            };
+        }
+    }
+
+    /// Returns the closest intersection of the ray closer than `max_t`.
+    ///
+    /// `hit` tests the ray against a primitive whose bounding box it enters,
+    /// and returns the distance along the ray and any data about the hit, if
+    /// the primitive is hit closer than the given distance.
+    pub fn intersect<H>(
+        &self,
+        ray: &Ray,
+        max_t: f32,
+        mut hit: impl FnMut(&T, f32) -> Option<(f32, H)>,
+    ) -> Option<(f32, H)> {
+        if self.is_empty() {
+            return None;
+        }
+        let inv_dir = ray.direction.recip();
+        let mut closest = None;
+        let mut max_t = max_t;
+        let mut stack = vec![0usize];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if node.bounds.intersect(ray, inv_dir, max_t).is_none() {
+                continue;
+            }
+            if node.is_leaf() {
+                let range = node.start as usize..(node.start + node.count) as usize;
+                for item in &self.items[range] {
+                    if let Some((t, data)) = hit(item, max_t) {
+                        max_t = t;
+                        closest = Some((t, data));
+                    }
+                }
+            } else {
+                // Visit the closest child first.
+                let (left, right) = (node.start as usize, node.start as usize + 1);
+                let t_left = self.nodes[left].bounds.intersect(ray, inv_dir, max_t);
+                let t_right = self.nodes[right].bounds.intersect(ray, inv_dir, max_t);
+                match (t_left, t_right) {
+                    (Some(l), Some(r)) if l <= r => stack.extend([right, left]),
+                    (Some(_), Some(_)) => stack.extend([left, right]),
+                    (Some(_), None) => stack.push(left),
+                    (None, Some(_)) => stack.push(right),
+                    (None, None) => {}
+                }
+            }
+        }
+        closest
+    }
+
+    /// Returns the primitive closest to the point, closer than `max_dist`.
+    ///
+    /// `nearest` returns the distance from the point to a primitive and any
+    /// data about the closest point, if it is closer than the given distance.
+    pub fn nearest<H>(
+        &self,
+        point: Vec3,
+        max_dist: f32,
+        mut nearest: impl FnMut(&T, f32) -> Option<(f32, H)>,
+    ) -> Option<(f32, H)> {
+        if self.is_empty() {
+            return None;
+        }
+        let mut closest = None;
+        let mut max_dist = max_dist;
+        let mut stack = vec![0usize];
+        while let Some(idx) = stack.pop() {
+            let node = &self.nodes[idx];
+            if node.bounds.distance_squared(point) >= max_dist * max_dist {
+                continue;
+            }
+            if node.is_leaf() {
+                let range = node.start as usize..(node.start + node.count) as usize;
+                for item in &self.items[range] {
+                    if let Some((dist, data)) = nearest(item, max_dist) {
+                        max_dist = dist;
+                        closest = Some((dist, data));
+                    }
+                }
+            } else {
+                // Visit the closest child first.
+                let (left, right) = (node.start as usize, node.start as usize + 1);
+                let d_left = self.nodes[left].bounds.distance_squared(point);
+                let d_right = self.nodes[right].bounds.distance_squared(point);
+                if d_left <= d_right {
+                    stack.extend([right, left]);
+                } else {
+                    stack.extend([left, right]);
+                }
+            }
+        }
+        closest
+    }
+}
+
+/// Intersection of a ray with a triangle of a [`TriangleBvh`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TriangleHit {
+    /// Distance along the ray, in units of the length of its direction.
+    pub t: f32,
+    /// Index of the triangle in the mesh.
+    pub triangle: u32,
+    /// Geometric normal of the triangle, following its winding order.
+    pub normal: Vec3,
+}
+
+/// Point of a [`TriangleBvh`] closest to a query point.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrianglePoint {
+    pub point: Vec3,
+    /// Distance to the query point.
+    pub distance: f32,
+    /// Index of the triangle in the mesh.
+    pub triangle: u32,
+}
+
+/// Triangle of a mesh, with its index in the mesh.
+#[derive(Clone, Copy, Debug)]
+struct Triangle {
+    index: u32,
+    vertices: [Vec3; 3],
+}
+
+/// Bounding volume hierarchy over the triangles of a mesh.
+#[derive(Clone, Debug)]
+pub struct TriangleBvh {
+    bvh: Bvh<Triangle>,
+}
+
+impl TriangleBvh {
+    /// Builds the BVH over the triangles given by vertex indices into
+    /// `positions`.
+    pub fn new(positions: &[[f32; 3]], triangles: &[[u32; 3]]) -> Self {
+        profiling::scope!("TriangleBvh::new");
+        let triangles = triangles.iter().enumerate().map(|(i, tri)| {
+            let vertices = tri.map(|v| Vec3::from(positions[v as usize]));
+            let triangle = Triangle {
+                index: i as u32,
+                vertices,
+            };
+            (triangle, Aabb::from_points(vertices))
+        });
+        Self {
+            bvh: Bvh::new(triangles),
+        }
+    }
+
+    /// Builds the BVH over the triangles of the mesh, or returns `None` if
+    /// the mesh is not made of triangles.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        Some(Self::new(mesh.positions()?, &mesh.triangles()?))
+    }
+
+    /// Returns the number of triangles.
+    pub fn len(&self) -> usize {
+        self.bvh.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bvh.is_empty()
+    }
+
+    /// Returns the bounds of all the triangles.
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    /// Returns the triangles with their index in the mesh, in no particular
+    /// order.
+    pub fn triangles(&self) -> impl ExactSizeIterator<Item = (u32, [Vec3; 3])> + '_ {
+        self.bvh
+            .items()
+            .iter()
+            .map(|triangle| (triangle.index, triangle.vertices))
+    }
+
+    /// Returns the closest intersection of the ray with the triangles closer
+    /// than `max_t`. Triangles are hit from both sides.
+    pub fn intersect(&self, ray: &Ray, max_t: f32) -> Option<TriangleHit> {
+        self.bvh
+            .intersect(ray, max_t, |triangle, max_t| {
+                let t = intersect_triangle(ray, &triangle.vertices, max_t)?;
+                Some((t, *triangle))
+            })
+            .map(|(t, triangle)| {
+                let [a, b, c] = triangle.vertices;
+                TriangleHit {
+                    t,
+                    triangle: triangle.index,
+                    normal: (b - a).cross(c - a).normalize_or_zero(),
+                }
+            })
+    }
+
+    /// Returns the point on the triangles closest to `point`, if it is
+    /// closer than `max_dist`.
+    pub fn nearest_point(&self, point: Vec3, max_dist: f32) -> Option<TrianglePoint> {
+        self.bvh
+            .nearest(point, max_dist, |triangle, max_dist| {
+                let closest = closest_point_on_triangle(point, &triangle.vertices);
+                let dist = closest.distance(point);
+                (dist < max_dist).then_some((dist, (closest, triangle.index)))
+            })
+            .map(|(distance, (point, triangle))| TrianglePoint {
+                point,
+                distance,
+                triangle,
+            })
+    }
+}
+
+/// Moves the elements satisfying the predicate to the front, returns their
+/// number.
+fn partition<T>(items: &mut [T], pred: impl Fn(&T) -> bool) -> usize {
+    let mut n = 0;
+    for i in 0..items.len() {
+        if pred(&items[i]) {
+            items.swap(i, n);
+            n += 1;
+        }
+    }
+    n
+}
+
+/// Möller-Trumbore ray-triangle intersection, returns the distance along the
+/// ray if the triangle is hit before `max_t`.
+fn intersect_triangle(ray: &Ray, [a, b, c]: &[Vec3; 3], max_t: f32) -> Option<f32> {
+    let e1 = *b - *a;
+    let e2 = *c - *a;
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f32::EPSILON {
+        return None;
+    }
+    let inv_det = det.recip();
+    let s = ray.origin - *a;
+    let u = s.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = e2.dot(q) * inv_det;
+    (t >= 0.0 && t < max_t).then_some(t)
+}
+
+/// Returns the point of the triangle closest to `p`, from the Voronoi region
+/// of the triangle `p` is in (Ericson, Real-Time Collision Detection, 5.1.5).
+fn closest_point_on_triangle(p: Vec3, [a, b, c]: &[Vec3; 3]) -> Vec3 {
+    let (a, b, c) = (*a, *b, *c);
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 >= d3 && d5 >= d6 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = (va + vb + vc).recip();
+    let closest = a + ab * (vb * denom) + ac * (vc * denom);
+    // Degenerate triangles have no inside.
+    if closest.is_finite() {
+        closest
+    } else {
+        a
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_hits_closest_triangle() {
+        let mesh = Mesh::sphere(1.0, 32, 16);
+        let bvh = TriangleBvh::from_mesh(&mesh).unwrap();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::NEG_Z);
+        let hit = bvh.intersect(&ray, f32::INFINITY).unwrap();
+        assert!((hit.t - 4.0).abs() < 0.05);
+        assert!(hit.normal.z.abs() > 0.9);
+        assert!(bvh.intersect(&ray, 3.0).is_none());
+
+        let miss = Ray::new(Vec3::new(2.0, 0.0, 5.0), Vec3::NEG_Z);
+        assert!(bvh.intersect(&miss, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn nearest_point_on_sphere() {
+        let mesh = Mesh::sphere(1.0, 32, 16);
+        let bvh = TriangleBvh::from_mesh(&mesh).unwrap();
+        let nearest = bvh
+            .nearest_point(Vec3::new(3.0, 0.0, 0.0), f32::INFINITY)
+            .unwrap();
+        assert!((nearest.distance - 2.0).abs() < 0.05);
+        assert!((nearest.point - Vec3::X).length() < 0.05);
+        assert!(bvh.nearest_point(Vec3::new(3.0, 0.0, 0.0), 1.5).is_none());
+    }
+
+    #[test]
+    fn refit_follows_moved_boxes() {
+        let mut offsets = (0..16).map(|i| i as f32 * 2.0).collect::<Vec<_>>();
+        let unit = Aabb::new(Vec3::ZERO, Vec3::ONE);
+        let boxes = |offsets: &[f32], i: usize| {
+            let offset = Vec3::new(offsets[i], 0.0, 0.0);
+            Aabb::new(unit.min + offset, unit.max + offset)
+        };
+        let mut bvh = Bvh::new((0..offsets.len()).map(|i| (i, boxes(&offsets, i))));
+        let ray = Ray::new(Vec3::new(-1.0, 0.5, 0.5), Vec3::X);
+        let hit_box = |bvh: &Bvh<usize>, offsets: &[f32]| {
+            let inv_dir = ray.direction.recip();
+            bvh.intersect(&ray, f32::INFINITY, |i, max_t| {
+                let t = boxes(offsets, *i).intersect(&ray, inv_dir, max_t)?;
+                Some((t, *i))
+            })
+            .map(|(_, i)| i)
+        };
+        assert_eq!(hit_box(&bvh, &offsets), Some(0));
+
+        // Move the first box behind the last one.
+        offsets[0] = 40.0;
+        bvh.refit(|i| boxes(&offsets, *i));
+        assert_eq!(hit_box(&bvh, &offsets), Some(1));
+        assert_eq!(bvh.bounds().max.x, 41.0);
+    }
+}
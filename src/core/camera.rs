@@ -1,6 +1,6 @@
-use crate::core::Color;
+use crate::core::{sky::Sky, Color};
 use glam::Mat4;
-use std::{fmt::Debug, ops::Range};
+use std::{fmt::Debug, ops::Range, path::PathBuf};
 
 /// The type of projection for a camera.
 #[pyo3::pyclass]
@@ -13,6 +13,34 @@ pub enum ProjectionKind {
     Perspective,
 }
 
+/// Easing function of an animation.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Default)]
+pub enum Easing {
+    /// Constant speed.
+    Linear,
+    /// Starts slowly and accelerates.
+    EaseIn,
+    /// Starts fast and decelerates.
+    EaseOut,
+    /// Accelerates then decelerates.
+    #[default]
+    EaseInOut,
+}
+
+impl Easing {
+    /// Applies the easing function to a normalized time in `[0, 1]`.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t * t,
+            Easing::EaseOut => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
 /// Describes the projection settings for a camera.
 #[pyo3::pyclass]
 #[derive(Clone, Copy)]
@@ -59,30 +87,52 @@ impl Debug for Projection {
 
 impl Projection {
     /// Returns the projection matrix for this projection.
-    pub fn matrix(&self, aspect: f32) -> Mat4 {
+    ///
+    /// With `reversed_z`, the depth goes from 1 at the near plane to 0 at the
+    /// far plane, which may be infinitely far for perspective projections.
+    pub fn matrix(&self, aspect: f32, reversed_z: bool) -> Mat4 {
+        // Swapping the planes reverses the depth.
+        let (near, far) = if reversed_z {
+            (self.max_depth, self.min_depth)
+        } else {
+            (self.min_depth, self.max_depth)
+        };
         match self.kind {
             ProjectionKind::Orthographic => {
                 let extent_v = unsafe { self.fov_or_ext.extent };
                 let half_extent_v = extent_v * 0.5;
+                let half_extent_h = half_extent_v * aspect;
                 Mat4::orthographic_rh(
+                    -half_extent_h,
+                    half_extent_h,
                     -half_extent_v,
                     half_extent_v,
-                    -half_extent_v,
-                    half_extent_v,
-                    self.min_depth,
-                    self.max_depth,
+                    near,
+                    far,
                 )
             }
             ProjectionKind::Perspective => {
                 let fov_v = unsafe { self.fov_or_ext.fov }.to_radians();
                 if self.max_depth == f32::INFINITY {
-                    Mat4::perspective_infinite_rh(fov_v, aspect, self.min_depth)
+                    if reversed_z {
+                        Mat4::perspective_infinite_reverse_rh(fov_v, aspect, self.min_depth)
+                    } else {
+                        Mat4::perspective_infinite_rh(fov_v, aspect, self.min_depth)
+                    }
                 } else {
-                    Mat4::perspective_rh(fov_v, aspect, self.min_depth, self.max_depth)
+                    Mat4::perspective_rh(fov_v, aspect, near, far)
                 }
             }
         }
     }
+
+    /// Sets the vertical field of view in degrees of a perspective
+    /// projection. Does nothing for orthographic projections.
+    pub fn set_fov(&mut self, fov: f32) {
+        if self.kind == ProjectionKind::Perspective {
+            self.fov_or_ext = VerticalFovOrExtent { fov };
+        }
+    }
 }
 
 #[pyo3::pymethods]
@@ -120,15 +170,71 @@ pub union VerticalFovOrExtent {
     extent: f32,
 }
 
+/// Background drawn behind the scene, over the background color of a camera.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Backdrop {
+    /// Vertical gradient from the bottom to the top of the screen.
+    Gradient { top: Color, bottom: Color },
+    /// Image covering the screen, cropped to keep its aspect ratio.
+    Image(PathBuf),
+    /// Analytical sky with the sun disk, and the ground below the horizon.
+    Sky(Sky),
+}
+
+/// Adaptation of the exposure of a camera to the average luminance of its
+/// image, see [`Camera::auto_exposure`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoExposure {
+    /// Average luminance the exposure brings the image to, 0.18 for a
+    /// middle grey.
+    pub key: f32,
+    /// Rate of the adaptation per second; the exposure covers about 63% of
+    /// the way to its target after `1 / speed` seconds.
+    pub speed: f32,
+    /// Lowest exposure in EV.
+    pub min_ev: f32,
+    /// Highest exposure in EV.
+    pub max_ev: f32,
+}
+
+impl Default for AutoExposure {
+    fn default() -> Self {
+        Self {
+            key: 0.18,
+            speed: 1.5,
+            min_ev: -4.0,
+            max_ev: 4.0,
+        }
+    }
+}
+
 /// A camera component.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Camera {
     /// The projection settings for this camera.
     pub proj: Projection,
     /// Background color for this camera.
     pub background: Color,
+    /// Background drawn over the background color, if any.
+    pub backdrop: Option<Backdrop>,
     /// If this camera is the main camera.
     pub is_main: bool,
+    /// Bit mask of the render layers visible to this camera. Bit `i` is set
+    /// if layer `i` is visible. Defaults to all layers.
+    pub layer_mask: u32,
+    /// Aspect ratio (width / height) of the image of the camera, follows the
+    /// size of the window unless the aspect ratio is fixed.
+    pub aspect: f32,
+    /// Fixed aspect ratio of the image, which is then letterboxed in the
+    /// window. Follows the window if `None`.
+    pub fixed_aspect: Option<f32>,
+    /// Exposure in EV: the lit colors are scaled by `2^exposure`. Added to
+    /// the adapted exposure if auto-exposure is enabled.
+    pub exposure: f32,
+    /// Adapts the exposure to the luminance of the previous frames if set.
+    /// Only the main window measures its frames, the camera textures keep
+    /// the manual exposure.
+    pub auto_exposure: Option<AutoExposure>,
 }
 
 impl Camera {
@@ -137,7 +243,13 @@ impl Camera {
         Self {
             proj,
             background,
+            backdrop: None,
             is_main: main,
+            layer_mask: u32::MAX,
+            aspect: 1.0,
+            fixed_aspect: None,
+            exposure: 0.0,
+            auto_exposure: None,
         }
     }
 
@@ -159,8 +271,15 @@ impl Camera {
         )
     }
 
-    /// Returns the projection matrix for this camera.
-    pub fn proj_matrix(&self, aspect: f32) -> Mat4 {
-        self.proj.matrix(aspect)
+    /// Returns the projection matrix for this camera, with the depth
+    /// reversed if `reversed_z`, see [`Projection::matrix`].
+    pub fn proj_matrix(&self, aspect: f32, reversed_z: bool) -> Mat4 {
+        self.proj.matrix(aspect, reversed_z)
+    }
+
+    /// Returns the projection matrix for the current aspect ratio of the
+    /// camera.
+    pub fn current_proj_matrix(&self) -> Mat4 {
+        self.proj.matrix(self.aspect, false)
     }
 }
@@ -86,6 +86,106 @@ impl Color {
         let hex = u32::from_str_radix(hex, 16).unwrap_or(0);
         Self::from_hex(hex)
     }
+
+    /// Creates a color from sRGB encoded components, e.g. from a color picker
+    /// or an image, converting them to linear.
+    #[inline]
+    pub fn from_srgb(r: f64, g: f64, b: f64, a: f64) -> Self {
+        Self::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a)
+    }
+
+    /// Returns the sRGB encoded `[r, g, b, a]` components of the color.
+    #[inline]
+    pub fn to_srgb(&self) -> [f64; 4] {
+        [
+            linear_to_srgb(self.0.r),
+            linear_to_srgb(self.0.g),
+            linear_to_srgb(self.0.b),
+            self.0.a,
+        ]
+    }
+
+    /// Creates an opaque color from hue in degrees, saturation and value in
+    /// `[0, 1]`, as used by color pickers, i.e. describing the sRGB encoded
+    /// color.
+    pub fn from_hsv(h: f64, s: f64, v: f64) -> Self {
+        let c = v * s;
+        let (r, g, b) = hue_to_rgb(h, c);
+        let m = v - c;
+        Self::from_srgb(r + m, g + m, b + m, 1.0)
+    }
+
+    /// Creates an opaque color from hue in degrees, saturation and lightness
+    /// in `[0, 1]`, describing the sRGB encoded color.
+    pub fn from_hsl(h: f64, s: f64, l: f64) -> Self {
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let (r, g, b) = hue_to_rgb(h, c);
+        let m = l - c * 0.5;
+        Self::from_srgb(r + m, g + m, b + m, 1.0)
+    }
+
+    /// Returns the hue in degrees, the saturation and the value of the sRGB
+    /// encoded color; the inverse of [`Color::from_hsv`].
+    pub fn to_hsv(&self) -> (f64, f64, f64) {
+        let [r, g, b, _] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+        let h = if delta <= 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let s = if max <= 0.0 { 0.0 } else { delta / max };
+        (h, s, max)
+    }
+
+    /// Linearly interpolates between this color and `other`, alpha included.
+    #[inline]
+    pub fn lerp(&self, other: Color, t: f64) -> Self {
+        Self::new(
+            self.0.r + (other.0.r - self.0.r) * t,
+            self.0.g + (other.0.g - self.0.g) * t,
+            self.0.b + (other.0.b - self.0.b) * t,
+            self.0.a + (other.0.a - self.0.a) * t,
+        )
+    }
+}
+
+/// Converts an sRGB encoded component to linear.
+pub fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear component to sRGB encoded.
+pub fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Returns the RGB components of the hue in degrees with the given chroma,
+/// before adding the offset of the value or lightness.
+fn hue_to_rgb(h: f64, c: f64) -> (f64, f64, f64) {
+    let h = h.rem_euclid(360.0) / 60.0;
+    let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+    match h as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    }
 }
 
 impl Deref for Color {
@@ -207,4 +307,198 @@ impl Color {
     pub fn new_py(r: f64, g: f64, b: f64) -> Self {
         Self::new(r, g, b, 1.0)
     }
+
+    #[staticmethod]
+    #[pyo3(name = "from_srgb", signature = (r, g, b, a=1.0))]
+    pub fn from_srgb_py(r: f64, g: f64, b: f64, a: f64) -> Self {
+        Self::from_srgb(r, g, b, a)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_hsv")]
+    pub fn from_hsv_py(h: f64, s: f64, v: f64) -> Self {
+        Self::from_hsv(h, s, v)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_hsl")]
+    pub fn from_hsl_py(h: f64, s: f64, l: f64) -> Self {
+        Self::from_hsl(h, s, l)
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_hex")]
+    pub fn from_hex_py(hex: &str) -> Self {
+        Self::from_hex_str(hex)
+    }
+
+    #[pyo3(name = "to_srgb")]
+    pub fn to_srgb_py(&self) -> [f64; 4] {
+        self.to_srgb()
+    }
+
+    #[pyo3(name = "to_hsv")]
+    pub fn to_hsv_py(&self) -> (f64, f64, f64) {
+        self.to_hsv()
+    }
+
+    #[pyo3(name = "lerp")]
+    pub fn lerp_py(&self, other: Color, t: f64) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+/// Colors evaluated along `[0, 1]` by interpolating between color stops,
+/// e.g. to map data such as sunlight scores to colors.
+#[pyo3::pyclass]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    /// Positions and colors of the stops, sorted by position.
+    stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    /// Creates a gradient from stops at arbitrary positions.
+    ///
+    /// Positions outside `[0, 1]` are allowed; before the first and after the
+    /// last stop, the gradient keeps the color of that stop.
+    pub fn new(stops: impl IntoIterator<Item = (f32, Color)>) -> Self {
+        let mut stops: Vec<_> = stops.into_iter().collect();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { stops }
+    }
+
+    /// Creates a gradient from colors evenly spaced over `[0, 1]`.
+    pub fn uniform(colors: impl IntoIterator<Item = Color>) -> Self {
+        let colors: Vec<_> = colors.into_iter().collect();
+        let last = colors.len().saturating_sub(1).max(1) as f32;
+        Self::new(
+            colors
+                .into_iter()
+                .enumerate()
+                .map(|(i, color)| (i as f32 / last, color)),
+        )
+    }
+
+    /// Creates a gradient from sRGB encoded `0xRRGGBB` colors evenly spaced
+    /// over `[0, 1]`.
+    fn from_srgb_hex(colors: &[u32]) -> Self {
+        Self::uniform(colors.iter().map(|hex| {
+            let channel = |shift: u32| ((hex >> shift) & 0xFF) as f64 / 255.0;
+            Color::from_srgb(channel(16), channel(8), channel(0), 1.0)
+        }))
+    }
+
+    /// The perceptually uniform viridis colormap, from dark blue to yellow.
+    pub fn viridis() -> Self {
+        Self::from_srgb_hex(&[
+            0x440154, 0x472D7B, 0x3B528B, 0x2C728E, 0x21908C, 0x27AD81, 0x5DC863, 0xAADC32,
+            0xFDE725,
+        ])
+    }
+
+    /// The perceptually uniform inferno colormap, from black to light yellow.
+    pub fn inferno() -> Self {
+        Self::from_srgb_hex(&[
+            0x000004, 0x1B0C42, 0x4B0C6B, 0x781C6D, 0xA52C60, 0xCF4446, 0xED6925, 0xFB9A06,
+            0xFCFFA4,
+        ])
+    }
+
+    /// Returns the stops of the gradient, sorted by position.
+    pub fn stops(&self) -> &[(f32, Color)] {
+        &self.stops
+    }
+
+    /// Returns the color at `t`, black if the gradient has no stops.
+    pub fn evaluate(&self, t: f32) -> Color {
+        let next = self.stops.partition_point(|(pos, _)| *pos <= t);
+        match (self.stops.get(next.wrapping_sub(1)), self.stops.get(next)) {
+            (Some((a, ca)), Some((b, cb))) => ca.lerp(*cb, ((t - a) / (b - a)) as f64),
+            (Some((_, c)), None) | (None, Some((_, c))) => *c,
+            (None, None) => Color::BLACK,
+        }
+    }
+}
+
+#[pyo3::pymethods]
+impl Gradient {
+    /// Creates a gradient from a list of colors evenly spaced over `[0, 1]`
+    /// or of `(position, color)` stops.
+    #[new]
+    pub fn new_py(stops: &pyo3::PyAny) -> pyo3::PyResult<Self> {
+        match stops.extract::<Vec<(f32, Color)>>() {
+            Ok(stops) => Ok(Self::new(stops)),
+            Err(_) => Ok(Self::uniform(stops.extract::<Vec<Color>>()?)),
+        }
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "viridis")]
+    pub fn viridis_py() -> Self {
+        Self::viridis()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "inferno")]
+    pub fn inferno_py() -> Self {
+        Self::inferno()
+    }
+
+    #[pyo3(name = "evaluate")]
+    pub fn evaluate_py(&self, t: f32) -> Color {
+        self.evaluate(t)
+    }
+
+    /// Evaluates the gradient at each value of the array, returning an array
+    /// of the same shape with an extra axis for the linear RGBA components.
+    pub fn evaluate_array(
+        &self,
+        py: pyo3::Python<'_>,
+        values: &numpy::PyArrayDyn<f32>,
+    ) -> pyo3::PyResult<pyo3::Py<numpy::PyArrayDyn<f32>>> {
+        let mut shape = values.shape().to_vec();
+        shape.push(4);
+        let colors: Vec<f32> = values
+            .readonly()
+            .as_array()
+            .iter()
+            .flat_map(|t| <[f32; 4]>::from(self.evaluate(*t)))
+            .collect();
+        Ok(numpy::PyArray1::from_vec(py, colors)
+            .reshape(shape)?
+            .to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsv_round_trips() {
+        for (h, s, v) in [(0.0, 1.0, 1.0), (120.0, 0.5, 0.8), (275.0, 0.25, 0.4)] {
+            let (h2, s2, v2) = Color::from_hsv(h, s, v).to_hsv();
+            assert!((h - h2).abs() < 1e-6 && (s - s2).abs() < 1e-6 && (v - v2).abs() < 1e-6);
+        }
+        assert_eq!(Color::from_hsl(240.0, 1.0, 0.5), Color::BLUE);
+    }
+
+    #[test]
+    fn gradient_interpolates_and_clamps() {
+        let gradient = Gradient::new([(1.0, Color::WHITE), (0.0, Color::BLACK)]);
+        assert_eq!(gradient.evaluate(-1.0), Color::BLACK);
+        assert_eq!(gradient.evaluate(0.5), color!(0.5, 0.5, 0.5));
+        assert_eq!(gradient.evaluate(2.0), Color::WHITE);
+        let viridis = Gradient::viridis();
+        assert_eq!(
+            viridis.evaluate(1.0),
+            Color::from_srgb(
+                0xFD as f64 / 255.0,
+                0xE7 as f64 / 255.0,
+                0x25 as f64 / 255.0,
+                1.0
+            )
+        );
+    }
 }
@@ -0,0 +1,417 @@
+//! Manipulator gizmo translating, rotating and scaling an entity with the
+//! mouse, and the lines of the debug gizmos showing the lights and cameras.
+
+use crate::{
+    core::{bvh::Ray, Color, Transform},
+    scene::Entity,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Quat, Vec3};
+
+/// Transformation applied by the handles of the gizmo.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GizmoMode {
+    /// Arrows moving the entity along the world axes.
+    #[default]
+    Translate,
+    /// Circles rotating the entity around the world axes.
+    Rotate,
+    /// Boxes scaling the entity along its local axes.
+    Scale,
+}
+
+/// Vertex of the handles of the gizmo.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct GizmoVertex {
+    /// Position in world space.
+    pub position: [f32; 3],
+    /// Color of the handle.
+    pub color: [f32; 4],
+}
+
+crate::impl_size_constant!(GizmoVertex);
+
+/// Drag of a handle in progress.
+#[derive(Clone, Copy, Debug)]
+pub struct GizmoDrag {
+    /// Axis of the dragged handle, 0 for X, 1 for Y and 2 for Z.
+    pub axis: usize,
+    /// World transform of the entity when the drag started.
+    pub start: Transform,
+    /// Local transform of the entity when the drag started.
+    pub start_local: Transform,
+    /// Size of the handles when the drag started.
+    pub size: f32,
+    /// Position of the cursor along the axis, or its angle around the axis,
+    /// when the drag started.
+    pub param: f32,
+}
+
+/// Handles drawn over the selected entity.
+#[derive(Clone, Copy, Debug)]
+pub struct Gizmo {
+    /// The manipulated entity.
+    pub target: Entity,
+    /// Transformation applied by the handles.
+    pub mode: GizmoMode,
+    /// Axis of the handle under the cursor, drawn highlighted.
+    pub hovered: Option<usize>,
+    /// Drag in progress, if any.
+    pub drag: Option<GizmoDrag>,
+    /// Whether the left mouse button was pressed at the previous frame, a
+    /// drag starting with a new press.
+    pub pressed: bool,
+}
+
+impl Gizmo {
+    /// Length of the handles per unit of distance to the camera, so that
+    /// they keep the same size on screen.
+    pub const SCREEN_SIZE: f32 = 0.15;
+    /// Distance from a handle within which the cursor picks it, relative to
+    /// the size of the handles.
+    pub const PICK_RADIUS: f32 = 0.08;
+    /// Smallest scale the handles can give to the entity.
+    pub const MIN_SCALE: f32 = 1e-3;
+    /// Number of segments of the circles and cones.
+    const SEGMENTS: usize = 48;
+    /// Color of the handle under the cursor.
+    const HIGHLIGHT: Color = Color::YELLOW;
+    /// Color of the handle of each axis.
+    const AXIS_COLORS: [Color; 3] = [Color::RED, Color::GREEN, Color::BLUE];
+
+    /// Creates a gizmo manipulating the entity.
+    pub fn new(target: Entity, mode: GizmoMode) -> Self {
+        Self {
+            target,
+            mode,
+            hovered: None,
+            drag: None,
+            pressed: false,
+        }
+    }
+
+    /// Returns the size of the handles centered at `origin` seen from the
+    /// camera at `camera_pos`.
+    pub fn size(origin: Vec3, camera_pos: Vec3) -> f32 {
+        (origin.distance(camera_pos) * Self::SCREEN_SIZE).max(f32::EPSILON)
+    }
+
+    /// Returns the axes of the handles for the entity at `transform`, the
+    /// scale handles following the rotation of the entity.
+    pub fn axes(&self, transform: &Transform) -> [Vec3; 3] {
+        match self.mode {
+            GizmoMode::Scale => [Vec3::X, Vec3::Y, Vec3::Z].map(|axis| transform.rotation * axis),
+            _ => [Vec3::X, Vec3::Y, Vec3::Z],
+        }
+    }
+
+    /// Returns the axis of the handle hit by the ray, if any.
+    pub fn pick(&self, transform: &Transform, size: f32, ray: &Ray) -> Option<usize> {
+        let origin = transform.translation;
+        self.axes(transform)
+            .into_iter()
+            .enumerate()
+            .filter_map(|(i, axis)| {
+                let distance = match self.mode {
+                    GizmoMode::Rotate => {
+                        let point = plane_intersection(origin, axis, ray)?;
+                        (point.distance(origin) - size).abs()
+                    }
+                    _ => {
+                        let s = closest_on_axis(origin, axis, ray)?.clamp(0.0, size);
+                        let point = origin + axis * s;
+                        let t = ray.direction.dot(point - ray.origin).max(0.0);
+                        point.distance(ray.at(t))
+                    }
+                };
+                (distance < Self::PICK_RADIUS * size).then_some((i, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    /// Starts dragging the handle of the axis with the cursor on the ray.
+    /// Returns false if the ray doesn't give a position on the handle.
+    pub fn begin_drag(
+        &mut self,
+        axis: usize,
+        (start, start_local): (Transform, Transform),
+        size: f32,
+        ray: &Ray,
+    ) -> bool {
+        let axis_dir = self.axes(&start)[axis];
+        match self.param(start.translation, axis_dir, ray) {
+            Some(param) => {
+                self.drag = Some(GizmoDrag {
+                    axis,
+                    start,
+                    start_local,
+                    size,
+                    param,
+                });
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the local transform of the entity dragged with the cursor on
+    /// the ray, or `None` if no handle is dragged or the ray doesn't give a
+    /// position on the handle.
+    ///
+    /// The translation and rotation are in world space, the scale is local.
+    pub fn drag_to(&self, ray: &Ray) -> Option<Transform> {
+        let drag = self.drag.as_ref()?;
+        let origin = drag.start.translation;
+        let axis = self.axes(&drag.start)[drag.axis];
+        let param = self.param(origin, axis, ray)?;
+        let mut transform = Transform {
+            translation: drag.start.translation,
+            rotation: drag.start.rotation,
+            scale: drag.start_local.scale,
+        };
+        match self.mode {
+            GizmoMode::Translate => transform.translation += axis * (param - drag.param),
+            GizmoMode::Rotate => {
+                transform.rotation =
+                    Quat::from_axis_angle(axis, param - drag.param) * drag.start.rotation;
+            }
+            GizmoMode::Scale => {
+                // Scales by the ratio of the distances to the center, the
+                // cursor starting at least a fraction of the handle away.
+                let start = drag.param.abs().max(Self::PICK_RADIUS * drag.size);
+                let scale = transform.scale[drag.axis] * param / start;
+                transform.scale[drag.axis] = scale.max(Self::MIN_SCALE);
+            }
+        }
+        Some(transform)
+    }
+
+    /// Returns the position of the cursor along the axis for the translation
+    /// and scale handles, or its angle around the axis for the rotation
+    /// handles.
+    fn param(&self, origin: Vec3, axis: Vec3, ray: &Ray) -> Option<f32> {
+        match self.mode {
+            GizmoMode::Rotate => {
+                let offset = plane_intersection(origin, axis, ray)? - origin;
+                let u = axis.any_orthonormal_vector();
+                let v = axis.cross(u);
+                Some(offset.dot(v).atan2(offset.dot(u)))
+            }
+            _ => closest_on_axis(origin, axis, ray),
+        }
+    }
+
+    /// Returns the lines and the triangles of the handles for the entity at
+    /// `transform`, as lists of vertices.
+    pub fn vertices(
+        &self,
+        transform: &Transform,
+        size: f32,
+    ) -> (Vec<GizmoVertex>, Vec<GizmoVertex>) {
+        let origin = transform.translation;
+        let active = self.drag.map(|drag| drag.axis).or(self.hovered);
+        let mut lines = Vec::new();
+        let mut triangles = Vec::new();
+        for (i, axis) in self.axes(transform).into_iter().enumerate() {
+            let color: [f32; 4] = if active == Some(i) {
+                Self::HIGHLIGHT.into()
+            } else {
+                Self::AXIS_COLORS[i].into()
+            };
+            let vertex = |p: Vec3| GizmoVertex {
+                position: p.to_array(),
+                color,
+            };
+            let u = axis.any_orthonormal_vector();
+            let v = axis.cross(u);
+            let circle = |center: Vec3, radius: f32, k: usize| {
+                let angle = k as f32 / Self::SEGMENTS as f32 * std::f32::consts::TAU;
+                center + (u * angle.cos() + v * angle.sin()) * radius
+            };
+            match self.mode {
+                GizmoMode::Translate => {
+                    let base = origin + axis * size * 0.8;
+                    let tip = origin + axis * size;
+                    lines.extend([vertex(origin), vertex(base)]);
+                    for k in 0..Self::SEGMENTS {
+                        let a = circle(base, size * 0.06, k);
+                        let b = circle(base, size * 0.06, k + 1);
+                        triangles.extend([vertex(a), vertex(b), vertex(tip)]);
+                        triangles.extend([vertex(b), vertex(a), vertex(base)]);
+                    }
+                }
+                GizmoMode::Rotate => {
+                    for k in 0..Self::SEGMENTS {
+                        lines.push(vertex(circle(origin, size, k)));
+                        lines.push(vertex(circle(origin, size, k + 1)));
+                    }
+                }
+                GizmoMode::Scale => {
+                    let center = origin + axis * size;
+                    lines.extend([vertex(origin), vertex(center)]);
+                    let half = size * 0.05;
+                    let corner =
+                        |x: f32, y: f32, z: f32| vertex(center + (axis * x + u * y + v * z) * half);
+                    // Two triangles per face of the box.
+                    for (n, a, b) in [(axis, u, v), (u, v, axis), (v, axis, u)] {
+                        for sign in [-1.0, 1.0] {
+                            let c = |s: f32, t: f32| {
+                                let p = n * sign + a * s + b * t;
+                                corner(p.dot(axis), p.dot(u), p.dot(v))
+                            };
+                            triangles.extend([c(-1.0, -1.0), c(1.0, -1.0), c(1.0, 1.0)]);
+                            triangles.extend([c(-1.0, -1.0), c(1.0, 1.0), c(-1.0, 1.0)]);
+                        }
+                    }
+                }
+            }
+        }
+        (lines, triangles)
+    }
+}
+
+/// Returns the lines of the 12 edges of the volume seen through the
+/// view-projection matrix whose inverse is `inv_view_proj`, i.e. the frustum
+/// of a camera or the box of an orthographic projection.
+pub fn frustum_lines(inv_view_proj: Mat4, color: Color) -> Vec<GizmoVertex> {
+    let color: [f32; 4] = color.into();
+    // Corners in normalized device coordinates, the depth in [0, 1].
+    let corner = |i: usize| {
+        let x = if i & 1 == 0 { -1.0 } else { 1.0 };
+        let y = if i & 2 == 0 { -1.0 } else { 1.0 };
+        let z = if i & 4 == 0 { 0.0 } else { 1.0 };
+        GizmoVertex {
+            position: inv_view_proj.project_point3(Vec3::new(x, y, z)).to_array(),
+            color,
+        }
+    };
+    let mut lines = Vec::with_capacity(24);
+    for i in 0..8 {
+        // Each edge joins two corners differing by a single coordinate.
+        for bit in [1, 2, 4] {
+            if i & bit == 0 {
+                lines.extend([corner(i), corner(i | bit)]);
+            }
+        }
+    }
+    lines
+}
+
+/// Returns the lines of an arrow of the given length starting at `origin`
+/// and pointing along `direction`.
+pub fn arrow_lines(origin: Vec3, direction: Vec3, length: f32, color: Color) -> Vec<GizmoVertex> {
+    let color: [f32; 4] = color.into();
+    let vertex = |p: Vec3| GizmoVertex {
+        position: p.to_array(),
+        color,
+    };
+    let direction = direction.normalize_or_zero();
+    let tip = origin + direction * length;
+    let base = origin + direction * length * 0.8;
+    let u = direction.any_orthonormal_vector();
+    let v = direction.cross(u);
+    let mut lines = vec![vertex(origin), vertex(tip)];
+    for side in [u, -u, v, -v] {
+        lines.extend([vertex(tip), vertex(base + side * length * 0.08)]);
+    }
+    lines
+}
+
+/// Returns the lines of a cross along the world axes centered at `center`,
+/// each branch of the given length.
+pub fn cross_lines(center: Vec3, length: f32, color: Color) -> Vec<GizmoVertex> {
+    let color: [f32; 4] = color.into();
+    [Vec3::X, Vec3::Y, Vec3::Z]
+        .into_iter()
+        .flat_map(|axis| {
+            [center - axis * length, center + axis * length].map(|p| GizmoVertex {
+                position: p.to_array(),
+                color,
+            })
+        })
+        .collect()
+}
+
+/// Returns the position along the axis through `origin` of the point the
+/// closest to the ray, or `None` if they are parallel.
+fn closest_on_axis(origin: Vec3, axis: Vec3, ray: &Ray) -> Option<f32> {
+    let w = origin - ray.origin;
+    let b = axis.dot(ray.direction);
+    let c = ray.direction.dot(ray.direction);
+    let denom = axis.dot(axis) * c - b * b;
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+    Some((b * ray.direction.dot(w) - c * axis.dot(w)) / denom)
+}
+
+/// Returns the intersection of the ray with the plane through `origin`
+/// orthogonal to `normal`, if in front of the ray.
+fn plane_intersection(origin: Vec3, normal: Vec3, ray: &Ray) -> Option<Vec3> {
+    let denom = normal.dot(ray.direction);
+    if denom.abs() < 1e-6 {
+        return None;
+    }
+    let t = normal.dot(origin - ray.origin) / denom;
+    (t >= 0.0).then(|| ray.at(t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::NodeIdx;
+
+    fn gizmo(mode: GizmoMode) -> Gizmo {
+        let entity = Entity {
+            raw: legion::World::default().push(()),
+            node: NodeIdx::root(),
+        };
+        Gizmo::new(entity, mode)
+    }
+
+    #[test]
+    fn dragging_the_translation_handle_moves_along_its_axis() {
+        let mut gizmo = gizmo(GizmoMode::Translate);
+        let transform = Transform::default();
+        // Looking down at the X handle from above.
+        let ray = Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::NEG_Y);
+        assert_eq!(gizmo.pick(&transform, 1.0, &ray), Some(0));
+        assert!(gizmo.begin_drag(0, (transform, transform), 1.0, &ray));
+
+        let moved = gizmo
+            .drag_to(&Ray::new(Vec3::new(2.0, 5.0, 0.3), Vec3::NEG_Y))
+            .unwrap();
+        assert!((moved.translation - Vec3::new(1.5, 0.0, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn dragging_the_rotation_handle_rotates_around_its_axis() {
+        let mut gizmo = gizmo(GizmoMode::Rotate);
+        let transform = Transform::default();
+        let ray = |x: f32, z: f32| Ray::new(Vec3::new(x, 5.0, z), Vec3::NEG_Y);
+        assert_eq!(gizmo.pick(&transform, 1.0, &ray(1.0, 0.0)), Some(1));
+        assert!(gizmo.begin_drag(1, (transform, transform), 1.0, &ray(1.0, 0.0)));
+
+        let rotated = gizmo.drag_to(&ray(0.0, -1.0)).unwrap();
+        let x = rotated.rotation * Vec3::X;
+        assert!((x - Vec3::NEG_Z).length() < 1e-5);
+    }
+
+    #[test]
+    fn frustum_lines_join_the_corners_of_the_volume() {
+        let proj = Mat4::orthographic_rh(-1.0, 1.0, -2.0, 2.0, 0.0, 3.0);
+        let lines = frustum_lines(proj.inverse(), Color::WHITE);
+        assert_eq!(lines.len(), 24);
+        for edge in lines.chunks(2) {
+            let a = Vec3::from(edge[0].position);
+            let b = Vec3::from(edge[1].position);
+            let length = a.distance(b);
+            assert!([2.0, 4.0, 3.0].iter().any(|l| (length - l).abs() < 1e-5));
+            assert!(a.x.abs() <= 1.0 + 1e-5 && a.y.abs() <= 2.0 + 1e-5);
+            assert!(a.z <= 1e-5 && a.z >= -3.0 - 1e-5);
+        }
+    }
+}
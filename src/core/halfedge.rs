@@ -0,0 +1,880 @@
+//! Half-edge representation of polygon meshes, for topology queries and
+//! local edits such as edge splits and collapses.
+//!
+//! Vertices of a [`Mesh`] at the same position are welded into a single
+//! vertex of the half-edge mesh, while the other vertex attributes are kept
+//! per corner of the faces, so that UV and normal seams survive the round
+//! trip back to a [`Mesh`].
+
+use crate::core::{
+    mesh::{welded_vertices, AttribContainer, Indices, Mesh, SubMesh, VertexAttribute},
+    FxHashMap, FxHashSet, Material, SmlString,
+};
+use glam::Vec3;
+use pyo3::prelude::*;
+use std::collections::hash_map::Entry;
+
+/// Index standing for no vertex, half-edge or face.
+const NONE: u32 = u32::MAX;
+
+#[derive(Clone, Copy, Debug)]
+struct HalfEdge {
+    /// Vertex the half-edge points to.
+    vertex: u32,
+    /// Face on the left of the half-edge, [`NONE`] once removed.
+    face: u32,
+    next: u32,
+    prev: u32,
+    /// Half-edge in the opposite direction, [`NONE`] on boundaries.
+    twin: u32,
+    /// Corner of the face at the vertex the half-edge points to.
+    corner: u32,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Face {
+    /// One of the half-edges around the face, [`NONE`] once removed.
+    halfedge: u32,
+    /// Index of the sub-mesh of the source mesh.
+    sub_mesh: u32,
+}
+
+/// Vertex attributes other than the position, per corner of the faces.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Corners {
+    attributes: Vec<(VertexAttribute, Vec<u8>)>,
+    len: u32,
+}
+
+impl Corners {
+    /// Adds a corner whose attributes are the average of those of the given
+    /// corners, and returns its index. Attributes which are not floats take
+    /// the value of the first corner.
+    pub(crate) fn push_average(&mut self, of: &[u32]) -> u32 {
+        for (attribute, data) in self.attributes.iter_mut() {
+            let size = attribute.size;
+            let is_float = matches!(
+                attribute.format,
+                wgpu::VertexFormat::Float32
+                    | wgpu::VertexFormat::Float32x2
+                    | wgpu::VertexFormat::Float32x3
+                    | wgpu::VertexFormat::Float32x4
+            );
+            if !is_float {
+                let first = of[0] as usize * size;
+                data.extend_from_within(first..first + size);
+                continue;
+            }
+            let mut sum = vec![0.0f32; size / 4];
+            for c in of {
+                let start = *c as usize * size;
+                let values: &[f32] = bytemuck::cast_slice(&data[start..start + size]);
+                for (s, value) in sum.iter_mut().zip(values) {
+                    *s += value;
+                }
+            }
+            for s in sum {
+                data.extend_from_slice(&(s / of.len() as f32).to_ne_bytes());
+            }
+        }
+        self.len += 1;
+        self.len - 1
+    }
+}
+
+/// Polygon mesh connected by half-edges.
+///
+/// Vertices, half-edges and faces are referred to by their index, which
+/// stays valid across edits: removed elements are only marked as such.
+/// Edges shared by more than two faces, or by faces of opposite
+/// orientations, can't be represented and are left as boundaries.
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct HalfEdgeMesh {
+    positions: Vec<Vec3>,
+    /// An outgoing half-edge of each vertex, [`NONE`] for isolated vertices.
+    outgoing: Vec<u32>,
+    removed: Vec<bool>,
+    halfedges: Vec<HalfEdge>,
+    faces: Vec<Face>,
+    pub(crate) corners: Corners,
+    /// Material of each sub-mesh, `None` if the source mesh has no
+    /// sub-meshes.
+    sub_meshes: Option<Vec<Option<u32>>>,
+    materials: Option<Vec<Material>>,
+    name: SmlString,
+    /// Number of edges left as boundaries because they are not manifold.
+    non_manifold_edges: usize,
+}
+
+impl HalfEdgeMesh {
+    /// Creates the half-edge mesh of the triangles of a mesh, welding the
+    /// vertices at the same position. Returns `None` if the mesh is not a
+    /// triangle list with positions.
+    ///
+    /// Degenerate triangles are dropped.
+    pub fn from_mesh(mesh: &Mesh) -> Option<Self> {
+        let (triangles, positions) = (mesh.triangles()?, mesh.positions()?);
+        let first = welded_vertices(positions);
+        let mut remap = vec![NONE; positions.len()];
+        let mut points = Vec::new();
+        let vertices: Vec<u32> = first
+            .iter()
+            .map(|f| {
+                if remap[*f as usize] == NONE {
+                    remap[*f as usize] = points.len() as u32;
+                    points.push(Vec3::from(positions[*f as usize]));
+                }
+                remap[*f as usize]
+            })
+            .collect();
+        let corners = Corners {
+            attributes: mesh
+                .attributes
+                .0
+                .iter()
+                .filter(|(a, _)| **a != VertexAttribute::POSITION)
+                .map(|(a, c)| (*a, c.data.to_vec()))
+                .collect(),
+            len: positions.len() as u32,
+        };
+
+        let sub_meshes = mesh
+            .sub_meshes
+            .clone()
+            .unwrap_or_else(|| vec![SubMesh::new(0, triangles.len() as u32 * 3, 0)]);
+        let mut faces = Vec::with_capacity(triangles.len());
+        for (i, sub_mesh) in sub_meshes.iter().enumerate() {
+            let range = sub_mesh.range.start as usize / 3..sub_mesh.range.end as usize / 3;
+            faces.extend(triangles[range].iter().map(|t| {
+                let face: Vec<(u32, u32)> = t.iter().map(|c| (vertices[*c as usize], *c)).collect();
+                (face, i as u32)
+            }));
+        }
+
+        let mut halfedge = Self::from_polygons(points, faces, corners);
+        halfedge.sub_meshes = mesh
+            .sub_meshes
+            .as_ref()
+            .map(|sub_meshes| sub_meshes.iter().map(|sm| sm.material).collect());
+        halfedge.materials = mesh.materials.clone();
+        halfedge.name = mesh.name.clone();
+        Some(halfedge)
+    }
+
+    /// Creates a half-edge mesh from faces given as their `(vertex, corner)`
+    /// pairs in counter-clockwise order, with the index of their sub-mesh.
+    ///
+    /// Faces with less than 3 vertices or going through a vertex twice are
+    /// dropped.
+    pub(crate) fn from_polygons(
+        positions: Vec<Vec3>,
+        faces: impl IntoIterator<Item = (Vec<(u32, u32)>, u32)>,
+        corners: Corners,
+    ) -> Self {
+        let n_vertices = positions.len();
+        let mut mesh = Self {
+            positions,
+            outgoing: vec![NONE; n_vertices],
+            removed: vec![false; n_vertices],
+            halfedges: Vec::new(),
+            faces: Vec::new(),
+            corners,
+            sub_meshes: None,
+            materials: None,
+            name: SmlString::from("halfedge"),
+            non_manifold_edges: 0,
+        };
+        let mut edges: FxHashMap<(u32, u32), u32> = FxHashMap::default();
+        for (face, sub_mesh) in faces {
+            let n = face.len();
+            let degenerate = (0..n).any(|i| face[i + 1..].iter().any(|(v, _)| *v == face[i].0));
+            if n < 3 || degenerate {
+                continue;
+            }
+            let f = mesh.faces.len() as u32;
+            let first = mesh.halfedges.len() as u32;
+            for k in 0..n {
+                let h = first + k as u32;
+                let (a, (b, corner)) = (face[k].0, face[(k + 1) % n]);
+                mesh.halfedges.push(HalfEdge {
+                    vertex: b,
+                    face: f,
+                    next: first + ((k + 1) % n) as u32,
+                    prev: first + ((k + n - 1) % n) as u32,
+                    twin: NONE,
+                    corner,
+                });
+                if mesh.outgoing[a as usize] == NONE {
+                    mesh.outgoing[a as usize] = h;
+                }
+                match edges.entry((a, b)) {
+                    Entry::Occupied(_) => mesh.non_manifold_edges += 1,
+                    Entry::Vacant(entry) => {
+                        entry.insert(h);
+                    }
+                }
+            }
+            mesh.faces.push(Face {
+                halfedge: first,
+                sub_mesh,
+            });
+        }
+        for ((a, b), h) in &edges {
+            if let Some(twin) = edges.get(&(*b, *a)) {
+                mesh.halfedges[*h as usize].twin = *twin;
+            }
+        }
+        mesh
+    }
+
+    /// Gives the mesh the materials, sub-meshes and name of `other`, e.g.
+    /// when rebuilt from its faces by [`Self::from_polygons`].
+    pub(crate) fn with_metadata_of(mut self, other: &Self) -> Self {
+        self.sub_meshes = other.sub_meshes.clone();
+        self.materials = other.materials.clone();
+        self.name = other.name.clone();
+        self
+    }
+
+    /// Converts back to a triangle list, splitting the vertices along the
+    /// seams of the corner attributes. Faces with more than 3 vertices are
+    /// triangulated as fans.
+    pub fn to_mesh(&self) -> Mesh {
+        let n_sub_meshes = self.sub_meshes.as_ref().map_or(1, Vec::len);
+        // Vertex of the mesh of each (corner, vertex) pair.
+        let mut remap: FxHashMap<(u32, u32), u32> = FxHashMap::default();
+        let mut kept = Vec::new();
+        let mut indices = Vec::new();
+        let mut sub_meshes = Vec::new();
+        for s in 0..n_sub_meshes as u32 {
+            let start = indices.len() as u32;
+            for f in self
+                .faces()
+                .filter(|f| self.faces[*f as usize].sub_mesh == s)
+            {
+                let face: Vec<u32> = self
+                    .face_halfedges(f)
+                    .into_iter()
+                    .map(|h| {
+                        let HalfEdge { vertex, corner, .. } = self.halfedges[h as usize];
+                        *remap.entry((corner, vertex)).or_insert_with(|| {
+                            kept.push((corner, vertex));
+                            kept.len() as u32 - 1
+                        })
+                    })
+                    .collect();
+                for k in 1..face.len() - 1 {
+                    indices.extend_from_slice(&[face[0], face[k], face[k + 1]]);
+                }
+            }
+            let end = indices.len() as u32;
+            if end > start {
+                sub_meshes.push(SubMesh {
+                    range: start..end,
+                    material: self
+                        .sub_meshes
+                        .as_ref()
+                        .and_then(|materials| materials[s as usize]),
+                });
+            }
+        }
+
+        let mut mesh = Mesh::new_with_name(&self.name, wgpu::PrimitiveTopology::TriangleList);
+        let positions: Vec<[f32; 3]> = kept
+            .iter()
+            .map(|(_, v)| self.positions[*v as usize].to_array())
+            .collect();
+        mesh.attributes
+            .insert(VertexAttribute::POSITION, AttribContainer::new(&positions));
+        for (attribute, data) in &self.corners.attributes {
+            let size = attribute.size;
+            let mut values = Vec::with_capacity(kept.len() * size);
+            for (c, _) in &kept {
+                values.extend_from_slice(&data[*c as usize * size..(*c as usize + 1) * size]);
+            }
+            mesh.attributes
+                .insert(*attribute, AttribContainer::new(&values));
+        }
+        mesh.indices = Some(Indices::U32(indices));
+        mesh.sub_meshes = self.sub_meshes.as_ref().map(|_| sub_meshes);
+        mesh.materials = self.materials.clone();
+        mesh
+    }
+
+    /// Returns the number of vertex indices, removed vertices included.
+    pub fn vertex_capacity(&self) -> usize {
+        self.positions.len()
+    }
+
+    /// Returns the indices of the vertices which are not removed.
+    pub fn vertices(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.positions.len() as u32).filter(|v| !self.removed[*v as usize])
+    }
+
+    /// Returns the indices of the faces which are not removed.
+    pub fn faces(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.faces.len() as u32).filter(|f| self.faces[*f as usize].halfedge != NONE)
+    }
+
+    /// Returns one half-edge per edge, the one with the lowest index.
+    pub fn edges(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.halfedges.len() as u32).filter(|h| {
+            let halfedge = &self.halfedges[*h as usize];
+            halfedge.face != NONE && (halfedge.twin == NONE || *h < halfedge.twin)
+        })
+    }
+
+    /// Returns the number of faces which are not removed.
+    pub fn face_count(&self) -> usize {
+        self.faces().count()
+    }
+
+    /// Returns whether the vertex has been removed by a collapse.
+    pub fn is_removed(&self, v: u32) -> bool {
+        self.removed[v as usize]
+    }
+
+    /// Returns the position of the vertex.
+    pub fn position(&self, v: u32) -> Vec3 {
+        self.positions[v as usize]
+    }
+
+    /// Moves the vertex.
+    pub fn set_position(&mut self, v: u32, position: Vec3) {
+        self.positions[v as usize] = position;
+    }
+
+    /// Returns the vertex the half-edge starts from.
+    pub fn source(&self, h: u32) -> u32 {
+        self.target(self.halfedges[h as usize].prev)
+    }
+
+    /// Returns the vertex the half-edge points to.
+    pub fn target(&self, h: u32) -> u32 {
+        self.halfedges[h as usize].vertex
+    }
+
+    /// Returns the next half-edge around the face of the half-edge.
+    pub fn next(&self, h: u32) -> u32 {
+        self.halfedges[h as usize].next
+    }
+
+    /// Returns the previous half-edge around the face of the half-edge.
+    pub fn prev(&self, h: u32) -> u32 {
+        self.halfedges[h as usize].prev
+    }
+
+    /// Returns the half-edge in the opposite direction, `None` on boundaries.
+    pub fn twin(&self, h: u32) -> Option<u32> {
+        Some(self.halfedges[h as usize].twin).filter(|t| *t != NONE)
+    }
+
+    /// Returns the face on the left of the half-edge.
+    pub fn face(&self, h: u32) -> u32 {
+        self.halfedges[h as usize].face
+    }
+
+    /// Returns the corner of the face at the vertex the half-edge points to.
+    pub(crate) fn corner(&self, h: u32) -> u32 {
+        self.halfedges[h as usize].corner
+    }
+
+    /// Returns the sub-mesh of the face.
+    pub(crate) fn sub_mesh(&self, f: u32) -> u32 {
+        self.faces[f as usize].sub_mesh
+    }
+
+    /// Returns the half-edges around the face, in counter-clockwise order.
+    pub fn face_halfedges(&self, f: u32) -> Vec<u32> {
+        let start = self.faces[f as usize].halfedge;
+        let mut halfedges = vec![start];
+        let mut h = self.next(start);
+        while h != start {
+            halfedges.push(h);
+            h = self.next(h);
+        }
+        halfedges
+    }
+
+    /// Returns the vertices of the face, in counter-clockwise order.
+    pub fn face_vertices(&self, f: u32) -> Vec<u32> {
+        self.face_halfedges(f)
+            .into_iter()
+            .map(|h| self.target(h))
+            .collect()
+    }
+
+    /// Returns the faces sharing an edge with the face.
+    pub fn face_neighbors(&self, f: u32) -> Vec<u32> {
+        self.face_halfedges(f)
+            .into_iter()
+            .filter_map(|h| self.twin(h))
+            .map(|t| self.face(t))
+            .collect()
+    }
+
+    /// Returns the half-edges starting from the vertex.
+    ///
+    /// Only the faces connected by edges to the face of the stored outgoing
+    /// half-edge are visited, so only one fan of non-manifold vertices.
+    pub fn outgoing(&self, v: u32) -> Vec<u32> {
+        let start = self.outgoing[v as usize];
+        if start == NONE {
+            return Vec::new();
+        }
+        let mut outgoing = vec![start];
+        let mut h = start;
+        while let Some(t) = self.twin(self.prev(h)) {
+            if t == start {
+                return outgoing;
+            }
+            outgoing.push(t);
+            h = t;
+        }
+        // The fan is open, go around the other way.
+        let mut h = start;
+        while let Some(t) = self.twin(h) {
+            h = self.next(t);
+            outgoing.push(h);
+        }
+        outgoing
+    }
+
+    /// Returns the vertices sharing an edge with the vertex.
+    pub fn vertex_neighbors(&self, v: u32) -> Vec<u32> {
+        let mut neighbors = Vec::new();
+        for h in self.outgoing(v) {
+            neighbors.push(self.target(h));
+            // The boundary edge ending at the vertex has no outgoing twin.
+            if self.twin(self.prev(h)).is_none() {
+                neighbors.push(self.source(self.prev(h)));
+            }
+        }
+        neighbors
+    }
+
+    /// Returns the vertices sharing a boundary edge with the vertex.
+    pub fn boundary_neighbors(&self, v: u32) -> Vec<u32> {
+        let mut neighbors = Vec::new();
+        for h in self.outgoing(v) {
+            if self.twin(h).is_none() {
+                neighbors.push(self.target(h));
+            }
+            if self.twin(self.prev(h)).is_none() {
+                neighbors.push(self.source(self.prev(h)));
+            }
+        }
+        neighbors
+    }
+
+    /// Returns the faces around the vertex.
+    pub fn vertex_faces(&self, v: u32) -> Vec<u32> {
+        self.outgoing(v).into_iter().map(|h| self.face(h)).collect()
+    }
+
+    /// Returns whether the vertex is on a boundary.
+    pub fn is_boundary_vertex(&self, v: u32) -> bool {
+        !self.boundary_neighbors(v).is_empty()
+    }
+
+    /// Returns the half-edge from `a` to `b`, if any.
+    pub fn find_halfedge(&self, a: u32, b: u32) -> Option<u32> {
+        self.outgoing(a).into_iter().find(|h| self.target(*h) == b)
+    }
+
+    /// Returns the vertices of each boundary loop, in the direction of the
+    /// half-edges along it.
+    pub fn boundary_loops(&self) -> Vec<Vec<u32>> {
+        let mut visited = FxHashSet::default();
+        let mut loops = Vec::new();
+        for start in 0..self.halfedges.len() as u32 {
+            let halfedge = &self.halfedges[start as usize];
+            if halfedge.face == NONE || halfedge.twin != NONE || visited.contains(&start) {
+                continue;
+            }
+            let mut vertices = Vec::new();
+            let mut h = start;
+            loop {
+                visited.insert(h);
+                vertices.push(self.target(h));
+                // Turn around the vertex to the next boundary half-edge.
+                h = self.next(h);
+                while let Some(t) = self.twin(h) {
+                    h = self.next(t);
+                }
+                if h == start || visited.contains(&h) {
+                    break;
+                }
+            }
+            loops.push(vertices);
+        }
+        loops
+    }
+
+    /// Returns whether the mesh is closed: it has faces, and every edge is
+    /// shared by exactly two faces of consistent orientations.
+    pub fn is_closed(&self) -> bool {
+        self.non_manifold_edges == 0
+            && self.faces().next().is_some()
+            && self
+                .halfedges
+                .iter()
+                .all(|h| h.face == NONE || h.twin != NONE)
+    }
+
+    /// Adds a vertex, not connected to any face yet.
+    fn add_vertex(&mut self, position: Vec3) -> u32 {
+        self.positions.push(position);
+        self.outgoing.push(NONE);
+        self.removed.push(false);
+        self.positions.len() as u32 - 1
+    }
+
+    /// Splits the edge of the half-edge at its middle, and returns the new
+    /// vertex. Triangles on either side are split in two, so that triangle
+    /// meshes stay triangle meshes; other faces gain a vertex.
+    pub fn split_edge(&mut self, h: u32) -> u32 {
+        let (a, b) = (self.source(h), self.target(h));
+        let m = self.add_vertex((self.position(a) + self.position(b)) * 0.5);
+        let twin = self.twin(h);
+        let h2 = self.insert_after(h, m);
+        let mut created = vec![h2];
+        if let Some(t) = twin {
+            let t2 = self.insert_after(t, m);
+            // h: a -> m, h2: m -> b, t: b -> m, t2: m -> a.
+            self.halfedges[h as usize].twin = t2;
+            self.halfedges[t2 as usize].twin = h;
+            self.halfedges[h2 as usize].twin = t;
+            self.halfedges[t as usize].twin = h2;
+            created.push(t2);
+        }
+        for g in created {
+            if self.face_halfedges(self.face(g)).len() == 4 {
+                self.split_quad_at(g);
+            }
+        }
+        m
+    }
+
+    /// Makes the half-edge end at the vertex `m` and inserts after it a
+    /// half-edge from `m` to its former target, returning the latter.
+    fn insert_after(&mut self, h: u32, m: u32) -> u32 {
+        let HalfEdge {
+            vertex,
+            face,
+            next,
+            corner,
+            ..
+        } = self.halfedges[h as usize];
+        let source_corner = self.corner(self.prev(h));
+        let middle = self.corners.push_average(&[source_corner, corner]);
+        let g = self.halfedges.len() as u32;
+        self.halfedges.push(HalfEdge {
+            vertex,
+            face,
+            next,
+            prev: h,
+            twin: NONE,
+            corner,
+        });
+        self.halfedges[next as usize].prev = g;
+        let halfedge = &mut self.halfedges[h as usize];
+        halfedge.next = g;
+        halfedge.vertex = m;
+        halfedge.corner = middle;
+        if self.outgoing[m as usize] == NONE {
+            self.outgoing[m as usize] = g;
+        }
+        g
+    }
+
+    /// Splits the quad of the half-edge `g` from `m`, inserted in a triangle
+    /// by [`Self::insert_after`], by an edge from `m` to the opposite vertex.
+    fn split_quad_at(&mut self, g: u32) {
+        // g: m -> b, n: b -> c, p: c -> a, h: a -> m.
+        let n = self.next(g);
+        let p = self.next(n);
+        let h = self.next(p);
+        let f = self.face(g);
+        let new_face = self.faces.len() as u32;
+        let (x, y) = (self.halfedges.len() as u32, self.halfedges.len() as u32 + 1);
+        self.halfedges.push(HalfEdge {
+            vertex: self.target(h),
+            face: f,
+            next: g,
+            prev: n,
+            twin: y,
+            corner: self.corner(h),
+        });
+        self.halfedges.push(HalfEdge {
+            vertex: self.target(n),
+            face: new_face,
+            next: p,
+            prev: h,
+            twin: x,
+            corner: self.corner(n),
+        });
+        self.halfedges[n as usize].next = x;
+        self.halfedges[g as usize].prev = x;
+        self.halfedges[h as usize].next = y;
+        self.halfedges[p as usize].prev = y;
+        self.halfedges[p as usize].face = new_face;
+        self.halfedges[h as usize].face = new_face;
+        self.faces[f as usize].halfedge = g;
+        self.faces.push(Face {
+            halfedge: y,
+            sub_mesh: self.faces[f as usize].sub_mesh,
+        });
+    }
+
+    /// Collapses the vertex `a` onto its neighbor `b`, moved to the given
+    /// position. Triangles on the edge are removed, other faces on it lose a
+    /// vertex, and the other faces around `a` take the attributes of `b` in
+    /// a face on the edge.
+    ///
+    /// Returns `false`, leaving the mesh as it is, if the vertices don't
+    /// share an edge or if the collapse would change the topology of the
+    /// surface: if they share neighbors other than the opposite vertices of
+    /// the triangles on the edge, or if they are both on a boundary the edge
+    /// is not part of.
+    pub fn collapse_edge(&mut self, a: u32, b: u32, position: Vec3) -> bool {
+        // On boundaries, the edge may only go from `b` to `a`.
+        let (h, b_corner) = match self.find_halfedge(a, b) {
+            Some(h) => (h, self.corner(h)),
+            None => match self.find_halfedge(b, a) {
+                Some(h) => (h, self.corner(self.prev(h))),
+                None => return false,
+            },
+        };
+        let twin = self.twin(h);
+        let sides: Vec<u32> = std::iter::once(h).chain(twin).collect();
+        let mut opposite: Vec<u32> = sides
+            .iter()
+            .filter(|s| self.face_halfedges(self.face(**s)).len() == 3)
+            .map(|s| self.target(self.next(*s)))
+            .collect();
+        let neighbors_b = self.vertex_neighbors(b);
+        let mut common: Vec<u32> = self
+            .vertex_neighbors(a)
+            .into_iter()
+            .filter(|v| neighbors_b.contains(v))
+            .collect();
+        common.sort_unstable();
+        common.dedup();
+        opposite.sort_unstable();
+        if common != opposite
+            || (twin.is_some() && self.is_boundary_vertex(a) && self.is_boundary_vertex(b))
+        {
+            return false;
+        }
+
+        // Half-edges around the vertices, whose vertices may need a new
+        // outgoing half-edge.
+        let around: Vec<u32> = self
+            .vertex_faces(a)
+            .into_iter()
+            .chain(self.vertex_faces(b))
+            .flat_map(|f| self.face_halfedges(f))
+            .collect();
+        let incoming: Vec<u32> = self.outgoing(a).into_iter().map(|g| self.prev(g)).collect();
+
+        for s in sides {
+            let f = self.face(s);
+            let (n, p) = (self.next(s), self.prev(s));
+            if self.face_halfedges(f).len() == 3 {
+                // The two other edges of the triangle become a single edge.
+                let (tn, tp) = (
+                    self.halfedges[n as usize].twin,
+                    self.halfedges[p as usize].twin,
+                );
+                if tn != NONE {
+                    self.halfedges[tn as usize].twin = tp;
+                }
+                if tp != NONE {
+                    self.halfedges[tp as usize].twin = tn;
+                }
+                for g in [s, n, p] {
+                    self.halfedges[g as usize].face = NONE;
+                }
+                self.faces[f as usize].halfedge = NONE;
+            } else {
+                self.halfedges[p as usize].next = n;
+                self.halfedges[n as usize].prev = p;
+                if self.faces[f as usize].halfedge == s {
+                    self.faces[f as usize].halfedge = n;
+                }
+                self.halfedges[s as usize].face = NONE;
+            }
+        }
+        for g in incoming {
+            let halfedge = &mut self.halfedges[g as usize];
+            if halfedge.face != NONE {
+                halfedge.vertex = b;
+                halfedge.corner = b_corner;
+            }
+        }
+        self.positions[b as usize] = position;
+        self.removed[a as usize] = true;
+        self.outgoing[a as usize] = NONE;
+
+        let live = |mesh: &Self, g: u32| g != NONE && mesh.halfedges[g as usize].face != NONE;
+        for g in &around {
+            let v = self.target(*g) as usize;
+            if !live(self, self.outgoing[v]) {
+                self.outgoing[v] = NONE;
+            }
+        }
+        for g in around {
+            if live(self, g) {
+                let v = self.source(g) as usize;
+                if self.outgoing[v] == NONE {
+                    self.outgoing[v] = g;
+                }
+            }
+        }
+        true
+    }
+}
+
+#[pymethods]
+impl HalfEdgeMesh {
+    /// Creates the half-edge mesh of a triangle mesh.
+    #[new]
+    pub fn new_py(mesh: &Mesh) -> PyResult<Self> {
+        Self::from_mesh(mesh).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(
+                "Only triangle lists with positions can be converted.",
+            )
+        })
+    }
+
+    /// Number of vertex indices, removed vertices included.
+    #[getter(vertex_count)]
+    pub fn vertex_count_py(&self) -> usize {
+        self.vertex_capacity()
+    }
+
+    #[getter(face_count)]
+    pub fn face_count_py(&self) -> usize {
+        self.face_count()
+    }
+
+    #[getter(edge_count)]
+    pub fn edge_count_py(&self) -> usize {
+        self.edges().count()
+    }
+
+    #[pyo3(name = "position")]
+    pub fn position_py(&self, vertex: u32) -> [f32; 3] {
+        self.position(vertex).to_array()
+    }
+
+    #[pyo3(name = "vertex_neighbors")]
+    pub fn vertex_neighbors_py(&self, vertex: u32) -> Vec<u32> {
+        self.vertex_neighbors(vertex)
+    }
+
+    #[pyo3(name = "vertex_faces")]
+    pub fn vertex_faces_py(&self, vertex: u32) -> Vec<u32> {
+        self.vertex_faces(vertex)
+    }
+
+    #[pyo3(name = "valence")]
+    pub fn valence_py(&self, vertex: u32) -> usize {
+        self.vertex_neighbors(vertex).len()
+    }
+
+    #[pyo3(name = "is_boundary_vertex")]
+    pub fn is_boundary_vertex_py(&self, vertex: u32) -> bool {
+        self.is_boundary_vertex(vertex)
+    }
+
+    #[pyo3(name = "face_vertices")]
+    pub fn face_vertices_py(&self, face: u32) -> Vec<u32> {
+        self.face_vertices(face)
+    }
+
+    #[pyo3(name = "face_neighbors")]
+    pub fn face_neighbors_py(&self, face: u32) -> Vec<u32> {
+        self.face_neighbors(face)
+    }
+
+    #[pyo3(name = "boundary_loops")]
+    pub fn boundary_loops_py(&self) -> Vec<Vec<u32>> {
+        self.boundary_loops()
+    }
+
+    #[pyo3(name = "is_closed")]
+    pub fn is_closed_py(&self) -> bool {
+        self.is_closed()
+    }
+
+    #[pyo3(name = "to_mesh")]
+    pub fn to_mesh_py(&self) -> Mesh {
+        self.to_mesh()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Alignment;
+
+    #[test]
+    fn cube_is_welded_and_closed() {
+        let cube = HalfEdgeMesh::from_mesh(&Mesh::cube(1.0)).unwrap();
+        assert_eq!(cube.vertex_capacity(), 8);
+        assert_eq!(cube.face_count(), 12);
+        assert_eq!(cube.edges().count(), 18);
+        assert!(cube.is_closed());
+        assert!(cube.boundary_loops().is_empty());
+        for v in cube.vertices() {
+            let neighbors = cube.vertex_neighbors(v);
+            assert!((3..=6).contains(&neighbors.len()));
+            assert_eq!(neighbors.len(), cube.vertex_faces(v).len());
+        }
+        // The seams survive the round trip.
+        let mesh = cube.to_mesh();
+        assert_eq!(mesh.attributes.vertex_count(), 24);
+        assert_eq!(mesh.triangle_count(), 12);
+    }
+
+    #[test]
+    fn quad_has_one_boundary_loop() {
+        let quad = HalfEdgeMesh::from_mesh(&Mesh::plane(1.0, Alignment::XY)).unwrap();
+        assert!(!quad.is_closed());
+        let loops = quad.boundary_loops();
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 4);
+        for v in quad.vertices() {
+            assert!(quad.is_boundary_vertex(v));
+            assert_eq!(quad.boundary_neighbors(v).len(), 2);
+        }
+        assert_eq!(quad.face_neighbors(0), vec![1]);
+    }
+
+    #[test]
+    fn split_and_collapse_edges() {
+        let mut cube = HalfEdgeMesh::from_mesh(&Mesh::cube(1.0)).unwrap();
+        let h = cube.edges().find(|h| cube.twin(*h).is_some()).unwrap();
+        let (a, b) = (cube.source(h), cube.target(h));
+        let m = cube.split_edge(h);
+        assert_eq!(cube.face_count(), 14);
+        assert!(cube.is_closed());
+        assert_eq!(
+            cube.position(m),
+            (cube.position(a) + cube.position(b)) * 0.5
+        );
+        assert_eq!(cube.vertex_neighbors(m).len(), 4);
+
+        assert!(!cube.collapse_edge(a, b, cube.position(b)));
+        assert!(cube.collapse_edge(m, b, cube.position(b)));
+        assert!(cube.is_removed(m));
+        assert_eq!(cube.face_count(), 12);
+        assert!(cube.is_closed());
+        let mesh = cube.to_mesh();
+        assert_eq!(mesh.triangle_count(), 12);
+        assert!(mesh.is_watertight());
+    }
+}
@@ -1,12 +1,23 @@
-use crate::core::Color;
+use crate::core::{Color, Transform};
 use glam::Vec3;
 
-#[derive(Debug, Clone, Copy)]
+/// Kind of a light source, see [`Light`].
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LightKind {
+    /// A directional light, e.g. the sun.
+    Directional,
+    /// A point light.
+    Point,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Light {
     /// A directional light.
     Directional {
-        /// The direction from which the light is coming (in world space, origin
-        /// - position). But the shader will use the opposite direction
+        /// The direction from which the light is coming (in the local space of
+        /// the node of the light, origin - position). Rotating the node
+        /// rotates the light. But the shader will use the opposite direction
         /// during shading calculations. See
         /// [`crate::render::rpass::LightsBindGroup::update_lights`].
         direction: Vec3,
@@ -18,6 +29,15 @@ pub enum Light {
 }
 
 impl Light {
+    /// Returns the kind of the light.
+    #[inline]
+    pub const fn kind(&self) -> LightKind {
+        match self {
+            Self::Directional { .. } => LightKind::Directional,
+            Self::Point { .. } => LightKind::Point,
+        }
+    }
+
     /// Returns true if the light is a directional source.
     #[inline]
     pub const fn is_directional(&self) -> bool {
@@ -29,4 +49,16 @@ impl Light {
     pub const fn is_point(&self) -> bool {
         matches!(self, Self::Point { .. })
     }
+
+    /// Returns the normalized direction of a directional light in world
+    /// space, given the world transform of its node, or `None` for a point
+    /// light.
+    pub fn world_direction(&self, world: &Transform) -> Option<Vec3> {
+        match self {
+            Self::Directional { direction, .. } => {
+                Some((world.rotation * *direction).normalize_or_zero())
+            }
+            Self::Point { .. } => None,
+        }
+    }
 }
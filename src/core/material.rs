@@ -15,6 +15,7 @@ use crate::core::{FxHashMap, FxHasher, SmlString};
 pub mod py;
 
 /// Texture type.
+#[pyo3::pyclass]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextureType {
     MapKa,    // ambient
@@ -26,9 +27,37 @@ pub enum TextureType {
     MapDisp,  // displacement,
     MapDecal, // stencil decal,
     MapNorm,  // normal,
+    MapKe,    // emissive,
+    MapPr,    // roughness,
+    MapPm,    // metallic,
+    MapPs,    // sheen,
     Unknown,  // unknown
 }
 
+impl TextureType {
+    /// Returns true if the texels of the textures of this type are colors,
+    /// stored in sRGB. The others store data, e.g. normals or roughness,
+    /// which is read as is.
+    pub fn is_color(&self) -> bool {
+        matches!(
+            self,
+            Self::MapKa | Self::MapKd | Self::MapKs | Self::MapKe | Self::MapPs
+        )
+    }
+}
+
+/// Texture of a material, read from an image file and sampled with the
+/// sampler of the given name, see `App.register_sampler`.
+#[pyo3::pyclass]
+#[pyo3(name = "Texture")]
+#[derive(Debug, Clone)]
+pub struct PyTexture {
+    /// Path to the image file.
+    pub path: PathBuf,
+    /// Name of the sampler, the default sampler if `None`.
+    pub sampler: Option<SmlString>,
+}
+
 /// Material name counter.
 static MATERIAL_NAME_COUNTER: AtomicU64 = AtomicU64::new(0);
 
@@ -80,9 +109,41 @@ pub struct Material {
     /// - 9: Transparency: Glass on, Reflection: Ray trace off
     /// - 10: Casts shadows onto invisible surfaces
     pub illumination_model: Option<u8>,
+    /// Strength of the mirror-like reflection of the environment map, from
+    /// 0.0 (no reflection) to 1.0 (perfect mirror). Not part of the `MTL`
+    /// spec.
+    pub reflectivity: Option<f32>,
+    /// Emissive color, added to the shaded color. `Ke` in the PBR extension
+    /// of the `MTL` spec.
+    pub emissive: Option<[f32; 3]>,
+    /// Roughness, from 0.0 (smooth) to 1.0 (rough). `Pr` in the PBR
+    /// extension of the `MTL` spec, not used by the Blinn-Phong shading.
+    pub roughness: Option<f32>,
+    /// Metalness, from 0.0 (dielectric) to 1.0 (metal). `Pm` in the PBR
+    /// extension of the `MTL` spec, not used by the Blinn-Phong shading.
+    pub metallic: Option<f32>,
+    /// Sheen. `Ps` in the PBR extension of the `MTL` spec, not used by the
+    /// Blinn-Phong shading.
+    pub sheen: Option<f32>,
+    /// Whether both sides of the surfaces cast shadows, e.g. for leaves or
+    /// sheets modelled as single quads. Not part of the `MTL` spec.
+    pub double_sided: bool,
+    /// Offset added to the texture coordinates after scaling and rotating
+    /// them. Not part of the `MTL` spec.
+    pub uv_offset: [f32; 2],
+    /// Scale of the texture coordinates, the textures repeat this many times
+    /// over the UV square. Not part of the `MTL` spec.
+    pub uv_scale: [f32; 2],
+    /// Counter-clockwise rotation in radians of the texture coordinates
+    /// around the origin, applied after scaling them. Not part of the `MTL`
+    /// spec.
+    pub uv_rotation: f32,
     /// Textures for the material. The key is the texture type and the value
     /// is the path to the texture.
     pub textures: FxHashMap<TextureType, PathBuf>,
+    /// Names of the samplers of the textures, the default sampler being used
+    /// by the other textures. Not part of the `MTL` spec.
+    pub samplers: FxHashMap<TextureType, SmlString>,
 }
 
 impl Asset for Material {}
@@ -113,6 +174,64 @@ impl Material {
         }
     }
 
+    /// Returns a hash of the content of the material: all parameters and
+    /// texture paths, but not the name.
+    ///
+    /// Two materials with the same content hash are rendered identically,
+    /// whatever their names.
+    pub fn content_hash(&self) -> u64 {
+        fn write_floats<H: Hasher>(hasher: &mut H, values: Option<&[f32]>) {
+            match values {
+                None => hasher.write_u8(0),
+                Some(values) => {
+                    hasher.write_u8(1);
+                    values.iter().for_each(|v| hasher.write_u32(v.to_bits()));
+                }
+            }
+        }
+
+        let mut hasher = FxHasher::default();
+        write_floats(&mut hasher, self.ambient.as_ref().map(|c| c.as_slice()));
+        write_floats(&mut hasher, self.diffuse.as_ref().map(|c| c.as_slice()));
+        write_floats(&mut hasher, self.specular.as_ref().map(|c| c.as_slice()));
+        write_floats(
+            &mut hasher,
+            self.shininess.as_ref().map(std::slice::from_ref),
+        );
+        write_floats(
+            &mut hasher,
+            self.refractive_index.as_ref().map(std::slice::from_ref),
+        );
+        write_floats(&mut hasher, self.opacity.as_ref().map(std::slice::from_ref));
+        self.illumination_model.hash(&mut hasher);
+        write_floats(
+            &mut hasher,
+            self.reflectivity.as_ref().map(std::slice::from_ref),
+        );
+        write_floats(&mut hasher, self.emissive.as_ref().map(|c| c.as_slice()));
+        write_floats(
+            &mut hasher,
+            self.roughness.as_ref().map(std::slice::from_ref),
+        );
+        write_floats(
+            &mut hasher,
+            self.metallic.as_ref().map(std::slice::from_ref),
+        );
+        write_floats(&mut hasher, self.sheen.as_ref().map(std::slice::from_ref));
+        self.double_sided.hash(&mut hasher);
+        write_floats(&mut hasher, Some(&self.uv_offset));
+        write_floats(&mut hasher, Some(&self.uv_scale));
+        write_floats(&mut hasher, Some(&[self.uv_rotation]));
+        // Textures are stored in a hash map, sort them to get a stable hash.
+        let mut textures = self.textures.iter().collect::<Vec<_>>();
+        textures.sort_by_key(|(ty, _)| **ty as u8);
+        textures.hash(&mut hasher);
+        let mut samplers = self.samplers.iter().collect::<Vec<_>>();
+        samplers.sort_by_key(|(ty, _)| **ty as u8);
+        samplers.hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Creates a new material from a loaded `MTL` file.
     ///
     /// # Arguments
@@ -178,6 +297,22 @@ impl Material {
             }
         }
 
+        // Maps of the PBR extension, not parsed by tobj.
+        for (key, ty, kind) in [
+            ("map_Ke", TextureType::MapKe, "Emissive"),
+            ("map_Pr", TextureType::MapPr, "Roughness"),
+            ("map_Pm", TextureType::MapPm, "Metallic"),
+            ("map_Ps", TextureType::MapPs, "Sheen"),
+        ] {
+            if let Some(path) = mtl.unknown_param.get(key) {
+                if let Some(resolved) = resolve_path(path.as_ref(), base) {
+                    textures.insert(ty, resolved);
+                } else {
+                    log::error!("{} map can't be loaded: {:?}", kind, path);
+                }
+            }
+        }
+
         if let Some(tex) = mtl.normal_texture.as_ref() {
             match tex {
                 NormalTexture::BumpMap(path) => {
@@ -197,6 +332,11 @@ impl Material {
             }
         }
 
+        let emissive = parse_param(&mtl, "Ke");
+        let roughness = parse_param(&mtl, "Pr").map(|[pr]| pr);
+        let metallic = parse_param(&mtl, "Pm").map(|[pm]| pm);
+        let sheen = parse_param(&mtl, "Ps").map(|[ps]| ps);
+
         Self {
             name: mtl.name.into(),
             ambient: mtl.ambient,
@@ -206,7 +346,17 @@ impl Material {
             refractive_index: mtl.optical_density,
             opacity: mtl.dissolve,
             illumination_model: mtl.illumination_model,
+            reflectivity: None,
+            emissive,
+            roughness,
+            metallic,
+            sheen,
+            double_sided: false,
+            uv_offset: [0.0, 0.0],
+            uv_scale: [1.0, 1.0],
+            uv_rotation: 0.0,
             textures,
+            samplers: FxHashMap::default(),
         }
     }
 }
@@ -222,7 +372,17 @@ impl Default for Material {
             refractive_index: Some(1.0),
             opacity: Some(1.0),
             illumination_model: Some(2),
+            reflectivity: None,
+            emissive: None,
+            roughness: None,
+            metallic: None,
+            sheen: None,
+            double_sided: false,
+            uv_offset: [0.0, 0.0],
+            uv_scale: [1.0, 1.0],
+            uv_rotation: 0.0,
             textures: FxHashMap::default(),
+            samplers: FxHashMap::default(),
         }
     }
 }
@@ -250,16 +410,37 @@ pub struct GpuMaterial {
     pub map_decal: u32,
 
     pub map_norm: u32,
-    _padding: [u32; 3],
+    pub reflectivity: f32,
+    pub uv_rotation: f32,
+    _padding: u32,
+
+    pub uv_offset: [f32; 2],
+    pub uv_scale: [f32; 2],
+
+    pub ke: [f32; 4],
+
+    pub map_ke: u32,
+    pub map_pr: u32,
+    pub map_pm: u32,
+    pub map_ps: u32,
+
+    pub pr: f32,
+    pub pm: f32,
+    pub ps: f32,
+    /// Flags of the material, see [`GpuMaterial::DOUBLE_SIDED`].
+    pub flags: u32,
 }
 
-static_assertions::assert_eq_size!(GpuMaterial, [u8; 112]);
+static_assertions::assert_eq_size!(GpuMaterial, [u8; 176]);
 
 impl Asset for GpuMaterial {}
 
 impl GpuMaterial {
     pub const SIZE: wgpu::BufferAddress = std::mem::size_of::<Self>() as wgpu::BufferAddress;
 
+    /// Flag of the materials whose back faces cast shadows.
+    pub const DOUBLE_SIDED: u32 = 1;
+
     /// Create a `MaterialUniform` from a `Material`.
     ///
     /// Note that the texture indices are not set.
@@ -293,14 +474,46 @@ impl GpuMaterial {
             map_disp: u32::MAX,
             map_decal: u32::MAX,
             map_norm: u32::MAX,
-            _padding: [0; 3],
+            reflectivity: mtl.reflectivity.unwrap_or(0.0).clamp(0.0, 1.0),
+            uv_rotation: mtl.uv_rotation,
+            _padding: 0,
+            uv_offset: mtl.uv_offset,
+            uv_scale: mtl.uv_scale,
+            ke: mtl
+                .emissive
+                .map(|c| [c[0], c[1], c[2], 0.0])
+                .unwrap_or([0.0; 4]),
+            map_ke: u32::MAX,
+            map_pr: u32::MAX,
+            map_pm: u32::MAX,
+            map_ps: u32::MAX,
+            pr: mtl.roughness.unwrap_or(1.0),
+            pm: mtl.metallic.unwrap_or(0.0),
+            ps: mtl.sheen.unwrap_or(0.0),
+            flags: if mtl.double_sided {
+                Self::DOUBLE_SIDED
+            } else {
+                0
+            },
         }
     }
+
+    /// Returns true if the back faces of the material cast shadows.
+    pub fn is_double_sided(&self) -> bool {
+        self.flags & Self::DOUBLE_SIDED != 0
+    }
+
+    /// Returns true if the material has a texture whose alpha may cut out
+    /// parts of the surfaces: a diffuse or an opacity map.
+    pub fn is_alpha_tested(&self) -> bool {
+        self.map_kd != u32::MAX || self.map_d != u32::MAX
+    }
 }
 
 /// A collection of materials that uploaded to the GPU.
 pub struct MaterialBundle {
-    /// List of materials (hash values of the material names).
+    /// List of materials (content hashes of the materials, see
+    /// [`Material::content_hash`]).
     pub materials: Vec<u64>,
     /// Buffer containing the material data.
     pub buffer: wgpu::Buffer,
@@ -308,6 +521,9 @@ pub struct MaterialBundle {
     pub bind_group: wgpu::BindGroup,
     /// Number of materials in the bundle.
     pub n_materials: u32,
+    /// Whether a material of the bundle is translucent, see
+    /// [`MaterialBundle::is_translucent`].
+    pub translucent: bool,
 }
 
 impl Deref for MaterialBundle {
@@ -354,13 +570,12 @@ impl MaterialBundle {
                 resource: material_buffer.as_entire_binding(),
             }],
         });
-        let mut hasher = FxHasher::default();
-        hasher.write(material.name.as_bytes());
         Self {
-            materials: vec![hasher.finish()],
+            materials: vec![material.content_hash()],
             buffer: material_buffer,
             bind_group,
             n_materials: 1,
+            translucent: false,
         }
     }
 
@@ -389,25 +604,50 @@ impl MaterialBundle {
                 resource: buffer.as_entire_binding(),
             }],
         });
-        let materials = materials
-            .map(|m| {
-                let mut hasher = FxHasher::default();
-                hasher.write(m.name.as_bytes());
-                hasher.finish()
-            })
-            .collect();
+        let materials = materials.map(Material::content_hash).collect();
         log::debug!("Material bundle created with materials: {:?}", materials);
         Self {
             materials,
             buffer,
             bind_group,
             n_materials: mtls.len() as u32,
+            translucent: Self::is_translucent(mtls),
         }
     }
+
+    /// Returns true if one of the materials is not fully opaque or may be
+    /// cut out by the alpha of its textures. Translucent materials are
+    /// dithered by the main render pass instead of blended, both discard
+    /// fragments.
+    pub fn is_translucent(mtls: &[GpuMaterial]) -> bool {
+        mtls.iter().any(|mtl| mtl.d < 1.0 || mtl.is_alpha_tested())
+    }
 }
 
 impl Asset for MaterialBundle {}
 
+/// Parses the `N` numbers of a parameter of a `MTL` file left unparsed by
+/// tobj. Returns `None` if the value is malformed.
+fn parse_floats<const N: usize>(value: &str) -> Option<[f32; N]> {
+    let mut floats = [0.0; N];
+    let mut tokens = value.split_whitespace();
+    for float in floats.iter_mut() {
+        *float = tokens.next()?.parse().ok()?;
+    }
+    tokens.next().is_none().then_some(floats)
+}
+
+/// Returns the `N` numbers of a parameter of a `MTL` file left unparsed by
+/// tobj, or `None` if it is missing or malformed.
+fn parse_param<const N: usize>(mtl: &tobj::Material, key: &str) -> Option<[f32; N]> {
+    let value = mtl.unknown_param.get(key)?;
+    let parsed = parse_floats(value);
+    if parsed.is_none() {
+        log::warn!("Invalid {} parameter: {:?}", key, value);
+    }
+    parsed
+}
+
 fn resolve_path(path: &Path, base: &Path) -> Option<PathBuf> {
     log::debug!("Resolving path: {:?} with base: {:?}", path, base);
     let path = if path.is_absolute() {
@@ -474,3 +714,60 @@ impl From<u8> for IllumModel {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn material_content_hash() {
+        let a = Material::new_with_name("a");
+        let mut b = Material::new_with_name("b");
+        // Same content, different names.
+        assert_eq!(a.content_hash(), b.content_hash());
+
+        // Same name, different content.
+        let mut c = Material::new_with_name("a");
+        c.diffuse = Some([1.0, 0.0, 0.0]);
+        assert_ne!(a.content_hash(), c.content_hash());
+
+        b.textures
+            .insert(TextureType::MapKd, PathBuf::from("diffuse.png"));
+        assert_ne!(a.content_hash(), b.content_hash());
+        b.textures
+            .insert(TextureType::MapNorm, PathBuf::from("normal.png"));
+        let mut d = Material::new_with_name("d");
+        d.textures
+            .insert(TextureType::MapNorm, PathBuf::from("normal.png"));
+        d.textures
+            .insert(TextureType::MapKd, PathBuf::from("diffuse.png"));
+        assert_eq!(b.content_hash(), d.content_hash());
+    }
+
+    #[test]
+    fn pbr_parameters_are_parsed() {
+        assert_eq!(parse_floats::<3>("0.5 1 0"), Some([0.5, 1.0, 0.0]));
+        assert_eq!(parse_floats::<1>(" 0.25 "), Some([0.25]));
+        assert_eq!(parse_floats::<3>("0.5 1"), None);
+        assert_eq!(parse_floats::<1>("0.5 1"), None);
+        assert_eq!(parse_floats::<1>("rough"), None);
+
+        let mut mtl = tobj::Material {
+            name: String::from("metal"),
+            ..Default::default()
+        };
+        mtl.unknown_param
+            .insert(String::from("Ke"), String::from("1 0.5 0"));
+        mtl.unknown_param
+            .insert(String::from("Pm"), String::from("1"));
+        mtl.unknown_param
+            .insert(String::from("Pr"), String::from("0.2"));
+        let material = Material::from_tobj_material(mtl, Path::new("metal.obj"));
+        assert_eq!(material.emissive, Some([1.0, 0.5, 0.0]));
+        assert_eq!(material.metallic, Some(1.0));
+        assert_eq!(material.roughness, Some(0.2));
+        assert_eq!(material.sheen, None);
+        assert!(TextureType::MapKe.is_color());
+        assert!(!TextureType::MapPr.is_color());
+    }
+}
@@ -1,4 +1,4 @@
-use crate::core::{Color, IllumModel, Material, SmlString, TextureType};
+use crate::core::{Color, IllumModel, Material, PyTexture, SmlString, TextureType};
 use pyo3::types::PyDict;
 use std::path::PathBuf;
 
@@ -79,10 +79,107 @@ impl Material {
         self.illumination_model.map(|i| i.into())
     }
 
+    #[setter]
+    pub fn set_reflectivity(&mut self, reflectivity: f32) {
+        self.reflectivity = Some(reflectivity);
+    }
+
+    #[getter]
+    pub fn get_reflectivity(&self) -> Option<f32> {
+        self.reflectivity
+    }
+
+    #[setter]
+    pub fn set_emissive(&mut self, ke: Color) {
+        self.emissive = Some([ke.r as f32, ke.g as f32, ke.b as f32]);
+    }
+
+    #[getter]
+    pub fn get_emissive(&self) -> Option<[f32; 3]> {
+        self.emissive
+    }
+
+    #[setter]
+    pub fn set_roughness(&mut self, roughness: f32) {
+        self.roughness = Some(roughness);
+    }
+
+    #[getter]
+    pub fn get_roughness(&self) -> Option<f32> {
+        self.roughness
+    }
+
+    #[setter]
+    pub fn set_metallic(&mut self, metallic: f32) {
+        self.metallic = Some(metallic);
+    }
+
+    #[getter]
+    pub fn get_metallic(&self) -> Option<f32> {
+        self.metallic
+    }
+
+    #[setter]
+    pub fn set_sheen(&mut self, sheen: f32) {
+        self.sheen = Some(sheen);
+    }
+
+    #[getter]
+    pub fn get_sheen(&self) -> Option<f32> {
+        self.sheen
+    }
+
+    /// Sets whether both sides of the surfaces cast shadows.
+    #[setter]
+    pub fn set_double_sided(&mut self, double_sided: bool) {
+        self.double_sided = double_sided;
+    }
+
+    #[getter]
+    pub fn get_double_sided(&self) -> bool {
+        self.double_sided
+    }
+
+    /// Sets the offset added to the texture coordinates.
+    #[setter]
+    pub fn set_uv_offset(&mut self, offset: [f32; 2]) {
+        self.uv_offset = offset;
+    }
+
+    #[getter]
+    pub fn get_uv_offset(&self) -> [f32; 2] {
+        self.uv_offset
+    }
+
+    /// Sets how many times the textures repeat over the UV square, along U
+    /// and V.
+    #[setter]
+    pub fn set_uv_scale(&mut self, scale: [f32; 2]) {
+        self.uv_scale = scale;
+    }
+
+    #[getter]
+    pub fn get_uv_scale(&self) -> [f32; 2] {
+        self.uv_scale
+    }
+
+    /// Sets the counter-clockwise rotation in radians of the texture
+    /// coordinates.
+    #[setter]
+    pub fn set_uv_rotation(&mut self, rotation: f32) {
+        self.uv_rotation = rotation;
+    }
+
+    #[getter]
+    pub fn get_uv_rotation(&self) -> f32 {
+        self.uv_rotation
+    }
+
     /// Sets the textures for the material.
     ///
     /// The textures are passed as a dictionary where the key is the texture
-    /// type and the value is the path to the texture.
+    /// type and the value is the path to the texture, or a `Texture` to
+    /// sample it with a specific sampler.
     #[setter]
     pub fn set_textures(&mut self, textures: &PyDict) {
         if textures.is_empty() {
@@ -96,9 +193,17 @@ impl Material {
                 log::warn!("Texture path is None for key: {}", key);
                 continue;
             }
-            let value: String = value
-                .extract()
-                .expect("Failed to downcast texture path to string");
+            let texture = match value.extract::<PyTexture>() {
+                Ok(texture) => texture,
+                Err(_) => PyTexture {
+                    path: PathBuf::from(
+                        value
+                            .extract::<String>()
+                            .expect("Failed to downcast texture path to string"),
+                    ),
+                    sampler: None,
+                },
+            };
 
             let texture_type = match key.to_lowercase().as_str() {
                 "map_ka" | "ambient_texture" => TextureType::MapKa,
@@ -110,6 +215,10 @@ impl Material {
                 "map_disp" | "displacement_texture" => TextureType::MapDisp,
                 "map_decal" | "decal_texture" => TextureType::MapDecal,
                 "map_norm" | "normal_texture" => TextureType::MapNorm,
+                "map_ke" | "emissive_texture" => TextureType::MapKe,
+                "map_pr" | "roughness_texture" => TextureType::MapPr,
+                "map_pm" | "metallic_texture" => TextureType::MapPm,
+                "map_ps" | "sheen_texture" => TextureType::MapPs,
                 _ => TextureType::Unknown,
             };
 
@@ -119,10 +228,46 @@ impl Material {
                     continue;
                 }
                 texture_type => {
-                    self.textures
-                        .insert(texture_type, PathBuf::from(value.as_str()));
+                    self.textures.insert(texture_type, texture.path);
+                    match texture.sampler {
+                        Some(sampler) => self.samplers.insert(texture_type, sampler),
+                        None => self.samplers.remove(&texture_type),
+                    };
                 }
             }
         }
     }
 }
+
+#[pyo3::pymethods]
+impl PyTexture {
+    #[new]
+    #[pyo3(signature = (path, sampler=None))]
+    pub fn new(path: &str, sampler: Option<&str>) -> Self {
+        Self {
+            path: PathBuf::from(path),
+            sampler: sampler.map(SmlString::from),
+        }
+    }
+
+    #[getter]
+    pub fn get_path(&self) -> String {
+        self.path.to_string_lossy().into_owned()
+    }
+
+    #[getter]
+    pub fn get_sampler(&self) -> Option<&str> {
+        self.sampler.as_deref()
+    }
+
+    /// Sets the name of the sampler the texture is sampled with, e.g.
+    /// `clamp_nearest`, `linear_aniso8` or a name registered with
+    /// `App.register_sampler`.
+    pub fn set_sampler(&mut self, name: &str) {
+        self.sampler = Some(SmlString::from(name));
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!("Texture(path={:?}, sampler={:?})", self.path, self.sampler)
+    }
+}
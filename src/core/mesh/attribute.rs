@@ -1,9 +1,19 @@
 use bytemuck::Pod;
-use std::collections::BTreeMap;
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+/// Names of the custom vertex attributes, leaked once per distinct name so
+/// that vertex attributes stay `Copy`.
+static CUSTOM_NAMES: Mutex<Vec<&'static str>> = Mutex::new(Vec::new());
 
 #[derive(Clone, Debug)]
 pub struct AttribContainer {
-    pub(crate) data: Vec<u8>,
+    /// Bytes of the values, shared with the clones of the container and the
+    /// numpy views of the attribute. They are copied before being modified
+    /// if they are shared.
+    pub(crate) data: Arc<Vec<u8>>,
     pub(crate) n_bytes: usize,
 }
 
@@ -12,7 +22,7 @@ impl AttribContainer {
     pub fn new<T: 'static + Pod>(data: &[T]) -> Self {
         let n_bytes = std::mem::size_of_val(data);
         Self {
-            data: bytemuck::cast_slice(data).to_vec(),
+            data: Arc::new(bytemuck::cast_slice(data).to_vec()),
             n_bytes,
         }
     }
@@ -38,7 +48,12 @@ impl AttribContainer {
     }
 
     pub fn as_slice_mut<T: 'static + Pod>(&mut self) -> &mut [T] {
-        bytemuck::cast_slice_mut(&mut self.data)
+        bytemuck::cast_slice_mut(Arc::make_mut(&mut self.data))
+    }
+
+    /// Returns the bytes of the values, shared with the container.
+    pub fn shared_bytes(&self) -> Arc<Vec<u8>> {
+        self.data.clone()
     }
 }
 
@@ -104,6 +119,54 @@ impl VertexAttribute {
         std::mem::size_of::<[f32; 4]>(),
     );
 
+    /// Shader location of the first custom attribute, the locations below
+    /// are reserved for the built-in attributes.
+    pub const FIRST_CUSTOM_LOCATION: u32 = 5;
+    /// Maximum number of custom attributes of a mesh, so that the vertex
+    /// buffers of a pipeline stay within the default limits.
+    pub const MAX_CUSTOM_ATTRIBUTES: usize = 3;
+    /// Number of shader locations of the vertex attributes.
+    pub const MAX_LOCATIONS: u32 = 16;
+
+    /// Creates a custom vertex attribute, read at the given location by the
+    /// custom shaders.
+    pub fn custom(
+        name: &str,
+        format: wgpu::VertexFormat,
+        shader_location: u32,
+    ) -> Result<Self, String> {
+        if !(Self::FIRST_CUSTOM_LOCATION..Self::MAX_LOCATIONS).contains(&shader_location) {
+            return Err(format!(
+                "Custom attribute {} must be at a location in {}..{}, not {}",
+                name,
+                Self::FIRST_CUSTOM_LOCATION,
+                Self::MAX_LOCATIONS,
+                shader_location
+            ));
+        }
+        let mut names = CUSTOM_NAMES.lock().unwrap();
+        let name = match names.iter().find(|n| **n == name) {
+            Some(name) => *name,
+            None => {
+                let leaked: &'static str = Box::leak(name.to_owned().into_boxed_str());
+                names.push(leaked);
+                leaked
+            }
+        };
+        Ok(Self::new(
+            name,
+            format,
+            shader_location,
+            format.size() as usize,
+        ))
+    }
+
+    /// Returns whether the attribute is a custom one, only read by custom
+    /// shaders.
+    pub fn is_custom(&self) -> bool {
+        self.shader_location >= Self::FIRST_CUSTOM_LOCATION
+    }
+
     pub const fn new(
         name: &'static str,
         format: wgpu::VertexFormat,
@@ -128,12 +191,30 @@ impl VertexAttributes {
         self.0.insert(attrib, data);
     }
 
-    /// Returns the range of the vertex attribute in the mesh data buffer, if it
-    /// exists.
+    /// Returns the number of vertices, which is the number of positions.
     pub fn vertex_count(&self) -> usize {
         self.0
             .get(&VertexAttribute::POSITION)
-            .map(|a| a.len())
+            .map(|a| a.n_bytes() / VertexAttribute::POSITION.size)
             .unwrap_or(0)
     }
+
+    /// Returns the custom attributes, ordered by their location.
+    pub fn custom(&self) -> impl Iterator<Item = (&VertexAttribute, &AttribContainer)> {
+        self.0.iter().filter(|(attribute, _)| attribute.is_custom())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_values_are_copied_on_write() {
+        let mut container = AttribContainer::new(&[[1.0f32, 2.0, 3.0]]);
+        let shared = container.shared_bytes();
+        container.as_slice_mut::<[f32; 3]>()[0][0] = 4.0;
+        assert_eq!(container.as_slice::<[f32; 3]>(), &[[4.0, 2.0, 3.0]]);
+        assert_eq!(bytemuck::cast_slice::<u8, f32>(&shared), &[1.0, 2.0, 3.0]);
+    }
 }
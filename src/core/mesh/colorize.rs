@@ -0,0 +1,107 @@
+//! Procedural vertex coloring from the geometry of meshes.
+
+use crate::core::{
+    mesh::{AttribContainer, Mesh, VertexAttribute},
+    Color,
+};
+use glam::{Vec3, Vec4};
+
+impl Mesh {
+    /// Generates the vertex colors from the height (y coordinate) of the
+    /// vertices.
+    ///
+    /// The gradient is a list of `(t, color)` stops, where `t` goes from 0 at
+    /// the lowest vertex to 1 at the highest one.
+    pub fn colorize_by_height(&mut self, gradient: &[(f32, Color)]) {
+        let Some(positions) = self.attributes.0.get(&VertexAttribute::POSITION) else {
+            log::warn!("Mesh has no positions. Skipping colorization.");
+            return;
+        };
+        let heights = positions
+            .as_slice::<[f32; 3]>()
+            .iter()
+            .map(|p| p[1])
+            .collect::<Vec<_>>();
+        let (min, max) = heights.iter().fold((f32::MAX, f32::MIN), |(min, max), h| {
+            (min.min(*h), max.max(*h))
+        });
+        let range = (max - min).max(f32::EPSILON);
+        self.set_vertex_colors(heights.iter().map(|h| (h - min) / range), gradient);
+    }
+
+    /// Generates the vertex colors from the slope of the surface, i.e. the
+    /// angle between the vertex normals and the up axis.
+    ///
+    /// The gradient is a list of `(t, color)` stops, where `t` goes from 0 for
+    /// flat surfaces to 1 for vertical ones; overhangs are clamped to 1.
+    /// Normals are computed if the mesh doesn't have them.
+    pub fn colorize_by_normal_slope(&mut self, gradient: &[(f32, Color)]) {
+        if !self.attributes.0.contains_key(&VertexAttribute::NORMAL) {
+            self.compute_normals();
+        }
+        let slopes = self.attributes.0[&VertexAttribute::NORMAL]
+            .as_slice::<[f32; 3]>()
+            .iter()
+            .map(|n| {
+                let cos = Vec3::from(*n).normalize_or_zero().dot(Vec3::Y);
+                (cos.clamp(0.0, 1.0).acos() / std::f32::consts::FRAC_PI_2).min(1.0)
+            })
+            .collect::<Vec<_>>();
+        self.set_vertex_colors(slopes.into_iter(), gradient);
+    }
+
+    /// Replaces the vertex colors by sampling the gradient at the given
+    /// parameters, one per vertex.
+    fn set_vertex_colors(&mut self, ts: impl Iterator<Item = f32>, gradient: &[(f32, Color)]) {
+        let mut stops = gradient
+            .iter()
+            .map(|(t, c)| (*t, Vec4::from(<[f32; 4]>::from(*c))))
+            .collect::<Vec<_>>();
+        stops.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let colors = ts
+            .map(|t| sample_gradient(&stops, t).to_array())
+            .collect::<Vec<_>>();
+        self.attributes
+            .insert(VertexAttribute::COLOR, AttribContainer::new(&colors));
+    }
+}
+
+/// Samples a gradient with stops sorted by parameter, clamping outside of
+/// the first and last stops. An empty gradient is white.
+fn sample_gradient(stops: &[(f32, Vec4)], t: f32) -> Vec4 {
+    let (first, last) = match (stops.first(), stops.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return Vec4::ONE,
+    };
+    if t <= first.0 {
+        return first.1;
+    }
+    if t >= last.0 {
+        return last.1;
+    }
+    stops
+        .windows(2)
+        .find(|w| t <= w[1].0)
+        .map(|w| {
+            let (t0, c0) = w[0];
+            let (t1, c1) = w[1];
+            c0.lerp(c1, (t - t0) / (t1 - t0).max(f32::EPSILON))
+        })
+        .unwrap_or(last.1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gradient_sampling() {
+        let stops = [(0.0, Vec4::ZERO), (0.5, Vec4::ONE), (1.0, Vec4::ZERO)];
+        assert_eq!(sample_gradient(&stops, -1.0), Vec4::ZERO);
+        assert_eq!(sample_gradient(&stops, 0.25), Vec4::splat(0.5));
+        assert_eq!(sample_gradient(&stops, 0.5), Vec4::ONE);
+        assert_eq!(sample_gradient(&stops, 0.75), Vec4::splat(0.5));
+        assert_eq!(sample_gradient(&stops, 2.0), Vec4::ZERO);
+        assert_eq!(sample_gradient(&[], 0.5), Vec4::ONE);
+    }
+}
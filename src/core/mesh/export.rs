@@ -0,0 +1,574 @@
+//! Export of meshes to wavefront obj and glTF files.
+
+use crate::core::{
+    mesh::{Indices, Mesh, SubMesh, VertexAttribute},
+    FxHashMap, Material, TextureType,
+};
+use glam::Vec3;
+use std::{
+    fmt::Write as _,
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+impl Mesh {
+    /// Writes the mesh to a wavefront obj file.
+    ///
+    /// The materials are written to a material library next to the obj file,
+    /// with the same name and the `mtl` extension. Textures are referenced
+    /// by their path relative to the obj file, they are not copied.
+    pub fn save_obj<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let data = ExportData::new(self)?;
+        let materials = self.materials.as_deref().unwrap_or_default();
+
+        let mut obj = BufWriter::new(std::fs::File::create(path)?);
+        writeln!(obj, "# {}", self.name)?;
+        if !materials.is_empty() {
+            let mtl_path = path.with_extension("mtl");
+            write_mtl(&mtl_path, materials)?;
+            writeln!(obj, "mtllib {}", file_name(&mtl_path))?;
+        }
+        writeln!(obj, "o {}", self.name)?;
+        for p in data.positions {
+            writeln!(obj, "v {} {} {}", p[0], p[1], p[2])?;
+        }
+        for uv in data.uvs.unwrap_or_default() {
+            writeln!(obj, "vt {} {}", uv[0], uv[1])?;
+        }
+        for n in data.normals.unwrap_or_default() {
+            writeln!(obj, "vn {} {} {}", n[0], n[1], n[2])?;
+        }
+        for sub_mesh in &data.sub_meshes {
+            if let Some(material) = sub_mesh.material.and_then(|i| materials.get(i as usize)) {
+                writeln!(obj, "usemtl {}", material.name)?;
+            }
+            let range = sub_mesh.range.start as usize..sub_mesh.range.end as usize;
+            for triangle in data.indices[range].chunks_exact(3) {
+                write!(obj, "f")?;
+                for index in triangle {
+                    // Obj indices are 1-based.
+                    let i = index + 1;
+                    match (data.uvs.is_some(), data.normals.is_some()) {
+                        (true, true) => write!(obj, " {}/{}/{}", i, i, i)?,
+                        (true, false) => write!(obj, " {}/{}", i, i)?,
+                        (false, true) => write!(obj, " {}//{}", i, i)?,
+                        (false, false) => write!(obj, " {}", i)?,
+                    }
+                }
+                writeln!(obj)?;
+            }
+        }
+        obj.flush()
+    }
+
+    /// Writes the mesh to a glTF file.
+    ///
+    /// The vertex and index data are written to a binary buffer next to the
+    /// glTF file, with the same name and the `bin` extension. Each sub-mesh
+    /// becomes a primitive of the glTF mesh. Textures are referenced by
+    /// their path relative to the glTF file, they are not copied.
+    pub fn save_gltf<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let data = ExportData::new(self)?;
+        let materials = self.materials.as_deref().unwrap_or_default();
+
+        // Binary buffer: positions, normals, uvs then indices.
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut buffer_views = Vec::new();
+        let mut accessors = Vec::new();
+        let mut add_view = |buffer: &mut Vec<u8>, bytes: &[u8], target: u32| {
+            buffer_views.push(format!(
+                r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+                buffer.len(),
+                bytes.len(),
+                target
+            ));
+            buffer.extend_from_slice(bytes);
+            buffer_views.len() - 1
+        };
+
+        let n_vertices = data.positions.len();
+        // The bounds of a mesh without vertices are zero.
+        let (min, max) = match data.positions.split_first() {
+            None => (Vec3::ZERO, Vec3::ZERO),
+            Some((first, rest)) => rest
+                .iter()
+                .fold((Vec3::from(*first), Vec3::from(*first)), |(min, max), p| {
+                    (min.min(Vec3::from(*p)), max.max(Vec3::from(*p)))
+                }),
+        };
+        let view = add_view(
+            &mut buffer,
+            bytemuck::cast_slice(data.positions),
+            ARRAY_BUFFER,
+        );
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+            view, FLOAT, n_vertices, min.x, min.y, min.z, max.x, max.y, max.z
+        ));
+        let mut attributes = format!(r#""POSITION":{}"#, accessors.len() - 1);
+        if let Some(normals) = data.normals {
+            let view = add_view(&mut buffer, bytemuck::cast_slice(normals), ARRAY_BUFFER);
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3"}}"#,
+                view, FLOAT, n_vertices
+            ));
+            let _ = write!(attributes, r#","NORMAL":{}"#, accessors.len() - 1);
+        }
+        if let Some(uvs) = data.uvs {
+            // glTF has the origin of texture coordinates at the top left.
+            let uvs = uvs
+                .iter()
+                .map(|uv| [uv[0], 1.0 - uv[1]])
+                .collect::<Vec<_>>();
+            let view = add_view(&mut buffer, bytemuck::cast_slice(&uvs), ARRAY_BUFFER);
+            accessors.push(format!(
+                r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC2"}}"#,
+                view, FLOAT, n_vertices
+            ));
+            let _ = write!(attributes, r#","TEXCOORD_0":{}"#, accessors.len() - 1);
+        }
+        let indices_view = add_view(
+            &mut buffer,
+            bytemuck::cast_slice(&data.indices),
+            ELEMENT_ARRAY_BUFFER,
+        );
+
+        let mut primitives = Vec::new();
+        for sub_mesh in &data.sub_meshes {
+            accessors.push(format!(
+                r#"{{"bufferView":{},"byteOffset":{},"componentType":{},"count":{},"type":"SCALAR"}}"#,
+                indices_view,
+                sub_mesh.range.start as usize * std::mem::size_of::<u32>(),
+                UNSIGNED_INT,
+                sub_mesh.range.len()
+            ));
+            let mut primitive = format!(
+                r#"{{"attributes":{{{}}},"indices":{},"mode":4"#,
+                attributes,
+                accessors.len() - 1
+            );
+            if let Some(material) = sub_mesh
+                .material
+                .filter(|i| (*i as usize) < materials.len())
+            {
+                let _ = write!(primitive, r#","material":{}"#, material);
+            }
+            primitive.push('}');
+            primitives.push(primitive);
+        }
+
+        // Materials, textures referencing the same image share it.
+        let mut images: Vec<String> = Vec::new();
+        let mut image_ids: FxHashMap<String, usize> = FxHashMap::default();
+        let dir = parent_dir(path);
+        let mut texture = |ty: TextureType, material: &Material| {
+            material.textures.get(&ty).map(|texture| {
+                let uri = relative_uri(texture, dir);
+                *image_ids.entry(uri.clone()).or_insert_with(|| {
+                    images.push(format!(r#"{{"uri":{}}}"#, json_string(&uri)));
+                    images.len() - 1
+                })
+            })
+        };
+        let gltf_materials = materials
+            .iter()
+            .map(|material| {
+                let diffuse = material.diffuse.unwrap_or([1.0; 3]);
+                let opacity = material.opacity.unwrap_or(1.0);
+                // Approximates the roughness from the Blinn-Phong exponent
+                // if the material has none.
+                let roughness = material.roughness.unwrap_or_else(|| {
+                    material
+                        .shininess
+                        .map(|ns| (2.0 / (ns.max(0.0) + 2.0)).sqrt())
+                        .unwrap_or(1.0)
+                });
+                let mut pbr = format!(
+                    r#""baseColorFactor":[{},{},{},{}],"metallicFactor":{},"roughnessFactor":{}"#,
+                    diffuse[0],
+                    diffuse[1],
+                    diffuse[2],
+                    opacity,
+                    material.metallic.unwrap_or(0.0),
+                    roughness
+                );
+                if let Some(image) = texture(TextureType::MapKd, material) {
+                    let _ = write!(pbr, r#","baseColorTexture":{{"index":{}}}"#, image);
+                }
+                let mut json = format!(
+                    r#"{{"name":{},"pbrMetallicRoughness":{{{}}}"#,
+                    json_string(&material.name),
+                    pbr
+                );
+                if let Some(image) = texture(TextureType::MapNorm, material) {
+                    let _ = write!(json, r#","normalTexture":{{"index":{}}}"#, image);
+                }
+                if let Some([r, g, b]) = material.emissive {
+                    let _ = write!(json, r#","emissiveFactor":[{},{},{}]"#, r, g, b);
+                }
+                if let Some(image) = texture(TextureType::MapKe, material) {
+                    if material.emissive.is_none() {
+                        json.push_str(r#","emissiveFactor":[1,1,1]"#);
+                    }
+                    let _ = write!(json, r#","emissiveTexture":{{"index":{}}}"#, image);
+                }
+                if opacity < 1.0 || material.textures.contains_key(&TextureType::MapD) {
+                    json.push_str(r#","alphaMode":"BLEND""#);
+                }
+                json.push('}');
+                json
+            })
+            .collect::<Vec<_>>();
+        // One texture per image, using the default sampler.
+        let textures = (0..images.len())
+            .map(|i| format!(r#"{{"source":{}}}"#, i))
+            .collect::<Vec<_>>();
+
+        let bin_path = path.with_extension("bin");
+        std::fs::write(&bin_path, &buffer)?;
+
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            r#"{{"asset":{{"version":"2.0","generator":"bkfw"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"name":{name},"mesh":0}}],"meshes":[{{"name":{name},"primitives":[{}]}}],"buffers":[{{"uri":{},"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]"#,
+            primitives.join(","),
+            json_string(&file_name(&bin_path)),
+            buffer.len(),
+            buffer_views.join(","),
+            accessors.join(","),
+            name = json_string(&self.name),
+        );
+        if !gltf_materials.is_empty() {
+            let _ = write!(json, r#","materials":[{}]"#, gltf_materials.join(","));
+        }
+        if !images.is_empty() {
+            let _ = write!(
+                json,
+                r#","textures":[{}],"images":[{}]"#,
+                textures.join(","),
+                images.join(",")
+            );
+        }
+        json.push('}');
+        std::fs::write(path, json)
+    }
+}
+
+/// glTF buffer view target of vertex data.
+const ARRAY_BUFFER: u32 = 34962;
+/// glTF buffer view target of index data.
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+/// glTF component type of `f32`.
+const FLOAT: u32 = 5126;
+/// glTF component type of `u32`.
+const UNSIGNED_INT: u32 = 5125;
+
+/// Vertex and index data of a mesh to export.
+struct ExportData<'a> {
+    positions: &'a [[f32; 3]],
+    normals: Option<&'a [[f32; 3]]>,
+    uvs: Option<&'a [[f32; 2]]>,
+    indices: Vec<u32>,
+    /// Sub-meshes, the whole mesh if the mesh has no sub-meshes.
+    sub_meshes: Vec<SubMesh>,
+}
+
+impl<'a> ExportData<'a> {
+    fn new(mesh: &'a Mesh) -> io::Result<Self> {
+        if mesh.topology != wgpu::PrimitiveTopology::TriangleList {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "only triangle meshes can be exported, got {:?}",
+                    mesh.topology
+                ),
+            ));
+        }
+        let positions = mesh
+            .attributes
+            .0
+            .get(&VertexAttribute::POSITION)
+            .map(|a| a.as_slice::<[f32; 3]>())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "mesh has no vertex positions")
+            })?;
+        // Attributes not matching the number of vertices are skipped.
+        let normals = mesh
+            .attributes
+            .0
+            .get(&VertexAttribute::NORMAL)
+            .map(|a| a.as_slice::<[f32; 3]>())
+            .filter(|n| n.len() == positions.len());
+        let uvs = mesh
+            .attributes
+            .0
+            .get(&VertexAttribute::UV)
+            .map(|a| a.as_slice::<[f32; 2]>())
+            .filter(|uv| uv.len() == positions.len());
+        let indices = match &mesh.indices {
+            Some(Indices::U32(indices)) => indices.clone(),
+            Some(Indices::U16(indices)) => indices.iter().map(|i| *i as u32).collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        let sub_meshes = match &mesh.sub_meshes {
+            Some(sub_meshes) if !sub_meshes.is_empty() => sub_meshes.clone(),
+            _ => vec![SubMesh {
+                range: 0..indices.len() as u32,
+                material: mesh.materials.as_ref().filter(|m| !m.is_empty()).map(|_| 0),
+            }],
+        };
+        Ok(Self {
+            positions,
+            normals,
+            uvs,
+            indices,
+            sub_meshes,
+        })
+    }
+}
+
+/// Writes the materials to a material library.
+fn write_mtl(path: &Path, materials: &[Material]) -> io::Result<()> {
+    let dir = parent_dir(path);
+    let mut mtl = BufWriter::new(std::fs::File::create(path)?);
+    for material in materials {
+        writeln!(mtl, "newmtl {}", material.name)?;
+        if let Some([r, g, b]) = material.ambient {
+            writeln!(mtl, "Ka {} {} {}", r, g, b)?;
+        }
+        if let Some([r, g, b]) = material.diffuse {
+            writeln!(mtl, "Kd {} {} {}", r, g, b)?;
+        }
+        if let Some([r, g, b]) = material.specular {
+            writeln!(mtl, "Ks {} {} {}", r, g, b)?;
+        }
+        if let Some(ns) = material.shininess {
+            writeln!(mtl, "Ns {}", ns)?;
+        }
+        if let Some(ni) = material.refractive_index {
+            writeln!(mtl, "Ni {}", ni)?;
+        }
+        if let Some(d) = material.opacity {
+            writeln!(mtl, "d {}", d)?;
+        }
+        if let Some(illum) = material.illumination_model {
+            writeln!(mtl, "illum {}", illum)?;
+        }
+        if let Some([r, g, b]) = material.emissive {
+            writeln!(mtl, "Ke {} {} {}", r, g, b)?;
+        }
+        if let Some(pr) = material.roughness {
+            writeln!(mtl, "Pr {}", pr)?;
+        }
+        if let Some(pm) = material.metallic {
+            writeln!(mtl, "Pm {}", pm)?;
+        }
+        if let Some(ps) = material.sheen {
+            writeln!(mtl, "Ps {}", ps)?;
+        }
+        // Sorted for a deterministic output.
+        let mut textures = material.textures.iter().collect::<Vec<_>>();
+        textures.sort_by_key(|(ty, _)| format!("{:?}", ty));
+        for (ty, texture) in textures {
+            let keyword = match ty {
+                TextureType::MapKa => "map_Ka",
+                TextureType::MapKd => "map_Kd",
+                TextureType::MapKs => "map_Ks",
+                TextureType::MapNs => "map_Ns",
+                TextureType::MapD => "map_d",
+                TextureType::MapBump => "map_bump",
+                TextureType::MapDisp => "disp",
+                TextureType::MapDecal => "decal",
+                TextureType::MapNorm => "norm",
+                TextureType::MapKe => "map_Ke",
+                TextureType::MapPr => "map_Pr",
+                TextureType::MapPm => "map_Pm",
+                TextureType::MapPs => "map_Ps",
+                TextureType::Unknown => continue,
+            };
+            writeln!(mtl, "{} {}", keyword, relative_uri(texture, dir))?;
+        }
+        writeln!(mtl)?;
+    }
+    mtl.flush()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Returns the directory of a file, `.` if the path has no parent.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Returns the path with forward slashes, as expected by obj and glTF.
+fn path_uri(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Returns the uri of a file referenced by a file in `dir`, relative to
+/// `dir` if they share a root, absolute otherwise.
+fn relative_uri(path: &Path, dir: &Path) -> String {
+    let absolute = |path: &Path| {
+        path.canonicalize()
+            .or_else(|_| std::env::current_dir().map(|cwd| cwd.join(path)))
+    };
+    let (Ok(path), Ok(dir)) = (absolute(path), absolute(dir)) else {
+        return path_uri(path);
+    };
+    let path_parts = path.components().collect::<Vec<_>>();
+    let dir_parts = dir.components().collect::<Vec<_>>();
+    let common = path_parts
+        .iter()
+        .zip(&dir_parts)
+        .take_while(|(a, b)| a == b)
+        .count();
+    if common == 0 {
+        return path_uri(&path);
+    }
+    let mut relative = PathBuf::new();
+    for _ in common..dir_parts.len() {
+        relative.push("..");
+    }
+    relative.extend(&path_parts[common..]);
+    path_uri(&relative)
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::relative_uri;
+    use crate::core::{
+        mesh::{AttribContainer, Mesh, VertexAttribute},
+        Material, TextureType,
+    };
+    use std::path::{Path, PathBuf};
+
+    /// Creates an empty directory for the files of a test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("bkfw-export-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("textures")).unwrap();
+        dir
+    }
+
+    /// Returns the positions of the corners of the triangles, sorted.
+    fn sorted_corners(mesh: &Mesh) -> Vec<[u32; 3]> {
+        let positions = mesh.positions().unwrap();
+        let mut corners = mesh
+            .triangles()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .map(|i| positions[i as usize].map(f32::to_bits))
+            .collect::<Vec<_>>();
+        corners.sort();
+        corners
+    }
+
+    #[test]
+    fn obj_round_trip_keeps_triangles_and_textures() {
+        let dir = test_dir("obj");
+        let texture = dir.join("textures").join("brick.png");
+        std::fs::write(&texture, []).unwrap();
+        let mut material = Material::default();
+        material.name = "brick".into();
+        material
+            .textures
+            .insert(TextureType::MapKd, texture.clone());
+        let mut cube = Mesh::cube(1.0);
+        cube.set_material(material);
+
+        let path = dir.join("cube.obj");
+        cube.save_obj(&path).unwrap();
+        let mtl = std::fs::read_to_string(dir.join("cube.mtl")).unwrap();
+        let loaded = Mesh::try_load_from_obj(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(mtl.contains("map_Kd textures/brick.png"));
+        assert_eq!(loaded.triangle_count(), cube.triangle_count());
+        assert_eq!(sorted_corners(&loaded), sorted_corners(&cube));
+        let materials = loaded.materials.unwrap();
+        assert_eq!(materials.len(), 1);
+        assert_eq!(
+            materials[0].textures[&TextureType::MapKd].file_name(),
+            texture.file_name()
+        );
+    }
+
+    #[test]
+    fn gltf_buffer_holds_the_vertices_and_indices() {
+        let dir = test_dir("gltf");
+        let cube = Mesh::cube(1.0);
+        let path = dir.join("cube.gltf");
+        cube.save_gltf(&path).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        let buffer = std::fs::read(dir.join("cube.bin")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let positions = cube.positions().unwrap();
+        let n_bytes = std::mem::size_of_val(positions);
+        assert_eq!(&buffer[..n_bytes], bytemuck::cast_slice::<_, u8>(positions));
+        assert!(json.contains(r#""uri":"cube.bin""#));
+        assert!(json.contains(&format!(r#""byteLength":{}"#, buffer.len())));
+        assert!(json.contains(r#""min":[-0.5,-0.5,-0.5],"max":[0.5,0.5,0.5]"#));
+    }
+
+    #[test]
+    fn empty_mesh_has_zero_bounds() {
+        let dir = test_dir("empty");
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.attributes.insert(
+            VertexAttribute::POSITION,
+            AttribContainer::new::<[f32; 3]>(&[]),
+        );
+        let path = dir.join("empty.gltf");
+        mesh.save_gltf(&path).unwrap();
+        let json = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(json.contains(r#""min":[0,0,0],"max":[0,0,0]"#));
+    }
+
+    #[test]
+    fn texture_uris_are_relative_to_the_exported_file() {
+        let dir = test_dir("uri");
+        let texture = dir.join("textures").join("brick.png");
+        std::fs::write(&texture, []).unwrap();
+        let nested = dir.join("out");
+        std::fs::create_dir_all(&nested).unwrap();
+
+        assert_eq!(relative_uri(&texture, &dir), "textures/brick.png");
+        assert_eq!(relative_uri(&texture, &nested), "../textures/brick.png");
+        assert_eq!(
+            relative_uri(Path::new("textures/brick.png"), Path::new(".")),
+            "textures/brick.png"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
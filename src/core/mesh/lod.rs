@@ -0,0 +1,64 @@
+use crate::core::mesh::MeshBundle;
+
+/// Level of detail of a [`LodGroup`].
+#[derive(Clone, Copy, Debug)]
+pub struct LodLevel {
+    /// Mesh drawn at this level.
+    pub mesh: MeshBundle,
+    /// Distance to the camera up to which this level is drawn.
+    pub max_distance: f32,
+}
+
+/// Set of meshes of decreasing detail, the entity draws the one matching its
+/// distance to the main camera.
+///
+/// The [`MeshBundle`] component of the entity is switched to the mesh of the
+/// selected level when the scene is prepared.
+#[derive(Clone, Debug)]
+pub struct LodGroup {
+    /// Levels sorted by increasing distance.
+    levels: Vec<LodLevel>,
+    /// Index of the selected level.
+    current: usize,
+}
+
+impl LodGroup {
+    /// Creates a new LOD group, the levels being sorted by distance. The
+    /// first level (the closest) is selected.
+    pub fn new(mut levels: Vec<LodLevel>) -> Self {
+        assert!(
+            !levels.is_empty(),
+            "A LOD group requires at least one level."
+        );
+        levels.sort_by(|a, b| a.max_distance.total_cmp(&b.max_distance));
+        Self { levels, current: 0 }
+    }
+
+    /// Returns the levels sorted by increasing distance.
+    pub fn levels(&self) -> &[LodLevel] {
+        &self.levels
+    }
+
+    /// Returns the mesh of the selected level.
+    pub fn current(&self) -> MeshBundle {
+        self.levels[self.current].mesh
+    }
+
+    /// Selects the level for the given distance to the camera; the last
+    /// level is used beyond the distance of all levels.
+    ///
+    /// Returns the previous and the new mesh if the level changed.
+    pub fn select(&mut self, distance: f32) -> Option<(MeshBundle, MeshBundle)> {
+        let level = self
+            .levels
+            .iter()
+            .position(|level| distance <= level.max_distance)
+            .unwrap_or(self.levels.len() - 1);
+        if level == self.current {
+            return None;
+        }
+        let previous = self.current();
+        self.current = level;
+        Some((previous, self.current()))
+    }
+}
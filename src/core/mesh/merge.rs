@@ -0,0 +1,206 @@
+//! Merging of meshes into a single mesh, e.g. to draw many small meshes at
+//! once.
+
+use crate::core::{
+    mesh::{AttribContainer, Indices, Mesh, SubMesh, VertexAttribute},
+    FxHashMap,
+};
+use glam::{Mat3, Mat4, Vec3, Vec4};
+
+impl Mesh {
+    /// Merges meshes into a single mesh, or returns `None` if there are no
+    /// meshes or if they can't be concatenated.
+    ///
+    /// The vertex attributes are concatenated, the attributes missing from
+    /// some of the meshes being filled with zeros, and the indices are offset
+    /// accordingly. Identical materials are shared, and each sub-mesh of the
+    /// meshes (or each whole mesh without sub-meshes) becomes a sub-mesh of
+    /// the merged mesh.
+    ///
+    /// The meshes must have the same list topology; strips can't be merged.
+    pub fn merge(meshes: &[Mesh]) -> Option<Mesh> {
+        let topology = meshes.first()?.topology;
+        if meshes.iter().any(|mesh| mesh.topology != topology) || topology.is_strip() {
+            log::error!("Only meshes of the same list topology can be merged.");
+            return None;
+        }
+
+        let n_vertices = meshes
+            .iter()
+            .map(|mesh| mesh.positions().map_or(0, |positions| positions.len()))
+            .collect::<Vec<_>>();
+        let mut merged = Mesh::new(topology);
+
+        // Attributes, filled with zeros for the meshes lacking them.
+        for attribute in meshes
+            .iter()
+            .flat_map(|mesh| mesh.attributes.0.keys().copied())
+        {
+            if merged.attributes.0.contains_key(&attribute) {
+                continue;
+            }
+            let mut data = Vec::with_capacity(n_vertices.iter().sum::<usize>() * attribute.size);
+            for (mesh, n) in meshes.iter().zip(&n_vertices) {
+                match mesh.attributes.0.get(&attribute) {
+                    Some(container) => data.extend_from_slice(container.as_bytes()),
+                    None => {
+                        log::warn!(
+                            "Mesh {} has no {} attribute, filled with zeros in the merged mesh.",
+                            mesh.name,
+                            attribute.name
+                        );
+                        data.resize(data.len() + n * attribute.size, 0);
+                    }
+                }
+            }
+            merged
+                .attributes
+                .insert(attribute, AttribContainer::new(&data));
+        }
+
+        let mut indices = Vec::new();
+        let mut sub_meshes = Vec::new();
+        let mut materials = Vec::new();
+        let mut material_of_hash = FxHashMap::default();
+        let mut vertex_offset = 0;
+        for (mesh, n) in meshes.iter().zip(&n_vertices) {
+            let index_offset = indices.len() as u32;
+            match &mesh.indices {
+                Some(Indices::U32(source)) => {
+                    indices.extend(source.iter().map(|i| i + vertex_offset))
+                }
+                Some(Indices::U16(source)) => {
+                    indices.extend(source.iter().map(|i| *i as u32 + vertex_offset))
+                }
+                None => indices.extend(vertex_offset..vertex_offset + *n as u32),
+            }
+
+            // Materials of the mesh, as indices in the merged materials.
+            let remap = mesh
+                .materials
+                .iter()
+                .flatten()
+                .map(|material| {
+                    *material_of_hash
+                        .entry(material.content_hash())
+                        .or_insert_with(|| {
+                            materials.push(material.clone());
+                            materials.len() as u32 - 1
+                        })
+                })
+                .collect::<Vec<_>>();
+            match &mesh.sub_meshes {
+                Some(sources) => sub_meshes.extend(sources.iter().map(|sub_mesh| {
+                    SubMesh {
+                        range: sub_mesh.range.start + index_offset
+                            ..sub_mesh.range.end + index_offset,
+                        material: sub_mesh
+                            .material
+                            .and_then(|material| remap.get(material as usize).copied()),
+                    }
+                })),
+                None => sub_meshes.push(SubMesh {
+                    range: index_offset..indices.len() as u32,
+                    material: None,
+                }),
+            }
+            vertex_offset += *n as u32;
+        }
+
+        merged.indices = Some(Indices::U32(indices));
+        merged.sub_meshes = Some(sub_meshes);
+        merged.materials = (!materials.is_empty()).then_some(materials);
+        Some(merged)
+    }
+
+    /// Transforms the vertices of the mesh by a matrix, e.g. to merge meshes
+    /// placed in the world.
+    ///
+    /// Normals and tangents are transformed as directions. The triangles are
+    /// turned over if the matrix mirrors the mesh, so that they keep facing
+    /// outwards.
+    pub fn transform(&mut self, matrix: Mat4) {
+        let normal_matrix = Mat3::from_mat4(matrix).inverse().transpose();
+        let mirrored = matrix.determinant() < 0.0;
+        for (attribute, container) in self.attributes.0.iter_mut() {
+            if *attribute == VertexAttribute::POSITION {
+                for p in container.as_slice_mut::<[f32; 3]>() {
+                    *p = matrix.transform_point3(Vec3::from(*p)).to_array();
+                }
+            } else if *attribute == VertexAttribute::NORMAL {
+                for n in container.as_slice_mut::<[f32; 3]>() {
+                    *n = (normal_matrix * Vec3::from(*n))
+                        .normalize_or_zero()
+                        .to_array();
+                }
+            } else if *attribute == VertexAttribute::TANGENT {
+                for t in container.as_slice_mut::<[f32; 4]>() {
+                    let tangent = matrix
+                        .transform_vector3(Vec3::from_slice(t))
+                        .normalize_or_zero();
+                    let w = if mirrored { -t[3] } else { t[3] };
+                    *t = Vec4::from((tangent, w)).to_array();
+                }
+            }
+        }
+        if mirrored && self.topology == wgpu::PrimitiveTopology::TriangleList {
+            if self.indices.is_none() {
+                let n_vertices = self.positions().map_or(0, |positions| positions.len());
+                self.indices = Some(Indices::U32((0..n_vertices as u32).collect()));
+            }
+            match self.indices.as_mut() {
+                Some(Indices::U32(indices)) => {
+                    indices.chunks_exact_mut(3).for_each(|t| t.swap(1, 2))
+                }
+                Some(Indices::U16(indices)) => {
+                    indices.chunks_exact_mut(3).for_each(|t| t.swap(1, 2))
+                }
+                None => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::core::{
+        mesh::{Indices, Mesh, VertexAttribute},
+        Alignment, Material,
+    };
+
+    #[test]
+    fn merged_meshes_keep_their_triangles_and_share_materials() {
+        let mut cube = Mesh::cube(1.0);
+        cube.set_material(Material::default());
+        let mut plane = Mesh::plane(1.0, Alignment::XY);
+        plane.set_material(Material::default());
+        let merged = Mesh::merge(&[cube.clone(), plane.clone()]).unwrap();
+
+        let n_cube = cube.positions().unwrap().len();
+        assert_eq!(
+            merged.positions().unwrap().len(),
+            n_cube + plane.positions().unwrap().len()
+        );
+        assert_eq!(
+            merged.triangle_count(),
+            cube.triangle_count() + plane.triangle_count()
+        );
+        let Some(Indices::U32(indices)) = &merged.indices else {
+            panic!("Merged indices are not 32 bits.");
+        };
+        let plane_triangles = plane.triangles().unwrap();
+        let offset = cube.indices.as_ref().unwrap().len();
+        assert!(indices[offset..]
+            .iter()
+            .zip(plane_triangles.iter().flatten())
+            .all(|(merged, source)| *merged == source + n_cube as u32));
+        assert_eq!(merged.materials.as_ref().unwrap().len(), 1);
+        let sub_meshes = merged.sub_meshes.as_ref().unwrap();
+        assert_eq!(sub_meshes.len(), 2);
+        assert_eq!(sub_meshes[1].range.start, offset as u32);
+        assert!(sub_meshes
+            .iter()
+            .all(|sub_mesh| sub_mesh.material == Some(0)));
+        assert!(merged.attributes.0.contains_key(&VertexAttribute::NORMAL));
+    }
+}
@@ -1,11 +1,26 @@
-use crate::core::{
-    mesh::{AttribContainer, Indices, Mesh, SubMesh, VertexAttribute},
-    Alignment, Color, Material,
+use crate::{
+    core::{
+        mesh::{
+            AttribContainer, Indices, Mesh, SubMesh, SubdivisionScheme, UvProjection,
+            VertexAttribute,
+        },
+        procgen::{building, BuildingParams},
+        spline::Spline,
+        Alignment, Color, FxHasher, Material,
+    },
+    scene::vec3_to_py,
 };
-use glam::Vec3;
+use glam::{Vec2, Vec3};
 use numpy as np;
-use pyo3::Python;
-use std::path::PathBuf;
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    PyAny, PyRef, PyResult, Python,
+};
+use std::{
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    sync::Arc,
+};
 
 /// Topology of a mesh primitive.
 #[pyo3::pyclass]
@@ -91,6 +106,12 @@ impl Mesh {
         Self::sphere(radius, segments, rings)
     }
 
+    #[staticmethod]
+    #[pyo3(name = "create_water_plane")]
+    pub fn new_water_plane_py(size: f32) -> Self {
+        Self::water_plane(size)
+    }
+
     #[staticmethod]
     #[pyo3(name = "create_grid")]
     pub fn new_grid_py(
@@ -126,6 +147,192 @@ impl Mesh {
         Self::load_from_obj(&path)
     }
 
+    /// Generates a building standing on the footprint, a polygon given as
+    /// `(x, z)` points on the ground. The walls, the roof and the ground
+    /// floor are sub-meshes with the materials of the parameters.
+    #[staticmethod]
+    #[pyo3(name = "create_building")]
+    pub fn create_building_py(
+        footprint: Vec<[f32; 2]>,
+        params: &BuildingParams,
+    ) -> pyo3::PyResult<Mesh> {
+        let footprint = footprint.into_iter().map(Vec2::from).collect::<Vec<_>>();
+        building(&footprint, params).map_err(pyo3::exceptions::PyValueError::new_err)
+    }
+
+    /// Creates the surface swept by the profile along the spline, e.g. a
+    /// road, a wall or a river bed.
+    ///
+    /// The profile points are `(x, y)` offsets to the right of and above
+    /// the path, ordered clockwise as seen looking along the path (e.g. from
+    /// left to right for a road). The texture is tiled every `uv_scale`
+    /// units along the profile and the path.
+    #[staticmethod]
+    #[pyo3(name = "sweep_along_spline")]
+    #[pyo3(signature = (profile, spline, uv_scale=1.0))]
+    pub fn sweep_along_spline_py(
+        profile: Vec<[f32; 2]>,
+        spline: &Spline,
+        uv_scale: f32,
+    ) -> pyo3::PyResult<Mesh> {
+        if profile.len() < 2 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "A profile requires at least 2 points.",
+            ));
+        }
+        if uv_scale <= 0.0 {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The UV scale must be positive.",
+            ));
+        }
+        let profile = profile.into_iter().map(Vec2::from).collect::<Vec<_>>();
+        Ok(Self::sweep_along_spline(&profile, spline, uv_scale))
+    }
+
+    /// Merges meshes into a single mesh drawn at once, each mesh becoming
+    /// one or more sub-meshes sharing the identical materials.
+    #[staticmethod]
+    #[pyo3(name = "merge")]
+    pub fn merge_py(meshes: Vec<Mesh>) -> pyo3::PyResult<Mesh> {
+        Self::merge(&meshes).ok_or_else(|| {
+            pyo3::exceptions::PyValueError::new_err(
+                "At least one mesh is required, all of the same list topology.",
+            )
+        })
+    }
+
+    /// Creates a triangle mesh from numpy arrays of `n` positions of shape
+    /// `(n, 3)` and of indices of shape `(m,)` or `(m, 3)`, with optional
+    /// normals of shape `(n, 3)`, UVs of shape `(n, 2)` and colors of shape
+    /// `(n, 3)` or `(n, 4)`. Contiguous float32 and uint32 arrays are copied
+    /// once, arrays of other float and integer types are converted. The
+    /// normals are computed if they are not given.
+    #[staticmethod]
+    #[pyo3(
+        name = "from_arrays",
+        signature = (positions, indices, normals=None, uvs=None, colors=None)
+    )]
+    pub fn from_arrays_py(
+        positions: &PyAny,
+        indices: &PyAny,
+        normals: Option<&PyAny>,
+        uvs: Option<&PyAny>,
+        colors: Option<&PyAny>,
+    ) -> PyResult<Mesh> {
+        let (positions, _) = float_rows("positions", positions, &[3], None)?;
+        let n_vertices = positions.n_bytes() / VertexAttribute::POSITION.size;
+        let indices = index_rows(indices, n_vertices)?;
+        if indices.len() % 3 != 0 {
+            return Err(PyValueError::new_err(format!(
+                "The number of indices must be a multiple of 3, not {}",
+                indices.len()
+            )));
+        }
+
+        let mut mesh = Self::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.attributes.insert(VertexAttribute::POSITION, positions);
+        mesh.indices = Some(Indices::U32(indices));
+        match normals {
+            Some(normals) => {
+                let (normals, _) = float_rows("normals", normals, &[3], Some(n_vertices))?;
+                mesh.attributes.insert(VertexAttribute::NORMAL, normals);
+            }
+            None => mesh.compute_normals(),
+        }
+        if let Some(uvs) = uvs {
+            let (uvs, _) = float_rows("uvs", uvs, &[2], Some(n_vertices))?;
+            mesh.attributes.insert(VertexAttribute::UV, uvs);
+        }
+        if let Some(colors) = colors {
+            let colors = match float_rows("colors", colors, &[3, 4], Some(n_vertices))? {
+                (colors, 3) => {
+                    let rgba = colors
+                        .as_slice::<[f32; 3]>()
+                        .iter()
+                        .map(|[r, g, b]| [*r, *g, *b, 1.0])
+                        .collect::<Vec<_>>();
+                    AttribContainer::new(&rgba)
+                }
+                (colors, _) => colors,
+            };
+            mesh.attributes.insert(VertexAttribute::COLOR, colors);
+        }
+        Ok(mesh)
+    }
+
+    /// Returns a simplified copy of the mesh with about `target_ratio` of its
+    /// triangles, e.g. to be used as a level of detail.
+    #[pyo3(name = "simplify")]
+    pub fn simplify_py(&self, target_ratio: f32) -> Mesh {
+        self.simplify(target_ratio)
+    }
+
+    /// Returns a smoothed copy of the mesh, subdivided `levels` times with
+    /// the given scheme.
+    #[pyo3(name = "subdivide")]
+    #[pyo3(signature = (levels=1, scheme=SubdivisionScheme::Loop))]
+    pub fn subdivide_py(&self, levels: u32, scheme: SubdivisionScheme) -> Mesh {
+        self.subdivide(levels, scheme)
+    }
+
+    /// Writes the mesh to a wavefront obj file, the materials are written to
+    /// a material library next to it.
+    #[pyo3(name = "save_obj")]
+    pub fn save_obj_py(&self, path: &str) -> pyo3::PyResult<()> {
+        self.save_obj(path).map_err(|err| {
+            pyo3::exceptions::PyIOError::new_err(format!("Failed to save {}: {}", path, err))
+        })
+    }
+
+    /// Writes the mesh to a glTF file, the vertex data is written to a binary
+    /// buffer next to it.
+    #[pyo3(name = "save_gltf")]
+    pub fn save_gltf_py(&self, path: &str) -> pyo3::PyResult<()> {
+        self.save_gltf(path).map_err(|err| {
+            pyo3::exceptions::PyIOError::new_err(format!("Failed to save {}: {}", path, err))
+        })
+    }
+
+    /// Replaces the texture coordinates of the mesh by a projection of its
+    /// vertices, see `UvProjection`.
+    #[pyo3(name = "generate_uvs")]
+    pub fn generate_uvs_py(&mut self, mode: UvProjection) {
+        self.generate_uvs(mode);
+    }
+
+    /// Returns the number of triangles of the mesh.
+    #[pyo3(name = "triangle_count")]
+    pub fn triangle_count_py(&self) -> usize {
+        self.triangle_count()
+    }
+
+    /// Returns the total area of the triangles of the mesh.
+    #[pyo3(name = "surface_area")]
+    pub fn surface_area_py(&self) -> f32 {
+        self.surface_area()
+    }
+
+    /// Returns the volume enclosed by the mesh, or `None` if the mesh is not
+    /// watertight.
+    #[pyo3(name = "volume")]
+    pub fn volume_py(&self) -> Option<f32> {
+        self.volume()
+    }
+
+    /// Returns whether the mesh is closed and consistently oriented.
+    #[pyo3(name = "is_watertight")]
+    pub fn is_watertight_py(&self) -> bool {
+        self.is_watertight()
+    }
+
+    /// Returns a sphere enclosing the vertices of the mesh as (center,
+    /// radius), or `None` if the mesh has no vertices.
+    #[pyo3(name = "bounding_sphere")]
+    pub fn bounding_sphere_py(&self) -> Option<(pyo3::Py<np::PyArray2<f32>>, f32)> {
+        self.bounding_sphere()
+            .map(|(center, radius)| (vec3_to_py(center), radius))
+    }
+
     #[deprecated]
     pub fn apply_material(&mut self, material: Material) {
         self.set_material(material)
@@ -139,7 +346,10 @@ impl Mesh {
         self.materials.as_mut().unwrap().push(material);
         let material_index = self.materials.as_ref().unwrap().len() as u32 - 1;
         self.sub_meshes = Some(vec![SubMesh {
-            range: 0..self.indices.as_ref().unwrap().len() as u32,
+            range: 0..self
+                .indices
+                .as_ref()
+                .map_or(self.attributes.vertex_count(), Indices::len) as u32,
             material: Some(material_index),
         }]);
     }
@@ -202,6 +412,13 @@ impl Mesh {
         self.sub_meshes = sub_meshes;
     }
 
+    /// Returns a read-only numpy view of the positions of the vertices, of
+    /// shape `(n, 3)`, `None` if the mesh has no positions.
+    #[getter]
+    pub fn get_positions<'py>(&self, py: Python<'py>) -> Option<&'py np::PyArray2<f32>> {
+        self.attribute_view(py, VertexAttribute::POSITION, 3)
+    }
+
     #[setter]
     pub fn set_positions(&mut self, vertices: Option<Vec<[f32; 3]>>) {
         if let Some(vertices) = vertices {
@@ -210,12 +427,33 @@ impl Mesh {
         }
     }
 
+    /// Returns a read-only numpy view of the normals of the vertices, of
+    /// shape `(n, 3)`, `None` if the mesh has no normals.
+    #[getter]
+    pub fn get_normals<'py>(&self, py: Python<'py>) -> Option<&'py np::PyArray2<f32>> {
+        self.attribute_view(py, VertexAttribute::NORMAL, 3)
+    }
+
     #[setter]
     pub fn set_normals(&mut self, normals: Vec<[f32; 3]>) {
         self.attributes
             .insert(VertexAttribute::NORMAL, AttribContainer::new(&normals));
     }
 
+    /// Returns a read-only numpy view of the UVs of the vertices, of shape
+    /// `(n, 2)`, `None` if the mesh has no UVs.
+    #[getter]
+    pub fn get_texcoords<'py>(&self, py: Python<'py>) -> Option<&'py np::PyArray2<f32>> {
+        self.attribute_view(py, VertexAttribute::UV, 2)
+    }
+
+    /// Returns a read-only numpy view of the colors of the vertices, of
+    /// shape `(n, 4)`, `None` if the mesh has no colors.
+    #[getter]
+    pub fn get_colors<'py>(&self, py: Python<'py>) -> Option<&'py np::PyArray2<f32>> {
+        self.attribute_view(py, VertexAttribute::COLOR, 4)
+    }
+
     #[setter]
     pub fn set_texcoords(&mut self, uvs: Option<Vec<[f32; 2]>>) {
         if let Some(uvs) = uvs {
@@ -231,6 +469,93 @@ impl Mesh {
         }
     }
 
+    /// Sets a custom vertex attribute read by the custom shaders, with 1 to
+    /// 4 values per vertex. The attribute is read at the given location, by
+    /// default the location of the attribute of the same name or the first
+    /// free one.
+    #[pyo3(name = "set_attribute", signature = (name, values, location=None))]
+    pub fn set_attribute_py(
+        &mut self,
+        name: &str,
+        values: &np::PyArrayDyn<f32>,
+        location: Option<u32>,
+    ) -> pyo3::PyResult<()> {
+        let format = match values.shape() {
+            [_] | [_, 1] => wgpu::VertexFormat::Float32,
+            [_, 2] => wgpu::VertexFormat::Float32x2,
+            [_, 3] => wgpu::VertexFormat::Float32x3,
+            [_, 4] => wgpu::VertexFormat::Float32x4,
+            shape => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "Attribute {} must have 1 to 4 values per vertex, not shape {:?}",
+                    name, shape
+                )))
+            }
+        };
+        let values = values
+            .to_vec()
+            .map_err(|err| pyo3::exceptions::PyValueError::new_err(err.to_string()))?;
+        let location = location
+            .or_else(|| {
+                self.custom_attribute(name)
+                    .map(|(attribute, _)| attribute.shader_location)
+            })
+            .unwrap_or_else(|| {
+                (VertexAttribute::FIRST_CUSTOM_LOCATION..VertexAttribute::MAX_LOCATIONS)
+                    .find(|location| {
+                        self.attributes
+                            .custom()
+                            .all(|(attribute, _)| attribute.shader_location != *location)
+                    })
+                    .unwrap_or(VertexAttribute::MAX_LOCATIONS)
+            });
+        let attribute = VertexAttribute::custom(name, format, location)
+            .map_err(pyo3::exceptions::PyValueError::new_err)?;
+        self.set_attribute(attribute, AttribContainer::new(&values))
+            .map_err(pyo3::exceptions::PyValueError::new_err)
+    }
+
+    /// Returns a copy of the indices of the primitives of the mesh, `None`
+    /// if the mesh is not indexed.
+    #[getter]
+    pub fn get_indices<'py>(&self, py: Python<'py>) -> Option<&'py np::PyArray1<u32>> {
+        self.indices.as_ref().map(|indices| match indices {
+            Indices::U32(indices) => np::PyArray1::from_slice(py, indices),
+            Indices::U16(indices) => {
+                np::PyArray1::from_iter(py, indices.iter().map(|index| *index as u32))
+            }
+        })
+    }
+
+    /// Sets the indices of the primitives of the mesh, whatever its
+    /// topology.
+    #[setter]
+    pub fn set_indices(&mut self, indices: Option<Vec<u32>>) {
+        self.indices = indices.map(Indices::U32);
+    }
+
+    /// Returns the topology of the mesh primitives.
+    #[getter]
+    pub fn get_topology(&self) -> PyTopology {
+        self.topology.into()
+    }
+
+    #[setter]
+    pub fn set_topology(&mut self, topology: PyTopology) {
+        self.topology = topology.into();
+    }
+
+    /// Returns the size of the points of a point list, in world units.
+    #[getter]
+    pub fn get_point_size(&self) -> f32 {
+        self.point_size
+    }
+
+    #[setter]
+    pub fn set_point_size(&mut self, size: f32) {
+        self.point_size = size.max(0.0);
+    }
+
     #[getter]
     pub fn get_name(&self) -> &str {
         self.name.as_str()
@@ -241,6 +566,31 @@ impl Mesh {
         self.name = name.into();
     }
 
+    pub fn __repr__(&self) -> String {
+        format!(
+            "Mesh(name='{}', topology={:?}, vertices={}, indices={}, sub_meshes={})",
+            self.name,
+            PyTopology::from(self.topology),
+            self.positions().map_or(0, |positions| positions.len()),
+            self.indices.as_ref().map_or(0, |indices| indices.len()),
+            self.sub_meshes
+                .as_ref()
+                .map_or(0, |sub_meshes| sub_meshes.len()),
+        )
+    }
+
+    /// Meshes are equal if they have the same name, which is unique unless
+    /// set explicitly.
+    pub fn __eq__(&self, other: PyRef<Self>) -> bool {
+        self.name == other.name
+    }
+
+    pub fn __hash__(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.name.hash(&mut hasher);
+        hasher.finish()
+    }
+
     /// Computes per vertex normals for the mesh from the UVs.
     #[pyo3(name = "compute_normals")]
     pub fn compute_normals_py(&mut self) {
@@ -252,4 +602,176 @@ impl Mesh {
     pub fn compute_tangents_py(&mut self) {
         self.compute_tangents();
     }
+
+    /// Generates the vertex colors from the height of the vertices. The
+    /// gradient is a list of `(t, color)` stops, `t` going from 0 at the lowest
+    /// vertex to 1 at the highest one.
+    #[pyo3(name = "colorize_by_height")]
+    pub fn colorize_by_height_py(&mut self, gradient: Vec<(f32, Color)>) {
+        self.colorize_by_height(&gradient);
+    }
+
+    /// Generates the vertex colors from the slope of the surface. The
+    /// gradient is a list of `(t, color)` stops, `t` going from 0 for flat
+    /// surfaces to 1 for vertical ones.
+    #[pyo3(name = "colorize_by_normal_slope")]
+    pub fn colorize_by_normal_slope_py(&mut self, gradient: Vec<(f32, Color)>) {
+        self.colorize_by_normal_slope(&gradient);
+    }
+
+    /// Bakes the ambient occlusion onto the vertex colors, casting `samples`
+    /// rays per vertex against the mesh within `radius`.
+    #[pyo3(name = "bake_ambient_occlusion", signature = (samples = 64, radius = 1.0))]
+    pub fn bake_ambient_occlusion_py(&mut self, samples: u32, radius: f32) {
+        self.bake_ambient_occlusion(samples, radius);
+    }
+}
+
+impl Mesh {
+    /// Returns a read-only numpy view of the values of a vertex attribute,
+    /// of shape `(n, width)`. The view shares the values with the mesh, which
+    /// copies them before modifying them while the view is alive.
+    fn attribute_view<'py>(
+        &self,
+        py: Python<'py>,
+        attribute: VertexAttribute,
+        width: usize,
+    ) -> Option<&'py np::PyArray2<f32>> {
+        let bytes = self.attributes.0.get(&attribute)?.shared_bytes();
+        let values = bytemuck::cast_slice::<u8, f32>(&bytes);
+        let view =
+            np::ndarray::ArrayView2::from_shape((values.len() / width, width), values).ok()?;
+        let owner = pyo3::PyCell::new(py, AttribBytes(bytes.clone())).ok()?;
+        // SAFETY: the values are kept alive by the owner of the view and are
+        // never modified in place while it holds them.
+        let array = unsafe { np::PyArray2::borrow_from_array(&view, owner.as_ref()) };
+        // Writing to the view would modify the values of the other meshes
+        // sharing them.
+        unsafe {
+            (*array.as_array_ptr()).flags &= !np::npyffi::NPY_ARRAY_WRITEABLE;
+        }
+        Some(array)
+    }
+}
+
+/// Values of a vertex attribute, kept alive by the numpy views of them.
+#[pyo3::pyclass]
+struct AttribBytes(Arc<Vec<u8>>);
+
+/// Returns the untyped numpy array of an argument.
+fn untyped_array<'py>(name: &str, values: &'py PyAny) -> PyResult<&'py np::PyUntypedArray> {
+    values.downcast::<np::PyUntypedArray>().map_err(|_| {
+        PyTypeError::new_err(format!(
+            "{} must be a numpy array, not {}",
+            name,
+            values.get_type().name().unwrap_or("an unknown type")
+        ))
+    })
+}
+
+/// Copies the rows of a 2D numpy array of floats into an attribute
+/// container, with a single copy if the array is a contiguous float32 one.
+/// The rows have one of the given widths, and there are `n_rows` of them if
+/// given. Returns the container and the width of the rows.
+fn float_rows(
+    name: &str,
+    values: &PyAny,
+    widths: &[usize],
+    n_rows: Option<usize>,
+) -> PyResult<(AttribContainer, usize)> {
+    let array = untyped_array(name, values)?;
+    let (rows, width) = match array.shape() {
+        [rows, width] if widths.contains(width) => (*rows, *width),
+        shape => {
+            let widths = widths
+                .iter()
+                .map(|width| format!("(n, {})", width))
+                .collect::<Vec<_>>();
+            return Err(PyValueError::new_err(format!(
+                "{} must be of shape {}, not {:?}",
+                name,
+                widths.join(" or "),
+                shape
+            )));
+        }
+    };
+    if let Some(n_rows) = n_rows.filter(|n_rows| *n_rows != rows) {
+        return Err(PyValueError::new_err(format!(
+            "{} has {} rows, the mesh has {} vertices",
+            name, rows, n_rows
+        )));
+    }
+    let container = if let Ok(values) = values.downcast::<np::PyArray2<f32>>() {
+        let values = values.readonly();
+        match values.as_slice() {
+            Ok(values) => AttribContainer::new(values),
+            Err(_) => AttribContainer::new(&values.as_array().iter().copied().collect::<Vec<_>>()),
+        }
+    } else if let Ok(values) = values.downcast::<np::PyArray2<f64>>() {
+        let values = values.readonly();
+        let values = values
+            .as_array()
+            .iter()
+            .map(|v| *v as f32)
+            .collect::<Vec<_>>();
+        AttribContainer::new(&values)
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "{} must be an array of float32 or float64, not {}",
+            name,
+            array.dtype()
+        )));
+    };
+    Ok((container, width))
+}
+
+/// Copies the indices of a numpy array of integers of shape `(m,)` or
+/// `(m, 3)`, checking that they refer to one of the `n_vertices` vertices.
+fn index_rows(values: &PyAny, n_vertices: usize) -> PyResult<Vec<u32>> {
+    let array = untyped_array("indices", values)?;
+    if !matches!(array.shape(), [_] | [_, 3]) {
+        return Err(PyValueError::new_err(format!(
+            "indices must be of shape (m,) or (m, 3), not {:?}",
+            array.shape()
+        )));
+    }
+    let indices = if let Ok(values) = values.downcast::<np::PyArrayDyn<u32>>() {
+        let values = values.readonly();
+        match values.as_slice() {
+            Ok(values) => Some(values.to_vec()),
+            Err(_) => Some(values.as_array().iter().copied().collect()),
+        }
+    } else if let Ok(values) = values.downcast::<np::PyArrayDyn<i64>>() {
+        cast_indices(values)
+    } else if let Ok(values) = values.downcast::<np::PyArrayDyn<i32>>() {
+        cast_indices(values)
+    } else if let Ok(values) = values.downcast::<np::PyArrayDyn<u64>>() {
+        cast_indices(values)
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "indices must be an array of integers, not {}",
+            array.dtype()
+        )));
+    };
+    match indices {
+        Some(indices) if indices.iter().all(|index| (*index as usize) < n_vertices) => Ok(indices),
+        _ => Err(PyValueError::new_err(format!(
+            "The indices must be in 0..{}, the number of vertices",
+            n_vertices
+        ))),
+    }
+}
+
+/// Converts the indices to `u32`, `None` if one of them doesn't fit.
+fn cast_indices<T>(values: &np::PyArrayDyn<T>) -> Option<Vec<u32>>
+where
+    T: np::Element + Copy,
+    u32: TryFrom<T>,
+{
+    let values = values.readonly();
+    let values = values.as_array();
+    values
+        .iter()
+        .map(|index| u32::try_from(*index).ok())
+        .collect()
 }
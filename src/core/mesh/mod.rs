@@ -1,13 +1,27 @@
 use glam::{Vec3, Vec4};
 use rustc_hash::FxHashMap;
 use std::{
+    borrow::Cow,
     fmt::Debug,
+    io::{BufRead, BufReader},
     ops::Range,
     path::{Path, PathBuf},
     sync::atomic::AtomicU64,
 };
 
 mod attribute;
+mod colorize;
+mod export;
+mod lod;
+mod merge;
+mod obj_stream;
+mod occlusion;
+mod scatter;
+mod simplify;
+mod stats;
+mod subdivide;
+mod sweep;
+mod uv;
 
 #[path = "mesh_py.rs"]
 pub mod py;
@@ -17,10 +31,16 @@ use crate::core::{
     Alignment, Material, MaterialBundle, SmlString, TextureBundle,
 };
 pub use attribute::*;
+pub use lod::*;
+pub use obj_stream::*;
+pub use scatter::*;
+pub(crate) use stats::welded_vertices;
+pub use subdivide::*;
+pub use uv::*;
 
 use super::Color;
 
-pub trait IndexType: Copy + Debug {
+pub trait IndexType: Copy + Debug + Send + Sync {
     fn as_u32(&self) -> u32;
     fn as_usize(&self) -> usize;
 }
@@ -88,6 +108,19 @@ impl Indices {
             Self::U16(_) => wgpu::IndexFormat::Uint16,
         }
     }
+
+    /// Returns the indices in the smallest format able to store them.
+    ///
+    /// 32-bit indices are converted to 16-bit indices if they are all below
+    /// `u16::MAX`, which is reserved for primitive restart.
+    pub fn compacted(&self) -> Cow<'_, Indices> {
+        match self {
+            Self::U32(indices) if indices.iter().all(|i| *i < u16::MAX as u32) => {
+                Cow::Owned(Self::U16(indices.iter().map(|i| *i as u16).collect()))
+            }
+            _ => Cow::Borrowed(self),
+        }
+    }
 }
 
 /// A submesh is a range of indices, it specifies a range of indices to be
@@ -161,6 +194,8 @@ pub struct Mesh {
     pub(crate) path: Option<PathBuf>,
     /// Materials of the mesh.
     pub(crate) materials: Option<Vec<Material>>,
+    /// Size of the points of a point list, in world units.
+    pub(crate) point_size: f32,
 }
 
 impl Asset for Mesh {}
@@ -172,6 +207,9 @@ pub struct VertexBufferLayout {
 }
 
 impl Mesh {
+    /// Default size of the points of a point list.
+    pub const DEFAULT_POINT_SIZE: f32 = 0.05;
+
     pub fn new(topology: wgpu::PrimitiveTopology) -> Self {
         Self {
             name: SmlString::from(format!(
@@ -184,6 +222,7 @@ impl Mesh {
             sub_meshes: None,
             path: None,
             materials: None,
+            point_size: Self::DEFAULT_POINT_SIZE,
         }
     }
 
@@ -196,6 +235,7 @@ impl Mesh {
             sub_meshes: None,
             path: None,
             materials: None,
+            point_size: Self::DEFAULT_POINT_SIZE,
         }
     }
 
@@ -482,12 +522,65 @@ impl Mesh {
         mesh
     }
 
+    /// Creates a subdivided square plane in the XZ plane centered at the
+    /// origin, facing +Y.
+    ///
+    /// The plane is subdivided so that waves can displace its vertices, see
+    /// [`crate::core::water::Water`].
+    pub fn water_plane(size: f32) -> Mesh {
+        const SUBDIVISIONS: u32 = 64;
+        let mut attributes = VertexAttributes::default();
+        let n = SUBDIVISIONS + 1;
+        let mut vertices: Vec<[f32; 3]> = Vec::with_capacity((n * n) as usize);
+        let mut normals: Vec<[f32; 3]> = Vec::with_capacity((n * n) as usize);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity((n * n) as usize);
+        let mut indices: Vec<u16> = Vec::with_capacity((SUBDIVISIONS * SUBDIVISIONS * 6) as usize);
+
+        for j in 0..n {
+            let v = j as f32 / SUBDIVISIONS as f32;
+            for i in 0..n {
+                let u = i as f32 / SUBDIVISIONS as f32;
+                vertices.push([(u - 0.5) * size, 0.0, (v - 0.5) * size]);
+                normals.push([0.0, 1.0, 0.0]);
+                uvs.push([u, v]);
+            }
+        }
+
+        for j in 0..SUBDIVISIONS {
+            for i in 0..SUBDIVISIONS {
+                let a = (j * n + i) as u16;
+                let b = ((j + 1) * n + i) as u16;
+                let c = b + 1;
+                let d = a + 1;
+                indices.extend_from_slice(&[a, b, c, a, c, d]);
+            }
+        }
+
+        attributes.insert(VertexAttribute::POSITION, AttribContainer::new(&vertices));
+        attributes.insert(VertexAttribute::NORMAL, AttribContainer::new(&normals));
+        attributes.insert(VertexAttribute::UV, AttribContainer::new(&uvs));
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.attributes = attributes;
+        mesh.indices = Some(Indices::U16(indices));
+        mesh.compute_tangents();
+        mesh
+    }
+
     /// Validates the mesh.
     ///
-    /// A mesh is valid if it has a position attribute, uv attribute, and
-    /// indices. If the mesh has not normals, they are computed.
+    /// A triangle list is valid if it has a position attribute, uv attribute,
+    /// and indices. If the mesh has not normals, they are computed.
+    ///
+    /// Other topologies only require a position attribute. Triangle strips
+    /// get their normals and tangents computed like triangle lists, points
+    /// and lines get constant ones. Indices not forming a whole primitive
+    /// are dropped.
     pub fn validate(&mut self) {
         log::info!("Validating mesh: {}.", self.name);
+        if self.topology != wgpu::PrimitiveTopology::TriangleList {
+            self.validate_primitives();
+            return;
+        }
         for attr in [VertexAttribute::POSITION, VertexAttribute::UV] {
             if !self.attributes.0.contains_key(&attr) {
                 panic!("Mesh must have a {:?} attribute.", attr);
@@ -496,6 +589,7 @@ impl Mesh {
         if self.indices.is_none() {
             panic!("Mesh must have indices.");
         }
+        self.truncate_indices(3);
         if !self.attributes.0.contains_key(&VertexAttribute::NORMAL) {
             log::warn!("Mesh has no normals. Computing normals.");
             self.compute_normals();
@@ -506,8 +600,222 @@ impl Mesh {
         }
     }
 
+    /// Validates a mesh which is not a triangle list.
+    fn validate_primitives(&mut self) {
+        if !self.attributes.0.contains_key(&VertexAttribute::POSITION) {
+            panic!(
+                "Mesh must have a {:?} attribute.",
+                VertexAttribute::POSITION
+            );
+        }
+        let min_indices = match self.topology {
+            wgpu::PrimitiveTopology::LineList => {
+                self.truncate_indices(2);
+                2
+            }
+            wgpu::PrimitiveTopology::LineStrip => 2,
+            wgpu::PrimitiveTopology::TriangleStrip => 3,
+            _ => 1,
+        };
+        let n_indices = self
+            .indices
+            .as_ref()
+            .map_or(self.attributes.vertex_count(), Indices::len);
+        if n_indices < min_indices {
+            log::warn!(
+                "Mesh {} has {} vertices, at least {} are required to draw a {:?}.",
+                self.name,
+                n_indices,
+                min_indices,
+                self.topology
+            );
+        }
+
+        let n_vertices = self.attributes.vertex_count();
+        if !self.attributes.0.contains_key(&VertexAttribute::UV) {
+            self.attributes.insert(
+                VertexAttribute::UV,
+                AttribContainer::new(&vec![[0.0f32; 2]; n_vertices]),
+            );
+        }
+        // Triangle strips are lit like triangle lists, the normal of points
+        // and lines only matters to their shading.
+        if self.topology == wgpu::PrimitiveTopology::TriangleStrip {
+            if !self.attributes.0.contains_key(&VertexAttribute::NORMAL) {
+                log::warn!("Mesh has no normals. Computing normals.");
+                self.compute_normals();
+            }
+            if !self.attributes.0.contains_key(&VertexAttribute::TANGENT) {
+                log::warn!("Mesh has no tangents. Computing tangents.");
+                self.compute_tangents();
+            }
+        } else {
+            if !self.attributes.0.contains_key(&VertexAttribute::NORMAL) {
+                self.attributes.insert(
+                    VertexAttribute::NORMAL,
+                    AttribContainer::new(&vec![[0.0f32, 0.0, 1.0]; n_vertices]),
+                );
+            }
+            if !self.attributes.0.contains_key(&VertexAttribute::TANGENT) {
+                self.attributes.insert(
+                    VertexAttribute::TANGENT,
+                    AttribContainer::new(&vec![[1.0f32, 0.0, 0.0, 1.0]; n_vertices]),
+                );
+            }
+        }
+    }
+
+    /// Drops the indices after the last whole primitive of `n` indices.
+    fn truncate_indices(&mut self, n: usize) {
+        let Some(indices) = self.indices.as_mut() else {
+            return;
+        };
+        let len = indices.len() - indices.len() % n;
+        if len == indices.len() {
+            return;
+        }
+        log::warn!(
+            "Mesh {} has {} indices, which is not a multiple of {}. Dropping the last {}.",
+            self.name,
+            indices.len(),
+            n,
+            indices.len() - len
+        );
+        match indices {
+            Indices::U32(indices) => indices.truncate(len),
+            Indices::U16(indices) => indices.truncate(len),
+        }
+    }
+
+    /// Returns the indices of the triangles of the mesh as a triangle list,
+    /// or `None` if the mesh has no triangles. Triangle strips are unrolled,
+    /// restarting at the primitive restart indices.
+    fn triangle_list_indices(&self) -> Option<Cow<'_, Indices>> {
+        match self.topology {
+            wgpu::PrimitiveTopology::TriangleList => self.indices.as_ref().map(Cow::Borrowed),
+            wgpu::PrimitiveTopology::TriangleStrip => {
+                let strip: Vec<u32> = match &self.indices {
+                    Some(Indices::U32(indices)) => indices.clone(),
+                    Some(Indices::U16(indices)) => indices
+                        .iter()
+                        .map(|i| if *i == u16::MAX { u32::MAX } else { *i as u32 })
+                        .collect(),
+                    None => (0..self.attributes.vertex_count() as u32).collect(),
+                };
+                let mut list = Vec::with_capacity(strip.len().saturating_sub(2) * 3);
+                for strip in strip.split(|i| *i == u32::MAX) {
+                    for (i, tri) in strip.windows(3).enumerate() {
+                        // Every other triangle of a strip is wound the
+                        // other way.
+                        if i % 2 == 0 {
+                            list.extend_from_slice(&[tri[0], tri[1], tri[2]]);
+                        } else {
+                            list.extend_from_slice(&[tri[1], tri[0], tri[2]]);
+                        }
+                    }
+                }
+                Some(Cow::Owned(Indices::U32(list)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Expands the points of a point list into quads of two triangles, so
+    /// that they can be drawn larger than a pixel. The uv of each vertex of
+    /// a quad is its offset from the point in the view space.
+    pub(crate) fn point_quads(&self) -> Mesh {
+        const CORNERS: [[f32; 2]; 4] = [[-1.0, -1.0], [1.0, -1.0], [1.0, 1.0], [-1.0, 1.0]];
+        let positions = self.positions().unwrap_or(&[]);
+        let points: Vec<u32> = match &self.indices {
+            Some(Indices::U32(indices)) => indices.clone(),
+            Some(Indices::U16(indices)) => indices.iter().map(|i| *i as u32).collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        let half_size = self.point_size * 0.5;
+        let mut vertices = Vec::with_capacity(points.len() * 4);
+        let mut uvs = Vec::with_capacity(points.len() * 4);
+        let mut indices = Vec::with_capacity(points.len() * 6);
+        for (i, point) in points.iter().enumerate() {
+            let base = i as u32 * 4;
+            for [x, y] in CORNERS {
+                vertices.push(positions[*point as usize]);
+                uvs.push([x * half_size, y * half_size]);
+            }
+            indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+
+        let n_vertices = vertices.len();
+        let mut quads = Mesh::new_with_name(&self.name, wgpu::PrimitiveTopology::TriangleList);
+        quads
+            .attributes
+            .insert(VertexAttribute::POSITION, AttribContainer::new(&vertices));
+        quads.attributes.insert(
+            VertexAttribute::NORMAL,
+            AttribContainer::new(&vec![[0.0f32, 0.0, 1.0]; n_vertices]),
+        );
+        quads
+            .attributes
+            .insert(VertexAttribute::UV, AttribContainer::new(&uvs));
+        quads.attributes.insert(
+            VertexAttribute::TANGENT,
+            AttribContainer::new(&vec![[1.0f32, 0.0, 0.0, 1.0]; n_vertices]),
+        );
+        quads.indices = Some(Indices::U32(indices));
+        // Each point of the sub-meshes is now drawn with 6 indices.
+        quads.sub_meshes = self.sub_meshes.as_ref().map(|sub_meshes| {
+            sub_meshes
+                .iter()
+                .map(|sm| SubMesh {
+                    range: sm.range.start * 6..sm.range.end * 6,
+                    material: sm.material,
+                })
+                .collect()
+        });
+        quads.path = self.path.clone();
+        quads
+    }
+
+    /// Returns a copy of the mesh whose materials have the given opacity,
+    /// the parts drawn with the default material getting a translucent copy
+    /// of it.
+    pub fn translucent(&self, opacity: f32) -> Mesh {
+        let mut mesh = self.clone();
+        let materials = mesh.materials.get_or_insert_with(Vec::new);
+        let default_material = materials.len() as u32;
+        materials.push(Material::default());
+        for material in materials.iter_mut() {
+            material.opacity = Some(opacity);
+        }
+        let count = self
+            .indices
+            .as_ref()
+            .map_or(self.attributes.vertex_count(), Indices::len) as u32;
+        let sub_meshes = mesh.sub_meshes.get_or_insert_with(|| {
+            vec![SubMesh {
+                range: 0..count,
+                material: None,
+            }]
+        });
+        for sm in sub_meshes.iter_mut() {
+            sm.material = sm.material.or(Some(default_material));
+        }
+        mesh
+    }
+
     /// Loads a mesh from a wavefront obj file.
     pub fn load_from_obj<P: AsRef<Path> + Debug + Copy>(path: P) -> Self {
+        Self::try_load_from_obj(path)
+            .map_err(|err| {
+                log::error!("Failed to load mesh from {:?}: {}", path, err);
+            })
+            .unwrap()
+    }
+
+    /// Loads a mesh from a wavefront obj file, returns an error if the file
+    /// or its materials can't be loaded.
+    pub fn try_load_from_obj<P: AsRef<Path> + Debug + Copy>(
+        path: P,
+    ) -> Result<Self, tobj::LoadError> {
         log::debug!("Loading mesh from {}.", path.as_ref().display());
         let options = tobj::LoadOptions {
             single_index: true,
@@ -515,12 +823,8 @@ impl Mesh {
             ignore_points: true,
             ignore_lines: true,
         };
-        let (models, materials) = tobj::load_obj(path, &options)
-            .map_err(|err| {
-                log::error!("Failed to load mesh from {:?}: {}", path, err);
-            })
-            .unwrap();
-        let materials = materials.expect("Failed to load materials.");
+        let (models, materials) = tobj::load_obj(path, &options)?;
+        let materials = materials?;
         log::debug!("- Loaded {} models.", models.len());
         log::debug!("- Loaded {} materials.", materials.len());
         log::debug!("-- Loaded materials: {:?}", materials);
@@ -598,7 +902,124 @@ impl Mesh {
         mesh.materials = Some(materials);
         mesh.path = Some(path.as_ref().to_path_buf());
         mesh.compute_tangents();
-        mesh
+        Ok(mesh)
+    }
+
+    /// Returns the files the mesh has been loaded with besides its own file:
+    /// the material libraries referenced by the obj file and the textures of
+    /// its materials.
+    pub fn dependency_files(&self) -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        if let Some(path) = &self.path {
+            if let Ok(file) = std::fs::File::open(path) {
+                let base = path.parent().unwrap_or_else(|| Path::new(""));
+                for line in BufReader::new(file).lines().map_while(Result::ok) {
+                    let mut tokens = line.split_whitespace();
+                    if tokens.next() == Some("mtllib") {
+                        files.extend(tokens.map(|lib| base.join(lib)));
+                    }
+                }
+            }
+        }
+        for material in self.materials.iter().flatten() {
+            files.extend(material.textures.values().cloned());
+        }
+        files
+    }
+
+    /// Returns the positions of the vertices, or `None` if the mesh has no
+    /// position attribute.
+    pub fn positions(&self) -> Option<&[[f32; 3]]> {
+        Some(
+            self.attributes
+                .0
+                .get(&VertexAttribute::POSITION)?
+                .as_slice::<[f32; 3]>(),
+        )
+    }
+
+    /// Sets a custom vertex attribute of the mesh, read by the custom
+    /// shaders at the location of the attribute. The attribute of the same
+    /// name or at the same location is replaced.
+    pub fn set_attribute(
+        &mut self,
+        attribute: VertexAttribute,
+        data: AttribContainer,
+    ) -> Result<(), String> {
+        if !attribute.is_custom() {
+            return Err(format!("{} is not a custom attribute", attribute.name));
+        }
+        if data.n_bytes() % attribute.size != 0 {
+            return Err(format!(
+                "The data of {} is not a whole number of {:?}",
+                attribute.name, attribute.format
+            ));
+        }
+        let n_vertices = self.attributes.vertex_count();
+        if self.positions().is_some() && data.n_bytes() / attribute.size != n_vertices {
+            return Err(format!(
+                "{} has {} values, the mesh has {} vertices",
+                attribute.name,
+                data.n_bytes() / attribute.size,
+                n_vertices
+            ));
+        }
+        let replaced = |a: &VertexAttribute| {
+            a.name == attribute.name || a.shader_location == attribute.shader_location
+        };
+        let n_kept = self
+            .attributes
+            .custom()
+            .filter(|(a, _)| !replaced(a))
+            .count();
+        if n_kept >= VertexAttribute::MAX_CUSTOM_ATTRIBUTES {
+            return Err(format!(
+                "A mesh has at most {} custom attributes",
+                VertexAttribute::MAX_CUSTOM_ATTRIBUTES
+            ));
+        }
+        self.attributes
+            .0
+            .retain(|a, _| !a.is_custom() || !replaced(a));
+        self.attributes.insert(attribute, data);
+        Ok(())
+    }
+
+    /// Returns the custom vertex attribute of the given name.
+    pub fn custom_attribute(&self, name: &str) -> Option<(&VertexAttribute, &AttribContainer)> {
+        self.attributes.custom().find(|(a, _)| a.name == name)
+    }
+
+    /// Returns the vertex indices of the triangles of the mesh, or `None` if
+    /// the mesh is not a triangle list.
+    pub fn triangles(&self) -> Option<Vec<[u32; 3]>> {
+        if self.topology != wgpu::PrimitiveTopology::TriangleList {
+            return None;
+        }
+        let indices: Vec<u32> = match &self.indices {
+            Some(Indices::U32(indices)) => indices.clone(),
+            Some(Indices::U16(indices)) => indices.iter().map(|i| *i as u32).collect(),
+            None => (0..self.positions()?.len() as u32).collect(),
+        };
+        Some(
+            indices
+                .chunks_exact(3)
+                .map(|tri| [tri[0], tri[1], tri[2]])
+                .collect(),
+        )
+    }
+
+    /// Returns the axis-aligned bounding box of the vertices as (min, max),
+    /// or `None` if the mesh has no vertices.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let positions = self.positions()?;
+        if positions.is_empty() {
+            return None;
+        }
+        Some(positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), p| (min.min(Vec3::from(*p)), max.max(Vec3::from(*p))),
+        ))
     }
 
     /// Computes per vertex normals for the mesh.
@@ -607,9 +1028,6 @@ impl Mesh {
             log::warn!("Mesh already has normals. Skipping normal computation.");
             return;
         }
-        if self.indices.is_none() {
-            panic!("Indices are required to compute the normals");
-        }
         let vertices = self
             .attributes
             .0
@@ -617,7 +1035,7 @@ impl Mesh {
             .unwrap()
             .as_slice::<[f32; 3]>();
         let mut normals: Vec<Vec3> = vec![Vec3::ZERO; vertices.len()];
-        match &self.indices {
+        match self.triangle_list_indices().as_deref() {
             None => {
                 panic!("Indices are required to compute the normals");
             }
@@ -663,7 +1081,7 @@ impl Mesh {
             .expect("Mesh must have normals to compute the tangents")
             .as_slice::<[f32; 3]>();
         let mut tangents: Vec<Vec4> = vec![Vec4::ZERO; vertices.len()];
-        match &self.indices {
+        match self.triangle_list_indices().as_deref() {
             None => {
                 panic!("Indices are required to compute the bi/tangents");
             }
@@ -704,6 +1122,9 @@ pub struct GpuMesh {
     pub index_count: u32,
     /// Sub-meshes of the mesh.
     pub sub_meshes: Option<Vec<SubMesh>>,
+    /// Axis-aligned bounding box of the vertices in the local space, as
+    /// (min, max).
+    pub bounds: (Vec3, Vec3),
 }
 
 impl Asset for GpuMesh {}
@@ -721,6 +1142,7 @@ impl GpuMesh {
             index_range: 0..0,
             index_count: 0,
             sub_meshes: None,
+            bounds: (Vec3::ZERO, Vec3::ZERO),
         }
     }
 
@@ -731,20 +1153,90 @@ impl GpuMesh {
             .iter()
             .find_map(|(attrib, range)| (*attrib == attribute).then_some(range.clone()))
     }
+
+    /// Returns the custom vertex attributes and their range in the mesh data
+    /// buffer, ordered by their location.
+    pub fn custom_attribute_ranges(&self) -> impl Iterator<Item = &(VertexAttribute, Range<u64>)> {
+        self.vertex_attribute_ranges
+            .iter()
+            .filter(|(attrib, _)| attrib.is_custom())
+    }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct MeshBundle {
     pub mesh: Handle<GpuMesh>,
     pub aesthetic: AestheticBundle,
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct AestheticBundle {
     pub textures: Handle<TextureBundle>,
     pub materials: Handle<MaterialBundle>,
 }
 
+/// Number of triangles or vertices from which the normals and tangents are
+/// computed on multiple threads.
+const PARALLEL_THRESHOLD: usize = 16384;
+
+/// Calls `f` on consecutive chunks of `data` with the index of their first
+/// element, on multiple threads if `data` is large.
+fn par_chunks_mut<D, F>(data: &mut [D], f: F)
+where
+    D: Send,
+    F: Fn(usize, &mut [D]) + Sync,
+{
+    let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if data.len() < PARALLEL_THRESHOLD || n_threads == 1 {
+        f(0, data);
+        return;
+    }
+    let chunk_size = data.len().div_ceil(n_threads);
+    std::thread::scope(|s| {
+        for (i, chunk) in data.chunks_mut(chunk_size).enumerate() {
+            let f = &f;
+            s.spawn(move || f(i * chunk_size, chunk));
+        }
+    });
+}
+
+/// Triangles adjacent to each vertex, in compressed rows: the triangles of
+/// vertex `v` are `triangles[offsets[v]..offsets[v + 1]]`.
+struct VertexTriangles {
+    offsets: Vec<u32>,
+    triangles: Vec<u32>,
+}
+
+impl VertexTriangles {
+    fn new<T: IndexType>(n_vertices: usize, indices: &[T]) -> Self {
+        let mut offsets = vec![0u32; n_vertices + 1];
+        for i in indices {
+            offsets[i.as_usize() + 1] += 1;
+        }
+        for v in 0..n_vertices {
+            offsets[v + 1] += offsets[v];
+        }
+        let mut cursors = offsets.clone();
+        let mut triangles = vec![0u32; offsets[n_vertices] as usize];
+        for (t, tri) in indices.chunks_exact(3).enumerate() {
+            for i in tri {
+                let cursor = &mut cursors[i.as_usize()];
+                triangles[*cursor as usize] = t as u32;
+                *cursor += 1;
+            }
+        }
+        Self { offsets, triangles }
+    }
+
+    fn of(&self, vertex: usize) -> &[u32] {
+        &self.triangles[self.offsets[vertex] as usize..self.offsets[vertex + 1] as usize]
+    }
+}
+
+/// Computes the tangents of the vertices from the UVs of their triangles.
+///
+/// The tangents and bitangents of the triangles are computed first, then
+/// summed per vertex, both in parallel for large meshes.
 fn compute_tangents<T: IndexType>(
     positions: &[[f32; 3]],
     indices: &[T],
@@ -752,78 +1244,187 @@ fn compute_tangents<T: IndexType>(
     normals: &[[f32; 3]],
     tangents: &mut [Vec4],
 ) {
-    let mut bitangents = vec![Vec3::ZERO; positions.len()];
-    for tri in indices.chunks(3) {
-        let (tri0, tri1, tri2) = (tri[0].as_usize(), tri[1].as_usize(), tri[2].as_usize());
-        let v0 = glam::Vec3::from(positions[tri0]);
-        let v1 = glam::Vec3::from(positions[tri1]);
-        let v2 = glam::Vec3::from(positions[tri2]);
-        let uv0 = glam::Vec2::from(uvs[tri0]);
-        let uv1 = glam::Vec2::from(uvs[tri1]);
-        let uv2 = glam::Vec2::from(uvs[tri2]);
-
-        // Calculate the edges of the triangle
-        let e1 = v1 - v0;
-        let e2 = v2 - v0;
-
-        // This will give us a direction to calculate the
-        // tangent and bitangent
-        let delta_uv1 = uv1 - uv0;
-        let delta_uv2 = uv2 - uv0;
-
-        // Solving the following system of equations
-        //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
-        //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
-        let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
-        let tangent = (e1 * delta_uv2.y - e2 * delta_uv1.y) * r;
-        let bitangent = (-e1 * delta_uv2.x + e2 * delta_uv1.x) * r;
-        tangents[tri0] = Vec4::new(
-            tangent.x + tangents[tri0].x,
-            tangent.y + tangents[tri0].y,
-            tangent.z + tangents[tri0].z,
-            0.0,
-        );
-        tangents[tri1] = Vec4::new(
-            tangent.x + tangents[tri1].x,
-            tangent.y + tangents[tri1].y,
-            tangent.z + tangents[tri1].z,
-            0.0,
-        );
-        tangents[tri2] = Vec4::new(
-            tangent.x + tangents[tri2].x,
-            tangent.y + tangents[tri2].y,
-            tangent.z + tangents[tri2].z,
-            0.0,
-        );
-        bitangents[tri0] += bitangent;
-        bitangents[tri1] += bitangent;
-        bitangents[tri2] += bitangent;
-    }
+    let mut frames = vec![(Vec3::ZERO, Vec3::ZERO); indices.len() / 3];
+    par_chunks_mut(&mut frames, |start, frames| {
+        for (frame, tri) in frames.iter_mut().zip(indices[start * 3..].chunks_exact(3)) {
+            let (tri0, tri1, tri2) = (tri[0].as_usize(), tri[1].as_usize(), tri[2].as_usize());
+            let v0 = Vec3::from(positions[tri0]);
+            let v1 = Vec3::from(positions[tri1]);
+            let v2 = Vec3::from(positions[tri2]);
+            let uv0 = glam::Vec2::from(uvs[tri0]);
+            let uv1 = glam::Vec2::from(uvs[tri1]);
+            let uv2 = glam::Vec2::from(uvs[tri2]);
+
+            // Calculate the edges of the triangle
+            let e1 = v1 - v0;
+            let e2 = v2 - v0;
+
+            // This will give us a direction to calculate the
+            // tangent and bitangent
+            let delta_uv1 = uv1 - uv0;
+            let delta_uv2 = uv2 - uv0;
+
+            // Solving the following system of equations
+            //     delta_pos1 = delta_uv1.x * T + delta_u.y * B
+            //     delta_pos2 = delta_uv2.x * T + delta_uv2.y * B
+            let r = 1.0 / (delta_uv1.x * delta_uv2.y - delta_uv1.y * delta_uv2.x);
+            let tangent = (e1 * delta_uv2.y - e2 * delta_uv1.y) * r;
+            let bitangent = (-e1 * delta_uv2.x + e2 * delta_uv1.x) * r;
+            *frame = (tangent, bitangent);
+        }
+    });
 
     // Average the tangents and bitangents
-    for i in 0..positions.len() {
-        let t = tangents[i].truncate().normalize();
-        let b = bitangents[i].normalize();
-        let n = Vec3::from(normals[i]);
-        let t_perp = t - n * t.dot(n);
-        tangents[i] = Vec4::from((t_perp, n.dot(t.cross(b)).signum()));
-    }
+    let adjacency = VertexTriangles::new(positions.len(), indices);
+    par_chunks_mut(tangents, |start, tangents| {
+        for (i, tangent) in tangents.iter_mut().enumerate() {
+            let v = start + i;
+            let (t, b) = adjacency
+                .of(v)
+                .iter()
+                .fold((Vec3::ZERO, Vec3::ZERO), |(t, b), f| {
+                    let frame = frames[*f as usize];
+                    (t + frame.0, b + frame.1)
+                });
+            let t = t.normalize();
+            let b = b.normalize();
+            let n = Vec3::from(normals[v]);
+            let t_perp = t - n * t.dot(n);
+            *tangent = Vec4::from((t_perp, n.dot(t.cross(b)).signum()));
+        }
+    });
 }
 
+/// Computes the normals of the vertices as the normalized sum of the normals
+/// of their triangles, in parallel for large meshes.
 fn compute_normals<T: IndexType>(positions: &[[f32; 3]], indices: &[T], normals: &mut [Vec3]) {
-    for tri in indices.chunks(3) {
-        let (tri0, tri1, tri2) = (tri[0].as_usize(), tri[1].as_usize(), tri[2].as_usize());
-        let v0 = glam::Vec3::from(positions[tri0]);
-        let v1 = glam::Vec3::from(positions[tri1]);
-        let v2 = glam::Vec3::from(positions[tri2]);
-        let e1 = v1 - v0;
-        let e2 = v2 - v0;
-        let normal = e1.cross(e2).normalize();
-        normals[tri0] += normal;
-        normals[tri1] += normal;
-        normals[tri2] += normal;
-    }
-    for normal in normals.iter_mut() {
-        *normal = normal.normalize();
+    let mut faces = vec![Vec3::ZERO; indices.len() / 3];
+    par_chunks_mut(&mut faces, |start, faces| {
+        for (face, tri) in faces.iter_mut().zip(indices[start * 3..].chunks_exact(3)) {
+            let v0 = Vec3::from(positions[tri[0].as_usize()]);
+            let v1 = Vec3::from(positions[tri[1].as_usize()]);
+            let v2 = Vec3::from(positions[tri[2].as_usize()]);
+            *face = (v1 - v0).cross(v2 - v0).normalize();
+        }
+    });
+    let adjacency = VertexTriangles::new(positions.len(), indices);
+    par_chunks_mut(normals, |start, normals| {
+        for (i, normal) in normals.iter_mut().enumerate() {
+            *normal = adjacency
+                .of(start + i)
+                .iter()
+                .map(|f| faces[*f as usize])
+                .sum::<Vec3>()
+                .normalize();
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parallel_normals_match_face_normals() {
+        // A grid large enough to be processed on multiple threads, folded
+        // along its diagonal so that the normals are not all the same.
+        let n = 200u32;
+        let positions = (0..=n)
+            .flat_map(|j| {
+                (0..=n).map(move |i| {
+                    let (x, z) = (i as f32, j as f32);
+                    [x, (x - z).abs() * 0.5, z]
+                })
+            })
+            .collect::<Vec<_>>();
+        let indices = (0..n)
+            .flat_map(|j| {
+                (0..n).flat_map(move |i| {
+                    let v = j * (n + 1) + i;
+                    [v, v + n + 1, v + 1, v + 1, v + n + 1, v + n + 2]
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut normals = vec![Vec3::ZERO; positions.len()];
+        compute_normals(&positions, &indices, &mut normals);
+        assert!(normals.iter().all(|n| (n.length() - 1.0).abs() < 1e-5));
+        // Corner vertices belong to triangles of a single side of the fold.
+        let expected = Vec3::new(-0.5, 1.0, 0.5).normalize();
+        assert!((normals[n as usize] - expected).length() < 1e-5);
+        assert!(normals[(n * (n + 1)) as usize].y > 0.0);
+    }
+
+    #[test]
+    fn triangle_strips_are_unrolled_with_a_consistent_winding() {
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleStrip);
+        mesh.indices = Some(Indices::U16(vec![0, 1, 2, 3, u16::MAX, 4, 5, 6]));
+        let list = match mesh.triangle_list_indices().as_deref() {
+            Some(Indices::U32(list)) => list.clone(),
+            _ => panic!("A triangle strip has triangles"),
+        };
+        assert_eq!(list, [0, 1, 2, 2, 1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn points_are_expanded_into_quads() {
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::PointList);
+        mesh.attributes.insert(
+            VertexAttribute::POSITION,
+            AttribContainer::new(&[[0.0f32, 0.0, 0.0], [1.0, 2.0, 3.0]]),
+        );
+        mesh.sub_meshes = Some(vec![SubMesh::new(1, 2, 0)]);
+        mesh.point_size = 0.5;
+        mesh.validate();
+
+        let quads = mesh.point_quads();
+        assert_eq!(quads.topology, wgpu::PrimitiveTopology::TriangleList);
+        assert_eq!(quads.positions().unwrap()[4..], [[1.0, 2.0, 3.0]; 4]);
+        let uvs = quads.attributes.0[&VertexAttribute::UV].as_slice::<[f32; 2]>();
+        assert_eq!(uvs[2], [0.25, 0.25]);
+        assert_eq!(quads.triangles().unwrap().len(), 4);
+        assert_eq!(quads.sub_meshes.unwrap()[0].range, 6..12);
+    }
+
+    #[test]
+    fn custom_attributes_replace_the_attribute_of_the_same_name() {
+        let mut mesh = Mesh::triangle(&[Vec3::ZERO, Vec3::X, Vec3::Y]);
+        let weight = VertexAttribute::custom("weight", wgpu::VertexFormat::Float32, 5).unwrap();
+        mesh.set_attribute(weight, AttribContainer::new(&[0.0f32, 0.5, 1.0]))
+            .unwrap();
+        assert!(mesh
+            .set_attribute(weight, AttribContainer::new(&[0.0f32, 1.0]))
+            .is_err());
+        assert!(VertexAttribute::custom("normal", wgpu::VertexFormat::Float32x3, 1).is_err());
+
+        let moved = VertexAttribute::custom("weight", wgpu::VertexFormat::Float32x2, 6).unwrap();
+        mesh.set_attribute(moved, AttribContainer::new(&[[0.0f32; 2]; 3]))
+            .unwrap();
+        let custom = mesh.attributes.custom().collect::<Vec<_>>();
+        assert_eq!(custom.len(), 1);
+        assert_eq!(custom[0].0.shader_location, 6);
+        assert_eq!(mesh.attributes.vertex_count(), 3);
+    }
+
+    #[test]
+    fn translucent_copies_draw_every_part_with_a_translucent_material() {
+        let mut mesh = Mesh::triangle(&[Vec3::ZERO, Vec3::X, Vec3::Y]);
+        let ghost = mesh.translucent(0.5);
+        let materials = ghost.materials.as_ref().unwrap();
+        assert_eq!(materials.len(), 1);
+        assert_eq!(ghost.sub_meshes.unwrap()[0].material, Some(0));
+
+        mesh.materials = Some(vec![Material::default()]);
+        mesh.sub_meshes = Some(vec![SubMesh::new(0, 1, 0), SubMesh::new(1, 2, 0)]);
+        mesh.sub_meshes.as_mut().unwrap()[1].material = None;
+        let ghost = mesh.translucent(0.5);
+        let materials = ghost.materials.as_ref().unwrap();
+        assert!(materials.iter().all(|m| m.opacity == Some(0.5)));
+        let sub_meshes = ghost.sub_meshes.unwrap();
+        assert_eq!(sub_meshes[0].material, Some(0));
+        assert_eq!(sub_meshes[1].material, Some(1));
+        assert_eq!(
+            mesh.materials.unwrap()[0].opacity,
+            Material::default().opacity
+        );
     }
 }
@@ -0,0 +1,366 @@
+use crate::core::{
+    mesh::{AttribContainer, Indices, Mesh, SubMesh, VertexAttribute},
+    FxHashMap, Material, SmlString,
+};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Lines},
+    path::{Path, PathBuf},
+};
+
+/// Streaming loader of wavefront obj files.
+///
+/// Instead of loading the whole file at once, the loader reads the file line
+/// by line and yields one mesh per object (`o`) or group (`g`) as soon as it
+/// has been read. Only the vertex data shared by all objects is kept in
+/// memory.
+///
+/// Every mesh refers to all the materials of the file, so that meshes of
+/// the same file share the same material bundle once uploaded.
+pub struct ObjStream {
+    /// Path to the obj file.
+    path: PathBuf,
+    /// Lines of the obj file.
+    lines: Lines<BufReader<File>>,
+    /// Vertex positions read so far.
+    positions: Vec<[f32; 3]>,
+    /// Vertex normals read so far.
+    normals: Vec<[f32; 3]>,
+    /// Vertex texture coordinates read so far.
+    uvs: Vec<[f32; 2]>,
+    /// Materials of the file.
+    materials: Vec<Material>,
+    /// Index of the materials by name.
+    material_ids: FxHashMap<String, u32>,
+    /// Object being read.
+    object: ObjObject,
+    /// Number of meshes yielded.
+    n_meshes: usize,
+}
+
+/// Object of an obj file being read.
+#[derive(Default)]
+struct ObjObject {
+    name: String,
+    /// Material currently in use.
+    material: Option<u32>,
+    /// Index of the vertices by their (position, uv, normal) indices.
+    vertices: FxHashMap<(usize, Option<usize>, Option<usize>), u32>,
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+    /// Whether all the vertices have a normal.
+    has_normals: bool,
+    /// Indices of the triangles grouped by material, in order of use.
+    triangles: Vec<(Option<u32>, Vec<u32>)>,
+}
+
+impl ObjObject {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            has_normals: true,
+            ..Default::default()
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+}
+
+impl ObjStream {
+    /// Opens an obj file for streaming.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)?;
+        Ok(Self {
+            path,
+            lines: BufReader::new(file).lines(),
+            positions: Vec::new(),
+            normals: Vec::new(),
+            uvs: Vec::new(),
+            materials: Vec::new(),
+            material_ids: FxHashMap::default(),
+            object: ObjObject::new("default"),
+            n_meshes: 0,
+        })
+    }
+
+    /// Loads the materials of a material library referenced by the file.
+    fn load_materials(&mut self, lib: &str) {
+        let lib_path = self
+            .path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(lib);
+        match tobj::load_mtl(&lib_path) {
+            Ok((materials, _)) => {
+                for mtl in materials {
+                    self.material_ids
+                        .insert(mtl.name.clone(), self.materials.len() as u32);
+                    self.materials
+                        .push(Material::from_tobj_material(mtl, &self.path));
+                }
+            }
+            Err(err) => log::error!("Failed to load materials from {:?}: {}", lib_path, err),
+        }
+    }
+
+    /// Returns the index of a vertex of the current object, adding it if
+    /// needed. Returns `None` if the vertex refers to missing data.
+    fn vertex(&mut self, token: &str) -> Option<u32> {
+        let mut indices = token.split('/');
+        let position = resolve_index(indices.next()?, self.positions.len())?;
+        let uv = indices
+            .next()
+            .filter(|s| !s.is_empty())
+            .and_then(|s| resolve_index(s, self.uvs.len()));
+        let normal = indices
+            .next()
+            .filter(|s| !s.is_empty())
+            .and_then(|s| resolve_index(s, self.normals.len()));
+
+        let object = &mut self.object;
+        if let Some(index) = object.vertices.get(&(position, uv, normal)) {
+            return Some(*index);
+        }
+        let index = object.positions.len() as u32;
+        object.positions.push(self.positions[position]);
+        object
+            .uvs
+            .push(uv.map(|i| self.uvs[i]).unwrap_or([0.0, 0.0]));
+        object
+            .normals
+            .push(normal.map(|i| self.normals[i]).unwrap_or([0.0, 0.0, 0.0]));
+        object.has_normals &= normal.is_some();
+        object.vertices.insert((position, uv, normal), index);
+        Some(index)
+    }
+
+    /// Adds a face to the current object, triangulated as a fan.
+    fn add_face<'a>(&mut self, tokens: impl Iterator<Item = &'a str>) {
+        let face = match tokens
+            .map(|token| self.vertex(token))
+            .collect::<Option<Vec<_>>>()
+        {
+            Some(face) if face.len() >= 3 => face,
+            _ => {
+                log::warn!("Skipping invalid face in {:?}", self.path);
+                return;
+            }
+        };
+        let material = self.object.material;
+        if self.object.triangles.last().map(|(m, _)| *m) != Some(material) {
+            self.object.triangles.push((material, Vec::new()));
+        }
+        let triangles = &mut self.object.triangles.last_mut().unwrap().1;
+        for i in 1..face.len() - 1 {
+            triangles.extend_from_slice(&[face[0], face[i], face[i + 1]]);
+        }
+    }
+
+    /// Finishes the current object and returns it as a mesh, starts a new
+    /// object with the given name.
+    fn finish_object(&mut self, next_name: &str) -> Option<Mesh> {
+        let object = std::mem::replace(&mut self.object, ObjObject::new(next_name));
+        if object.is_empty() {
+            return None;
+        }
+
+        let mut indices = Vec::new();
+        let mut sub_meshes = Vec::new();
+        for (material, triangles) in object.triangles {
+            let start = indices.len() as u32;
+            indices.extend(triangles);
+            sub_meshes.push(SubMesh {
+                range: start..indices.len() as u32,
+                material,
+            });
+        }
+
+        let file_name = self
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.name = SmlString::from(format!("{}/{}#{}", file_name, object.name, self.n_meshes));
+        mesh.attributes.insert(
+            VertexAttribute::POSITION,
+            AttribContainer::new(&object.positions),
+        );
+        mesh.attributes
+            .insert(VertexAttribute::UV, AttribContainer::new(&object.uvs));
+        mesh.indices = Some(Indices::U32(indices));
+        mesh.sub_meshes = Some(sub_meshes);
+        mesh.materials = Some(self.materials.clone());
+        if object.has_normals {
+            mesh.attributes.insert(
+                VertexAttribute::NORMAL,
+                AttribContainer::new(&object.normals),
+            );
+            mesh.compute_tangents();
+        } else {
+            mesh.compute_normals();
+        }
+        self.n_meshes += 1;
+        Some(mesh)
+    }
+}
+
+impl Iterator for ObjStream {
+    type Item = std::io::Result<Mesh>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = match self.lines.next() {
+                None => break,
+                Some(Ok(line)) => line,
+                Some(Err(err)) => return Some(Err(err)),
+            };
+            let mut tokens = line.split_whitespace();
+            match tokens.next() {
+                Some("v") => self.positions.push(parse_floats(tokens)),
+                Some("vn") => self.normals.push(parse_floats(tokens)),
+                Some("vt") => self.uvs.push(parse_floats(tokens)),
+                Some("f") => self.add_face(tokens),
+                Some("usemtl") => {
+                    let name = tokens.next().unwrap_or_default();
+                    self.object.material = self.material_ids.get(name).copied();
+                }
+                Some("mtllib") => {
+                    for lib in tokens {
+                        self.load_materials(lib);
+                    }
+                }
+                Some("o") | Some("g") => {
+                    let name = tokens.next().unwrap_or("default");
+                    let material = self.object.material;
+                    let mesh = self.finish_object(name);
+                    // The material stays in use across objects.
+                    self.object.material = material;
+                    if let Some(mesh) = mesh {
+                        return Some(Ok(mesh));
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.finish_object("default").map(Ok)
+    }
+}
+
+/// Resolves a 1-based or negative (relative) obj index into a 0-based index.
+fn resolve_index(token: &str, len: usize) -> Option<usize> {
+    let index = token.parse::<isize>().ok()?;
+    let index = if index < 0 {
+        len as isize + index
+    } else {
+        index - 1
+    };
+    (0..len as isize).contains(&index).then_some(index as usize)
+}
+
+/// Parses the first N floats of a line, missing values are zero.
+fn parse_floats<'a, const N: usize>(tokens: impl Iterator<Item = &'a str>) -> [f32; N] {
+    let mut values = [0.0; N];
+    for (value, token) in values.iter_mut().zip(tokens) {
+        *value = token.parse().unwrap_or(0.0);
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ObjStream;
+    use crate::core::mesh::{Mesh, VertexAttribute};
+
+    /// Objects whose faces refer to the vertices read with the previous
+    /// objects, with absolute and relative indices.
+    const OBJ: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+o first
+f 1/1 2/2 3/3 4/4
+v 2 0 0
+v 2 1 0
+vt 0.5 0.5
+o second
+f 2/2 -2/-1 -1/-1 3/3
+o third
+f 1/1 3/3 -1/-1
+";
+
+    /// Returns the corners (position and uv) of the triangles of a mesh,
+    /// each triangle starting from its smallest corner to keep the winding.
+    fn triangle_corners(mesh: &Mesh) -> Vec<[[u32; 5]; 3]> {
+        let positions = mesh.positions().unwrap();
+        let uvs = mesh.attributes.0[&VertexAttribute::UV].as_slice::<[f32; 2]>();
+        mesh.triangles()
+            .unwrap()
+            .into_iter()
+            .map(|tri| {
+                let mut corners = tri.map(|i| {
+                    let [x, y, z] = positions[i as usize];
+                    let [u, v] = uvs[i as usize];
+                    [x, y, z, u, v].map(f32::to_bits)
+                });
+                let first = (0..3).min_by_key(|i| corners[*i]).unwrap();
+                corners.rotate_left(first);
+                corners
+            })
+            .collect()
+    }
+
+    fn write_obj(name: &str) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("bkfw-{}-{}.obj", name, std::process::id()));
+        std::fs::write(&path, OBJ).unwrap();
+        path
+    }
+
+    #[test]
+    fn streamed_objects_match_the_whole_file() {
+        let path = write_obj("obj-stream");
+        let meshes = ObjStream::open(&path)
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        let whole = Mesh::try_load_from_obj(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(meshes.len(), 3);
+        assert_eq!(
+            meshes.iter().map(Mesh::triangle_count).collect::<Vec<_>>(),
+            [2, 2, 1]
+        );
+        let mut streamed = meshes.iter().flat_map(triangle_corners).collect::<Vec<_>>();
+        let mut expected = triangle_corners(&whole);
+        streamed.sort();
+        expected.sort();
+        assert_eq!(streamed, expected);
+    }
+
+    #[test]
+    fn streamed_objects_only_keep_their_vertices() {
+        let path = write_obj("obj-stream-vertices");
+        let meshes = ObjStream::open(&path)
+            .unwrap()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let n_vertices = meshes
+            .iter()
+            .map(|mesh| mesh.positions().unwrap().len())
+            .collect::<Vec<_>>();
+        assert_eq!(n_vertices, [4, 4, 3]);
+        assert_eq!(meshes[1].positions().unwrap()[1], [2.0, 0.0, 0.0]);
+    }
+}
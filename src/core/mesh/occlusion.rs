@@ -0,0 +1,111 @@
+//! Baking of per-vertex ambient occlusion.
+
+use crate::core::{
+    bvh::{Ray, TriangleBvh},
+    mesh::{AttribContainer, Mesh, VertexAttribute},
+    Rng,
+};
+use glam::{Vec3, Vec4};
+
+impl Mesh {
+    /// Bakes the ambient occlusion of the mesh onto its vertices.
+    ///
+    /// For each vertex, `samples` rays are cast over the hemisphere around
+    /// its normal, with a cosine-weighted distribution, and the ambient
+    /// occlusion is the fraction of the rays not hitting the mesh within
+    /// `radius`: 1 for fully exposed vertices, 0 for fully occluded ones.
+    ///
+    /// The result multiplies the RGB channels of the vertex colors, which are
+    /// white if the mesh has none, so that it can be combined with
+    /// [`Mesh::colorize_by_height`] and the like. Normals are computed if the
+    /// mesh doesn't have them. The rays are seeded per vertex, so baking the
+    /// same mesh twice gives the same result.
+    pub fn bake_ambient_occlusion(&mut self, samples: u32, radius: f32) {
+        profiling::scope!("Mesh::bake_ambient_occlusion");
+        let Some(bvh) = TriangleBvh::from_mesh(self) else {
+            log::warn!(
+                "Only triangle lists with positions can be baked, skipping ambient occlusion of \
+                 {}.",
+                self.name
+            );
+            return;
+        };
+        if !self.attributes.0.contains_key(&VertexAttribute::NORMAL) {
+            self.compute_normals();
+        }
+        let positions = self.positions().unwrap_or(&[]);
+        let normals = self.attributes.0[&VertexAttribute::NORMAL].as_slice::<[f32; 3]>();
+        // Rays start slightly above the surface so that they don't hit the
+        // triangles around their vertex.
+        let bias = (bvh.bounds().extent().max_element() * 1e-4).max(1e-5);
+        let samples = samples.max(1);
+        let occlusion = positions
+            .iter()
+            .zip(normals)
+            .enumerate()
+            .map(|(i, (p, n))| {
+                let normal = Vec3::from(*n).normalize_or_zero();
+                if normal == Vec3::ZERO {
+                    return 1.0;
+                }
+                let origin = Vec3::from(*p) + normal * bias;
+                let (tangent, bitangent) = normal.any_orthonormal_pair();
+                let mut rng = Rng::new(i as u64);
+                let unoccluded = (0..samples)
+                    .filter(|_| {
+                        let (u, v) = (rng.next_f32(), rng.next_f32());
+                        let (r, phi) = (u.sqrt(), v * std::f32::consts::TAU);
+                        let direction = tangent * (r * phi.cos())
+                            + bitangent * (r * phi.sin())
+                            + normal * (1.0 - u).sqrt();
+                        bvh.intersect(&Ray::new(origin, direction), radius)
+                            .is_none()
+                    })
+                    .count();
+                unoccluded as f32 / samples as f32
+            })
+            .collect::<Vec<_>>();
+
+        let colors = match self.attributes.0.get(&VertexAttribute::COLOR) {
+            Some(colors) => colors
+                .as_slice::<[f32; 4]>()
+                .iter()
+                .zip(&occlusion)
+                .map(|(c, ao)| {
+                    let c = Vec4::from(*c);
+                    (c.truncate() * *ao).extend(c.w).to_array()
+                })
+                .collect::<Vec<_>>(),
+            None => occlusion.iter().map(|ao| [*ao, *ao, *ao, 1.0]).collect(),
+        };
+        self.attributes
+            .insert(VertexAttribute::COLOR, AttribContainer::new(&colors));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::Alignment;
+
+    #[test]
+    fn occlusion_of_open_and_enclosed_surfaces() {
+        let mut plane = Mesh::plane(1.0, Alignment::XZ);
+        plane.bake_ambient_occlusion(16, 1.0);
+        let colors = plane.attributes.0[&VertexAttribute::COLOR].as_slice::<[f32; 4]>();
+        assert!(colors.iter().all(|c| *c == [1.0; 4]));
+
+        // The normals of the cube flipped inwards see the other faces.
+        let mut cube = Mesh::cube(1.0);
+        let normals = cube.attributes.0[&VertexAttribute::NORMAL]
+            .as_slice::<[f32; 3]>()
+            .iter()
+            .map(|n| (-Vec3::from(*n)).to_array())
+            .collect::<Vec<_>>();
+        cube.attributes
+            .insert(VertexAttribute::NORMAL, AttribContainer::new(&normals));
+        cube.bake_ambient_occlusion(16, 10.0);
+        let colors = cube.attributes.0[&VertexAttribute::COLOR].as_slice::<[f32; 4]>();
+        assert!(colors.iter().all(|c| c[0] == 0.0 && c[3] == 1.0));
+    }
+}
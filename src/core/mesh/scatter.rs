@@ -0,0 +1,114 @@
+use crate::core::{Rng, Transform};
+use glam::{Quat, Vec3};
+
+/// Placement of the instances scattered over a surface, see [`scatter`].
+#[derive(Clone, Copy, Debug)]
+pub struct ScatterParams {
+    /// Average number of instances per unit of area.
+    pub density: f32,
+    /// Seed of the random placement, the same seed giving the same
+    /// instances.
+    pub seed: u64,
+    /// Whether the up axis of the instances follows the normal of the
+    /// surface, otherwise the instances stay upright.
+    pub align_to_normal: bool,
+    /// Largest relative change of the scale of the instances, each instance
+    /// being scaled uniformly by a factor in `[1 - scale_jitter, 1 +
+    /// scale_jitter]`.
+    pub scale_jitter: f32,
+}
+
+/// Returns the transforms of instances scattered at random over the
+/// triangles, given in the space of the instances.
+///
+/// Each triangle receives on average `density` instances per unit of area,
+/// rotated at random around their up axis.
+pub fn scatter(
+    triangles: impl IntoIterator<Item = [Vec3; 3]>,
+    params: &ScatterParams,
+) -> Vec<Transform> {
+    profiling::scope!("scatter");
+    let mut rng = Rng::new(params.seed);
+    let mut transforms = Vec::new();
+    for [a, b, c] in triangles {
+        let cross = (b - a).cross(c - a);
+        let expected = cross.length() * 0.5 * params.density;
+        if !expected.is_finite() || expected <= 0.0 {
+            continue;
+        }
+        // The fractional part of the expected count gives the probability
+        // of an additional instance.
+        let mut count = expected as usize;
+        if rng.next_f32() < expected.fract() {
+            count += 1;
+        }
+        let normal = cross.normalize();
+        for _ in 0..count {
+            // Uniform sampling of the triangle.
+            let r = rng.next_f32().sqrt();
+            let s = rng.next_f32();
+            let position = a * (1.0 - r) + b * (r * (1.0 - s)) + c * (r * s);
+            let yaw = Quat::from_rotation_y(rng.next_f32() * std::f32::consts::TAU);
+            let rotation = if params.align_to_normal {
+                Quat::from_rotation_arc(Vec3::Y, normal) * yaw
+            } else {
+                yaw
+            };
+            let scale = 1.0 + (rng.next_f32() * 2.0 - 1.0) * params.scale_jitter;
+            transforms.push(Transform {
+                translation: position,
+                rotation,
+                scale: Vec3::splat(scale.max(f32::EPSILON)),
+            });
+        }
+    }
+    transforms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Square of side 2 in the XZ plane, facing up.
+    fn square() -> [[Vec3; 3]; 2] {
+        let corners = [
+            Vec3::new(-1.0, 0.0, -1.0),
+            Vec3::new(-1.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+        ];
+        [
+            [corners[0], corners[1], corners[2]],
+            [corners[0], corners[2], corners[3]],
+        ]
+    }
+
+    #[test]
+    fn instances_are_scattered_over_the_surface() {
+        let params = ScatterParams {
+            density: 100.0,
+            seed: 3,
+            align_to_normal: true,
+            scale_jitter: 0.2,
+        };
+        let transforms = scatter(square(), &params);
+        // 400 instances are expected, up to one more per triangle.
+        assert!((400..=402).contains(&transforms.len()));
+        for transform in &transforms {
+            let p = transform.translation;
+            assert!(p.x.abs() <= 1.0 && p.z.abs() <= 1.0 && p.y == 0.0);
+            assert!((transform.rotation * Vec3::Y - Vec3::Y).length() < 1e-5);
+            assert!((0.8..=1.2).contains(&transform.scale.x));
+        }
+        // Both halves of the square are covered.
+        assert!(transforms.iter().any(|t| t.translation.x > t.translation.z));
+        assert!(transforms.iter().any(|t| t.translation.x < t.translation.z));
+
+        let again = scatter(square(), &params);
+        assert_eq!(again.len(), transforms.len());
+        assert!(again
+            .iter()
+            .zip(&transforms)
+            .all(|(a, b)| a.translation == b.translation));
+    }
+}
@@ -0,0 +1,335 @@
+//! Mesh simplification by quadric edge collapse.
+
+use crate::core::{
+    halfedge::HalfEdgeMesh,
+    mesh::{Mesh, VertexAttribute},
+    SmlString,
+};
+use glam::DVec3;
+use std::{cmp::Ordering, collections::BinaryHeap};
+
+/// Weight of the planes constraining the open boundaries of the mesh.
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+impl Mesh {
+    /// Returns a simplified copy of the mesh with about `target_ratio` of its
+    /// triangles, computed by quadric error edge collapses.
+    ///
+    /// Edges are collapsed onto one of their vertices so that the vertex
+    /// attributes are kept as they are. Vertices shared by several UV or
+    /// normal seams are not moved, and the sub-meshes keep their materials.
+    /// Only indexed triangle lists are simplified; other meshes are copied.
+    pub fn simplify(&self, target_ratio: f32) -> Mesh {
+        let mut simplified = self.clone();
+        simplified.name = SmlString::from(format!("{}_simplified", self.name));
+        // The simplified mesh must not be replaced by its file when reloaded.
+        simplified.path = None;
+
+        let (Some(indices), Some(positions)) = (
+            self.indices.as_ref(),
+            self.attributes.0.get(&VertexAttribute::POSITION),
+        ) else {
+            log::warn!(
+                "Mesh {} has no indices or positions, can't simplify.",
+                self.name
+            );
+            return simplified;
+        };
+        if self.topology != wgpu::PrimitiveTopology::TriangleList {
+            log::warn!(
+                "Only triangle lists can be simplified, {} is copied.",
+                self.name
+            );
+            return simplified;
+        }
+
+        let n_triangles = indices.len() / 3;
+        let target = ((n_triangles as f32 * target_ratio.clamp(0.0, 1.0)).ceil() as usize).max(1);
+        let Some(mesh) = HalfEdgeMesh::from_mesh(self) else {
+            return simplified;
+        };
+        let n_vertices = positions.len() / VertexAttribute::POSITION.size;
+        let mut simplifier = Simplifier::new(mesh);
+        simplifier.run(target);
+
+        let mut result = simplifier.mesh.to_mesh();
+        result.name = simplified.name;
+        log::debug!(
+            "Simplified {}: {} -> {} triangles, {} -> {} vertices.",
+            self.name,
+            n_triangles,
+            result.triangle_count(),
+            n_vertices,
+            result.attributes.vertex_count()
+        );
+        result
+    }
+}
+
+/// Error quadric, the upper triangle of a symmetric 4x4 matrix.
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Quadric of the squared distance to the plane of normal `n` passing
+    /// through `p`, scaled by `weight`.
+    fn from_plane(n: DVec3, p: DVec3, weight: f64) -> Self {
+        let d = -n.dot(p);
+        let (a, b, c) = (n.x, n.y, n.z);
+        Self(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|q| q * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (q, o) in self.0.iter_mut().zip(other.0.iter()) {
+            *q += o;
+        }
+    }
+
+    fn error(&self, p: DVec3) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+/// Candidate collapse of the vertex `from` onto the vertex `to`.
+#[derive(Clone, Copy, Debug)]
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    /// Versions of the vertices when the cost was computed.
+    versions: (u32, u32),
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // Reversed so that the heap pops the cheapest collapse first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+struct Simplifier {
+    mesh: HalfEdgeMesh,
+    quadrics: Vec<Quadric>,
+    /// Incremented each time the neighborhood of a vertex changes.
+    versions: Vec<u32>,
+    /// Vertices that can't be moved.
+    locked: Vec<bool>,
+    heap: BinaryHeap<Collapse>,
+    n_faces: usize,
+}
+
+impl Simplifier {
+    fn new(mesh: HalfEdgeMesh) -> Self {
+        let n = mesh.vertex_capacity();
+        let mut quadrics = vec![Quadric::default(); n];
+        let normal = |f: u32| {
+            let [p0, p1, p2] = face_positions(&mesh, f);
+            (p1 - p0).cross(p2 - p0)
+        };
+        for f in mesh.faces() {
+            let cross = normal(f);
+            let area = cross.length() * 0.5;
+            let p0 = face_positions(&mesh, f)[0];
+            let quadric = Quadric::from_plane(cross.normalize_or_zero(), p0, area);
+            for v in mesh.face_vertices(f) {
+                quadrics[v as usize].add(&quadric);
+            }
+        }
+
+        // Constrain the open boundaries with planes perpendicular to the faces.
+        for h in mesh.edges().filter(|h| mesh.twin(*h).is_none()) {
+            let (a, b) = (mesh.source(h), mesh.target(h));
+            let (pa, pb) = (mesh.position(a).as_dvec3(), mesh.position(b).as_dvec3());
+            let edge = pb - pa;
+            let plane = edge
+                .cross(normal(mesh.face(h)).normalize_or_zero())
+                .normalize_or_zero();
+            let quadric = Quadric::from_plane(plane, pa, BOUNDARY_WEIGHT * edge.length_squared());
+            quadrics[a as usize].add(&quadric);
+            quadrics[b as usize].add(&quadric);
+        }
+
+        // Vertices split along seams would tear their attributes apart if
+        // moved.
+        let locked = (0..n as u32)
+            .map(|v| {
+                let mut corners: Vec<u32> = mesh
+                    .outgoing(v)
+                    .into_iter()
+                    .map(|h| mesh.corner(mesh.prev(h)))
+                    .collect();
+                corners.sort_unstable();
+                corners.dedup();
+                corners.len() > 1
+            })
+            .collect();
+
+        let n_faces = mesh.face_count();
+        let mut simplifier = Self {
+            mesh,
+            quadrics,
+            versions: vec![0; n],
+            locked,
+            heap: BinaryHeap::new(),
+            n_faces,
+        };
+        for v in 0..n as u32 {
+            simplifier.push_collapses(v);
+        }
+        simplifier
+    }
+
+    /// Queues the collapses of the edges around `v`, in both directions.
+    fn push_collapses(&mut self, v: u32) {
+        for u in self.mesh.vertex_neighbors(v) {
+            for (from, to) in [(v, u), (u, v)] {
+                if self.locked[from as usize] {
+                    continue;
+                }
+                let mut quadric = self.quadrics[from as usize];
+                quadric.add(&self.quadrics[to as usize]);
+                self.heap.push(Collapse {
+                    cost: quadric.error(self.mesh.position(to).as_dvec3()),
+                    from,
+                    to,
+                    versions: (self.versions[from as usize], self.versions[to as usize]),
+                });
+            }
+        }
+    }
+
+    /// Returns true if moving `from` onto `to` flips or degenerates none of
+    /// the faces that remain.
+    fn is_valid(&self, from: u32, to: u32) -> bool {
+        let target = self.mesh.position(to);
+        self.mesh
+            .vertex_faces(from)
+            .into_iter()
+            .map(|f| self.mesh.face_vertices(f))
+            .filter(|t| !t.contains(&to))
+            .all(|t| {
+                let [p0, p1, p2] = [t[0], t[1], t[2]].map(|v| self.mesh.position(v));
+                let before = (p1 - p0).cross(p2 - p0);
+                let [q0, q1, q2] = [t[0], t[1], t[2]].map(|v| {
+                    if v == from {
+                        target
+                    } else {
+                        self.mesh.position(v)
+                    }
+                });
+                let after = (q1 - q0).cross(q2 - q0);
+                after.length_squared() > f32::EPSILON * before.length_squared()
+                    && before.normalize_or_zero().dot(after.normalize_or_zero()) > 0.2
+            })
+    }
+
+    /// Collapses edges until at most `target` triangles remain.
+    fn run(&mut self, target: usize) {
+        while self.n_faces > target {
+            let Some(collapse) = self.heap.pop() else {
+                break;
+            };
+            let (from, to) = (collapse.from, collapse.to);
+            if self.mesh.is_removed(from)
+                || self.mesh.is_removed(to)
+                || collapse.versions != (self.versions[from as usize], self.versions[to as usize])
+                || !self.is_valid(from, to)
+            {
+                continue;
+            }
+            let removed = self
+                .mesh
+                .vertex_faces(from)
+                .into_iter()
+                .filter(|f| self.mesh.face_vertices(*f).contains(&to))
+                .count();
+            if !self.mesh.collapse_edge(from, to, self.mesh.position(to)) {
+                continue;
+            }
+            self.n_faces -= removed;
+            let quadric = self.quadrics[from as usize];
+            self.quadrics[to as usize].add(&quadric);
+            self.versions[to as usize] += 1;
+            for u in self.mesh.vertex_neighbors(to) {
+                self.versions[u as usize] += 1;
+            }
+            self.push_collapses(to);
+            for u in self.mesh.vertex_neighbors(to) {
+                self.push_collapses(u);
+            }
+        }
+    }
+}
+
+/// Returns the positions of the vertices of the triangle, in double
+/// precision.
+fn face_positions(mesh: &HalfEdgeMesh, f: u32) -> [DVec3; 3] {
+    let vertices = mesh.face_vertices(f);
+    [0, 1, 2].map(|k| mesh.position(vertices[k]).as_dvec3())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::mesh::Indices;
+
+    #[test]
+    fn simplify_reduces_triangles() {
+        let mut mesh = Mesh::sphere(1.0, 32, 16);
+        mesh.validate();
+        let n_triangles = mesh.indices.as_ref().unwrap().len() / 3;
+        let simplified = mesh.simplify(0.5);
+        let indices = match simplified.indices.as_ref().unwrap() {
+            Indices::U32(indices) => indices.clone(),
+            Indices::U16(_) => unreachable!(),
+        };
+        let n_vertices = simplified.attributes.0[&VertexAttribute::POSITION]
+            .as_slice::<[f32; 3]>()
+            .len();
+        assert!(indices.len() / 3 < n_triangles);
+        assert!(indices.len() / 3 + 2 >= n_triangles / 2);
+        assert!(indices.iter().all(|i| (*i as usize) < n_vertices));
+        for (attribute, container) in simplified.attributes.0.iter() {
+            assert_eq!(container.len(), n_vertices * attribute.size);
+        }
+    }
+}
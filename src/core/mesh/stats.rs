@@ -0,0 +1,140 @@
+//! Geometric statistics of meshes.
+
+use crate::core::{halfedge::HalfEdgeMesh, mesh::Mesh, FxHashMap};
+use glam::Vec3;
+
+impl Mesh {
+    /// Returns the number of triangles of the mesh, zero if it is not a
+    /// triangle list.
+    pub fn triangle_count(&self) -> usize {
+        self.triangles().map_or(0, |triangles| triangles.len())
+    }
+
+    /// Returns the total area of the triangles of the mesh.
+    pub fn surface_area(&self) -> f32 {
+        self.triangle_positions()
+            .map(|[a, b, c]| (b - a).cross(c - a).length() * 0.5)
+            .sum()
+    }
+
+    /// Returns the volume enclosed by the mesh, or `None` if the mesh is not
+    /// watertight.
+    pub fn volume(&self) -> Option<f32> {
+        if !self.is_watertight() {
+            return None;
+        }
+        // Sum of the signed volumes of the tetrahedra formed by the origin
+        // and each triangle.
+        let volume = self
+            .triangle_positions()
+            .map(|[a, b, c]| a.dot(b.cross(c)))
+            .sum::<f32>()
+            / 6.0;
+        Some(volume.abs())
+    }
+
+    /// Returns whether the mesh is closed and consistently oriented: every
+    /// edge is shared by exactly two triangles going through it in opposite
+    /// directions.
+    ///
+    /// Vertices at the same position are considered the same, so that meshes
+    /// split at their normal or UV seams are still watertight.
+    pub fn is_watertight(&self) -> bool {
+        HalfEdgeMesh::from_mesh(self).is_some_and(|mesh| mesh.is_closed())
+    }
+
+    /// Returns a sphere enclosing the vertices of the mesh as (center,
+    /// radius), or `None` if the mesh has no vertices.
+    ///
+    /// The sphere is close to but not always the smallest one.
+    pub fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        let positions = self
+            .positions()?
+            .iter()
+            .map(|p| Vec3::from(*p))
+            .collect::<Vec<_>>();
+        let first = *positions.first()?;
+        // Ritter's algorithm: starts from the sphere through the two points
+        // farthest apart along a rough diameter, then grows it to enclose the
+        // points left outside.
+        let farthest = |from: Vec3| {
+            positions
+                .iter()
+                .copied()
+                .max_by(|a, b| {
+                    a.distance_squared(from)
+                        .total_cmp(&b.distance_squared(from))
+                })
+                .unwrap()
+        };
+        let a = farthest(first);
+        let b = farthest(a);
+        let mut center = (a + b) * 0.5;
+        let mut radius = a.distance(b) * 0.5;
+        for p in &positions {
+            let distance = p.distance(center);
+            if distance > radius {
+                let new_radius = (radius + distance) * 0.5;
+                center += (*p - center) * ((new_radius - radius) / distance);
+                radius = new_radius;
+            }
+        }
+        Some((center, radius))
+    }
+
+    /// Returns the positions of the vertices of each triangle.
+    fn triangle_positions(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        let positions = self.positions().unwrap_or_default();
+        self.triangles()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(move |triangle| {
+                let [a, b, c] = triangle.map(|i| positions.get(i as usize).copied());
+                Some([a?, b?, c?].map(Vec3::from))
+            })
+    }
+}
+
+/// Returns for each vertex the index of the first vertex at the same
+/// position.
+pub(crate) fn welded_vertices(positions: &[[f32; 3]]) -> Vec<u32> {
+    let mut welded = FxHashMap::default();
+    positions
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            // Adding zero turns -0.0 into 0.0, which are the same position
+            // with different bits.
+            let key = p.map(|c| (c + 0.0).to_bits());
+            *welded.entry(key).or_insert(i as u32)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::welded_vertices;
+    use crate::core::{mesh::Mesh, Alignment};
+
+    #[test]
+    fn cube_statistics() {
+        let cube = Mesh::cube(2.0);
+        assert_eq!(cube.triangle_count(), 12);
+        assert!((cube.surface_area() - 24.0).abs() < 1e-4);
+        assert!(cube.is_watertight());
+        assert!((cube.volume().unwrap() - 8.0).abs() < 1e-4);
+        let (center, radius) = cube.bounding_sphere().unwrap();
+        assert!(center.length() < 1e-4);
+        assert!((radius - 3.0f32.sqrt()).abs() < 1e-4);
+
+        let plane = Mesh::plane(1.0, Alignment::XY);
+        assert!(!plane.is_watertight());
+        assert_eq!(plane.volume(), None);
+    }
+
+    #[test]
+    fn signed_zeros_are_welded() {
+        let positions = [[0.0, 1.0, 0.0], [-0.0, 1.0, -0.0], [1.0, -0.0, 0.0]];
+        assert_eq!(welded_vertices(&positions), vec![0, 0, 2]);
+    }
+}
@@ -0,0 +1,251 @@
+//! Smooth subdivision of triangle meshes.
+
+use crate::core::{
+    halfedge::HalfEdgeMesh,
+    mesh::{welded_vertices, AttribContainer, Mesh, VertexAttribute},
+    FxHashMap, SmlString,
+};
+use glam::Vec3;
+
+/// Subdivision scheme of [`Mesh::subdivide`].
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SubdivisionScheme {
+    /// Loop subdivision, splitting each triangle into 4 triangles.
+    #[default]
+    Loop,
+    /// Catmull-Clark subdivision, splitting each face into quads, one per
+    /// corner. The quads are kept between the levels and only triangulated
+    /// at the end.
+    CatmullClark,
+}
+
+impl Mesh {
+    /// Returns a smoothed copy of the mesh, subdivided `levels` times with
+    /// the given scheme.
+    ///
+    /// Vertices at the same position are welded for the smoothing, so that
+    /// UV and normal seams don't tear the surface apart; the other vertex
+    /// attributes are interpolated linearly. Open boundaries are smoothed
+    /// as curves and vertices where they meet are kept in place. Normals and
+    /// tangents are recomputed and the sub-meshes keep their materials. Only
+    /// triangle lists are subdivided; other meshes are copied.
+    pub fn subdivide(&self, levels: u32, scheme: SubdivisionScheme) -> Mesh {
+        let name = SmlString::from(format!("{}_subdivided", self.name));
+        let Some(mut halfedge) = HalfEdgeMesh::from_mesh(self) else {
+            log::warn!(
+                "Only triangle lists with positions can be subdivided, {} is copied.",
+                self.name
+            );
+            let mut copy = self.clone();
+            copy.name = name;
+            // The copy must not be replaced by its file when reloaded.
+            copy.path = None;
+            return copy;
+        };
+        for _ in 0..levels {
+            halfedge = subdivide_once(&halfedge, scheme);
+        }
+        let mut subdivided = halfedge.to_mesh();
+        subdivided.name = name;
+
+        // Normals are shared by the vertices at the same position so that
+        // the smoothed surface is shaded smoothly across the seams.
+        let positions = subdivided.positions().unwrap_or(&[]);
+        let welded = welded_vertices(positions);
+        let indices: Vec<u32> = subdivided
+            .triangles()
+            .unwrap_or_default()
+            .iter()
+            .flatten()
+            .map(|v| welded[*v as usize])
+            .collect();
+        let mut normals = vec![Vec3::ZERO; positions.len()];
+        super::compute_normals(positions, &indices, &mut normals);
+        let normals: Vec<[f32; 3]> = welded
+            .iter()
+            .map(|w| normals[*w as usize].to_array())
+            .collect();
+        subdivided
+            .attributes
+            .insert(VertexAttribute::NORMAL, AttribContainer::new(&normals));
+        subdivided.attributes.0.remove(&VertexAttribute::TANGENT);
+        if subdivided.attributes.0.contains_key(&VertexAttribute::UV) {
+            subdivided.compute_tangents();
+        }
+        log::debug!(
+            "Subdivided {}: {} -> {} triangles.",
+            self.name,
+            self.triangle_count(),
+            subdivided.triangle_count()
+        );
+        subdivided
+    }
+}
+
+/// Subdivides the faces of the mesh once.
+///
+/// The vertices are kept, and new ones are added at the middle of the edges
+/// and, for Catmull-Clark, at the center of the faces.
+fn subdivide_once(mesh: &HalfEdgeMesh, scheme: SubdivisionScheme) -> HalfEdgeMesh {
+    let faces: Vec<u32> = mesh.faces().collect();
+    let n_faces = faces.last().map_or(0, |f| *f as usize + 1);
+
+    // Face points, only used by Catmull-Clark.
+    let mut face_points = Vec::new();
+    if scheme == SubdivisionScheme::CatmullClark {
+        face_points = vec![Vec3::ZERO; n_faces];
+        for f in &faces {
+            let vertices = mesh.face_vertices(*f);
+            face_points[*f as usize] =
+                vertices.iter().map(|v| mesh.position(*v)).sum::<Vec3>() / vertices.len() as f32;
+        }
+    }
+
+    // Vertex points. Boundaries, and edges left as boundaries because they
+    // are not manifold, are smoothed as curves.
+    let mut positions: Vec<Vec3> = (0..mesh.vertex_capacity() as u32)
+        .map(|v| {
+            let point = mesh.position(v);
+            if mesh.is_removed(v) {
+                return point;
+            }
+            let around = |vs: &[u32]| vs.iter().map(|u| mesh.position(*u)).sum::<Vec3>();
+            let neighbors = mesh.vertex_neighbors(v);
+            let creases = mesh.boundary_neighbors(v);
+            match creases.len() {
+                0 if !neighbors.is_empty() => {
+                    let n = neighbors.len() as f32;
+                    match scheme {
+                        SubdivisionScheme::Loop => {
+                            let beta = if neighbors.len() == 3 {
+                                3.0 / 16.0
+                            } else {
+                                3.0 / (8.0 * n)
+                            };
+                            point * (1.0 - n * beta) + around(&neighbors) * beta
+                        }
+                        SubdivisionScheme::CatmullClark => {
+                            let vertex_faces = mesh.vertex_faces(v);
+                            let faces = vertex_faces
+                                .iter()
+                                .map(|f| face_points[*f as usize])
+                                .sum::<Vec3>()
+                                / vertex_faces.len() as f32;
+                            let midpoints = (point + around(&neighbors) / n) * 0.5;
+                            (faces + midpoints * 2.0 + point * (n - 3.0)) / n
+                        }
+                    }
+                }
+                2 => point * 0.75 + around(&creases) * 0.125,
+                _ => point,
+            }
+        })
+        .collect();
+
+    // Edge points, indexed by both half-edges of the edges.
+    let mut edge_points = FxHashMap::default();
+    for h in mesh.edges() {
+        let (a, b) = (mesh.position(mesh.source(h)), mesh.position(mesh.target(h)));
+        let point = match mesh.twin(h) {
+            None => (a + b) * 0.5,
+            Some(t) => match scheme {
+                SubdivisionScheme::Loop => {
+                    let opposite = |g: u32| mesh.position(mesh.target(mesh.next(g)));
+                    (a + b) * 0.375 + (opposite(h) + opposite(t)) * 0.125
+                }
+                SubdivisionScheme::CatmullClark => {
+                    let (fh, ft) = (mesh.face(h) as usize, mesh.face(t) as usize);
+                    (a + b + face_points[fh] + face_points[ft]) * 0.25
+                }
+            },
+        };
+        edge_points.insert(h, positions.len() as u32);
+        if let Some(t) = mesh.twin(h) {
+            edge_points.insert(t, positions.len() as u32);
+        }
+        positions.push(point);
+    }
+
+    // Corners in the middle of the edges are shared by the faces on both
+    // sides unless the edge is a seam.
+    let mut corners = mesh.corners.clone();
+    let mut mid_corners: FxHashMap<(u32, u32), u32> = FxHashMap::default();
+    let mut new_faces = Vec::new();
+    for f in faces {
+        let halfedges = mesh.face_halfedges(f);
+        let n = halfedges.len();
+        // The k-th corner is at the source of the k-th half-edge.
+        let vertices: Vec<(u32, u32)> = (0..n)
+            .map(|k| {
+                let prev = halfedges[(k + n - 1) % n];
+                (mesh.target(prev), mesh.corner(prev))
+            })
+            .collect();
+        let mids: Vec<(u32, u32)> = (0..n)
+            .map(|k| {
+                let (ca, cb) = (vertices[k].1, vertices[(k + 1) % n].1);
+                let corner = *mid_corners
+                    .entry((ca.min(cb), ca.max(cb)))
+                    .or_insert_with(|| corners.push_average(&[ca, cb]));
+                (edge_points[&halfedges[k]], corner)
+            })
+            .collect();
+        let sub_mesh = mesh.sub_mesh(f);
+        match scheme {
+            SubdivisionScheme::Loop => {
+                for k in 0..n {
+                    new_faces.push((vec![vertices[k], mids[k], mids[(k + n - 1) % n]], sub_mesh));
+                }
+                new_faces.push((mids, sub_mesh));
+            }
+            SubdivisionScheme::CatmullClark => {
+                let face_corners: Vec<u32> = vertices.iter().map(|(_, c)| *c).collect();
+                let center = (positions.len() as u32, corners.push_average(&face_corners));
+                positions.push(face_points[f as usize]);
+                for k in 0..n {
+                    new_faces.push((
+                        vec![vertices[k], mids[k], center, mids[(k + n - 1) % n]],
+                        sub_mesh,
+                    ));
+                }
+            }
+        }
+    }
+    HalfEdgeMesh::from_polygons(positions, new_faces, corners).with_metadata_of(mesh)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn subdivided_cube_stays_closed_and_shrinks_towards_a_sphere() {
+        let cube = Mesh::cube(2.0);
+        for (scheme, n_triangles) in [
+            (SubdivisionScheme::Loop, 12 * 16),
+            (SubdivisionScheme::CatmullClark, 12 * 3 * 4 * 2),
+        ] {
+            let smooth = cube.subdivide(2, scheme);
+            assert_eq!(smooth.triangle_count(), n_triangles, "{scheme:?}");
+            assert!(smooth.is_watertight(), "{scheme:?}");
+            let n_vertices = smooth.attributes.vertex_count();
+            for (attribute, container) in smooth.attributes.0.iter() {
+                assert_eq!(container.len(), n_vertices * attribute.size);
+            }
+            let positions = smooth.positions().unwrap();
+            assert!(positions
+                .iter()
+                .all(|p| Vec3::from(*p).length() < 3.0f32.sqrt()));
+        }
+    }
+
+    #[test]
+    fn open_boundaries_stay_in_their_plane() {
+        let plane = Mesh::plane(1.0, crate::core::Alignment::XZ);
+        let smooth = plane.subdivide(1, SubdivisionScheme::Loop);
+        assert_eq!(smooth.triangle_count(), 8);
+        let positions = smooth.positions().unwrap();
+        assert!(positions.iter().all(|p| p[1] == 0.0));
+    }
+}
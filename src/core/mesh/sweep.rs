@@ -0,0 +1,127 @@
+use crate::core::{
+    mesh::{AttribContainer, Indices, Mesh, VertexAttribute, VertexAttributes},
+    spline::Spline,
+};
+use glam::{Vec2, Vec3};
+
+impl Mesh {
+    /// Largest angle in radians between adjacent edges of a swept profile
+    /// whose normals are smoothed, sharper corners being kept hard.
+    const SWEEP_SMOOTH_ANGLE: f32 = std::f32::consts::FRAC_PI_6;
+
+    /// Creates the surface swept by the profile along the spline, e.g. a
+    /// road, a wall or a river bed. The ends are left open.
+    ///
+    /// The profile is given in the plane of each frame of the spline, `x`
+    /// to the right of the path and `y` above it. Its points are ordered
+    /// clockwise as seen looking along the path, e.g. from left to right for
+    /// a road, so that the surface faces outwards.
+    ///
+    /// The texture coordinates are the distances along the profile and along
+    /// the path divided by `uv_scale`, so that a texture is tiled every
+    /// `uv_scale` units.
+    pub fn sweep_along_spline(profile: &[Vec2], spline: &Spline, uv_scale: f32) -> Mesh {
+        assert!(profile.len() >= 2, "A profile requires at least 2 points.");
+        assert!(uv_scale > 0.0, "The UV scale must be positive.");
+        let frames = spline.frames();
+        // Normal of each edge of the profile.
+        let edge_normals = profile
+            .windows(2)
+            .map(|edge| {
+                let d = edge[1] - edge[0];
+                Vec2::new(-d.y, d.x).normalize_or_zero()
+            })
+            .collect::<Vec<_>>();
+        let cos_smooth = Self::SWEEP_SMOOTH_ANGLE.cos();
+        let smoothed = |a: Vec2, b: Vec2| {
+            if a.dot(b) >= cos_smooth {
+                (a + b).normalize_or_zero()
+            } else {
+                a
+            }
+        };
+
+        // Each edge of the profile has its own vertices, so that sharp
+        // corners have distinct normals.
+        let n_vertices = edge_normals.len() * frames.len() * 2;
+        let mut positions: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
+        let mut normals: Vec<[f32; 3]> = Vec::with_capacity(n_vertices);
+        let mut uvs: Vec<[f32; 2]> = Vec::with_capacity(n_vertices);
+        let mut indices = Vec::new();
+        let mut u = 0.0;
+        for (k, normal) in edge_normals.iter().enumerate() {
+            let ends = [
+                (
+                    profile[k],
+                    k.checked_sub(1)
+                        .map_or(*normal, |prev| smoothed(*normal, edge_normals[prev])),
+                ),
+                (
+                    profile[k + 1],
+                    edge_normals
+                        .get(k + 1)
+                        .map_or(*normal, |next| smoothed(*normal, *next)),
+                ),
+            ];
+            let us = [u, u + profile[k].distance(profile[k + 1])];
+            u = us[1];
+            let base = positions.len() as u32;
+            for frame in &frames {
+                for ((point, normal), u) in ends.iter().zip(us) {
+                    let position =
+                        frame.position + frame.side * (point.x * frame.miter) + frame.up * point.y;
+                    let normal = (frame.side * normal.x + frame.up * normal.y).normalize_or_zero();
+                    positions.push(position.to_array());
+                    normals.push(normal.to_array());
+                    uvs.push([u / uv_scale, frame.distance / uv_scale]);
+                }
+            }
+            for i in 0..frames.len().saturating_sub(1) as u32 {
+                let a = base + i * 2;
+                let [b, c, d] = [a + 1, a + 2, a + 3];
+                indices.extend([a, b, c, b, d, c]);
+            }
+        }
+
+        let mut attributes = VertexAttributes::default();
+        attributes.insert(VertexAttribute::POSITION, AttribContainer::new(&positions));
+        attributes.insert(VertexAttribute::NORMAL, AttribContainer::new(&normals));
+        attributes.insert(VertexAttribute::UV, AttribContainer::new(&uvs));
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.attributes = attributes;
+        mesh.indices = Some(Indices::U32(indices));
+        mesh.compute_tangents();
+        mesh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::spline::SplineKind;
+
+    #[test]
+    fn swept_road_faces_up_with_tiled_uvs() {
+        let spline = Spline::new(
+            vec![Vec3::ZERO, Vec3::new(0.0, 0.0, 4.0)],
+            SplineKind::Polyline,
+        )
+        .unwrap();
+        let profile = [Vec2::new(-1.0, 0.0), Vec2::new(1.0, 0.0)];
+        let mesh = Mesh::sweep_along_spline(&profile, &spline, 2.0);
+        let positions = mesh.positions().unwrap();
+        assert_eq!(positions.len(), 4);
+        // The first point of the profile is on the left of the path.
+        assert_eq!(positions[0], [1.0, 0.0, 0.0]);
+        assert_eq!(positions[3], [-1.0, 0.0, 4.0]);
+
+        let indices = mesh.triangles().unwrap();
+        assert_eq!(indices.len(), 2);
+        for tri in indices {
+            let [a, b, c] = tri.map(|i| Vec3::from(positions[i as usize]));
+            assert!((b - a).cross(c - a).normalize().abs_diff_eq(Vec3::Y, 1e-6));
+        }
+        let uvs = mesh.attributes.0[&VertexAttribute::UV].as_slice::<[f32; 2]>();
+        assert_eq!(uvs[3], [1.0, 2.0]);
+    }
+}
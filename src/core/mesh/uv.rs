@@ -0,0 +1,327 @@
+//! Generation of texture coordinates by projecting the geometry of meshes.
+
+use crate::core::{
+    mesh::{stats::welded_vertices, AttribContainer, Indices, Mesh, VertexAttribute},
+    FxHashMap,
+};
+use glam::{Vec2, Vec3};
+use std::{
+    collections::hash_map::Entry,
+    f32::consts::{PI, TAU},
+};
+
+/// Projection used to generate the texture coordinates of a mesh.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UvProjection {
+    /// Projection onto the plane of the two largest dimensions of the mesh.
+    Planar,
+    /// Projection of each triangle onto the side of the bounding box it
+    /// faces the most.
+    Box,
+    /// Projection onto a sphere around the center of the mesh, by longitude
+    /// and latitude.
+    Spherical,
+    /// Projection onto a cylinder around the Y axis through the center of the
+    /// mesh.
+    Cylindrical,
+    /// Unwrapping into charts of connected triangles facing the same side,
+    /// each projected flat and packed without overlaps into the unit square.
+    Auto,
+}
+
+impl Mesh {
+    /// Replaces the texture coordinates of the mesh by the given projection
+    /// of its vertices, and recomputes its tangents.
+    ///
+    /// The coordinates go from 0 to 1 over the mesh, the triangles across the
+    /// seam of the spherical and cylindrical projections going past 1.
+    /// Vertices are duplicated where the triangles sharing them need
+    /// different coordinates. Only triangle lists are supported.
+    pub fn generate_uvs(&mut self, projection: UvProjection) {
+        let (Some(positions), Some(triangles), Some((min, max))) =
+            (self.positions(), self.triangles(), self.bounds())
+        else {
+            log::warn!(
+                "Mesh {} has no triangles, can't generate the UVs.",
+                self.name
+            );
+            return;
+        };
+        let corners = triangles
+            .iter()
+            .map(|triangle| triangle.map(|i| Vec3::from(positions[i as usize])))
+            .collect::<Vec<_>>();
+        let size = (max - min).max_element().max(f32::EPSILON);
+        let uvs = match projection {
+            UvProjection::Planar => {
+                let axis = min_axis(max - min);
+                corners
+                    .iter()
+                    .map(|corners| corners.map(|p| project(p - min, axis) / size))
+                    .collect()
+            }
+            UvProjection::Box => corners
+                .iter()
+                .map(|corners| {
+                    let axis = max_axis(face_normal(corners).abs());
+                    corners.map(|p| project(p - min, axis) / size)
+                })
+                .collect(),
+            UvProjection::Spherical | UvProjection::Cylindrical => {
+                let center = (min + max) * 0.5;
+                let height = (max.y - min.y).max(f32::EPSILON);
+                corners
+                    .iter()
+                    .map(|corners| {
+                        let mut uvs = corners.map(|p| {
+                            let d = p - center;
+                            let u = 0.5 + d.x.atan2(d.z) / TAU;
+                            let v = match projection {
+                                UvProjection::Spherical => {
+                                    0.5 + (d.y / d.length().max(f32::EPSILON)).asin() / PI
+                                }
+                                _ => (p.y - min.y) / height,
+                            };
+                            Vec2::new(u, v)
+                        });
+                        // Triangles across the seam wrap around past 1.
+                        let (u_min, u_max) =
+                            uvs.iter().fold((f32::MAX, f32::MIN), |(lo, hi), uv| {
+                                (lo.min(uv.x), hi.max(uv.x))
+                            });
+                        if u_max - u_min > 0.5 {
+                            for uv in uvs.iter_mut().filter(|uv| uv.x < 0.5) {
+                                uv.x += 1.0;
+                            }
+                        }
+                        uvs
+                    })
+                    .collect()
+            }
+            UvProjection::Auto => unwrap_charts(&triangles, &corners, positions),
+        };
+        self.set_corner_uvs(&triangles, &uvs);
+    }
+
+    /// Sets the texture coordinates of each corner of the triangles,
+    /// duplicating the vertices having different coordinates per triangle.
+    fn set_corner_uvs(&mut self, triangles: &[[u32; 3]], uvs: &[[Vec2; 3]]) {
+        // New vertex of each pair of original vertex and coordinates.
+        let mut remap = FxHashMap::default();
+        let mut kept = Vec::new();
+        let mut new_uvs = Vec::new();
+        let mut indices = Vec::with_capacity(triangles.len() * 3);
+        for (triangle, uvs) in triangles.iter().zip(uvs) {
+            for (v, uv) in triangle.iter().zip(uvs) {
+                let index = *remap
+                    .entry((*v, uv.to_array().map(f32::to_bits)))
+                    .or_insert_with(|| {
+                        kept.push(*v as usize);
+                        new_uvs.push(uv.to_array());
+                        kept.len() as u32 - 1
+                    });
+                indices.push(index);
+            }
+        }
+        for (attribute, container) in self.attributes.0.iter_mut() {
+            let size = attribute.size;
+            let mut data = Vec::with_capacity(kept.len() * size);
+            for v in &kept {
+                data.extend_from_slice(&container.data[v * size..(v + 1) * size]);
+            }
+            *container = AttribContainer::new(&data);
+        }
+        self.attributes
+            .insert(VertexAttribute::UV, AttribContainer::new(&new_uvs));
+        self.indices = Some(Indices::U32(indices));
+        self.attributes.0.remove(&VertexAttribute::TANGENT);
+        if self.attributes.0.contains_key(&VertexAttribute::NORMAL) {
+            self.compute_tangents();
+        }
+    }
+}
+
+/// Returns the coordinates of `p` in the plane orthogonal to `axis`, with the
+/// second coordinate along Y for the vertical planes.
+fn project(p: Vec3, axis: usize) -> Vec2 {
+    match axis {
+        0 => Vec2::new(p.z, p.y),
+        1 => Vec2::new(p.x, p.z),
+        _ => Vec2::new(p.x, p.y),
+    }
+}
+
+/// Returns the axis of the largest component of `v`.
+fn max_axis(v: Vec3) -> usize {
+    (0..3).max_by(|a, b| v[*a].total_cmp(&v[*b])).unwrap()
+}
+
+/// Returns the axis of the smallest component of `v`.
+fn min_axis(v: Vec3) -> usize {
+    (0..3).min_by(|a, b| v[*a].total_cmp(&v[*b])).unwrap()
+}
+
+/// Returns the unnormalized normal of a counter-clockwise triangle.
+fn face_normal([a, b, c]: &[Vec3; 3]) -> Vec3 {
+    (*b - *a).cross(*c - *a)
+}
+
+/// Groups the triangles into charts of connected triangles facing the same
+/// side of the bounding box, projects each chart onto that side and packs
+/// the charts in rows into the unit square.
+fn unwrap_charts(
+    triangles: &[[u32; 3]],
+    corners: &[[Vec3; 3]],
+    positions: &[[f32; 3]],
+) -> Vec<[Vec2; 3]> {
+    // Side faced by each triangle, as the axis and whether it is negative.
+    let sides = corners
+        .iter()
+        .map(|corners| {
+            let normal = face_normal(corners);
+            let axis = max_axis(normal.abs());
+            (axis, normal[axis] < 0.0)
+        })
+        .collect::<Vec<_>>();
+
+    // Merges the triangles facing the same side across their shared edges.
+    let vertices = welded_vertices(positions);
+    let mut parents = (0..triangles.len()).collect::<Vec<_>>();
+    let mut edges = FxHashMap::<(u32, u32), usize>::default();
+    for (t, triangle) in triangles.iter().enumerate() {
+        let [a, b, c] = triangle.map(|i| vertices[i as usize]);
+        for (a, b) in [(a, b), (b, c), (c, a)] {
+            match edges.entry((a.min(b), a.max(b))) {
+                Entry::Occupied(entry) => {
+                    let other = *entry.get();
+                    if sides[other] == sides[t] {
+                        let (ra, rb) = (root(&mut parents, other), root(&mut parents, t));
+                        parents[ra] = rb;
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(t);
+                }
+            }
+        }
+    }
+
+    // Projects the triangles onto their side, as seen from outside.
+    let mut uvs = corners
+        .iter()
+        .zip(&sides)
+        .map(|(corners, (axis, negative))| {
+            corners.map(|p| {
+                let uv = project(p, *axis);
+                // The charts facing +X, +Y and -Z are seen mirrored.
+                if *negative == (*axis == 2) {
+                    Vec2::new(-uv.x, uv.y)
+                } else {
+                    uv
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut chart_of = FxHashMap::default();
+    let mut charts: Vec<(Vec2, Vec2, Vec<usize>)> = Vec::new();
+    for t in 0..triangles.len() {
+        let r = root(&mut parents, t);
+        let chart = *chart_of.entry(r).or_insert_with(|| {
+            charts.push((Vec2::splat(f32::MAX), Vec2::splat(f32::MIN), Vec::new()));
+            charts.len() - 1
+        });
+        let (min, max, members) = &mut charts[chart];
+        for uv in &uvs[t] {
+            *min = min.min(*uv);
+            *max = max.max(*uv);
+        }
+        members.push(t);
+    }
+
+    // Packs the charts in rows of about the width of a square of their total
+    // area, the tallest ones first.
+    let padding = charts
+        .iter()
+        .map(|(min, max, _)| (*max - *min).max_element())
+        .fold(0.0, f32::max)
+        * 0.02;
+    let area = charts
+        .iter()
+        .map(|(min, max, _)| (max.x - min.x + padding) * (max.y - min.y + padding))
+        .sum::<f32>();
+    let row_width = charts
+        .iter()
+        .map(|(min, max, _)| max.x - min.x + padding)
+        .fold(area.sqrt(), f32::max);
+    charts.sort_by(|a, b| (b.1.y - b.0.y).total_cmp(&(a.1.y - a.0.y)));
+    let mut cursor = Vec2::ZERO;
+    let mut row_height = 0.0f32;
+    let mut extent = Vec2::ZERO;
+    for (min, max, members) in &charts {
+        let size = *max - *min;
+        if cursor.x > 0.0 && cursor.x + size.x > row_width {
+            cursor = Vec2::new(0.0, cursor.y + row_height + padding);
+            row_height = 0.0;
+        }
+        let offset = cursor - *min;
+        for t in members {
+            for uv in uvs[*t].iter_mut() {
+                *uv += offset;
+            }
+        }
+        extent = extent.max(cursor + size);
+        cursor.x += size.x + padding;
+        row_height = row_height.max(size.y);
+    }
+    let scale = 1.0 / extent.max_element().max(f32::EPSILON);
+    for uv in uvs.iter_mut().flatten() {
+        *uv *= scale;
+    }
+    uvs
+}
+
+/// Returns the root of the set of `i` in a union-find forest.
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unwrapped_cube_charts_do_not_overlap() {
+        let mut cube = Mesh::cube(1.0);
+        cube.generate_uvs(UvProjection::Auto);
+        assert_eq!(cube.triangle_count(), 12);
+        let uvs = cube.attributes.0[&VertexAttribute::UV].as_slice::<[f32; 2]>();
+        assert!(uvs
+            .iter()
+            .all(|uv| uv.iter().all(|x| (-1e-5..=1.0 + 1e-5).contains(x))));
+        // One chart per side: the bounds of the sides are disjoint.
+        let triangles = cube.triangles().unwrap();
+        let bounds = triangles
+            .chunks(2)
+            .map(|side| {
+                side.iter().flatten().fold(
+                    (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+                    |(min, max), i| {
+                        let uv = Vec2::from(uvs[*i as usize]);
+                        (min.min(uv), max.max(uv))
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+        for (i, a) in bounds.iter().enumerate() {
+            for b in &bounds[i + 1..] {
+                let overlap = a.0.cmplt(b.1).all() && b.0.cmplt(a.1).all();
+                assert!(!overlap);
+            }
+        }
+    }
+}
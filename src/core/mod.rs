@@ -1,8 +1,11 @@
 //! Core module
 //!
 //! This module contains the core types and functions of the framework.
+pub mod bvh;
 pub mod camera;
 mod color;
+pub mod gizmo;
+pub mod halfedge;
 pub use color::*;
 pub mod assets;
 mod material;
@@ -10,6 +13,15 @@ pub use material::*;
 mod light;
 pub use light::*;
 pub mod mesh;
+pub mod noise;
+pub mod particle;
+pub mod procgen;
+mod rng;
+pub use rng::*;
+pub mod sky;
+pub mod spline;
+pub mod sprite;
+pub mod water;
 
 mod transform;
 pub use transform::*;
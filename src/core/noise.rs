@@ -0,0 +1,418 @@
+//! Seeded coherent noise, e.g. for heightmaps and procedural textures.
+//!
+//! The same [`Noise`] gives the same values in Rust and in Python, so that
+//! textures generated from Python match the meshes generated in Rust.
+
+use glam::{IVec3, Vec2, Vec3};
+use numpy as np;
+use pyo3::prelude::*;
+
+/// Basis function of a [`Noise`].
+#[pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum NoiseKind {
+    /// Gradient noise on a square lattice, roughly in `[-1, 1]`.
+    #[default]
+    Perlin,
+    /// Gradient noise on a simplex lattice, roughly in `[-1, 1]`, with fewer
+    /// directional artifacts than Perlin noise.
+    Simplex,
+    /// Distance to the nearest of randomly scattered feature points, one per
+    /// unit cell, clamped to `[0, 1]`. Gives cell-like patterns.
+    Worley,
+}
+
+/// Fractal noise: the sum of `octaves` layers of the basis noise, each
+/// `lacunarity` times the frequency and `gain` times the amplitude of the
+/// previous one, normalized to the range of the basis noise.
+#[pyclass]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    /// Basis function.
+    #[pyo3(get, set)]
+    pub kind: NoiseKind,
+    /// Seed of the noise, different seeds giving unrelated values.
+    #[pyo3(get, set)]
+    pub seed: u64,
+    /// Frequency of the first octave, i.e. number of lattice cells per unit.
+    #[pyo3(get, set)]
+    pub frequency: f32,
+    /// Number of layers summed, 1 giving the basis noise.
+    #[pyo3(get, set)]
+    pub octaves: u32,
+    /// Frequency multiplier between successive octaves.
+    #[pyo3(get, set)]
+    pub lacunarity: f32,
+    /// Amplitude multiplier between successive octaves.
+    #[pyo3(get, set)]
+    pub gain: f32,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self::new(NoiseKind::Perlin, 0)
+    }
+}
+
+impl Noise {
+    /// Creates a single octave noise of unit frequency.
+    pub fn new(kind: NoiseKind, seed: u64) -> Self {
+        Self {
+            kind,
+            seed,
+            frequency: 1.0,
+            octaves: 1,
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    /// Returns the fractal noise at the 2D point.
+    pub fn sample2(&self, p: Vec2) -> f32 {
+        self.fbm(|frequency| self.basis2(p * frequency))
+    }
+
+    /// Returns the fractal noise at the 3D point.
+    pub fn sample3(&self, p: Vec3) -> f32 {
+        self.fbm(|frequency| self.basis3(p * frequency))
+    }
+
+    /// Returns the basis noise of a single octave at the 2D point.
+    pub fn basis2(&self, p: Vec2) -> f32 {
+        match self.kind {
+            NoiseKind::Perlin => self.perlin2(p),
+            NoiseKind::Simplex => self.simplex2(p),
+            NoiseKind::Worley => self.worley2(p),
+        }
+    }
+
+    /// Returns the basis noise of a single octave at the 3D point.
+    pub fn basis3(&self, p: Vec3) -> f32 {
+        match self.kind {
+            NoiseKind::Perlin => self.perlin3(p),
+            NoiseKind::Simplex => self.simplex3(p),
+            NoiseKind::Worley => self.worley3(p),
+        }
+    }
+
+    fn fbm(&self, basis: impl Fn(f32) -> f32) -> f32 {
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+        for _ in 0..self.octaves.max(1) {
+            sum += basis(frequency) * amplitude;
+            total += amplitude;
+            amplitude *= self.gain;
+            frequency *= self.lacunarity;
+        }
+        sum / total
+    }
+
+    /// Hashes the lattice cell with the seed; `channel` gives independent
+    /// values for the same cell.
+    fn hash(&self, cell: IVec3, channel: u32) -> u32 {
+        let mut h = (self.seed as u32) ^ ((self.seed >> 32) as u32).rotate_left(16);
+        for v in [cell.x as u32, cell.y as u32, cell.z as u32, channel] {
+            h = (h ^ v).wrapping_mul(0x9E37_79B1);
+            h ^= h >> 15;
+        }
+        h = h.wrapping_mul(0x85EB_CA6B);
+        h ^= h >> 13;
+        h = h.wrapping_mul(0xC2B2_AE35);
+        h ^ (h >> 16)
+    }
+
+    /// Returns a number uniformly distributed in `[0, 1)` for the cell.
+    fn hash_unit(&self, cell: IVec3, channel: u32) -> f32 {
+        (self.hash(cell, channel) >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    /// Dot product of the offset with one of 8 gradients picked by the hash.
+    fn grad2(hash: u32, d: Vec2) -> f32 {
+        match hash & 7 {
+            0 => d.x + d.y,
+            1 => -d.x + d.y,
+            2 => d.x - d.y,
+            3 => -d.x - d.y,
+            4 => d.x,
+            5 => -d.x,
+            6 => d.y,
+            _ => -d.y,
+        }
+    }
+
+    /// Dot product of the offset with one of the 12 gradients of Perlin's
+    /// improved noise picked by the hash.
+    fn grad3(hash: u32, d: Vec3) -> f32 {
+        let h = hash & 15;
+        let u = if h < 8 { d.x } else { d.y };
+        let v = if h < 4 {
+            d.y
+        } else if h == 12 || h == 14 {
+            d.x
+        } else {
+            d.z
+        };
+        (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+    }
+
+    fn fade(t: f32) -> f32 {
+        t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+    }
+
+    fn perlin2(&self, p: Vec2) -> f32 {
+        let cell = p.floor();
+        let f = p - cell;
+        let cell = IVec3::new(cell.x as i32, cell.y as i32, 0);
+        let corner = |x: i32, y: i32| {
+            let h = self.hash(cell + IVec3::new(x, y, 0), 0);
+            Self::grad2(h, f - Vec2::new(x as f32, y as f32))
+        };
+        let (u, v) = (Self::fade(f.x), Self::fade(f.y));
+        let bottom = corner(0, 0) + (corner(1, 0) - corner(0, 0)) * u;
+        let top = corner(0, 1) + (corner(1, 1) - corner(0, 1)) * u;
+        bottom + (top - bottom) * v
+    }
+
+    fn perlin3(&self, p: Vec3) -> f32 {
+        let cell = p.floor();
+        let f = p - cell;
+        let cell = cell.as_ivec3();
+        let corner = |x: i32, y: i32, z: i32| {
+            let offset = IVec3::new(x, y, z);
+            Self::grad3(self.hash(cell + offset, 0), f - offset.as_vec3())
+        };
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let (u, v, w) = (Self::fade(f.x), Self::fade(f.y), Self::fade(f.z));
+        let face = |z: i32| {
+            lerp(
+                lerp(corner(0, 0, z), corner(1, 0, z), u),
+                lerp(corner(0, 1, z), corner(1, 1, z), u),
+                v,
+            )
+        };
+        lerp(face(0), face(1), w)
+    }
+
+    fn simplex2(&self, p: Vec2) -> f32 {
+        // Skewing factors between the square and the simplex lattices.
+        const F2: f32 = 0.366_025_4; // (sqrt(3) - 1) / 2
+        const G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+        let cell = (p + (p.x + p.y) * F2).floor();
+        let d0 = p - (cell - (cell.x + cell.y) * G2);
+        let step = if d0.x > d0.y { Vec2::X } else { Vec2::Y };
+        let corners = [
+            (Vec2::ZERO, d0),
+            (step, d0 - step + G2),
+            (Vec2::ONE, d0 - 1.0 + 2.0 * G2),
+        ];
+        let cell = IVec3::new(cell.x as i32, cell.y as i32, 0);
+        let sum: f32 = corners
+            .iter()
+            .map(|(offset, d)| {
+                let t = 0.5 - d.length_squared();
+                if t <= 0.0 {
+                    0.0
+                } else {
+                    let h = self.hash(cell + offset.as_ivec2().extend(0), 0);
+                    t * t * t * t * Self::grad2(h, *d)
+                }
+            })
+            .sum();
+        70.0 * sum
+    }
+
+    fn simplex3(&self, p: Vec3) -> f32 {
+        const F3: f32 = 1.0 / 3.0;
+        const G3: f32 = 1.0 / 6.0;
+        let cell = (p + (p.x + p.y + p.z) * F3).floor();
+        let d0 = p - (cell - (cell.x + cell.y + cell.z) * G3);
+        // The simplex containing the point is found by ranking the offsets.
+        let (first, second) = if d0.x >= d0.y {
+            if d0.y >= d0.z {
+                (Vec3::X, Vec3::new(1.0, 1.0, 0.0))
+            } else if d0.x >= d0.z {
+                (Vec3::X, Vec3::new(1.0, 0.0, 1.0))
+            } else {
+                (Vec3::Z, Vec3::new(1.0, 0.0, 1.0))
+            }
+        } else if d0.y < d0.z {
+            (Vec3::Z, Vec3::new(0.0, 1.0, 1.0))
+        } else if d0.x < d0.z {
+            (Vec3::Y, Vec3::new(0.0, 1.0, 1.0))
+        } else {
+            (Vec3::Y, Vec3::new(1.0, 1.0, 0.0))
+        };
+        let corners = [
+            (Vec3::ZERO, d0),
+            (first, d0 - first + G3),
+            (second, d0 - second + 2.0 * G3),
+            (Vec3::ONE, d0 - 1.0 + 3.0 * G3),
+        ];
+        let cell = cell.as_ivec3();
+        let sum: f32 = corners
+            .iter()
+            .map(|(offset, d)| {
+                let t = 0.6 - d.length_squared();
+                if t <= 0.0 {
+                    0.0
+                } else {
+                    let h = self.hash(cell + offset.as_ivec3(), 0);
+                    t * t * t * t * Self::grad3(h, *d)
+                }
+            })
+            .sum();
+        32.0 * sum
+    }
+
+    fn worley2(&self, p: Vec2) -> f32 {
+        let cell = p.floor();
+        let mut nearest = f32::INFINITY;
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let neighbour = cell + Vec2::new(x as f32, y as f32);
+                let c = neighbour.as_ivec2().extend(0);
+                let feature = neighbour + Vec2::new(self.hash_unit(c, 1), self.hash_unit(c, 2));
+                nearest = nearest.min(p.distance_squared(feature));
+            }
+        }
+        nearest.sqrt().min(1.0)
+    }
+
+    fn worley3(&self, p: Vec3) -> f32 {
+        let cell = p.floor();
+        let mut nearest = f32::INFINITY;
+        for z in -1..=1 {
+            for y in -1..=1 {
+                for x in -1..=1 {
+                    let neighbour = cell + Vec3::new(x as f32, y as f32, z as f32);
+                    let c = neighbour.as_ivec3();
+                    let feature = neighbour
+                        + Vec3::new(
+                            self.hash_unit(c, 1),
+                            self.hash_unit(c, 2),
+                            self.hash_unit(c, 3),
+                        );
+                    nearest = nearest.min(p.distance_squared(feature));
+                }
+            }
+        }
+        nearest.sqrt().min(1.0)
+    }
+}
+
+#[pymethods]
+impl Noise {
+    #[new]
+    #[pyo3(signature = (kind=NoiseKind::Perlin, seed=0, frequency=1.0, octaves=1, lacunarity=2.0, gain=0.5))]
+    pub fn new_py(
+        kind: NoiseKind,
+        seed: u64,
+        frequency: f32,
+        octaves: u32,
+        lacunarity: f32,
+        gain: f32,
+    ) -> Self {
+        Self {
+            kind,
+            seed,
+            frequency,
+            octaves,
+            lacunarity,
+            gain,
+        }
+    }
+
+    /// Samples the noise at the coordinates given by arrays of the same
+    /// shape, e.g. from `numpy.meshgrid`. The noise is 2D unless `z` is
+    /// given. Returns an array of that shape.
+    #[pyo3(name = "sample", signature = (x, y, z=None))]
+    pub fn sample_py(
+        &self,
+        py: Python<'_>,
+        x: &np::PyArrayDyn<f32>,
+        y: &np::PyArrayDyn<f32>,
+        z: Option<&np::PyArrayDyn<f32>>,
+    ) -> PyResult<Py<np::PyArrayDyn<f32>>> {
+        let shape = x.shape();
+        if y.shape() != shape || z.is_some_and(|z| z.shape() != shape) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The coordinates must have the same shape.",
+            ));
+        }
+        let (x, y) = (x.readonly(), y.readonly());
+        let xy = x.as_array().into_iter().zip(y.as_array());
+        let values: Vec<f32> = match z {
+            None => xy.map(|(x, y)| self.sample2(Vec2::new(*x, *y))).collect(),
+            Some(z) => {
+                let z = z.readonly();
+                xy.zip(z.as_array())
+                    .map(|((x, y), z)| self.sample3(Vec3::new(*x, *y, *z)))
+                    .collect()
+            }
+        };
+        Ok(np::PyArray1::from_vec(py, values)
+            .reshape(shape)?
+            .to_owned())
+    }
+
+    /// Samples the 2D noise over a grid of `height` rows and `width` columns
+    /// starting at `origin` with the given spacing, e.g. a heightmap.
+    #[pyo3(name = "grid", signature = (width, height, origin=[0.0, 0.0], spacing=1.0))]
+    pub fn grid_py(
+        &self,
+        py: Python<'_>,
+        width: usize,
+        height: usize,
+        origin: [f32; 2],
+        spacing: f32,
+    ) -> PyResult<Py<np::PyArray2<f32>>> {
+        let origin = Vec2::from(origin);
+        let values: Vec<f32> = (0..height)
+            .flat_map(|row| {
+                (0..width).map(move |col| {
+                    self.sample2(origin + Vec2::new(col as f32, row as f32) * spacing)
+                })
+            })
+            .collect();
+        Ok(np::PyArray1::from_vec(py, values)
+            .reshape([height, width])?
+            .to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_deterministic_and_bounded() {
+        for kind in [NoiseKind::Perlin, NoiseKind::Simplex, NoiseKind::Worley] {
+            let noise = Noise {
+                octaves: 4,
+                ..Noise::new(kind, 7)
+            };
+            let other = Noise::new(kind, 8);
+            let mut differs = false;
+            for i in 0..500 {
+                let p = Vec3::new(i as f32 * 0.137, i as f32 * -0.291, i as f32 * 0.053);
+                let value = noise.sample3(p);
+                assert_eq!(value, noise.sample3(p));
+                assert!((-1.0..=1.0).contains(&value), "{kind:?} gave {value}");
+                assert!((-1.0..=1.0).contains(&noise.sample2(p.truncate())));
+                differs |= value != other.sample3(p);
+            }
+            assert!(differs, "{kind:?} ignores the seed");
+        }
+    }
+
+    #[test]
+    fn gradient_noise_vanishes_on_the_lattice() {
+        let noise = Noise::new(NoiseKind::Perlin, 3);
+        assert_eq!(noise.sample2(Vec2::new(4.0, -2.0)), 0.0);
+        assert_eq!(noise.sample3(Vec3::new(1.0, 5.0, -3.0)), 0.0);
+        let worley = Noise::new(NoiseKind::Worley, 3);
+        assert!(worley.sample2(Vec2::new(0.5, 0.5)) >= 0.0);
+    }
+}
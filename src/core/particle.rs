@@ -0,0 +1,68 @@
+use crate::core::Color;
+
+/// Particle emitter component.
+///
+/// Particles are spawned at the position of the node of the emitter, then
+/// simulated and drawn on the GPU as camera-facing quads. The color of a
+/// particle is interpolated from `start_color` to `end_color` over its life.
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy)]
+pub struct ParticleEmitter {
+    /// Number of particles spawned per second.
+    #[pyo3(get, set)]
+    pub rate: f32,
+    /// Life time of a particle in seconds.
+    #[pyo3(get, set)]
+    pub lifetime: f32,
+    /// Mean initial velocity of the particles.
+    #[pyo3(get, set)]
+    pub velocity: [f32; 3],
+    /// Random variation added to each component of the initial velocity.
+    #[pyo3(get, set)]
+    pub velocity_spread: f32,
+    /// Constant acceleration applied to the particles.
+    #[pyo3(get, set)]
+    pub gravity: [f32; 3],
+    /// Size of a particle in world units.
+    #[pyo3(get, set)]
+    pub size: f32,
+    /// Color of a particle when spawned.
+    #[pyo3(get, set)]
+    pub start_color: Color,
+    /// Color of a particle when it dies.
+    #[pyo3(get, set)]
+    pub end_color: Color,
+    /// Maximum number of particles alive at the same time.
+    #[pyo3(get)]
+    pub max_particles: u32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            rate: 50.0,
+            lifetime: 2.0,
+            velocity: [0.0, 1.0, 0.0],
+            velocity_spread: 0.5,
+            gravity: [0.0, -0.98, 0.0],
+            size: 0.1,
+            start_color: Color::WHITE,
+            end_color: Color::new(1.0, 1.0, 1.0, 0.0),
+            max_particles: 1024,
+        }
+    }
+}
+
+#[pyo3::pymethods]
+impl ParticleEmitter {
+    #[new]
+    #[pyo3(signature = (rate=50.0, lifetime=2.0, max_particles=1024))]
+    pub fn new_py(rate: f32, lifetime: f32, max_particles: u32) -> Self {
+        Self {
+            rate,
+            lifetime,
+            max_particles: max_particles.max(1),
+            ..Default::default()
+        }
+    }
+}
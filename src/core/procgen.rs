@@ -0,0 +1,411 @@
+//! Procedural generation of buildings from their footprints.
+
+use crate::core::{
+    mesh::{AttribContainer, Indices, Mesh, SubMesh, VertexAttribute, VertexAttributes},
+    Material,
+};
+use glam::{Vec2, Vec3};
+use pyo3::prelude::*;
+
+/// Shape of the roof of a generated building.
+#[pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RoofStyle {
+    /// Horizontal roof covering the footprint.
+    #[default]
+    Flat,
+    /// Faces sloping from each wall to a single apex above the center of
+    /// the footprint, which should be convex.
+    Pyramid,
+    /// Two faces sloping from the longer walls to a ridge, the shorter walls
+    /// being extended by triangular gables. Only for footprints of 4 points.
+    Gabled,
+}
+
+/// Parameters of a generated building, see [`building`].
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct BuildingParams {
+    /// Number of floors.
+    #[pyo3(get, set)]
+    pub floors: u32,
+    /// Height of each floor.
+    #[pyo3(get, set)]
+    pub floor_height: f32,
+    /// Shape of the roof.
+    #[pyo3(get, set)]
+    pub roof: RoofStyle,
+    /// Height of the apex or the ridge of a sloping roof above the last
+    /// floor.
+    #[pyo3(get, set)]
+    pub roof_height: f32,
+    /// Width of the facade covered by the texture of a floor, the texture
+    /// being tiled along the walls.
+    #[pyo3(get, set)]
+    pub tile_width: f32,
+    /// Material of the walls.
+    #[pyo3(get, set)]
+    pub facade: Material,
+    /// Material of the walls of the ground floor, the facade material if
+    /// `None`.
+    #[pyo3(get, set)]
+    pub ground_floor: Option<Material>,
+    /// Material of the roof.
+    #[pyo3(get, set)]
+    pub roof_material: Material,
+}
+
+impl Default for BuildingParams {
+    fn default() -> Self {
+        let mut facade = Material::new();
+        facade.diffuse = Some([0.8, 0.78, 0.72]);
+        let mut roof_material = Material::new();
+        roof_material.diffuse = Some([0.45, 0.25, 0.2]);
+        Self {
+            floors: 3,
+            floor_height: 3.0,
+            roof: RoofStyle::Flat,
+            roof_height: 2.0,
+            tile_width: 3.0,
+            facade,
+            ground_floor: None,
+            roof_material,
+        }
+    }
+}
+
+#[pymethods]
+impl BuildingParams {
+    #[new]
+    #[pyo3(signature = (floors=3, floor_height=3.0, roof=RoofStyle::Flat, roof_height=2.0, tile_width=3.0))]
+    pub fn new_py(
+        floors: u32,
+        floor_height: f32,
+        roof: RoofStyle,
+        roof_height: f32,
+        tile_width: f32,
+    ) -> Self {
+        Self {
+            floors,
+            floor_height,
+            roof,
+            roof_height,
+            tile_width,
+            ..Self::default()
+        }
+    }
+}
+
+/// Triangles with flat normals sharing a material.
+#[derive(Default)]
+struct Part {
+    positions: Vec<[f32; 3]>,
+    normals: Vec<[f32; 3]>,
+    uvs: Vec<[f32; 2]>,
+}
+
+impl Part {
+    /// Appends a triangle, counter-clockwise seen from its front.
+    fn triangle(&mut self, vertices: [Vec3; 3], uvs: [Vec2; 3]) {
+        let [a, b, c] = vertices;
+        let normal = (b - a).cross(c - a).normalize_or_zero();
+        for (vertex, uv) in vertices.iter().zip(uvs) {
+            self.positions.push(vertex.to_array());
+            self.normals.push(normal.to_array());
+            self.uvs.push(uv.to_array());
+        }
+    }
+
+    /// Returns the mesh of the triangles with the material, or `None` if
+    /// there are no triangles.
+    fn into_mesh(self, material: &Material) -> Option<Mesh> {
+        if self.positions.is_empty() {
+            return None;
+        }
+        let n = self.positions.len() as u32;
+        let mut attributes = VertexAttributes::default();
+        attributes.insert(
+            VertexAttribute::POSITION,
+            AttribContainer::new(&self.positions),
+        );
+        attributes.insert(VertexAttribute::NORMAL, AttribContainer::new(&self.normals));
+        attributes.insert(VertexAttribute::UV, AttribContainer::new(&self.uvs));
+        let mut mesh = Mesh::new(wgpu::PrimitiveTopology::TriangleList);
+        mesh.attributes = attributes;
+        mesh.indices = Some(Indices::U32((0..n).collect()));
+        mesh.sub_meshes = Some(vec![SubMesh::new(0, n, 0)]);
+        mesh.materials = Some(vec![material.clone()]);
+        Some(mesh)
+    }
+}
+
+/// Generates a building standing on the footprint, a polygon in the XZ
+/// plane given as `(x, z)` points in either winding order, the ground being
+/// at `y = 0`.
+///
+/// The walls, the roof and the walls of the ground floor are sub-meshes
+/// with their own materials. The walls are textured one floor at a time.
+/// The floor of the building is left open.
+pub fn building(footprint: &[Vec2], params: &BuildingParams) -> Result<Mesh, String> {
+    profiling::scope!("procgen::building");
+    if footprint.len() < 3 {
+        return Err("A footprint requires at least 3 points.".to_string());
+    }
+    if params.floors == 0 || params.floor_height <= 0.0 || params.tile_width <= 0.0 {
+        return Err(
+            "A building requires at least one floor, and a positive floor height and tile width."
+                .to_string(),
+        );
+    }
+    if params.roof == RoofStyle::Gabled && footprint.len() != 4 {
+        return Err("A gabled roof requires a footprint of 4 points.".to_string());
+    }
+    let area = signed_area(footprint);
+    if area.abs() <= f32::EPSILON {
+        return Err("The footprint has no area.".to_string());
+    }
+    // Counter-clockwise in the (x, z) plane.
+    let mut footprint = footprint.to_vec();
+    if area < 0.0 {
+        footprint.reverse();
+    }
+    let n = footprint.len();
+    let top = params.floors as f32 * params.floor_height;
+    let at = |p: Vec2, y: f32| Vec3::new(p.x, y, p.y);
+    let planar_uv = |p: Vec3| Vec2::new(p.x, p.z) / params.tile_width;
+
+    // Walls, one quad per floor and edge so that the texture of a floor is
+    // repeated on each floor.
+    let mut ground = Part::default();
+    let mut walls = Part::default();
+    let mut u = 0.0;
+    for i in 0..n {
+        let (a, b) = (footprint[i], footprint[(i + 1) % n]);
+        let (u0, u1) = (u, u + a.distance(b) / params.tile_width);
+        u = u1;
+        for floor in 0..params.floors {
+            let part = if floor == 0 && params.ground_floor.is_some() {
+                &mut ground
+            } else {
+                &mut walls
+            };
+            let (y0, y1) = (
+                floor as f32 * params.floor_height,
+                (floor + 1) as f32 * params.floor_height,
+            );
+            let (v0, v1) = (floor as f32, floor as f32 + 1.0);
+            part.triangle(
+                [at(a, y0), at(a, y1), at(b, y1)],
+                [Vec2::new(u0, v0), Vec2::new(u0, v1), Vec2::new(u1, v1)],
+            );
+            part.triangle(
+                [at(a, y0), at(b, y1), at(b, y0)],
+                [Vec2::new(u0, v0), Vec2::new(u1, v1), Vec2::new(u1, v0)],
+            );
+        }
+    }
+
+    let mut roof = Part::default();
+    match params.roof {
+        RoofStyle::Flat => {
+            for [i, j, k] in triangulate(&footprint) {
+                // Counter-clockwise in (x, z) faces down, hence the order.
+                let vertices = [
+                    at(footprint[i], top),
+                    at(footprint[k], top),
+                    at(footprint[j], top),
+                ];
+                roof.triangle(vertices, vertices.map(planar_uv));
+            }
+        }
+        RoofStyle::Pyramid => {
+            let center = footprint.iter().sum::<Vec2>() / n as f32;
+            let apex = at(center, top + params.roof_height);
+            for i in 0..n {
+                let vertices = [at(footprint[i], top), apex, at(footprint[(i + 1) % n], top)];
+                roof.triangle(vertices, vertices.map(planar_uv));
+            }
+        }
+        RoofStyle::Gabled => {
+            // The ridge is parallel to the longer pair of opposite walls.
+            let length = |i: usize| footprint[i].distance(footprint[(i + 1) % 4]);
+            let first = if length(0) + length(2) >= length(1) + length(3) {
+                0
+            } else {
+                1
+            };
+            let q = [0, 1, 2, 3].map(|i| footprint[(i + first) % 4]);
+            let ridge_y = top + params.roof_height;
+            let m_a = at((q[3] + q[0]) * 0.5, ridge_y);
+            let m_b = at((q[1] + q[2]) * 0.5, ridge_y);
+            let [q0, q1, q2, q3] = q.map(|p| at(p, top));
+            for vertices in [[q0, m_a, q1], [q1, m_a, m_b], [q2, m_b, q3], [q3, m_b, m_a]] {
+                roof.triangle(vertices, vertices.map(planar_uv));
+            }
+            // The gables continue the walls, with their texture.
+            let v_top = params.floors as f32;
+            let v_apex = v_top + params.roof_height / params.floor_height;
+            for (a, apex, b) in [(q1, m_b, q2), (q3, m_a, q0)] {
+                let width = a.distance(b) / params.tile_width;
+                walls.triangle(
+                    [a, apex, b],
+                    [
+                        Vec2::new(0.0, v_top),
+                        Vec2::new(width * 0.5, v_apex),
+                        Vec2::new(width, v_top),
+                    ],
+                );
+            }
+        }
+    }
+
+    let parts = [
+        ground.into_mesh(params.ground_floor.as_ref().unwrap_or(&params.facade)),
+        walls.into_mesh(&params.facade),
+        roof.into_mesh(&params.roof_material),
+    ];
+    let parts = parts.into_iter().flatten().collect::<Vec<_>>();
+    let mut mesh =
+        Mesh::merge(&parts).ok_or_else(|| "Failed to merge the building.".to_string())?;
+    mesh.compute_tangents();
+    Ok(mesh)
+}
+
+/// Returns twice the signed area of the polygon, positive if its points are
+/// counter-clockwise.
+fn signed_area(polygon: &[Vec2]) -> f32 {
+    (0..polygon.len())
+        .map(|i| polygon[i].perp_dot(polygon[(i + 1) % polygon.len()]))
+        .sum()
+}
+
+/// Returns the triangles of a counter-clockwise simple polygon, as indices of
+/// its points, by ear clipping.
+fn triangulate(polygon: &[Vec2]) -> Vec<[usize; 3]> {
+    let mut remaining = (0..polygon.len()).collect::<Vec<_>>();
+    let mut triangles = Vec::with_capacity(polygon.len().saturating_sub(2));
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        let ear = (0..n).find(|&i| {
+            let [a, b, c] = [
+                remaining[(i + n - 1) % n],
+                remaining[i],
+                remaining[(i + 1) % n],
+            ];
+            let [pa, pb, pc] = [polygon[a], polygon[b], polygon[c]];
+            // Convex corner without any other point inside the triangle.
+            (pb - pa).perp_dot(pc - pb) > 0.0
+                && remaining
+                    .iter()
+                    .all(|&p| [a, b, c].contains(&p) || !in_triangle(polygon[p], pa, pb, pc))
+        });
+        // Degenerate polygons have no ear, the remaining points are fanned.
+        let i = ear.unwrap_or(0);
+        triangles.push([
+            remaining[(i + n - 1) % n],
+            remaining[i],
+            remaining[(i + 1) % n],
+        ]);
+        remaining.remove(i);
+    }
+    if let [a, b, c] = remaining[..] {
+        triangles.push([a, b, c]);
+    }
+    triangles
+}
+
+/// Whether the point is inside or on the counter-clockwise triangle.
+fn in_triangle(p: Vec2, a: Vec2, b: Vec2, c: Vec2) -> bool {
+    (b - a).perp_dot(p - a) >= 0.0
+        && (c - b).perp_dot(p - b) >= 0.0
+        && (a - c).perp_dot(p - c) >= 0.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normals_and_positions(mesh: &Mesh) -> Vec<(Vec3, Vec3)> {
+        let positions = mesh.positions().unwrap();
+        let normals = mesh.attributes.0[&VertexAttribute::NORMAL].as_slice::<[f32; 3]>();
+        positions
+            .iter()
+            .zip(normals)
+            .map(|(p, n)| (Vec3::from(*p), Vec3::from(*n)))
+            .collect()
+    }
+
+    #[test]
+    fn concave_footprints_are_covered_by_the_flat_roof() {
+        // L-shaped footprint, clockwise.
+        let footprint = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(0.0, 2.0),
+            Vec2::new(1.0, 2.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(2.0, 0.0),
+        ];
+        let mut reversed = footprint;
+        reversed.reverse();
+        let triangles = triangulate(&reversed);
+        assert_eq!(triangles.len(), 4);
+        let area = triangles
+            .iter()
+            .map(|[a, b, c]| (reversed[*b] - reversed[*a]).perp_dot(reversed[*c] - reversed[*a]))
+            .sum::<f32>();
+        assert!((area - signed_area(&reversed)).abs() < 1e-5);
+
+        let params = BuildingParams {
+            floors: 2,
+            ..BuildingParams::default()
+        };
+        let mesh = building(&footprint, &params).unwrap();
+        // Walls and roof.
+        assert_eq!(mesh.sub_meshes.as_ref().unwrap().len(), 2);
+        assert_eq!(mesh.materials.as_ref().unwrap().len(), 2);
+        for (p, n) in normals_and_positions(&mesh) {
+            if p.y == 6.0 && n.y != 0.0 {
+                assert_eq!(n, Vec3::Y);
+            } else {
+                // The walls are vertical.
+                assert_eq!(n.y, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn sloping_roofs_face_up_and_out() {
+        let footprint = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(4.0, 0.0),
+            Vec2::new(4.0, 2.0),
+            Vec2::new(0.0, 2.0),
+        ];
+        let center = Vec3::new(2.0, 0.0, 1.0);
+        for roof in [RoofStyle::Pyramid, RoofStyle::Gabled] {
+            let mut ground_floor = Material::new();
+            ground_floor.diffuse = Some([0.1, 0.1, 0.1]);
+            let params = BuildingParams {
+                roof,
+                ground_floor: Some(ground_floor),
+                ..BuildingParams::default()
+            };
+            let mesh = building(&footprint, &params).unwrap();
+            assert_eq!(mesh.sub_meshes.as_ref().unwrap().len(), 3);
+            for (p, n) in normals_and_positions(&mesh) {
+                let outwards = Vec3::new(p.x - center.x, 0.0, p.z - center.z);
+                assert!(n.dot(outwards) >= -1e-5);
+                assert!(n.y >= 0.0);
+            }
+        }
+        assert!(building(
+            &footprint[..3],
+            &BuildingParams {
+                roof: RoofStyle::Gabled,
+                ..BuildingParams::default()
+            }
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,160 @@
+//! Analytical daylight sky after Preetham et al., "A Practical Analytic
+//! Model for Daylight" (1999).
+
+use crate::core::Color;
+use glam::Vec3;
+
+/// Analytical sky drawn behind the scene, lit by the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sky {
+    /// Unit vector pointing towards the sun. The sky fades into the
+    /// background color of the camera when the sun goes below the horizon.
+    pub sun: Vec3,
+    /// Haziness of the atmosphere, from 2 for a clear sky to 10 for a hazy
+    /// one.
+    pub turbidity: f32,
+    /// Scale of the luminance of the sky before it is mapped to the display.
+    pub exposure: f32,
+    /// Color of the ground below the horizon, lit by the sky.
+    pub ground: Color,
+    /// Angular radius of the sun disk in degrees.
+    pub sun_size: f32,
+}
+
+impl Default for Sky {
+    fn default() -> Self {
+        Self {
+            sun: Vec3::new(0.3, 0.6, 0.2).normalize(),
+            turbidity: 3.0,
+            exposure: 1.0,
+            ground: Color::new(0.1, 0.09, 0.08, 1.0),
+            sun_size: 0.5,
+        }
+    }
+}
+
+/// Coefficients of the sky evaluated in the background shader, for the
+/// luminance Y and the chromaticities x and y of the CIE xyY color space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SkyCoefficients {
+    /// Coefficients A to E of the Perez distribution function.
+    pub perez: [[f32; 3]; 5],
+    /// Values at the zenith, divided by the Perez function at the zenith so
+    /// that the sky is `zenith * F(theta, gamma)`.
+    pub zenith: [f32; 3],
+    /// Scale of the luminance, fading to 0 at night.
+    pub brightness: f32,
+}
+
+impl Sky {
+    /// Lowest turbidity the model is fitted for.
+    pub const MIN_TURBIDITY: f32 = 1.7;
+    /// Highest turbidity the model is fitted for.
+    pub const MAX_TURBIDITY: f32 = 10.0;
+
+    /// Returns the coefficients of the sky for the current sun and
+    /// turbidity.
+    pub fn coefficients(&self) -> SkyCoefficients {
+        let t = self
+            .turbidity
+            .clamp(Self::MIN_TURBIDITY, Self::MAX_TURBIDITY);
+        let sun = self.sun.normalize_or(Vec3::Y);
+        // The model only holds for the sun above the horizon; below, the
+        // sky at sunset is dimmed.
+        let theta_s = sun.y.clamp(0.0, 1.0).acos().min(89.0f32.to_radians());
+        let brightness = smoothstep(-0.15, 0.02, sun.y);
+
+        let perez = [
+            [
+                0.1787 * t - 1.4630,
+                -0.0193 * t - 0.2592,
+                -0.0167 * t - 0.2608,
+            ],
+            [
+                -0.3554 * t + 0.4275,
+                -0.0665 * t + 0.0008,
+                -0.0950 * t + 0.0092,
+            ],
+            [
+                -0.0227 * t + 5.3251,
+                -0.0004 * t + 0.2125,
+                -0.0079 * t + 0.2102,
+            ],
+            [
+                0.1206 * t - 2.5771,
+                -0.0641 * t - 0.8989,
+                -0.0441 * t - 1.6537,
+            ],
+            [
+                -0.0670 * t + 0.3703,
+                -0.0033 * t + 0.0452,
+                -0.0109 * t + 0.0529,
+            ],
+        ];
+
+        // Zenith luminance in kcd/m^2 and chromaticities.
+        let chi = (4.0 / 9.0 - t / 120.0) * (std::f32::consts::PI - 2.0 * theta_s);
+        let luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let (th, th2, th3) = (theta_s, theta_s * theta_s, theta_s * theta_s * theta_s);
+        let t2 = t * t;
+        let x = t2 * (0.00166 * th3 - 0.00375 * th2 + 0.00209 * th)
+            + t * (-0.02903 * th3 + 0.06377 * th2 - 0.03202 * th + 0.00394)
+            + (0.11693 * th3 - 0.21196 * th2 + 0.06052 * th + 0.25886);
+        let y = t2 * (0.00275 * th3 - 0.00610 * th2 + 0.00317 * th)
+            + t * (-0.04214 * th3 + 0.08970 * th2 - 0.04153 * th + 0.00516)
+            + (0.15346 * th3 - 0.26756 * th2 + 0.06670 * th + 0.26688);
+
+        let zenith = [luminance, x, y];
+        let zenith = std::array::from_fn(|i| {
+            let c = perez.map(|coefs| coefs[i]);
+            zenith[i] / perez_function(c, 0.0, theta_s)
+        });
+        SkyCoefficients {
+            perez,
+            zenith,
+            brightness,
+        }
+    }
+}
+
+/// Perez distribution function at the zenith angle `theta` for a direction
+/// at angle `gamma` from the sun.
+fn perez_function([a, b, c, d, e]: [f32; 5], theta: f32, gamma: f32) -> f32 {
+    let cos_gamma = gamma.cos();
+    (1.0 + a * (b / theta.cos().max(0.01)).exp())
+        * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sky_at_noon_and_at_night() {
+        let noon = Sky {
+            sun: Vec3::Y,
+            ..Default::default()
+        }
+        .coefficients();
+        assert_eq!(noon.brightness, 1.0);
+        // Luminance and chromaticities at the zenith are recovered from the
+        // Perez function.
+        let [luminance, x, y] = std::array::from_fn(|i| {
+            noon.zenith[i] * perez_function(noon.perez.map(|c| c[i]), 0.0, 0.0)
+        });
+        assert!(luminance > 1.0 && luminance < 50.0);
+        assert!((0.2..0.35).contains(&x) && (0.2..0.35).contains(&y));
+
+        let night = Sky {
+            sun: Vec3::NEG_Y,
+            ..Default::default()
+        }
+        .coefficients();
+        assert_eq!(night.brightness, 0.0);
+    }
+}
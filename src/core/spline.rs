@@ -0,0 +1,252 @@
+//! Paths along which meshes are swept, e.g. roads, walls or rivers.
+
+use glam::Vec3;
+use pyo3::prelude::*;
+
+/// Interpretation of the points of a [`Spline`].
+#[pyclass]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SplineKind {
+    /// Straight segments between the points.
+    #[default]
+    Polyline,
+    /// Cubic Bézier curves joined end to end: the points are the start of
+    /// the path followed by two control points and the end of each curve,
+    /// i.e. `3 * n + 1` points for `n` curves.
+    Bezier,
+}
+
+/// Frame of a point sampled along a spline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SplineFrame {
+    /// Position of the point.
+    pub position: Vec3,
+    /// Direction of the path at the point.
+    pub tangent: Vec3,
+    /// Direction to the right of the path, orthogonal to the tangent and
+    /// horizontal unless the path is vertical.
+    pub side: Vec3,
+    /// Direction above the path, orthogonal to the tangent and the side.
+    pub up: Vec3,
+    /// Factor by which the offsets along the side are stretched so that the
+    /// width of the swept profile is kept at the corners of a polyline.
+    pub miter: f32,
+    /// Length of the path from its start to the point.
+    pub distance: f32,
+}
+
+/// Path defined by points, see [`SplineKind`].
+#[pyclass]
+#[derive(Clone, Debug)]
+pub struct Spline {
+    points: Vec<Vec3>,
+    kind: SplineKind,
+}
+
+impl Spline {
+    /// Number of points sampled along each curve of a Bézier spline.
+    pub const BEZIER_SEGMENTS: usize = 16;
+    /// Smallest cosine of the half angle of the corners of a polyline, so
+    /// that sharp corners are not stretched indefinitely.
+    const MIN_MITER_COS: f32 = 0.25;
+
+    /// Creates a spline through the points, or returns an error if there
+    /// are too few points for the kind of spline.
+    pub fn new(points: Vec<Vec3>, kind: SplineKind) -> Result<Self, String> {
+        match kind {
+            SplineKind::Polyline if points.len() < 2 => {
+                Err("A polyline requires at least 2 points.".to_string())
+            }
+            SplineKind::Bezier if points.len() < 4 || (points.len() - 1) % 3 != 0 => Err(
+                "A Bézier spline requires 3 * n + 1 points, n >= 1 being the number of curves."
+                    .to_string(),
+            ),
+            _ => Ok(Self { points, kind }),
+        }
+    }
+
+    /// Returns the points defining the spline.
+    pub fn points(&self) -> &[Vec3] {
+        &self.points
+    }
+
+    /// Returns the kind of the spline.
+    pub fn kind(&self) -> SplineKind {
+        self.kind
+    }
+
+    /// Returns the positions along the path: the points of a polyline, or
+    /// [`Self::BEZIER_SEGMENTS`] segments per curve of a Bézier spline.
+    /// Consecutive duplicate positions are removed.
+    pub fn positions(&self) -> Vec<Vec3> {
+        let mut positions = match self.kind {
+            SplineKind::Polyline => self.points.clone(),
+            SplineKind::Bezier => {
+                let mut positions = vec![self.points[0]];
+                for curve in self.points.windows(4).step_by(3) {
+                    let [p0, p1, p2, p3] = [curve[0], curve[1], curve[2], curve[3]];
+                    positions.extend((1..=Self::BEZIER_SEGMENTS).map(|i| {
+                        let t = i as f32 / Self::BEZIER_SEGMENTS as f32;
+                        let s = 1.0 - t;
+                        p0 * (s * s * s)
+                            + p1 * (3.0 * s * s * t)
+                            + p2 * (3.0 * s * t * t)
+                            + p3 * (t * t * t)
+                    }));
+                }
+                positions
+            }
+        };
+        positions.dedup_by(|a, b| a.distance_squared(*b) <= f32::EPSILON);
+        positions
+    }
+
+    /// Returns the frames of the positions along the path, see
+    /// [`Self::positions`].
+    ///
+    /// The tangent at a position is the bisector of the directions of its
+    /// segments. The side stays horizontal so that roads don't bank; on
+    /// vertical parts of the path, the side of the previous frame is kept.
+    pub fn frames(&self) -> Vec<SplineFrame> {
+        let positions = self.positions();
+        let n = positions.len();
+        let mut frames = Vec::with_capacity(n);
+        let mut distance = 0.0;
+        let mut prev_side = Vec3::X;
+        for i in 0..n {
+            let incoming = (i > 0).then(|| (positions[i] - positions[i - 1]).normalize_or_zero());
+            let outgoing =
+                (i + 1 < n).then(|| (positions[i + 1] - positions[i]).normalize_or_zero());
+            let tangent = match (incoming, outgoing) {
+                (Some(a), Some(b)) => (a + b).try_normalize().unwrap_or(b),
+                (Some(a), None) => a,
+                (None, Some(b)) => b,
+                (None, None) => Vec3::Z,
+            };
+            let miter = match (incoming, self.kind) {
+                (Some(a), SplineKind::Polyline) if outgoing.is_some() => {
+                    1.0 / tangent.dot(a).max(Self::MIN_MITER_COS)
+                }
+                _ => 1.0,
+            };
+            let side = tangent
+                .cross(Vec3::Y)
+                .try_normalize()
+                .unwrap_or_else(|| (prev_side - tangent * prev_side.dot(tangent)).normalize());
+            prev_side = side;
+            if i > 0 {
+                distance += positions[i].distance(positions[i - 1]);
+            }
+            frames.push(SplineFrame {
+                position: positions[i],
+                tangent,
+                side,
+                up: side.cross(tangent),
+                miter,
+                distance,
+            });
+        }
+        frames
+    }
+
+    /// Returns the length of the path.
+    pub fn length(&self) -> f32 {
+        self.positions()
+            .windows(2)
+            .map(|segment| segment[0].distance(segment[1]))
+            .sum()
+    }
+}
+
+#[pymethods]
+impl Spline {
+    #[new]
+    #[pyo3(signature = (points, kind=SplineKind::Polyline))]
+    pub fn new_py(points: Vec<[f32; 3]>, kind: SplineKind) -> PyResult<Self> {
+        Self::new(points.into_iter().map(Vec3::from).collect(), kind)
+            .map_err(pyo3::exceptions::PyValueError::new_err)
+    }
+
+    /// Returns the points defining the spline.
+    #[getter(points)]
+    pub fn points_py(&self) -> Vec<[f32; 3]> {
+        self.points.iter().map(|p| p.to_array()).collect()
+    }
+
+    /// Returns the kind of the spline.
+    #[getter(kind)]
+    pub fn kind_py(&self) -> SplineKind {
+        self.kind
+    }
+
+    /// Returns the length of the path.
+    #[pyo3(name = "length")]
+    pub fn length_py(&self) -> f32 {
+        self.length()
+    }
+
+    /// Returns the positions sampled along the path.
+    #[pyo3(name = "sample")]
+    pub fn sample_py(&self) -> Vec<[f32; 3]> {
+        self.positions().iter().map(|p| p.to_array()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polyline_frames_keep_the_width_at_corners() {
+        let spline = Spline::new(
+            vec![
+                Vec3::ZERO,
+                Vec3::new(0.0, 0.0, 2.0),
+                Vec3::new(2.0, 0.0, 2.0),
+            ],
+            SplineKind::Polyline,
+        )
+        .unwrap();
+        let frames = spline.frames();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].side, Vec3::NEG_X);
+        assert_eq!(frames[0].up, Vec3::Y);
+        assert_eq!(frames[2].distance, 4.0);
+        // The corner of 90 degrees is stretched by sqrt(2).
+        assert!((frames[1].miter - std::f32::consts::SQRT_2).abs() < 1e-5);
+        for frame in &frames {
+            assert!(frame.tangent.dot(frame.side).abs() < 1e-6);
+            assert!((frame.up - Vec3::Y).length() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn bezier_splines_pass_through_the_ends_of_their_curves() {
+        assert!(Spline::new(vec![Vec3::ZERO; 3], SplineKind::Bezier).is_err());
+        let ends = [
+            Vec3::ZERO,
+            Vec3::new(3.0, 0.0, 0.0),
+            Vec3::new(3.0, 0.0, 3.0),
+        ];
+        let spline = Spline::new(
+            vec![
+                ends[0],
+                Vec3::new(1.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                ends[1],
+                Vec3::new(4.0, 0.0, 1.0),
+                Vec3::new(4.0, 0.0, 2.0),
+                ends[2],
+            ],
+            SplineKind::Bezier,
+        )
+        .unwrap();
+        let positions = spline.positions();
+        assert_eq!(positions.len(), 2 * Spline::BEZIER_SEGMENTS + 1);
+        assert_eq!(positions[0], ends[0]);
+        assert!((positions[Spline::BEZIER_SEGMENTS] - ends[1]).length() < 1e-5);
+        assert!((positions[2 * Spline::BEZIER_SEGMENTS] - ends[2]).length() < 1e-5);
+        // The first curve is a straight line.
+        assert!((spline.frames()[4].tangent - Vec3::X).length() < 1e-5);
+    }
+}
@@ -0,0 +1,37 @@
+use crate::core::Color;
+use glam::Vec2;
+
+/// A rectangular region of a texture atlas in normalized texture coordinates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtlasRegion {
+    /// The top-left corner of the region.
+    pub min: Vec2,
+    /// The bottom-right corner of the region.
+    pub max: Vec2,
+}
+
+/// A camera-facing textured quad.
+///
+/// The sprite is centered at the position of its node and always faces the
+/// main camera. Its image is stored in the sprite texture atlas of the
+/// renderer, see [`crate::render::TextureAtlas`].
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    /// The region of the atlas containing the image of the sprite.
+    pub region: AtlasRegion,
+    /// The size of the sprite in world units.
+    pub size: Vec2,
+    /// The color multiplied with the image of the sprite.
+    pub tint: Color,
+}
+
+impl Sprite {
+    /// Creates a new sprite with the given atlas region and size.
+    pub fn new(region: AtlasRegion, size: Vec2) -> Self {
+        Self {
+            region,
+            size,
+            tint: Color::WHITE,
+        }
+    }
+}
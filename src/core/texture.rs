@@ -32,6 +32,24 @@ pub struct Texture {
 
 impl Asset for Texture {}
 
+impl Texture {
+    /// Returns the number of bytes of the texels of all the mip levels and
+    /// layers of the texture.
+    pub fn memory_size(&self) -> u64 {
+        let format = self.raw.format();
+        let (block_width, block_height) = format.block_dimensions();
+        let block_size = format.block_copy_size(None).unwrap_or(4) as u64;
+        (0..self.raw.mip_level_count())
+            .map(|level| {
+                let width = (self.size.width >> level).max(1).div_ceil(block_width) as u64;
+                let height = (self.size.height >> level).max(1).div_ceil(block_height) as u64;
+                width * height * block_size
+            })
+            .sum::<u64>()
+            * self.size.depth_or_array_layers as u64
+    }
+}
+
 impl Deref for Texture {
     type Target = wgpu::Texture;
 
@@ -40,13 +58,17 @@ impl Deref for Texture {
     }
 }
 
-/// A collection of textures and samplers.
+/// A collection of textures used by a material bundle.
+///
+/// Textures are bound through the global texture array of the renderer, the
+/// texture indices of the materials are the indices of the texture handles.
+/// Without binding arrays, the textures are bound one by one with the
+/// materials, the texture indices of the materials are then their slots.
 #[derive(Default)]
 pub struct TextureBundle {
     pub textures: Vec<Handle<Texture>>,
-    pub samplers: Vec<SmlString>,
-    pub bind_group: Option<wgpu::BindGroup>,
-    pub sampler_index_buffer: Option<wgpu::Buffer>,
+    /// Textures bound to the slots of the materials, without binding arrays.
+    pub slots: Vec<Handle<Texture>>,
 }
 
 impl Asset for TextureBundle {}
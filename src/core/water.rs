@@ -0,0 +1,56 @@
+use crate::core::Color;
+
+/// Water surface component.
+///
+/// The surface is a horizontal plane through the node of the entity, scaled
+/// by the node's scale. Waves are animated in the shader by perturbing the
+/// vertices and the normals with a sum of directional sine waves; the color
+/// blends between `color` and `sky_color` following Fresnel's law.
+///
+/// With `reflection`, the scene is rendered mirrored below the surface into
+/// an offscreen texture which replaces `sky_color`. Only the first visible
+/// reflective surface is mirrored, the others keep their sky color.
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy)]
+pub struct Water {
+    /// Color of the water seen from above.
+    #[pyo3(get, set)]
+    pub color: Color,
+    /// Color reflected by the water at grazing angles.
+    #[pyo3(get, set)]
+    pub sky_color: Color,
+    /// Height of the waves in world units.
+    #[pyo3(get, set)]
+    pub wave_amplitude: f32,
+    /// Length of the longest wave in world units.
+    #[pyo3(get, set)]
+    pub wave_length: f32,
+    /// Speed of the waves in world units per second.
+    #[pyo3(get, set)]
+    pub wave_speed: f32,
+    /// Whether the surface reflects the scene, rendered a second time from
+    /// the main camera mirrored below it.
+    #[pyo3(get, set)]
+    pub reflection: bool,
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            color: Color::new(0.02, 0.15, 0.25, 0.85),
+            sky_color: Color::new(0.65, 0.8, 0.95, 1.0),
+            wave_amplitude: 0.05,
+            wave_length: 4.0,
+            wave_speed: 1.0,
+            reflection: false,
+        }
+    }
+}
+
+#[pyo3::pymethods]
+impl Water {
+    #[new]
+    pub fn new_py() -> Self {
+        Self::default()
+    }
+}
@@ -1,6 +1,8 @@
 pub mod app;
 pub mod compute;
 pub mod core;
+#[cfg(feature = "physics")]
+pub mod physics;
 pub mod render;
 pub mod scene;
 
@@ -21,19 +23,54 @@ use pyo3::prelude::*;
 fn bkfw(_py: Python, module: &PyModule) -> PyResult<()> {
     module.add_class::<app::PyWindowBuilder>()?;
     module.add_class::<app::PyAppState>()?;
+    module.add_class::<app::PyAppConfig>()?;
     module.add_function(wrap_pyfunction!(app::run_main_loop, module)?)?;
     module.add_class::<app::Input>()?;
     module.add_class::<app::MouseButton>()?;
     module.add_class::<app::KeyCode>()?;
+    module.add_class::<render::AdapterInfo>()?;
+    module.add_class::<render::Backend>()?;
+    module.add_class::<render::PowerPreference>()?;
+    module.add_class::<render::DepthFormat>()?;
+    module.add_class::<render::ShadingMode>()?;
+    module.add_class::<render::SamplerConfig>()?;
+    module.add_class::<render::WrapMode>()?;
+    module.add_class::<render::FilterMode>()?;
     module.add_class::<core::camera::Projection>()?;
     module.add_class::<core::camera::ProjectionKind>()?;
+    module.add_class::<core::camera::Easing>()?;
+    module.add_class::<core::gizmo::GizmoMode>()?;
+    module.add_class::<core::halfedge::HalfEdgeMesh>()?;
     module.add_class::<core::mesh::Mesh>()?;
     module.add_class::<core::mesh::SubMesh>()?;
     module.add_class::<core::mesh::py::PyTopology>()?;
+    module.add_class::<core::mesh::UvProjection>()?;
+    module.add_class::<core::mesh::SubdivisionScheme>()?;
+    module.add_class::<core::noise::Noise>()?;
+    module.add_class::<core::noise::NoiseKind>()?;
+    module.add_class::<core::procgen::BuildingParams>()?;
+    module.add_class::<core::procgen::RoofStyle>()?;
+    module.add_class::<core::spline::Spline>()?;
+    module.add_class::<core::spline::SplineKind>()?;
     module.add_class::<core::Material>()?;
     module.add_class::<core::ConcatOrder>()?;
     module.add_class::<core::Alignment>()?;
     module.add_class::<core::Color>()?;
+    module.add_class::<core::Gradient>()?;
     module.add_class::<core::IllumModel>()?;
+    module.add_class::<core::TextureType>()?;
+    module.add_class::<core::PyTexture>()?;
+    module.add_class::<core::assets::TextureMemoryStats>()?;
+    module.add_class::<core::LightKind>()?;
+    module.add_class::<scene::Billboard>()?;
+    module.add_class::<scene::PyEntity>()?;
+    module.add_class::<scene::PyScene>()?;
+    module.add_class::<scene::Prefab>()?;
+    module.add_class::<core::particle::ParticleEmitter>()?;
+    module.add_class::<core::water::Water>()?;
+    #[cfg(feature = "physics")]
+    module.add_class::<physics::ColliderShape>()?;
+    #[cfg(feature = "osc")]
+    module.add_class::<app::OscTarget>()?;
     Ok(())
 }
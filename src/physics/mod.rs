@@ -0,0 +1,326 @@
+//! Rigid body simulation of the scene entities, backed by rapier.
+
+use crate::{
+    core::{mesh::Mesh, FxHashMap, Transform},
+    scene::{Entity, Nodes},
+};
+use glam::{Quat, Vec3};
+use rapier3d::{crossbeam::channel::Receiver, na, prelude::*};
+
+/// Shape of the collider created from the mesh of an entity.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ColliderShape {
+    /// Axis-aligned bounding box of the mesh.
+    Aabb,
+    /// Convex hull of the vertices of the mesh.
+    ConvexHull,
+}
+
+/// Rigid body simulated for an entity.
+#[derive(Clone, Copy, Debug)]
+pub struct RigidBody {
+    pub handle: RigidBodyHandle,
+}
+
+/// Colliders attached to an entity.
+#[derive(Clone, Debug)]
+pub struct Collider {
+    pub handles: Vec<ColliderHandle>,
+}
+
+/// Collision between the colliders of two entities.
+#[derive(Clone, Copy, Debug)]
+pub struct Collision {
+    pub entities: (Entity, Entity),
+    /// Whether the entities started touching, or stopped.
+    pub started: bool,
+}
+
+/// Simulation of the rigid bodies of a scene.
+///
+/// The simulation advances with a fixed time step, independent of the frame
+/// rate. Rigid bodies are simulated in world space and write the world
+/// transform of the node of their entity, keeping its scale.
+pub struct PhysicsWorld {
+    gravity: Vector<Real>,
+    parameters: IntegrationParameters,
+    pipeline: PhysicsPipeline,
+    islands: IslandManager,
+    broad_phase: DefaultBroadPhase,
+    narrow_phase: NarrowPhase,
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    impulse_joints: ImpulseJointSet,
+    multibody_joints: MultibodyJointSet,
+    ccd_solver: CCDSolver,
+    events: ChannelEventCollector,
+    collision_events: Receiver<CollisionEvent>,
+    contact_force_events: Receiver<ContactForceEvent>,
+    /// Entity of each rigid body.
+    body_entities: FxHashMap<RigidBodyHandle, Entity>,
+    /// Entity of each collider.
+    collider_entities: FxHashMap<ColliderHandle, Entity>,
+    /// Rigid body of each entity.
+    entity_bodies: FxHashMap<legion::Entity, RigidBodyHandle>,
+    /// Time not simulated yet, less than a time step.
+    accumulator: f32,
+}
+
+impl PhysicsWorld {
+    /// Duration of a simulation step in seconds.
+    pub const TIME_STEP: f32 = 1.0 / 60.0;
+    /// Maximum number of steps simulated per frame, the simulation slows
+    /// down rather than falling behind when frames take too long.
+    pub const MAX_STEPS_PER_FRAME: u32 = 4;
+
+    pub fn new() -> Self {
+        let (collision_sender, collision_events) = rapier3d::crossbeam::channel::unbounded();
+        let (contact_force_sender, contact_force_events) =
+            rapier3d::crossbeam::channel::unbounded();
+        let parameters = IntegrationParameters {
+            dt: Self::TIME_STEP,
+            ..Default::default()
+        };
+        Self {
+            gravity: vector![0.0, -9.81, 0.0],
+            parameters,
+            pipeline: PhysicsPipeline::new(),
+            islands: IslandManager::new(),
+            broad_phase: DefaultBroadPhase::new(),
+            narrow_phase: NarrowPhase::new(),
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            impulse_joints: ImpulseJointSet::new(),
+            multibody_joints: MultibodyJointSet::new(),
+            ccd_solver: CCDSolver::new(),
+            events: ChannelEventCollector::new(collision_sender, contact_force_sender),
+            collision_events,
+            contact_force_events,
+            body_entities: FxHashMap::default(),
+            collider_entities: FxHashMap::default(),
+            entity_bodies: FxHashMap::default(),
+            accumulator: 0.0,
+        }
+    }
+
+    /// Sets the gravity applied to the dynamic bodies.
+    pub fn set_gravity(&mut self, gravity: Vec3) {
+        self.gravity = vector![gravity.x, gravity.y, gravity.z];
+    }
+
+    /// Returns whether nothing is simulated.
+    pub fn is_empty(&self) -> bool {
+        self.bodies.is_empty() && self.colliders.is_empty()
+    }
+
+    /// Adds a rigid body to the entity at its world transform. The body is
+    /// dynamic with the given mass, or fixed if the mass is not positive.
+    ///
+    /// Any previous rigid body of the entity is replaced.
+    pub fn add_rigid_body(
+        &mut self,
+        entity: Entity,
+        world: &Transform,
+        mass: f32,
+    ) -> RigidBodyHandle {
+        self.remove_rigid_body(entity);
+        let builder = if mass > 0.0 {
+            RigidBodyBuilder::dynamic().additional_mass(mass)
+        } else {
+            RigidBodyBuilder::fixed()
+        };
+        let body = builder.position(to_isometry(world)).build();
+        let handle = self.bodies.insert(body);
+        self.body_entities.insert(handle, entity);
+        self.entity_bodies.insert(entity.raw, handle);
+        handle
+    }
+
+    /// Adds a collider to the entity, attached to its rigid body if it has
+    /// one, or fixed at its world transform otherwise. `offset` is the
+    /// position of the shape in the space of the entity.
+    pub fn add_collider(
+        &mut self,
+        entity: Entity,
+        world: &Transform,
+        shape: SharedShape,
+        offset: Vec3,
+    ) -> ColliderHandle {
+        let builder = ColliderBuilder::new(shape).active_events(ActiveEvents::COLLISION_EVENTS);
+        let handle = match self.entity_bodies.get(&entity.raw) {
+            Some(body) => {
+                // The mass of the body is given when it is created.
+                let collider = builder
+                    .density(0.0)
+                    .translation(vector![offset.x, offset.y, offset.z])
+                    .build();
+                self.colliders
+                    .insert_with_parent(collider, *body, &mut self.bodies)
+            }
+            None => {
+                let position = Transform {
+                    translation: world.translation + world.rotation * offset,
+                    rotation: world.rotation,
+                    scale: Vec3::ONE,
+                };
+                self.colliders
+                    .insert(builder.position(to_isometry(&position)).build())
+            }
+        };
+        self.collider_entities.insert(handle, entity);
+        handle
+    }
+
+    /// Removes the rigid body and the colliders of the entity.
+    pub fn remove_entity(&mut self, entity: Entity) {
+        self.remove_rigid_body(entity);
+        let colliders = self
+            .collider_entities
+            .iter()
+            .filter(|(_, e)| e.raw == entity.raw)
+            .map(|(handle, _)| *handle)
+            .collect::<Vec<_>>();
+        for handle in colliders {
+            self.collider_entities.remove(&handle);
+            self.colliders
+                .remove(handle, &mut self.islands, &mut self.bodies, true);
+        }
+    }
+
+    /// Removes the rigid body of the entity, its colliders are kept fixed
+    /// where they are.
+    fn remove_rigid_body(&mut self, entity: Entity) {
+        if let Some(handle) = self.entity_bodies.remove(&entity.raw) {
+            self.body_entities.remove(&handle);
+            self.bodies.remove(
+                handle,
+                &mut self.islands,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                false,
+            );
+        }
+    }
+
+    /// Advances the simulation by `dt` seconds, in as many fixed steps as
+    /// fit, and writes the transforms of the nodes of the moving bodies.
+    ///
+    /// Returns the collisions which started or stopped during the steps.
+    pub fn step(&mut self, dt: f32, nodes: &mut Nodes) -> Vec<Collision> {
+        profiling::scope!("PhysicsWorld::step");
+        self.accumulator =
+            (self.accumulator + dt).min(Self::TIME_STEP * Self::MAX_STEPS_PER_FRAME as f32);
+        while self.accumulator >= Self::TIME_STEP {
+            self.accumulator -= Self::TIME_STEP;
+            self.pipeline.step(
+                &self.gravity,
+                &self.parameters,
+                &mut self.islands,
+                &mut self.broad_phase,
+                &mut self.narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.impulse_joints,
+                &mut self.multibody_joints,
+                &mut self.ccd_solver,
+                None,
+                &(),
+                &self.events,
+            );
+        }
+
+        for handle in self.islands.active_dynamic_bodies() {
+            let (Some(body), Some(entity)) =
+                (self.bodies.get(*handle), self.body_entities.get(handle))
+            else {
+                continue;
+            };
+            let (translation, rotation) = from_isometry(body.position());
+            let world = Transform {
+                translation,
+                rotation,
+                scale: nodes.world(entity.node).scale,
+            };
+            let local = match nodes[entity.node].parent {
+                Some(parent) => nodes.inverse_world(parent) * world,
+                None => world,
+            };
+            nodes[entity.node].set_transform(local);
+        }
+
+        // Contact forces are not reported.
+        while self.contact_force_events.try_recv().is_ok() {}
+        let mut collisions = Vec::new();
+        while let Ok(event) = self.collision_events.try_recv() {
+            let (Some(a), Some(b)) = (
+                self.collider_entities.get(&event.collider1()),
+                self.collider_entities.get(&event.collider2()),
+            ) else {
+                continue;
+            };
+            collisions.push(Collision {
+                entities: (*a, *b),
+                started: event.started(),
+            });
+        }
+        collisions
+    }
+}
+
+impl Default for PhysicsWorld {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Creates the shape of a collider enclosing the mesh scaled by `scale`.
+///
+/// Returns the shape and its position in the space of the mesh, or `None`
+/// if the mesh has no vertices.
+pub fn mesh_collider_shape(
+    shape: ColliderShape,
+    mesh: &Mesh,
+    scale: Vec3,
+) -> Option<(SharedShape, Vec3)> {
+    match shape {
+        ColliderShape::Aabb => {
+            let (min, max) = mesh.bounds()?;
+            let half_extents = (max - min) * scale.abs() * 0.5;
+            Some((
+                SharedShape::cuboid(half_extents.x, half_extents.y, half_extents.z),
+                (min + max) * scale * 0.5,
+            ))
+        }
+        ColliderShape::ConvexHull => {
+            let points = mesh
+                .positions()?
+                .iter()
+                .map(|p| {
+                    let p = Vec3::from(*p) * scale;
+                    point![p.x, p.y, p.z]
+                })
+                .collect::<Vec<_>>();
+            Some((SharedShape::convex_hull(&points)?, Vec3::ZERO))
+        }
+    }
+}
+
+fn to_isometry(transform: &Transform) -> Isometry<Real> {
+    let t = transform.translation;
+    let r = transform.rotation;
+    Isometry::from_parts(
+        Translation::new(t.x, t.y, t.z),
+        Rotation::from_quaternion(na::Quaternion::new(r.w, r.x, r.y, r.z)),
+    )
+}
+
+fn from_isometry(isometry: &Isometry<Real>) -> (Vec3, Quat) {
+    let t = isometry.translation.vector;
+    let r = isometry.rotation;
+    (
+        Vec3::new(t.x, t.y, t.z),
+        Quat::from_xyzw(r.i, r.j, r.k, r.w),
+    )
+}
@@ -0,0 +1,161 @@
+use crate::core::{sprite::AtlasRegion, FxHashMap, SmlString};
+use glam::Vec2;
+use std::path::Path;
+
+/// Texture atlas packing the images of all sprites into a single texture.
+///
+/// Images are packed row by row (shelf packing) on the CPU side, and the whole
+/// atlas is uploaded to the GPU when it changes. Images are never removed from
+/// the atlas.
+pub struct TextureAtlas {
+    /// The CPU copy of the atlas.
+    image: image::RgbaImage,
+    /// Regions of the images already in the atlas, keyed by file path.
+    regions: FxHashMap<SmlString, AtlasRegion>,
+    /// Horizontal position of the next image in the current shelf.
+    cursor_x: u32,
+    /// Top of the current shelf.
+    shelf_y: u32,
+    /// Height of the current shelf (height of the tallest image in it).
+    shelf_height: u32,
+    /// Whether the atlas needs to be uploaded to the GPU.
+    dirty: bool,
+    /// The atlas texture.
+    pub texture: wgpu::Texture,
+    /// The view of the atlas texture.
+    pub view: wgpu::TextureView,
+}
+
+impl TextureAtlas {
+    /// Size of the atlas texture in pixels.
+    pub const SIZE: u32 = 2048;
+    /// Padding between images to avoid bleeding when filtering.
+    const PADDING: u32 = 1;
+
+    /// Creates a new empty atlas.
+    pub fn new(device: &wgpu::Device) -> Self {
+        let (texture, view) = Self::create_texture(device);
+        Self {
+            image: image::RgbaImage::new(Self::SIZE, Self::SIZE),
+            regions: FxHashMap::default(),
+            cursor_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+            dirty: false,
+            texture,
+            view,
+        }
+    }
+
+    /// Recreates the atlas texture on a new device, the images are uploaded
+    /// again from the CPU copy of the atlas.
+    pub fn restore(&mut self, device: &wgpu::Device) {
+        let (texture, view) = Self::create_texture(device);
+        self.texture = texture;
+        self.view = view;
+        self.dirty = true;
+    }
+
+    fn create_texture(device: &wgpu::Device) -> (wgpu::Texture, wgpu::TextureView) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("sprite_atlas_texture"),
+            size: wgpu::Extent3d {
+                width: Self::SIZE,
+                height: Self::SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        (texture, view)
+    }
+
+    /// Loads the image at the given path into the atlas and returns its
+    /// region. Images already in the atlas are not loaded again.
+    ///
+    /// Returns `None` if the image could not be loaded or if there is no
+    /// space left in the atlas.
+    pub fn load_from_file<P: AsRef<Path>>(&mut self, path: P) -> Option<AtlasRegion> {
+        let key = SmlString::from(path.as_ref().to_string_lossy().as_ref());
+        if let Some(region) = self.regions.get(&key) {
+            return Some(*region);
+        }
+        let img = match image::open(path.as_ref()) {
+            Ok(img) => img.to_rgba8(),
+            Err(err) => {
+                log::error!("Failed to load sprite image {:?}: {}", path.as_ref(), err);
+                return None;
+            }
+        };
+        let region = self.insert(&img)?;
+        self.regions.insert(key, region);
+        Some(region)
+    }
+
+    /// Inserts an image into the atlas and returns its region.
+    pub fn insert(&mut self, img: &image::RgbaImage) -> Option<AtlasRegion> {
+        let (width, height) = img.dimensions();
+        let (x, y) = self.allocate(width, height)?;
+        image::imageops::replace(&mut self.image, img, x as i64, y as i64);
+        self.dirty = true;
+        let size = Self::SIZE as f32;
+        Some(AtlasRegion {
+            min: Vec2::new(x as f32 / size, y as f32 / size),
+            max: Vec2::new((x + width) as f32 / size, (y + height) as f32 / size),
+        })
+    }
+
+    /// Finds space for an image of the given size, starting a new shelf if
+    /// the current one is full.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if width > Self::SIZE || height > Self::SIZE {
+            log::error!("Sprite image {}x{} exceeds the atlas size", width, height);
+            return None;
+        }
+        if self.cursor_x + width > Self::SIZE {
+            self.shelf_y += self.shelf_height + Self::PADDING;
+            self.cursor_x = 0;
+            self.shelf_height = 0;
+        }
+        if self.shelf_y + height > Self::SIZE {
+            log::error!("Sprite atlas is full!");
+            return None;
+        }
+        let pos = (self.cursor_x, self.shelf_y);
+        self.cursor_x += width + Self::PADDING;
+        self.shelf_height = self.shelf_height.max(height);
+        Some(pos)
+    }
+
+    /// Uploads the atlas to the GPU if it changed since the last upload.
+    pub fn upload(&mut self, queue: &wgpu::Queue) {
+        if !self.dirty {
+            return;
+        }
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * Self::SIZE),
+                rows_per_image: Some(Self::SIZE),
+            },
+            wgpu::Extent3d {
+                width: Self::SIZE,
+                height: Self::SIZE,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.dirty = false;
+    }
+}
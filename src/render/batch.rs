@@ -0,0 +1,77 @@
+//! Static batching: instances merged into a single mesh to reduce the number
+//! of draws.
+
+use crate::{
+    core::mesh::{Mesh, MeshBundle},
+    render::Renderer,
+    scene::{Entity, NodeIdx},
+};
+use glam::Mat4;
+
+/// Instances merged into a single mesh with their world transforms applied,
+/// drawn by a single entity instead of them.
+#[derive(Clone, Debug)]
+pub struct StaticBatch {
+    /// Entity drawing the merged mesh.
+    pub entity: Entity,
+    /// The merged mesh.
+    pub mesh: MeshBundle,
+    /// Instances merged into the batch, no longer drawn on their own.
+    pub instances: Vec<(Entity, MeshBundle)>,
+}
+
+impl Renderer {
+    /// Merges the meshes of the instances, transformed by their world
+    /// matrices, into a single mesh and stops drawing the instances.
+    ///
+    /// Returns the merged mesh, to be drawn by an entity at the root of the
+    /// scene, or `None` if the meshes can't be merged.
+    pub fn bake_static_batch(
+        &mut self,
+        instances: &[(MeshBundle, NodeIdx, Mat4)],
+    ) -> Option<MeshBundle> {
+        profiling::scope!("Renderer::bake_static_batch");
+        let meshes = instances
+            .iter()
+            .map(|(bundle, _, model)| {
+                let mut mesh = self.mesh_source(bundle.mesh)?.clone();
+                mesh.transform(*model);
+                Some(mesh)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        let mut merged = Mesh::merge(&meshes)?;
+        merged.path = None;
+        let bundle = self.upload_mesh(&merged);
+        for (mesh, node, _) in instances {
+            self.remove_instancing(*mesh, *node);
+        }
+        Some(bundle)
+    }
+
+    /// Registers a batch baked with `bake_static_batch` and the entity
+    /// drawing it.
+    pub fn add_static_batch(&mut self, batch: StaticBatch) {
+        self.add_instancing(batch.mesh, &[batch.entity.node]);
+        self.static_batches.push(batch);
+    }
+
+    /// Releases the merged meshes of the batches and returns the batches,
+    /// whose instances must be drawn again with `add_instancing` if they
+    /// still exist.
+    pub fn take_static_batches(&mut self) -> Vec<StaticBatch> {
+        let batches = std::mem::take(&mut self.static_batches);
+        for batch in &batches {
+            self.instancing.remove(&batch.mesh);
+            self.mesh_sources.remove(&batch.mesh.mesh);
+            self.mesh_bvhs.remove(&batch.mesh.mesh);
+            self.meshes.remove(batch.mesh.mesh);
+        }
+        self.draws_generation += 1;
+        batches
+    }
+
+    /// Returns the batches of static instances.
+    pub fn static_batches(&self) -> &[StaticBatch] {
+        &self.static_batches
+    }
+}
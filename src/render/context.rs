@@ -1,5 +1,8 @@
 use crate::core::ArrVec;
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 use wgpu::DeviceType;
 
 /// Aggregates all the objects needed to use the GPU.
@@ -18,8 +21,166 @@ pub struct GpuContext {
     pub features: wgpu::Features,
     /// Limits of the device.
     pub limits: wgpu::Limits,
-    /// Whether the adapter supports constant sized binding arrays.
+    /// Whether the device supports push constants, emulated with uniform
+    /// buffers otherwise.
+    pub push_constants: bool,
+    /// Whether the device supports binding arrays of textures, and enough
+    /// bind groups for the main pass to bind them. Otherwise, only a few
+    /// textures are bound one by one with the materials of each mesh.
+    pub binding_arrays: bool,
+    /// Whether the adapter only supports constant sized binding arrays.
     pub constant_sized_binding_array: bool,
+    /// Set once the device is lost, after which the context must be
+    /// recreated.
+    lost: Arc<AtomicBool>,
+}
+
+/// Graphics API used to render.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Backend {
+    Vulkan,
+    Metal,
+    Dx12,
+    /// OpenGL or OpenGL ES, the most widely available but the slowest.
+    Gl,
+}
+
+impl Backend {
+    fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+
+    /// Parses the name of a backend, case-insensitively.
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "vulkan" | "vk" => Some(Backend::Vulkan),
+            "metal" | "mtl" => Some(Backend::Metal),
+            "dx12" | "d3d12" => Some(Backend::Dx12),
+            "gl" | "gles" | "opengl" => Some(Backend::Gl),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of adapter preferred when several are available.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Default)]
+pub enum PowerPreference {
+    /// Prefers integrated GPUs, e.g. to save the battery of a laptop.
+    LowPower,
+    /// Prefers discrete GPUs.
+    #[default]
+    HighPerformance,
+}
+
+/// Selection of the adapter the GPU context is created on.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AdapterOptions {
+    /// Graphics API to use, Vulkan, Metal or DirectX 12 if `None`.
+    pub backend: Option<Backend>,
+    /// Kind of adapter preferred.
+    pub power_preference: PowerPreference,
+    /// Whether to only use software adapters rendering on the CPU.
+    pub force_fallback_adapter: bool,
+}
+
+impl AdapterOptions {
+    /// Environment variable overriding the backend, e.g. `vulkan` or `gl`.
+    pub const BACKEND_VAR: &'static str = "BKFW_BACKEND";
+    /// Environment variable overriding the power preference, `low` or
+    /// `high`.
+    pub const POWER_PREFERENCE_VAR: &'static str = "BKFW_POWER_PREFERENCE";
+    /// Environment variable forcing the fallback adapter when set to `1`.
+    pub const FORCE_FALLBACK_ADAPTER_VAR: &'static str = "BKFW_FORCE_FALLBACK_ADAPTER";
+
+    /// Returns the options overridden by the environment variables set, so
+    /// that the adapter can be changed without modifying the application.
+    pub fn overridden_by_env(mut self) -> Self {
+        if let Ok(name) = std::env::var(Self::BACKEND_VAR) {
+            match Backend::from_name(&name) {
+                Some(backend) => self.backend = Some(backend),
+                None => log::warn!("Unknown backend '{}' in {}", name, Self::BACKEND_VAR),
+            }
+        }
+        if let Ok(name) = std::env::var(Self::POWER_PREFERENCE_VAR) {
+            match name.trim().to_lowercase().as_str() {
+                "low" | "low_power" => self.power_preference = PowerPreference::LowPower,
+                "high" | "high_performance" => {
+                    self.power_preference = PowerPreference::HighPerformance
+                }
+                _ => log::warn!(
+                    "Unknown power preference '{}' in {}",
+                    name,
+                    Self::POWER_PREFERENCE_VAR
+                ),
+            }
+        }
+        if let Ok(value) = std::env::var(Self::FORCE_FALLBACK_ADAPTER_VAR) {
+            self.force_fallback_adapter = matches!(value.trim(), "1" | "true" | "yes");
+        }
+        self
+    }
+
+    /// Backends the instance is created with.
+    fn backends(&self) -> wgpu::Backends {
+        self.backend.map_or(
+            wgpu::Backends::VULKAN | wgpu::Backends::METAL | wgpu::Backends::DX12,
+            Backend::backends,
+        )
+    }
+
+    /// Rank of an adapter of the given type, the lowest is used.
+    fn rank(&self, device_type: DeviceType) -> u32 {
+        match (device_type, self.power_preference) {
+            (DeviceType::DiscreteGpu, PowerPreference::HighPerformance) => 0,
+            (DeviceType::IntegratedGpu, PowerPreference::HighPerformance) => 1,
+            (DeviceType::IntegratedGpu, PowerPreference::LowPower) => 0,
+            (DeviceType::DiscreteGpu, PowerPreference::LowPower) => 1,
+            (DeviceType::VirtualGpu, _) => 2,
+            (DeviceType::Cpu, _) => 3,
+            (DeviceType::Other, _) => 4,
+        }
+    }
+}
+
+/// Description of the adapter the application renders with, to be included
+/// in bug reports.
+#[pyo3::pyclass]
+#[derive(Clone, Debug)]
+pub struct AdapterInfo {
+    /// Name of the adapter.
+    #[pyo3(get)]
+    pub name: String,
+    /// Graphics API used, e.g. `Vulkan`.
+    #[pyo3(get)]
+    pub backend: String,
+    /// Kind of adapter, e.g. `DiscreteGpu`.
+    #[pyo3(get)]
+    pub device_type: String,
+    /// PCI id of the vendor of the adapter.
+    #[pyo3(get)]
+    pub vendor: u32,
+    /// PCI id of the adapter.
+    #[pyo3(get)]
+    pub device: u32,
+    /// Name of the driver.
+    #[pyo3(get)]
+    pub driver: String,
+    /// Version of the driver.
+    #[pyo3(get)]
+    pub driver_info: String,
+    /// Features enabled on the device.
+    #[pyo3(get)]
+    pub features: Vec<String>,
+    /// Limits of the device, by name.
+    #[pyo3(get)]
+    pub limits: Vec<(String, u64)>,
 }
 
 /// Potential adapter to use.
@@ -31,10 +192,10 @@ struct PotentialAdapter {
 }
 
 impl GpuContext {
-    /// Creates a new GPU context.
-    pub fn new(desired_features: Option<wgpu::Features>) -> Self {
+    /// Creates a new GPU context on the adapter selected by the options.
+    pub fn new(desired_features: Option<wgpu::Features>, options: &AdapterOptions) -> Self {
         profiling::scope!("GPUContext::new");
-        let backends = wgpu::Backends::VULKAN | wgpu::Backends::METAL | wgpu::Backends::DX12;
+        let backends = options.backends();
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
             backends,
@@ -49,25 +210,29 @@ impl GpuContext {
             let features = adapter.features();
             let info = adapter.get_info();
             log::info!("{:?} Adapter: {:#?}", backends, info);
-            if features.contains(wgpu::Features::PUSH_CONSTANTS) {
-                adapters.push(PotentialAdapter {
-                    adapter,
-                    info,
-                    limits,
-                    features,
-                });
+            let is_fallback = info.device_type == DeviceType::Cpu;
+            if options.force_fallback_adapter && !is_fallback {
+                continue;
             }
+            adapters.push(PotentialAdapter {
+                adapter,
+                info,
+                limits,
+                features,
+            });
         }
-        adapters.sort_by_key(|adapter| match adapter.info.device_type {
-            DeviceType::DiscreteGpu => 0,
-            DeviceType::IntegratedGpu => 1,
-            DeviceType::VirtualGpu => 2,
-            DeviceType::Cpu => 3,
-            DeviceType::Other => 4,
+        // Adapters without push constants or binding arrays are only used if
+        // there is no other adapter of the same kind.
+        adapters.sort_by_key(|adapter| {
+            let fallbacks = !adapter.features.contains(wgpu::Features::PUSH_CONSTANTS) as u32
+                + !adapter
+                    .features
+                    .contains(wgpu::Features::TEXTURE_BINDING_ARRAY) as u32;
+            (options.rank(adapter.info.device_type), fallbacks)
         });
 
         if adapters.is_empty() {
-            panic!("No adapters found");
+            panic!("No adapters found with {:?}", options);
         }
 
         let adapter = adapters.remove(0);
@@ -81,7 +246,25 @@ impl GpuContext {
             "Max samplers: {}",
             adapter.limits.max_samplers_per_shader_stage
         );
-        let constant_sized_binding_array = !features.contains(wgpu::Features::BUFFER_BINDING_ARRAY);
+        let push_constants = features.contains(wgpu::Features::PUSH_CONSTANTS);
+        // The main pass binds the textures and the shadow maps in groups of
+        // their own with binding arrays, followed by the emulated constants.
+        // Otherwise it binds at most 3 groups, see `SlotBindGroups`.
+        let bind_groups = 6 + !push_constants as u32;
+        let binding_arrays = features.contains(wgpu::Features::TEXTURE_BINDING_ARRAY)
+            && adapter.limits.max_bind_groups >= bind_groups;
+        if !push_constants {
+            log::warn!("Push constants are not supported, use uniform buffers instead.");
+        }
+        if !binding_arrays {
+            log::warn!(
+                "Binding arrays or {} bind groups are not supported, only a few textures can be \
+                 bound per mesh.",
+                bind_groups
+            );
+        }
+        let constant_sized_binding_array =
+            binding_arrays && !features.contains(wgpu::Features::BUFFER_BINDING_ARRAY);
         log::info!(
             "Constant sized binding array: {}",
             constant_sized_binding_array
@@ -133,6 +316,15 @@ impl GpuContext {
                 .expect("Failed to create device")
         });
 
+        let lost = Arc::new(AtomicBool::new(false));
+        {
+            let lost = lost.clone();
+            device.set_device_lost_callback(move |reason, message| {
+                log::error!("GPU device lost ({:?}): {}", reason, message);
+                lost.store(true, Ordering::Release);
+            });
+        }
+
         GpuContext {
             instance: Arc::new(instance),
             adapter: Arc::new(adapter.adapter),
@@ -140,15 +332,76 @@ impl GpuContext {
             queue: Arc::new(queue),
             features,
             limits,
+            push_constants,
+            binding_arrays,
             constant_sized_binding_array,
+            lost,
         }
     }
+
+    /// Returns the description of the adapter and the limits of the device.
+    pub fn adapter_info(&self) -> AdapterInfo {
+        let info = self.adapter.get_info();
+        let limits = &self.limits;
+        AdapterInfo {
+            name: info.name,
+            backend: format!("{:?}", info.backend),
+            device_type: format!("{:?}", info.device_type),
+            vendor: info.vendor,
+            device: info.device,
+            driver: info.driver,
+            driver_info: info.driver_info,
+            features: self
+                .features
+                .iter()
+                .map(|feature| format!("{:?}", feature))
+                .collect(),
+            limits: [
+                (
+                    "max_texture_dimension_2d",
+                    limits.max_texture_dimension_2d as u64,
+                ),
+                ("max_bind_groups", limits.max_bind_groups as u64),
+                (
+                    "max_sampled_textures_per_shader_stage",
+                    limits.max_sampled_textures_per_shader_stage as u64,
+                ),
+                (
+                    "max_samplers_per_shader_stage",
+                    limits.max_samplers_per_shader_stage as u64,
+                ),
+                (
+                    "max_storage_buffer_binding_size",
+                    limits.max_storage_buffer_binding_size as u64,
+                ),
+                ("max_buffer_size", limits.max_buffer_size),
+                (
+                    "max_push_constant_size",
+                    limits.max_push_constant_size as u64,
+                ),
+            ]
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect(),
+        }
+    }
+
+    /// Returns true if the device is lost and the context must be recreated.
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    /// Marks the device as lost, e.g. after it ran out of memory, so that the
+    /// context is recreated.
+    pub fn mark_lost(&self) {
+        self.lost.store(true, Ordering::Release);
+    }
 }
 
 #[pyo3::pymethods]
 impl GpuContext {
     #[new]
     pub fn new_py() -> Self {
-        Self::new(None)
+        Self::new(None, &AdapterOptions::default().overridden_by_env())
     }
 }
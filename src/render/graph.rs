@@ -0,0 +1,316 @@
+//! Render graph ordering the passes of a frame from the resources they read
+//! and write.
+//!
+//! Each pass declares the resources it reads and writes by name. Passes
+//! writing the same resource run in the order they were added, and a pass
+//! reading a resource runs after the passes writing it added before it, or
+//! after all of them if it was added first. Passes not contributing to the
+//! resources marked as outputs are skipped. Transient textures are only
+//! created for the passes kept, and reused across frames from a
+//! [`TransientTextures`] pool.
+
+use crate::core::FxHashMap;
+
+/// Name of a texture or a buffer read or written by the passes of a
+/// [`RenderGraph`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GraphResource(pub &'static str);
+
+/// Texture created by the graph for the frame, see
+/// [`RenderGraph::add_transient`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TransientTexture {
+    pub size: wgpu::Extent3d,
+    pub format: wgpu::TextureFormat,
+    pub usage: wgpu::TextureUsages,
+}
+
+/// Pool of the transient textures of a render graph, kept between frames
+/// so that the textures of the same description are reused.
+#[derive(Default)]
+pub struct TransientTextures {
+    free: FxHashMap<TransientTexture, Vec<wgpu::Texture>>,
+}
+
+impl TransientTextures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a texture of the given description, created if none is free.
+    fn acquire(&mut self, device: &wgpu::Device, desc: &TransientTexture) -> wgpu::Texture {
+        if let Some(texture) = self.free.get_mut(desc).and_then(|free| free.pop()) {
+            return texture;
+        }
+        device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render_graph_transient_texture"),
+            size: desc.size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: desc.format,
+            usage: desc.usage,
+            view_formats: &[],
+        })
+    }
+
+    /// Gives back the textures acquired for a frame. Textures of a
+    /// description not used by the frame are dropped.
+    fn release(&mut self, used: Vec<(TransientTexture, wgpu::Texture)>) {
+        self.free
+            .retain(|desc, _| used.iter().any(|(d, _)| d == desc));
+        for (desc, texture) in used {
+            self.free.entry(desc).or_default().push(texture);
+        }
+    }
+}
+
+/// Transient textures of the frame, given to the passes.
+pub struct GraphResources {
+    views: FxHashMap<GraphResource, wgpu::TextureView>,
+}
+
+impl GraphResources {
+    /// Returns the view of the transient texture, `None` if the resource is
+    /// not a transient texture.
+    pub fn view(&self, resource: GraphResource) -> Option<&wgpu::TextureView> {
+        self.views.get(&resource)
+    }
+}
+
+type PassFn<'a, C> = Box<dyn FnOnce(&mut C, &mut wgpu::CommandEncoder, &GraphResources) + 'a>;
+
+/// A pass of the graph.
+struct GraphPass<'a, C> {
+    name: &'static str,
+    reads: Vec<GraphResource>,
+    writes: Vec<GraphResource>,
+    run: PassFn<'a, C>,
+}
+
+/// Passes of a frame, recorded in the order of their dependencies.
+///
+/// The passes get the context `C` mutably when they run, typically the
+/// render pass owning the pipelines, so that the graph can hold all of them
+/// at once.
+pub struct RenderGraph<'a, C> {
+    passes: Vec<GraphPass<'a, C>>,
+    transients: FxHashMap<GraphResource, TransientTexture>,
+    outputs: Vec<GraphResource>,
+}
+
+impl<'a, C> Default for RenderGraph<'a, C> {
+    fn default() -> Self {
+        Self {
+            passes: Vec::new(),
+            transients: FxHashMap::default(),
+            outputs: Vec::new(),
+        }
+    }
+}
+
+impl<'a, C> RenderGraph<'a, C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a pass reading and writing the given resources.
+    pub fn add_pass(
+        &mut self,
+        name: &'static str,
+        reads: &[GraphResource],
+        writes: &[GraphResource],
+        run: impl FnOnce(&mut C, &mut wgpu::CommandEncoder, &GraphResources) + 'a,
+    ) {
+        self.passes.push(GraphPass {
+            name,
+            reads: reads.to_vec(),
+            writes: writes.to_vec(),
+            run: Box::new(run),
+        });
+    }
+
+    /// Declares a texture created by the graph for the frame, available to
+    /// the passes through [`GraphResources::view`].
+    pub fn add_transient(&mut self, resource: GraphResource, desc: TransientTexture) {
+        self.transients.insert(resource, desc);
+    }
+
+    /// Marks a resource as a result of the frame, e.g. the render target, so
+    /// that the passes writing it are kept.
+    pub fn mark_output(&mut self, resource: GraphResource) {
+        if !self.outputs.contains(&resource) {
+            self.outputs.push(resource);
+        }
+    }
+
+    /// Returns the names of the passes kept, in the order they run.
+    pub fn pass_names(&self) -> Result<Vec<&'static str>, String> {
+        Ok(self
+            .order()?
+            .into_iter()
+            .map(|i| self.passes[i].name)
+            .collect())
+    }
+
+    /// Returns the indices of the passes kept, in the order they run, or an
+    /// error if their dependencies form a cycle.
+    fn order(&self) -> Result<Vec<usize>, String> {
+        let n = self.passes.len();
+        let mut writers: FxHashMap<GraphResource, Vec<usize>> = FxHashMap::default();
+        for (i, pass) in self.passes.iter().enumerate() {
+            for resource in &pass.writes {
+                writers.entry(*resource).or_default().push(i);
+            }
+        }
+
+        // Dependencies of each pass: the writers of the resources it writes
+        // added before it, and the writers of the resources it only reads
+        // added before it, or all of them if they are all added after it.
+        let mut dependencies = vec![Vec::new(); n];
+        for (i, pass) in self.passes.iter().enumerate() {
+            for resource in &pass.writes {
+                let previous = writers[resource].iter().take_while(|w| **w < i);
+                dependencies[i].extend(previous);
+            }
+            for resource in pass.reads.iter().filter(|r| !pass.writes.contains(r)) {
+                if let Some(ws) = writers.get(resource) {
+                    let previous = ws.iter().copied().filter(|w| *w < i).collect::<Vec<_>>();
+                    if previous.is_empty() {
+                        dependencies[i].extend(ws.iter().copied());
+                    } else {
+                        dependencies[i].extend(previous);
+                    }
+                }
+            }
+        }
+
+        // Passes contributing to the outputs.
+        let mut kept = vec![false; n];
+        let mut stack = self
+            .outputs
+            .iter()
+            .filter_map(|output| writers.get(output))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        while let Some(i) = stack.pop() {
+            if !std::mem::replace(&mut kept[i], true) {
+                stack.extend(dependencies[i].iter().copied());
+            }
+        }
+
+        // Depth-first topological sort, visiting the passes in the order
+        // they were added.
+        const UNVISITED: u8 = 0;
+        const VISITING: u8 = 1;
+        const DONE: u8 = 2;
+        fn visit(
+            i: usize,
+            dependencies: &[Vec<usize>],
+            marks: &mut [u8],
+            order: &mut Vec<usize>,
+        ) -> Result<(), usize> {
+            match marks[i] {
+                DONE => return Ok(()),
+                VISITING => return Err(i),
+                _ => {}
+            }
+            marks[i] = VISITING;
+            for d in &dependencies[i] {
+                visit(*d, dependencies, marks, order)?;
+            }
+            marks[i] = DONE;
+            order.push(i);
+            Ok(())
+        }
+        let mut marks = vec![UNVISITED; n];
+        let mut order = Vec::with_capacity(n);
+        for i in (0..n).filter(|i| kept[*i]) {
+            visit(i, &dependencies, &mut marks, &mut order).map_err(|i| {
+                format!(
+                    "The passes of the render graph depend on each other around {}",
+                    self.passes[i].name
+                )
+            })?;
+        }
+        Ok(order)
+    }
+
+    /// Records the passes kept into the encoder in the order of their
+    /// dependencies, creating the transient textures they use.
+    pub fn execute(
+        self,
+        context: &mut C,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        pool: &mut TransientTextures,
+    ) -> Result<(), String> {
+        profiling::scope!("RenderGraph::execute");
+        let order = self.order()?;
+        let Self {
+            passes, transients, ..
+        } = self;
+
+        let mut used = Vec::new();
+        let mut views = FxHashMap::default();
+        for (resource, desc) in transients {
+            let is_used = order.iter().any(|i| {
+                let pass = &passes[*i];
+                pass.reads.contains(&resource) || pass.writes.contains(&resource)
+            });
+            if is_used {
+                let texture = pool.acquire(device, &desc);
+                views.insert(resource, texture.create_view(&Default::default()));
+                used.push((desc, texture));
+            }
+        }
+        let resources = GraphResources { views };
+
+        let mut passes = passes.into_iter().map(Some).collect::<Vec<_>>();
+        for i in order {
+            let pass = passes[i].take().unwrap();
+            (pass.run)(context, encoder, &resources);
+        }
+        pool.release(used);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGET: GraphResource = GraphResource("target");
+    const DEPTH: GraphResource = GraphResource("depth");
+    const BLOOM: GraphResource = GraphResource("bloom");
+    const QUERIES: GraphResource = GraphResource("queries");
+
+    fn noop(_: &mut (), _: &mut wgpu::CommandEncoder, _: &GraphResources) {}
+
+    #[test]
+    fn passes_are_ordered_by_their_resources() {
+        let mut graph = RenderGraph::<()>::new();
+        // Added before the pass writing the depth it reads.
+        graph.add_pass("occlusion", &[DEPTH], &[QUERIES], noop);
+        graph.add_pass("main", &[], &[TARGET, DEPTH], noop);
+        graph.add_pass("bloom", &[TARGET], &[BLOOM], noop);
+        graph.add_pass("composite", &[BLOOM], &[TARGET], noop);
+        graph.add_pass("unused", &[DEPTH], &[GraphResource("unused")], noop);
+        graph.mark_output(TARGET);
+        graph.mark_output(QUERIES);
+        assert_eq!(
+            graph.pass_names().unwrap(),
+            ["main", "occlusion", "bloom", "composite"]
+        );
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let mut graph = RenderGraph::<()>::new();
+        graph.add_pass("a", &[BLOOM], &[TARGET], noop);
+        graph.add_pass("b", &[TARGET], &[BLOOM], noop);
+        graph.mark_output(TARGET);
+        assert!(graph.pass_names().is_err());
+    }
+}
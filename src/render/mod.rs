@@ -3,40 +3,63 @@ use crate::{
     core::{Color, FxHasher},
 };
 use crossbeam_channel::Receiver;
-use std::{collections::hash_map::Entry, hash::Hasher, path::Path, sync::Arc};
+use legion::IntoQuery;
+use std::{
+    collections::hash_map::Entry,
+    hash::Hasher,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use wgpu::util::DeviceExt;
 
+mod atlas;
+mod batch;
 mod context;
+mod graph;
+pub use graph::*;
+mod pathtrace;
+pub use pathtrace::*;
 mod pipeline;
 pub use pipeline::*;
+mod recorder;
+pub use recorder::*;
 pub mod rpass;
 mod sampler;
+mod shader;
+mod staging;
 pub mod surface;
 mod target;
 pub mod util;
 
 pub use sampler::*;
+pub use shader::*;
+pub use staging::*;
 
 pub use target::*;
 
 use crate::{
     app::command::{Command, CommandReceiver},
     core::{
-        assets::{GpuMeshAssets, Handle, MaterialBundleAssets, TextureAssets, TextureBundleAssets},
-        mesh::{AestheticBundle, Mesh, MeshBundle},
-        FxHashMap, GpuMaterial, Material, MaterialBundle, SmlString, Texture, TextureBundle,
-        TextureType,
+        assets::{
+            storage::MeshBufferStats, DecodedImage, FileWatcher, GpuMeshAssets, Handle,
+            MaterialBundleAssets, RefCounts, TextureAssets, TextureBudget, TextureBundleAssets,
+            TextureMemoryStats,
+        },
+        bvh::TriangleBvh,
+        mesh::{AestheticBundle, GpuMesh, Mesh, MeshBundle},
+        sprite::AtlasRegion,
+        FxHashMap, FxHashSet, GpuMaterial, Material, MaterialBundle, SmlString, Texture,
+        TextureBundle, TextureType,
     },
     render::rpass::{
-        texture_bundle_bind_group_layout, BlinnPhongRenderPass, LightsBindGroup, RenderingPass,
+        material_slots_bind_group_layout, textures_bind_group_layout, BlinnPhongRenderPass,
+        EnvironmentMap, LightsBindGroup, RenderingPass, WaterRenderPass,
     },
     scene::{NodeIdx, Scene},
 };
+pub use atlas::*;
+pub use batch::*;
 pub use context::*;
-// TODO: render bundles enables us to create N uniform buffers and dispatch N
-// render calls, which is a bit slow if we iterate over all of them every frame,
-// but render bundles can speed this up.
-
 // Currently, we only support instancing for meshes (not materials).
 
 /// Shading mode.
@@ -51,19 +74,40 @@ pub enum ShadingMode {
     BlinnPhong,
 }
 
+#[derive(Debug, Clone)]
 pub struct RenderParams {
     /// Shading mode.
     pub mode: ShadingMode,
     /// Whether to enable back face culling.
     pub enable_back_face_culling: bool,
-    /// Whether to enable occlusion culling. TODO: implement occlusion culling.
+    /// Whether to skip the instances occluded by the geometry drawn in the
+    /// previous frames.
     pub enable_occlusion_culling: bool,
+    /// Whether to skip the instances outside the view on the GPU, when the
+    /// device supports indirect draws.
+    pub enable_gpu_culling: bool,
     /// Whether to draw wireframe.
     pub enable_wireframe: bool,
+    /// Whether to write the depth of the meshes before shading them, so that
+    /// each pixel is shaded only once. Translucent and alpha-tested meshes
+    /// are left out of the pre-pass.
+    pub enable_depth_prepass: bool,
     /// Whether to enable shadow.
     pub enable_shadows: bool,
     /// Whether to enable lighing.
     pub enable_lighting: bool,
+    /// Whether the frames are presented in sync with the display refresh
+    /// rate.
+    pub vsync: bool,
+    /// Maximum number of frames rendered per second, uncapped if `None`.
+    pub target_fps: Option<f32>,
+    /// Maximum number of bytes of the textures kept on the GPU, unlimited if
+    /// `None`. The least recently used textures of the entities which are
+    /// not visible are evicted beyond it.
+    pub texture_budget: Option<u64>,
+    /// Whether to draw the lights, the frustums of the cameras other than
+    /// the main one and the volumes covered by the shadow maps.
+    pub show_debug_gizmos: bool,
     /// Whether to write shadow maps once.
     #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
     pub write_shadow_maps: bool,
@@ -75,19 +119,188 @@ impl RenderParams {
             mode: ShadingMode::BlinnPhong,
             enable_back_face_culling: true,
             enable_occlusion_culling: false,
+            enable_gpu_culling: false,
             enable_wireframe: false,
+            enable_depth_prepass: false,
             enable_shadows: false,
             enable_lighting: true,
+            vsync: true,
+            target_fps: None,
+            texture_budget: None,
+            show_debug_gizmos: false,
             #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
             write_shadow_maps: false,
         }
     }
 
+    /// Lowest frame rate the frames can be capped to, longer frame times
+    /// not being representable.
+    pub const MIN_TARGET_FPS: f32 = 0.01;
+
+    /// Returns the cap of the frame rate, `None` if `fps` is not positive,
+    /// raised to `MIN_TARGET_FPS` if below.
+    pub fn clamp_target_fps(fps: Option<f32>) -> Option<f32> {
+        fps.filter(|fps| *fps > 0.0)
+            .map(|fps| fps.max(Self::MIN_TARGET_FPS))
+    }
+
     /// Whether to cast shadows.
     #[inline]
     pub const fn casting_shadows(&self) -> bool {
         self.enable_shadows && !self.enable_wireframe && self.enable_lighting
     }
+
+    /// Whether to evaluate the depth pre-pass.
+    #[inline]
+    pub const fn depth_prepass(&self) -> bool {
+        self.enable_depth_prepass && !self.enable_wireframe
+    }
+}
+
+/// Format of the depth buffer of the rendering passes.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Default)]
+pub enum DepthFormat {
+    /// At least 24 bits of depth, fixed or floating point depending on the
+    /// platform.
+    Depth24Plus,
+    /// 32-bit floating point depth.
+    #[default]
+    Depth32Float,
+}
+
+/// Depth buffer and multisampling of the rendering passes, chosen when the
+/// application is created since the pipelines are created for them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthSettings {
+    /// Format of the depth buffer.
+    pub format: DepthFormat,
+    /// Whether the depth goes from 1 at the near plane to 0 at the far
+    /// plane. With a floating point format, the precision is spread evenly
+    /// over the distance instead of being spent close to the near plane,
+    /// which avoids z-fighting far away in large scenes.
+    ///
+    /// The depth range is always `[0, 1]` in wgpu, so only the projection
+    /// matrices, the depth tests and the depth clear value change.
+    pub reversed_z: bool,
+    /// Number of samples per pixel of the depth buffer and of the color
+    /// texture the passes drawing with it render into, resolved into the
+    /// render target. 1 disables the multisampling.
+    pub samples: u32,
+}
+
+impl Default for DepthSettings {
+    fn default() -> Self {
+        Self {
+            format: DepthFormat::default(),
+            reversed_z: false,
+            samples: 1,
+        }
+    }
+}
+
+impl DepthSettings {
+    /// Returns the settings with the number of samples lowered to the
+    /// largest one supported by the adapter for the depth buffer and the
+    /// color format, 1 if none is.
+    pub fn supported_by(self, adapter: &wgpu::Adapter, format: wgpu::TextureFormat) -> Self {
+        let color = adapter.get_texture_format_features(format).flags;
+        let depth = adapter
+            .get_texture_format_features(self.texture_format())
+            .flags;
+        let resolved = color.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+        let samples = [16, 8, 4, 2]
+            .into_iter()
+            .filter(|&n| n <= self.samples && resolved)
+            .find(|&n| color.sample_count_supported(n) && depth.sample_count_supported(n))
+            .unwrap_or(1);
+        if samples != self.samples {
+            log::warn!(
+                "{} samples per pixel are not supported by the adapter, using {}.",
+                self.samples,
+                samples
+            );
+        }
+        Self { samples, ..self }
+    }
+
+    /// Returns the multisample state of the pipelines drawing with the depth
+    /// buffer.
+    pub const fn multisample(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.samples,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        }
+    }
+
+    /// Returns true if the passes drawing with the depth buffer render into
+    /// a multisampled color texture.
+    pub const fn is_multisampled(&self) -> bool {
+        self.samples > 1
+    }
+
+    /// Returns the format of the depth textures.
+    pub const fn texture_format(&self) -> wgpu::TextureFormat {
+        match self.format {
+            DepthFormat::Depth24Plus => wgpu::TextureFormat::Depth24Plus,
+            DepthFormat::Depth32Float => wgpu::TextureFormat::Depth32Float,
+        }
+    }
+
+    /// Returns the depth test letting the fragments closer to the camera
+    /// pass.
+    pub const fn compare(&self) -> wgpu::CompareFunction {
+        if self.reversed_z {
+            wgpu::CompareFunction::GreaterEqual
+        } else {
+            wgpu::CompareFunction::LessEqual
+        }
+    }
+
+    /// Returns the depth the depth buffer is cleared to, the one of the far
+    /// plane.
+    pub const fn clear_value(&self) -> f32 {
+        if self.reversed_z {
+            0.0
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Time of the animations of the scene, scaled, paused or stepped by the
+/// application, see `PyAppState::set_time_scale`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SceneTime {
+    /// Seconds elapsed in the scene since the application started.
+    pub elapsed: f32,
+    /// Seconds elapsed in the scene since the previous frame.
+    pub delta: f32,
+}
+
+/// Top-down map of the scene drawn in a corner of the window, see
+/// `PyAppState::show_minimap`.
+#[derive(Clone, Copy, Debug)]
+pub struct Minimap {
+    /// Orthographic camera looking down at the main camera, rendering into
+    /// the texture of the map.
+    pub camera: legion::Entity,
+    /// Texture the camera renders into.
+    pub texture: Handle<Texture>,
+    /// Width and height of the map in pixels.
+    pub size: u32,
+    /// Direction the main camera is looking at on the map, x to the right
+    /// and y up.
+    pub heading: glam::Vec2,
+}
+
+/// Instances of the meshes of a scene, drawn by the renderer while the scene
+/// is active and kept aside otherwise.
+#[derive(Debug, Default)]
+pub struct SceneDraws {
+    instancing: FxHashMap<MeshBundle, Vec<NodeIdx>>,
+    static_batches: Vec<StaticBatch>,
 }
 
 pub struct Renderer {
@@ -104,11 +317,83 @@ pub struct Renderer {
     // TODO: remove these default bundles, make them inside the assets.
     default_material_bundle: Handle<MaterialBundle>,
     default_texture_bundle: Handle<TextureBundle>,
-    aesthetic_bundles: Vec<AestheticBundle>,
+    /// Material and texture bundles keyed by the content of their materials.
+    aesthetic_bundles: FxHashMap<u64, AestheticBundle>,
+    /// Strong references to the GPU meshes, held by the entities using them.
+    mesh_refs: RefCounts<GpuMesh>,
+    /// Strong references to the material bundles.
+    material_refs: RefCounts<MaterialBundle>,
+    /// Strong references to the texture bundles.
+    texture_bundle_refs: RefCounts<TextureBundle>,
+    /// Mesh bundles unpinned since the last update of the references,
+    /// removed then if they are not used.
+    unpinned_meshes: Vec<MeshBundle>,
+    /// Textures loaded from files, keyed by path and format.
+    loaded_textures: FxHashMap<(PathBuf, Option<wgpu::TextureFormat>), Handle<Texture>>,
+    /// Meshes loaded from files, keyed by canonical path. A file may have
+    /// been loaded several times.
+    loaded_meshes: FxHashMap<PathBuf, Vec<Handle<GpuMesh>>>,
+    /// Material libraries and textures the meshes have been loaded with,
+    /// keyed by canonical path, with the canonical paths of the mesh files.
+    /// Only tracked while hot-reload is enabled.
+    mesh_dependencies: FxHashMap<PathBuf, FxHashSet<PathBuf>>,
+    /// CPU copies of the uploaded meshes, to upload them again if the device
+    /// is lost.
+    mesh_sources: FxHashMap<Handle<GpuMesh>, Mesh>,
+    /// BVHs over the triangles of the meshes, built when first raycast.
+    mesh_bvhs: FxHashMap<Handle<GpuMesh>, Arc<TriangleBvh>>,
+    /// GPU materials of the material bundles, to upload them again if the
+    /// device is lost.
+    material_sources: FxHashMap<Handle<MaterialBundle>, Vec<GpuMaterial>>,
+    /// Faces of the environment map, to load it again if the device is lost.
+    environment_faces: Option<[PathBuf; 6]>,
+    /// Textures the secondary cameras render into, keyed by camera entity.
+    camera_targets: FxHashMap<legion::Entity, CameraTarget>,
+    /// Map shown in a corner of the window, if any.
+    minimap: Option<Minimap>,
+    /// Scene mirrored below the reflective water surface, if any.
+    water_reflection: Option<WaterReflection>,
+    /// Incremented each time the texture of the reflection is recreated.
+    reflection_generation: u64,
+    /// Watches the files of the loaded meshes and textures, if hot-reload is
+    /// enabled.
+    file_watcher: Option<FileWatcher>,
     /// Nodes that use instancing for each mesh bundle.
     pub(crate) instancing: FxHashMap<MeshBundle, Vec<NodeIdx>>,
-    samplers: FxHashMap<SmlString, Sampler>,
+    /// Static instances merged into single meshes.
+    static_batches: Vec<StaticBatch>,
+    samplers: SamplerRegistry,
+    /// Number of textures in the global texture array.
+    texture_array_len: u32,
+    /// Whether the textures are bound as a binding array, otherwise they are
+    /// bound one by one with their sampler.
+    binding_arrays: bool,
+    /// Bind group of the global texture array shared by all materials.
+    pub(crate) textures_bind_group: Option<wgpu::BindGroup>,
+    /// Bind groups of the materials of the drawn meshes with the textures
+    /// of their slots, without binding arrays. See
+    /// [`material_slots_bind_group_layout`].
+    pub(crate) material_slots: FxHashMap<AestheticBundle, wgpu::BindGroup>,
+    /// Whether textures were added since the texture bind group was created.
+    textures_dirty: bool,
+    /// GPU memory of the textures, evicting them beyond the budget.
+    texture_budget: TextureBudget,
+    /// Texture atlas storing the images of all sprites.
+    pub(crate) sprite_atlas: TextureAtlas,
+    /// Sources of the shaders, possibly provided by the user.
+    pub(crate) shaders: ShaderManager,
+    /// Environment map reflected by the materials.
+    pub(crate) environment_map: Option<EnvironmentMap>,
+    /// Incremented each time the environment map changes.
+    pub(crate) environment_generation: u64,
+    /// Incremented each time the meshes or the texture bind group change,
+    /// invalidating the draw calls recorded by the rendering passes.
+    pub(crate) draws_generation: u64,
     params: RenderParams,
+    /// Depth buffer the rendering passes are created with.
+    depth: DepthSettings,
+    /// Time of the animations, advanced by the application every frame.
+    time: SceneTime,
     cmd_receiver: Receiver<Command>,
 
     // Variable controlling the scale of the orthographic projection matrix
@@ -123,24 +408,28 @@ impl Renderer {
     /// Clear color of the renderer.
     pub const CLEAR_COLOR: Color = color!(0.60383, 0.66539, 0.42327);
 
-    /// Creates a new renderer.
-    pub fn new(context: &GpuContext, receiver: CommandReceiver) -> Self {
+    /// Name of the texture of the minimap, which materials may use as well.
+    pub const MINIMAP_TEXTURE: &'static str = "minimap";
+
+    /// Creates a new renderer whose rendering passes use the given depth
+    /// buffer.
+    pub fn new(context: &GpuContext, receiver: CommandReceiver, depth: DepthSettings) -> Self {
         profiling::scope!("Renderer::new");
         let device = context.device.clone();
         let queue = context.queue.clone();
         let limits = context.limits.clone();
         let meshes = GpuMeshAssets::new(&device);
         let textures = TextureAssets::new(&context.device, &context.queue);
-        let samplers = Self::create_samplers(&context.device);
+        let mut samplers = SamplerRegistry::new();
+        samplers.prepare(&context.device, SamplerRegistry::DEFAULT);
+        let sprite_atlas = TextureAtlas::new(&context.device);
         let mut material_bundles = MaterialBundleAssets::new();
         let default_material_bundle =
             material_bundles.add(MaterialBundle::default(&context.device));
         let mut texture_bundles = TextureBundleAssets::new();
         let default_texture_bundle = texture_bundles.add(TextureBundle {
             textures: vec![textures.default_texture()],
-            samplers: vec!["linear".into()],
-            bind_group: None,
-            sampler_index_buffer: None,
+            slots: Vec::new(),
         });
 
         Self {
@@ -152,77 +441,98 @@ impl Renderer {
             textures,
             default_material_bundle,
             default_texture_bundle,
-            aesthetic_bundles: vec![],
+            aesthetic_bundles: FxHashMap::default(),
+            loaded_textures: FxHashMap::default(),
+            loaded_meshes: FxHashMap::default(),
+            mesh_dependencies: FxHashMap::default(),
+            mesh_sources: FxHashMap::default(),
+            mesh_bvhs: FxHashMap::default(),
+            material_sources: FxHashMap::default(),
+            environment_faces: None,
+            camera_targets: FxHashMap::default(),
+            minimap: None,
+            water_reflection: None,
+            reflection_generation: 0,
+            mesh_refs: RefCounts::new(),
+            material_refs: RefCounts::new(),
+            texture_bundle_refs: RefCounts::new(),
+            unpinned_meshes: Vec::new(),
+            texture_budget: TextureBudget::default(),
+            file_watcher: None,
             instancing: FxHashMap::default(),
+            static_batches: Vec::new(),
             samplers,
+            texture_array_len: BlinnPhongRenderPass::texture_array_len(context),
+            binding_arrays: context.binding_arrays,
+            textures_bind_group: None,
+            material_slots: FxHashMap::default(),
+            textures_dirty: true,
+            sprite_atlas,
+            shaders: ShaderManager::default(),
+            environment_map: None,
+            environment_generation: 0,
+            draws_generation: 0,
             params: RenderParams {
                 mode: ShadingMode::BlinnPhong,
                 enable_back_face_culling: true,
                 enable_occlusion_culling: false,
+                enable_gpu_culling: false,
                 enable_wireframe: false,
+                enable_depth_prepass: false,
                 enable_shadows: false,
                 enable_lighting: true,
+                vsync: true,
+                target_fps: None,
+                texture_budget: None,
+                show_debug_gizmos: false,
                 #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
                 write_shadow_maps: true,
             },
+            depth,
+            time: SceneTime::default(),
             cmd_receiver: receiver,
             texture_bundles,
             light_proj_scale: 1.0,
         }
     }
 
-    fn create_samplers(device: &wgpu::Device) -> FxHashMap<SmlString, Sampler> {
-        let mut samplers = FxHashMap::default();
-        samplers.insert(
-            SmlString::from("linear"),
-            Sampler::new(
-                &device,
-                wgpu::SamplerDescriptor {
-                    label: Some("linear_repeat_sampler"),
-                    address_mode_u: wgpu::AddressMode::Repeat,
-                    address_mode_v: wgpu::AddressMode::Repeat,
-                    address_mode_w: wgpu::AddressMode::Repeat,
-                    mag_filter: wgpu::FilterMode::Linear,
-                    min_filter: wgpu::FilterMode::Linear,
-                    mipmap_filter: wgpu::FilterMode::Linear,
-                    ..Default::default()
-                },
-            ),
-        );
-        samplers.insert(
-            SmlString::from("nearest"),
-            Sampler::new(
-                &device,
-                wgpu::SamplerDescriptor {
-                    label: Some("nearest_repeat_sampler"),
-                    address_mode_u: wgpu::AddressMode::Repeat,
-                    address_mode_v: wgpu::AddressMode::Repeat,
-                    address_mode_w: wgpu::AddressMode::Repeat,
-                    mag_filter: wgpu::FilterMode::Nearest,
-                    min_filter: wgpu::FilterMode::Nearest,
-                    mipmap_filter: wgpu::FilterMode::Nearest,
-                    ..Default::default()
-                },
-            ),
-        );
-        samplers.insert(
-            SmlString::from("depth"),
-            Sampler::new(
-                &device,
-                wgpu::SamplerDescriptor {
-                    label: Some("depth_sampler"),
-                    address_mode_u: wgpu::AddressMode::ClampToEdge,
-                    address_mode_v: wgpu::AddressMode::ClampToEdge,
-                    address_mode_w: wgpu::AddressMode::ClampToEdge,
-                    mag_filter: wgpu::FilterMode::Nearest,
-                    min_filter: wgpu::FilterMode::Nearest,
-                    mipmap_filter: wgpu::FilterMode::Nearest,
-                    compare: Some(wgpu::CompareFunction::LessEqual),
-                    ..Default::default()
-                },
-            ),
-        );
-        samplers
+    /// Returns the parameters the frames are rendered with.
+    pub fn params(&self) -> &RenderParams {
+        &self.params
+    }
+
+    /// Returns the depth buffer the rendering passes are created with.
+    pub fn depth(&self) -> DepthSettings {
+        self.depth
+    }
+
+    /// Returns true if one of the materials of the mesh bundle is not fully
+    /// opaque or alpha-tested, see [`MaterialBundle::is_translucent`].
+    pub fn is_translucent(&self, bundle: &MeshBundle) -> bool {
+        self.material_bundles
+            .get(bundle.aesthetic.materials)
+            .is_some_and(|bundle| bundle.translucent)
+    }
+
+    /// Returns the mesh the GPU mesh was uploaded from.
+    pub fn mesh_source(&self, handle: Handle<GpuMesh>) -> Option<&Mesh> {
+        self.mesh_sources.get(&handle)
+    }
+
+    /// Returns the BVH over the triangles of the mesh, building it the first
+    /// time. Returns `None` if the mesh is not made of triangles.
+    pub fn mesh_bvh(&mut self, handle: Handle<GpuMesh>) -> Option<Arc<TriangleBvh>> {
+        if let Some(bvh) = self.mesh_bvhs.get(&handle) {
+            return Some(bvh.clone());
+        }
+        let bvh = Arc::new(TriangleBvh::from_mesh(self.mesh_sources.get(&handle)?)?);
+        self.mesh_bvhs.insert(handle, bvh.clone());
+        Some(bvh)
+    }
+
+    /// Returns the memory statistics of the buffer holding the mesh data.
+    pub fn mesh_buffer_stats(&self) -> MeshBufferStats {
+        self.meshes.stats()
     }
 
     /// Uploads a mesh to the GPU, creates `GpuMesh` from `Mesh` then adds it to
@@ -232,6 +542,20 @@ impl Renderer {
         log::debug!("Mesh materials: {:?}", mesh.materials);
 
         let mesh_hdl = self.meshes.add(&self.device, &self.queue, mesh);
+        self.mesh_sources.insert(mesh_hdl, mesh.clone());
+        self.draws_generation += 1;
+        if let Some(path) = &mesh.path {
+            match path.canonicalize() {
+                Ok(path) => {
+                    self.loaded_meshes
+                        .entry(path.clone())
+                        .or_default()
+                        .push(mesh_hdl);
+                    self.watch_mesh_files(&path, mesh);
+                }
+                Err(err) => log::warn!("Mesh file {:?} won't be reloaded: {}", path, err),
+            }
+        }
         // Upload materials and create a material bundle.
         match &mesh.materials {
             None => {
@@ -255,120 +579,279 @@ impl Renderer {
         }
     }
 
+    /// Updates the strong references to the assets of the mesh bundles
+    /// referenced (`true`) or no longer referenced (`false`) by entities,
+    /// see `Scene::take_mesh_refs`, then removes the assets which are no
+    /// longer referenced nor pinned.
+    ///
+    /// All the references are updated before removing anything, so that a
+    /// bundle released and referenced again since the last update is kept.
+    pub fn update_mesh_refs(&mut self, refs: &[(MeshBundle, bool)]) {
+        let mut released = std::mem::take(&mut self.unpinned_meshes);
+        for (bundle, referenced) in refs {
+            if *referenced {
+                self.mesh_refs.retain(bundle.mesh);
+                self.material_refs.retain(bundle.aesthetic.materials);
+                self.texture_bundle_refs.retain(bundle.aesthetic.textures);
+            } else {
+                self.mesh_refs.release(bundle.mesh);
+                self.material_refs.release(bundle.aesthetic.materials);
+                self.texture_bundle_refs.release(bundle.aesthetic.textures);
+                released.push(*bundle);
+            }
+        }
+        released.sort_unstable();
+        released.dedup();
+        for bundle in released {
+            self.remove_unused(bundle);
+        }
+    }
+
+    /// Keeps the assets of the mesh bundle alive while no entity uses them,
+    /// e.g. to spawn the mesh again later without uploading it, or lets them
+    /// be removed once unused.
+    pub fn pin_mesh(&mut self, bundle: MeshBundle, pinned: bool) {
+        self.mesh_refs.pin(bundle.mesh, pinned);
+        self.material_refs.pin(bundle.aesthetic.materials, pinned);
+        self.texture_bundle_refs
+            .pin(bundle.aesthetic.textures, pinned);
+        if !pinned {
+            self.unpinned_meshes.push(bundle);
+        }
+    }
+
+    /// Removes the assets of the mesh bundle which are no longer referenced
+    /// nor pinned, with everything derived from them. The default bundles
+    /// are kept.
+    fn remove_unused(&mut self, bundle: MeshBundle) {
+        let mesh = bundle.mesh;
+        if self.mesh_refs.take_unused(mesh) && self.meshes.remove(mesh).is_some() {
+            log::debug!("Removing unused mesh {:?}", mesh);
+            self.mesh_sources.remove(&mesh);
+            self.mesh_bvhs.remove(&mesh);
+            self.loaded_meshes.retain(|_, loaded| {
+                loaded.retain(|loaded| *loaded != mesh);
+                !loaded.is_empty()
+            });
+            let loaded_meshes = &self.loaded_meshes;
+            self.mesh_dependencies.retain(|_, meshes| {
+                meshes.retain(|path| loaded_meshes.contains_key(path));
+                !meshes.is_empty()
+            });
+            self.instancing.retain(|bundle, _| bundle.mesh != mesh);
+            self.draws_generation += 1;
+        }
+
+        let materials = bundle.aesthetic.materials;
+        if materials != self.default_material_bundle
+            && self.material_refs.take_unused(materials)
+            && self.material_bundles.remove(materials).is_some()
+        {
+            log::debug!("Removing unused material bundle {:?}", materials);
+            self.material_sources.remove(&materials);
+            self.aesthetic_bundles
+                .retain(|_, aesthetic| aesthetic.materials != materials);
+            self.instancing
+                .retain(|bundle, _| bundle.aesthetic.materials != materials);
+        }
+
+        let textures = bundle.aesthetic.textures;
+        if textures != self.default_texture_bundle
+            && self.texture_bundle_refs.take_unused(textures)
+            && self.texture_bundles.remove(textures).is_some()
+        {
+            log::debug!("Removing unused texture bundle {:?}", textures);
+            self.aesthetic_bundles
+                .retain(|_, aesthetic| aesthetic.textures != textures);
+        }
+    }
+
+    /// Returns the key of a list of materials in the material cache.
+    ///
+    /// The key depends on the content of the materials and their order, as
+    /// sub-meshes refer to materials by index.
+    fn materials_key(materials: &[Material]) -> u64 {
+        let mut hasher = FxHasher::default();
+        for mtl in materials {
+            hasher.write_u64(mtl.content_hash());
+        }
+        hasher.finish()
+    }
+
     /// Creates a bundle of materials and a bundle of textures from a list of
     /// materials.
+    ///
+    /// Bundles are cached by the content of the materials, identical lists of
+    /// materials share the same bundles whatever the names of the materials.
     fn upload_materials(&mut self, materials: &[Material]) -> AestheticBundle {
-        let materials_name_hashes = materials
-            .iter()
-            .map(|mtl| {
-                let mut hasher = FxHasher::default();
-                hasher.write(mtl.name.as_bytes());
-                hasher.finish()
-            })
-            .collect::<Vec<_>>();
-        log::debug!(
-            "Try to find material name hashes: {:?}",
-            materials_name_hashes
-        );
-        let mut aesthetic_bundle = None;
-        for bundle in &self.aesthetic_bundles {
-            let material_bundle = self.material_bundles.get(bundle.materials).unwrap();
-            if material_bundle.materials.len() - 1 != materials.len() {
-                continue;
-            }
-            if materials_name_hashes
-                .iter()
-                .all(|mtl| material_bundle.materials.contains(mtl))
-            {
-                log::debug!("Found existing material bundle: {:?}", bundle);
-                aesthetic_bundle = Some(bundle);
-                break;
-            }
+        let key = Self::materials_key(materials);
+        if let Some(bundle) = self.aesthetic_bundles.get(&key) {
+            log::debug!("Found existing material bundle: {:?}", bundle);
+            return *bundle;
         }
 
-        match aesthetic_bundle {
-            Some(bundle) => *bundle,
+        log::debug!("No existing material bundle found, create a new one.");
+        let default_material = Material::default();
+        // Material bundle size is the number of materials + 1 (for the
+        // default material, last one).
+        let mtls = materials.iter().chain(std::iter::once(&default_material));
+        let (gpu_mtls, textures) = self.create_gpu_materials(mtls.clone());
+        let bundle = MaterialBundle::new(&self.device, mtls, &gpu_mtls);
+        let material_bundle = self.material_bundles.add(bundle);
+        self.material_sources.insert(material_bundle, gpu_mtls);
+        let texture_bundle = self.texture_bundles.add(textures);
+        let aesthetic = AestheticBundle {
+            materials: material_bundle,
+            textures: texture_bundle,
+        };
+        self.aesthetic_bundles.insert(key, aesthetic);
+        aesthetic
+    }
+
+    /// Re-uploads the materials of an existing bundle, e.g. after they have
+    /// been modified. All meshes sharing the bundle are affected.
+    ///
+    /// The number of materials must match the number of materials of the
+    /// bundle, returns false otherwise.
+    pub fn update_materials(&mut self, aesthetic: AestheticBundle, materials: &[Material]) -> bool {
+        let n_materials = match self.material_bundles.get(aesthetic.materials) {
+            Some(bundle) => bundle.n_materials as usize,
             None => {
-                log::debug!("No existing material bundle found, create a new one.");
-                let default_material = Material::default();
-                // Material bundle size is the number of materials + 1 (for the
-                // default material, last one).
-                let mtls = materials.iter().chain(std::iter::once(&default_material));
-                let mut gpu_mtls = mtls
-                    .clone()
-                    .map(GpuMaterial::from_material)
-                    .collect::<Vec<_>>();
+                log::error!("Missing material bundle {:?}", aesthetic.materials);
+                return false;
+            }
+        };
+        if aesthetic.materials == self.default_material_bundle || n_materials != materials.len() + 1
+        {
+            log::error!(
+                "Can't update material bundle {:?} of {} materials with {} materials.",
+                aesthetic.materials,
+                n_materials - 1,
+                materials.len()
+            );
+            return false;
+        }
 
-                // Load textures and create a bundle of textures.
-                let mut textures = Vec::new();
-                for (mtl, gpu_mtl) in mtls.clone().zip(gpu_mtls.iter_mut()) {
-                    for (tex_ty, tex_path) in mtl.textures.iter() {
-                        let format = match tex_ty {
-                            TextureType::MapNorm => Some(wgpu::TextureFormat::Rgba8Unorm),
-                            _ => None,
-                        };
-                        let texture_hdl = self.add_texture(tex_path, format);
-                        let texture_idx = textures.len();
-                        textures.push(texture_hdl);
-                        match tex_ty {
-                            TextureType::MapKa => {
-                                gpu_mtl.map_ka = texture_idx as u32;
-                            }
-                            TextureType::MapKd => {
-                                gpu_mtl.map_kd = texture_idx as u32;
-                            }
-                            TextureType::MapKs => {
-                                gpu_mtl.map_ks = texture_idx as u32;
-                            }
-                            TextureType::MapNs => {
-                                gpu_mtl.map_ns = texture_idx as u32;
-                            }
-                            TextureType::MapD => {
-                                gpu_mtl.map_d = texture_idx as u32;
-                            }
-                            TextureType::MapBump => {
-                                gpu_mtl.map_bump = texture_idx as u32;
-                            }
-                            TextureType::MapDisp => {
-                                gpu_mtl.map_disp = texture_idx as u32;
-                            }
-                            TextureType::MapDecal => {
-                                gpu_mtl.map_decal = texture_idx as u32;
-                            }
-                            TextureType::MapNorm => {
-                                gpu_mtl.map_norm = texture_idx as u32;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
+        let default_material = Material::default();
+        let mtls = materials.iter().chain(std::iter::once(&default_material));
+        let (gpu_mtls, textures) = self.create_gpu_materials(mtls.clone());
+        let bundle = self.material_bundles.get_mut(aesthetic.materials).unwrap();
+        self.queue
+            .write_buffer(&bundle.buffer, 0, bytemuck::cast_slice(&gpu_mtls));
+        bundle.materials = mtls.map(Material::content_hash).collect();
+        let translucent = MaterialBundle::is_translucent(&gpu_mtls);
+        if bundle.translucent != translucent {
+            // Translucent meshes are drawn by other pipelines.
+            bundle.translucent = translucent;
+            self.draws_generation += 1;
+        }
+        self.material_sources.insert(aesthetic.materials, gpu_mtls);
+        if let Some(bundle) = self.texture_bundles.get_mut(aesthetic.textures) {
+            *bundle = textures;
+        }
+        // The textures bound with the materials may have changed.
+        if self.material_slots.remove(&aesthetic).is_some() {
+            self.draws_generation += 1;
+        }
 
-                log::debug!("loaded textures: {:?}", textures);
-                log::debug!("GpuMaterials to be uploaded: {:?}", gpu_mtls);
-                textures.push(self.textures.default_texture());
-                let bundle = MaterialBundle::new(&self.device, mtls, &gpu_mtls);
-                let material_bundle = self.material_bundles.add(bundle);
-                let samplers = textures
-                    .iter()
-                    .map(|tex| {
-                        let texture = self.textures.get(*tex).unwrap();
-                        texture.sampler.clone()
-                    })
-                    .collect();
-                let texture_bundle = self.texture_bundles.add(TextureBundle {
-                    textures,
-                    samplers,
-                    bind_group: None,
-                    sampler_index_buffer: None,
-                });
-                let aesthetic = AestheticBundle {
-                    materials: material_bundle,
-                    textures: texture_bundle,
+        // The bundle is now cached under the key of the new content.
+        self.aesthetic_bundles
+            .retain(|_, bundle| *bundle != aesthetic);
+        self.aesthetic_bundles
+            .insert(Self::materials_key(materials), aesthetic);
+        true
+    }
+
+    /// Converts materials to their GPU representation, loading their
+    /// textures. Returns the GPU materials and the bundle of the textures
+    /// they use, the default texture being the last one.
+    ///
+    /// Without binding arrays, the texture indices of the materials are the
+    /// slots of the textures in the bundle. The textures beyond the slots
+    /// are not sampled.
+    fn create_gpu_materials<'a, M>(&mut self, materials: M) -> (Vec<GpuMaterial>, TextureBundle)
+    where
+        M: Iterator<Item = &'a Material>,
+    {
+        let mut gpu_mtls = Vec::new();
+        let mut textures = Vec::new();
+        let mut slots = Vec::new();
+        for mtl in materials {
+            let mut gpu_mtl = GpuMaterial::from_material(mtl);
+            for (tex_ty, tex_path) in mtl.textures.iter() {
+                let texture_hdl = self.add_texture(tex_path, Self::texture_format(*tex_ty));
+                if let Some(sampler) = mtl.samplers.get(tex_ty) {
+                    self.set_texture_sampler(texture_hdl, sampler);
+                }
+                textures.push(texture_hdl);
+                let texture_idx = if self.binding_arrays {
+                    self.texture_index(texture_hdl)
+                } else if let Some(slot) = slots.iter().position(|t| *t == texture_hdl) {
+                    slot as u32
+                } else if slots.len() < BlinnPhongRenderPass::MAX_TEXTURE_SLOTS {
+                    slots.push(texture_hdl);
+                    slots.len() as u32 - 1
+                } else {
+                    log::warn!(
+                        "The materials of a mesh sample more than {} textures, the {:?} map {} \
+                         of material {} is ignored.",
+                        BlinnPhongRenderPass::MAX_TEXTURE_SLOTS,
+                        tex_ty,
+                        tex_path.display(),
+                        mtl.name
+                    );
+                    continue;
                 };
-                self.aesthetic_bundles.push(aesthetic);
-                aesthetic
+                match tex_ty {
+                    TextureType::MapKa => {
+                        gpu_mtl.map_ka = texture_idx;
+                    }
+                    TextureType::MapKd => {
+                        gpu_mtl.map_kd = texture_idx;
+                    }
+                    TextureType::MapKs => {
+                        gpu_mtl.map_ks = texture_idx;
+                    }
+                    TextureType::MapNs => {
+                        gpu_mtl.map_ns = texture_idx;
+                    }
+                    TextureType::MapD => {
+                        gpu_mtl.map_d = texture_idx;
+                    }
+                    TextureType::MapBump => {
+                        gpu_mtl.map_bump = texture_idx;
+                    }
+                    TextureType::MapDisp => {
+                        gpu_mtl.map_disp = texture_idx;
+                    }
+                    TextureType::MapDecal => {
+                        gpu_mtl.map_decal = texture_idx;
+                    }
+                    TextureType::MapNorm => {
+                        gpu_mtl.map_norm = texture_idx;
+                    }
+                    TextureType::MapKe => {
+                        gpu_mtl.map_ke = texture_idx;
+                    }
+                    TextureType::MapPr => {
+                        gpu_mtl.map_pr = texture_idx;
+                    }
+                    TextureType::MapPm => {
+                        gpu_mtl.map_pm = texture_idx;
+                    }
+                    TextureType::MapPs => {
+                        gpu_mtl.map_ps = texture_idx;
+                    }
+                    _ => {}
+                }
             }
+            gpu_mtls.push(gpu_mtl);
         }
+
+        log::debug!("loaded textures: {:?}", textures);
+        log::debug!("GpuMaterials to be uploaded: {:?}", gpu_mtls);
+        textures.push(self.textures.default_texture());
+        (gpu_mtls, TextureBundle { textures, slots })
     }
 
     /// Adds a new instancing data for a mesh.
@@ -376,17 +859,41 @@ impl Renderer {
         if nodes.is_empty() {
             return;
         }
+        // The instances are kept sorted, so that they are drawn in the same
+        // order whatever the order they were added in.
         match self.instancing.entry(mesh) {
             Entry::Occupied(mut instancing) => {
                 instancing.get_mut().extend(nodes.iter());
+                instancing.get_mut().sort_unstable();
             }
             Entry::Vacant(_) => {
-                self.instancing.insert(mesh, nodes.to_vec());
+                let mut instances = nodes.to_vec();
+                instances.sort_unstable();
+                self.instancing.insert(mesh, instances);
             }
         }
         log::debug!("Instancing: {:?}", self.instancing);
     }
 
+    /// Returns the time of the animations of the scene.
+    pub fn time(&self) -> SceneTime {
+        self.time
+    }
+
+    /// Sets the time of the animations of the scene for the next frame.
+    pub fn set_time(&mut self, time: SceneTime) {
+        self.time = time;
+    }
+
+    /// Exchanges the instances drawn by the renderer with the ones of
+    /// another scene, which becomes the drawn scene. The meshes, materials
+    /// and textures are shared by the scenes and stay uploaded.
+    pub fn swap_scene_draws(&mut self, draws: &mut SceneDraws) {
+        std::mem::swap(&mut self.instancing, &mut draws.instancing);
+        std::mem::swap(&mut self.static_batches, &mut draws.static_batches);
+        self.draws_generation += 1;
+    }
+
     /// Removes one instancing data for a mesh.
     pub fn remove_instancing(&mut self, mesh: MeshBundle, node: NodeIdx) {
         if let Some(nodes) = self.instancing.get_mut(&mesh) {
@@ -394,13 +901,508 @@ impl Renderer {
         }
     }
 
+    /// Returns the format of the textures of the given type, `None` for the
+    /// default sRGB format of the color textures. The other textures are
+    /// linear.
+    fn texture_format(ty: TextureType) -> Option<wgpu::TextureFormat> {
+        if ty.is_color() {
+            None
+        } else {
+            Some(wgpu::TextureFormat::Rgba8Unorm)
+        }
+    }
+
+    /// Returns the textures of the materials which are not loaded yet, as
+    /// (path, format), each only once.
+    pub fn missing_textures(
+        &self,
+        materials: &[Material],
+    ) -> Vec<(PathBuf, Option<wgpu::TextureFormat>)> {
+        let mut missing = Vec::new();
+        for mtl in materials {
+            for (tex_ty, tex_path) in mtl.textures.iter() {
+                let key = (tex_path.clone(), Self::texture_format(*tex_ty));
+                if !self.loaded_textures.contains_key(&key) && !missing.contains(&key) {
+                    missing.push(key);
+                }
+            }
+        }
+        missing
+    }
+
+    /// Adds a texture from an image decoded beforehand, e.g. on another
+    /// thread, registering it as loaded from `filepath`.
+    ///
+    /// Returns `None` if the image can't be uploaded, the texture being then
+    /// loaded again from the file when used.
+    pub fn add_decoded_texture(
+        &mut self,
+        filepath: &Path,
+        format: Option<wgpu::TextureFormat>,
+        image: &DecodedImage,
+    ) -> Option<Handle<Texture>> {
+        let key = (filepath.to_path_buf(), format);
+        if let Some(texture) = self.loaded_textures.get(&key) {
+            return Some(*texture);
+        }
+        let texture = self
+            .textures
+            .load_from_image(&self.device, &self.queue, image, format)
+            .map_err(|err| log::error!("Failed to upload texture {:?}: {}", filepath, err))
+            .ok()?;
+        self.textures_dirty = true;
+        self.loaded_textures.insert(key, texture);
+        self.track_texture(texture, true);
+        if let Some(watcher) = &mut self.file_watcher {
+            watcher.watch(filepath);
+        }
+        Some(texture)
+    }
+
     pub fn add_texture(
         &mut self,
         filepath: &Path,
         format: Option<wgpu::TextureFormat>,
     ) -> Handle<Texture> {
-        self.textures
-            .load_from_file(&self.device, &self.queue, filepath, format)
+        let key = (filepath.to_path_buf(), format);
+        if let Some(texture) = self.loaded_textures.get(&key) {
+            return *texture;
+        }
+        self.textures_dirty = true;
+        let texture = self
+            .textures
+            .load_from_file(&self.device, &self.queue, filepath, format);
+        self.loaded_textures.insert(key, texture);
+        self.track_texture(texture, true);
+        if let Some(watcher) = &mut self.file_watcher {
+            watcher.watch(filepath);
+        }
+        texture
+    }
+
+    /// Tracks the GPU memory of a texture after it has been uploaded.
+    fn track_texture(&mut self, handle: Handle<Texture>, evictable: bool) {
+        if let Some(texture) = self.textures.get(handle) {
+            self.texture_budget
+                .track(handle, texture.memory_size(), evictable);
+        }
+    }
+
+    /// Keeps the textures within the memory budget, before the frame is
+    /// prepared.
+    ///
+    /// The textures of the visible mesh entities are marked as used and
+    /// loaded again from their files if they were evicted. The least
+    /// recently used textures are then evicted until the others fit in the
+    /// budget.
+    pub fn update_texture_residency(&mut self, scene: &Scene) {
+        profiling::scope!("Renderer::update_texture_residency");
+        self.texture_budget.set_limit(self.params.texture_budget);
+        self.texture_budget.begin_frame();
+
+        let mut used = FxHashSet::default();
+        for (bundle, node) in <(&MeshBundle, &NodeIdx)>::query().iter(&scene.world) {
+            if !scene.nodes[*node].is_visible() {
+                continue;
+            }
+            if let Some(bundle) = self.texture_bundles.get(bundle.aesthetic.textures) {
+                used.extend(bundle.textures.iter().copied());
+            }
+        }
+        let evicted = used
+            .into_iter()
+            .filter(|texture| !self.texture_budget.touch(*texture))
+            .collect::<Vec<_>>();
+        if !evicted.is_empty() {
+            let files = self
+                .loaded_textures
+                .iter()
+                .filter(|(_, texture)| evicted.contains(texture))
+                .map(|((path, format), texture)| (path.clone(), *format, *texture))
+                .collect::<Vec<_>>();
+            for (path, format, texture) in files {
+                // A texture which can't be loaded again is not evicted again.
+                let reloaded = self.textures.reload_from_file(
+                    &self.device,
+                    &self.queue,
+                    texture,
+                    &path,
+                    format,
+                );
+                self.track_texture(texture, reloaded);
+            }
+            self.textures_dirty = true;
+        }
+
+        let evicted = self.texture_budget.evict();
+        if !evicted.is_empty() {
+            for texture in &evicted {
+                self.textures.evict(&self.device, *texture);
+            }
+            log::debug!(
+                "Evicted {} textures: {:?}",
+                evicted.len(),
+                self.texture_budget.stats()
+            );
+            self.textures_dirty = true;
+        }
+    }
+
+    /// Returns the memory statistics of the textures.
+    pub fn texture_memory_stats(&self) -> TextureMemoryStats {
+        self.texture_budget.stats()
+    }
+
+    /// Registers the settings of the sampler of the given name, replacing the
+    /// ones previously registered under this name.
+    pub fn register_sampler(&mut self, name: &str, config: SamplerConfig) {
+        if self.samplers.register(name, config) {
+            self.textures_dirty = true;
+        }
+    }
+
+    /// Sets the name of the sampler the texture is sampled with. A texture
+    /// shared by several materials uses the sampler set last.
+    pub fn set_texture_sampler(&mut self, texture: Handle<Texture>, sampler: &str) {
+        if let Some(texture) = self.textures.get_mut(texture) {
+            if texture.sampler.as_str() != sampler {
+                texture.sampler = SmlString::from(sampler);
+                self.textures_dirty = true;
+            }
+        }
+    }
+
+    /// Makes the camera render the scene into a texture of the given size
+    /// each frame. Materials use the texture by giving `name` as the path of
+    /// one of their textures.
+    ///
+    /// A texture previously created with the same name is resized and
+    /// reused, keeping the materials using it valid.
+    pub fn add_camera_target(
+        &mut self,
+        camera: legion::Entity,
+        name: &str,
+        width: u32,
+        height: u32,
+    ) -> Handle<Texture> {
+        let key = (PathBuf::from(name), None);
+        let existing = self.loaded_textures.get(&key).copied();
+        // A texture keeps its name when moved to another camera.
+        self.camera_targets
+            .retain(|_, target| Some(target.texture) != existing);
+        let target = CameraTarget::new(
+            &self.device,
+            &mut self.textures,
+            key.0.clone(),
+            (width, height),
+            existing,
+        );
+        let texture = target.texture;
+        self.loaded_textures.insert(key, texture);
+        self.camera_targets.insert(camera, target);
+        self.track_texture(texture, false);
+        self.textures_dirty = true;
+        texture
+    }
+
+    /// Stops the camera rendering into its texture, which keeps its last
+    /// image.
+    pub fn remove_camera_target(&mut self, camera: legion::Entity) {
+        self.camera_targets.remove(&camera);
+    }
+
+    /// Shows the map rendered by the camera, `size` pixels wide, in a corner
+    /// of the window.
+    pub fn show_minimap(&mut self, camera: legion::Entity, size: u32) {
+        if let Some(minimap) = self.minimap.filter(|minimap| minimap.camera != camera) {
+            self.camera_targets.remove(&minimap.camera);
+        }
+        let texture = self.add_camera_target(camera, Self::MINIMAP_TEXTURE, size, size);
+        let heading = self
+            .minimap
+            .map_or(glam::Vec2::Y, |minimap| minimap.heading);
+        self.minimap = Some(Minimap {
+            camera,
+            texture,
+            size,
+            heading,
+        });
+    }
+
+    /// Hides the map, and returns its camera which no longer renders.
+    pub fn hide_minimap(&mut self) -> Option<legion::Entity> {
+        let minimap = self.minimap.take()?;
+        self.camera_targets.remove(&minimap.camera);
+        Some(minimap.camera)
+    }
+
+    /// Returns the map shown in a corner of the window, if any.
+    pub fn minimap(&self) -> Option<&Minimap> {
+        self.minimap.as_ref()
+    }
+
+    /// Sets the direction the main camera is looking at on the map.
+    pub fn set_minimap_heading(&mut self, heading: glam::Vec2) {
+        if let Some(minimap) = self.minimap.as_mut() {
+            minimap.heading = heading;
+        }
+    }
+
+    /// Renders the scene from the cameras rendering into textures, with a
+    /// pass per camera created on first use. The passes of the cameras which
+    /// stopped rendering are dropped.
+    pub fn render_camera_targets<P, F>(
+        &self,
+        scene: &Scene,
+        passes: &mut FxHashMap<legion::Entity, P>,
+        mut create_pass: F,
+    ) where
+        P: RenderingPass,
+        F: FnMut(legion::Entity, &RenderTarget) -> P,
+    {
+        profiling::scope!("Renderer::render_camera_targets");
+        passes.retain(|camera, _| self.camera_targets.contains_key(camera));
+        for (camera, target) in &self.camera_targets {
+            let Some(texture) = self.textures.get(target.texture) else {
+                continue;
+            };
+            if scene.world.entry_ref(*camera).is_err() {
+                continue;
+            }
+            let pass = passes
+                .entry(*camera)
+                .or_insert_with(|| create_pass(*camera, &target.target));
+            let mut commands = pass.record(self, &target.target, &self.params, scene);
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("camera_target_copy_encoder"),
+                });
+            target.copy_to_texture(&mut encoder, texture);
+            commands.push(encoder.finish());
+            self.queue.submit(commands);
+        }
+    }
+
+    /// Renders the scene mirrored below the reflective water surface into
+    /// the texture sampled by the water, of the size of the window. The pass
+    /// is created on first use, and dropped with the texture once no surface
+    /// reflects.
+    pub fn render_water_reflection<P, F>(
+        &mut self,
+        scene: &Scene,
+        size: wgpu::Extent3d,
+        pass: &mut Option<P>,
+        create_pass: F,
+    ) where
+        P: RenderingPass,
+        F: FnOnce(&RenderTarget) -> P,
+    {
+        profiling::scope!("Renderer::render_water_reflection");
+        if WaterRenderPass::reflection_plane(scene).is_none() {
+            self.water_reflection = None;
+            *pass = None;
+            return;
+        }
+        if self
+            .water_reflection
+            .as_ref()
+            .map_or(true, |reflection| reflection.target.size != size)
+        {
+            self.water_reflection = Some(WaterReflection::new(&self.device, size));
+            self.reflection_generation += 1;
+        }
+        let reflection = self.water_reflection.as_ref().unwrap();
+        let pass = pass.get_or_insert_with(|| create_pass(&reflection.target));
+        let commands = pass.record(self, &reflection.target, &self.params, scene);
+        self.queue.submit(commands);
+    }
+
+    /// Returns the texture of the scene mirrored below the reflective water
+    /// surface, and the number of times it has been recreated.
+    pub fn water_reflection(&self) -> Option<(&Texture, u64)> {
+        self.water_reflection
+            .as_ref()
+            .map(|reflection| (&reflection.texture, self.reflection_generation))
+    }
+
+    /// Enables or disables the reloading of the mesh and texture files
+    /// modified on disk.
+    pub fn enable_hot_reload(&mut self, enable: bool) {
+        if !enable {
+            self.file_watcher = None;
+            self.mesh_dependencies.clear();
+            return;
+        }
+        if self.file_watcher.is_some() {
+            return;
+        }
+        match FileWatcher::new() {
+            Ok(mut watcher) => {
+                for (path, _) in self.loaded_textures.keys() {
+                    watcher.watch(path);
+                }
+                self.file_watcher = Some(watcher);
+                let meshes = self
+                    .loaded_meshes
+                    .iter()
+                    .filter_map(|(path, handles)| {
+                        let mesh = self.mesh_sources.get(handles.first()?)?;
+                        Some((path.clone(), mesh.dependency_files()))
+                    })
+                    .collect::<Vec<_>>();
+                for (path, files) in meshes {
+                    self.watch_mesh_dependencies(&path, files);
+                }
+            }
+            Err(err) => log::error!("Failed to enable hot-reload: {}", err),
+        }
+    }
+
+    /// Reloads the meshes and textures of which the files have been modified
+    /// since the last frame.
+    fn reload_changed_files(&mut self) {
+        let changed = match &self.file_watcher {
+            Some(watcher) => watcher.changed_files(),
+            None => return,
+        };
+        if changed.is_empty() {
+            return;
+        }
+
+        let textures = self
+            .loaded_textures
+            .iter()
+            .filter(|((path, _), _)| {
+                path.canonicalize()
+                    .map(|path| changed.contains(&path))
+                    .unwrap_or(false)
+            })
+            .map(|((path, format), texture)| (path.clone(), *format, *texture))
+            .collect::<Vec<_>>();
+        let mut reloaded_textures = FxHashSet::default();
+        for (path, format, texture) in textures {
+            if self
+                .textures
+                .reload_from_file(&self.device, &self.queue, texture, &path, format)
+            {
+                self.track_texture(texture, true);
+                self.textures_dirty = true;
+            }
+            if let Ok(path) = path.canonicalize() {
+                reloaded_textures.insert(path);
+            }
+        }
+
+        // Meshes of which the file changed, or a material library or a
+        // texture not reloaded on its own, e.g. one which failed to load.
+        let mut mesh_files = changed
+            .iter()
+            .filter(|path| self.loaded_meshes.contains_key(*path))
+            .cloned()
+            .collect::<FxHashSet<_>>();
+        for path in changed.difference(&reloaded_textures) {
+            if let Some(meshes) = self.mesh_dependencies.get(path) {
+                mesh_files.extend(meshes.iter().cloned());
+            }
+        }
+        for path in mesh_files {
+            let handles = self.loaded_meshes[&path].clone();
+            self.reload_mesh(&path, &handles);
+        }
+    }
+
+    /// Watches the file of a mesh and the files it has been loaded with, see
+    /// [`Mesh::dependency_files`], if hot-reload is enabled.
+    fn watch_mesh_files(&mut self, path: &Path, mesh: &Mesh) {
+        if self.file_watcher.is_some() {
+            self.watch_mesh_dependencies(path, mesh.dependency_files());
+        }
+    }
+
+    /// Watches the file of a mesh and the given files it depends on.
+    fn watch_mesh_dependencies(&mut self, path: &Path, files: Vec<PathBuf>) {
+        let Some(watcher) = &mut self.file_watcher else {
+            return;
+        };
+        watcher.watch(path);
+        for file in files {
+            watcher.watch(&file);
+            if let Ok(file) = file.canonicalize() {
+                self.mesh_dependencies
+                    .entry(file)
+                    .or_default()
+                    .insert(path.to_path_buf());
+            }
+        }
+    }
+
+    /// Reloads the meshes loaded from a file, updating the materials of all
+    /// the bundles the meshes are rendered with.
+    fn reload_mesh(&mut self, path: &Path, handles: &[Handle<GpuMesh>]) {
+        let mut mesh = match Mesh::try_load_from_obj(path) {
+            Ok(mesh) => mesh,
+            Err(err) => {
+                log::error!("Failed to reload mesh from {:?}: {}", path, err);
+                return;
+            }
+        };
+        mesh.validate();
+        for handle in handles {
+            self.meshes
+                .replace(&self.device, &self.queue, *handle, &mesh);
+            self.mesh_sources.insert(*handle, mesh.clone());
+            self.mesh_bvhs.remove(handle);
+        }
+        self.draws_generation += 1;
+        // The mesh may reference other material libraries or textures.
+        self.watch_mesh_files(path, &mesh);
+        log::info!("Reloaded mesh from: {:?}", path);
+
+        let materials = mesh.materials.unwrap_or_default();
+        let aesthetics = self
+            .instancing
+            .keys()
+            .filter(|bundle| handles.contains(&bundle.mesh))
+            .map(|bundle| bundle.aesthetic)
+            .filter(|aesthetic| aesthetic.materials != self.default_material_bundle)
+            .collect::<FxHashSet<_>>();
+        for aesthetic in aesthetics {
+            if !self.update_materials(aesthetic, &materials) {
+                log::warn!(
+                    "Number of materials of {:?} changed, materials are not reloaded.",
+                    path
+                );
+            }
+        }
+    }
+
+    /// Returns the index of a texture in the global texture array.
+    ///
+    /// Textures beyond the capacity of the array fall back to the default
+    /// texture.
+    fn texture_index(&self, texture: Handle<Texture>) -> u32 {
+        if texture.index < self.texture_array_len {
+            texture.index
+        } else {
+            log::error!(
+                "Texture array is full ({} textures), use the default texture instead.",
+                self.texture_array_len
+            );
+            self.textures.default_texture().index
+        }
+    }
+
+    /// Registers the custom material shaders used by entities.
+    pub fn add_custom_shaders(&mut self, paths: &[PathBuf]) {
+        for path in paths {
+            self.shaders.register_custom(path);
+        }
+    }
+
+    /// Loads an image into the sprite atlas and returns its region.
+    pub fn add_sprite_image(&mut self, filepath: &Path) -> Option<AtlasRegion> {
+        self.sprite_atlas.load_from_file(filepath)
     }
 
     /// Prepares the renderer for rendering.
@@ -414,12 +1416,50 @@ impl Renderer {
                 Command::EnableWireframe(enable) => {
                     self.params.enable_wireframe = enable;
                 }
+                Command::ShowDebugGizmos(show) => {
+                    self.params.show_debug_gizmos = show;
+                }
                 Command::EnableShadows(enable) => {
                     self.params.enable_shadows = enable;
                 }
                 Command::EnableLighting(enable) => {
                     self.params.enable_lighting = enable;
                 }
+                Command::EnableOcclusionCulling(enable) => {
+                    self.params.enable_occlusion_culling = enable;
+                }
+                Command::EnableGpuCulling(enable) => {
+                    self.params.enable_gpu_culling = enable;
+                }
+                Command::EnableDepthPrepass(enable) => {
+                    self.params.enable_depth_prepass = enable;
+                }
+                Command::EnableHotReload(enable) => {
+                    self.enable_hot_reload(enable);
+                }
+                Command::SetRenderParams(params) => {
+                    self.params = *params;
+                    self.params.target_fps = RenderParams::clamp_target_fps(self.params.target_fps);
+                }
+                Command::SetVsync(enable) => {
+                    self.params.vsync = enable;
+                }
+                Command::SetTargetFps(fps) => {
+                    self.params.target_fps = RenderParams::clamp_target_fps(fps);
+                }
+                Command::SetShaderDirectory(dir) => {
+                    self.shaders.set_directory(dir);
+                }
+                Command::SetEnvironmentMap(faces) => {
+                    self.environment_faces = faces;
+                    self.load_environment_map();
+                }
+                Command::SetTextureBudget(budget) => {
+                    self.params.texture_budget = budget;
+                }
+                Command::RegisterSampler { name, config } => {
+                    self.register_sampler(&name, config);
+                }
                 Command::UpdateShadowMapOrthoProj(size) => {
                     let scale = size * 0.9 / LightsBindGroup::ORTHO_H;
                     log::debug!("Update shadow map ortho proj scale: {}", scale.max(1.0));
@@ -429,71 +1469,329 @@ impl Renderer {
             }
         }
 
-        let mut sampler_indices = [0u32; BlinnPhongRenderPass::MAX_TEXTURE_ARRAY_LEN];
-        let default_texture = self.textures.get(self.textures.default_texture()).unwrap();
-        let default_sampler = self.samplers.get("linear").unwrap();
-        let default_texture_view = &default_texture.view;
-        // Create bind groups for each texture bundle.
-        for bundle in self.texture_bundles.iter_mut() {
-            sampler_indices.fill(0);
-            if bundle.textures.is_empty() || bundle.bind_group.is_some() {
+        self.reload_changed_files();
+        self.shaders.poll();
+        self.sprite_atlas.upload(&self.queue);
+
+        // Compact the mesh data buffer once removed meshes left too many
+        // holes in it.
+        if self.meshes.defragment(&self.device, &self.queue) {
+            log::info!("Compacted mesh buffer: {:?}", self.meshes.stats());
+            self.draws_generation += 1;
+        }
+
+        if self.binding_arrays && self.textures_bind_group.is_none() {
+            self.textures_dirty = true;
+        }
+        if self.textures_dirty {
+            self.update_textures_bind_group();
+        }
+        if !self.binding_arrays {
+            self.update_material_slots();
+        }
+    }
+
+    /// Loads the environment map from its faces, if any.
+    fn load_environment_map(&mut self) {
+        self.environment_map = self.environment_faces.as_ref().and_then(|faces| {
+            EnvironmentMap::load(&self.device, &self.queue, faces)
+                .map_err(|err| log::error!("Failed to load environment map: {}", err))
+                .ok()
+        });
+        self.environment_generation += 1;
+    }
+
+    /// Recreates the GPU resources of the renderer on the device of the
+    /// given context, after the previous device was lost.
+    ///
+    /// Meshes and materials are uploaded again from their CPU copies and
+    /// textures are reloaded from their files, keeping their handles valid.
+    pub fn restore(&mut self, context: &GpuContext) {
+        profiling::scope!("Renderer::restore");
+        self.device = context.device.clone();
+        self.queue = context.queue.clone();
+        self.limits = context.limits.clone();
+
+        self.meshes
+            .restore(&self.device, &self.queue, &self.mesh_sources);
+        self.textures.restore(
+            &self.device,
+            &self.queue,
+            self.loaded_textures
+                .iter()
+                .filter(|(_, handle)| {
+                    !self
+                        .camera_targets
+                        .values()
+                        .any(|target| target.texture == **handle)
+                })
+                .map(|((path, format), handle)| (*handle, path.as_path(), *format)),
+        );
+        for target in self.camera_targets.values_mut() {
+            *target = CameraTarget::new(
+                &self.device,
+                &mut self.textures,
+                target.name.clone(),
+                (target.target.size.width, target.target.size.height),
+                Some(target.texture),
+            );
+        }
+        // The reflection is created again on the next frame.
+        self.water_reflection = None;
+        self.samplers.clear();
+        self.sprite_atlas.restore(&self.device);
+        // The evicted textures are loaded again as well.
+        self.texture_budget.clear();
+        let textures = self.loaded_textures.values().copied().collect::<Vec<_>>();
+        for texture in textures {
+            let evictable = !self
+                .camera_targets
+                .values()
+                .any(|target| target.texture == texture);
+            self.track_texture(texture, evictable);
+        }
+
+        self.material_bundles.insert(
+            self.default_material_bundle,
+            MaterialBundle::default(&self.device),
+        );
+        for (handle, gpu_mtls) in &self.material_sources {
+            let Some(hashes) = self
+                .material_bundles
+                .get(*handle)
+                .map(|bundle| bundle.materials.clone())
+            else {
                 continue;
-            }
+            };
+            let mut bundle = MaterialBundle::new(&self.device, std::iter::empty(), gpu_mtls);
+            bundle.materials = hashes;
+            self.material_bundles.insert(*handle, bundle);
+        }
 
-            // Populate texture views and samplers with default values.
-            let mut views = [default_texture_view; BlinnPhongRenderPass::MAX_TEXTURE_ARRAY_LEN];
-            let mut samplers =
-                [&default_sampler.sampler; BlinnPhongRenderPass::MAX_SAMPLER_ARRAY_LEN];
+        self.load_environment_map();
+        self.textures_bind_group = None;
+        self.textures_dirty = true;
+        self.draws_generation += 1;
+        log::info!("Renderer restored on the new device");
+    }
 
-            for (i, hdl) in bundle.textures.iter().enumerate() {
-                let texture = self.textures.get(*hdl).unwrap();
-                views[i] = &texture.view;
-            }
+    /// Releases the GPU resources of all the loaded assets once the GPU is
+    /// done with them, leaving the renderer as if it was just created.
+    pub fn release(&mut self) {
+        profiling::scope!("Renderer::release");
+        self.device.poll(wgpu::Maintain::Wait);
+        self.file_watcher = None;
+        self.environment_faces = None;
+        self.environment_map = None;
+        self.camera_targets.clear();
+        self.minimap = None;
+        self.water_reflection = None;
+        self.textures_bind_group = None;
+        self.instancing.clear();
+        self.static_batches.clear();
+        self.aesthetic_bundles.clear();
+        self.loaded_textures.clear();
+        self.texture_budget.clear();
+        self.mesh_refs.clear();
+        self.material_refs.clear();
+        self.texture_bundle_refs.clear();
+        self.unpinned_meshes.clear();
+        self.loaded_meshes.clear();
+        self.mesh_sources.clear();
+        self.mesh_bvhs.clear();
+        self.material_sources.clear();
 
-            let mut unique_samplers = vec![];
-            for (idx, sampler) in bundle.samplers.iter().enumerate() {
-                if !unique_samplers.contains(sampler) {
-                    sampler_indices[idx] = unique_samplers.len() as u32;
-                    unique_samplers.push(sampler.clone());
-                }
-            }
+        self.meshes = GpuMeshAssets::new(&self.device);
+        self.textures = TextureAssets::new(&self.device, &self.queue);
+        self.sprite_atlas = TextureAtlas::new(&self.device);
+        self.material_bundles = MaterialBundleAssets::new();
+        self.default_material_bundle = self
+            .material_bundles
+            .add(MaterialBundle::default(&self.device));
+        self.texture_bundles = TextureBundleAssets::new();
+        self.default_texture_bundle = self.texture_bundles.add(TextureBundle {
+            textures: vec![self.textures.default_texture()],
+            slots: Vec::new(),
+        });
+
+        self.textures_dirty = true;
+        self.draws_generation += 1;
+        self.environment_generation += 1;
+        log::info!("Renderer resources released");
+    }
 
-            for (i, sampler) in unique_samplers.iter().enumerate() {
-                samplers[i] = self.samplers.get(sampler).unwrap();
+    /// Recreates the bind group of the global texture array, called when
+    /// textures are added.
+    ///
+    /// Without binding arrays, the bind groups of the materials are created
+    /// again instead, see [`Renderer::update_material_slots`].
+    fn update_textures_bind_group(&mut self) {
+        profiling::scope!("Renderer::update_textures_bind_group");
+        if !self.binding_arrays {
+            self.material_slots.clear();
+            self.textures_dirty = false;
+            self.draws_generation += 1;
+            return;
+        }
+        let len = self.texture_array_len as usize;
+        let default_texture = self.textures.get(self.textures.default_texture()).unwrap();
+        // Create the samplers requested by the textures, the ones with an
+        // unknown sampler use the default sampler.
+        self.samplers
+            .prepare(&self.device, SamplerRegistry::DEFAULT);
+        for texture in self.textures.iter().take(len) {
+            if !self.samplers.prepare(&self.device, &texture.sampler) {
+                log::warn!(
+                    "Unknown texture sampler {}, use the default sampler instead.",
+                    texture.sampler
+                );
             }
+        }
+        let default_sampler = self.samplers.get(SamplerRegistry::DEFAULT).unwrap();
+
+        // Populate texture views and samplers with default values.
+        let mut views = vec![&default_texture.view; len];
+        let mut texture_samplers = vec![&default_sampler.sampler; len];
+        let mut sampler_indices = vec![0u32; len];
+        let mut samplers = [&default_sampler.sampler; BlinnPhongRenderPass::MAX_SAMPLER_ARRAY_LEN];
+
+        // The default sampler comes first, used by the textures whose sampler
+        // doesn't fit in the sampler array.
+        let mut unique_samplers: Vec<&str> = vec![SamplerRegistry::DEFAULT];
+        for (i, texture) in self.textures.iter().enumerate().take(len) {
+            views[i] = &texture.view;
+            let name = match self.samplers.get(&texture.sampler) {
+                Some(sampler) => {
+                    texture_samplers[i] = &sampler.sampler;
+                    texture.sampler.as_str()
+                }
+                None => SamplerRegistry::DEFAULT,
+            };
+            let sampler_idx = match unique_samplers.iter().position(|s| *s == name) {
+                Some(idx) => idx,
+                None if unique_samplers.len() < BlinnPhongRenderPass::MAX_SAMPLER_ARRAY_LEN => {
+                    unique_samplers.push(name);
+                    unique_samplers.len() - 1
+                }
+                None => {
+                    log::error!("Too many texture samplers, use the default sampler instead.");
+                    0
+                }
+            };
+            sampler_indices[i] = sampler_idx as u32;
+        }
+        for (i, sampler) in unique_samplers.iter().enumerate() {
+            samplers[i] = &self.samplers.get(sampler).unwrap().sampler;
+        }
 
-            bundle.sampler_index_buffer = Some(self.device.create_buffer_init(
-                &wgpu::util::BufferInitDescriptor {
+        let bind_group_layout = textures_bind_group_layout(&self.device, self.texture_array_len);
+        let sampler_index_buffer =
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("sampler_index_buffer"),
                     contents: bytemuck::cast_slice(&sampler_indices),
-                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+                    usage: wgpu::BufferUsages::STORAGE,
+                });
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("shading_textures_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureViewArray(&views),
                 },
-            ));
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: sampler_index_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::SamplerArray(&samplers),
+                },
+            ],
+        });
+        self.textures_bind_group = Some(bind_group);
+        self.textures_dirty = false;
+        self.draws_generation += 1;
+    }
+
+    /// Creates the bind groups of the materials of the drawn meshes with the
+    /// textures of their slots, without binding arrays. Unused slots are
+    /// bound to the default texture.
+    fn update_material_slots(&mut self) {
+        profiling::scope!("Renderer::update_material_slots");
+        let aesthetics = self
+            .instancing
+            .keys()
+            .map(|bundle| bundle.aesthetic)
+            .collect::<FxHashSet<_>>();
+        self.material_slots
+            .retain(|aesthetic, _| aesthetics.contains(aesthetic));
 
-            let bind_group_layout = texture_bundle_bind_group_layout(&self.device);
+        self.samplers
+            .prepare(&self.device, SamplerRegistry::DEFAULT);
+        let mut layout = None;
+        let mut created = false;
+        for aesthetic in aesthetics {
+            if self.material_slots.contains_key(&aesthetic) {
+                continue;
+            }
+            let (Some(materials), Some(textures)) = (
+                self.material_bundles.get(aesthetic.materials),
+                self.texture_bundles.get(aesthetic.textures),
+            ) else {
+                continue;
+            };
+            let default_texture = self.textures.get(self.textures.default_texture()).unwrap();
+            let mut views = [&default_texture.view; BlinnPhongRenderPass::MAX_TEXTURE_SLOTS];
+            let mut sampler_names =
+                [SamplerRegistry::DEFAULT; BlinnPhongRenderPass::MAX_TEXTURE_SLOTS];
+            for (i, handle) in textures.slots.iter().enumerate() {
+                if let Some(texture) = self.textures.get(*handle) {
+                    views[i] = &texture.view;
+                    if self.samplers.prepare(&self.device, &texture.sampler) {
+                        sampler_names[i] = texture.sampler.as_str();
+                    } else {
+                        log::warn!(
+                            "Unknown texture sampler {}, use the default sampler instead.",
+                            texture.sampler
+                        );
+                    }
+                }
+            }
+            let mut entries = vec![wgpu::BindGroupEntry {
+                binding: 0,
+                resource: materials.buffer.as_entire_binding(),
+            }];
+            entries.extend(
+                views
+                    .iter()
+                    .enumerate()
+                    .map(|(i, view)| wgpu::BindGroupEntry {
+                        binding: 1 + i as u32,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    }),
+            );
+            entries.extend(sampler_names.iter().enumerate().map(|(i, name)| {
+                wgpu::BindGroupEntry {
+                    binding: 1 + (BlinnPhongRenderPass::MAX_TEXTURE_SLOTS + i) as u32,
+                    resource: wgpu::BindingResource::Sampler(
+                        &self.samplers.get(name).unwrap().sampler,
+                    ),
+                }
+            }));
+            let layout =
+                layout.get_or_insert_with(|| material_slots_bind_group_layout(&self.device));
             let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("shading_textures_bind_group"),
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureViewArray(&views),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: bundle
-                            .sampler_index_buffer
-                            .as_ref()
-                            .unwrap()
-                            .as_entire_binding(),
-                    },
-                    wgpu::BindGroupEntry {
-                        binding: 2,
-                        resource: wgpu::BindingResource::SamplerArray(&samplers),
-                    },
-                ],
+                label: Some("material_slots_bind_group"),
+                layout,
+                entries: &entries,
             });
-            bundle.bind_group.replace(bind_group);
+            self.material_slots.insert(aesthetic, bind_group);
+            created = true;
+        }
+        if created {
+            self.draws_generation += 1;
         }
     }
 
@@ -505,15 +1803,8 @@ impl Renderer {
         rpass: &mut dyn RenderingPass,
     ) -> Result<(), wgpu::SurfaceError> {
         profiling::scope!("Renderer::render");
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Render"),
-            });
-
-        rpass.record(self, target, &self.params, scene, &mut encoder);
-
-        self.queue.submit(std::iter::once(encoder.finish()));
+        let commands = rpass.record(self, target, &self.params, scene);
+        self.queue.submit(commands);
         Ok(())
     }
 }
@@ -0,0 +1,404 @@
+//! CPU path tracer rendering reference images of a scene.
+//!
+//! The path tracer renders the same scene as the rasterizer, with the
+//! materials approximated by their diffuse and specular colors, so that the
+//! images of both can be compared. Texture maps and vertex normals are
+//! ignored: surfaces are shaded with their geometric normals.
+
+use crate::{
+    core::{
+        bvh::{Bvh, Ray, TriangleBvh},
+        camera::Camera,
+        mesh::MeshBundle,
+        FxHashMap, Light, Rng,
+    },
+    render::Renderer,
+    scene::{Baked, NodeIdx, Scene},
+};
+use glam::{Mat3, Mat4, Vec3};
+use legion::{component, IntoQuery};
+use std::{f32::consts::TAU, path::Path, sync::Arc};
+
+/// Diffuse and specular approximation of a material.
+#[derive(Clone, Copy, Debug)]
+struct Surface {
+    kd: Vec3,
+    ks: Vec3,
+    ns: f32,
+}
+
+impl Surface {
+    /// Blinn-Phong BRDF times the cosine of the incident direction, the same
+    /// as the rasterizer.
+    fn shade(&self, wi: Vec3, wo: Vec3, n: Vec3) -> Vec3 {
+        let cos_i = n.dot(wi).max(0.0);
+        if cos_i <= 0.0 {
+            return Vec3::ZERO;
+        }
+        let h = (wi + wo).normalize_or_zero();
+        self.kd * cos_i + self.ks * n.dot(h).max(0.0).powf(self.ns)
+    }
+}
+
+/// Light of the scene in world space.
+#[derive(Clone, Copy, Debug)]
+enum TracedLight {
+    Directional { to_light: Vec3, color: Vec3 },
+    Point { position: Vec3, color: Vec3 },
+}
+
+/// Mesh instance of the scene.
+struct TracedInstance {
+    bvh: Arc<TriangleBvh>,
+    inv_model: Mat4,
+    /// Transforms the normals from local to world space.
+    normal_matrix: Mat3,
+    surfaces: Arc<[Surface]>,
+    /// Index in `surfaces` of the material of each triangle.
+    triangle_surfaces: Arc<[u32]>,
+}
+
+/// Hit of a ray with the scene.
+struct SceneHit {
+    t: f32,
+    normal: Vec3,
+    surface: Surface,
+}
+
+/// Path tracer over a snapshot of a scene, rendering it from its main camera.
+pub struct PathTracer {
+    instances: Bvh<TracedInstance>,
+    lights: Vec<TracedLight>,
+    /// Radiance of the rays escaping the scene.
+    background: Vec3,
+    inv_view_proj: Mat4,
+    width: u32,
+    height: u32,
+}
+
+impl PathTracer {
+    /// Maximum number of bounces of a path.
+    pub const MAX_BOUNCES: u32 = 8;
+    /// Number of bounces after which paths are randomly terminated.
+    const MIN_BOUNCES: u32 = 3;
+
+    /// Takes a snapshot of the visible meshes and active lights of the scene,
+    /// to render images of `width` x `height` pixels from its main camera.
+    ///
+    /// Returns `None` if the scene has no camera.
+    pub fn new(scene: &Scene, renderer: &mut Renderer, width: u32, height: u32) -> Option<Self> {
+        profiling::scope!("PathTracer::new");
+        let mut camera_query = <(&Camera, &NodeIdx)>::query();
+        let cameras = camera_query.iter(&scene.world).collect::<Vec<_>>();
+        // Use the first camera if there is no main camera.
+        let (camera, camera_node) = cameras
+            .iter()
+            .find(|(camera, _)| camera.is_main)
+            .or(cameras.first())
+            .copied()?;
+        let aspect = camera
+            .fixed_aspect
+            .unwrap_or(width as f32 / height.max(1) as f32);
+        let view = scene.nodes.inverse_world(*camera_node).to_mat4();
+        let inv_view_proj = (camera.proj_matrix(aspect, false) * view).inverse();
+        let background = Vec3::new(
+            camera.background.r as f32,
+            camera.background.g as f32,
+            camera.background.b as f32,
+        );
+
+        let lights = <(&Light, &NodeIdx)>::query()
+            .iter(&scene.world)
+            .filter(|(_, node)| scene.nodes[**node].is_active())
+            .map(|(light, node)| {
+                let world = scene.nodes.world(*node);
+                match light {
+                    Light::Directional { color, .. } => TracedLight::Directional {
+                        to_light: -light.world_direction(&world).unwrap_or(Vec3::NEG_Y),
+                        color: Vec3::new(color.r as f32, color.g as f32, color.b as f32),
+                    },
+                    Light::Point { color } => TracedLight::Point {
+                        position: world.translation,
+                        color: Vec3::new(color.r as f32, color.g as f32, color.b as f32),
+                    },
+                }
+            })
+            .collect();
+
+        let mut triangle_surfaces = FxHashMap::default();
+        let mut surfaces = FxHashMap::default();
+        let mut instances = Vec::new();
+        let mut query = <(&MeshBundle, &NodeIdx)>::query().filter(!component::<Baked>());
+        for (mesh, node) in query.iter(&scene.world) {
+            if !scene.nodes[*node].is_visible() {
+                continue;
+            }
+            let Some(bvh) = renderer.mesh_bvh(mesh.mesh) else {
+                continue;
+            };
+            let Some(gpu_mesh) = renderer.meshes.get(mesh.mesh) else {
+                continue;
+            };
+            let materials = surfaces
+                .entry(mesh.aesthetic.materials)
+                .or_insert_with(|| {
+                    let materials = renderer
+                        .material_sources
+                        .get(&mesh.aesthetic.materials)
+                        .map(|mtls| mtls.as_slice())
+                        .unwrap_or_default();
+                    let mut surfaces = materials
+                        .iter()
+                        .map(|mtl| Surface {
+                            kd: Vec3::from_slice(&mtl.kd[..3]),
+                            ks: if mtl.illum == 2 {
+                                Vec3::from_slice(&mtl.ks[..3])
+                            } else {
+                                Vec3::ZERO
+                            },
+                            ns: mtl.ns,
+                        })
+                        .collect::<Vec<_>>();
+                    if surfaces.is_empty() {
+                        surfaces.push(Surface {
+                            kd: Vec3::splat(0.8),
+                            ks: Vec3::ZERO,
+                            ns: 1.0,
+                        });
+                    }
+                    Arc::<[Surface]>::from(surfaces)
+                })
+                .clone();
+            let triangles = triangle_surfaces
+                .entry((mesh.mesh, materials.len()))
+                .or_insert_with(|| {
+                    // The default material is the last one of the bundle.
+                    let default = materials.len() as u32 - 1;
+                    let mut triangles = vec![default; bvh.len()];
+                    for sub_mesh in gpu_mesh.sub_meshes.iter().flatten() {
+                        let material = sub_mesh
+                            .material
+                            .filter(|m| *m < default)
+                            .unwrap_or(default);
+                        let start = (sub_mesh.range.start / 3) as usize;
+                        let end = ((sub_mesh.range.end / 3) as usize).min(triangles.len());
+                        triangles[start.min(end)..end].fill(material);
+                    }
+                    Arc::<[u32]>::from(triangles)
+                })
+                .clone();
+            let model = scene.nodes.world(*node).to_mat4();
+            let bounds = bvh.bounds().transform(&model);
+            instances.push((
+                TracedInstance {
+                    bvh,
+                    inv_model: model.inverse(),
+                    normal_matrix: Mat3::from_mat4(model).inverse().transpose(),
+                    surfaces: materials,
+                    triangle_surfaces: triangles,
+                },
+                bounds,
+            ));
+        }
+
+        Some(Self {
+            instances: Bvh::new(instances),
+            lights,
+            background,
+            inv_view_proj,
+            width,
+            height,
+        })
+    }
+
+    /// Adds one sample of every pixel to `accum`, in row-major order.
+    ///
+    /// Samples are deterministic: the same sample index gives the same image.
+    /// The rows are traced in parallel.
+    pub fn render_sample(&self, sample: u32, accum: &mut [Vec3]) {
+        profiling::scope!("PathTracer::render_sample");
+        let width = self.width as usize;
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = (self.height as usize).div_ceil(threads).max(1);
+        std::thread::scope(|s| {
+            for (i, chunk) in accum.chunks_mut(rows_per_thread * width).enumerate() {
+                s.spawn(move || {
+                    let first = i * rows_per_thread * width;
+                    for (j, pixel) in chunk.iter_mut().enumerate() {
+                        let (x, y) = ((first + j) % width, (first + j) / width);
+                        *pixel += self.trace_pixel(x as u32, y as u32, sample);
+                    }
+                });
+            }
+        });
+    }
+
+    /// Writes the average of `samples` samples accumulated in `accum` as an
+    /// sRGB image.
+    pub fn save(
+        &self,
+        accum: &[Vec3],
+        samples: u32,
+        path: impl AsRef<Path>,
+    ) -> image::ImageResult<()> {
+        let scale = 1.0 / samples.max(1) as f32;
+        let image = image::RgbImage::from_fn(self.width, self.height, |x, y| {
+            let color = accum[(y * self.width + x) as usize] * scale;
+            image::Rgb(color.to_array().map(linear_to_srgb))
+        });
+        image.save(path)
+    }
+
+    /// Traces a path through a random point of the pixel.
+    fn trace_pixel(&self, x: u32, y: u32, sample: u32) -> Vec3 {
+        let seed = ((sample as u64) << 40) ^ ((y as u64) << 20) ^ x as u64;
+        let mut rng = Rng::new(seed);
+        let ndc_x = (x as f32 + rng.next_f32()) / self.width as f32 * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y as f32 + rng.next_f32()) / self.height as f32 * 2.0;
+        let near = self
+            .inv_view_proj
+            .project_point3(Vec3::new(ndc_x, ndc_y, 0.0));
+        let far = self
+            .inv_view_proj
+            .project_point3(Vec3::new(ndc_x, ndc_y, 1.0));
+        let Some(direction) = (far - near).try_normalize() else {
+            return self.background;
+        };
+        self.trace(Ray::new(near, direction), &mut rng)
+    }
+
+    /// Returns the radiance coming back along the ray.
+    fn trace(&self, mut ray: Ray, rng: &mut Rng) -> Vec3 {
+        let mut radiance = Vec3::ZERO;
+        let mut throughput = Vec3::ONE;
+        for bounce in 0..Self::MAX_BOUNCES {
+            let Some(hit) = self.intersect(&ray, f32::INFINITY) else {
+                radiance += throughput * self.background;
+                break;
+            };
+            let point = ray.at(hit.t);
+            let wo = -ray.direction;
+            let n = if hit.normal.dot(wo) < 0.0 {
+                -hit.normal
+            } else {
+                hit.normal
+            };
+            // Offset the secondary rays to not hit the surface again.
+            let origin = point + n * 1e-4 * point.abs().max_element().max(1.0);
+
+            // Light sources are points, only reached by sampling them.
+            for light in &self.lights {
+                let (wi, color, dist) = match *light {
+                    TracedLight::Directional { to_light, color } => {
+                        (to_light, color, f32::INFINITY)
+                    }
+                    TracedLight::Point { position, color } => {
+                        let to_light = position - origin;
+                        let dist = to_light.length();
+                        (to_light / dist, color / (1.0 + 0.02 * dist * dist), dist)
+                    }
+                };
+                if n.dot(wi) <= 0.0 || self.intersect(&Ray::new(origin, wi), dist).is_some() {
+                    continue;
+                }
+                radiance += throughput * color * hit.surface.shade(wi, wo, n);
+            }
+
+            // Continue the path along the diffuse or the specular lobe.
+            let Surface { kd, ks, ns } = hit.surface;
+            let (diffuse, specular) = (kd.max_element(), ks.max_element());
+            if diffuse + specular <= 0.0 {
+                break;
+            }
+            let p_specular = specular / (diffuse + specular);
+            let wi = if rng.next_f32() < p_specular {
+                let reflected = n * 2.0 * n.dot(wo) - wo;
+                let wi = sample_lobe(reflected, ns, rng);
+                let cos_i = n.dot(wi);
+                if cos_i <= 0.0 {
+                    break;
+                }
+                throughput *= ks * cos_i / p_specular;
+                wi
+            } else {
+                // Cosine-weighted sampling cancels out the cosine and the
+                // 1 / pi of the Lambertian BRDF.
+                throughput *= kd / (1.0 - p_specular);
+                sample_lobe(n, 1.0, rng)
+            };
+
+            if bounce >= Self::MIN_BOUNCES {
+                let survival = throughput.max_element().min(0.95);
+                if rng.next_f32() >= survival {
+                    break;
+                }
+                throughput /= survival;
+            }
+            ray = Ray::new(origin, wi);
+        }
+        radiance
+    }
+
+    /// Returns the closest hit of the ray with the meshes, closer than
+    /// `max_t`. The direction of the ray must be normalized.
+    fn intersect(&self, ray: &Ray, max_t: f32) -> Option<SceneHit> {
+        self.instances
+            .intersect(ray, max_t, |instance, max_t| {
+                // The local direction is not normalized so that distances
+                // along the local ray are the same as in world space.
+                let local_ray = Ray::new(
+                    instance.inv_model.transform_point3(ray.origin),
+                    instance.inv_model.transform_vector3(ray.direction),
+                );
+                let hit = instance.bvh.intersect(&local_ray, max_t)?;
+                let surface = instance.triangle_surfaces[hit.triangle as usize];
+                Some((
+                    hit.t,
+                    SceneHit {
+                        t: hit.t,
+                        normal: (instance.normal_matrix * hit.normal).normalize_or_zero(),
+                        surface: instance.surfaces[surface as usize],
+                    },
+                ))
+            })
+            .map(|(_, hit)| hit)
+    }
+}
+
+/// Samples a direction around `axis` with a density proportional to the
+/// cosine to the axis raised to the power `exponent`.
+fn sample_lobe(axis: Vec3, exponent: f32, rng: &mut Rng) -> Vec3 {
+    let cos_theta = rng.next_f32().powf(1.0 / (exponent + 1.0));
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = TAU * rng.next_f32();
+    let (b1, b2) = axis.any_orthonormal_pair();
+    (b1 * phi.cos() * sin_theta + b2 * phi.sin() * sin_theta + axis * cos_theta).normalize()
+}
+
+/// Converts a linear color component to an 8-bit sRGB value.
+fn linear_to_srgb(c: f32) -> u8 {
+    let c = c.clamp(0.0, 1.0);
+    let s = if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    (s * 255.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lobe_samples_around_axis() {
+        let mut rng = Rng::new(7);
+        for _ in 0..256 {
+            let dir = sample_lobe(Vec3::Y, 1.0, &mut rng);
+            assert!((dir.length() - 1.0).abs() < 1e-4);
+            assert!(dir.y >= 0.0);
+        }
+        assert_eq!(linear_to_srgb(0.0), 0);
+        assert_eq!(linear_to_srgb(1.0), 255);
+    }
+}
@@ -0,0 +1,460 @@
+use crossbeam_channel::Sender;
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Instant,
+};
+
+/// Number of frames that can be copied from the GPU at the same time, frames
+/// are dropped if the writer can't keep up.
+const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+/// Extensions of the files recorded as videos with ffmpeg.
+const VIDEO_EXTENSIONS: [&str; 5] = ["mp4", "mkv", "webm", "mov", "avi"];
+
+/// State of a readback buffer.
+const BUFFER_FREE: u8 = 0;
+const BUFFER_PENDING: u8 = 1;
+const BUFFER_READY: u8 = 2;
+
+/// Frame read back from the GPU.
+struct Frame {
+    width: u32,
+    height: u32,
+    /// RGBA8 pixels, rows are tightly packed.
+    pixels: Vec<u8>,
+    /// Number of times the frame is written to keep the requested frame rate.
+    repeat: u32,
+}
+
+/// Buffer the frames are copied to.
+struct ReadbackBuffer {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    /// Bytes per row, aligned as required by wgpu.
+    padded_bytes_per_row: u32,
+    state: Arc<AtomicU8>,
+    /// Whether the texels are stored as BGRA.
+    bgra: bool,
+    repeat: u32,
+}
+
+/// Running recording.
+struct Recording {
+    device: Arc<wgpu::Device>,
+    /// Frame rate of the recording.
+    fps: f32,
+    /// Time not yet covered by recorded frames, in seconds.
+    pending_time: f32,
+    /// Time of the previous frame.
+    prev_frame: Instant,
+    buffers: Vec<ReadbackBuffer>,
+    /// Indices of the buffers being copied, in order of submission.
+    in_flight: VecDeque<usize>,
+    sender: Option<Sender<Frame>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+/// Records the presented frames to an image sequence or a video.
+///
+/// Frames are copied to buffers on the GPU and read back asynchronously;
+/// encoding and writing happen on a separate thread so that the rendering
+/// doesn't stall.
+#[derive(Default)]
+pub struct FrameRecorder {
+    recording: Option<Recording>,
+}
+
+impl FrameRecorder {
+    /// Returns true if frames are being recorded.
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Starts recording at the given frame rate.
+    ///
+    /// If the path has a video extension (e.g. `mp4`) and ffmpeg is
+    /// available, the frames are piped to ffmpeg. Otherwise, the frames are
+    /// written as numbered PNG images in the directory `path` (without its
+    /// extension).
+    pub fn start(
+        &mut self,
+        device: Arc<wgpu::Device>,
+        path: &Path,
+        fps: f32,
+    ) -> std::io::Result<()> {
+        self.stop();
+        let fps = fps.max(1.0);
+        let (sender, receiver) = crossbeam_channel::bounded::<Frame>(MAX_FRAMES_IN_FLIGHT);
+        let is_video = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+        let ffmpeg_available = is_video
+            && Command::new("ffmpeg")
+                .arg("-version")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+        let mut writer: Box<dyn FrameWriter> = if ffmpeg_available {
+            Box::new(FfmpegWriter {
+                path: path.to_path_buf(),
+                fps,
+                process: None,
+                resized: false,
+            })
+        } else {
+            if is_video {
+                log::warn!("ffmpeg not found, recording an image sequence instead.");
+            }
+            let dir = path.with_extension("");
+            std::fs::create_dir_all(&dir)?;
+            Box::new(ImageSequenceWriter { dir, n_frames: 0 })
+        };
+        log::info!("Recording frames to {:?} at {} fps", path, fps);
+        let writer = std::thread::spawn(move || {
+            for frame in receiver {
+                if let Err(err) = writer.write(&frame) {
+                    log::error!("Failed to write recorded frame: {}", err);
+                    break;
+                }
+            }
+            if let Err(err) = writer.finish() {
+                log::error!("Failed to finish recording: {}", err);
+            }
+        });
+        self.recording = Some(Recording {
+            device,
+            fps,
+            pending_time: 0.0,
+            prev_frame: Instant::now(),
+            buffers: Vec::new(),
+            in_flight: VecDeque::new(),
+            sender: Some(sender),
+            writer: Some(writer),
+        });
+        Ok(())
+    }
+
+    /// Stops the recording, waiting for the frames being copied and written.
+    pub fn stop(&mut self) {
+        if let Some(mut recording) = self.recording.take() {
+            for index in recording.in_flight.drain(..) {
+                let buffer = &recording.buffers[index];
+                while buffer.state.load(Ordering::Acquire) == BUFFER_PENDING {
+                    recording.device.poll(wgpu::Maintain::Wait);
+                }
+                if buffer.state.load(Ordering::Acquire) == BUFFER_READY {
+                    if let Some(sender) = &recording.sender {
+                        let _ = sender.send(buffer.read());
+                    }
+                }
+            }
+            // Closes the channel, the writer finishes once all frames are
+            // written.
+            recording.sender = None;
+            if let Some(writer) = recording.writer.take() {
+                let _ = writer.join();
+            }
+            log::info!("Recording stopped");
+        }
+    }
+
+    /// Copies the frame about to be presented.
+    ///
+    /// The surface texture must have been configured with the `COPY_SRC`
+    /// usage.
+    pub fn capture(&mut self, queue: &wgpu::Queue, texture: &wgpu::Texture) {
+        let Some(recording) = &mut self.recording else {
+            return;
+        };
+        recording.send_ready_frames();
+        let device = recording.device.clone();
+
+        let now = Instant::now();
+        recording.pending_time += now.duration_since(recording.prev_frame).as_secs_f32();
+        recording.prev_frame = now;
+        let interval = 1.0 / recording.fps;
+        if recording.pending_time < interval {
+            return;
+        }
+        let repeat = (recording.pending_time / interval) as u32;
+        recording.pending_time -= repeat as f32 * interval;
+
+        let bgra = match texture.format() {
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            format => {
+                log::error!("Can't record frames of format {:?}", format);
+                return;
+            }
+        };
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            log::error!("Can't record frames, the surface can't be copied.");
+            return;
+        }
+
+        let (width, height) = (texture.width(), texture.height());
+        // Buffers of a previous size are not reused.
+        recording.buffers.iter_mut().for_each(|buffer| {
+            if buffer.state.load(Ordering::Acquire) == BUFFER_FREE
+                && (buffer.width, buffer.height) != (width, height)
+            {
+                *buffer = ReadbackBuffer::new(&device, width, height);
+            }
+        });
+        let index = match recording
+            .buffers
+            .iter()
+            .position(|b| b.state.load(Ordering::Acquire) == BUFFER_FREE)
+        {
+            Some(index) => index,
+            None if recording.buffers.len() < MAX_FRAMES_IN_FLIGHT => {
+                recording
+                    .buffers
+                    .push(ReadbackBuffer::new(&device, width, height));
+                recording.buffers.len() - 1
+            }
+            None => {
+                log::warn!("Recording can't keep up, dropping a frame.");
+                return;
+            }
+        };
+
+        let buffer = &mut recording.buffers[index];
+        buffer.bgra = bgra;
+        buffer.repeat = repeat;
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("frame_recorder_copy"),
+        });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer.buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(buffer.padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+
+        buffer.state.store(BUFFER_PENDING, Ordering::Release);
+        let state = buffer.state.clone();
+        buffer
+            .buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| match result {
+                Ok(_) => state.store(BUFFER_READY, Ordering::Release),
+                Err(err) => {
+                    log::error!("Failed to read back recorded frame: {}", err);
+                    state.store(BUFFER_FREE, Ordering::Release);
+                }
+            });
+        recording.in_flight.push_back(index);
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl Recording {
+    /// Sends the frames already read back to the writer, in order.
+    fn send_ready_frames(&mut self) {
+        self.device.poll(wgpu::Maintain::Poll);
+        while let Some(&index) = self.in_flight.front() {
+            let buffer = &self.buffers[index];
+            match buffer.state.load(Ordering::Acquire) {
+                BUFFER_PENDING => break,
+                BUFFER_READY => {
+                    if let Some(sender) = &self.sender {
+                        if sender.try_send(buffer.read()).is_err() {
+                            log::warn!("Recording can't keep up, dropping a frame.");
+                        }
+                    }
+                }
+                _ => {}
+            }
+            self.in_flight.pop_front();
+        }
+    }
+}
+
+impl ReadbackBuffer {
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let padded_bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame_recorder_readback"),
+            size: padded_bytes_per_row as u64 * height as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            state: Arc::new(AtomicU8::new(BUFFER_FREE)),
+            bgra: false,
+            repeat: 1,
+        }
+    }
+
+    /// Reads the mapped buffer as RGBA8 pixels and frees the buffer.
+    fn read(&self) -> Frame {
+        let row_size = self.width as usize * 4;
+        let mut pixels = Vec::with_capacity(row_size * self.height as usize);
+        {
+            let data = self.buffer.slice(..).get_mapped_range();
+            for row in data.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..row_size]);
+            }
+        }
+        self.buffer.unmap();
+        self.state.store(BUFFER_FREE, Ordering::Release);
+        if self.bgra {
+            pixels.chunks_exact_mut(4).for_each(|px| px.swap(0, 2));
+        }
+        Frame {
+            width: self.width,
+            height: self.height,
+            pixels,
+            repeat: self.repeat,
+        }
+    }
+}
+
+/// Destination of the recorded frames.
+trait FrameWriter: Send {
+    fn write(&mut self, frame: &Frame) -> std::io::Result<()>;
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes the frames as numbered PNG images.
+struct ImageSequenceWriter {
+    dir: PathBuf,
+    n_frames: u32,
+}
+
+impl FrameWriter for ImageSequenceWriter {
+    fn write(&mut self, frame: &Frame) -> std::io::Result<()> {
+        for _ in 0..frame.repeat {
+            let path = self.dir.join(format!("frame_{:06}.png", self.n_frames));
+            image::save_buffer(
+                &path,
+                &frame.pixels,
+                frame.width,
+                frame.height,
+                image::ExtendedColorType::Rgba8,
+            )
+            .map_err(std::io::Error::other)?;
+            self.n_frames += 1;
+        }
+        Ok(())
+    }
+}
+
+/// Pipes the frames to ffmpeg, which encodes the video.
+struct FfmpegWriter {
+    path: PathBuf,
+    fps: f32,
+    /// ffmpeg process, started with the size of the first frame.
+    process: Option<(Child, ChildStdin, (u32, u32))>,
+    /// Whether frames of another size than the video have been scaled.
+    resized: bool,
+}
+
+impl FrameWriter for FfmpegWriter {
+    fn write(&mut self, frame: &Frame) -> std::io::Result<()> {
+        if self.process.is_none() {
+            let mut child = Command::new("ffmpeg")
+                .args(["-y", "-loglevel", "error", "-f", "rawvideo"])
+                .args(["-pixel_format", "rgba", "-video_size"])
+                .arg(format!("{}x{}", frame.width, frame.height))
+                .arg("-framerate")
+                .arg(self.fps.to_string())
+                .args(["-i", "-"])
+                // yuv420p requires even dimensions.
+                .args([
+                    "-vf",
+                    "pad=ceil(iw/2)*2:ceil(ih/2)*2",
+                    "-pix_fmt",
+                    "yuv420p",
+                ])
+                .arg(&self.path)
+                .stdin(Stdio::piped())
+                .spawn()?;
+            let stdin = child.stdin.take().expect("ffmpeg stdin is piped");
+            self.process = Some((child, stdin, (frame.width, frame.height)));
+        }
+        let (_, stdin, (width, height)) = self.process.as_mut().unwrap();
+        if (*width, *height) == (frame.width, frame.height) {
+            for _ in 0..frame.repeat {
+                stdin.write_all(&frame.pixels)?;
+            }
+            return Ok(());
+        }
+        // The size of a video can't change, frames rendered after a resize
+        // are scaled to the size of the first frame.
+        if !self.resized {
+            log::warn!(
+                "Window resized while recording a video, frames are scaled from {}x{} to {}x{}.",
+                frame.width,
+                frame.height,
+                width,
+                height
+            );
+            self.resized = true;
+        }
+        let image = image::RgbaImage::from_raw(frame.width, frame.height, frame.pixels.clone())
+            .ok_or_else(|| std::io::Error::other("recorded frame has an invalid size"))?;
+        let scaled = image::imageops::resize(
+            &image,
+            *width,
+            *height,
+            image::imageops::FilterType::Triangle,
+        );
+        for _ in 0..frame.repeat {
+            stdin.write_all(scaled.as_raw())?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if let Some((mut child, stdin, _)) = self.process.take() {
+            // Closing stdin tells ffmpeg the video is complete.
+            drop(stdin);
+            let status = child.wait()?;
+            if !status.success() {
+                return Err(std::io::Error::other(format!(
+                    "ffmpeg exited with {}",
+                    status
+                )));
+            }
+            log::info!("Video written to {:?}", self.path);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{
+    core::{camera::Backdrop, FxHashMap},
+    render::{DepthSettings, Renderer, StagingRing},
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use std::path::{Path, PathBuf};
+
+/// Uniforms of the background pass.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct BackgroundUniforms {
+    /// Color at the top of the screen.
+    pub top: [f32; 4],
+    /// Color at the bottom of the screen.
+    pub bottom: [f32; 4],
+    /// x: mode (0: gradient, 1: image, 2: sky), yz: scale of the image uv,
+    /// w: unused.
+    pub params: [f32; 4],
+    /// Inverse of the view-projection matrix of the camera, giving the
+    /// direction of the sky seen through each pixel.
+    pub inv_view_proj: [[f32; 4]; 4],
+    /// xyz: unit vector towards the sun, w: cosine of the angular radius of
+    /// the sun disk.
+    pub sun: [f32; 4],
+    /// Coefficients A to E of the Perez function of the sky, for Y, x and
+    /// y in xyz, see [`SkyCoefficients`](crate::core::sky::SkyCoefficients).
+    pub perez: [[f32; 4]; 5],
+    /// xyz: Y, x and y at the zenith, w: exposure.
+    pub zenith: [f32; 4],
+    /// rgb: color of the ground, a: brightness of the sky.
+    pub ground: [f32; 4],
+}
+
+crate::impl_size_constant!(BackgroundUniforms);
+
+/// Image loaded for a backdrop.
+struct BackgroundImage {
+    bind_group: wgpu::BindGroup,
+    aspect_ratio: f32,
+}
+
+/// Draws the backdrop of the camera, a gradient, an image covering the
+/// screen or an analytical sky, before the geometry of the main pass.
+pub struct BackgroundRenderPass {
+    pipeline: wgpu::RenderPipeline,
+    uniforms_buffer: wgpu::Buffer,
+    uniforms_bind_group: wgpu::BindGroup,
+    image_bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    /// Image bound when drawing a gradient.
+    placeholder: wgpu::BindGroup,
+    /// Loaded images, `None` if the image failed to load.
+    images: FxHashMap<PathBuf, Option<BackgroundImage>>,
+    /// Image used by the backdrop prepared for the current frame, if any.
+    current_image: Option<PathBuf>,
+    /// Whether a backdrop is drawn in the current frame.
+    enabled: bool,
+}
+
+impl BackgroundRenderPass {
+    /// Creates a new background render pass.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
+        depth: DepthSettings,
+    ) -> Self {
+        let uniforms_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("background_uniforms_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: BackgroundUniforms::BUFFER_SIZE,
+                },
+                count: None,
+            }],
+        });
+        let uniforms_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("background_uniforms_buffer"),
+            size: BackgroundUniforms::SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let uniforms_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("background_uniforms_bind_group"),
+            layout: &uniforms_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: uniforms_buffer.as_entire_binding(),
+            }],
+        });
+
+        let image_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("background_image_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("background_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let placeholder = Self::create_image_bind_group(
+            device,
+            queue,
+            &image_bind_group_layout,
+            &sampler,
+            &image::RgbaImage::from_pixel(1, 1, image::Rgba([255; 4])),
+        );
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("background_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("background.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("background_pipeline_layout"),
+            bind_group_layouts: &[&uniforms_layout, &image_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("background_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            // Drawn behind everything, without writing depth.
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.texture_format(),
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: depth.multisample(),
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            uniforms_buffer,
+            uniforms_bind_group,
+            image_bind_group_layout,
+            sampler,
+            placeholder,
+            images: FxHashMap::default(),
+            current_image: None,
+            enabled: false,
+        }
+    }
+
+    fn create_image_bind_group(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        sampler: &wgpu::Sampler,
+        image: &image::RgbaImage,
+    ) -> wgpu::BindGroup {
+        let size = wgpu::Extent3d {
+            width: image.width(),
+            height: image.height(),
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("background_image"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * image.width()),
+                rows_per_image: Some(image.height()),
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("background_image_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Loads the image of a backdrop, once.
+    fn load_image(&mut self, renderer: &Renderer, path: &Path) -> Option<&BackgroundImage> {
+        if !self.images.contains_key(path) {
+            let image = match image::open(path) {
+                Ok(image) => {
+                    let image = image.to_rgba8();
+                    Some(BackgroundImage {
+                        aspect_ratio: image.width() as f32 / image.height().max(1) as f32,
+                        bind_group: Self::create_image_bind_group(
+                            &renderer.device,
+                            &renderer.queue,
+                            &self.image_bind_group_layout,
+                            &self.sampler,
+                            &image,
+                        ),
+                    })
+                }
+                Err(err) => {
+                    log::error!("Failed to load background image {:?}: {}", path, err);
+                    None
+                }
+            };
+            self.images.insert(path.to_path_buf(), image);
+        }
+        self.images.get(path).and_then(|image| image.as_ref())
+    }
+
+    /// Uploads the uniforms of the backdrop of the camera, `view_proj`
+    /// being its view-projection matrix, `exposure` the scale of the lit
+    /// colors and `aspect_ratio` the aspect ratio of the render target.
+    pub fn prepare(
+        &mut self,
+        renderer: &Renderer,
+        staging: &mut StagingRing,
+        encoder: &mut wgpu::CommandEncoder,
+        backdrop: Option<&Backdrop>,
+        view_proj: Mat4,
+        exposure: f32,
+        aspect_ratio: f32,
+    ) {
+        profiling::scope!("BackgroundRenderPass::prepare");
+        self.current_image = None;
+        let uniforms = match backdrop {
+            None => None,
+            Some(Backdrop::Gradient { top, bottom }) => Some(BackgroundUniforms {
+                top: (*top).into(),
+                bottom: (*bottom).into(),
+                params: [0.0, 1.0, 1.0, 0.0],
+                ..Zeroable::zeroed()
+            }),
+            Some(Backdrop::Sky(sky)) => {
+                let coefs = sky.coefficients();
+                let ground: [f32; 4] = sky.ground.into();
+                Some(BackgroundUniforms {
+                    params: [2.0, 1.0, 1.0, 0.0],
+                    inv_view_proj: view_proj.inverse().to_cols_array_2d(),
+                    sun: sky
+                        .sun
+                        .normalize_or(Vec3::Y)
+                        .extend(sky.sun_size.to_radians().cos())
+                        .to_array(),
+                    perez: coefs.perez.map(|c| [c[0], c[1], c[2], 0.0]),
+                    zenith: [
+                        coefs.zenith[0],
+                        coefs.zenith[1],
+                        coefs.zenith[2],
+                        sky.exposure.max(0.0) * exposure,
+                    ],
+                    ground: [ground[0], ground[1], ground[2], coefs.brightness],
+                    ..Zeroable::zeroed()
+                })
+            }
+            Some(Backdrop::Image(path)) => {
+                self.load_image(renderer, path).map(|image| {
+                    // Scales the image to cover the screen.
+                    let scale = if aspect_ratio > image.aspect_ratio {
+                        [1.0, image.aspect_ratio / aspect_ratio]
+                    } else {
+                        [aspect_ratio / image.aspect_ratio, 1.0]
+                    };
+                    BackgroundUniforms {
+                        top: [1.0; 4],
+                        bottom: [1.0; 4],
+                        params: [1.0, scale[0], scale[1], 0.0],
+                        ..Zeroable::zeroed()
+                    }
+                })
+            }
+        };
+        self.enabled = uniforms.is_some();
+        if let Some(uniforms) = uniforms {
+            if let Some(Backdrop::Image(path)) = backdrop {
+                self.current_image = Some(path.clone());
+            }
+            staging.write(
+                &renderer.device,
+                encoder,
+                &self.uniforms_buffer,
+                0,
+                bytemuck::bytes_of(&uniforms),
+            );
+        }
+    }
+
+    /// Draws the backdrop prepared for the current frame, if any. The bind
+    /// groups 0 and 1 must be set again afterwards.
+    pub fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        if !self.enabled {
+            return;
+        }
+        let image = self
+            .current_image
+            .as_ref()
+            .and_then(|path| self.images.get(path))
+            .and_then(|image| image.as_ref())
+            .map(|image| &image.bind_group)
+            .unwrap_or(&self.placeholder);
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
+        render_pass.set_bind_group(1, image, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
@@ -0,0 +1,104 @@
+struct Background {
+    top: vec4<f32>,
+    bottom: vec4<f32>,
+    // x: mode (0: gradient, 1: image, 2: sky), yz: scale of the image uv,
+    // w: unused.
+    params: vec4<f32>,
+    inv_view_proj: mat4x4<f32>,
+    // xyz: direction towards the sun, w: cosine of the radius of the sun disk.
+    sun: vec4<f32>,
+    // Perez coefficients A to E, for Y, x and y.
+    perez: array<vec4<f32>, 5>,
+    // xyz: Y, x and y at the zenith, w: exposure.
+    zenith: vec4<f32>,
+    // rgb: ground color, a: brightness of the sky.
+    ground: vec4<f32>,
+}
+
+struct VSOutput {
+    @builtin(position) pos: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0)
+var<uniform> background: Background;
+
+@group(1) @binding(0)
+var image: texture_2d<f32>;
+@group(1) @binding(1)
+var image_sampler: sampler;
+
+// Fullscreen triangle on the far plane.
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32) -> VSOutput {
+    let uv = vec2<f32>(f32((vertex_index << 1u) & 2u), f32(vertex_index & 2u));
+    var output: VSOutput;
+    output.pos = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 1.0, 1.0);
+    output.uv = uv;
+    return output;
+}
+
+// Perez distribution function, for Y, x and y at once.
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32) -> vec3<f32> {
+    let a = background.perez[0].xyz;
+    let b = background.perez[1].xyz;
+    let c = background.perez[2].xyz;
+    let d = background.perez[3].xyz;
+    let e = background.perez[4].xyz;
+    return (1.0 + a * exp(b / max(cos_theta, 0.01)))
+        * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+// Linear RGB luminance of the sky in the given direction, above the horizon.
+fn sky_radiance(dir: vec3<f32>) -> vec3<f32> {
+    let cos_gamma = clamp(dot(dir, background.sun.xyz), -1.0, 1.0);
+    let Yxy = background.zenith.xyz * perez(dir.y, acos(cos_gamma), cos_gamma);
+    // xyY to XYZ to linear sRGB.
+    let Y = Yxy.x;
+    let X = Yxy.y * Y / max(Yxy.z, 1e-4);
+    let Z = (1.0 - Yxy.y - Yxy.z) * Y / max(Yxy.z, 1e-4);
+    let rgb = mat3x3<f32>(
+        3.2406, -0.9689, 0.0557,
+        -1.5372, 1.8758, -0.2040,
+        -0.4986, 0.0415, 1.0570,
+    ) * vec3<f32>(X, Y, Z);
+    return max(rgb, vec3<f32>(0.0));
+}
+
+fn sky(uv: vec2<f32>) -> vec4<f32> {
+    let ndc = vec2<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let near = background.inv_view_proj * vec4<f32>(ndc, 0.0, 1.0);
+    let far = background.inv_view_proj * vec4<f32>(ndc, 1.0, 1.0);
+    let dir = normalize(far.xyz / far.w - near.xyz / near.w);
+
+    // The sky is evaluated at the horizon below it, and fades into the ground.
+    let above = vec3<f32>(dir.x, max(dir.y, 0.0), dir.z);
+    var radiance = sky_radiance(normalize(above + vec3<f32>(0.0, 1e-3, 0.0)));
+    let horizon = sky_radiance(normalize(vec3<f32>(dir.x, 1e-3, dir.z)));
+    let ground = background.ground.rgb * 2.0 * dot(horizon, vec3<f32>(0.2126, 0.7152, 0.0722));
+    radiance = mix(radiance, ground, smoothstep(0.0, 0.02, -dir.y));
+
+    // Sun disk with a soft edge, hidden below the horizon.
+    let cos_sun = dot(dir, background.sun.xyz);
+    let radius = 1.0 - background.sun.w;
+    let disk = smoothstep(background.sun.w - radius * 0.5, background.sun.w, cos_sun)
+        * smoothstep(-0.005, 0.005, dir.y);
+    radiance += vec3<f32>(1.0, 0.95, 0.85) * 200.0 * disk;
+
+    // Luminance in kcd/m^2 mapped to the display. At night, the sky fades
+    // into the background color of the camera.
+    let exposed = radiance * background.zenith.w * 0.08;
+    return vec4<f32>(1.0 - exp(-exposed), background.ground.a);
+}
+
+@fragment
+fn fs_main(in: VSOutput) -> @location(0) vec4<f32> {
+    if (background.params.x < 0.5) {
+        return mix(background.top, background.bottom, in.uv.y);
+    }
+    if (background.params.x > 1.5) {
+        return sky(in.uv);
+    }
+    let uv = (in.uv - 0.5) * background.params.yz + 0.5;
+    return textureSample(image, image_sampler, uv);
+}
@@ -3,39 +3,70 @@ use crate::render::GpuContext;
 use crate::{
     core::{
         camera::Camera,
-        mesh::{MeshBundle, VertexAttribute},
+        mesh::{GpuMesh, MeshBundle, VertexAttribute},
+        particle::ParticleEmitter,
+        sprite::Sprite,
+        water::Water,
         FxHashSet, GpuMaterial, Light,
     },
     render::{
         rpass::{
-            BlinnPhongRenderPass, Globals, GlobalsBindGroup, GpuLight, InstanceLocals, LightArray,
-            LightsBindGroup, Locals, LocalsBindGroup, PConsts, PConstsShadowPass, RenderingPass,
-            ShadowMaps, ShadowPassLocals, DEPTH_FORMAT,
+            oblique_projection, reflection_matrix, scene_color_attachment, BackgroundRenderPass,
+            BlinnPhongRenderPass, CustomShaderModules, DrawBounds, DrawBundleKey, DrawBundles,
+            DrawBundlesState, DrawConstants, EnvironmentMap, GizmoRenderPass, Globals,
+            GlobalsBindGroup, GpuCulling, GpuLight, IndirectDraws, InstanceLocals, LightArray,
+            LightsBindGroup, Locals, LocalsBindGroup, MinimapRenderPass, OcclusionCulling, PConsts,
+            PConstsShadowPass, ParticleRenderPass, RenderingPass, ShadowCasters, ShadowMaps,
+            ShadowPassLocals, SlotBindGroups, SpriteRenderPass, WaterRenderPass, DEPTH_FORMAT,
         },
-        PipelineId, PipelineKind, Pipelines, RenderParams, RenderTarget, Renderer,
+        validated, DepthSettings, PipelineId, PipelineKind, Pipelines, RenderGraph, RenderParams,
+        RenderTarget, Renderer, ShaderManager, StagingRing, TransientTextures, Viewport,
     },
-    scene::{NodeIdx, Nodes, Scene},
+    scene::{CustomShader, NodeIdx, Nodes, RenderLayer, Scene},
 };
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec3, Vec4};
 use legion::IntoQuery;
 use rustc_hash::FxHashMap;
-use std::num::{NonZeroU32, NonZeroU64};
+use std::{
+    num::{NonZeroU32, NonZeroU64},
+    ops::Range,
+    time::Instant,
+};
 
 impl GlobalsBindGroup {
     /// Creates a new globals bind group.
     pub fn new(device: &wgpu::Device) -> Self {
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("blph_globals_bg_layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: Globals::BUFFER_SIZE,
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Globals::BUFFER_SIZE,
+                    },
+                    count: None,
                 },
-                count: None,
-            }],
+                // Environment map reflected by the materials.
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
         });
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("blph_globals_buffer"),
@@ -43,20 +74,249 @@ impl GlobalsBindGroup {
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some("blph_globals_bg"),
-            layout: &layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: buffer.as_entire_binding(),
-            }],
-        });
+        // Textures are zero-initialized: the placeholder is transparent.
+        let placeholder_environment = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("blph_placeholder_environment"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 6,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::Cube),
+                ..Default::default()
+            });
+        let placeholder_sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        let bind_group = Self::create_bind_group(
+            device,
+            &layout,
+            &buffer,
+            &placeholder_environment,
+            &placeholder_sampler,
+        );
 
         Self {
             group: bind_group,
             layout,
             buffer,
+            placeholder_environment,
+            placeholder_sampler,
+        }
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        environment: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("blph_globals_bg"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(environment),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Returns the view and the sampler of the environment map, or of the
+    /// placeholder if `None`.
+    pub fn environment<'a>(
+        &'a self,
+        environment: Option<&'a EnvironmentMap>,
+    ) -> (&'a wgpu::TextureView, &'a wgpu::Sampler) {
+        match environment {
+            Some(env) => (&env.view, &env.sampler),
+            None => (&self.placeholder_environment, &self.placeholder_sampler),
+        }
+    }
+
+    /// Binds the environment map reflected by the materials, or the
+    /// placeholder if `None`.
+    pub fn set_environment(&mut self, device: &wgpu::Device, environment: Option<&EnvironmentMap>) {
+        let (view, sampler) = self.environment(environment);
+        self.group = Self::create_bind_group(device, &self.layout, &self.buffer, view, sampler);
+    }
+}
+
+impl SlotBindGroups {
+    /// Creates the layouts of the packed bind groups.
+    pub fn new(device: &wgpu::Device) -> Self {
+        let storage = |binding, visibility, min_binding_size| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size,
+            },
+            count: None,
+        };
+        let lights_size = wgpu::BufferSize::new(LightArray::buffer_size(1));
+        let frame_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("blph_frame_bg_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Globals::BUFFER_SIZE,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::Cube,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                storage(3, wgpu::ShaderStages::VERTEX, Locals::BUFFER_SIZE),
+                storage(4, wgpu::ShaderStages::VERTEX_FRAGMENT, lights_size),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+        });
+        let shadow_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("blph_shadow_maps_bg_layout"),
+            entries: &[
+                storage(0, wgpu::ShaderStages::VERTEX, ShadowPassLocals::BUFFER_SIZE),
+                storage(1, wgpu::ShaderStages::VERTEX_FRAGMENT, lights_size),
+            ],
+        });
+        Self {
+            frame_layout,
+            shadow_layout,
+            materials_layout: material_slots_bind_group_layout(device),
+            frame: None,
+            shadow: None,
+        }
+    }
+
+    /// Creates the frame group of the main pass binding the given resources.
+    /// The shaders sample the layers of the first texture of the shadow
+    /// maps.
+    pub fn update_frame(
+        &mut self,
+        device: &wgpu::Device,
+        globals: &GlobalsBindGroup,
+        environment: Option<&EnvironmentMap>,
+        locals: &LocalsBindGroup<Locals>,
+        lights: &LightsBindGroup,
+        shadow_maps: &ShadowMaps,
+    ) {
+        let (environment, environment_sampler) = globals.environment(environment);
+        self.frame = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("blph_frame_bg"),
+            layout: &self.frame_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: globals.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(environment),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(environment_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: locals.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: lights.lights_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(&shadow_maps.depth_textures[0].1),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::Sampler(&shadow_maps.depth_sampler),
+                },
+            ],
+        }));
+    }
+
+    /// Creates the group of the shadow maps pass if the locals buffer has
+    /// been resized since.
+    pub fn update_shadow(
+        &mut self,
+        device: &wgpu::Device,
+        locals: &LocalsBindGroup<ShadowPassLocals>,
+        lights: &LightsBindGroup,
+    ) {
+        if self
+            .shadow
+            .as_ref()
+            .is_some_and(|(capacity, _)| *capacity == locals.capacity)
+        {
+            return;
         }
+        let group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("blph_shadow_maps_bg"),
+            layout: &self.shadow_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: locals.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lights.lights_buffer.as_entire_binding(),
+                },
+            ],
+        });
+        self.shadow = Some((locals.capacity, group));
     }
 }
 
@@ -107,8 +367,11 @@ impl<L: InstanceLocals> LocalsBindGroup<L> {
             log::debug!("No need to resize instance locals buffer");
             return;
         }
-        // Calculate the new capacity with an increment of 256 instances.
-        let new_capacity = (n_instances / 256 + 1) * 256;
+        // Double the capacity, so that growing scenes only recreate the
+        // buffer a few times.
+        let new_capacity = n_instances
+            .next_power_of_two()
+            .max(Self::INITIAL_INSTANCE_CAPACITY as u32);
         let size = new_capacity as u64 * L::SIZE as u64;
         log::debug!("Resize instance locals buffer to {}", size);
         self.buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -136,8 +399,8 @@ impl LightsBindGroup {
     pub const ORTHO_H: f32 = 34.0;
     pub const ORTHO_W: f32 = 34.0;
 
-    /// Creates a new lights bind group.
-    pub fn new(device: &wgpu::Device) -> Self {
+    /// Creates a new lights bind group holding up to `capacity` lights.
+    pub fn new(device: &wgpu::Device, capacity: usize) -> Self {
         let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: Some("blph_lights_bg_layout"),
             entries: &[wgpu::BindGroupLayoutEntry {
@@ -146,7 +409,8 @@ impl LightsBindGroup {
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Storage { read_only: true },
                     has_dynamic_offset: false,
-                    min_binding_size: LightArray::BUFFER_SIZE,
+                    // The number of lights and at least one light.
+                    min_binding_size: wgpu::BufferSize::new(LightArray::buffer_size(1)),
                 },
                 count: None,
             }],
@@ -155,7 +419,7 @@ impl LightsBindGroup {
         // Preallocate a buffer for lights.
         let lights_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some("blph_lights_buffer"),
-            size: LightArray::SIZE as u64,
+            size: LightArray::buffer_size(capacity.max(1)),
             usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false,
         });
@@ -173,17 +437,76 @@ impl LightsBindGroup {
             group: bind_group,
             layout,
             lights_buffer,
-            lights: LightArray::default(),
+            lights: LightArray::with_capacity(capacity),
+            capacity,
+            warned_overflow: false,
+        }
+    }
+
+    /// Returns the maximum number of lights in the storage buffer.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Drops the lights over the capacity of the storage buffer, warning
+    /// the first time it happens. See [`Self::prioritize_lights`] for the
+    /// lights kept.
+    pub fn fit_lights(
+        &mut self,
+        lights: &mut Vec<(&legion::Entity, &Light, &NodeIdx)>,
+        nodes: &Nodes,
+        view_pos: Vec3,
+    ) {
+        let dropped = Self::prioritize_lights(lights, nodes, view_pos, self.capacity);
+        if dropped > 0 && !self.warned_overflow {
+            log::warn!(
+                "{} lights exceed the maximum of {} lights, the {} least \
+                 contributing ones are ignored",
+                lights.len() + dropped,
+                self.capacity,
+                dropped
+            );
+            self.warned_overflow = true;
         }
     }
 
+    /// Keeps at most `capacity` lights and returns the number of lights
+    /// dropped. When there are too many, the directional lights come first,
+    /// then the point lights by decreasing intensity over the squared
+    /// distance to the viewer at `view_pos`. Otherwise the order is kept.
+    pub fn prioritize_lights(
+        lights: &mut Vec<(&legion::Entity, &Light, &NodeIdx)>,
+        nodes: &Nodes,
+        view_pos: Vec3,
+        capacity: usize,
+    ) -> usize {
+        if lights.len() <= capacity {
+            return 0;
+        }
+        let priority = |(_, light, node_idx): &(&legion::Entity, &Light, &NodeIdx)| match light {
+            Light::Directional { .. } => f32::INFINITY,
+            Light::Point { color } => {
+                let intensity = (color.r + color.g + color.b) as f32 / 3.0;
+                let distance = nodes.world(**node_idx).translation.distance(view_pos);
+                intensity / (1.0 + distance * distance)
+            }
+        };
+        lights.sort_by(|a, b| priority(b).total_cmp(&priority(a)));
+        let dropped = lights.len() - capacity;
+        lights.truncate(capacity);
+        dropped
+    }
+
     /// Updates the cached light data in the bind group,
-    /// and updates the light buffers.
+    /// and updates the light buffers through the staging ring.
     pub fn update_lights(
         &mut self,
-        lights: &[(&Light, &NodeIdx)],
+        lights: &[(&legion::Entity, &Light, &NodeIdx)],
+        casters: &ShadowCasters,
         nodes: &Nodes,
-        queue: &wgpu::Queue,
+        staging: &mut StagingRing,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
         scale: f32,
     ) {
         self.lights.clear();
@@ -191,19 +514,28 @@ impl LightsBindGroup {
         let ortho_h = Self::ORTHO_H * scale;
         let ortho_near = Self::ORTHO_NEAR * scale;
         let ortho_far = Self::ORTHO_FAR * scale;
-        for (light, node_idx) in lights {
-            let len = self.lights.len[0] as usize;
-            self.lights.lights[len] = match light {
-                Light::Directional { direction, color } => {
+        for (_, light, node_idx) in lights.iter().take(self.capacity) {
+            let len = self.lights.len();
+            let gpu_light = match light {
+                Light::Directional { color, .. } => {
                     // In shader, the light direction is the opposite of the
                     // actual direction.
-                    let rev_dir = -direction.normalize();
+                    let rev_dir = -light
+                        .world_direction(&nodes.world(**node_idx))
+                        .unwrap_or(Vec3::NEG_Y);
+                    // The node may point the light straight down.
+                    let up = if rev_dir.y.abs() > 0.999 {
+                        Vec3::Z
+                    } else {
+                        Vec3::Y
+                    };
                     GpuLight {
                         dir_or_pos: [rev_dir.x, rev_dir.y, rev_dir.z, 0.0],
-                        color: [color.r as f32, color.g as f32, color.b as f32, 1.0],
+                        color: [color.r as f32, color.g as f32, color.b as f32],
+                        shadow_map: casters.slot_of_light(len as u32),
                         w2l: (Mat4::orthographic_rh(
                             -ortho_w, ortho_w, -ortho_h, ortho_h, ortho_near, ortho_far,
-                        ) * Mat4::look_at_rh(rev_dir, Vec3::ZERO, Vec3::Y))
+                        ) * Mat4::look_at_rh(rev_dir, Vec3::ZERO, up))
                         .to_cols_array(),
                     }
                 }
@@ -213,21 +545,39 @@ impl LightsBindGroup {
                     // TODO: Matrix from world to light space.
                     GpuLight {
                         dir_or_pos: [position.x, position.y, position.z, 1.0],
-                        color: [color.r as f32, color.g as f32, color.b as f32, 1.0],
+                        color: [color.r as f32, color.g as f32, color.b as f32],
+                        shadow_map: ShadowCasters::NONE,
                         w2l: Mat4::IDENTITY.to_cols_array(),
                     }
                 }
             };
-            self.lights.len[0] += 1;
+            self.lights.push(gpu_light);
         }
         // Update light buffers.
-        queue.write_buffer(&self.lights_buffer, 0, bytemuck::bytes_of(&self.lights));
+        staging.write(
+            device,
+            encoder,
+            &self.lights_buffer,
+            0,
+            bytemuck::bytes_of(&self.lights.len),
+        );
+        if !self.lights.is_empty() {
+            staging.write(
+                device,
+                encoder,
+                &self.lights_buffer,
+                LightArray::HEADER_SIZE as u64,
+                bytemuck::cast_slice(&self.lights.lights),
+            );
+        }
     }
 }
 
 impl BlinnPhongRenderPass {
-    /// Creates a new blinn-phong shading render pass.
-    pub fn new(context: &GpuContext, format: wgpu::TextureFormat) -> Self {
+    /// Creates a new blinn-phong shading render pass, rendering to targets
+    /// of the given format with the given depth buffer.
+    pub fn new(context: &GpuContext, format: wgpu::TextureFormat, depth: DepthSettings) -> Self {
+        let depth = depth.supported_by(&context.adapter, format);
         let globals_bind_group = GlobalsBindGroup::new(&context.device);
         let locals_bind_group = LocalsBindGroup::new(&context.device);
         let shadow_pass_locals_bind_group = LocalsBindGroup::new(&context.device);
@@ -249,500 +599,826 @@ impl BlinnPhongRenderPass {
                     }],
                 });
 
-        let textures_bind_group_layout = texture_bundle_bind_group_layout(&context.device);
-
-        let lights_bind_group = LightsBindGroup::new(&context.device);
-        let mut pipelines = Pipelines::new();
-        // Create shadow maps pass pipeline. This pipeline is used to evaluate
-        // shadow maps for all meshes that cast shadows.
-        {
-            let shader_module = context
-                .device
-                .create_shader_module(wgpu::ShaderModuleDescriptor {
-                    label: Some("shadow_maps_shader_module"),
-                    source: wgpu::ShaderSource::Wgsl(include_str!("shadow.wgsl").into()),
-                });
-            let layout = context
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("blinn_phong_shadow_maps_pipeline_layout"),
-                    bind_group_layouts: &[&locals_bind_group.layout, &lights_bind_group.layout],
-                    push_constant_ranges: &[wgpu::PushConstantRange {
-                        stages: wgpu::ShaderStages::VERTEX,
-                        range: 0..PConstsShadowPass::SIZE as u32,
-                    }],
-                });
-            let (id, pipeline) =
-                Self::create_shadow_maps_pass_pipeline(&context.device, &layout, &shader_module);
-            pipelines.insert("shadow", id, pipeline);
-        }
-
-        let shadow_maps = {
-            let width = 1024;
-            let height = 1024;
-            let count = 1;
-            debug_assert!(
-                width <= context.limits.max_texture_dimension_1d,
-                "Shadow map width exceeds the limit."
-            );
-            debug_assert!(
-                height <= context.limits.max_texture_dimension_1d,
-                "Shadow map height exceeds the limit."
-            );
-
-            let layers_per_texture = context.limits.max_texture_array_layers;
-            let n_textures = (count + layers_per_texture - 1) / layers_per_texture;
-            let last_texture_layers = count % layers_per_texture;
-
-            // Create the depth textures, each of which is a 2D texture array with
-            // `layers_per_texture` layers, and the last texture may have less layers.
-            let depth_textures = (0..n_textures)
-                .map(|n| {
-                    let layer_count = if n == n_textures - 1 {
-                        last_texture_layers
-                    } else {
-                        layers_per_texture
-                    };
-                    let texture = context.device.create_texture(&wgpu::TextureDescriptor {
-                        label: Some("shadow_maps_depth_texture"),
-                        size: wgpu::Extent3d {
-                            width,
-                            height,
-                            depth_or_array_layers: layer_count,
-                        },
-                        mip_level_count: 1,
-                        sample_count: 1,
-                        dimension: wgpu::TextureDimension::D2,
-                        format: DEPTH_FORMAT,
-                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
-                            | wgpu::TextureUsages::TEXTURE_BINDING
-                            | wgpu::TextureUsages::COPY_SRC,
-                        view_formats: &[],
-                    });
-                    let view = texture.create_view(&wgpu::TextureViewDescriptor {
-                        label: Some("shadow_maps_depth_texture_view"),
-                        format: Some(DEPTH_FORMAT),
-                        dimension: Some(wgpu::TextureViewDimension::D2Array),
-                        aspect: wgpu::TextureAspect::All,
-                        base_array_layer: 0,
-                        array_layer_count: Some(layer_count),
-                        ..Default::default()
-                    });
-                    (texture, view)
-                })
-                .collect::<Vec<_>>();
-
-            #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
-            let storage_buffers = (0..count)
-                .map(|_| {
-                    context.device.create_buffer(&wgpu::BufferDescriptor {
-                        label: Some("shadow_maps_storage_buffer"),
-                        size: (width * height * size_of::<f32>() as u32) as u64,
-                        usage: wgpu::BufferUsages::STORAGE
-                            | wgpu::BufferUsages::COPY_DST
-                            | wgpu::BufferUsages::COPY_SRC
-                            | wgpu::BufferUsages::MAP_READ,
-                        mapped_at_creation: false,
-                    })
-                })
-                .collect::<Vec<_>>();
+        let textures_bind_group_layout =
+            textures_bind_group_layout(&context.device, Self::texture_array_len(context));
 
-            let depth_sampler = context.device.create_sampler(&wgpu::SamplerDescriptor {
-                label: Some("shadow_maps_depth_sampler"),
-                address_mode_u: wgpu::AddressMode::ClampToEdge,
-                address_mode_v: wgpu::AddressMode::ClampToEdge,
-                address_mode_w: wgpu::AddressMode::ClampToEdge,
-                mag_filter: wgpu::FilterMode::Nearest,
-                min_filter: wgpu::FilterMode::Nearest,
-                mipmap_filter: wgpu::FilterMode::Nearest,
-                compare: Some(wgpu::CompareFunction::LessEqual),
-                ..Default::default()
-            });
+        let lights_bind_group = LightsBindGroup::new(&context.device, Self::max_lights(context));
 
-            let bind_group_layout =
+        let empty_bind_group = {
+            let layout =
                 context
                     .device
                     .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                        label: Some("shadow_maps_bind_group_layout"),
-                        entries: &[
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 0,
-                                visibility: wgpu::ShaderStages::FRAGMENT,
-                                ty: wgpu::BindingType::Texture {
-                                    multisampled: false,
-                                    view_dimension: wgpu::TextureViewDimension::D2Array,
-                                    sample_type: wgpu::TextureSampleType::Depth,
-                                },
-                                count: NonZeroU32::new(n_textures),
-                            },
-                            wgpu::BindGroupLayoutEntry {
-                                binding: 1,
-                                visibility: wgpu::ShaderStages::FRAGMENT,
-                                ty: wgpu::BindingType::Sampler(
-                                    wgpu::SamplerBindingType::Comparison,
-                                ),
-                                count: None,
-                            },
-                        ],
+                        label: Some("blinn_phong_empty_bind_group_layout"),
+                        entries: &[],
                     });
-
-            // Create the bind group for using the shadow maps in the main pass.
-            let views = depth_textures
-                .iter()
-                .map(|(_, view)| view)
-                .collect::<Vec<_>>();
-
             let bind_group = context
                 .device
                 .create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("shadow_maps_bind_group"),
-                    layout: &bind_group_layout,
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: 0,
-                            resource: wgpu::BindingResource::TextureViewArray(&views),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: 1,
-                            resource: wgpu::BindingResource::Sampler(&depth_sampler),
-                        },
-                    ],
+                    label: Some("blinn_phong_empty_bind_group"),
+                    layout: &layout,
+                    entries: &[],
                 });
+            (layout, bind_group)
+        };
 
-            let shadow_map_views = (0..count)
-                .map(|i| {
-                    let texture_index = i / layers_per_texture;
-                    let layer_index = i % layers_per_texture;
-                    depth_textures[texture_index as usize].0.create_view(
-                        &wgpu::TextureViewDescriptor {
-                            label: Some(&format!("shadow_map_view_{}", i)),
-                            format: Some(DEPTH_FORMAT),
-                            dimension: Some(wgpu::TextureViewDimension::D2),
-                            aspect: wgpu::TextureAspect::DepthOnly,
-                            base_array_layer: layer_index,
-                            array_layer_count: Some(1),
-                            ..Default::default()
-                        },
-                    )
-                })
-                .collect::<Vec<_>>();
+        let shadow_maps = ShadowMaps::new(
+            &context.device,
+            &context.limits,
+            1024,
+            1024,
+            1,
+            context.binding_arrays,
+        );
 
-            ShadowMaps {
-                depth_textures,
-                bind_group,
-                bind_group_layout,
-                shadow_map_size: (width, height),
-                shadow_map_count: count,
-                shadow_map_views,
-                depth_sampler,
-                layers_per_texture,
-                #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
-                storage_buffers,
-            }
+        let sprites =
+            SpriteRenderPass::new(&context.device, &globals_bind_group.layout, format, depth);
+        let gizmo = GizmoRenderPass::new(&context.device, &globals_bind_group.layout, format);
+        let minimap = MinimapRenderPass::new(&context.device, format);
+        let particles =
+            ParticleRenderPass::new(&context.device, &globals_bind_group.layout, format, depth);
+        let water =
+            WaterRenderPass::new(&context.device, &globals_bind_group.layout, format, depth);
+        let background = BackgroundRenderPass::new(&context.device, &context.queue, format, depth);
+        // The indirect draws bind the textures shared by all materials, the
+        // texture slots are bound with the materials of each mesh.
+        let indirect_supported =
+            context.device.features().contains(IndirectDraws::FEATURES) && context.binding_arrays;
+        let culling =
+            indirect_supported.then(|| GpuCulling::new(&context.device, &locals_bind_group.layout));
+
+        let mut pass = Self {
+            depth_att: None,
+            msaa_att: None,
+            globals_bind_group,
+            locals_bind_group,
+            shadow_pass_locals_bind_group,
+            materials_bind_group_layout,
+            textures_bind_group_layout,
+            lights_bind_group,
+            pipelines: Pipelines::new(),
+            custom_shaders: CustomShaderModules::default(),
+            format,
+            depth,
+            constant_sized_binding_array: context.constant_sized_binding_array,
+            slots: (!context.binding_arrays).then(|| SlotBindGroups::new(&context.device)),
+            constants: DrawConstants::new(
+                &context.device,
+                "blinn_phong_constants",
+                wgpu::ShaderStages::VERTEX_FRAGMENT,
+                PConsts::SIZE as u32,
+            ),
+            shadow_constants: DrawConstants::new(
+                &context.device,
+                "blinn_phong_shadow_maps_constants",
+                wgpu::ShaderStages::VERTEX_FRAGMENT,
+                PConstsShadowPass::SIZE as u32,
+            ),
+            depth_constants: DrawConstants::new(
+                &context.device,
+                "blinn_phong_depth_prepass_constants",
+                wgpu::ShaderStages::VERTEX,
+                std::mem::size_of::<u32>() as u32,
+            ),
+            empty_bind_group,
+            warned_shadow_casters: false,
+            shaders_generation: 0,
+            environment_generation: 0,
+            shadow_maps,
+            shadow_casters: ShadowCasters::default(),
+            sprites,
+            gizmo,
+            minimap,
+            particles,
+            water,
+            background,
+            occlusion: OcclusionCulling::new(&context.device, depth),
+            transients: TransientTextures::new(),
+            exposure: ExposureMeter::new(&context.device),
+            draw_bundles: DrawBundles::default(),
+            indirect: indirect_supported.then(|| IndirectDraws::new(&context.device)),
+            culling,
+            staging: StagingRing::new(),
+            shadow_staging: StagingRing::new(),
+            viewport: Viewport::full(wgpu::Extent3d::default()),
+            camera: None,
+            reflection: false,
+            last_frame: Instant::now(),
         };
+        pass.create_pipelines(&context.device, &ShaderManager::default());
+        pass
+    }
+
+    /// Renders the pass from the given camera instead of the main camera.
+    pub fn with_camera(mut self, camera: legion::Entity) -> Self {
+        self.camera = Some(camera);
+        self
+    }
+
+    /// Renders the pass from the main camera mirrored below the reflective
+    /// water surface, without the water, for the reflection of the surface.
+    pub fn with_reflection(mut self) -> Self {
+        self.reflection = true;
+        self
+    }
+
+    /// Returns true if the pass renders the main window, not a camera
+    /// texture nor a reflection.
+    fn is_main_view(&self) -> bool {
+        self.camera.is_none() && !self.reflection
+    }
 
+    /// Creates the pipelines of the shadow maps pass and of the main pass,
+    /// including the pipelines of the custom material shaders.
+    ///
+    /// User-provided shaders failing to compile are replaced by the embedded
+    /// ones; entities whose custom shader fails to compile are drawn with the
+    /// default pipelines.
+    fn create_pipelines(&mut self, device: &wgpu::Device, shaders: &ShaderManager) {
+        profiling::scope!("BlinnPhongShading::create_pipelines");
+        let push_constants = self.constants.is_push_constants();
+        let texture_slots = self.slots.is_some();
         let mut conditions = FxHashMap::default();
+        conditions.insert("push_constants", push_constants);
+        conditions.insert("texture_slots", texture_slots);
         conditions.insert(
             "constant_sized_binding_array",
-            context.constant_sized_binding_array,
+            self.constant_sized_binding_array,
         );
+        conditions.insert(
+            "runtime_sized_binding_array",
+            !texture_slots && !self.constant_sized_binding_array,
+        );
+        // The emulated constants follow the other groups, fewer with the
+        // texture slots.
+        conditions.insert(
+            "binding_arrays_uniform_constants",
+            !push_constants && !texture_slots,
+        );
+        conditions.insert(
+            "texture_slots_uniform_constants",
+            !push_constants && texture_slots,
+        );
+        let mut pipelines = Pipelines::new();
 
-        let blinn_phong_shader = preprocess_wgsl(include_str!("blph.wgsl"), &conditions);
-
-        log::debug!("Blinn-Phong shading shader:\n{}", blinn_phong_shader);
+        // Create shadow maps pass pipelines, used to evaluate the shadow
+        // maps of all meshes that cast shadows. The alpha-tested pipelines
+        // also read the materials and textures, and both kinds have a
+        // variant for the double-sided materials.
+        {
+            // The emulated constants follow the groups of the materials and
+            // textures, left empty by the draws which are not alpha-tested.
+            // The texture slots are bound in the group of the materials.
+            let (mut bind_group_layouts, material_groups) = match &self.slots {
+                Some(slots) => (vec![&slots.shadow_layout], vec![&slots.materials_layout]),
+                None => (
+                    vec![
+                        &self.locals_bind_group.layout,
+                        &self.lights_bind_group.layout,
+                    ],
+                    vec![
+                        &self.materials_bind_group_layout,
+                        &self.textures_bind_group_layout,
+                    ],
+                ),
+            };
+            let mut alpha_test_bind_group_layouts = bind_group_layouts.clone();
+            alpha_test_bind_group_layouts.extend(&material_groups);
+            alpha_test_bind_group_layouts.extend(self.shadow_constants.bind_group_layout());
+            if let Some(constants) = self.shadow_constants.bind_group_layout() {
+                bind_group_layouts.extend(material_groups.iter().map(|_| &self.empty_bind_group.0));
+                bind_group_layouts.push(constants);
+            }
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("blinn_phong_shadow_maps_pipeline_layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &self
+                    .shadow_constants
+                    .push_constant_ranges(wgpu::ShaderStages::VERTEX),
+            });
+            let alpha_test_layout =
+                device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("blinn_phong_shadow_maps_alpha_test_pipeline_layout"),
+                    bind_group_layouts: &alpha_test_bind_group_layouts,
+                    push_constant_ranges: &self
+                        .shadow_constants
+                        .push_constant_ranges(wgpu::ShaderStages::VERTEX_FRAGMENT),
+                });
+            let created =
+                Self::with_fallback(device, shaders, "shadow.wgsl", &conditions, |source| {
+                    let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                        label: Some("shadow_maps_shader_module"),
+                        source: wgpu::ShaderSource::Wgsl(source.into()),
+                    });
+                    let mut created = Vec::new();
+                    for cull_mode in [Some(wgpu::Face::Back), None] {
+                        let (id, pipeline) = Self::create_shadow_maps_pass_pipeline(
+                            device,
+                            &layout,
+                            &shader_module,
+                            false,
+                            cull_mode,
+                        );
+                        created.push(("shadow", id, pipeline));
+                        let (id, pipeline) = Self::create_shadow_maps_pass_pipeline(
+                            device,
+                            &alpha_test_layout,
+                            &shader_module,
+                            true,
+                            cull_mode,
+                        );
+                        created.push(("shadow_alpha_test", id, pipeline));
+                    }
+                    created
+                });
+            for (label, id, pipeline) in created {
+                pipelines.insert(label, id, pipeline);
+            }
+        }
 
-        let shader_module = context
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: Some("shading_shader_module"),
-                source: wgpu::ShaderSource::Wgsl(blinn_phong_shader.into()),
+        // Create depth pre-pass pipelines, one per cull mode of the main
+        // render pass.
+        {
+            let mut bind_group_layouts = vec![
+                &self.globals_bind_group.layout,
+                &self.locals_bind_group.layout,
+            ];
+            bind_group_layouts.extend(self.depth_constants.bind_group_layout());
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("blinn_phong_depth_prepass_pipeline_layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &self
+                    .depth_constants
+                    .push_constant_ranges(wgpu::ShaderStages::VERTEX),
+            });
+            let source = preprocess_wgsl(include_str!("depth.wgsl"), &conditions);
+            let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("depth_prepass_shader_module"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
             });
+            for cull_mode in [Some(wgpu::Face::Back), None] {
+                let (id, pipeline) =
+                    self.create_depth_prepass_pipeline(device, &layout, &shader_module, cull_mode);
+                pipelines.insert("depth_prepass", id, pipeline);
+            }
+        }
 
-        // Create main render pass pipeline.
+        // Create main render pass pipelines.
         {
-            let layout = context
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("blinn_phong_shading_pipeline_layout"),
-                    bind_group_layouts: &[
-                        &globals_bind_group.layout,
-                        &locals_bind_group.layout,
-                        &materials_bind_group_layout,
-                        &lights_bind_group.layout,
-                        &textures_bind_group_layout,
-                        &shadow_maps.bind_group_layout,
-                    ],
-                    push_constant_ranges: &[wgpu::PushConstantRange {
-                        stages: wgpu::ShaderStages::VERTEX_FRAGMENT,
-                        range: 0..PConsts::SIZE as u32,
-                    }],
+            let mut bind_group_layouts = match &self.slots {
+                Some(slots) => vec![&slots.frame_layout, &slots.materials_layout],
+                None => vec![
+                    &self.globals_bind_group.layout,
+                    &self.locals_bind_group.layout,
+                    &self.materials_bind_group_layout,
+                    &self.lights_bind_group.layout,
+                    &self.textures_bind_group_layout,
+                    &self.shadow_maps.bind_group_layout,
+                ],
+            };
+            bind_group_layouts.extend(self.constants.bind_group_layout());
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("blinn_phong_shading_pipeline_layout"),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &self
+                    .constants
+                    .push_constant_ranges(wgpu::ShaderStages::VERTEX_FRAGMENT),
+            });
+            let create_shader_module = |source: &str| {
+                log::debug!("Blinn-Phong shading shader:\n{}", source);
+                device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                    label: Some("shading_shader_module"),
+                    source: wgpu::ShaderSource::Wgsl(source.into()),
+                })
+            };
+            let create_main_pipelines =
+                |shader_module: &wgpu::ShaderModule, all_topologies: bool| {
+                    let mut created = Vec::new();
+                    for cull_mode in [Some(wgpu::Face::Back), None] {
+                        for polygon_mode in [wgpu::PolygonMode::Fill, wgpu::PolygonMode::Line] {
+                            let id = PipelineId::from_states(
+                                PipelineKind::Render,
+                                wgpu::PrimitiveTopology::TriangleList,
+                                polygon_mode,
+                                cull_mode,
+                            );
+                            let (id, pipeline) = self.create_main_render_pass_pipeline(
+                                device,
+                                &layout,
+                                shader_module,
+                                id,
+                                false,
+                                &[],
+                            );
+                            created.push(("entity", id, pipeline));
+                        }
+                        // Pipeline drawing over the depth of the pre-pass, which
+                        // is disabled in wireframe mode.
+                        let id = PipelineId::from_states(
+                            PipelineKind::Render,
+                            wgpu::PrimitiveTopology::TriangleList,
+                            wgpu::PolygonMode::Fill,
+                            cull_mode,
+                        );
+                        let (id, pipeline) = self.create_main_render_pass_pipeline(
+                            device,
+                            &layout,
+                            shader_module,
+                            id,
+                            true,
+                            &[],
+                        );
+                        created.push(("entity_depth_prepassed", id, pipeline));
+                    }
+                    // Pipelines of the meshes which are not triangle lists. Only
+                    // triangle strips are culled and drawn in wireframe.
+                    if all_topologies {
+                        let mut states = [
+                            wgpu::PrimitiveTopology::PointList,
+                            wgpu::PrimitiveTopology::LineList,
+                            wgpu::PrimitiveTopology::LineStrip,
+                        ]
+                        .map(|topology| (topology, wgpu::PolygonMode::Fill, None))
+                        .to_vec();
+                        for cull_mode in [Some(wgpu::Face::Back), None] {
+                            for polygon_mode in [wgpu::PolygonMode::Fill, wgpu::PolygonMode::Line] {
+                                states.push((
+                                    wgpu::PrimitiveTopology::TriangleStrip,
+                                    polygon_mode,
+                                    cull_mode,
+                                ));
+                            }
+                        }
+                        for (topology, polygon_mode, cull_mode) in states {
+                            let id = PipelineId::from_states(
+                                PipelineKind::Render,
+                                topology,
+                                polygon_mode,
+                                cull_mode,
+                            );
+                            let (id, pipeline) = self.create_main_render_pass_pipeline(
+                                device,
+                                &layout,
+                                shader_module,
+                                id,
+                                false,
+                                &[],
+                            );
+                            created.push(("primitives", id, pipeline));
+                        }
+                    }
+                    created
+                };
+
+            let created =
+                Self::with_fallback(device, shaders, "blph.wgsl", &conditions, |source| {
+                    create_main_pipelines(&create_shader_module(source), true)
                 });
+            for (label, id, pipeline) in created {
+                pipelines.insert(label, id, pipeline);
+            }
 
-            for cull_mode in [Some(wgpu::Face::Back), None] {
-                for polygon_mode in [wgpu::PolygonMode::Fill, wgpu::PolygonMode::Line] {
-                    let (id, pipeline) = Self::create_main_render_pass_pipeline(
-                        &context.device,
-                        &layout,
-                        format,
-                        &shader_module,
-                        polygon_mode,
-                        wgpu::PrimitiveTopology::TriangleList,
-                        cull_mode,
-                    );
-                    pipelines.insert("entity", id, pipeline);
+            // Custom material shaders share the layout of the main pass, only
+            // triangle lists are drawn with them. Their modules are kept to
+            // create the pipelines reading the custom vertex attributes.
+            let mut custom_modules = FxHashMap::default();
+            for path in shaders.custom_shaders() {
+                let source = match shaders.custom_source(path) {
+                    Some(source) => preprocess_wgsl(&source, &conditions),
+                    None => continue,
+                };
+                let shader_module = match validated(device, || create_shader_module(&source)) {
+                    Ok(shader_module) => shader_module,
+                    Err(err) => {
+                        log::error!("Failed to compile shader {:?}: {}", path, err);
+                        continue;
+                    }
+                };
+                let label = CustomShader(path.to_path_buf()).label();
+                match validated(device, || create_main_pipelines(&shader_module, false)) {
+                    Ok(created) => {
+                        for (_, id, pipeline) in created
+                            .into_iter()
+                            .filter(|(label, _, _)| *label == "entity")
+                        {
+                            pipelines.insert(&label, id, pipeline);
+                        }
+                    }
+                    // The shader may read custom vertex attributes, missing
+                    // from the meshes without them.
+                    Err(err) => log::warn!(
+                        "Shader {:?} can only draw meshes with custom attributes: {}",
+                        path,
+                        err
+                    ),
                 }
+                custom_modules.insert(label, shader_module);
             }
+            self.custom_shaders = CustomShaderModules {
+                layout: Some(layout),
+                modules: custom_modules,
+                failed: FxHashSet::default(),
+            };
+        }
 
-            // Pipeline for drawing line segments, same as the main render pass pipeline,
-            // except the topology is line list.
-            let (id, pipeline) = Self::create_main_render_pass_pipeline(
-                &context.device,
-                &layout,
-                format,
-                &shader_module,
-                wgpu::PolygonMode::Fill,
-                wgpu::PrimitiveTopology::LineList,
-                None,
-            );
-            pipelines.insert("lines", id, pipeline);
+        self.pipelines = pipelines;
+        self.shaders_generation = shaders.generation();
+    }
+
+    /// Creates GPU objects from the shader with the given file name. If the
+    /// user-provided version of the shader fails to compile, the objects are
+    /// created from the embedded version.
+    fn with_fallback<T>(
+        device: &wgpu::Device,
+        shaders: &ShaderManager,
+        name: &str,
+        conditions: &FxHashMap<&str, bool>,
+        create: impl Fn(&str) -> T,
+    ) -> T {
+        let (source, embedded) = shaders.source(name);
+        let source = preprocess_wgsl(&source, conditions);
+        if embedded {
+            return create(&source);
+        }
+        match validated(device, || create(&source)) {
+            Ok(created) => created,
+            Err(err) => {
+                log::error!(
+                    "Failed to compile {}, use the embedded version: {}",
+                    name,
+                    err
+                );
+                create(&preprocess_wgsl(ShaderManager::embedded(name), conditions))
+            }
         }
+    }
 
-        Self {
-            depth_att: None,
-            globals_bind_group,
-            locals_bind_group,
-            shadow_pass_locals_bind_group,
-            materials_bind_group_layout,
-            textures_bind_group_layout,
-            lights_bind_group,
-            pipelines,
-            shadow_maps,
+    /// Returns the camera the main pass is rendered from: the main camera,
+    /// or the first camera if there is none.
+    fn main_camera(scene: &Scene) -> Option<(&Camera, NodeIdx)> {
+        let mut camera_query = <(&Camera, &NodeIdx)>::query();
+        let main_camera = camera_query
+            .iter(&scene.world)
+            .find(|(camera, _)| camera.is_main);
+        match main_camera {
+            None => {
+                // If there is no main camera, use the first camera.
+                let Some((camera, node_idx)) = camera_query.iter(&scene.world).next() else {
+                    log::error!("No camera found in the scene! Skip rendering!");
+                    return None;
+                };
+                log::warn!("No main camera found, use the first camera #{:?}", node_idx);
+                Some((camera, *node_idx))
+            }
+            Some((camera, node_idx)) => {
+                // If there is a main camera, use it.
+                log::debug!("Use main camera {:?}", node_idx);
+                Some((camera, *node_idx))
+            }
         }
     }
 
-    /// Evaluates shadow maps.
-    fn eval_shadow_maps_pass<'a, M>(
-        &mut self,
-        encoder: &mut wgpu::CommandEncoder,
-        mesh_bundles: M,
-        scene: &Scene,
-        renderer: &Renderer,
-    ) where
-        M: Iterator<Item = (&'a MeshBundle, &'a NodeIdx)>,
-    {
-        profiling::scope!("BlinnPhongShading::eval_shadow_maps_pass");
-        let (_, pipeline) = &self.pipelines.get_all("shadow").unwrap()[0];
+    /// Returns the camera of the given entity, if it still has one.
+    fn entity_camera(scene: &Scene, entity: legion::Entity) -> Option<(&Camera, NodeIdx)> {
+        let entry = scene.world.entry_ref(entity).ok()?;
+        let node_idx = *entry.get_component::<NodeIdx>().ok()?;
+        let camera = entry.into_component::<Camera>().ok()?;
+        Some((camera, node_idx))
+    }
 
-        // Process meshes;
-        let mut unique_bundles = FxHashSet::default();
+    /// Groups the instances drawn by the main pass into batches and gathers
+    /// their locals.
+    ///
+    /// Instances are grouped by render layer and mesh bundle, so that lower
+    /// layers are drawn first; instances drawn with a custom shader are
+    /// batched separately. The locals of large scenes are gathered on
+    /// multiple threads.
+    fn prepare_main_draws<'a>(
+        meshes: &[(
+            &'a MeshBundle,
+            &'a NodeIdx,
+            Option<&'a RenderLayer>,
+            Option<&'a CustomShader>,
+        )],
+        (camera, camera_node): (&'a Camera, NodeIdx),
+        mirror: Option<Vec4>,
+        nodes: &Nodes,
+        instancing: &FxHashMap<MeshBundle, Vec<NodeIdx>>,
+        occlusion: Option<&OcclusionCulling>,
+    ) -> MainPassDraws<'a> {
+        profiling::scope!("BlinnPhongShading::prepare_main_draws");
+        let mut view_mat = nodes.inverse_world(camera_node).to_mat4();
+        if let Some(plane) = mirror {
+            view_mat *= reflection_matrix(plane);
+        }
+        let mut node_batches = FxHashMap::default();
+        let mut unique_meshes = FxHashSet::default();
+        let mut batches = Vec::new();
         let mut n_inst = 0;
-        for (bundle, _) in mesh_bundles {
-            unique_bundles.insert(bundle);
+        for (mesh, node_idx, layer, shader) in meshes {
+            let layer = layer.copied().unwrap_or_default();
+            if !layer.is_in(camera.layer_mask) {
+                continue;
+            }
+            node_batches.insert(**node_idx, (layer, *shader));
+            if unique_meshes.insert((layer, *shader, *mesh)) {
+                batches.push((layer, *shader, *mesh));
+            }
             n_inst += 1;
         }
+        // Sort the batches by layer, then by shader and mesh so that the draws
+        // and the locals don't depend on the order of the query.
+        batches.sort_by_key(|(layer, shader, mesh)| (*layer, *shader, *mesh));
 
         log::debug!(
-            "{} instances of {} meshes cast shadows",
+            "Processed {} instances of {} meshes",
             n_inst,
-            unique_bundles.len()
+            unique_meshes.len()
         );
 
-        if n_inst == 0 {
-            return;
-        }
-
-        // Update locals buffer content.
-        self.shadow_pass_locals_bind_group
-            .resize(&renderer.device, n_inst);
-        let mut locals = vec![ShadowPassLocals::identity(); n_inst as usize];
-        let mut offsets_and_inst_count = vec![(0, 0); n_inst as usize];
-        let mut offset = 0u32;
-        for (i, bundle) in unique_bundles.iter().enumerate() {
-            let instances = renderer
-                .instancing
-                .get(bundle)
-                .expect("Unreachable! Instancing should be created for all meshes!");
-            offsets_and_inst_count[i].0 = offset;
-            for (j, node_idx) in instances.iter().enumerate() {
-                let node = &scene.nodes[*node_idx];
-                if !node.is_visible() {
-                    continue;
-                }
-                offsets_and_inst_count[i].1 += 1;
-                locals[offset as usize + j] = ShadowPassLocals {
-                    model: scene.nodes.world(*node_idx).to_mat4().to_cols_array(),
-                }
-            }
-            offset += offsets_and_inst_count[i].1;
-        }
-        renderer.queue.write_buffer(
-            &self.shadow_pass_locals_bind_group.buffer,
-            0,
-            bytemuck::cast_slice(&locals),
-        );
-
-        let mesh_buffer = renderer.meshes.buffer();
-
-        for (light_idx, shadow_map) in self.shadow_maps.shadow_map_views.iter().enumerate() {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("blinn_phong_shadow_maps_pass"),
-                color_attachments: &[],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: shadow_map,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-            render_pass.set_pipeline(pipeline);
-            // Bind locals.
-            render_pass.set_bind_group(0, &self.shadow_pass_locals_bind_group, &[]);
-            // Bind lights storage buffer.
-            render_pass.set_bind_group(1, &self.lights_bind_group, &[]);
-            // Set push constants - light index.
-            render_pass.set_push_constants(
-                wgpu::ShaderStages::VERTEX,
-                4,
-                bytemuck::bytes_of(&(light_idx as u32)),
-            );
-
-            for (bundle, (offset, inst_count)) in
-                unique_bundles.iter().zip(offsets_and_inst_count.iter())
-            {
-                match renderer.meshes.get(bundle.mesh) {
-                    Some(mesh) => {
-                        // Bind vertex buffer - position.
-                        if let Some(pos_range) =
-                            mesh.get_vertex_attribute_range(VertexAttribute::POSITION)
-                        {
-                            render_pass.set_vertex_buffer(0, mesh_buffer.slice(pos_range.clone()));
+        // Gathers the locals of the instances of a batch.
+        let batch_locals =
+            |(layer, shader, bundle): &(RenderLayer, Option<&'a CustomShader>, &'a MeshBundle)| {
+                instancing
+                    .get(*bundle)
+                    .expect("Unreachable! Instancing should be created for all meshes!")
+                    .iter()
+                    .filter(|node_idx| {
+                        nodes[**node_idx].is_visible()
+                            && node_batches.get(*node_idx) == Some(&(*layer, *shader))
+                            && !occlusion.is_some_and(|occlusion| occlusion.is_occluded(**node_idx))
+                    })
+                    .map(|node_idx| {
+                        let model_mat = nodes.world(*node_idx).to_mat4();
+                        Locals {
+                            model: model_mat.to_cols_array(),
+                            model_view_it: (view_mat * model_mat)
+                                .inverse()
+                                .transpose()
+                                .to_cols_array(),
+                            material_index: [
+                                nodes[*node_idx].material_override.unwrap_or(u32::MAX),
+                                nodes[*node_idx]
+                                    .tint
+                                    .map_or(0, |tint| u32::from_le_bytes(tint.into())),
+                                u32::MAX,
+                                u32::MAX,
+                            ],
                         }
-                        // Set push constants - instance base index.
-                        render_pass.set_push_constants(
-                            wgpu::ShaderStages::VERTEX,
-                            0,
-                            bytemuck::bytes_of(offset),
-                        );
+                    })
+                    .collect::<Vec<_>>()
+            };
+        let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let per_batch = if n_inst < Self::PARALLEL_LOCALS_THRESHOLD || n_threads == 1 {
+            batches.iter().map(batch_locals).collect::<Vec<_>>()
+        } else {
+            let chunk_size = batches.len().div_ceil(n_threads);
+            std::thread::scope(|s| {
+                let handles = batches
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        s.spawn(move || chunk.iter().map(batch_locals).collect::<Vec<_>>())
+                    })
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("Failed to prepare the locals!"))
+                    .collect::<Vec<_>>()
+            })
+        };
 
-                        match mesh.index_format {
-                            Some(index_format) => {
-                                render_pass.set_index_buffer(
-                                    mesh_buffer.slice(mesh.index_range.clone()),
-                                    index_format,
-                                );
-                                match mesh.sub_meshes.as_ref() {
-                                    Some(sub_meshes) => {
-                                        for sm in sub_meshes {
-                                            render_pass.draw_indexed(
-                                                sm.range.start..sm.range.end,
-                                                0,
-                                                0..*inst_count,
-                                            );
-                                        }
-                                    }
-                                    None => {
-                                        render_pass.draw_indexed(
-                                            0..mesh.index_count,
-                                            0,
-                                            0..*inst_count,
-                                        );
-                                    }
-                                }
-                            }
-                            None => match mesh.sub_meshes.as_ref() {
-                                Some(sub_meshes) => {
-                                    for sm in sub_meshes {
-                                        render_pass
-                                            .draw(sm.range.start..sm.range.end, 0..*inst_count)
-                                    }
-                                }
-                                None => {
-                                    render_pass.draw(0..mesh.vertex_count, 0..*inst_count);
-                                }
-                            },
-                        }
-                    }
-                    None => {
-                        log::error!("Missing mesh {:?}", bundle.mesh);
-                        continue;
-                    }
-                }
+        // The instances of a mesh are drawn with a single draw call.
+        let mut locals = Vec::with_capacity(n_inst);
+        let mut draws = Vec::with_capacity(batches.len());
+        for ((_, shader, bundle), instances) in batches.into_iter().zip(per_batch) {
+            // All the instances of the mesh may be occluded.
+            if instances.is_empty() {
+                continue;
             }
+            draws.push((shader, bundle, locals.len() as u32, instances.len() as u32));
+            locals.extend(instances);
+        }
+        MainPassDraws {
+            camera,
+            camera_node,
+            view_mat,
+            mirror,
+            locals,
+            draws,
         }
     }
 
     /// Evaluates the main render pass.
-    fn eval_main_render_pass<'a>(
+    fn eval_main_render_pass(
         &mut self,
         encoder: &mut wgpu::CommandEncoder,
-        meshes: &[(&'a MeshBundle, &'a NodeIdx)],
+        main: MainPassDraws,
         scene: &Scene,
         renderer: &Renderer,
         params: &RenderParams,
         target: &RenderTarget,
     ) {
         profiling::scope!("BlinnPhongShading::eval_main_render_pass");
-        // Update globals.
-        let (view_mat, clear_color) = {
-            // Update camera globals.
-            let mut camera_query = <(&Camera, &NodeIdx)>::query();
-            let num_cameras = camera_query.iter(&scene.world).count();
-            if num_cameras == 0 {
-                log::error!("No camera found in the scene! Skip rendering!");
-                return;
+        let MainPassDraws {
+            camera,
+            camera_node,
+            view_mat,
+            mirror,
+            mut locals,
+            draws,
+        } = main;
+        let clear_color = camera.background;
+
+        // Update camera and time globals.
+        let mut proj = camera.proj_matrix(self.viewport.aspect_ratio(), self.depth.reversed_z);
+        let mut camera_pos = scene.nodes.world(camera_node).translation;
+        if let Some(plane) = mirror {
+            // What lies below the mirror plane is clipped by the near plane.
+            let view_plane = view_mat.inverse().transpose() * plane;
+            proj = oblique_projection(proj, view_plane, self.depth.reversed_z);
+            camera_pos = reflection_matrix(plane).transform_point3(camera_pos);
+        }
+        // The exposure adapts in real time, the animations in the time of
+        // the scene.
+        let now = Instant::now();
+        let exposure = self
+            .exposure
+            .exposure(camera, now.duration_since(self.last_frame).as_secs_f32());
+        let time = renderer.time();
+        let globals = Globals {
+            view: view_mat.to_cols_array(),
+            proj: proj.to_cols_array(),
+            camera_pos: camera_pos.to_array(),
+            time: time.elapsed,
+            delta_time: time.delta,
+            exposure,
+            _padding: [0.0; 2],
+        };
+        self.last_frame = now;
+        self.staging.write(
+            &renderer.device,
+            encoder,
+            &self.globals_bind_group.buffer,
+            0,
+            bytemuck::bytes_of(&globals),
+        );
+        self.sprites.prepare(
+            scene,
+            renderer,
+            &mut self.staging,
+            encoder,
+            view_mat,
+            camera.layer_mask,
+        );
+        // Only the main window shows the gizmos and the minimap, not the
+        // camera textures nor the reflection.
+        if self.is_main_view() {
+            self.minimap
+                .prepare(renderer, &mut self.staging, encoder, target);
+            let debug_lights = params
+                .show_debug_gizmos
+                .then_some(&self.lights_bind_group.lights);
+            self.gizmo.prepare(
+                scene,
+                renderer,
+                &mut self.staging,
+                encoder,
+                camera_pos,
+                debug_lights,
+            );
+        }
+        // The water doesn't reflect itself. Only the main window is
+        // rendered from the camera of the reflection.
+        if !self.reflection {
+            self.water.prepare(
+                scene,
+                renderer,
+                &mut self.staging,
+                encoder,
+                camera.layer_mask,
+                self.is_main_view(),
+            );
+        }
+        self.background.prepare(
+            renderer,
+            &mut self.staging,
+            encoder,
+            camera.backdrop.as_ref(),
+            // The backdrop casts its rays between the near and far planes.
+            camera.proj_matrix(self.viewport.aspect_ratio(), false) * view_mat,
+            exposure,
+            self.viewport.aspect_ratio(),
+        );
+        self.occlusion.set_camera(proj * view_mat, camera_pos);
+
+        // Indexed triangle meshes drawn with the default shader are drawn
+        // indirectly if supported, see `indirect_draw_args`.
+        let n_direct_locals = locals.len() as u32;
+        let mut indirect_args = Vec::new();
+        let indirect_draws = draws
+            .iter()
+            .map(|draw| {
+                self.indirect.as_ref()?;
+                Self::indirect_draw_args(renderer, draw, &mut locals, &mut indirect_args)
+            })
+            .collect::<Vec<_>>();
+        let gpu_culling = params.enable_gpu_culling && self.culling.is_some();
+        if let Some(indirect) = self.indirect.as_mut().filter(|_| !indirect_args.is_empty()) {
+            indirect.resize(&renderer.device, indirect_args.len() as u32);
+            // The GPU culling counts the visible instances of the draws.
+            let bytes = indirect_args
+                .iter()
+                .map(|args| wgpu::util::DrawIndexedIndirectArgs {
+                    instance_count: if gpu_culling { 0 } else { args.instance_count },
+                    ..*args
+                })
+                .flat_map(|args| args.as_bytes().to_vec())
+                .collect::<Vec<_>>();
+            self.staging
+                .write(&renderer.device, encoder, &indirect.buffer, 0, &bytes);
+            if gpu_culling {
+                let bounds = draws
+                    .iter()
+                    .zip(&indirect_draws)
+                    .filter_map(|((_, bundle, _, _), range)| {
+                        let mesh = renderer.meshes.get(bundle.mesh)?;
+                        Some(range.clone()?.map(|_| DrawBounds::new(mesh.bounds)))
+                    })
+                    .flatten()
+                    .collect::<Vec<_>>();
+                self.staging.write(
+                    &renderer.device,
+                    encoder,
+                    &indirect.bounds,
+                    0,
+                    bytemuck::cast_slice(&bounds),
+                );
             }
+        }
 
-            let main_camera = camera_query
-                .iter(&scene.world)
-                .find(|(camera, _)| camera.is_main);
-
-            let (camera, node_idx) = match main_camera {
-                None => {
-                    // If there is no main camera, use the first camera.
-                    let camera = camera_query.iter(&scene.world).next().unwrap();
-                    log::warn!("No main camera found, use the first camera #{:?}", camera.1);
-                    camera
-                }
-                Some(camera) => {
-                    // If there is a main camera, use it.
-                    log::debug!("Use main camera {:?}", camera.1);
-                    camera
-                }
-            };
-
-            let view_mat = scene.nodes.inverse_world(*node_idx).to_mat4();
-            let proj = camera.proj_matrix(target.aspect_ratio());
-            let globals = Globals {
-                view: view_mat.to_cols_array(),
-                proj: proj.to_cols_array(),
-            };
-            renderer.queue.write_buffer(
-                &self.globals_bind_group.buffer,
+        if !locals.is_empty() {
+            // Resize locals buffer in case the number of instances is larger
+            // than the current capacity.
+            self.locals_bind_group
+                .resize(&renderer.device, locals.len() as u32);
+            self.staging.write(
+                &renderer.device,
+                encoder,
+                &self.locals_bind_group.buffer,
                 0,
-                bytemuck::bytes_of(&globals),
+                bytemuck::cast_slice(&locals),
             );
-            (view_mat, camera.background)
-        };
+        }
+
+        // Cull the instances drawn indirectly once their locals are uploaded.
+        if let (true, Some(culling), Some(indirect)) = (
+            gpu_culling && !indirect_args.is_empty(),
+            self.culling.as_mut(),
+            self.indirect.as_ref(),
+        ) {
+            culling.set_camera(proj * view_mat);
+            culling.prepare(
+                &renderer.device,
+                encoder,
+                &mut self.staging,
+                &self.locals_bind_group,
+                indirect,
+                n_direct_locals..locals.len() as u32,
+            );
+            culling.dispatch(encoder);
+        }
+
+        // Write the depth of the meshes beforehand, so that the main render
+        // pass shades each pixel only once.
+        let depth_prepass = params.depth_prepass() && !draws.is_empty();
+        if depth_prepass {
+            self.eval_depth_prepass(encoder, &draws, renderer, params);
+        }
+
+        // Create the pipelines of the custom shaders reading the custom
+        // attributes of the meshes drawn with them.
+        for (shader, bundle, _, _) in &draws {
+            if let (Some(shader), Some(mesh)) = (shader, renderer.meshes.get(bundle.mesh)) {
+                self.create_custom_attributes_pipelines(&renderer.device, shader, mesh);
+            }
+        }
 
         // Create render pass.
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("blinn_phong_render_pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &target.view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(*clear_color),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
+            color_attachments: &[Some(scene_color_attachment(
+                target,
+                self.msaa_att.as_ref().map(|(_, view)| view),
+                wgpu::LoadOp::Clear(*clear_color),
+            ))],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &self.depth_att.as_ref().unwrap().1,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: if depth_prepass {
+                        wgpu::LoadOp::Load
+                    } else {
+                        wgpu::LoadOp::Clear(self.depth.clear_value())
+                    },
                     store: wgpu::StoreOp::Store,
                 }),
                 stencil_ops: None,
@@ -751,297 +1427,846 @@ impl BlinnPhongRenderPass {
             occlusion_query_set: None,
         });
 
-        // Choose the pipeline.
-        let pipeline = self.pipelines.get_all_filtered("entity", |id| {
-            let cull_mode = if params.enable_back_face_culling {
-                Some(wgpu::Face::Back)
-            } else {
-                None
-            };
-            let polygon_mode = if params.enable_wireframe {
-                wgpu::PolygonMode::Line
-            } else {
-                wgpu::PolygonMode::Fill
-            };
-            id.cull_mode() == cull_mode && id.polygon_mode() == polygon_mode
-        });
+        // Draw the backdrop behind everything.
+        self.viewport.apply(&mut render_pass);
+        self.background.draw(&mut render_pass);
 
-        let mut current_pipeline = None;
+        if draws.is_empty() {
+            return;
+        }
 
-        match pipeline {
+        // Choose the pipeline.
+        let matches_params = |id: &PipelineId| Self::matches_params(params, id);
+        // Meshes written by the depth pre-pass are only shaded where their
+        // depth is the closest.
+        let label = if depth_prepass {
+            "entity_depth_prepassed"
+        } else {
+            "entity"
+        };
+        let pipeline = self.pipelines.get_all_filtered(label, matches_params);
+
+        let default_pipeline = match pipeline.as_ref().and_then(|p| p.first()) {
             None => {
                 log::error!("Missing pipeline for entity shading!");
                 return;
             }
-            Some(pipelines) => {
-                render_pass.set_pipeline(pipelines[0]);
-                current_pipeline = Some(pipelines[0]);
+            Some(pipeline) => *pipeline,
+        };
+        // Translucent meshes are not part of the depth pre-pass, the holes
+        // dithered or cut out in them must not hide what is behind.
+        let translucent_pipeline = if depth_prepass {
+            self.pipelines
+                .get_all_filtered("entity", matches_params)
+                .and_then(|p| p.first().copied())
+                .unwrap_or(default_pipeline)
+        } else {
+            default_pipeline
+        };
+        if self.slots.is_none() && renderer.textures_bind_group.is_none() {
+            log::error!("Missing texture bind group, the renderer is not prepared!");
+            return;
+        }
+
+        // Drop the recorded draw calls if the resources they bind changed.
+        let state = DrawBundlesState {
+            shaders_generation: self.shaders_generation,
+            environment_generation: self.environment_generation,
+            draws_generation: renderer.draws_generation,
+            locals_capacity: self.locals_bind_group.capacity,
+            shadow_map_count: self.shadow_maps.shadow_map_count,
+            enable_back_face_culling: params.enable_back_face_culling,
+            enable_wireframe: params.enable_wireframe,
+            casting_shadows: params.casting_shadows(),
+            enable_lighting: params.enable_lighting,
+            depth_prepass,
+        };
+        if self.draw_bundles.state != Some(state) {
+            self.draw_bundles.bundles.clear();
+            self.draw_bundles.state = Some(state);
+            // The frame group binds the resources the state depends on.
+            if let Some(slots) = self.slots.as_mut() {
+                slots.update_frame(
+                    &renderer.device,
+                    &self.globals_bind_group,
+                    renderer.environment_map.as_ref(),
+                    &self.locals_bind_group,
+                    &self.lights_bind_group,
+                    &self.shadow_maps,
+                );
             }
         }
 
-        // Bind globals.
-        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
-        // Bind shadow maps and sampler.
-        render_pass.set_bind_group(5, Some(&self.shadow_maps.bind_group), &[]);
+        // Reuse the draw calls recorded in the previous frames, only the
+        // batches of instances which changed are recorded again. Bundles
+        // which are not drawn anymore are dropped.
+        let mut recorded = std::mem::take(&mut self.draw_bundles.bundles);
+        let mut steps = Vec::with_capacity(draws.len());
+        let mut pending = Vec::new();
+        for ((shader, bundle, locals_offset, inst_count), indirect) in
+            draws.into_iter().zip(indirect_draws)
+        {
+            if let Some(args) = indirect {
+                steps.push(MainStep::Indirect(bundle, args));
+                continue;
+            }
+            let key = DrawBundleKey {
+                shader: shader.cloned(),
+                mesh: *bundle,
+                instances: locals_offset..locals_offset + inst_count,
+            };
+            match recorded.remove(&key) {
+                Some(draw_bundle) => {
+                    self.draw_bundles.bundles.insert(key.clone(), draw_bundle);
+                }
+                None => {
+                    // Use the pipeline of the custom shader if any, falling
+                    // back to the default pipeline. Custom shaders are not
+                    // part of the depth pre-pass as they may move the
+                    // vertices.
+                    let pipeline = shader
+                        .zip(renderer.meshes.get(bundle.mesh))
+                        .and_then(|(shader, mesh)| {
+                            self.pipelines
+                                .get_all_filtered(
+                                    &Self::custom_shader_label(shader, mesh),
+                                    matches_params,
+                                )
+                                .and_then(|p| p.first().copied())
+                        })
+                        .unwrap_or(if renderer.is_translucent(bundle) {
+                            translucent_pipeline
+                        } else {
+                            default_pipeline
+                        });
+                    pending.push((key.clone(), pipeline, bundle));
+                }
+            }
+            steps.push(MainStep::Bundle(key));
+        }
 
-        let enable_shadows = if params.casting_shadows() { 1u32 } else { 0u32 };
-        let enable_lighting = if params.enable_lighting { 1u32 } else { 0u32 };
+        // Record the new bundles, on multiple threads if there are many of
+        // them, e.g. once the scene or the bound resources changed.
+        let record =
+            |(key, pipeline, bundle): &(DrawBundleKey, &wgpu::RenderPipeline, &MeshBundle)| {
+                self.record_draw_bundle(renderer, params, pipeline, bundle, key.instances.clone())
+            };
+        let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let new_bundles = if pending.len() < Self::PARALLEL_BUNDLES_THRESHOLD || n_threads == 1 {
+            pending.iter().map(record).collect::<Vec<_>>()
+        } else {
+            let chunk_size = pending.len().div_ceil(n_threads);
+            std::thread::scope(|s| {
+                let handles = pending
+                    .chunks(chunk_size)
+                    .map(|chunk| s.spawn(move || chunk.iter().map(record).collect::<Vec<_>>()))
+                    .collect::<Vec<_>>();
+                handles
+                    .into_iter()
+                    .flat_map(|handle| handle.join().expect("Failed to record the draw bundles!"))
+                    .collect::<Vec<_>>()
+            })
+        };
+        for ((key, _, _), draw_bundle) in pending.into_iter().zip(new_bundles) {
+            if let Some(draw_bundle) = draw_bundle {
+                self.draw_bundles.bundles.insert(key, draw_bundle);
+            }
+        }
 
-        render_pass.set_push_constants(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
-            8,
-            bytemuck::bytes_of(&enable_shadows),
-        );
-        render_pass.set_push_constants(
-            wgpu::ShaderStages::VERTEX_FRAGMENT,
-            12,
-            bytemuck::bytes_of(&enable_lighting),
+        // Draw the batches in order, the bundles between two indirect draws
+        // being executed at once. Executing bundles resets the bound state.
+        // The batches whose bundle failed to be recorded are skipped.
+        let mut keys = Vec::new();
+        let mut bound = false;
+        for step in steps {
+            match step {
+                MainStep::Bundle(key) => keys.push(key),
+                MainStep::Indirect(bundle, args) => {
+                    if !keys.is_empty() {
+                        render_pass.execute_bundles(
+                            keys.drain(..)
+                                .filter_map(|key| self.draw_bundles.bundles.get(&key)),
+                        );
+                        bound = false;
+                    }
+                    self.draw_indirect(
+                        &mut render_pass,
+                        renderer,
+                        params,
+                        (!bound).then_some(default_pipeline),
+                        bundle,
+                        args,
+                    );
+                    bound = true;
+                }
+            }
+        }
+        render_pass.execute_bundles(
+            keys.iter()
+                .filter_map(|key| self.draw_bundles.bundles.get(key)),
         );
+    }
 
-        {
-            let mut unique_meshes = FxHashSet::default();
-            let mut n_inst = 0;
-            for (mesh, _) in meshes {
-                unique_meshes.insert(mesh);
-                n_inst += 1;
+    /// Prepares the indirect draws of the instances of an indexed triangle
+    /// mesh drawn with the default shader, one draw per sub-mesh.
+    ///
+    /// The push constants can't change between the draws of a single call,
+    /// so the locals of the instances are copied at the end of `locals` for
+    /// each sub-mesh, with the material of the sub-mesh. Returns the range
+    /// of the draws in `args`, or `None` if the mesh is drawn by a bundle.
+    fn indirect_draw_args(
+        renderer: &Renderer,
+        (shader, bundle, locals_offset, inst_count): &MainDraw,
+        locals: &mut Vec<Locals>,
+        args: &mut Vec<wgpu::util::DrawIndexedIndirectArgs>,
+    ) -> Option<Range<u32>> {
+        if shader.is_some() || renderer.is_translucent(bundle) {
+            return None;
+        }
+        let mesh = renderer.meshes.get(bundle.mesh).filter(|mesh| {
+            mesh.topology == wgpu::PrimitiveTopology::TriangleList
+                && mesh.index_format.is_some()
+                && mesh
+                    .get_vertex_attribute_range(VertexAttribute::POSITION)
+                    .is_some()
+        })?;
+        let n_materials = renderer
+            .material_bundles
+            .get(bundle.aesthetic.materials)?
+            .n_materials;
+        // Index range and material of each sub-mesh.
+        let sub_meshes = match mesh.sub_meshes.as_ref() {
+            Some(sub_meshes) => sub_meshes
+                .iter()
+                .map(|sm| (sm.range.clone(), sm.material.unwrap_or(n_materials - 1)))
+                .collect(),
+            None => vec![(0..mesh.index_count, 0)],
+        };
+        let instances = *locals_offset as usize..(*locals_offset + *inst_count) as usize;
+        let start = args.len() as u32;
+        for (range, material) in sub_meshes {
+            let first_instance = locals.len() as u32;
+            locals.extend_from_within(instances.clone());
+            for local in &mut locals[first_instance as usize..] {
+                local.material_index[2] = material;
+                local.material_index[3] = args.len() as u32;
             }
+            args.push(wgpu::util::DrawIndexedIndirectArgs {
+                index_count: range.end - range.start,
+                instance_count: *inst_count,
+                first_index: range.start,
+                base_vertex: 0,
+                first_instance,
+            });
+        }
+        Some(start..args.len() as u32)
+    }
 
-            log::debug!(
-                "Processed {} instances of {} meshes",
-                n_inst,
-                unique_meshes.len()
+    /// Draws the sub-meshes of the instances of a mesh with a single indirect
+    /// call. The state shared by the indirect draws is bound first if the
+    /// pipeline is given.
+    fn draw_indirect(
+        &self,
+        render_pass: &mut wgpu::RenderPass,
+        renderer: &Renderer,
+        params: &RenderParams,
+        pipeline: Option<&wgpu::RenderPipeline>,
+        bundle: &MeshBundle,
+        args: Range<u32>,
+    ) {
+        let (Some(indirect), Some(mesh), Some(mtls)) = (
+            self.indirect.as_ref(),
+            renderer.meshes.get(bundle.mesh),
+            renderer.material_bundles.get(bundle.aesthetic.materials),
+        ) else {
+            return;
+        };
+        if let Some(pipeline) = pipeline {
+            render_pass.set_pipeline(pipeline);
+            render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+            // The instances of the draws are the visible ones if culled.
+            match self.culling.as_ref().filter(|_| params.enable_gpu_culling) {
+                Some(culling) => render_pass.set_bind_group(1, &culling.culled_bind_group, &[]),
+                None => render_pass.set_bind_group(1, &self.locals_bind_group, &[]),
+            }
+            render_pass.set_bind_group(3, &self.lights_bind_group, &[]);
+            render_pass.set_bind_group(4, renderer.textures_bind_group.as_ref(), &[]);
+            render_pass.set_bind_group(5, Some(&self.shadow_maps.bind_group), &[]);
+            // The instances start at the first instance of the draws, and
+            // their material is in their locals.
+            let pconsts = PConsts {
+                instance_base_index: 0,
+                material_index: 0,
+                enable_shadows: params.casting_shadows() as u32,
+                enable_lighting: params.enable_lighting as u32,
+            };
+            self.constants.set(
+                &renderer.device,
+                &renderer.queue,
+                render_pass,
+                6,
+                wgpu::ShaderStages::VERTEX_FRAGMENT,
+                &pconsts,
             );
+        }
 
-            if n_inst == 0 {
-                return;
+        let mesh_buffer = renderer.meshes.buffer();
+        for attribute in Self::BUILTIN_ATTRIBUTES {
+            if let Some(range) = mesh.get_vertex_attribute_range(attribute) {
+                render_pass
+                    .set_vertex_buffer(attribute.shader_location, mesh_buffer.slice(range.clone()));
             }
+        }
+        render_pass.set_bind_group(2, &mtls.bind_group, &[]);
+        render_pass.set_index_buffer(
+            mesh_buffer.slice(mesh.index_range.clone()),
+            mesh.index_format.unwrap(),
+        );
+        render_pass.multi_draw_indexed_indirect(
+            &indirect.buffer,
+            args.start as u64 * IndirectDraws::ARGS_SIZE,
+            args.end - args.start,
+        );
+    }
 
-            // Resize locals buffer in case the number of instances is larger than
-            // the current capacity.
-            self.locals_bind_group.resize(&renderer.device, n_inst);
-            // Bind instance locals.
-            render_pass.set_bind_group(1, &self.locals_bind_group, &[]);
-            // Bind lights storage buffer.
-            render_pass.set_bind_group(3, &self.lights_bind_group, &[]);
+    /// Returns the label of the pipelines drawing the mesh with the custom
+    /// shader, which read the custom attributes of the mesh if it has any.
+    fn custom_shader_label(shader: &CustomShader, mesh: &GpuMesh) -> String {
+        let mut label = shader.label();
+        for (attribute, _) in mesh.custom_attribute_ranges() {
+            label.push_str(&format!(
+                "|{}:{:?}",
+                attribute.shader_location, attribute.format
+            ));
+        }
+        label
+    }
 
-            // Preparing locals for each mesh.
-            let mut locals = vec![Locals::identity(); n_inst as usize];
-            let mut locals_offset = 0u32;
-            // Get the mesh buffer, which contains all vertex attributes.
-            let mesh_buffer = renderer.meshes.buffer();
-            for bundle in unique_meshes {
-                let instances = renderer
-                    .instancing
-                    .get(bundle)
-                    .expect("Unreachable! Instancing should be created for all meshes!");
-                let mut inst_count = 0;
-                for (i, node_idx) in instances.iter().enumerate() {
-                    let node = &scene.nodes[*node_idx];
-                    if !node.is_visible() {
-                        continue;
-                    }
-                    inst_count += 1;
-                    let model_mat = scene.nodes.world(*node_idx).to_mat4();
-                    locals[locals_offset as usize + i] = Locals {
-                        model: model_mat.to_cols_array(),
-                        model_view_it: (view_mat * model_mat).inverse().transpose().to_cols_array(),
-                        material_index: [
-                            node.material_override.unwrap_or(u32::MAX),
-                            u32::MAX,
-                            u32::MAX,
-                            u32::MAX,
-                        ],
-                    }
+    /// Creates the pipelines of the custom shader reading the custom
+    /// attributes of the mesh, unless they have already been created.
+    fn create_custom_attributes_pipelines(
+        &mut self,
+        device: &wgpu::Device,
+        shader: &CustomShader,
+        mesh: &GpuMesh,
+    ) {
+        let attributes = mesh
+            .custom_attribute_ranges()
+            .map(|(attribute, _)| *attribute)
+            .collect::<Vec<_>>();
+        let label = Self::custom_shader_label(shader, mesh);
+        if attributes.is_empty()
+            || self.pipelines.get_by_label(&label).is_some()
+            || self.custom_shaders.failed.contains(&label)
+        {
+            return;
+        }
+        let (Some(layout), Some(shader_module)) = (
+            self.custom_shaders.layout.as_ref(),
+            self.custom_shaders.modules.get(&shader.label()),
+        ) else {
+            return;
+        };
+        let created = validated(device, || {
+            let mut created = Vec::new();
+            for cull_mode in [Some(wgpu::Face::Back), None] {
+                for polygon_mode in [wgpu::PolygonMode::Fill, wgpu::PolygonMode::Line] {
+                    let id = PipelineId::from_states(
+                        PipelineKind::Render,
+                        wgpu::PrimitiveTopology::TriangleList,
+                        polygon_mode,
+                        cull_mode,
+                    );
+                    created.push(self.create_main_render_pass_pipeline(
+                        device,
+                        layout,
+                        shader_module,
+                        id,
+                        false,
+                        &attributes,
+                    ));
+                }
+            }
+            created
+        });
+        match created {
+            Ok(created) => {
+                for (id, pipeline) in created {
+                    self.pipelines.insert(&label, id, pipeline);
                 }
-                debug_assert!(
-                    inst_count > 0,
-                    "Unreachable! Only visible nodes will be rendered!"
+            }
+            Err(err) => {
+                log::error!("Failed to create the pipelines {}: {}", label, err);
+                self.custom_shaders.failed.insert(label);
+            }
+        }
+    }
+
+    /// Returns whether the pipeline has the cull mode and polygon mode
+    /// selected by the render parameters.
+    fn matches_params(params: &RenderParams, id: &PipelineId) -> bool {
+        let cull_mode = if params.enable_back_face_culling {
+            Some(wgpu::Face::Back)
+        } else {
+            None
+        };
+        let polygon_mode = if params.enable_wireframe {
+            wgpu::PolygonMode::Line
+        } else {
+            wgpu::PolygonMode::Fill
+        };
+        id.cull_mode() == cull_mode && id.polygon_mode() == polygon_mode
+    }
+
+    /// Returns the pipeline drawing the meshes of the given topology, which
+    /// are not triangle lists, in the main pass.
+    fn primitives_pipeline(
+        &self,
+        params: &RenderParams,
+        topology: wgpu::PrimitiveTopology,
+    ) -> Option<&wgpu::RenderPipeline> {
+        self.pipelines
+            .get_all_filtered("primitives", |id| {
+                id.topology() == topology
+                    && (topology != wgpu::PrimitiveTopology::TriangleStrip
+                        || Self::matches_params(params, id))
+            })
+            .and_then(|p| p.first().copied())
+    }
+
+    /// Records the draw calls of the instances of a mesh into a render
+    /// bundle, `instances` being the range of their locals.
+    fn record_draw_bundle(
+        &self,
+        renderer: &Renderer,
+        params: &RenderParams,
+        pipeline: &wgpu::RenderPipeline,
+        bundle: &MeshBundle,
+        instances: Range<u32>,
+    ) -> Option<wgpu::RenderBundle> {
+        profiling::scope!("BlinnPhongShading::record_draw_bundle");
+        let mesh = match renderer.meshes.get(bundle.mesh) {
+            None => {
+                log::error!("Missing mesh {:?}", bundle.mesh);
+                return None;
+            }
+            Some(mesh) => mesh,
+        };
+        let mtls = renderer
+            .material_bundles
+            .get(bundle.aesthetic.materials)
+            .unwrap();
+        // Without binding arrays, the materials are bound with their
+        // textures.
+        let (materials_group, materials_bind_group, constants_group) = match &self.slots {
+            Some(_) => match renderer.material_slots.get(&bundle.aesthetic) {
+                Some(bind_group) => (1, bind_group, 2),
+                None => {
+                    log::error!("Missing material slots of {:?}", bundle.aesthetic);
+                    return None;
+                }
+            },
+            None => (2, &mtls.bind_group, 6),
+        };
+
+        // Constants of the draws, one per sub-mesh, or one for the whole mesh
+        // drawn with the default material.
+        let pconsts = match mesh.sub_meshes.as_ref() {
+            None => vec![0],
+            Some(sub_meshes) => sub_meshes
+                .iter()
+                .map(|sm| sm.material.unwrap_or(mtls.n_materials - 1))
+                .collect(),
+        }
+        .into_iter()
+        .map(|material_index| PConsts {
+            instance_base_index: instances.start,
+            material_index,
+            enable_shadows: params.casting_shadows() as u32,
+            enable_lighting: params.enable_lighting as u32,
+        })
+        .collect::<Vec<_>>();
+        let constants = self
+            .constants
+            .prepare(&renderer.device, &renderer.queue, &pconsts);
+        let stages = wgpu::ShaderStages::VERTEX_FRAGMENT;
+
+        let mut encoder =
+            renderer
+                .device
+                .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
+                    label: Some("blinn_phong_draw_bundle_encoder"),
+                    color_formats: &[Some(self.format)],
+                    depth_stencil: Some(wgpu::RenderBundleDepthStencil {
+                        format: self.depth.texture_format(),
+                        depth_read_only: false,
+                        stencil_read_only: true,
+                    }),
+                    sample_count: self.depth.samples,
+                    multiview: None,
+                });
+        // Meshes which are not triangle lists are drawn with the pipeline of
+        // their topology, whatever their shader.
+        let pipeline = match mesh.topology {
+            wgpu::PrimitiveTopology::TriangleList => pipeline,
+            topology => match self.primitives_pipeline(params, topology) {
+                Some(pipeline) => pipeline,
+                None => {
+                    log::error!("Missing pipeline for {:?} meshes!", topology);
+                    return None;
+                }
+            },
+        };
+        encoder.set_pipeline(pipeline);
+
+        match &self.slots {
+            // Bind globals, instance locals, lights and shadow maps at once.
+            Some(slots) => encoder.set_bind_group(0, slots.frame.as_ref(), &[]),
+            None => {
+                // Bind globals.
+                encoder.set_bind_group(0, &self.globals_bind_group, &[]);
+                // Bind instance locals.
+                encoder.set_bind_group(1, &self.locals_bind_group, &[]);
+                // Bind lights storage buffer.
+                encoder.set_bind_group(3, &self.lights_bind_group, &[]);
+                // Bind the textures shared by all materials.
+                encoder.set_bind_group(4, renderer.textures_bind_group.as_ref(), &[]);
+                // Bind shadow maps and sampler.
+                encoder.set_bind_group(5, Some(&self.shadow_maps.bind_group), &[]);
+            }
+        }
+
+        let inst_range = 0..instances.len() as u32;
+
+        // Get the mesh buffer, which contains all vertex attributes.
+        let mesh_buffer = renderer.meshes.buffer();
+        if let Some(pos_range) = mesh.get_vertex_attribute_range(VertexAttribute::POSITION) {
+            // Bind vertex buffer - position.
+            encoder.set_vertex_buffer(0, mesh_buffer.slice(pos_range.clone()));
+
+            // Bind vertex buffer - normal.
+            if let Some(normals_range) = mesh.get_vertex_attribute_range(VertexAttribute::NORMAL) {
+                encoder.set_vertex_buffer(1, mesh_buffer.slice(normals_range.clone()));
+            }
+            // Bind vertex buffer - uv.
+            if let Some(uv_range) = mesh.get_vertex_attribute_range(VertexAttribute::UV) {
+                encoder.set_vertex_buffer(2, mesh_buffer.slice(uv_range.clone()));
+            }
+            // Bind vertex buffer - tangent.
+            if let Some(tangent_range) = mesh.get_vertex_attribute_range(VertexAttribute::TANGENT) {
+                encoder.set_vertex_buffer(
+                    VertexAttribute::TANGENT.shader_location,
+                    mesh_buffer.slice(tangent_range.clone()),
                 );
-                // Update push constants: isntance base index.
-                render_pass.set_push_constants(
-                    wgpu::ShaderStages::VERTEX_FRAGMENT,
-                    0,
-                    bytemuck::bytes_of(&locals_offset),
+            }
+            // Bind vertex buffer - color.
+            if let Some(color_range) = mesh.get_vertex_attribute_range(VertexAttribute::COLOR) {
+                encoder.set_vertex_buffer(
+                    VertexAttribute::COLOR.shader_location,
+                    mesh_buffer.slice(color_range.clone()),
                 );
-                locals_offset += inst_count;
-                let inst_range = 0..inst_count;
+            }
+            // Bind vertex buffers - custom attributes, read by custom shaders.
+            for (i, (_, range)) in mesh.custom_attribute_ranges().enumerate() {
+                encoder.set_vertex_buffer(
+                    (Self::BUILTIN_ATTRIBUTES.len() + i) as u32,
+                    mesh_buffer.slice(range.clone()),
+                );
+            }
 
-                let mtls = renderer
-                    .material_bundles
-                    .get(bundle.aesthetic.materials)
-                    .unwrap();
-                let texs = renderer
-                    .texture_bundles
-                    .get(bundle.aesthetic.textures)
-                    .unwrap();
-
-                match renderer.meshes.get(bundle.mesh) {
-                    None => {
-                        log::error!("Missing mesh {:?}", bundle.mesh);
-                        continue;
-                    }
-                    Some(mesh) => {
-                        if let Some(pos_range) =
-                            mesh.get_vertex_attribute_range(VertexAttribute::POSITION)
-                        {
-                            // Bind vertex buffer - position.
-                            render_pass.set_vertex_buffer(0, mesh_buffer.slice(pos_range.clone()));
-
-                            // Bind vertex buffer - normal.
-                            if let Some(normals_range) =
-                                mesh.get_vertex_attribute_range(VertexAttribute::NORMAL)
-                            {
-                                render_pass
-                                    .set_vertex_buffer(1, mesh_buffer.slice(normals_range.clone()));
-                            }
-                            // Bind vertex buffer - uv.
-                            if let Some(uv_range) =
-                                mesh.get_vertex_attribute_range(VertexAttribute::UV)
-                            {
-                                render_pass
-                                    .set_vertex_buffer(2, mesh_buffer.slice(uv_range.clone()));
+            // Bind material.
+            encoder.set_bind_group(materials_group, materials_bind_group, &[]);
+
+            match mesh.index_format {
+                None => {
+                    // No index buffer, draw directly.
+                    match mesh.sub_meshes.as_ref() {
+                        None => {
+                            // No sub-meshes, use the default material.
+                            constants.set(&mut encoder, constants_group, stages, 0);
+                            encoder.draw(0..mesh.vertex_count, inst_range);
+                        }
+                        Some(sub_meshes) => {
+                            // Draw each sub-mesh with its material.
+                            for (i, sm) in sub_meshes.iter().enumerate() {
+                                constants.set(&mut encoder, constants_group, stages, i);
+                                encoder.draw(sm.range.start..sm.range.end, inst_range.clone())
                             }
-                            // Bind vertex buffer - tangent.
-                            if let Some(tangent_range) =
-                                mesh.get_vertex_attribute_range(VertexAttribute::TANGENT)
-                            {
-                                render_pass.set_vertex_buffer(
-                                    VertexAttribute::TANGENT.shader_location,
-                                    mesh_buffer.slice(tangent_range.clone()),
+                        }
+                    }
+                }
+                Some(index_format) => {
+                    encoder.set_index_buffer(
+                        mesh_buffer.slice(mesh.index_range.clone()),
+                        index_format,
+                    );
+                    match mesh.sub_meshes.as_ref() {
+                        None => {
+                            log::trace!("Draw mesh with index, no sub-meshes");
+                            // No sub-meshes, use the default material.
+                            constants.set(&mut encoder, constants_group, stages, 0);
+                            encoder.draw_indexed(0..mesh.index_count, 0, inst_range);
+                        }
+                        Some(sub_meshes) => {
+                            log::trace!("Draw mesh with index, with sub-meshes");
+                            for (i, sm) in sub_meshes.iter().enumerate() {
+                                log::trace!("Draw sub-mesh {}-{}", sm.range.start, sm.range.end);
+                                // Update the material index.
+                                constants.set(&mut encoder, constants_group, stages, i);
+                                // Draw the sub-mesh.
+                                encoder.draw_indexed(
+                                    sm.range.start..sm.range.end,
+                                    0,
+                                    inst_range.clone(),
                                 );
                             }
+                        }
+                    }
+                }
+            }
+        }
 
-                            // Bind material.
-                            render_pass.set_bind_group(2, &mtls.bind_group, &[]);
-                            // Bind textures.
-                            render_pass.set_bind_group(4, texs.bind_group.as_ref().unwrap(), &[]);
+        Some(encoder.finish(&wgpu::RenderBundleDescriptor {
+            label: Some("blinn_phong_draw_bundle"),
+        }))
+    }
 
-                            // TODO: ad-hoc solution for line meshes. Need to refactor.
-                            if mesh.topology == wgpu::PrimitiveTopology::LineList {
-                                render_pass.set_pipeline(
-                                    &self.pipelines.get_by_label("lines").unwrap()[0].1,
-                                );
-                                render_pass.set_index_buffer(
-                                    mesh_buffer.slice(mesh.index_range.clone()),
-                                    mesh.index_format.unwrap(),
-                                );
+    /// Evaluates the depth pre-pass, writing the depth of the opaque
+    /// triangle meshes drawn with the default shader.
+    fn eval_depth_prepass(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        draws: &[MainDraw],
+        renderer: &Renderer,
+        params: &RenderParams,
+    ) {
+        profiling::scope!("BlinnPhongShading::eval_depth_prepass");
+        let cull_mode = if params.enable_back_face_culling {
+            Some(wgpu::Face::Back)
+        } else {
+            None
+        };
+        let pipeline = match self
+            .pipelines
+            .get_all_filtered("depth_prepass", |id| id.cull_mode() == cull_mode)
+            .and_then(|p| p.first().copied())
+        {
+            None => {
+                log::error!("Missing pipeline for the depth pre-pass!");
+                return;
+            }
+            Some(pipeline) => pipeline,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("blinn_phong_depth_prepass"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_att.as_ref().unwrap().1,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.depth.clear_value()),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        self.viewport.apply(&mut render_pass);
+        // Bind globals.
+        render_pass.set_bind_group(0, &self.globals_bind_group, &[]);
+        // Bind instance locals.
+        render_pass.set_bind_group(1, &self.locals_bind_group, &[]);
+
+        let mesh_buffer = renderer.meshes.buffer();
+        for (shader, bundle, locals_offset, inst_count) in draws {
+            if shader.is_some() || renderer.is_translucent(bundle) {
+                continue;
+            }
+            let mesh = match renderer.meshes.get(bundle.mesh) {
+                Some(mesh) if mesh.topology == wgpu::PrimitiveTopology::TriangleList => mesh,
+                _ => continue,
+            };
+            // Bind vertex buffer - position.
+            match mesh.get_vertex_attribute_range(VertexAttribute::POSITION) {
+                Some(pos_range) => {
+                    render_pass.set_vertex_buffer(0, mesh_buffer.slice(pos_range.clone()))
+                }
+                None => continue,
+            }
+            // Set the constants - instance base index.
+            self.depth_constants.set(
+                &renderer.device,
+                &renderer.queue,
+                &mut render_pass,
+                2,
+                wgpu::ShaderStages::VERTEX,
+                locals_offset,
+            );
+            // Draw the same ranges as the main render pass.
+            match mesh.index_format {
+                Some(index_format) => {
+                    render_pass.set_index_buffer(
+                        mesh_buffer.slice(mesh.index_range.clone()),
+                        index_format,
+                    );
+                    match mesh.sub_meshes.as_ref() {
+                        Some(sub_meshes) => {
+                            for sm in sub_meshes {
                                 render_pass.draw_indexed(
-                                    0..mesh.index_count,
+                                    sm.range.start..sm.range.end,
                                     0,
-                                    inst_range.clone(),
+                                    0..*inst_count,
                                 );
-                                // Set back to the original pipeline.
-                                render_pass.set_pipeline(current_pipeline.unwrap());
-                            } else {
-                                match mesh.index_format {
-                                    None => {
-                                        // No index buffer, draw directly.
-                                        match mesh.sub_meshes.as_ref() {
-                                            None => {
-                                                // No sub-meshes, use the default material.
-                                                // Update material index.
-                                                render_pass.set_push_constants(
-                                                    wgpu::ShaderStages::VERTEX_FRAGMENT,
-                                                    4,
-                                                    bytemuck::bytes_of(&0u32),
-                                                );
-                                                render_pass.draw(0..mesh.vertex_count, inst_range);
-                                            }
-                                            Some(sub_meshes) => {
-                                                // Draw each sub-mesh.
-                                                for sm in sub_meshes {
-                                                    let material_id =
-                                                        sm.material.unwrap_or(mtls.n_materials - 1);
-                                                    // Update material index.
-                                                    render_pass.set_push_constants(
-                                                        wgpu::ShaderStages::VERTEX_FRAGMENT,
-                                                        4,
-                                                        bytemuck::bytes_of(&material_id),
-                                                    );
-                                                    render_pass.draw(
-                                                        sm.range.start..sm.range.end,
-                                                        inst_range.clone(),
-                                                    )
-                                                }
-                                            }
-                                        }
-                                    }
-                                    Some(index_format) => {
-                                        render_pass.set_index_buffer(
-                                            mesh_buffer.slice(mesh.index_range.clone()),
-                                            index_format,
-                                        );
-                                        match mesh.sub_meshes.as_ref() {
-                                            None => {
-                                                log::trace!("Draw mesh with index, no sub-meshes");
-                                                // No sub-meshes, use the default material.
-                                                // Update material index.
-                                                render_pass.set_push_constants(
-                                                    wgpu::ShaderStages::VERTEX_FRAGMENT,
-                                                    4,
-                                                    bytemuck::bytes_of(&0u32),
-                                                );
-                                                render_pass.draw_indexed(
-                                                    0..mesh.index_count,
-                                                    0,
-                                                    inst_range,
-                                                );
-                                            }
-                                            Some(sub_meshes) => {
-                                                log::trace!(
-                                                    "Draw mesh with index, with sub-meshes"
-                                                );
-                                                for sm in sub_meshes {
-                                                    log::trace!(
-                                                        "Draw sub-mesh {}-{}",
-                                                        sm.range.start,
-                                                        sm.range.end
-                                                    );
-                                                    let material_id =
-                                                        sm.material.unwrap_or(mtls.n_materials - 1);
-                                                    // Update material index.
-                                                    render_pass.set_push_constants(
-                                                        wgpu::ShaderStages::VERTEX_FRAGMENT,
-                                                        4,
-                                                        bytemuck::bytes_of(&material_id),
-                                                    );
-                                                    // Draw the sub-mesh.
-                                                    render_pass.draw_indexed(
-                                                        sm.range.start..sm.range.end,
-                                                        0,
-                                                        inst_range.clone(),
-                                                    );
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
                             }
                         }
+                        None => render_pass.draw_indexed(0..mesh.index_count, 0, 0..*inst_count),
                     }
                 }
+                None => match mesh.sub_meshes.as_ref() {
+                    Some(sub_meshes) => {
+                        for sm in sub_meshes {
+                            render_pass.draw(sm.range.start..sm.range.end, 0..*inst_count);
+                        }
+                    }
+                    None => render_pass.draw(0..mesh.vertex_count, 0..*inst_count),
+                },
             }
-            renderer.queue.write_buffer(
-                &self.locals_bind_group.buffer,
-                0,
-                bytemuck::cast_slice(&locals),
-            );
         }
     }
 
-    fn create_shadow_maps_pass_pipeline(
+    /// Creates a pipeline of the shadow maps pass. If `alpha_tested` is
+    /// true, the pipeline also reads the texture coordinates and discards
+    /// the fragments cut out by the diffuse or opacity map of the material.
+    fn create_shadow_maps_pass_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader_module: &wgpu::ShaderModule,
+        alpha_tested: bool,
+        cull_mode: Option<wgpu::Face>,
+    ) -> (PipelineId, wgpu::RenderPipeline) {
+        let id = PipelineId::from_states(
+            PipelineKind::Render,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            cull_mode,
+        );
+        let position = [wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 0,
+            format: wgpu::VertexFormat::Float32x3,
+        }];
+        let uv = [wgpu::VertexAttribute {
+            offset: 0,
+            shader_location: 1,
+            format: wgpu::VertexFormat::Float32x2,
+        }];
+        let buffers = [
+            wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &position,
+            },
+            wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &uv,
+            },
+        ];
+        let (label, vertex_entry_point, buffers) = if alpha_tested {
+            (
+                "blinn_phong_shadow_maps_alpha_test_pipeline",
+                "vs_alpha_test",
+                &buffers[..],
+            )
+        } else {
+            ("blinn_phong_shadow_maps_pipeline", "vs_main", &buffers[..1])
+        };
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader_module,
+                entry_point: Some(vertex_entry_point),
+                compilation_options: Default::default(),
+                buffers,
+            },
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: Default::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2, // corresponds to bilinear filtering
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            fragment: alpha_tested.then(|| wgpu::FragmentState {
+                module: shader_module,
+                entry_point: Some("fs_alpha_test"),
+                compilation_options: Default::default(),
+                targets: &[],
+            }),
+            multiview: None,
+            cache: None,
+        });
+        (id, pipeline)
+    }
+
+    /// Returns the pipeline of the shadow maps pass drawing the sub-meshes
+    /// of a material, and the shader stages reading its push constants.
+    fn shadow_pipeline(
+        pipelines: &Pipelines,
+        alpha_tested: bool,
+        double_sided: bool,
+    ) -> (&wgpu::RenderPipeline, wgpu::ShaderStages) {
+        let id = PipelineId::from_states(
+            PipelineKind::Render,
+            wgpu::PrimitiveTopology::TriangleList,
+            wgpu::PolygonMode::Fill,
+            (!double_sided).then_some(wgpu::Face::Back),
+        );
+        if alpha_tested {
+            let pipeline = pipelines.get("shadow_alpha_test", id).unwrap();
+            (pipeline, wgpu::ShaderStages::VERTEX_FRAGMENT)
+        } else {
+            let pipeline = pipelines.get("shadow", id).unwrap();
+            (pipeline, wgpu::ShaderStages::VERTEX)
+        }
+    }
+
+    /// Creates the pipeline of the depth pre-pass, which only writes the
+    /// depth of the meshes, using the position-only vertex layout of the
+    /// shadow maps pass.
+    fn create_depth_prepass_pipeline(
+        &self,
         device: &wgpu::Device,
         layout: &wgpu::PipelineLayout,
         shader_module: &wgpu::ShaderModule,
+        cull_mode: Option<wgpu::Face>,
     ) -> (PipelineId, wgpu::RenderPipeline) {
         let id = PipelineId::from_states(
             PipelineKind::Render,
             wgpu::PrimitiveTopology::TriangleList,
             wgpu::PolygonMode::Fill,
-            Some(wgpu::Face::Back),
+            cull_mode,
         );
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("blinn_phong_shadow_maps_pipeline"),
+            label: Some("blinn_phong_depth_prepass_pipeline"),
             layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: shader_module,
@@ -1063,26 +2288,18 @@ impl BlinnPhongRenderPass {
             primitive: wgpu::PrimitiveState {
                 topology: wgpu::PrimitiveTopology::TriangleList,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
+                cull_mode,
                 polygon_mode: wgpu::PolygonMode::Fill,
                 ..Default::default()
             },
             depth_stencil: Some(wgpu::DepthStencilState {
-                format: DEPTH_FORMAT,
+                format: self.depth.texture_format(),
                 depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
+                depth_compare: self.depth.compare(),
                 stencil: Default::default(),
-                bias: wgpu::DepthBiasState {
-                    constant: 2, // corresponds to bilinear filtering
-                    slope_scale: 2.0,
-                    clamp: 0.0,
-                },
+                bias: Default::default(),
             }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+            multisample: self.depth.multisample(),
             fragment: None,
             multiview: None,
             cache: None,
@@ -1090,80 +2307,77 @@ impl BlinnPhongRenderPass {
         (id, pipeline)
     }
 
+    /// Creates a pipeline of the main render pass with the states of the
+    /// given id. If `depth_prepassed` is true, the pipeline only draws the
+    /// fragments whose depth equals the one written by the depth pre-pass.
+    /// The custom attributes are read from the vertex buffers following the
+    /// ones of the built-in attributes.
     fn create_main_render_pass_pipeline(
+        &self,
         device: &wgpu::Device,
         layout: &wgpu::PipelineLayout,
-        output_format: wgpu::TextureFormat,
         shader_module: &wgpu::ShaderModule,
-        polygon_mode: wgpu::PolygonMode,
-        topology: wgpu::PrimitiveTopology,
-        cull_mode: Option<wgpu::Face>,
+        id: PipelineId,
+        depth_prepassed: bool,
+        custom_attributes: &[VertexAttribute],
     ) -> (PipelineId, wgpu::RenderPipeline) {
-        let id = PipelineId::from_states(PipelineKind::Render, topology, polygon_mode, cull_mode);
+        // Points are drawn as quads of two triangles facing the camera.
+        let (topology, vertex_entry_point) = match id.topology() {
+            wgpu::PrimitiveTopology::PointList => {
+                (wgpu::PrimitiveTopology::TriangleList, "vs_points")
+            }
+            topology => (topology, "vs_main"),
+        };
+        // Wireframes are drawn filled if the device can't draw lines.
+        let polygon_mode = match id.polygon_mode() {
+            wgpu::PolygonMode::Line
+                if !device
+                    .features()
+                    .contains(wgpu::Features::POLYGON_MODE_LINE) =>
+            {
+                wgpu::PolygonMode::Fill
+            }
+            polygon_mode => polygon_mode,
+        };
+        // Each vertex attribute is stored in its own buffer.
+        let vertex_attributes = Self::BUILTIN_ATTRIBUTES
+            .iter()
+            .chain(custom_attributes)
+            .collect::<Vec<_>>();
+        let buffer_attributes = vertex_attributes
+            .iter()
+            .map(|attribute| {
+                [wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: attribute.shader_location,
+                    format: attribute.format,
+                }]
+            })
+            .collect::<Vec<_>>();
+        let buffers = vertex_attributes
+            .iter()
+            .zip(&buffer_attributes)
+            .map(|(attribute, attributes)| wgpu::VertexBufferLayout {
+                array_stride: attribute.size as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes,
+            })
+            .collect::<Vec<_>>();
         let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("blinn_phong_shading_pipeline"),
             layout: Some(layout),
             vertex: wgpu::VertexState {
                 module: shader_module,
-                entry_point: Some("vs_main"),
+                entry_point: Some(vertex_entry_point),
                 compilation_options: Default::default(),
-                buffers: &[
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            // Position.
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                shader_location: 0,
-                                format: wgpu::VertexFormat::Float32x3,
-                            },
-                        ],
-                    },
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            // Normal.
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                shader_location: 1,
-                                format: wgpu::VertexFormat::Float32x3,
-                            },
-                        ],
-                    },
-                    wgpu::VertexBufferLayout {
-                        array_stride: std::mem::size_of::<[f32; 2]>() as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            // UV.
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                shader_location: 2,
-                                format: wgpu::VertexFormat::Float32x2,
-                            },
-                        ],
-                    },
-                    wgpu::VertexBufferLayout {
-                        array_stride: VertexAttribute::TANGENT.size as wgpu::BufferAddress,
-                        step_mode: wgpu::VertexStepMode::Vertex,
-                        attributes: &[
-                            // Tangent.
-                            wgpu::VertexAttribute {
-                                offset: 0,
-                                shader_location: VertexAttribute::TANGENT.shader_location,
-                                format: VertexAttribute::TANGENT.format,
-                            },
-                        ],
-                    },
-                ],
+                buffers: &buffers,
             },
             fragment: Some(wgpu::FragmentState {
                 module: shader_module,
                 entry_point: Some("fs_main"),
                 compilation_options: Default::default(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: output_format,
+                    format: self.format,
                     blend: Some(wgpu::BlendState {
                         color: wgpu::BlendComponent {
                             src_factor: wgpu::BlendFactor::SrcAlpha,
@@ -1182,22 +2396,28 @@ impl BlinnPhongRenderPass {
             primitive: wgpu::PrimitiveState {
                 topology,
                 front_face: wgpu::FrontFace::Ccw,
-                cull_mode,
+                cull_mode: id.cull_mode(),
                 polygon_mode,
                 ..Default::default()
             },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: Default::default(),
-                bias: Default::default(),
+            depth_stencil: Some(if depth_prepassed {
+                wgpu::DepthStencilState {
+                    format: self.depth.texture_format(),
+                    depth_write_enabled: false,
+                    depth_compare: wgpu::CompareFunction::Equal,
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }
+            } else {
+                wgpu::DepthStencilState {
+                    format: self.depth.texture_format(),
+                    depth_write_enabled: true,
+                    depth_compare: self.depth.compare(),
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }
             }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
+            multisample: self.depth.multisample(),
             multiview: None,
             cache: None,
         });
@@ -1205,7 +2425,10 @@ impl BlinnPhongRenderPass {
     }
 }
 
-pub fn texture_bundle_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+/// Creates the layout of the global texture bind group shared by all
+/// materials. It contains `n_textures` textures, the index of the sampler
+/// used by each texture, and the samplers.
+pub fn textures_bind_group_layout(device: &wgpu::Device, n_textures: u32) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some("blinn_phong_textures_bind_group_layout"),
         entries: &[
@@ -1217,7 +2440,7 @@ pub fn texture_bundle_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGrou
                     sample_type: wgpu::TextureSampleType::Float { filterable: true },
                     view_dimension: wgpu::TextureViewDimension::D2,
                 },
-                count: NonZeroU32::new(BlinnPhongRenderPass::MAX_TEXTURE_ARRAY_LEN as u32),
+                count: NonZeroU32::new(n_textures),
             },
             wgpu::BindGroupLayoutEntry {
                 binding: 1,
@@ -1226,8 +2449,7 @@ pub fn texture_bundle_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGrou
                     ty: wgpu::BufferBindingType::Storage { read_only: true },
                     has_dynamic_offset: false,
                     min_binding_size: NonZeroU64::new(
-                        std::mem::size_of::<u32>() as u64
-                            * BlinnPhongRenderPass::MAX_TEXTURE_ARRAY_LEN as u64,
+                        std::mem::size_of::<u32>() as u64 * n_textures as u64,
                     ),
                 },
                 count: None,
@@ -1242,6 +2464,47 @@ pub fn texture_bundle_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGrou
     })
 }
 
+/// Creates the layout of the bind group of the materials of a mesh with
+/// their textures, used without binding arrays. The materials are followed
+/// by the [`BlinnPhongRenderPass::MAX_TEXTURE_SLOTS`] textures, then by the
+/// sampler of each texture.
+pub fn material_slots_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let n_slots = BlinnPhongRenderPass::MAX_TEXTURE_SLOTS as u32;
+    let materials = wgpu::BindGroupLayoutEntry {
+        binding: 0,
+        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only: true },
+            has_dynamic_offset: false,
+            min_binding_size: wgpu::BufferSize::new(GpuMaterial::SIZE),
+        },
+        count: None,
+    };
+    let textures = (0..n_slots).map(|i| wgpu::BindGroupLayoutEntry {
+        binding: 1 + i,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+        },
+        count: None,
+    });
+    let samplers = (0..n_slots).map(|i| wgpu::BindGroupLayoutEntry {
+        binding: 1 + n_slots + i,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+        count: None,
+    });
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("blinn_phong_material_slots_bind_group_layout"),
+        entries: &std::iter::once(materials)
+            .chain(textures)
+            .chain(samplers)
+            .collect::<Vec<_>>(),
+    })
+}
+
 impl RenderingPass for BlinnPhongRenderPass {
     fn record(
         &mut self,
@@ -1249,31 +2512,121 @@ impl RenderingPass for BlinnPhongRenderPass {
         target: &RenderTarget,
         params: &RenderParams,
         scene: &Scene,
-        encoder: &mut wgpu::CommandEncoder,
-    ) {
+    ) -> Vec<wgpu::CommandBuffer> {
         profiling::scope!("BlinnPhongShading::record");
-        let mut mesh_bundle_query = <(&MeshBundle, &NodeIdx)>::query();
-        let visible_meshes = mesh_bundle_query
-            .iter(&scene.world)
-            .filter(|(_, node_idx)| scene.nodes[**node_idx].is_visible())
-            .collect::<Vec<_>>();
+        // The reflection is rendered without culling, the mirrored faces
+        // winding the other way and the main camera's visibility not
+        // applying to the mirrored one.
+        let mirrored_params;
+        let (params, mirror) = if self.reflection {
+            let Some((_, plane)) = WaterRenderPass::reflection_plane(scene) else {
+                return Vec::new();
+            };
+            mirrored_params = RenderParams {
+                enable_back_face_culling: false,
+                enable_occlusion_culling: false,
+                enable_gpu_culling: false,
+                show_debug_gizmos: false,
+                ..params.clone()
+            };
+            (&mirrored_params, Some(plane))
+        } else {
+            (params, None)
+        };
 
-        if visible_meshes.is_empty() {
-            // No visible meshes, skip rendering.
-            return;
+        // Recycle the staging chunks of the previous frames, which have been
+        // submitted since.
+        self.staging.recall();
+        self.shadow_staging.recall();
+
+        let visible_meshes = <(
+            &MeshBundle,
+            &NodeIdx,
+            Option<&RenderLayer>,
+            Option<&CustomShader>,
+        )>::query()
+        .iter(&scene.world)
+        .filter(|(_, node_idx, _, _)| scene.nodes[**node_idx].is_visible())
+        .collect::<Vec<_>>();
+
+        let has_effects = <&Sprite>::query().iter(&scene.world).next().is_some()
+            || <&ParticleEmitter>::query()
+                .iter(&scene.world)
+                .next()
+                .is_some()
+            || <&Water>::query().iter(&scene.world).next().is_some()
+            || scene.gizmo.is_some()
+            || (params.show_debug_gizmos && self.is_main_view());
+        if visible_meshes.is_empty() && !has_effects {
+            // No visible meshes, sprites, particles, water nor gizmos, skip
+            // rendering.
+            return Vec::new();
+        }
+
+        // The shadow maps are encoded on their own thread while the main
+        // pass is prepared, their commands are submitted first.
+        let mut shadow_encoder =
+            renderer
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("blinn_phong_shadow_maps_encoder"),
+                });
+        let mut encoder = renderer
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("blinn_phong_main_encoder"),
+            });
+
+        // Rebuild the pipelines if the shaders changed.
+        if self.shaders_generation != renderer.shaders.generation() {
+            self.create_pipelines(&renderer.device, &renderer.shaders);
+        }
+
+        // Bind the new environment map if it changed.
+        if self.environment_generation != renderer.environment_generation {
+            self.globals_bind_group
+                .set_environment(&renderer.device, renderer.environment_map.as_ref());
+            self.environment_generation = renderer.environment_generation;
         }
 
+        let camera = match self.camera {
+            Some(entity) => Self::entity_camera(scene, entity),
+            None => Self::main_camera(scene),
+        };
+
         // Update lights information.
         {
-            let mut light_query = <(&Light, &NodeIdx)>::query();
-            let active_lights = light_query
+            let mut light_query = <(legion::Entity, &Light, &NodeIdx)>::query();
+            let mut active_lights = light_query
                 .iter(&scene.world)
-                .filter(|(_, node_idx)| scene.nodes[**node_idx].is_active())
+                .filter(|(_, _, node_idx)| scene.nodes[**node_idx].is_active())
                 .collect::<Vec<_>>();
+            // The lights the closest to the camera are kept if there are
+            // too many.
+            let view_pos = camera.map_or(Vec3::ZERO, |(_, node_idx)| {
+                scene.nodes.world(node_idx).translation
+            });
+            self.lights_bind_group
+                .fit_lights(&mut active_lights, &scene.nodes, view_pos);
+            // Only the lights casting shadows get a shadow map. The shaders
+            // sample the layers of the first texture of the shadow maps.
+            let capacity = renderer.limits.max_texture_array_layers as usize;
+            self.shadow_casters = ShadowCasters::assign(&active_lights, &scene.nodes, capacity);
+            if self.shadow_casters.dropped() > 0 && !self.warned_shadow_casters {
+                log::warn!(
+                    "{} lights cast shadows, only the first {} have a shadow map",
+                    capacity + self.shadow_casters.dropped(),
+                    capacity
+                );
+                self.warned_shadow_casters = true;
+            }
             self.lights_bind_group.update_lights(
                 &active_lights,
+                &self.shadow_casters,
                 &scene.nodes,
-                &renderer.queue,
+                &mut self.shadow_staging,
+                &renderer.device,
+                &mut shadow_encoder,
                 renderer.light_proj_scale,
             );
             self.shadow_maps.update(
@@ -1281,12 +2634,13 @@ impl RenderingPass for BlinnPhongRenderPass {
                 &renderer.limits,
                 2048,
                 2048,
-                active_lights.len() as u32,
+                // The bind group needs at least one shadow map.
+                self.shadow_casters.len().max(1) as u32,
             );
         }
 
-        // Resize depth buffer if necessary.
-        // The depth buffer is shared by all render passes.
+        // Resize depth buffer and the multisampled color texture if
+        // necessary. The depth buffer is shared by all render passes.
         {
             let need_recreate = match &self.depth_att {
                 None => true,
@@ -1298,35 +2652,691 @@ impl RenderingPass for BlinnPhongRenderPass {
                     label: Some("rpass_depth_texture"),
                     size: target.size,
                     mip_level_count: 1,
-                    sample_count: 1,
+                    sample_count: self.depth.samples,
                     dimension: wgpu::TextureDimension::D2,
-                    format: DEPTH_FORMAT,
+                    format: self.depth.texture_format(),
                     usage: wgpu::TextureUsages::RENDER_ATTACHMENT
                         | wgpu::TextureUsages::TEXTURE_BINDING,
                     view_formats: &[],
                 });
                 let view = texture.create_view(&Default::default());
                 self.depth_att = Some((texture, view));
+                self.msaa_att = self.depth.is_multisampled().then(|| {
+                    let texture = renderer.device.create_texture(&wgpu::TextureDescriptor {
+                        label: Some("rpass_msaa_texture"),
+                        size: target.size,
+                        mip_level_count: 1,
+                        sample_count: self.depth.samples,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: self.format,
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        view_formats: &[],
+                    });
+                    let view = texture.create_view(&Default::default());
+                    (texture, view)
+                });
             }
         }
 
         // Evaluate shadow maps only if shadows are enabled and wireframe is
         // disabled.
-        if params.casting_shadows() {
-            let meshes_casting_shadow = mesh_bundle_query
-                .iter(&scene.world)
-                .filter(|(_, node_idx)| scene.nodes[**node_idx].cast_shadows());
-            self.eval_shadow_maps_pass(encoder, meshes_casting_shadow, scene, renderer);
-            #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
-            {
-                self.shadow_maps.update_storage_buffers(encoder);
+        let shadow_casters = if params.casting_shadows() {
+            let mut unique_bundles = FxHashSet::default();
+            let mut bundles = Vec::new();
+            // Only triangle lists are drawn in the shadow maps, points and
+            // lines have no surface.
+            let is_triangle_list = |bundle: &MeshBundle| {
+                renderer
+                    .meshes
+                    .get(bundle.mesh)
+                    .is_some_and(|mesh| mesh.topology == wgpu::PrimitiveTopology::TriangleList)
+            };
+            for (bundle, node_idx) in <(&MeshBundle, &NodeIdx)>::query().iter(&scene.world) {
+                if casts_shadow(&scene.nodes, *node_idx)
+                    && is_triangle_list(bundle)
+                    && unique_bundles.insert(bundle)
+                {
+                    bundles.push(bundle);
+                }
+            }
+            // Draw the meshes in the same order whatever the order of the
+            // query.
+            bundles.sort_unstable();
+            let n_inst = bundles
+                .iter()
+                .filter_map(|bundle| renderer.instancing.get(*bundle))
+                .flatten()
+                .filter(|node_idx| casts_shadow(&scene.nodes, **node_idx))
+                .count();
+            // The locals buffer can't be resized while the pass is encoded.
+            if n_inst > 0 {
+                self.shadow_pass_locals_bind_group
+                    .resize(&renderer.device, n_inst as u32);
+            }
+            if let Some(slots) = self.slots.as_mut() {
+                slots.update_shadow(
+                    &renderer.device,
+                    &self.shadow_pass_locals_bind_group,
+                    &self.lights_bind_group,
+                );
+            }
+            bundles
+        } else {
+            Vec::new()
+        };
+
+        // Read the luminance and the visibility found by the previous frames.
+        self.exposure
+            .fetch_result(&renderer.device, camera.map(|(camera, _)| camera));
+        if params.enable_occlusion_culling {
+            self.occlusion.fetch_results(&renderer.device);
+        } else {
+            self.occlusion.clear();
+        }
+
+        // The image of cameras with a fixed aspect ratio is letterboxed.
+        self.viewport = camera.map_or_else(
+            || Viewport::full(target.size),
+            |(camera, _)| Viewport::for_camera(camera, target.size),
+        );
+        let main_draws = std::thread::scope(|s| {
+            let shadow_pass = (!shadow_casters.is_empty()).then(|| {
+                let encoding = ShadowMapsEncoding {
+                    pipelines: &self.pipelines,
+                    locals: &self.shadow_pass_locals_bind_group,
+                    lights: &self.lights_bind_group,
+                    shadow_maps: &self.shadow_maps,
+                    casters: &self.shadow_casters,
+                    constants: &self.shadow_constants,
+                    empty_bind_group: &self.empty_bind_group.1,
+                    slots: self.slots.as_ref(),
+                    renderer,
+                    nodes: &scene.nodes,
+                };
+                let encoder = &mut shadow_encoder;
+                let staging = &mut self.shadow_staging;
+                let bundles = &shadow_casters;
+                s.spawn(move || encoding.encode(encoder, staging, bundles))
+            });
+
+            // Simulate particles before drawing.
+            self.particles.update(
+                &mut encoder,
+                &mut self.staging,
+                scene,
+                renderer,
+                camera.map_or(u32::MAX, |(camera, _)| camera.layer_mask),
+            );
+
+            let main_draws = camera.map(|camera| {
+                Self::prepare_main_draws(
+                    &visible_meshes,
+                    camera,
+                    mirror,
+                    &scene.nodes,
+                    &renderer.instancing,
+                    params.enable_occlusion_culling.then_some(&self.occlusion),
+                )
+            });
+
+            if let Some(shadow_pass) = shadow_pass {
+                shadow_pass
+                    .join()
+                    .expect("Failed to encode the shadow maps pass!");
+            }
+            main_draws
+        });
+
+        #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
+        {
+            if params.casting_shadows() {
+                self.shadow_maps.update_storage_buffers(&mut encoder);
                 if params.write_shadow_maps {
                     self.shadow_maps.write_shadow_maps(&renderer.device);
                 }
             }
         }
 
-        // Evaluate the main render pass.
-        self.eval_main_render_pass(encoder, &visible_meshes, scene, renderer, params, target);
+        // The passes drawing into the target are ordered by the render
+        // graph. The shadow maps are encoded on their own thread and
+        // submitted before the graph.
+        let mut graph = RenderGraph::<Self>::new();
+        graph.mark_output(Self::TARGET);
+        if let Some(main_draws) = main_draws {
+            graph.add_pass(
+                "main",
+                &[],
+                &[Self::TARGET, Self::DEPTH],
+                move |this, encoder, _| {
+                    this.eval_main_render_pass(encoder, main_draws, scene, renderer, params, target)
+                },
+            );
+        }
+
+        // Test the bounding boxes against the depth of the opaque geometry.
+        if params.enable_occlusion_culling {
+            let instances = visible_meshes
+                .iter()
+                .filter_map(|(bundle, node_idx, _, _)| {
+                    renderer.meshes.get(bundle.mesh).map(|mesh| {
+                        (
+                            **node_idx,
+                            scene.nodes.world(**node_idx).to_mat4(),
+                            mesh.bounds,
+                        )
+                    })
+                })
+                .collect::<Vec<_>>();
+            graph.mark_output(Self::OCCLUSION_QUERIES);
+            graph.add_pass(
+                "occlusion",
+                &[Self::DEPTH],
+                &[Self::OCCLUSION_QUERIES],
+                move |this, encoder, _| {
+                    let depth_view = &this.depth_att.as_ref().unwrap().1;
+                    this.occlusion.query(
+                        renderer,
+                        &mut this.staging,
+                        encoder,
+                        depth_view,
+                        &this.viewport,
+                        &instances,
+                    );
+                },
+            );
+        }
+
+        // Draw water, particles and sprites on top of the opaque geometry.
+        graph.add_pass(
+            "water",
+            &[Self::DEPTH],
+            &[Self::TARGET],
+            move |this, encoder, _| {
+                let depth_view = &this.depth_att.as_ref().unwrap().1;
+                this.water.record(
+                    encoder,
+                    target,
+                    this.msaa_att.as_ref().map(|(_, view)| view),
+                    &this.globals_bind_group,
+                    depth_view,
+                    &this.viewport,
+                );
+            },
+        );
+        graph.add_pass(
+            "particles",
+            &[Self::DEPTH],
+            &[Self::TARGET],
+            move |this, encoder, _| {
+                let depth_view = &this.depth_att.as_ref().unwrap().1;
+                this.particles.record(
+                    encoder,
+                    target,
+                    this.msaa_att.as_ref().map(|(_, view)| view),
+                    &this.globals_bind_group,
+                    depth_view,
+                    &this.viewport,
+                );
+            },
+        );
+        graph.add_pass(
+            "sprites",
+            &[Self::DEPTH],
+            &[Self::TARGET],
+            move |this, encoder, _| {
+                let depth_view = &this.depth_att.as_ref().unwrap().1;
+                this.sprites.record(
+                    encoder,
+                    target,
+                    this.msaa_att.as_ref().map(|(_, view)| view),
+                    &this.globals_bind_group,
+                    depth_view,
+                    &this.viewport,
+                );
+            },
+        );
+
+        // Draw the gizmo over everything.
+        graph.add_pass("gizmo", &[], &[Self::TARGET], move |this, encoder, _| {
+            this.gizmo
+                .record(encoder, target, &this.globals_bind_group, &this.viewport);
+        });
+        graph.add_pass("minimap", &[], &[Self::TARGET], move |this, encoder, _| {
+            this.minimap.record(encoder, target);
+        });
+
+        let mut transients = std::mem::take(&mut self.transients);
+        if let Err(err) = graph.execute(self, &renderer.device, &mut encoder, &mut transients) {
+            log::error!("Failed to record the render graph: {}", err);
+        }
+        self.transients = transients;
+
+        // Close the staging chunks written during the frame before the
+        // encoders are submitted.
+        self.shadow_staging.finish();
+        self.staging.finish();
+
+        vec![shadow_encoder.finish(), encoder.finish()]
+    }
+}
+
+/// Returns whether the node is drawn into the shadow maps.
+fn casts_shadow(nodes: &Nodes, node_idx: NodeIdx) -> bool {
+    let node = &nodes[node_idx];
+    node.is_visible() && node.cast_shadows()
+}
+
+/// Draws of the main pass, prepared before encoding it.
+struct MainPassDraws<'a> {
+    /// Camera the pass is rendered from.
+    camera: &'a Camera,
+    camera_node: NodeIdx,
+    /// View matrix of the camera, mirrored about the plane of `mirror`.
+    view_mat: Mat4,
+    /// Plane the camera is mirrored about in world space, if the pass
+    /// renders a reflection.
+    mirror: Option<Vec4>,
+    /// Locals of all the drawn instances.
+    locals: Vec<Locals>,
+    draws: Vec<MainDraw<'a>>,
+}
+
+/// Draw of the instances of a mesh in the main pass: the custom shader of
+/// the instances, their mesh, and the offset and number of their locals.
+type MainDraw<'a> = (Option<&'a CustomShader>, &'a MeshBundle, u32, u32);
+
+/// Batch of instances of the main pass, drawn by a recorded bundle or by an
+/// indirect call over a range of draws.
+enum MainStep<'a> {
+    Bundle(DrawBundleKey),
+    Indirect(&'a MeshBundle, Range<u32>),
+}
+
+/// Resources read when encoding the shadow maps pass, which is encoded on
+/// its own thread while the main pass is prepared.
+struct ShadowMapsEncoding<'a> {
+    /// Pipelines of the shadow maps pass, see
+    /// [`BlinnPhongRenderPass::shadow_pipeline`].
+    pipelines: &'a Pipelines,
+    locals: &'a LocalsBindGroup<ShadowPassLocals>,
+    lights: &'a LightsBindGroup,
+    shadow_maps: &'a ShadowMaps,
+    casters: &'a ShadowCasters,
+    constants: &'a DrawConstants,
+    /// Bound to the groups of the materials and textures by the draws which
+    /// are not alpha-tested, when the constants are read from a uniform
+    /// buffer.
+    empty_bind_group: &'a wgpu::BindGroup,
+    /// Packed bind groups, without binding arrays.
+    slots: Option<&'a SlotBindGroups>,
+    renderer: &'a Renderer,
+    nodes: &'a Nodes,
+}
+
+impl ShadowMapsEncoding<'_> {
+    /// Evaluates the shadow maps of the visible instances of the given
+    /// meshes which cast shadows. The locals buffer must be large enough to
+    /// hold all of them.
+    ///
+    /// The sub-meshes whose material has a diffuse or opacity map are
+    /// alpha-tested, and the back faces of the double-sided materials are
+    /// not culled.
+    fn encode(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        staging: &mut StagingRing,
+        mesh_bundles: &[&MeshBundle],
+    ) {
+        profiling::scope!("BlinnPhongShading::eval_shadow_maps_pass");
+        // Update locals buffer content.
+        let mut locals = Vec::new();
+        let mut offsets_and_inst_count = Vec::with_capacity(mesh_bundles.len());
+        for bundle in mesh_bundles {
+            let instances = self
+                .renderer
+                .instancing
+                .get(*bundle)
+                .expect("Unreachable! Instancing should be created for all meshes!");
+            let offset = locals.len() as u32;
+            locals.extend(
+                instances
+                    .iter()
+                    .filter(|node_idx| casts_shadow(self.nodes, **node_idx))
+                    .map(|node_idx| ShadowPassLocals {
+                        model: self.nodes.world(*node_idx).to_mat4().to_cols_array(),
+                    }),
+            );
+            offsets_and_inst_count.push((offset, locals.len() as u32 - offset));
+        }
+
+        log::debug!(
+            "{} instances of {} meshes cast shadows",
+            locals.len(),
+            mesh_bundles.len()
+        );
+
+        if locals.is_empty() {
+            return;
+        }
+        staging.write(
+            &self.renderer.device,
+            encoder,
+            &self.locals.buffer,
+            0,
+            bytemuck::cast_slice(&locals),
+        );
+
+        let mesh_buffer = self.renderer.meshes.buffer();
+        for (light_idx, shadow_map) in self
+            .casters
+            .lights()
+            .zip(&self.shadow_maps.shadow_map_views)
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("blinn_phong_shadow_maps_pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: shadow_map,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            // Groups of the materials and textures, then of the constants.
+            let (material_groups, constants_group) = match self.slots {
+                Some(slots) => {
+                    // Bind locals and lights storage buffer at once.
+                    let shadow = slots.shadow.as_ref().map(|(_, group)| group);
+                    render_pass.set_bind_group(0, shadow, &[]);
+                    (1..2, 2)
+                }
+                None => {
+                    // Bind locals.
+                    render_pass.set_bind_group(0, self.locals, &[]);
+                    // Bind lights storage buffer.
+                    render_pass.set_bind_group(1, self.lights, &[]);
+                    (2..4, 4)
+                }
+            };
+
+            for (bundle, (offset, inst_count)) in
+                mesh_bundles.iter().zip(offsets_and_inst_count.iter())
+            {
+                let Some(mesh) = self.renderer.meshes.get(bundle.mesh) else {
+                    log::error!("Missing mesh {:?}", bundle.mesh);
+                    continue;
+                };
+                let Some(pos_range) = mesh.get_vertex_attribute_range(VertexAttribute::POSITION)
+                else {
+                    continue;
+                };
+                // Bind vertex buffer - position.
+                render_pass.set_vertex_buffer(0, mesh_buffer.slice(pos_range.clone()));
+                // Bind vertex buffer - uv, for the alpha-tested draws.
+                let uv_range = mesh.get_vertex_attribute_range(VertexAttribute::UV);
+                if let Some(uv_range) = uv_range {
+                    render_pass.set_vertex_buffer(1, mesh_buffer.slice(uv_range.clone()));
+                }
+                if let Some(index_format) = mesh.index_format {
+                    render_pass.set_index_buffer(
+                        mesh_buffer.slice(mesh.index_range.clone()),
+                        index_format,
+                    );
+                }
+                let materials = self
+                    .renderer
+                    .material_bundles
+                    .get(bundle.aesthetic.materials);
+                let gpu_materials = self
+                    .renderer
+                    .material_sources
+                    .get(&bundle.aesthetic.materials);
+                // Index range and material of each sub-mesh, as in the main
+                // pass.
+                let n_materials = materials.map_or(1, |mtls| mtls.n_materials);
+                let count = if mesh.index_format.is_some() {
+                    mesh.index_count
+                } else {
+                    mesh.vertex_count
+                };
+                let sub_meshes = match mesh.sub_meshes.as_ref() {
+                    Some(sub_meshes) => sub_meshes
+                        .iter()
+                        .map(|sm| (sm.range.clone(), sm.material.unwrap_or(n_materials - 1)))
+                        .collect::<Vec<_>>(),
+                    None => vec![(0..count, 0)],
+                };
+
+                for (range, material_index) in sub_meshes {
+                    let material = gpu_materials.and_then(|mtls| mtls.get(material_index as usize));
+                    // Materials and textures read by the alpha test, bound
+                    // together without binding arrays.
+                    let alpha_test = match self.slots {
+                        Some(_) => self
+                            .renderer
+                            .material_slots
+                            .get(&bundle.aesthetic)
+                            .map(|group| (group, None)),
+                        None => materials
+                            .zip(self.renderer.textures_bind_group.as_ref())
+                            .map(|(materials, textures)| (&materials.bind_group, Some(textures))),
+                    }
+                    .filter(|_| {
+                        uv_range.is_some() && material.is_some_and(GpuMaterial::is_alpha_tested)
+                    });
+                    let double_sided = material.is_some_and(GpuMaterial::is_double_sided);
+                    let (pipeline, stages) = BlinnPhongRenderPass::shadow_pipeline(
+                        self.pipelines,
+                        alpha_test.is_some(),
+                        double_sided,
+                    );
+                    render_pass.set_pipeline(pipeline);
+                    match alpha_test {
+                        Some((materials, textures)) => {
+                            render_pass.set_bind_group(material_groups.start, materials, &[]);
+                            if let Some(textures) = textures {
+                                render_pass.set_bind_group(
+                                    material_groups.start + 1,
+                                    textures,
+                                    &[],
+                                );
+                            }
+                        }
+                        None if !self.constants.is_push_constants() => {
+                            for group in material_groups.clone() {
+                                render_pass.set_bind_group(group, self.empty_bind_group, &[]);
+                            }
+                        }
+                        None => {}
+                    }
+                    let pconsts = PConstsShadowPass {
+                        instance_base_index: *offset,
+                        light_index: light_idx,
+                        material_index,
+                    };
+                    self.constants.set(
+                        &self.renderer.device,
+                        &self.renderer.queue,
+                        &mut render_pass,
+                        constants_group,
+                        stages,
+                        &pconsts,
+                    );
+                    if mesh.index_format.is_some() {
+                        render_pass.draw_indexed(range, 0, 0..*inst_count);
+                    } else {
+                        render_pass.draw(range, 0..*inst_count);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{assets::Handle, Color};
+    use crate::scene::Node;
+    use std::marker::PhantomData;
+
+    fn handle<T: crate::core::assets::Asset>(index: u32) -> Handle<T> {
+        Handle {
+            generation: 0,
+            index,
+            marker: PhantomData,
+        }
+    }
+
+    #[test]
+    fn main_draws_do_not_depend_on_the_query_order() {
+        let mut nodes = Nodes::new();
+        let camera = Camera::perspective(60.0, 0.1..100.0, Color::BLACK);
+        let camera_node = nodes.push(Node::new(Some(NodeIdx::root())));
+        let bundles = (0..3)
+            .map(|i| MeshBundle {
+                mesh: handle(i),
+                aesthetic: crate::core::mesh::AestheticBundle {
+                    textures: handle(0),
+                    materials: handle(i % 2),
+                },
+            })
+            .collect::<Vec<_>>();
+        let mut instancing = FxHashMap::default();
+        let mut entities = Vec::new();
+        for i in 0..12 {
+            let mut node = Node::new(Some(NodeIdx::root()));
+            node.set_visible(true);
+            node.transform_mut().translation = Vec3::new(i as f32, 0.0, 0.0);
+            let node_idx = nodes.push(node);
+            let bundle = &bundles[i % bundles.len()];
+            instancing
+                .entry(*bundle)
+                .or_insert_with(Vec::new)
+                .push(node_idx);
+            entities.push((bundle, node_idx));
+        }
+
+        let draws_of = |entities: &[(&MeshBundle, NodeIdx)]| {
+            let meshes = entities
+                .iter()
+                .map(|(bundle, node_idx)| (*bundle, node_idx, None, None))
+                .collect::<Vec<_>>();
+            let main = BlinnPhongRenderPass::prepare_main_draws(
+                &meshes,
+                (&camera, camera_node),
+                None,
+                &nodes,
+                &instancing,
+                None,
+            );
+            let draws = main
+                .draws
+                .iter()
+                .map(|(_, bundle, offset, count)| (**bundle, *offset, *count))
+                .collect::<Vec<_>>();
+            (draws, bytemuck::cast_slice::<_, u8>(&main.locals).to_vec())
+        };
+        let expected = draws_of(&entities);
+        entities.reverse();
+        assert_eq!(draws_of(&entities), expected);
+        entities.rotate_left(5);
+        assert_eq!(draws_of(&entities), expected);
+    }
+
+    #[test]
+    fn only_shadow_casting_directional_lights_get_shadow_maps() {
+        let mut world = legion::World::default();
+        let mut nodes = Nodes::new();
+        let directional = Light::Directional {
+            direction: Vec3::NEG_Y,
+            color: Color::WHITE,
+        };
+        let point = Light::Point {
+            color: Color::WHITE,
+        };
+        let lights = [
+            (point, true),
+            (directional, true),
+            (directional, false),
+            (directional, true),
+        ]
+        .into_iter()
+        .map(|(light, casts_shadows)| {
+            let mut node = Node::new(Some(NodeIdx::root()));
+            node.set_cast_shadows(casts_shadows);
+            let node_idx = nodes.push(node);
+            (world.push((node_idx,)), light, node_idx)
+        })
+        .collect::<Vec<_>>();
+        let refs = lights
+            .iter()
+            .map(|(entity, light, node)| (entity, light, node))
+            .collect::<Vec<_>>();
+
+        let casters = ShadowCasters::assign(&refs, &nodes, usize::MAX);
+        assert_eq!(casters.len(), 2);
+        assert_eq!(casters.dropped(), 0);
+        assert_eq!(casters.lights().collect::<Vec<_>>(), vec![1, 3]);
+        assert_eq!(casters.slot(lights[0].0), None);
+        assert_eq!(casters.slot(lights[3].0), Some(1));
+        assert_eq!(casters.slot_of_light(2), ShadowCasters::NONE);
+
+        // The lights over the capacity of the shadow maps cast no shadows.
+        let casters = ShadowCasters::assign(&refs, &nodes, 1);
+        assert_eq!(casters.len(), 1);
+        assert_eq!(casters.dropped(), 1);
+        assert_eq!(casters.slot(lights[1].0), Some(0));
+        assert_eq!(casters.slot_of_light(3), ShadowCasters::NONE);
+    }
+
+    #[test]
+    fn lights_over_the_capacity_are_the_least_contributing() {
+        let mut world = legion::World::default();
+        let mut nodes = Nodes::new();
+        let mut node_at = |x: f32| {
+            let mut node = Node::new(Some(NodeIdx::root()));
+            node.transform_mut().translation = Vec3::new(x, 0.0, 0.0);
+            nodes.push(node)
+        };
+        let point = Light::Point {
+            color: Color::WHITE,
+        };
+        let sun = Light::Directional {
+            direction: Vec3::NEG_Y,
+            color: Color::WHITE,
+        };
+        let lights = [
+            (point, node_at(10.0)),
+            (sun, node_at(50.0)),
+            (point, node_at(1.0)),
+        ];
+        let entities = lights.map(|_| world.push(()));
+        let mut kept = entities
+            .iter()
+            .zip(&lights)
+            .map(|(entity, (light, node_idx))| (entity, light, node_idx))
+            .collect::<Vec<_>>();
+        let order = |lights: &[(&legion::Entity, &Light, &NodeIdx)]| {
+            lights
+                .iter()
+                .map(|(entity, _, _)| **entity)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            LightsBindGroup::prioritize_lights(&mut kept, &nodes, Vec3::ZERO, 3),
+            0
+        );
+        assert_eq!(order(&kept), entities);
+
+        assert_eq!(
+            LightsBindGroup::prioritize_lights(&mut kept, &nodes, Vec3::ZERO, 2),
+            1
+        );
+        assert_eq!(order(&kept), vec![entities[1], entities[2]]);
     }
 }
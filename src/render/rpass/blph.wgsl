@@ -16,12 +16,25 @@
 
 const PNT_LIGHT: f32 = 1.0;
 const DIR_LIGHT: f32 = 0.0;
+const NO_SHADOW_MAP: u32 = 0xffffffffu;
 const INVALID_INDEX: u32 = 0xffffffffu;
+/// Alpha of the diffuse and opacity maps under which the surfaces are cut
+/// out, as in the shadow maps pass.
+const ALPHA_CUTOFF: f32 = 0.5;
 
-/// Camera data.
+/// Camera and time data, shared by all the shaders of the main pass,
+/// including the custom shaders.
 struct Globals {
     view: mat4x4<f32>,
     proj: mat4x4<f32>,
+    /// Position of the camera in world space.
+    camera_pos: vec3<f32>,
+    /// Seconds elapsed since the renderer started.
+    time: f32,
+    /// Seconds elapsed since the previous frame.
+    delta_time: f32,
+    /// Scale of the lit colors, from the exposure of the camera.
+    exposure: f32,
 }
 
 struct Locals {
@@ -42,6 +55,8 @@ struct Light {
    dir_or_pos: vec4<f32>,
    /// Color/intensity of light.
    color: vec3<f32>,
+   /// Shadow map of the light, NO_SHADOW_MAP if it casts no shadows.
+   shadow_map: u32,
    /// Matrix transforming from world space to light space.
    world_to_light: mat4x4<f32>,
 }
@@ -68,6 +83,20 @@ struct Material {
     map_disp: u32,
     map_decal: u32,
     map_norm: u32,
+    reflectivity: f32,
+    uv_rotation: f32, // Radians, counter-clockwise.
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    ke: vec4<f32>,
+    map_ke: u32,
+    // Parameters of the PBR extension, not used by the Blinn-Phong shading.
+    map_pr: u32,
+    map_pm: u32,
+    map_ps: u32,
+    pr: f32,
+    pm: f32,
+    ps: f32,
+    flags: u32,
 }
 
 /// Vertex shader input.
@@ -77,12 +106,14 @@ struct VSInput {
     @location(1) normal: vec3<f32>,
     @location(2) texcoord: vec2<f32>,
     @location(3) tangent: vec4<f32>,
+    @location(4) color: vec4<f32>,
 }
 
 struct VSOutput {
     // Clip space position when the struct is used as vertex stae output.
     // Screen space position when the struct is used as fragment stage input.
-    @builtin(position) position: vec4<f32>,
+    // Invariant so that it matches the depth written by the depth pre-pass.
+    @builtin(position) @invariant position: vec4<f32>,
     @location(0) pos_eye_space: vec3<f32>,
     @location(1) normal_eye_space: vec3<f32>,
     @location(2) tangent_eye_space: vec4<f32>,
@@ -93,35 +124,100 @@ struct VSOutput {
     @location(7) view_mat_z: vec4<f32>,
     @location(8) view_mat_w: vec4<f32>,
     @location(9) pos_world: vec3<f32>,
+    @location(10) tint: u32,
+    @location(11) color: vec4<f32>,
 }
 
+// #if !texture_slots
 @group(0) @binding(0) var<uniform> globals: Globals;
+@group(0) @binding(1) var environment: texture_cube<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
 @group(1) @binding(0) var<storage, read> instances: array<Locals>;
 @group(2) @binding(0) var<storage, read> materials: array<Material>;
 @group(3) @binding(0) var<storage, read> lights: LightArray;
-
-// #if !constant_sized_binding_array
+// #fi
+// #if runtime_sized_binding_array
 @group(4) @binding(0) var textures: binding_array<texture_2d<f32>>;
 @group(4) @binding(1) var<storage, read> texture_sampler_ids: array<u32>;
 @group(4) @binding(2) var samplers: binding_array<sampler>;
 @group(5) @binding(0) var shadow: binding_array<texture_depth_2d_array>;
 @group(5) @binding(1) var shadow_sampler: sampler_comparison;
-// #else
+// #fi
+// #if constant_sized_binding_array
 @group(4) @binding(0) var textures: binding_array<texture_2d<f32>, 64>;
 @group(4) @binding(1) var<storage, read> texture_sampler_ids: array<u32>;
 @group(4) @binding(2) var samplers: binding_array<sampler, 8>;
 @group(5) @binding(0) var shadow: binding_array<texture_depth_2d_array, 1>;
 @group(5) @binding(1) var shadow_sampler: sampler_comparison;
 // #fi
+// Without binding arrays, the resources shared by the draws of a frame are
+// bound in a single group, and a few textures are bound one by one with the
+// materials of each mesh, each with its sampler.
+// #if texture_slots
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(0) @binding(1) var environment: texture_cube<f32>;
+@group(0) @binding(2) var environment_sampler: sampler;
+@group(0) @binding(3) var<storage, read> instances: array<Locals>;
+@group(0) @binding(4) var<storage, read> lights: LightArray;
+@group(0) @binding(5) var shadow: texture_depth_2d_array;
+@group(0) @binding(6) var shadow_sampler: sampler_comparison;
+@group(1) @binding(0) var<storage, read> materials: array<Material>;
+@group(1) @binding(1) var texture_0: texture_2d<f32>;
+@group(1) @binding(2) var texture_1: texture_2d<f32>;
+@group(1) @binding(3) var texture_2: texture_2d<f32>;
+@group(1) @binding(4) var texture_3: texture_2d<f32>;
+@group(1) @binding(5) var texture_4: texture_2d<f32>;
+@group(1) @binding(6) var texture_5: texture_2d<f32>;
+@group(1) @binding(7) var texture_6: texture_2d<f32>;
+@group(1) @binding(8) var texture_7: texture_2d<f32>;
+@group(1) @binding(9) var sampler_0: sampler;
+@group(1) @binding(10) var sampler_1: sampler;
+@group(1) @binding(11) var sampler_2: sampler;
+@group(1) @binding(12) var sampler_3: sampler;
+@group(1) @binding(13) var sampler_4: sampler;
+@group(1) @binding(14) var sampler_5: sampler;
+@group(1) @binding(15) var sampler_6: sampler;
+@group(1) @binding(16) var sampler_7: sampler;
+// #fi
 
 /* Sampling shadow map as normal texture. */
 // @group(5) @binding(0) var shadow: binding_array<texture_2d_array<f32>>;
 // @group(5) @binding(1) var shadow_sampler: sampler;
 
+// Without push constants, the constants are read from a uniform buffer
+// bound to the group following the others.
+// #if push_constants
 var<push_constant> pconsts: PConsts;
+// #fi
+// #if binding_arrays_uniform_constants
+@group(6) @binding(0) var<uniform> pconsts: PConsts;
+// #fi
+// #if texture_slots_uniform_constants
+@group(2) @binding(0) var<uniform> pconsts: PConsts;
+// #fi
 
 @vertex
 fn vs_main(vin: VSInput) -> VSOutput {
+    return transform_vertex(vin);
+}
+
+/// Vertex shader of the points, expanded into quads of two triangles. The uv
+/// of each vertex of a quad is its offset from the point in eye space, so
+/// that the quads face the camera.
+@vertex
+fn vs_points(vin: VSInput) -> VSOutput {
+    var out = transform_vertex(vin);
+    let pos_eye_space = out.pos_eye_space + vec3<f32>(vin.texcoord, 0.0);
+    out.position = globals.proj * vec4<f32>(pos_eye_space, 1.0);
+    out.pos_eye_space = pos_eye_space;
+    out.normal_eye_space = vec3<f32>(0.0, 0.0, 1.0);
+    out.tangent_eye_space = vec4<f32>(1.0, 0.0, 0.0, 1.0);
+    // Corner of the quad, for textured points.
+    out.texcoord = step(vec2<f32>(0.0), vin.texcoord);
+    return out;
+}
+
+fn transform_vertex(vin: VSInput) -> VSOutput {
     let locals = instances[vin.instance_index + pconsts.instance_base_index];
 
     var out: VSOutput;
@@ -129,15 +225,20 @@ fn vs_main(vin: VSInput) -> VSOutput {
     out.pos_world = (locals.model * vec4<f32>(vin.position, 1.0)).xyz;
     let pos_eye_space = model_view * vec4<f32>(vin.position, 1.0);
 
+    // Overridden material, then material of the sub-mesh of indirect draws.
     if (locals.material_index.x != INVALID_INDEX) {
         out.material_index = locals.material_index.x;
+    } else if (locals.material_index.z != INVALID_INDEX) {
+        out.material_index = locals.material_index.z;
     } else {
         out.material_index = pconsts.material_index;
     }
+    out.tint = locals.material_index.y;
 
     let nrm_mat = mat3x3(locals.model_view_it.x.xyz, locals.model_view_it.y.xyz, locals.model_view_it.z.xyz);
     out.position = globals.proj * pos_eye_space;
     out.texcoord = vin.texcoord;
+    out.color = vin.color;
     out.pos_eye_space = pos_eye_space.xyz / pos_eye_space.w;
     out.normal_eye_space = normalize(nrm_mat * vin.normal);
     out.tangent_eye_space = vec4<f32>(normalize(nrm_mat * vin.tangent.xyz), vin.tangent.w);
@@ -174,7 +275,6 @@ fn blinn_phong_shading_eye_space(view_mat: mat4x4<f32>, pos_world: vec3<f32>, po
     // View direction in camera space.
     let wo = normalize(-pos_eye_space);
 
-    var dir_light_index = 0u;
     for (var i: u32 = 0u; i < lights.len; i++) {
         let light = lights.data[i];
         if (light.dir_or_pos.w == DIR_LIGHT) {
@@ -183,11 +283,10 @@ fn blinn_phong_shading_eye_space(view_mat: mat4x4<f32>, pos_world: vec3<f32>, po
             let coeff = blinn_phong_brdf(wi, wo, n, kd, ks, ns, illum);
             let pos_light_space = light.world_to_light * vec4<f32>(pos_world, 1.0);
             var shadow = 1.0;
-            if (pconsts.enable_shadows != 0u) {
-                shadow = fetch_shadow(dir_light_index, pos_light_space);
+            if (pconsts.enable_shadows != 0u && light.shadow_map != NO_SHADOW_MAP) {
+                shadow = fetch_shadow(light.shadow_map, pos_light_space);
             }
             color += shadow * coeff * light.color;
-            dir_light_index += 1u;
         } else if (light.dir_or_pos.w == PNT_LIGHT) {
             // Light position in view space.
             var light_pos = view_mat * light.dir_or_pos;
@@ -203,11 +302,33 @@ fn blinn_phong_shading_eye_space(view_mat: mat4x4<f32>, pos_world: vec3<f32>, po
     return color;
 }
 
+/// Samples the texture of the given index in the textures shared by all
+/// materials.
+// #if !texture_slots
+fn sample_texture(map: u32, texcoord: vec2<f32>) -> vec4<f32> {
+    return textureSample(textures[map], samplers[texture_sampler_ids[map]], texcoord);
+}
+// #else
+fn sample_texture(map: u32, texcoord: vec2<f32>) -> vec4<f32> {
+    switch map {
+        case 0u: { return textureSample(texture_0, sampler_0, texcoord); }
+        case 1u: { return textureSample(texture_1, sampler_1, texcoord); }
+        case 2u: { return textureSample(texture_2, sampler_2, texcoord); }
+        case 3u: { return textureSample(texture_3, sampler_3, texcoord); }
+        case 4u: { return textureSample(texture_4, sampler_4, texcoord); }
+        case 5u: { return textureSample(texture_5, sampler_5, texcoord); }
+        case 6u: { return textureSample(texture_6, sampler_6, texcoord); }
+        case 7u: { return textureSample(texture_7, sampler_7, texcoord); }
+        default: { return vec4<f32>(1.0); }
+    }
+}
+// #fi
+
 /// Unpack normal from normal map.
 ///
 /// The normal map is assumed to be in tangent space. The normal is unpacked to [-1, 1].
 fn unpack_normal_map(map: u32, texcoord: vec2<f32>) -> vec3<f32> {
-    var m = textureSample(textures[map], samplers[texture_sampler_ids[map]], texcoord).xyz;
+    var m = sample_texture(map, texcoord).xyz;
     m = m * 2.0 - vec3<f32>(1.0);
     return normalize(m);
 }
@@ -232,7 +353,21 @@ fn fetch_shadow(light_idx: u32, pos_light_space: vec4<f32>) -> f32 {
     // Compute texture coordinates for shadow map lookup. Transform from [-1, 1] to [0, 1]. * 0.5 + 0.5
     let light_local = pos_light_space.xy * flip_correction * proj_correction + vec2<f32>(0.5, 0.5);
     // Fetch shadow map, use HW PCF and comparison                                    current depth
+// #if texture_slots
+    return textureSampleCompareLevel(shadow, shadow_sampler, light_local, light_idx, pos_light_space.z * proj_correction);
+// #else
     return textureSampleCompareLevel(shadow[0], shadow_sampler, light_local, light_idx, pos_light_space.z * proj_correction);
+// #fi
+}
+
+/// Applies the texture transform of the material to the texture coordinates,
+/// and flips them vertically to the texture space.
+fn transform_texcoord(material: Material, uv: vec2<f32>) -> vec2<f32> {
+    let c = cos(material.uv_rotation);
+    let s = sin(material.uv_rotation);
+    let scaled = uv * material.uv_scale;
+    let transformed = vec2<f32>(c * scaled.x - s * scaled.y, s * scaled.x + c * scaled.y) + material.uv_offset;
+    return vec2<f32>(transformed.x, 1.0 - transformed.y);
 }
 
 @fragment
@@ -240,23 +375,32 @@ fn fs_main(vout : VSOutput) -> @location(0) vec4<f32> {
     var materials_count : u32 = arrayLength(&materials);
     var default_material_index : u32 = materials_count - 1u;
     var material = materials[vout.material_index];
-    let texcoord = vec2<f32>(vout.texcoord.x, 1.0 - vout.texcoord.y);
+    let texcoord = transform_texcoord(material, vout.texcoord);
 
     var kd = material.kd.rgb;
+    var alpha = 1.0;
     if (material.map_kd != INVALID_INDEX) {
-        kd = textureSample(textures[material.map_kd], samplers[texture_sampler_ids[material.map_kd]], texcoord).rgb;
+        let texel = sample_texture(material.map_kd, texcoord);
+        kd = texel.rgb;
+        alpha = texel.a;
     }
+    if (material.map_d != INVALID_INDEX) {
+        alpha *= sample_texture(material.map_d, texcoord).r;
+    }
+    // Vertex colors, e.g. baked ambient occlusion, scale the diffuse and
+    // ambient reflectance.
+    kd *= vout.color.rgb;
 
     var color = materials[default_material_index].kd.rgb;
 
     var ks = material.ks.rgb;
     if (material.map_ks != INVALID_INDEX) {
-        ks = textureSample(textures[material.map_ks], samplers[texture_sampler_ids[material.map_ks]], texcoord).rgb;
+        ks = sample_texture(material.map_ks, texcoord).rgb;
     }
 
     var ns = material.ns;
     if (material.map_ns != INVALID_INDEX) {
-        ns = textureSample(textures[material.map_ns], samplers[texture_sampler_ids[material.map_ns]], texcoord).r;
+        ns = sample_texture(material.map_ns, texcoord).r;
     }
 
     // Output kd as color.
@@ -275,6 +419,10 @@ fn fs_main(vout : VSOutput) -> @location(0) vec4<f32> {
     var n = normalize(vout.normal_eye_space);
     if (material.map_norm != INVALID_INDEX) {
         n = unpack_normal_map(material.map_norm, texcoord);
+        // Undo the rotation of the texture coordinates in the tangent plane.
+        let c = cos(material.uv_rotation);
+        let s = sin(material.uv_rotation);
+        n = vec3<f32>(c * n.x + s * n.y, -s * n.x + c * n.y, n.z);
         let tbn = tbn_matrix(vout.tangent_eye_space, vout.normal_eye_space);
         n = normalize(tbn * n);
     }
@@ -285,7 +433,7 @@ fn fs_main(vout : VSOutput) -> @location(0) vec4<f32> {
     if (material.illum != 0u) {
         var ka = material.ka.rgb;
         if (material.map_ka != INVALID_INDEX) {
-            ka = textureSample(textures[material.map_ka], samplers[texture_sampler_ids[material.map_ka]], texcoord).rgb;
+            ka = sample_texture(material.map_ka, texcoord).rgb;
         }
 
         var ia = vec3<f32>(0.0, 0.0, 0.0);
@@ -296,5 +444,47 @@ fn fs_main(vout : VSOutput) -> @location(0) vec4<f32> {
         color += ka * ia * kd;
     }
 
-    return vec4<f32>(color, 1.0);
+    // Mirror-like reflection of the environment map.
+    if (material.reflectivity > 0.0) {
+        let r_eye = reflect(normalize(vout.pos_eye_space), n);
+        // The inverse of the rotation of the view matrix is its transpose.
+        let r_world = transpose(mat3x3<f32>(view_mat.x.xyz, view_mat.y.xyz, view_mat.z.xyz)) * r_eye;
+        let env = textureSampleLevel(environment, environment_sampler, r_world, 0.0);
+        color = mix(color, env.rgb, material.reflectivity * env.a);
+    }
+
+    // Emission, whatever the lights.
+    var ke = material.ke.rgb;
+    if (material.map_ke != INVALID_INDEX) {
+        ke = sample_texture(material.map_ke, texcoord).rgb;
+    }
+    color += ke;
+
+    // Overlay tint of the instance.
+    let tint = unpack4x8unorm(vout.tint);
+    color = mix(color, tint.rgb, tint.a);
+
+    // Screen-door transparency: translucent materials keep a fraction of
+    // their pixels given by the opacity, without sorting nor blending.
+    if (material.d < 1.0 && material.d <= dither_threshold(vout.position.xy)) {
+        discard;
+    }
+    // Alpha-masked parts of the surfaces, e.g. the gaps between leaves.
+    if (alpha < ALPHA_CUTOFF) {
+        discard;
+    }
+
+    return vec4<f32>(color * globals.exposure, 1.0);
+}
+
+/// Threshold of the 4x4 ordered dithering matrix at the pixel, in (0, 1).
+fn dither_threshold(pixel: vec2<f32>) -> f32 {
+    var bayer = array<f32, 16>(
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0,
+    );
+    let p = vec2<u32>(pixel) % vec2<u32>(4u);
+    return (bayer[p.y * 4u + p.x] + 0.5) / 16.0;
 }
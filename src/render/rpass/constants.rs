@@ -0,0 +1,245 @@
+use crate::core::FxHashMap;
+use bytemuck::Pod;
+use std::{
+    num::NonZeroU64,
+    sync::{Arc, Mutex},
+};
+
+/// Constants of the draws of a pass, set with push constants if the device
+/// supports them.
+///
+/// Otherwise, the constants are read from a uniform buffer bound with a
+/// dynamic offset to the last group of the pipeline layouts, see
+/// [`DrawConstants::bind_group_layout`]. Each distinct value is written to
+/// the buffer once and never overwritten, so that the draw calls recorded in
+/// render bundles stay valid while they are reused.
+pub struct DrawConstants {
+    /// Size of the constants in bytes.
+    size: u32,
+    /// `None` if the device supports push constants.
+    uniform: Option<UniformConstants>,
+}
+
+/// Uniform buffer emulating the push constants.
+struct UniformConstants {
+    label: &'static str,
+    layout: wgpu::BindGroupLayout,
+    /// Distance between two values in the buffer, the alignment of the
+    /// dynamic offsets.
+    stride: u32,
+    slots: Mutex<ConstantSlots>,
+}
+
+/// Values written to the uniform buffer.
+#[derive(Default)]
+struct ConstantSlots {
+    data: Vec<u8>,
+    offsets: FxHashMap<Vec<u8>, u32>,
+    /// Buffer holding the values and its bind group. The bind group is
+    /// shared with the draw calls recorded with it, which keep the buffer
+    /// alive once it is replaced.
+    buffer: Option<(wgpu::Buffer, Arc<wgpu::BindGroup>)>,
+}
+
+/// Constants of the draws of a render bundle, prepared before recording it.
+pub struct BundleConstants {
+    /// Values of the draws, set with push constants.
+    data: Vec<Vec<u8>>,
+    /// Bind group and offset of each draw, if the constants are emulated.
+    uniform: Option<(Arc<wgpu::BindGroup>, Vec<u32>)>,
+}
+
+impl DrawConstants {
+    /// Number of values after which the uniform buffer is started over. The
+    /// draws recorded with the previous buffer keep reading it.
+    const MAX_SLOTS: usize = 4096;
+
+    /// Creates the constants of the given size, emulated with a uniform
+    /// buffer visible to the given stages if the device doesn't support push
+    /// constants.
+    pub fn new(
+        device: &wgpu::Device,
+        label: &'static str,
+        visibility: wgpu::ShaderStages,
+        size: u32,
+    ) -> Self {
+        // The size of uniform structures is a multiple of 16 bytes.
+        let size = size.next_multiple_of(16);
+        let push_constants = device.features().contains(wgpu::Features::PUSH_CONSTANTS);
+        let uniform = (!push_constants).then(|| UniformConstants {
+            label,
+            layout: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: NonZeroU64::new(size as u64),
+                    },
+                    count: None,
+                }],
+            }),
+            stride: size.max(device.limits().min_uniform_buffer_offset_alignment),
+            slots: Mutex::new(ConstantSlots::default()),
+        });
+        Self { size, uniform }
+    }
+
+    /// Returns whether the constants are set with push constants.
+    pub fn is_push_constants(&self) -> bool {
+        self.uniform.is_none()
+    }
+
+    /// Returns the push constant ranges of the pipeline layouts reading the
+    /// constants from the given stages, none if they are emulated.
+    pub fn push_constant_ranges(&self, stages: wgpu::ShaderStages) -> Vec<wgpu::PushConstantRange> {
+        match self.uniform {
+            Some(_) => Vec::new(),
+            None => vec![wgpu::PushConstantRange {
+                stages,
+                range: 0..self.size,
+            }],
+        }
+    }
+
+    /// Returns the layout of the bind group of the emulated constants, to be
+    /// added at the end of the pipeline layouts, `None` if the device
+    /// supports push constants.
+    pub fn bind_group_layout(&self) -> Option<&wgpu::BindGroupLayout> {
+        self.uniform.as_ref().map(|uniform| &uniform.layout)
+    }
+
+    /// Sets the constants of the next draws of the render pass, `group`
+    /// being the index of the bind group of the emulated constants.
+    pub fn set<T: Pod>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        render_pass: &mut wgpu::RenderPass,
+        group: u32,
+        stages: wgpu::ShaderStages,
+        value: &T,
+    ) {
+        let bytes = bytemuck::bytes_of(value);
+        match self.uniform.as_ref() {
+            Some(uniform) => {
+                let mut slots = uniform.slots.lock().unwrap();
+                let offset = slots.offset(device, queue, uniform, self.size, bytes);
+                let bind_group = slots.bind_group();
+                render_pass.set_bind_group(group, bind_group.as_ref(), &[offset]);
+            }
+            None => render_pass.set_push_constants(stages, 0, bytes),
+        }
+    }
+
+    /// Prepares the constants of the draws of a render bundle, before the
+    /// bundle encoder is created.
+    pub fn prepare<T: Pod>(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        values: &[T],
+    ) -> BundleConstants {
+        let data = values
+            .iter()
+            .map(|value| bytemuck::bytes_of(value).to_vec())
+            .collect::<Vec<_>>();
+        let uniform = self.uniform.as_ref().map(|uniform| {
+            let mut slots = uniform.slots.lock().unwrap();
+            let offsets = data
+                .iter()
+                .map(|bytes| slots.offset(device, queue, uniform, self.size, bytes))
+                .collect::<Vec<_>>();
+            // The last buffer holds all the values written since it was
+            // started, including the ones of the previous draws.
+            (slots.bind_group(), offsets)
+        });
+        BundleConstants { data, uniform }
+    }
+}
+
+impl ConstantSlots {
+    /// Returns the offset of the value in the buffer, writing it if it is
+    /// not there yet.
+    fn offset(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        uniform: &UniformConstants,
+        size: u32,
+        bytes: &[u8],
+    ) -> u32 {
+        if let Some(offset) = self.offsets.get(bytes) {
+            return *offset;
+        }
+        if self.offsets.len() >= DrawConstants::MAX_SLOTS {
+            self.data.clear();
+            self.offsets.clear();
+            self.buffer = None;
+        }
+
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(bytes);
+        self.data.resize((offset + uniform.stride) as usize, 0);
+        self.offsets.insert(bytes.to_vec(), offset);
+
+        let capacity = self.buffer.as_ref().map_or(0, |(buffer, _)| buffer.size());
+        match self.buffer.as_ref() {
+            Some((buffer, _)) if self.data.len() as u64 <= capacity => {
+                queue.write_buffer(
+                    buffer,
+                    offset as u64,
+                    &self.data[offset as usize..offset as usize + size as usize],
+                );
+            }
+            _ => {
+                // Start a larger buffer holding all the values.
+                let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some(uniform.label),
+                    size: (self.data.len() as u64 * 2).max(uniform.stride as u64 * 64),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    mapped_at_creation: false,
+                });
+                queue.write_buffer(&buffer, 0, &self.data);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(uniform.label),
+                    layout: &uniform.layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &buffer,
+                            offset: 0,
+                            size: NonZeroU64::new(size as u64),
+                        }),
+                    }],
+                });
+                self.buffer = Some((buffer, Arc::new(bind_group)));
+            }
+        }
+        offset
+    }
+
+    fn bind_group(&self) -> Arc<wgpu::BindGroup> {
+        self.buffer.as_ref().unwrap().1.clone()
+    }
+}
+
+impl BundleConstants {
+    /// Sets the constants of the `i`-th draw of the bundle.
+    pub fn set(
+        &self,
+        encoder: &mut wgpu::RenderBundleEncoder,
+        group: u32,
+        stages: wgpu::ShaderStages,
+        i: usize,
+    ) {
+        match self.uniform.as_ref() {
+            Some((bind_group, offsets)) => {
+                encoder.set_bind_group(group, bind_group.as_ref(), &[offsets[i]])
+            }
+            None => encoder.set_push_constants(stages, 0, &self.data[i]),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::render::{
+    rpass::{IndirectDraws, Locals, LocalsBindGroup},
+    StagingRing,
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use std::ops::Range;
+
+/// Bounding box of the mesh of an indirect draw, in its local space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct DrawBounds {
+    min: [f32; 4],
+    max: [f32; 4],
+}
+
+impl DrawBounds {
+    pub fn new((min, max): (Vec3, Vec3)) -> Self {
+        Self {
+            min: min.extend(1.0).to_array(),
+            max: max.extend(1.0).to_array(),
+        }
+    }
+}
+
+/// Parameters of the culling compute pass.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct CullingParams {
+    view_proj: [f32; 16],
+    first_local: u32,
+    n_locals: u32,
+    _padding: [u32; 2],
+}
+
+/// Culls the instances drawn indirectly by the main pass on the GPU.
+///
+/// A compute pass tests the bounding box of each instance against the view
+/// frustum, and appends the locals of the visible instances to the instances
+/// of their draw, counting them in the instance count of the draw arguments.
+/// The main pass then draws the culled locals, so that the instances outside
+/// the view are skipped without the CPU iterating them.
+pub struct GpuCulling {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Locals of the visible instances, at the same place as in the locals
+    /// of the main pass.
+    culled: wgpu::Buffer,
+    /// Number of locals the culled buffer can hold.
+    capacity: u32,
+    /// Bind group of the culled locals, replacing the locals of the main
+    /// pass in the indirect draws.
+    pub culled_bind_group: wgpu::BindGroup,
+    params: wgpu::Buffer,
+    /// Bind group of the compute pass, recreated each frame as the buffers
+    /// it binds may have been resized.
+    bind_group: Option<wgpu::BindGroup>,
+    /// Number of locals culled by the next dispatch.
+    n_locals: u32,
+    /// View-projection matrix of the camera of the frame.
+    view_proj: Mat4,
+}
+
+impl GpuCulling {
+    /// Number of instances culled by a workgroup.
+    const WORKGROUP_SIZE: u32 = 64;
+
+    /// Creates the culling of the main pass, `locals_layout` being the layout
+    /// of the locals bind group of the main pass.
+    pub fn new(device: &wgpu::Device, locals_layout: &wgpu::BindGroupLayout) -> Self {
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("culling_bind_group_layout"),
+            entries: &[
+                storage(0, true),
+                storage(1, true),
+                storage(2, false),
+                storage(3, false),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("culling_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("culling.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("culling_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("culling_pipeline"),
+            layout: Some(&layout),
+            module: &shader_module,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("culling_params_buffer"),
+            size: std::mem::size_of::<CullingParams>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let capacity = LocalsBindGroup::<Locals>::INITIAL_INSTANCE_CAPACITY as u32;
+        let (culled, culled_bind_group) = Self::create_culled(device, locals_layout, capacity);
+        Self {
+            pipeline,
+            bind_group_layout,
+            culled,
+            capacity,
+            culled_bind_group,
+            params,
+            bind_group: None,
+            n_locals: 0,
+            view_proj: Mat4::IDENTITY,
+        }
+    }
+
+    fn create_culled(
+        device: &wgpu::Device,
+        locals_layout: &wgpu::BindGroupLayout,
+        capacity: u32,
+    ) -> (wgpu::Buffer, wgpu::BindGroup) {
+        let culled = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("culling_culled_buffer"),
+            size: capacity as u64 * std::mem::size_of::<Locals>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("culling_culled_bind_group"),
+            layout: locals_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: culled.as_entire_binding(),
+            }],
+        });
+        (culled, bind_group)
+    }
+
+    /// Sets the camera of the frame.
+    pub fn set_camera(&mut self, view_proj: Mat4) {
+        self.view_proj = view_proj;
+    }
+
+    /// Binds the buffers of the frame, whose locals and draws have been
+    /// uploaded. `culled` is the range of the locals drawn indirectly, whose
+    /// last material index is their draw.
+    pub fn prepare(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        staging: &mut StagingRing,
+        locals: &LocalsBindGroup<Locals>,
+        indirect: &IndirectDraws,
+        culled: Range<u32>,
+    ) {
+        // The culled locals are written at the same place as the locals.
+        if locals.capacity > self.capacity {
+            self.capacity = locals.capacity;
+            (self.culled, self.culled_bind_group) =
+                Self::create_culled(device, &locals.layout, self.capacity);
+        }
+        let params = CullingParams {
+            view_proj: self.view_proj.to_cols_array(),
+            first_local: culled.start,
+            n_locals: culled.end - culled.start,
+            _padding: [0; 2],
+        };
+        staging.write(
+            device,
+            encoder,
+            &self.params,
+            0,
+            bytemuck::bytes_of(&params),
+        );
+        self.n_locals = params.n_locals;
+
+        self.bind_group = Some(device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("culling_bind_group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: locals.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: indirect.bounds.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: indirect.buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: self.culled.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: self.params.as_entire_binding(),
+                },
+            ],
+        }));
+    }
+
+    /// Culls the instances bound by `prepare`. Must be encoded after the
+    /// uploads of the frame and before the main pass.
+    pub fn dispatch(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        let Some(bind_group) = self.bind_group.take() else {
+            return;
+        };
+        if self.n_locals == 0 {
+            return;
+        }
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("culling_compute_pass"),
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(self.n_locals.div_ceil(Self::WORKGROUP_SIZE), 1, 1);
+    }
+}
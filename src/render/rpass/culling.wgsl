@@ -0,0 +1,77 @@
+struct Locals {
+    model: mat4x4<f32>,
+    model_view_it: mat4x4<f32>,
+    // The last component is the indirect draw of the instance.
+    material_index: vec4<u32>,
+}
+
+// Layout of `wgpu::util::DrawIndexedIndirectArgs`.
+struct DrawArgs {
+    index_count: u32,
+    instance_count: atomic<u32>,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+// Bounding box of the mesh of a draw in its local space.
+struct DrawBounds {
+    lower: vec4<f32>,
+    upper: vec4<f32>,
+}
+
+struct Params {
+    view_proj: mat4x4<f32>,
+    // Range of the locals of the instances drawn indirectly.
+    first_local: u32,
+    n_locals: u32,
+}
+
+@group(0) @binding(0) var<storage, read> locals: array<Locals>;
+@group(0) @binding(1) var<storage, read> bounds: array<DrawBounds>;
+@group(0) @binding(2) var<storage, read_write> args: array<DrawArgs>;
+@group(0) @binding(3) var<storage, read_write> culled: array<Locals>;
+@group(0) @binding(4) var<uniform> params: Params;
+
+// Returns true if the box is entirely outside one of the planes of the view
+// frustum, in clip space.
+fn is_outside(clip_from_local: mat4x4<f32>, bmin: vec3<f32>, bmax: vec3<f32>) -> bool {
+    // Number of corners outside each plane: -x, +x, -y, +y, near, far.
+    var outside = array<u32, 6>(0u, 0u, 0u, 0u, 0u, 0u);
+    for (var i = 0u; i < 8u; i++) {
+        let corner = vec3<f32>(
+            select(bmin.x, bmax.x, (i & 1u) != 0u),
+            select(bmin.y, bmax.y, (i & 2u) != 0u),
+            select(bmin.z, bmax.z, (i & 4u) != 0u),
+        );
+        let p = clip_from_local * vec4<f32>(corner, 1.0);
+        outside[0] += u32(p.x < -p.w);
+        outside[1] += u32(p.x > p.w);
+        outside[2] += u32(p.y < -p.w);
+        outside[3] += u32(p.y > p.w);
+        outside[4] += u32(p.z < 0.0);
+        outside[5] += u32(p.z > p.w);
+    }
+    for (var plane = 0u; plane < 6u; plane++) {
+        if (outside[plane] == 8u) {
+            return true;
+        }
+    }
+    return false;
+}
+
+// Appends the locals of each visible instance to the instances of its draw.
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= params.n_locals) {
+        return;
+    }
+    let local = locals[params.first_local + id.x];
+    let draw = local.material_index.w;
+    let aabb = bounds[draw];
+    if (is_outside(params.view_proj * local.model, aabb.lower.xyz, aabb.upper.xyz)) {
+        return;
+    }
+    let slot = atomicAdd(&args[draw].instance_count, 1u);
+    culled[args[draw].first_instance + slot] = local;
+}
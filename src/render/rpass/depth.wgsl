@@ -0,0 +1,51 @@
+// Depth-only pre-pass of the main render pass. The clip space position must
+// be computed exactly as in `blph.wgsl` so that the main pass can test the
+// depth for equality.
+
+/// Camera data.
+struct Globals {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    time: f32,
+    delta_time: f32,
+}
+
+struct Locals {
+    model: mat4x4<f32>,
+    model_view_it: mat4x4<f32>,
+    material_index: vec4<u32>,
+}
+
+struct PConsts {
+    instance_base_index: u32,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var<storage, read> instances: array<Locals>;
+
+// Without push constants, the constants are read from a uniform buffer.
+// #if push_constants
+var<push_constant> pconsts: PConsts;
+// #else
+@group(2) @binding(0) var<uniform> pconsts: PConsts;
+// #fi
+
+struct DepthVSInput {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+}
+
+struct DepthVSOutput {
+    @builtin(position) @invariant position: vec4<f32>,
+}
+
+@vertex
+fn vs_main(vin: DepthVSInput) -> DepthVSOutput {
+    let locals = instances[vin.instance_index + pconsts.instance_base_index];
+    let model_view = globals.view * locals.model;
+    let pos_eye_space = model_view * vec4<f32>(vin.position, 1.0);
+    var out: DepthVSOutput;
+    out.position = globals.proj * pos_eye_space;
+    return out;
+}
@@ -0,0 +1,249 @@
+use crate::core::camera::Camera;
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+/// No measure is in flight, a new frame can be measured.
+const READBACK_IDLE: u8 = 0;
+/// The luminance has been copied to the readback buffer.
+const READBACK_COPIED: u8 = 1;
+/// The readback buffer is being mapped.
+const READBACK_MAPPING: u8 = 2;
+/// The readback buffer is mapped and can be read.
+const READBACK_MAPPED: u8 = 3;
+
+/// Exposure of the main pass, adapted over time to the average luminance of
+/// the frames if the camera has auto-exposure enabled.
+///
+/// The presented frame is copied, its log-average luminance computed by a
+/// compute pass and read back a few frames later. The exposure then moves
+/// towards the one bringing that luminance to the key of the camera.
+pub struct ExposureMeter {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Copy of the measured frame and its bind group, recreated when the
+    /// size or the format of the frames changes.
+    frame: Option<(wgpu::Texture, wgpu::BindGroup)>,
+    result: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    state: Arc<AtomicU8>,
+    /// Exposure adapted to the measured frames, in EV.
+    adapted_ev: f32,
+    /// Exposure the adaptation moves towards, in EV.
+    target_ev: f32,
+    /// Whether the camera of the current frame adapts its exposure.
+    enabled: bool,
+    /// Whether the frames have been found impossible to copy.
+    unsupported: bool,
+}
+
+impl ExposureMeter {
+    /// Creates the exposure meter of the main pass.
+    pub fn new(device: &wgpu::Device) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("exposure_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("exposure_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("exposure.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("exposure_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("exposure_pipeline"),
+            layout: Some(&layout),
+            module: &shader_module,
+            entry_point: Some("cs_main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        let size = std::mem::size_of::<f32>() as u64;
+        let result = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("exposure_result_buffer"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("exposure_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+            frame: None,
+            result,
+            readback,
+            state: Arc::new(AtomicU8::new(READBACK_IDLE)),
+            adapted_ev: 0.0,
+            target_ev: 0.0,
+            enabled: false,
+            unsupported: false,
+        }
+    }
+
+    /// Reads the luminance measured a few frames ago, if it is available,
+    /// and sets the target of the adaptation from it.
+    pub fn fetch_result(&mut self, device: &wgpu::Device, camera: Option<&Camera>) {
+        match self.state.load(Ordering::Acquire) {
+            READBACK_COPIED => {
+                // The commands copying the luminance have been submitted.
+                self.state.store(READBACK_MAPPING, Ordering::Release);
+                let state = self.state.clone();
+                self.readback.slice(..).map_async(
+                    wgpu::MapMode::Read,
+                    move |result| match result {
+                        Ok(_) => state.store(READBACK_MAPPED, Ordering::Release),
+                        Err(err) => {
+                            log::error!("Failed to read back the frame luminance: {}", err);
+                            state.store(READBACK_IDLE, Ordering::Release);
+                        }
+                    },
+                );
+                device.poll(wgpu::Maintain::Poll);
+            }
+            READBACK_MAPPING => {
+                device.poll(wgpu::Maintain::Poll);
+            }
+            READBACK_MAPPED => {
+                let luminance = {
+                    let data = self.readback.slice(..).get_mapped_range();
+                    *bytemuck::from_bytes::<f32>(&data)
+                };
+                self.readback.unmap();
+                self.state.store(READBACK_IDLE, Ordering::Release);
+                if let Some(camera) = camera {
+                    if let Some(auto) = camera.auto_exposure {
+                        // The frame was exposed with about the current
+                        // exposure, the compensation of the camera brightens
+                        // or darkens the key.
+                        let ev = self.adapted_ev + camera.exposure;
+                        let correction = (auto.key / luminance.max(1e-4)).log2();
+                        self.target_ev = (ev + correction).clamp(auto.min_ev, auto.max_ev);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the scale of the lit colors seen by the camera, moving the
+    /// adapted exposure towards its target over `dt` seconds.
+    pub fn exposure(&mut self, camera: &Camera, dt: f32) -> f32 {
+        self.enabled = camera.auto_exposure.is_some();
+        let ev = match camera.auto_exposure {
+            Some(auto) => {
+                let t = 1.0 - (-auto.speed.max(0.0) * dt).exp();
+                self.adapted_ev += (self.target_ev - self.adapted_ev) * t;
+                self.adapted_ev + camera.exposure
+            }
+            None => camera.exposure,
+        };
+        ev.exp2()
+    }
+
+    /// Measures the average luminance of the presented frame if the camera
+    /// of the frame adapts its exposure and no measure is in flight. The
+    /// frame must allow copies.
+    pub fn measure(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, frame: &wgpu::Texture) {
+        if !self.enabled || self.state.load(Ordering::Acquire) != READBACK_IDLE {
+            return;
+        }
+        if !frame.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            if !self.unsupported {
+                log::warn!("The frames can't be copied, auto-exposure is disabled.");
+                self.unsupported = true;
+            }
+            return;
+        }
+        profiling::scope!("ExposureMeter::measure");
+
+        let outdated = self.frame.as_ref().map_or(true, |(texture, _)| {
+            texture.size() != frame.size() || texture.format() != frame.format()
+        });
+        if outdated {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("exposure_frame"),
+                size: frame.size(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: frame.format(),
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("exposure_bind_group"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.result.as_entire_binding(),
+                    },
+                ],
+            });
+            self.frame = Some((texture, bind_group));
+        }
+        let (texture, bind_group) = self.frame.as_ref().unwrap();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("exposure_encoder"),
+        });
+        encoder.copy_texture_to_texture(
+            frame.as_image_copy(),
+            texture.as_image_copy(),
+            frame.size(),
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("exposure_compute_pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, bind_group, &[]);
+            compute_pass.dispatch_workgroups(1, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(
+            &self.result,
+            0,
+            &self.readback,
+            0,
+            std::mem::size_of::<f32>() as u64,
+        );
+        queue.submit(Some(encoder.finish()));
+        self.state.store(READBACK_COPIED, Ordering::Release);
+    }
+}
@@ -0,0 +1,44 @@
+// Log-average luminance of a frame, over a grid of samples reduced by a
+// single workgroup.
+
+@group(0) @binding(0)
+var frame: texture_2d<f32>;
+@group(0) @binding(1)
+var<storage, read_write> result: f32;
+
+// Number of samples along each side of the frame.
+const GRID: u32 = 64u;
+// Number of samples along each side of the block of an invocation.
+const BLOCK: u32 = 4u;
+
+var<workgroup> sums: array<f32, 256>;
+
+@compute @workgroup_size(16, 16)
+fn cs_main(
+    @builtin(local_invocation_id) id: vec3<u32>,
+    @builtin(local_invocation_index) index: u32,
+) {
+    let size = textureDimensions(frame);
+    var sum = 0.0;
+    for (var y = 0u; y < BLOCK; y++) {
+        for (var x = 0u; x < BLOCK; x++) {
+            let cell = id.xy * BLOCK + vec2<u32>(x, y);
+            let pixel = (vec2<f32>(cell) + 0.5) / f32(GRID) * vec2<f32>(size);
+            let color = textureLoad(frame, min(vec2<u32>(pixel), size - 1u), 0).rgb;
+            let luminance = dot(color, vec3<f32>(0.2126, 0.7152, 0.0722));
+            sum += log2(luminance + 1e-4);
+        }
+    }
+    sums[index] = sum;
+    workgroupBarrier();
+
+    for (var stride = 128u; stride > 0u; stride >>= 1u) {
+        if (index < stride) {
+            sums[index] += sums[index + stride];
+        }
+        workgroupBarrier();
+    }
+    if (index == 0u) {
+        result = exp2(sums[0] / f32(GRID * GRID));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::{
+    core::{
+        camera::Camera,
+        gizmo::{arrow_lines, cross_lines, frustum_lines, Gizmo, GizmoVertex},
+        Color, Light,
+    },
+    render::{
+        rpass::{GlobalsBindGroup, LightArray, ShadowCasters},
+        RenderTarget, Renderer, StagingRing, Viewport,
+    },
+    scene::{NodeIdx, Scene},
+};
+use glam::{Mat4, Vec3};
+use legion::IntoQuery;
+
+/// Render pass drawing the handles of the manipulator gizmo, and the debug
+/// gizmos of the lights and cameras when enabled.
+///
+/// The handles are drawn after everything else without depth test, so that
+/// they are never hidden by the scene. Their lines and triangles share a
+/// single vertex buffer, the lines first.
+pub struct GizmoRenderPass {
+    /// The pipeline drawing the lines of the handles.
+    lines_pipeline: wgpu::RenderPipeline,
+    /// The pipeline drawing the triangles of the handles.
+    triangles_pipeline: wgpu::RenderPipeline,
+    /// The vertex buffer storing the handles.
+    vertices: wgpu::Buffer,
+    /// Maximum number of vertices in the vertex buffer.
+    capacity: u32,
+    /// Number of vertices of the lines uploaded by the last call to
+    /// `prepare`.
+    n_lines: u32,
+    /// Number of vertices of the triangles uploaded by the last call to
+    /// `prepare`.
+    n_triangles: u32,
+}
+
+impl GizmoRenderPass {
+    /// Initial vertex capacity of the handles.
+    pub const INITIAL_VERTEX_CAPACITY: u32 = 1024;
+
+    /// Creates a new gizmo render pass.
+    pub fn new(
+        device: &wgpu::Device,
+        globals_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gizmo_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("gizmo.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("gizmo_pipeline_layout"),
+            bind_group_layouts: &[globals_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |label, topology| {
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(label),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[wgpu::VertexBufferLayout {
+                        array_stride: GizmoVertex::SIZE as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x4],
+                    }],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology,
+                    front_face: wgpu::FrontFace::Ccw,
+                    // Each handle has a single color, its back faces may be
+                    // drawn over its front faces.
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState {
+                    count: 1,
+                    mask: !0,
+                    alpha_to_coverage_enabled: false,
+                },
+                multiview: None,
+                cache: None,
+            })
+        };
+        let lines_pipeline =
+            create_pipeline("gizmo_lines_pipeline", wgpu::PrimitiveTopology::LineList);
+        let triangles_pipeline = create_pipeline(
+            "gizmo_triangles_pipeline",
+            wgpu::PrimitiveTopology::TriangleList,
+        );
+        let vertices = Self::create_vertex_buffer(device, Self::INITIAL_VERTEX_CAPACITY);
+
+        Self {
+            lines_pipeline,
+            triangles_pipeline,
+            vertices,
+            capacity: Self::INITIAL_VERTEX_CAPACITY,
+            n_lines: 0,
+            n_triangles: 0,
+        }
+    }
+
+    fn create_vertex_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gizmo_vertex_buffer"),
+            size: GizmoVertex::SIZE as u64 * capacity as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Uploads the handles of the gizmo of the scene, if any and if its
+    /// entity is visible, sized for the camera at `camera_pos`.
+    ///
+    /// With the lights uploaded for the frame, the debug gizmos are uploaded
+    /// as well: an arrow per directional light, a cross per point light, the
+    /// frustum of the cameras other than the main one and the volume covered
+    /// by each shadow map.
+    pub fn prepare(
+        &mut self,
+        scene: &Scene,
+        renderer: &Renderer,
+        staging: &mut StagingRing,
+        encoder: &mut wgpu::CommandEncoder,
+        camera_pos: Vec3,
+        debug_lights: Option<&LightArray>,
+    ) {
+        profiling::scope!("GizmoRenderPass::prepare");
+        self.n_lines = 0;
+        self.n_triangles = 0;
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+        if let Some(lights) = debug_lights {
+            vertices = Self::debug_lines(scene, lights, camera_pos);
+        }
+        if let Some(gizmo) = scene.gizmo.as_ref() {
+            if scene.nodes[gizmo.target.node].is_visible() {
+                let transform = scene.nodes.world(gizmo.target.node);
+                let size = Gizmo::size(transform.translation, camera_pos);
+                let (lines, handles) = gizmo.vertices(&transform, size);
+                vertices.extend(lines);
+                triangles = handles;
+            }
+        }
+        self.n_lines = vertices.len() as u32;
+        self.n_triangles = triangles.len() as u32;
+        vertices.extend(triangles);
+        if vertices.is_empty() {
+            return;
+        }
+
+        if vertices.len() as u32 > self.capacity {
+            self.capacity = (vertices.len() as u32).next_power_of_two();
+            self.vertices = Self::create_vertex_buffer(&renderer.device, self.capacity);
+        }
+        staging.write(
+            &renderer.device,
+            encoder,
+            &self.vertices,
+            0,
+            bytemuck::cast_slice(&vertices),
+        );
+    }
+
+    /// Returns the lines of the debug gizmos of the active lights and
+    /// cameras, the icons of the lights sized for the camera at
+    /// `camera_pos`.
+    fn debug_lines(scene: &Scene, lights: &LightArray, camera_pos: Vec3) -> Vec<GizmoVertex> {
+        let mut lines = Vec::new();
+        for (light, node_idx) in <(&Light, &NodeIdx)>::query().iter(&scene.world) {
+            if !scene.nodes[*node_idx].is_active() {
+                continue;
+            }
+            let transform = scene.nodes.world(*node_idx);
+            let origin = transform.translation;
+            let size = Gizmo::size(origin, camera_pos);
+            match light {
+                Light::Directional { color, .. } => {
+                    let direction = light.world_direction(&transform).unwrap_or(Vec3::NEG_Y);
+                    lines.extend(arrow_lines(origin, direction, size, *color));
+                }
+                Light::Point { color } => {
+                    lines.extend(cross_lines(origin, size * 0.5, *color));
+                }
+            }
+        }
+        for (camera, node_idx) in <(&Camera, &NodeIdx)>::query().iter(&scene.world) {
+            if camera.is_main || !scene.nodes[*node_idx].is_active() {
+                continue;
+            }
+            let view = scene.nodes.inverse_world(*node_idx).to_mat4();
+            let inv_view_proj = (camera.current_proj_matrix() * view).inverse();
+            lines.extend(frustum_lines(inv_view_proj, Color::WHITE));
+        }
+        for light in lights
+            .lights
+            .iter()
+            .filter(|light| light.shadow_map != ShadowCasters::NONE)
+        {
+            let w2l = Mat4::from_cols_array(&light.w2l);
+            lines.extend(frustum_lines(w2l.inverse(), Color::ORANGE));
+        }
+        lines
+    }
+
+    /// Records the gizmo pass. The globals must be already updated by the
+    /// main pass.
+    pub fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RenderTarget,
+        globals: &GlobalsBindGroup,
+        viewport: &Viewport,
+    ) {
+        profiling::scope!("GizmoRenderPass::record");
+        if self.n_lines + self.n_triangles == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("gizmo_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        viewport.apply(&mut render_pass);
+        render_pass.set_bind_group(0, globals, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        if self.n_lines > 0 {
+            render_pass.set_pipeline(&self.lines_pipeline);
+            render_pass.draw(0..self.n_lines, 0..1);
+        }
+        if self.n_triangles > 0 {
+            render_pass.set_pipeline(&self.triangles_pipeline);
+            render_pass.draw(self.n_lines..self.n_lines + self.n_triangles, 0..1);
+        }
+        drop(render_pass);
+        self.n_lines = 0;
+        self.n_triangles = 0;
+    }
+}
@@ -0,0 +1,33 @@
+/// Camera data.
+struct Globals {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    time: f32,
+    delta_time: f32,
+}
+
+struct VSInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+struct VSOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+
+@vertex
+fn vs_main(vin: VSInput) -> VSOutput {
+    var vout: VSOutput;
+    vout.position = globals.proj * globals.view * vec4<f32>(vin.position, 1.0);
+    vout.color = vin.color;
+    return vout;
+}
+
+@fragment
+fn fs_main(vout: VSOutput) -> @location(0) vec4<f32> {
+    return vout.color;
+}
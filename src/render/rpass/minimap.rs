@@ -0,0 +1,245 @@
+use crate::render::{RenderTarget, Renderer, StagingRing};
+use bytemuck::{Pod, Zeroable};
+
+/// Placement of the minimap in the window and position of its marker.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct MinimapLocals {
+    /// Rectangle covered by the map in clip space: min x, min y, max x and
+    /// max y.
+    rect: [f32; 4],
+    /// Direction of the marker of the main camera, x to the right and y up.
+    heading: [f32; 2],
+    /// Thickness of the border in texture coordinates.
+    border: f32,
+    _padding: f32,
+}
+
+crate::impl_size_constant!(MinimapLocals);
+
+/// Render pass drawing the texture of the minimap in the top right corner of
+/// the window, with a marker of the main camera at its center.
+///
+/// The quad and the marker are drawn in a single draw call after everything
+/// else, the marker being shaped in the fragment shader.
+pub struct MinimapRenderPass {
+    /// The pipeline drawing the map.
+    pipeline: wgpu::RenderPipeline,
+    /// The layout of the map bind group.
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// The bind group of the map texture, created by `prepare` when the map
+    /// is shown.
+    bind_group: Option<wgpu::BindGroup>,
+    /// The sampler of the map texture.
+    sampler: wgpu::Sampler,
+    /// The uniform buffer storing the locals.
+    locals: wgpu::Buffer,
+}
+
+impl MinimapRenderPass {
+    /// Distance in pixels between the map and the edges of the window.
+    pub const MARGIN: f32 = 16.0;
+
+    /// Thickness in pixels of the border of the map.
+    pub const BORDER: f32 = 2.0;
+
+    /// Creates a new minimap render pass.
+    pub fn new(device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("minimap_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: MinimapLocals::BUFFER_SIZE,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("minimap_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let locals = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("minimap_locals_buffer"),
+            size: MinimapLocals::SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("minimap_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("minimap.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("minimap_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("minimap_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            bind_group: None,
+            sampler,
+            locals,
+        }
+    }
+
+    /// Uploads the placement of the minimap of the renderer in the target,
+    /// if it is shown and its texture exists.
+    pub fn prepare(
+        &mut self,
+        renderer: &Renderer,
+        staging: &mut StagingRing,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RenderTarget,
+    ) {
+        profiling::scope!("MinimapRenderPass::prepare");
+        self.bind_group = None;
+        let Some(minimap) = renderer.minimap() else {
+            return;
+        };
+        let Some(texture) = renderer.textures.get(minimap.texture) else {
+            return;
+        };
+
+        // The map shrinks to fit small windows.
+        let width = target.size.width as f32;
+        let height = target.size.height as f32;
+        let size = (minimap.size as f32)
+            .min(width - 2.0 * Self::MARGIN)
+            .min(height - 2.0 * Self::MARGIN);
+        if size <= 2.0 * Self::BORDER {
+            return;
+        }
+        let max_x = 1.0 - 2.0 * Self::MARGIN / width;
+        let max_y = 1.0 - 2.0 * Self::MARGIN / height;
+        let locals = MinimapLocals {
+            rect: [
+                max_x - 2.0 * size / width,
+                max_y - 2.0 * size / height,
+                max_x,
+                max_y,
+            ],
+            heading: minimap.heading.to_array(),
+            border: Self::BORDER / size,
+            _padding: 0.0,
+        };
+        staging.write(
+            &renderer.device,
+            encoder,
+            &self.locals,
+            0,
+            bytemuck::bytes_of(&locals),
+        );
+        self.bind_group = Some(
+            renderer
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("minimap_bind_group"),
+                    layout: &self.bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.locals.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&texture.view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                }),
+        );
+    }
+
+    /// Records the minimap pass over the whole target, regardless of the
+    /// viewport of the main camera.
+    pub fn record(&mut self, encoder: &mut wgpu::CommandEncoder, target: &RenderTarget) {
+        profiling::scope!("MinimapRenderPass::record");
+        let Some(bind_group) = self.bind_group.take() else {
+            return;
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("minimap_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
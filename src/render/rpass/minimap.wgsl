@@ -0,0 +1,58 @@
+/// Placement of the minimap.
+struct Locals {
+    rect: vec4<f32>,
+    heading: vec2<f32>,
+    border: f32,
+    _padding: f32,
+}
+
+struct VSOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@group(0) @binding(0) var<uniform> locals: Locals;
+@group(0) @binding(1) var map: texture_2d<f32>;
+@group(0) @binding(2) var map_sampler: sampler;
+
+/// Half size of the marker of the camera in texture coordinates.
+const MARKER_SIZE: f32 = 0.04;
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VSOutput {
+    // Two triangles covering the rectangle.
+    var corners = array<vec2<f32>, 6>(
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 0.0),
+        vec2<f32>(1.0, 1.0),
+        vec2<f32>(0.0, 1.0),
+    );
+    let corner = corners[index];
+    var vout: VSOutput;
+    vout.position = vec4<f32>(mix(locals.rect.xy, locals.rect.zw, corner), 0.0, 1.0);
+    // The rows of the texture go downwards.
+    vout.uv = vec2<f32>(corner.x, 1.0 - corner.y);
+    return vout;
+}
+
+@fragment
+fn fs_main(vout: VSOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(map, map_sampler, vout.uv);
+    let uv = vout.uv;
+    if any(uv < vec2<f32>(locals.border)) || any(uv > vec2<f32>(1.0 - locals.border)) {
+        return vec4<f32>(1.0, 1.0, 1.0, 0.9);
+    }
+
+    // Triangle at the center pointing in the heading of the camera.
+    let p = vec2<f32>(uv.x - 0.5, 0.5 - uv.y);
+    let forward = locals.heading;
+    let right = vec2<f32>(forward.y, -forward.x);
+    let local = vec2<f32>(dot(p, right), dot(p, forward));
+    let t = (local.y + MARKER_SIZE) / (2.0 * MARKER_SIZE);
+    if t >= 0.0 && t <= 1.0 && abs(local.x) <= (1.0 - t) * MARKER_SIZE * 0.8 {
+        return vec4<f32>(1.0, 0.2, 0.1, 1.0);
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
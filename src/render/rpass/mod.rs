@@ -1,15 +1,43 @@
+mod background;
 mod blph;
+mod constants;
+mod culling;
+mod exposure;
+mod gizmo;
+mod minimap;
+mod occlusion;
+mod particle;
 #[allow(dead_code)]
 mod skybox;
+mod sprite;
+mod water;
 
 use crate::{
-    render::{Pipelines, RenderParams, RenderTarget, Renderer},
-    scene::Scene,
+    core::{
+        mesh::{MeshBundle, VertexAttribute},
+        FxHashMap, FxHashSet, Light,
+    },
+    render::{
+        DepthSettings, GpuContext, GraphResource, Pipelines, RenderParams, RenderTarget, Renderer,
+        StagingRing, TransientTextures, Viewport,
+    },
+    scene::{CustomShader, NodeIdx, Nodes, Scene},
 };
+pub use background::*;
 pub use blph::*;
 use bytemuck::{Pod, Zeroable};
+pub use constants::*;
+pub use culling::*;
+pub use exposure::*;
+pub use gizmo::*;
 use glam::Mat4;
-use std::num::NonZeroU32;
+pub use minimap::*;
+pub use occlusion::*;
+pub use particle::*;
+pub use skybox::EnvironmentMap;
+pub use sprite::*;
+use std::{num::NonZeroU32, ops::Range, time::Instant};
+pub use water::*;
 
 crate::impl_size_constant!(
     Globals,
@@ -17,8 +45,7 @@ crate::impl_size_constant!(
     ShadowPassLocals,
     PConsts,
     PConstsShadowPass,
-    GpuLight,
-    LightArray
+    GpuLight
 );
 
 /// The global uniforms for the rendering passes.
@@ -29,6 +56,15 @@ pub struct Globals {
     pub view: [f32; 16],
     /// The projection matrix.
     pub proj: [f32; 16],
+    /// The position of the camera in world space.
+    pub camera_pos: [f32; 3],
+    /// Seconds elapsed since the pass was created.
+    pub time: f32,
+    /// Seconds elapsed since the previous frame.
+    pub delta_time: f32,
+    /// Scale of the lit colors, from the exposure of the camera.
+    pub exposure: f32,
+    pub _padding: [f32; 2],
 }
 
 /// The local information (per entity/instance) for the rendering passes.
@@ -39,7 +75,10 @@ pub struct Locals {
     model: [f32; 16],
     /// The transpose of the inverse of the model-view matrix.
     model_view_it: [f32; 16],
-    /// The material index in case of overriding the material.
+    /// The material index in case of overriding the material, followed by
+    /// the tint of the instance packed as RGBA8, transparent if none, the
+    /// material of the sub-mesh for the indirect draws and the indirect draw
+    /// of the instance for the GPU culling.
     material_index: [u32; 4],
 }
 
@@ -48,7 +87,7 @@ impl Locals {
         Self {
             model: Mat4::IDENTITY.to_cols_array(),
             model_view_it: Mat4::IDENTITY.to_cols_array(),
-            material_index: [u32::MAX; 4],
+            material_index: [u32::MAX, 0, u32::MAX, u32::MAX],
         }
     }
 }
@@ -97,11 +136,34 @@ struct PConsts {
 pub struct PConstsShadowPass {
     instance_base_index: u32,
     light_index: u32,
+    /// Material of the drawn sub-mesh, read by the alpha-tested draws.
+    material_index: u32,
 }
 
-/// Depth format for the rendering passes.
+/// Depth format of the shadow maps, whose depth is never reversed. The
+/// depth buffer of the main pass is set by [`DepthSettings`].
 pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
 
+/// Returns the color attachment of a pass drawing into the render target
+/// with the depth buffer of the main pass: the multisampled color texture
+/// resolved into the target if the depth buffer is multisampled, the target
+/// itself otherwise.
+pub fn scene_color_attachment<'a>(
+    target: &'a RenderTarget,
+    msaa_view: Option<&'a wgpu::TextureView>,
+    load: wgpu::LoadOp<wgpu::Color>,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    wgpu::RenderPassColorAttachment {
+        view: msaa_view.unwrap_or(&target.view),
+        resolve_target: msaa_view.map(|_| &target.view),
+        ops: wgpu::Operations {
+            load,
+            // The multisampled texture is kept for the following passes.
+            store: wgpu::StoreOp::Store,
+        },
+    }
+}
+
 /// The binding group for the global uniforms.
 ///
 /// See [`Globals`] for the uniforms.
@@ -112,6 +174,11 @@ pub struct GlobalsBindGroup {
     pub layout: wgpu::BindGroupLayout,
     /// The uniform buffer containing the global uniforms.
     pub buffer: wgpu::Buffer,
+    /// Transparent environment map bound when none is set, so that
+    /// materials don't reflect anything.
+    pub placeholder_environment: wgpu::TextureView,
+    /// Sampler of the placeholder environment map.
+    pub placeholder_sampler: wgpu::Sampler,
 }
 
 impl<'a> Into<Option<&'a wgpu::BindGroup>> for &'a GlobalsBindGroup {
@@ -157,31 +224,48 @@ impl<'a, L: InstanceLocals> Into<Option<&'a wgpu::BindGroup>> for &'a LocalsBind
 #[derive(Debug, Clone, Copy, Default, Pod, Zeroable)]
 pub struct GpuLight {
     pub dir_or_pos: [f32; 4],
-    pub color: [f32; 4],
+    pub color: [f32; 3],
+    /// Shadow map of the light, `ShadowCasters::NONE` if it casts no
+    /// shadows.
+    pub shadow_map: u32,
     pub w2l: [f32; 16],
 }
 
-/// Array of lights passed to the shader as a storage buffer.
-#[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+/// Array of lights passed to the shader as a storage buffer: the number of
+/// lights followed by the lights.
+#[derive(Debug, Clone)]
 pub struct LightArray {
     pub len: [u32; 4], // with padding to make sure the array is 16-byte aligned.
-    pub lights: [GpuLight; BlinnPhongRenderPass::MAX_LIGHTS],
+    pub lights: Vec<GpuLight>,
 }
 
-impl Default for LightArray {
-    fn default() -> Self {
+impl LightArray {
+    /// Size in bytes of the number of lights preceding the lights.
+    pub const HEADER_SIZE: usize = std::mem::size_of::<[u32; 4]>();
+
+    /// Creates an empty array holding up to `capacity` lights.
+    pub fn with_capacity(capacity: usize) -> Self {
         Self {
             len: [0; 4],
-            lights: [GpuLight::default(); BlinnPhongRenderPass::MAX_LIGHTS],
+            lights: Vec::with_capacity(capacity),
         }
     }
-}
 
-impl LightArray {
-    /// Only reset the length of the array.
+    /// Returns the size in bytes of the buffer holding `capacity` lights.
+    pub const fn buffer_size(capacity: usize) -> u64 {
+        (Self::HEADER_SIZE + capacity * GpuLight::SIZE) as u64
+    }
+
+    /// Removes all the lights.
     pub fn clear(&mut self) {
         self.len = [0; 4];
+        self.lights.clear();
+    }
+
+    /// Appends a light.
+    pub fn push(&mut self, light: GpuLight) {
+        self.lights.push(light);
+        self.len[0] += 1;
     }
 
     pub fn is_empty(&self) -> bool {
@@ -204,6 +288,10 @@ pub struct LightsBindGroup {
     pub lights_buffer: wgpu::Buffer,
     /// Cached lights of each frame to avoid unnecessary allocation.
     lights: LightArray,
+    /// Maximum number of lights in the storage buffer.
+    capacity: usize,
+    /// Whether the lights over the capacity have already been reported.
+    warned_overflow: bool,
 }
 
 impl<'a> Into<Option<&'a wgpu::BindGroup>> for &'a LightsBindGroup {
@@ -213,14 +301,90 @@ impl<'a> Into<Option<&'a wgpu::BindGroup>> for &'a LightsBindGroup {
 }
 
 pub trait RenderingPass {
+    /// Records the commands of a frame, returned in submission order.
     fn record(
         &mut self,
         renderer: &Renderer,
         target: &RenderTarget,
         params: &RenderParams,
         scene: &Scene,
-        encoder: &mut wgpu::CommandEncoder,
-    );
+    ) -> Vec<wgpu::CommandBuffer>;
+}
+
+/// Assignment of the shadow maps to the lights casting shadows.
+///
+/// Only the active directional lights whose node casts shadows are given a
+/// shadow map, in the order of the lights. Point lights can't use the
+/// orthographic shadow maps.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ShadowCasters {
+    /// Light entity and index in the light array rendered into each shadow
+    /// map.
+    slots: Vec<(legion::Entity, u32)>,
+    /// Number of lights casting shadows left without a shadow map.
+    dropped: usize,
+}
+
+impl ShadowCasters {
+    /// Shadow map of the lights casting no shadows.
+    pub const NONE: u32 = u32::MAX;
+
+    /// Assigns at most `capacity` shadow maps to the lights, given in the
+    /// order of the light array. The following lights cast no shadows.
+    pub fn assign(
+        lights: &[(&legion::Entity, &Light, &NodeIdx)],
+        nodes: &Nodes,
+        capacity: usize,
+    ) -> Self {
+        let mut slots = lights
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, light, node_idx))| {
+                light.is_directional() && nodes[**node_idx].cast_shadows()
+            })
+            .map(|(index, (entity, _, _))| (**entity, index as u32))
+            .collect::<Vec<_>>();
+        let dropped = slots.len().saturating_sub(capacity);
+        slots.truncate(capacity);
+        Self { slots, dropped }
+    }
+
+    /// Returns the number of shadow maps.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Returns the number of lights casting shadows over the capacity.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Returns the shadow map of the light entity, if it casts shadows.
+    pub fn slot(&self, entity: legion::Entity) -> Option<u32> {
+        self.slots
+            .iter()
+            .position(|(e, _)| *e == entity)
+            .map(|slot| slot as u32)
+    }
+
+    /// Returns the shadow map of the light at the given index in the light
+    /// array, or `NONE`.
+    pub fn slot_of_light(&self, index: u32) -> u32 {
+        self.slots
+            .iter()
+            .position(|(_, i)| *i == index)
+            .map_or(Self::NONE, |slot| slot as u32)
+    }
+
+    /// Returns the index in the light array of the light rendered into each
+    /// shadow map.
+    pub fn lights(&self) -> impl Iterator<Item = u32> + '_ {
+        self.slots.iter().map(|(_, index)| *index)
+    }
 }
 
 /// Helper struct managing the shadow maps of the same size to minimize the
@@ -264,6 +428,9 @@ pub struct ShadowMaps {
 
     /// The number of layers per texture.
     layers_per_texture: u32,
+    /// Whether the textures are bound as a binding array, otherwise only the
+    /// first one is bound.
+    binding_arrays: bool,
 
     #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
     /// The storage buffers for the shadow maps. Each buffer stores the
@@ -280,12 +447,14 @@ impl ShadowMaps {
     /// * `width` - The width of the shadow maps.
     /// * `height` - The height of the shadow maps.
     /// * `count` - The number of shadow maps.
+    /// * `binding_arrays` - Whether the device supports binding arrays.
     pub fn new(
         device: &wgpu::Device,
         limits: &wgpu::Limits,
         width: u32,
         height: u32,
         count: u32,
+        binding_arrays: bool,
     ) -> Self {
         debug_assert!(
             width <= limits.max_texture_dimension_1d,
@@ -376,7 +545,11 @@ impl ShadowMaps {
                         view_dimension: wgpu::TextureViewDimension::D2Array,
                         sample_type: wgpu::TextureSampleType::Depth,
                     },
-                    count: NonZeroU32::new(n_textures),
+                    count: if binding_arrays {
+                        NonZeroU32::new(n_textures)
+                    } else {
+                        None
+                    },
                 },
                 wgpu::BindGroupLayoutEntry {
                     binding: 1,
@@ -399,7 +572,11 @@ impl ShadowMaps {
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
-                    resource: wgpu::BindingResource::TextureViewArray(&views),
+                    resource: if binding_arrays {
+                        wgpu::BindingResource::TextureViewArray(&views)
+                    } else {
+                        wgpu::BindingResource::TextureView(views[0])
+                    },
                 },
                 wgpu::BindGroupEntry {
                     binding: 1,
@@ -435,6 +612,7 @@ impl ShadowMaps {
             shadow_map_views,
             depth_sampler,
             layers_per_texture,
+            binding_arrays,
             #[cfg(all(debug_assertions, feature = "debug-shadow-map"))]
             storage_buffers,
         }
@@ -466,7 +644,7 @@ impl ShadowMaps {
             || self.shadow_map_size.1 != height
             || self.shadow_map_count != count
         {
-            *self = Self::new(device, limits, width, height, count);
+            *self = Self::new(device, limits, width, height, count, self.binding_arrays);
         }
     }
 
@@ -535,10 +713,143 @@ impl ShadowMaps {
     }
 }
 
+/// State of the renderer affecting the draw calls recorded in render bundles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawBundlesState {
+    pub shaders_generation: u64,
+    pub environment_generation: u64,
+    pub draws_generation: u64,
+    pub locals_capacity: u32,
+    pub shadow_map_count: u32,
+    pub enable_back_face_culling: bool,
+    pub enable_wireframe: bool,
+    pub casting_shadows: bool,
+    pub enable_lighting: bool,
+    pub depth_prepass: bool,
+}
+
+/// Identifies the draw calls of a batch of instances of the main pass.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DrawBundleKey {
+    /// Custom shader the instances are drawn with.
+    pub shader: Option<CustomShader>,
+    /// Mesh of the instances.
+    pub mesh: MeshBundle,
+    /// Range of the instances in the locals buffer.
+    pub instances: Range<u32>,
+}
+
+/// Draw calls of the main pass recorded in render bundles, reused across
+/// frames while the drawn instances and the bound resources don't change.
+#[derive(Default)]
+pub struct DrawBundles {
+    /// State of the renderer the bundles were recorded with.
+    pub state: Option<DrawBundlesState>,
+    pub bundles: FxHashMap<DrawBundleKey, wgpu::RenderBundle>,
+}
+
+/// Custom material shaders of the main pass, kept to create the pipelines
+/// reading the custom vertex attributes of the meshes drawn with them.
+#[derive(Default)]
+pub struct CustomShaderModules {
+    /// Layout of the pipelines of the main pass.
+    pub layout: Option<wgpu::PipelineLayout>,
+    /// Shader modules by the label of their pipelines.
+    pub modules: FxHashMap<String, wgpu::ShaderModule>,
+    /// Labels of the pipelines which failed to be created, so that they are
+    /// not created again each frame.
+    pub failed: FxHashSet<String>,
+}
+
+/// Arguments of the draws of the main pass issued with
+/// `multi_draw_indexed_indirect`, when the device supports it.
+pub struct IndirectDraws {
+    /// Buffer of the `wgpu::util::DrawIndexedIndirectArgs` of the draws.
+    pub buffer: wgpu::Buffer,
+    /// Buffer of the `DrawBounds` of the draws, for the GPU culling.
+    pub bounds: wgpu::Buffer,
+    /// Number of draws the buffers can hold.
+    pub capacity: u32,
+}
+
+impl IndirectDraws {
+    /// Features required to draw the sub-meshes of the instances of a mesh
+    /// with a single indirect call.
+    pub const FEATURES: wgpu::Features =
+        wgpu::Features::MULTI_DRAW_INDIRECT.union(wgpu::Features::INDIRECT_FIRST_INSTANCE);
+    /// Size of the arguments of a draw.
+    pub const ARGS_SIZE: u64 = std::mem::size_of::<wgpu::util::DrawIndexedIndirectArgs>() as u64;
+
+    pub fn new(device: &wgpu::Device) -> Self {
+        let capacity = 256;
+        let (buffer, bounds) = Self::create_buffers(device, capacity);
+        Self {
+            buffer,
+            bounds,
+            capacity,
+        }
+    }
+
+    /// Grows the buffers to hold at least `count` draws, dropping their
+    /// content.
+    pub fn resize(&mut self, device: &wgpu::Device, count: u32) {
+        if count > self.capacity {
+            self.capacity = count.next_power_of_two();
+            (self.buffer, self.bounds) = Self::create_buffers(device, self.capacity);
+        }
+    }
+
+    fn create_buffers(device: &wgpu::Device, capacity: u32) -> (wgpu::Buffer, wgpu::Buffer) {
+        // The arguments are written by the GPU culling.
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("blinn_phong_indirect_draws"),
+            size: capacity as u64 * Self::ARGS_SIZE,
+            usage: wgpu::BufferUsages::INDIRECT
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bounds = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("blinn_phong_indirect_bounds"),
+            size: capacity as u64 * std::mem::size_of::<DrawBounds>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        (buffer, bounds)
+    }
+}
+
+/// Bind groups of the main pass and of the shadow maps pass without binding
+/// arrays, packed into a few groups for the devices binding only 4 groups.
+///
+/// The main pass binds the resources shared by its draws in the frame group,
+/// followed by the group of the materials of each mesh with their textures,
+/// see [`material_slots_bind_group_layout`]. The shadow maps pass binds the
+/// instances and the lights in a group of its own, followed by the same
+/// group of the materials for the alpha-tested draws.
+pub struct SlotBindGroups {
+    /// Layout of the globals, environment map, instances, lights and shadow
+    /// maps of the main pass.
+    pub frame_layout: wgpu::BindGroupLayout,
+    /// Layout of the instances and lights of the shadow maps pass.
+    pub shadow_layout: wgpu::BindGroupLayout,
+    /// Layout of the materials of a mesh with their textures.
+    pub materials_layout: wgpu::BindGroupLayout,
+    /// Frame group of the main pass, created again with the draw bundles
+    /// when the resources it binds change.
+    pub frame: Option<wgpu::BindGroup>,
+    /// Group of the shadow maps pass, with the capacity of the locals buffer
+    /// it binds.
+    pub shadow: Option<(u32, wgpu::BindGroup)>,
+}
+
 /// The render pass for the blinn-phong shading.
 pub struct BlinnPhongRenderPass {
     /// The depth attachment.
     pub depth_att: Option<(wgpu::Texture, wgpu::TextureView)>,
+    /// The multisampled color attachment resolved into the render target,
+    /// `None` if the depth buffer is not multisampled.
+    pub msaa_att: Option<(wgpu::Texture, wgpu::TextureView)>,
     /// The global uniforms bind group.
     pub globals_bind_group: GlobalsBindGroup,
     /// The local information (per entity/instance) bind group for visible
@@ -553,8 +864,78 @@ pub struct BlinnPhongRenderPass {
     pub lights_bind_group: LightsBindGroup,
     /// The shadow maps.
     pub shadow_maps: ShadowMaps,
+    /// The lights rendered into the shadow maps.
+    pub shadow_casters: ShadowCasters,
     /// The pipelines.
     pub pipelines: Pipelines,
+    /// Custom material shaders the pipelines were created from.
+    pub custom_shaders: CustomShaderModules,
+    /// The format of the render target.
+    pub format: wgpu::TextureFormat,
+    /// The depth buffer of the main pass.
+    pub depth: DepthSettings,
+    /// Whether the adapter only supports constant sized binding arrays.
+    pub constant_sized_binding_array: bool,
+    /// Bind groups packed with the textures bound one by one in a few slots,
+    /// if the adapter doesn't support binding arrays.
+    pub slots: Option<SlotBindGroups>,
+    /// Constants of the draws of the main pass.
+    pub constants: DrawConstants,
+    /// Constants of the draws of the shadow maps pass.
+    pub shadow_constants: DrawConstants,
+    /// Constants of the draws of the depth pre-pass.
+    pub depth_constants: DrawConstants,
+    /// Empty bind group and its layout, bound to the groups of the materials
+    /// and textures by the shadow maps pipelines which don't read them, when
+    /// the constants are read from the following group.
+    pub empty_bind_group: (wgpu::BindGroupLayout, wgpu::BindGroup),
+    /// Whether the lights casting shadows over the capacity of the shadow
+    /// maps have already been reported.
+    pub warned_shadow_casters: bool,
+    /// Generation of the shaders the pipelines were created from.
+    pub shaders_generation: u64,
+    /// Generation of the environment map bound to the globals.
+    pub environment_generation: u64,
+    /// The sprite pass drawn after the main pass.
+    pub sprites: SpriteRenderPass,
+    /// The handles of the manipulator gizmo drawn over everything.
+    pub gizmo: GizmoRenderPass,
+    /// The minimap drawn over the gizmo in a corner of the window.
+    pub minimap: MinimapRenderPass,
+    /// The particle pass simulating and drawing particle emitters.
+    pub particles: ParticleRenderPass,
+    /// The water pass drawn after the main pass.
+    pub water: WaterRenderPass,
+    /// The backdrop drawn at the start of the main pass.
+    pub background: BackgroundRenderPass,
+    /// Culling of the instances hidden in the main pass.
+    pub occlusion: OcclusionCulling,
+    /// Exposure of the main pass, adapted to the frames if enabled.
+    pub exposure: ExposureMeter,
+    /// Transient textures of the render graph of the frames.
+    pub transients: TransientTextures,
+    /// Draw calls of the main pass recorded in the previous frames.
+    pub draw_bundles: DrawBundles,
+    /// Indirect draws of the main pass, `None` if the device doesn't
+    /// support them.
+    pub indirect: Option<IndirectDraws>,
+    /// Culling of the indirect draws on the GPU, `None` if the device doesn't
+    /// support indirect draws.
+    pub culling: Option<GpuCulling>,
+    /// Staging buffers through which the per-frame data is uploaded.
+    pub staging: StagingRing,
+    /// Staging buffers of the shadow maps pass, which is encoded on its own
+    /// thread.
+    pub shadow_staging: StagingRing,
+    /// Region of the render target the main camera renders to.
+    pub viewport: Viewport,
+    /// Camera the pass renders from, the main camera of the scene if `None`.
+    pub camera: Option<legion::Entity>,
+    /// Whether the pass renders the main camera mirrored below the
+    /// reflective water surface, see [`WaterRenderPass::reflection_plane`].
+    pub reflection: bool,
+    /// Time at which the previous frame was rendered, to adapt the exposure.
+    pub last_frame: Instant,
 }
 
 impl BlinnPhongRenderPass {
@@ -562,10 +943,75 @@ impl BlinnPhongRenderPass {
     pub const MAX_DIR_LIGHTS: usize = 64;
     /// Maximum number of point lights.
     pub const MAX_PNT_LIGHTS: usize = 448;
-    /// Maximum number of lights.
+    /// Maximum number of lights, fewer if the storage buffers of the device
+    /// can't hold them, see [`Self::max_lights`].
     pub const MAX_LIGHTS: usize = Self::MAX_DIR_LIGHTS + Self::MAX_PNT_LIGHTS;
-    /// Maximum number of textures in a texture binding array.
+    /// Maximum number of textures in a texture binding array when the
+    /// adapter only supports constant sized binding arrays.
     pub const MAX_TEXTURE_ARRAY_LEN: usize = 64;
+    /// Maximum number of textures in a texture binding array when the
+    /// adapter supports runtime sized binding arrays.
+    pub const MAX_BINDLESS_TEXTURE_ARRAY_LEN: usize = 1024;
     /// Maximum number of texture sampler in a texture sampler bindingr array.
     pub const MAX_SAMPLER_ARRAY_LEN: usize = 8;
+    /// Number of textures bound one by one, each with its sampler, when the
+    /// adapter doesn't support binding arrays.
+    pub const MAX_TEXTURE_SLOTS: usize = 8;
+    /// Render target of the frame, in the render graph of the passes.
+    pub const TARGET: GraphResource = GraphResource("target");
+    /// Depth buffer shared by the passes, in the render graph.
+    pub const DEPTH: GraphResource = GraphResource("depth");
+    /// Occlusion queries of the frame, read back by the next frames.
+    pub const OCCLUSION_QUERIES: GraphResource = GraphResource("occlusion_queries");
+    /// Vertex attributes read by the main pass, each bound to the vertex
+    /// buffer of its index. The custom attributes of the meshes drawn with
+    /// custom shaders are bound to the following vertex buffers.
+    pub const BUILTIN_ATTRIBUTES: [VertexAttribute; 5] = [
+        VertexAttribute::POSITION,
+        VertexAttribute::NORMAL,
+        VertexAttribute::UV,
+        VertexAttribute::TANGENT,
+        VertexAttribute::COLOR,
+    ];
+    /// Number of instances from which the locals of the main pass are
+    /// gathered on multiple threads.
+    pub const PARALLEL_LOCALS_THRESHOLD: usize = 4096;
+    /// Number of draw bundles of the main pass from which they are recorded
+    /// on multiple threads.
+    pub const PARALLEL_BUNDLES_THRESHOLD: usize = 64;
+
+    /// Returns the number of lights fitting in the storage buffer of the
+    /// lights, at most [`Self::MAX_LIGHTS`].
+    pub fn max_lights(context: &GpuContext) -> usize {
+        let size = context.limits.max_storage_buffer_binding_size as usize;
+        (size.saturating_sub(LightArray::HEADER_SIZE) / GpuLight::SIZE).min(Self::MAX_LIGHTS)
+    }
+
+    /// Returns the number of textures in the global texture binding array
+    /// shared by all materials.
+    ///
+    /// The runtime sized array leaves room for the other textures sampled by
+    /// the fragment stage, see [`Self::reserved_sampled_textures`].
+    pub fn texture_array_len(context: &GpuContext) -> u32 {
+        if !context.binding_arrays {
+            Self::MAX_TEXTURE_SLOTS as u32
+        } else if context.constant_sized_binding_array {
+            Self::MAX_TEXTURE_ARRAY_LEN as u32
+        } else {
+            context
+                .limits
+                .max_sampled_textures_per_shader_stage
+                .saturating_sub(Self::reserved_sampled_textures(context))
+                .min(Self::MAX_BINDLESS_TEXTURE_ARRAY_LEN as u32)
+        }
+    }
+
+    /// Returns the number of textures sampled by the fragment stage of the
+    /// main pass besides the material textures: the environment map and the
+    /// shadow map arrays of the lights.
+    pub fn reserved_sampled_textures(context: &GpuContext) -> u32 {
+        let shadow_textures = (Self::max_lights(context) as u32)
+            .div_ceil(context.limits.max_texture_array_layers.max(1));
+        1 + shadow_textures
+    }
 }
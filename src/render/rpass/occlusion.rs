@@ -0,0 +1,348 @@
+use crate::{
+    core::FxHashSet,
+    render::{DepthSettings, Renderer, StagingRing, Viewport},
+    scene::NodeIdx,
+};
+use glam::{Mat4, Vec3, Vec4};
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+
+/// No query results are in flight, new queries can be issued.
+const READBACK_IDLE: u8 = 0;
+/// Query results have been copied to the readback buffer by the last frame.
+const READBACK_COPIED: u8 = 1;
+/// The readback buffer is being mapped.
+const READBACK_MAPPING: u8 = 2;
+/// The readback buffer is mapped and can be read.
+const READBACK_MAPPED: u8 = 3;
+
+/// Culls the instances hidden behind the geometry with hardware occlusion
+/// queries.
+///
+/// After the main pass, the bounding box of each instance inside the view
+/// frustum is tested against the depth buffer. The results are read back a
+/// few frames later, then the instances whose box has no visible sample are
+/// skipped by the main pass until a later query finds them visible again.
+pub struct OcclusionCulling {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Box to clip space transforms of the queried instances.
+    boxes: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+    /// Number of queries the buffers can hold.
+    capacity: u32,
+    state: Arc<AtomicU8>,
+    /// Instances queried by the queries in flight, in order.
+    queried: Vec<NodeIdx>,
+    /// Instances found occluded by the last results.
+    occluded: FxHashSet<NodeIdx>,
+    /// View-projection matrix and position of the camera of the frame.
+    camera: Option<(Mat4, Vec3)>,
+}
+
+impl OcclusionCulling {
+    /// Creates the occlusion culling of the main pass, testing against its
+    /// depth buffer.
+    pub fn new(device: &wgpu::Device, depth: DepthSettings) -> Self {
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("occlusion_bind_group_layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("occlusion_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("occlusion.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("occlusion_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("occlusion_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[],
+            },
+            // Only the depth test matters.
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                cull_mode: None,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.texture_format(),
+                depth_write_enabled: false,
+                depth_compare: depth.compare(),
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: depth.multisample(),
+            multiview: None,
+            cache: None,
+        });
+
+        let capacity = 256;
+        let (boxes, bind_group, query_set, resolve, readback) =
+            Self::create_buffers(device, &bind_group_layout, capacity);
+        Self {
+            pipeline,
+            bind_group_layout,
+            boxes,
+            bind_group,
+            query_set,
+            resolve,
+            readback,
+            capacity,
+            state: Arc::new(AtomicU8::new(READBACK_IDLE)),
+            queried: Vec::new(),
+            occluded: FxHashSet::default(),
+            camera: None,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn create_buffers(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        capacity: u32,
+    ) -> (
+        wgpu::Buffer,
+        wgpu::BindGroup,
+        wgpu::QuerySet,
+        wgpu::Buffer,
+        wgpu::Buffer,
+    ) {
+        let boxes = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occlusion_boxes_buffer"),
+            size: capacity as u64 * std::mem::size_of::<[f32; 16]>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("occlusion_bind_group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: boxes.as_entire_binding(),
+            }],
+        });
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("occlusion_query_set"),
+            ty: wgpu::QueryType::Occlusion,
+            count: capacity,
+        });
+        let size = capacity as u64 * std::mem::size_of::<u64>() as u64;
+        let resolve = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occlusion_resolve_buffer"),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("occlusion_readback_buffer"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        (boxes, bind_group, query_set, resolve, readback)
+    }
+
+    /// Returns true if the instance was occluded by the last results.
+    pub fn is_occluded(&self, node: NodeIdx) -> bool {
+        self.occluded.contains(&node)
+    }
+
+    /// Forgets the results, all the instances are drawn.
+    pub fn clear(&mut self) {
+        self.occluded.clear();
+        self.camera = None;
+    }
+
+    /// Sets the camera of the frame.
+    pub fn set_camera(&mut self, view_proj: Mat4, position: Vec3) {
+        self.camera = Some((view_proj, position));
+    }
+
+    /// Reads the results of the queries issued a few frames ago, if they are
+    /// available. Must be called before recording the main pass.
+    pub fn fetch_results(&mut self, device: &wgpu::Device) {
+        match self.state.load(Ordering::Acquire) {
+            READBACK_COPIED => {
+                // The commands copying the results have been submitted.
+                self.state.store(READBACK_MAPPING, Ordering::Release);
+                let state = self.state.clone();
+                self.readback.slice(..).map_async(
+                    wgpu::MapMode::Read,
+                    move |result| match result {
+                        Ok(_) => state.store(READBACK_MAPPED, Ordering::Release),
+                        Err(err) => {
+                            log::error!("Failed to read back occlusion queries: {}", err);
+                            state.store(READBACK_IDLE, Ordering::Release);
+                        }
+                    },
+                );
+                device.poll(wgpu::Maintain::Poll);
+            }
+            READBACK_MAPPING => {
+                device.poll(wgpu::Maintain::Poll);
+            }
+            READBACK_MAPPED => {
+                {
+                    let size = self.queried.len() as u64 * std::mem::size_of::<u64>() as u64;
+                    let data = self.readback.slice(..size).get_mapped_range();
+                    let samples: &[u64] = bytemuck::cast_slice(&data);
+                    self.occluded = self
+                        .queried
+                        .iter()
+                        .zip(samples)
+                        .filter(|(_, samples)| **samples == 0)
+                        .map(|(node, _)| *node)
+                        .collect();
+                }
+                self.readback.unmap();
+                self.state.store(READBACK_IDLE, Ordering::Release);
+            }
+            _ => {}
+        }
+    }
+
+    /// Tests the bounding boxes, as (node, model matrix, local bounds), of the
+    /// instances against the depth buffer of the main pass.
+    ///
+    /// Instances outside of the view frustum or containing the camera are not
+    /// tested and are considered visible. Nothing is done while the results of
+    /// previous queries are in flight.
+    pub fn query(
+        &mut self,
+        renderer: &Renderer,
+        staging: &mut StagingRing,
+        encoder: &mut wgpu::CommandEncoder,
+        depth_view: &wgpu::TextureView,
+        viewport: &Viewport,
+        instances: &[(NodeIdx, Mat4, (Vec3, Vec3))],
+    ) {
+        profiling::scope!("OcclusionCulling::query");
+        let Some((view_proj, camera)) = self.camera else {
+            return;
+        };
+        if self.state.load(Ordering::Acquire) != READBACK_IDLE {
+            return;
+        }
+
+        self.queried.clear();
+        let mut boxes = Vec::new();
+        for (node, model, (min, max)) in instances {
+            let center = (*min + *max) * 0.5;
+            // Slightly enlarged so that the box is not hidden by the instance.
+            let half = (*max - *min) * 0.5 * 1.01 + Vec3::splat(1e-3);
+            let local_camera = model.inverse().transform_point3(camera);
+            if ((local_camera - center).abs() - half * 1.1 - Vec3::splat(0.1))
+                .max_element()
+                .is_sign_negative()
+            {
+                continue;
+            }
+            let to_clip =
+                view_proj * *model * Mat4::from_translation(center) * Mat4::from_scale(half);
+            if is_outside_frustum(&to_clip) {
+                continue;
+            }
+            self.queried.push(*node);
+            boxes.push(to_clip.to_cols_array());
+        }
+        // Instances that are not queried anymore are visible.
+        let queried = self.queried.iter().copied().collect::<FxHashSet<_>>();
+        self.occluded.retain(|node| queried.contains(node));
+        if boxes.is_empty() {
+            return;
+        }
+
+        if boxes.len() as u32 > self.capacity {
+            self.capacity = (boxes.len() as u32).next_power_of_two();
+            (
+                self.boxes,
+                self.bind_group,
+                self.query_set,
+                self.resolve,
+                self.readback,
+            ) = Self::create_buffers(&renderer.device, &self.bind_group_layout, self.capacity);
+        }
+        staging.write(
+            &renderer.device,
+            encoder,
+            &self.boxes,
+            0,
+            bytemuck::cast_slice(&boxes),
+        );
+
+        let n_queries = boxes.len() as u32;
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("occlusion_render_pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: Some(&self.query_set),
+            });
+            render_pass.set_pipeline(&self.pipeline);
+            viewport.apply(&mut render_pass);
+            render_pass.set_bind_group(0, &self.bind_group, &[]);
+            for i in 0..n_queries {
+                render_pass.begin_occlusion_query(i);
+                render_pass.draw(0..36, i..i + 1);
+                render_pass.end_occlusion_query();
+            }
+        }
+        encoder.resolve_query_set(&self.query_set, 0..n_queries, &self.resolve, 0);
+        let size = n_queries as u64 * std::mem::size_of::<u64>() as u64;
+        encoder.copy_buffer_to_buffer(&self.resolve, 0, &self.readback, 0, size);
+        self.state.store(READBACK_COPIED, Ordering::Release);
+    }
+}
+
+/// Returns true if the unit cube transformed to the clip space by `to_clip`
+/// is entirely outside of one of the planes of the view frustum.
+fn is_outside_frustum(to_clip: &Mat4) -> bool {
+    let corners = (0..8).map(|i| {
+        *to_clip
+            * Vec4::new(
+                if i & 1 == 0 { -1.0 } else { 1.0 },
+                if i & 2 == 0 { -1.0 } else { 1.0 },
+                if i & 4 == 0 { -1.0 } else { 1.0 },
+                1.0,
+            )
+    });
+    let corners = corners.collect::<Vec<_>>();
+    let outside = |plane: fn(&Vec4) -> bool| corners.iter().all(plane);
+    outside(|c| c.x < -c.w)
+        || outside(|c| c.x > c.w)
+        || outside(|c| c.y < -c.w)
+        || outside(|c| c.y > c.w)
+        || outside(|c| c.z < 0.0)
+        || outside(|c| c.z > c.w)
+}
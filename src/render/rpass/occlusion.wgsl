@@ -0,0 +1,23 @@
+// Transforms from the unit cube [-1, 1]^3 to the clip space, one per box.
+@group(0) @binding(0) var<storage, read> boxes: array<mat4x4<f32>>;
+
+// Triangles of the unit cube, as indices into its corners.
+var<private> CUBE_INDICES: array<u32, 36> = array<u32, 36>(
+    0u, 2u, 1u, 1u, 2u, 3u, // -Z
+    4u, 5u, 6u, 5u, 7u, 6u, // +Z
+    0u, 1u, 4u, 1u, 5u, 4u, // -Y
+    2u, 6u, 3u, 3u, 6u, 7u, // +Y
+    0u, 4u, 2u, 2u, 4u, 6u, // -X
+    1u, 3u, 5u, 3u, 7u, 5u, // +X
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) box_index: u32) -> @builtin(position) vec4<f32> {
+    let corner = CUBE_INDICES[vertex_index];
+    let pos = vec3<f32>(
+        f32(corner & 1u) * 2.0 - 1.0,
+        f32((corner >> 1u) & 1u) * 2.0 - 1.0,
+        f32((corner >> 2u) & 1u) * 2.0 - 1.0,
+    );
+    return boxes[box_index] * vec4<f32>(pos, 1.0);
+}
@@ -0,0 +1,403 @@
+use crate::{
+    core::{particle::ParticleEmitter, FxHashMap},
+    render::{
+        rpass::{scene_color_attachment, GlobalsBindGroup},
+        DepthSettings, RenderTarget, Renderer, StagingRing, Viewport,
+    },
+    scene::{NodeIdx, RenderLayer, Scene},
+};
+use bytemuck::{Pod, Zeroable};
+use legion::IntoQuery;
+
+/// Parameters of an emitter passed to the particle shaders.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct GpuEmitterParams {
+    /// Position of the emitter in world space, w is the particle size.
+    pub origin: [f32; 4],
+    /// Mean initial velocity, w is the random variation.
+    pub velocity: [f32; 4],
+    /// Constant acceleration, w is the life time of new particles.
+    pub gravity: [f32; 4],
+    /// Color of the particles when spawned.
+    pub start_color: [f32; 4],
+    /// Color of the particles when they die.
+    pub end_color: [f32; 4],
+    /// Time step of the simulation in seconds.
+    pub dt: f32,
+    /// Index of the first particle to spawn in this frame.
+    pub spawn_start: u32,
+    /// Number of particles to spawn in this frame.
+    pub spawn_count: u32,
+    /// Seed of the random number generator.
+    pub seed: u32,
+}
+
+crate::impl_size_constant!(GpuEmitterParams);
+
+/// Size of a particle in the particle buffer: position, age, velocity and
+/// life time.
+const PARTICLE_SIZE: u64 = 8 * std::mem::size_of::<f32>() as u64;
+
+/// GPU resources of a particle emitter. The particle buffer is used as a
+/// ring buffer and kept alive by the bind groups.
+struct EmitterState {
+    /// The uniform buffer of the emitter parameters.
+    params: wgpu::Buffer,
+    /// Bind group used by the update pass.
+    update_bind_group: wgpu::BindGroup,
+    /// Bind group used by the render pass.
+    render_bind_group: wgpu::BindGroup,
+    /// Capacity of the particle buffer.
+    max_particles: u32,
+    /// Index of the next particle to spawn.
+    next_spawn: u32,
+    /// Fractional number of particles left to spawn.
+    spawn_accum: f32,
+    /// Whether the emitter is simulated in the current frame.
+    visible: bool,
+    /// Whether the emitter is on a layer drawn by the camera of the pass.
+    in_layer: bool,
+}
+
+impl EmitterState {
+    /// Returns whether the particles of the emitter are drawn in the current
+    /// frame.
+    fn is_drawn(&self) -> bool {
+        self.visible && self.in_layer
+    }
+}
+
+/// Particle simulation and rendering.
+///
+/// Particles of each emitter are updated by a compute shader every frame, then
+/// drawn as camera-facing quads with alpha blending after the opaque
+/// geometry.
+pub struct ParticleRenderPass {
+    /// The compute pipeline updating the particles.
+    update_pipeline: wgpu::ComputePipeline,
+    /// The render pipeline drawing the particles.
+    render_pipeline: wgpu::RenderPipeline,
+    /// The bind group layout of the update pass.
+    update_layout: wgpu::BindGroupLayout,
+    /// The bind group layout of the render pass.
+    render_layout: wgpu::BindGroupLayout,
+    /// GPU resources of each emitter.
+    emitters: FxHashMap<legion::Entity, EmitterState>,
+    /// Frame counter used to seed the random number generator.
+    frame: u32,
+}
+
+impl ParticleRenderPass {
+    /// Number of threads in a workgroup of the update shader.
+    const WORKGROUP_SIZE: u32 = 64;
+    /// Maximum time step, avoids bursts of particles after a stall.
+    const MAX_TIME_STEP: f32 = 0.1;
+
+    /// Creates a new particle render pass.
+    pub fn new(
+        device: &wgpu::Device,
+        globals_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        depth: DepthSettings,
+    ) -> Self {
+        let bind_group_layout = |label, visibility, read_only| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(PARTICLE_SIZE),
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: GpuEmitterParams::BUFFER_SIZE,
+                        },
+                        count: None,
+                    },
+                ],
+            })
+        };
+        let update_layout = bind_group_layout(
+            "particle_update_bind_group_layout",
+            wgpu::ShaderStages::COMPUTE,
+            false,
+        );
+        let render_layout = bind_group_layout(
+            "particle_render_bind_group_layout",
+            wgpu::ShaderStages::VERTEX,
+            true,
+        );
+
+        let update_pipeline = {
+            let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("particle_update_shader_module"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("particle_update.wgsl").into()),
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("particle_update_pipeline_layout"),
+                bind_group_layouts: &[&update_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("particle_update_pipeline"),
+                layout: Some(&layout),
+                module: &shader_module,
+                entry_point: Some("cs_update"),
+                compilation_options: Default::default(),
+                cache: None,
+            })
+        };
+
+        let render_pipeline = {
+            let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("particle_shader_module"),
+                source: wgpu::ShaderSource::Wgsl(include_str!("particle.wgsl").into()),
+            });
+            let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("particle_pipeline_layout"),
+                bind_group_layouts: &[globals_layout, &render_layout],
+                push_constant_ranges: &[],
+            });
+            device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some("particle_pipeline"),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &shader_module,
+                    entry_point: Some("vs_main"),
+                    compilation_options: Default::default(),
+                    buffers: &[],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &shader_module,
+                    entry_point: Some("fs_main"),
+                    compilation_options: Default::default(),
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format,
+                        blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleList,
+                    front_face: wgpu::FrontFace::Ccw,
+                    cull_mode: None,
+                    polygon_mode: wgpu::PolygonMode::Fill,
+                    ..Default::default()
+                },
+                depth_stencil: Some(wgpu::DepthStencilState {
+                    format: depth.texture_format(),
+                    depth_write_enabled: false,
+                    depth_compare: depth.compare(),
+                    stencil: Default::default(),
+                    bias: Default::default(),
+                }),
+                multisample: depth.multisample(),
+                multiview: None,
+                cache: None,
+            })
+        };
+
+        Self {
+            update_pipeline,
+            render_pipeline,
+            update_layout,
+            render_layout,
+            emitters: FxHashMap::default(),
+            frame: 0,
+        }
+    }
+
+    /// Creates the GPU resources of an emitter.
+    fn create_emitter_state(&self, device: &wgpu::Device, max_particles: u32) -> EmitterState {
+        let particles = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle_buffer"),
+            size: PARTICLE_SIZE * max_particles as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            // Zero-initialized particles are dead as their life time is zero.
+            mapped_at_creation: false,
+        });
+        let params = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle_emitter_params_buffer"),
+            size: GpuEmitterParams::SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = |label, layout| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(label),
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: particles.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: params.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let update_bind_group = bind_group("particle_update_bind_group", &self.update_layout);
+        let render_bind_group = bind_group("particle_render_bind_group", &self.render_layout);
+        EmitterState {
+            params,
+            update_bind_group,
+            render_bind_group,
+            max_particles,
+            next_spawn: 0,
+            spawn_accum: 0.0,
+            visible: false,
+            in_layer: false,
+        }
+    }
+
+    /// Spawns new particles and simulates all particles of the visible
+    /// emitters. Only the emitters on the layers of the layer mask are
+    /// drawn.
+    pub fn update(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        staging: &mut StagingRing,
+        scene: &Scene,
+        renderer: &Renderer,
+        layer_mask: u32,
+    ) {
+        profiling::scope!("ParticleRenderPass::update");
+        let dt = renderer.time().delta.min(Self::MAX_TIME_STEP);
+        self.frame = self.frame.wrapping_add(1);
+
+        let mut query = <(
+            legion::Entity,
+            &ParticleEmitter,
+            &NodeIdx,
+            Option<&RenderLayer>,
+        )>::query();
+        // Release the resources of removed emitters.
+        self.emitters
+            .retain(|entity, _| query.get(&scene.world, *entity).is_ok());
+
+        // Upload the parameters of the visible emitters before simulating
+        // them in a single compute pass.
+        let mut simulated = Vec::new();
+        for (i, (entity, emitter, node_idx, layer)) in query.iter(&scene.world).enumerate() {
+            let node = &scene.nodes[*node_idx];
+            let needs_recreate = self
+                .emitters
+                .get(entity)
+                .map_or(true, |state| state.max_particles != emitter.max_particles);
+            if needs_recreate {
+                let state = self.create_emitter_state(&renderer.device, emitter.max_particles);
+                self.emitters.insert(*entity, state);
+            }
+            let state = self.emitters.get_mut(entity).unwrap();
+            state.visible = node.is_visible();
+            state.in_layer = layer.copied().unwrap_or_default().is_in(layer_mask);
+            if !state.visible {
+                continue;
+            }
+
+            // Spawn new particles only if the emitter is active.
+            let mut spawn_count = 0;
+            if node.is_active() {
+                state.spawn_accum += emitter.rate * dt;
+                spawn_count = (state.spawn_accum as u32).min(state.max_particles);
+                state.spawn_accum -= spawn_count as f32;
+            }
+
+            let origin = scene.nodes.world(*node_idx).translation;
+            let [vx, vy, vz] = emitter.velocity;
+            let [gx, gy, gz] = emitter.gravity;
+            let params = GpuEmitterParams {
+                origin: [origin.x, origin.y, origin.z, emitter.size],
+                velocity: [vx, vy, vz, emitter.velocity_spread],
+                gravity: [gx, gy, gz, emitter.lifetime],
+                start_color: emitter.start_color.into(),
+                end_color: emitter.end_color.into(),
+                dt,
+                spawn_start: state.next_spawn,
+                spawn_count,
+                seed: self.frame.wrapping_mul(0x9e37_79b9) ^ (i as u32).wrapping_mul(0x85eb_ca6b),
+            };
+            state.next_spawn = (state.next_spawn + spawn_count) % state.max_particles;
+            staging.write(
+                &renderer.device,
+                encoder,
+                &state.params,
+                0,
+                bytemuck::bytes_of(&params),
+            );
+            simulated.push(*entity);
+        }
+
+        let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some("particle_update_pass"),
+            timestamp_writes: None,
+        });
+        compute_pass.set_pipeline(&self.update_pipeline);
+        for entity in simulated {
+            let state = &self.emitters[&entity];
+            compute_pass.set_bind_group(0, &state.update_bind_group, &[]);
+            compute_pass.dispatch_workgroups(
+                (state.max_particles + Self::WORKGROUP_SIZE - 1) / Self::WORKGROUP_SIZE,
+                1,
+                1,
+            );
+        }
+    }
+
+    /// Draws the particles of the visible emitters on the layers of the
+    /// camera. The globals must be
+    /// already updated by the main pass.
+    pub fn record(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RenderTarget,
+        msaa_view: Option<&wgpu::TextureView>,
+        globals: &GlobalsBindGroup,
+        depth_view: &wgpu::TextureView,
+        viewport: &Viewport,
+    ) {
+        profiling::scope!("ParticleRenderPass::record");
+        if !self.emitters.values().any(EmitterState::is_drawn) {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("particle_render_pass"),
+            color_attachments: &[Some(scene_color_attachment(
+                target,
+                msaa_view,
+                wgpu::LoadOp::Load,
+            ))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.render_pipeline);
+        viewport.apply(&mut render_pass);
+        render_pass.set_bind_group(0, globals, &[]);
+        for state in self.emitters.values().filter(|state| state.is_drawn()) {
+            render_pass.set_bind_group(1, &state.render_bind_group, &[]);
+            render_pass.draw(0..6, 0..state.max_particles);
+        }
+    }
+}
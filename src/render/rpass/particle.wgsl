@@ -0,0 +1,79 @@
+/// Camera data.
+struct Globals {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    time: f32,
+    delta_time: f32,
+}
+
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+}
+
+struct EmitterParams {
+    origin: vec4<f32>,
+    velocity: vec4<f32>,
+    gravity: vec4<f32>,
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    dt: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    seed: u32,
+}
+
+struct VSOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) corner: vec2<f32>,
+    @location(1) color: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var<storage, read> particles: array<Particle>;
+@group(1) @binding(1) var<uniform> params: EmitterParams;
+
+// Corners of the quad made of two triangles.
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, 0.5),
+);
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex_index: u32, @builtin(instance_index) instance_index: u32) -> VSOutput {
+    let p = particles[instance_index];
+    var vout: VSOutput;
+    if (p.age >= p.lifetime) {
+        // Dead particle, emit a degenerate triangle.
+        vout.position = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+        return vout;
+    }
+
+    let corner = CORNERS[vertex_index];
+    let right = vec3<f32>(globals.view[0].x, globals.view[1].x, globals.view[2].x);
+    let up = vec3<f32>(globals.view[0].y, globals.view[1].y, globals.view[2].y);
+    let size = params.origin.w;
+    let position = p.position + (right * corner.x + up * corner.y) * size;
+
+    vout.position = globals.proj * globals.view * vec4<f32>(position, 1.0);
+    vout.corner = corner;
+    vout.color = mix(params.start_color, params.end_color, p.age / p.lifetime);
+    return vout;
+}
+
+@fragment
+fn fs_main(vout: VSOutput) -> @location(0) vec4<f32> {
+    // Soft round particle.
+    let d = length(vout.corner) * 2.0;
+    if (d > 1.0) {
+        discard;
+    }
+    return vec4<f32>(vout.color.rgb, vout.color.a * (1.0 - d * d));
+}
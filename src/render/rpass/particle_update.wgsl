@@ -0,0 +1,63 @@
+struct Particle {
+    position: vec3<f32>,
+    age: f32,
+    velocity: vec3<f32>,
+    lifetime: f32,
+}
+
+struct EmitterParams {
+    /// Position of the emitter in world space, w is the particle size.
+    origin: vec4<f32>,
+    /// Mean initial velocity, w is the random variation.
+    velocity: vec4<f32>,
+    /// Constant acceleration, w is the life time of new particles.
+    gravity: vec4<f32>,
+    start_color: vec4<f32>,
+    end_color: vec4<f32>,
+    dt: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    seed: u32,
+}
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> params: EmitterParams;
+
+// PCG hash, see https://www.jcgt.org/published/0009/03/02/
+fn pcg_hash(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// Random number in [-1, 1].
+fn random_signed(seed: ptr<function, u32>) -> f32 {
+    *seed = pcg_hash(*seed);
+    return f32(*seed) / 2147483647.5 - 1.0;
+}
+
+@compute @workgroup_size(64)
+fn cs_update(@builtin(global_invocation_id) id: vec3<u32>) {
+    let n = arrayLength(&particles);
+    let i = id.x;
+    if (i >= n) {
+        return;
+    }
+
+    var p = particles[i];
+    // Index of the particle relative to the first particle to spawn.
+    let rel = (i + n - params.spawn_start) % n;
+    if (rel < params.spawn_count) {
+        var seed = pcg_hash(i ^ params.seed);
+        let jitter = vec3<f32>(random_signed(&seed), random_signed(&seed), random_signed(&seed));
+        p.position = params.origin.xyz;
+        p.velocity = params.velocity.xyz + jitter * params.velocity.w;
+        p.age = 0.0;
+        p.lifetime = params.gravity.w;
+    } else if (p.age < p.lifetime) {
+        p.velocity += params.gravity.xyz * params.dt;
+        p.position += p.velocity * params.dt;
+        p.age += params.dt;
+    }
+    particles[i] = p;
+}
@@ -1,3 +1,8 @@
+const INVALID_INDEX: u32 = 0xffffffffu;
+/// Alpha of the diffuse and opacity maps under which the surfaces are cut
+/// out, as in the main pass.
+const ALPHA_CUTOFF: f32 = 0.5;
+
 struct Locals {
     model: mat4x4<f32>,
 }
@@ -5,6 +10,8 @@ struct Locals {
 struct PConsts {
     instance_base_index: u32,
     light_index: u32,
+    /// Material of the drawn sub-mesh, only read by the alpha-tested draws.
+    material_index: u32,
 }
 
 struct Light {
@@ -13,6 +20,8 @@ struct Light {
     dir_or_pos: vec4<f32>,
     /// Color/intensity of light.
     color: vec3<f32>,
+    /// Shadow map of the light.
+    shadow_map: u32,
     /// Matrix transforming from world space to light space.
     world_to_light: mat4x4<f32>,
 }
@@ -22,10 +31,91 @@ struct LightArray {
     data: array<Light>,
 }
 
+/// Material of the main pass, see blph.wgsl.
+struct Material {
+    ka: vec4<f32>,
+    kd: vec4<f32>,
+    ks: vec4<f32>,
+    ns: f32,
+    ni: f32,
+    d: f32,
+    illum: u32,
+    map_ka: u32,
+    map_kd: u32,
+    map_ks: u32,
+    map_ns: u32,
+    map_d: u32,
+    map_bump: u32,
+    map_disp: u32,
+    map_decal: u32,
+    map_norm: u32,
+    reflectivity: f32,
+    uv_rotation: f32,
+    uv_offset: vec2<f32>,
+    uv_scale: vec2<f32>,
+    ke: vec4<f32>,
+    map_ke: u32,
+    map_pr: u32,
+    map_pm: u32,
+    map_ps: u32,
+    pr: f32,
+    pm: f32,
+    ps: f32,
+    flags: u32,
+}
+
+// #if !texture_slots
 @group(0) @binding(0) var<storage, read> instances: array<Locals>;
 @group(1) @binding(0) var<storage, read> lights: LightArray;
+// Only bound for the alpha-tested draws.
+@group(2) @binding(0) var<storage, read> materials: array<Material>;
+// #fi
+// #if runtime_sized_binding_array
+@group(3) @binding(0) var textures: binding_array<texture_2d<f32>>;
+@group(3) @binding(1) var<storage, read> texture_sampler_ids: array<u32>;
+@group(3) @binding(2) var samplers: binding_array<sampler>;
+// #fi
+// #if constant_sized_binding_array
+@group(3) @binding(0) var textures: binding_array<texture_2d<f32>, 64>;
+@group(3) @binding(1) var<storage, read> texture_sampler_ids: array<u32>;
+@group(3) @binding(2) var samplers: binding_array<sampler, 8>;
+// #fi
+// Without binding arrays, the materials are bound with their textures.
+// #if texture_slots
+@group(0) @binding(0) var<storage, read> instances: array<Locals>;
+@group(0) @binding(1) var<storage, read> lights: LightArray;
+// Only bound for the alpha-tested draws.
+@group(1) @binding(0) var<storage, read> materials: array<Material>;
+@group(1) @binding(1) var texture_0: texture_2d<f32>;
+@group(1) @binding(2) var texture_1: texture_2d<f32>;
+@group(1) @binding(3) var texture_2: texture_2d<f32>;
+@group(1) @binding(4) var texture_3: texture_2d<f32>;
+@group(1) @binding(5) var texture_4: texture_2d<f32>;
+@group(1) @binding(6) var texture_5: texture_2d<f32>;
+@group(1) @binding(7) var texture_6: texture_2d<f32>;
+@group(1) @binding(8) var texture_7: texture_2d<f32>;
+@group(1) @binding(9) var sampler_0: sampler;
+@group(1) @binding(10) var sampler_1: sampler;
+@group(1) @binding(11) var sampler_2: sampler;
+@group(1) @binding(12) var sampler_3: sampler;
+@group(1) @binding(13) var sampler_4: sampler;
+@group(1) @binding(14) var sampler_5: sampler;
+@group(1) @binding(15) var sampler_6: sampler;
+@group(1) @binding(16) var sampler_7: sampler;
+// #fi
 
+// Without push constants, the constants are read from a uniform buffer,
+// after the groups of the materials and textures left empty by the draws
+// which are not alpha-tested.
+// #if push_constants
 var<push_constant> pconsts: PConsts;
+// #fi
+// #if binding_arrays_uniform_constants
+@group(4) @binding(0) var<uniform> pconsts: PConsts;
+// #fi
+// #if texture_slots_uniform_constants
+@group(2) @binding(0) var<uniform> pconsts: PConsts;
+// #fi
 
 struct ShadowMapVSInput {
     @builtin(instance_index) instance_index: u32,
@@ -38,3 +128,76 @@ fn vs_main(vin: ShadowMapVSInput) -> @builtin(position) vec4<f32> {
     let light = lights.data[pconsts.light_index];
     return light.world_to_light * locals.model * vec4<f32>(vin.position, 1.0);
 }
+
+struct AlphaTestVSInput {
+    @builtin(instance_index) instance_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) texcoord: vec2<f32>,
+}
+
+struct AlphaTestVSOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) texcoord: vec2<f32>,
+}
+
+/// Vertex shader of the sub-meshes whose material may cut out parts of the
+/// surfaces.
+@vertex
+fn vs_alpha_test(vin: AlphaTestVSInput) -> AlphaTestVSOutput {
+    let locals = instances[vin.instance_index + pconsts.instance_base_index];
+    let light = lights.data[pconsts.light_index];
+    var out: AlphaTestVSOutput;
+    out.position = light.world_to_light * locals.model * vec4<f32>(vin.position, 1.0);
+    out.texcoord = vin.texcoord;
+    return out;
+}
+
+/// Samples the texture of the given index in the textures shared by all
+/// materials.
+// #if !texture_slots
+fn sample_texture(map: u32, texcoord: vec2<f32>) -> vec4<f32> {
+    return textureSample(textures[map], samplers[texture_sampler_ids[map]], texcoord);
+}
+// #else
+fn sample_texture(map: u32, texcoord: vec2<f32>) -> vec4<f32> {
+    switch map {
+        case 0u: { return textureSample(texture_0, sampler_0, texcoord); }
+        case 1u: { return textureSample(texture_1, sampler_1, texcoord); }
+        case 2u: { return textureSample(texture_2, sampler_2, texcoord); }
+        case 3u: { return textureSample(texture_3, sampler_3, texcoord); }
+        case 4u: { return textureSample(texture_4, sampler_4, texcoord); }
+        case 5u: { return textureSample(texture_5, sampler_5, texcoord); }
+        case 6u: { return textureSample(texture_6, sampler_6, texcoord); }
+        case 7u: { return textureSample(texture_7, sampler_7, texcoord); }
+        default: { return vec4<f32>(1.0); }
+    }
+}
+// #fi
+
+/// Applies the texture transform of the material to the texture coordinates,
+/// as in the main pass.
+fn transform_texcoord(material: Material, uv: vec2<f32>) -> vec2<f32> {
+    let c = cos(material.uv_rotation);
+    let s = sin(material.uv_rotation);
+    let scaled = uv * material.uv_scale;
+    let transformed = vec2<f32>(c * scaled.x - s * scaled.y, s * scaled.x + c * scaled.y) + material.uv_offset;
+    return vec2<f32>(transformed.x, 1.0 - transformed.y);
+}
+
+/// Discards the fragments cut out by the diffuse or opacity map, only the
+/// depth being written.
+@fragment
+fn fs_alpha_test(vout: AlphaTestVSOutput) {
+    let material = materials[pconsts.material_index];
+    let texcoord = transform_texcoord(material, vout.texcoord);
+    var alpha = 1.0;
+    if (material.map_kd != INVALID_INDEX) {
+        alpha = sample_texture(material.map_kd, texcoord).a;
+    }
+    if (material.map_d != INVALID_INDEX) {
+        alpha *= sample_texture(material.map_d, texcoord).r;
+    }
+    if (alpha < ALPHA_CUTOFF) {
+        discard;
+    }
+}
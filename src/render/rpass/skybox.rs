@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 /// A skybox environment map.
 ///
 /// This is a cube map texture that is used to render the skybox. It is either
@@ -29,7 +31,7 @@ impl EnvironmentMap {
                 height,
                 depth_or_array_layers: 6,
             },
-            mip_level_count: 4,
+            mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -55,25 +57,31 @@ impl EnvironmentMap {
             ..Default::default()
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            bytemuck::cast_slice(&images[0]),
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: None,
-            },
-            wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 6,
-            },
-        );
+        for (layer, image) in images.iter().enumerate() {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture: &texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer as u32,
+                    },
+                    aspect: wgpu::TextureAspect::All,
+                },
+                bytemuck::cast_slice(image),
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
 
         Self {
             texture,
@@ -82,6 +90,36 @@ impl EnvironmentMap {
         }
     }
 
+    /// Loads an environment map from the 6 image files of its faces, in the
+    /// order of +X, -X, +Y, -Y, +Z, -Z. All faces must have the same size.
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        paths: &[PathBuf; 6],
+    ) -> Result<Self, String> {
+        let images = paths
+            .iter()
+            .map(|path| {
+                image::open(path)
+                    .map(|image| image.to_rgba8())
+                    .map_err(|err| format!("failed to load {:?}: {}", path, err))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // The faces of a cube map are squares of the same size.
+        let size = images[0].width();
+        if images
+            .iter()
+            .any(|image| image.dimensions() != (size, size))
+        {
+            return Err(String::from(
+                "the faces of the environment map must be squares of the same size",
+            ));
+        }
+        let (width, height) = images[0].dimensions();
+        let images: [image::RgbaImage; 6] = images.try_into().unwrap();
+        Ok(Self::new_from_images(device, queue, width, height, images))
+    }
+
     /// Creates a new environment map from a single equirectangular image.
     pub fn new_from_equirectangular(
         _device: &wgpu::Device,
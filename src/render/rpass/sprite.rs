@@ -0,0 +1,293 @@
+use crate::{
+    core::sprite::Sprite,
+    render::{
+        rpass::{scene_color_attachment, GlobalsBindGroup},
+        DepthSettings, RenderTarget, Renderer, StagingRing, Viewport,
+    },
+    scene::{NodeIdx, RenderLayer, Scene},
+};
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use legion::IntoQuery;
+
+/// Per-instance data of a sprite.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct SpriteInstance {
+    /// Center of the sprite in world space.
+    pub position: [f32; 3],
+    /// Size of the sprite in world units.
+    pub size: [f32; 2],
+    /// Top-left corner of the sprite in the atlas.
+    pub uv_min: [f32; 2],
+    /// Bottom-right corner of the sprite in the atlas.
+    pub uv_max: [f32; 2],
+    /// Color multiplied with the sprite image.
+    pub tint: [f32; 4],
+}
+
+crate::impl_size_constant!(SpriteInstance);
+
+/// Render pass drawing all sprites as camera-facing quads.
+///
+/// All sprites share the same texture atlas, so they are drawn with a single
+/// instanced draw call after the opaque geometry, sorted back to front for
+/// alpha blending. Sprites test against the depth buffer but do not write to
+/// it.
+pub struct SpriteRenderPass {
+    /// The pipeline drawing the sprites.
+    pipeline: wgpu::RenderPipeline,
+    /// The layout of the atlas bind group.
+    atlas_bind_group_layout: wgpu::BindGroupLayout,
+    /// The bind group of the atlas texture, created on first use.
+    atlas_bind_group: Option<wgpu::BindGroup>,
+    /// The sampler of the atlas texture.
+    sampler: wgpu::Sampler,
+    /// The vertex buffer storing the sprite instances.
+    instances: wgpu::Buffer,
+    /// Maximum number of instances in the instance buffer.
+    capacity: u32,
+    /// Number of sprites uploaded by the last call to `prepare`.
+    n_sprites: u32,
+}
+
+impl SpriteRenderPass {
+    /// Initial instance capacity for sprites.
+    pub const INITIAL_INSTANCE_CAPACITY: u32 = 256;
+
+    /// Creates a new sprite render pass.
+    pub fn new(
+        device: &wgpu::Device,
+        globals_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        depth: DepthSettings,
+    ) -> Self {
+        let atlas_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("sprite_atlas_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            multisampled: false,
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("sprite_atlas_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("sprite_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("sprite.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sprite_pipeline_layout"),
+            bind_group_layouts: &[globals_layout, &atlas_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("sprite_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: SpriteInstance::SIZE as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![
+                        0 => Float32x3,
+                        1 => Float32x2,
+                        2 => Float32x2,
+                        3 => Float32x2,
+                        4 => Float32x4,
+                    ],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.texture_format(),
+                depth_write_enabled: false,
+                depth_compare: depth.compare(),
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: depth.multisample(),
+            multiview: None,
+            cache: None,
+        });
+        let instances = Self::create_instance_buffer(device, Self::INITIAL_INSTANCE_CAPACITY);
+
+        Self {
+            pipeline,
+            atlas_bind_group_layout,
+            atlas_bind_group: None,
+            sampler,
+            instances,
+            capacity: Self::INITIAL_INSTANCE_CAPACITY,
+            n_sprites: 0,
+        }
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sprite_instance_buffer"),
+            size: SpriteInstance::SIZE as u64 * capacity as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Collects the visible sprites on the layers of the layer mask and
+    /// uploads them to the instance buffer.
+    pub fn prepare(
+        &mut self,
+        scene: &Scene,
+        renderer: &Renderer,
+        staging: &mut StagingRing,
+        encoder: &mut wgpu::CommandEncoder,
+        view_mat: Mat4,
+        layer_mask: u32,
+    ) {
+        profiling::scope!("SpriteRenderPass::prepare");
+        let mut sprites = <(&Sprite, &NodeIdx, Option<&RenderLayer>)>::query()
+            .iter(&scene.world)
+            .filter(|(_, node, layer)| {
+                scene.nodes[**node].is_visible()
+                    && layer.copied().unwrap_or_default().is_in(layer_mask)
+            })
+            .map(|(sprite, node, _)| {
+                let world = scene.nodes.world(*node);
+                let depth = view_mat.transform_point3(world.translation).z;
+                let instance = SpriteInstance {
+                    position: world.translation.to_array(),
+                    size: (sprite.size * world.scale.truncate()).to_array(),
+                    uv_min: sprite.region.min.to_array(),
+                    uv_max: sprite.region.max.to_array(),
+                    tint: sprite.tint.into(),
+                };
+                (depth, instance)
+            })
+            .collect::<Vec<_>>();
+
+        self.n_sprites = sprites.len() as u32;
+        if sprites.is_empty() {
+            return;
+        }
+
+        // Sort back to front, the camera looks along -Z in view space.
+        sprites.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let instances = sprites.into_iter().map(|(_, i)| i).collect::<Vec<_>>();
+
+        if self.n_sprites > self.capacity {
+            self.capacity = self.n_sprites.next_power_of_two();
+            self.instances = Self::create_instance_buffer(&renderer.device, self.capacity);
+        }
+        staging.write(
+            &renderer.device,
+            encoder,
+            &self.instances,
+            0,
+            bytemuck::cast_slice(&instances),
+        );
+
+        self.atlas_bind_group.get_or_insert_with(|| {
+            renderer
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("sprite_atlas_bind_group"),
+                    layout: &self.atlas_bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(
+                                &renderer.sprite_atlas.view,
+                            ),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                    ],
+                })
+        });
+    }
+
+    /// Records the sprite pass. The globals must be already updated by the
+    /// main pass, and the depth buffer must contain the opaque geometry.
+    pub fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RenderTarget,
+        msaa_view: Option<&wgpu::TextureView>,
+        globals: &GlobalsBindGroup,
+        depth_view: &wgpu::TextureView,
+        viewport: &Viewport,
+    ) {
+        profiling::scope!("SpriteRenderPass::record");
+        let atlas_bind_group = match (&self.atlas_bind_group, self.n_sprites) {
+            (Some(bind_group), n) if n > 0 => bind_group,
+            _ => return,
+        };
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("sprite_render_pass"),
+            color_attachments: &[Some(scene_color_attachment(
+                target,
+                msaa_view,
+                wgpu::LoadOp::Load,
+            ))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        viewport.apply(&mut render_pass);
+        render_pass.set_bind_group(0, globals, &[]);
+        render_pass.set_bind_group(1, atlas_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.instances.slice(..));
+        render_pass.draw(0..6, 0..self.n_sprites);
+        drop(render_pass);
+        self.n_sprites = 0;
+    }
+}
@@ -0,0 +1,64 @@
+/// Camera data.
+struct Globals {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    time: f32,
+    delta_time: f32,
+}
+
+struct VSInput {
+    @builtin(vertex_index) vertex_index: u32,
+    @location(0) position: vec3<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) uv_min: vec2<f32>,
+    @location(3) uv_max: vec2<f32>,
+    @location(4) tint: vec4<f32>,
+}
+
+struct VSOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) tint: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var atlas: texture_2d<f32>;
+@group(1) @binding(1) var atlas_sampler: sampler;
+
+// Corners of the quad made of two triangles.
+const CORNERS = array<vec2<f32>, 6>(
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, -0.5),
+    vec2<f32>(0.5, 0.5),
+    vec2<f32>(-0.5, 0.5),
+);
+
+@vertex
+fn vs_main(vin: VSInput) -> VSOutput {
+    let corner = CORNERS[vin.vertex_index];
+    // Right and up axes of the camera in world space are the first two rows
+    // of the view matrix.
+    let right = vec3<f32>(globals.view[0].x, globals.view[1].x, globals.view[2].x);
+    let up = vec3<f32>(globals.view[0].y, globals.view[1].y, globals.view[2].y);
+    let position = vin.position + right * corner.x * vin.size.x + up * corner.y * vin.size.y;
+
+    var vout: VSOutput;
+    vout.position = globals.proj * globals.view * vec4<f32>(position, 1.0);
+    // Image rows go from top to bottom.
+    let t = vec2<f32>(corner.x + 0.5, 0.5 - corner.y);
+    vout.uv = mix(vin.uv_min, vin.uv_max, t);
+    vout.tint = vin.tint;
+    return vout;
+}
+
+@fragment
+fn fs_main(vout: VSOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(atlas, atlas_sampler, vout.uv) * vout.tint;
+    if (color.a < 0.01) {
+        discard;
+    }
+    return color;
+}
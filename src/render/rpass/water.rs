@@ -0,0 +1,523 @@
+use crate::{
+    core::{
+        mesh::{Mesh, VertexAttribute},
+        water::Water,
+        Color, Light,
+    },
+    render::{
+        rpass::{scene_color_attachment, GlobalsBindGroup},
+        DepthSettings, RenderTarget, Renderer, StagingRing, Viewport,
+    },
+    scene::{NodeIdx, RenderLayer, Scene},
+};
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3, Vec4};
+use legion::IntoQuery;
+use wgpu::util::DeviceExt;
+
+/// Per-instance data of a water surface.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct WaterInstance {
+    /// The model matrix of the surface.
+    pub model: [f32; 16],
+    /// Color of the water seen from above.
+    pub color: [f32; 4],
+    /// Color reflected at grazing angles.
+    pub sky_color: [f32; 4],
+    /// Amplitude, length and speed of the waves, w is 1 if the surface
+    /// samples the reflection texture.
+    pub wave: [f32; 4],
+}
+
+crate::impl_size_constant!(WaterInstance);
+
+/// Per-frame data shared by all water surfaces.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct WaterFrame {
+    /// Direction towards the sun in world space, w is the elapsed time in
+    /// seconds.
+    pub sun_dir: [f32; 4],
+    /// Color of the sun.
+    pub sun_color: [f32; 4],
+}
+
+crate::impl_size_constant!(WaterFrame);
+
+/// Render pass drawing water surfaces.
+///
+/// All water surfaces share the same subdivided plane, see
+/// [`Mesh::water_plane`], and are drawn with a single instanced draw call
+/// after the opaque geometry. The waves are animated in the shader; the
+/// specular highlight uses the first active directional light of the scene.
+///
+/// The reflective surface samples the scene rendered mirrored below it, see
+/// [`Renderer::render_water_reflection`], at the position of its pixels on
+/// the screen.
+pub struct WaterRenderPass {
+    /// The pipeline drawing the water surfaces.
+    pipeline: wgpu::RenderPipeline,
+    /// The layout of the bind group of the per-frame uniforms.
+    frame_bind_group_layout: wgpu::BindGroupLayout,
+    /// The bind group of the per-frame uniforms and of the reflection.
+    frame_bind_group: wgpu::BindGroup,
+    /// The buffer of the per-frame uniforms.
+    frame_buffer: wgpu::Buffer,
+    /// Texture bound in place of the reflection when there is none.
+    no_reflection: wgpu::TextureView,
+    /// Sampler of the reflection texture.
+    sampler: wgpu::Sampler,
+    /// Generation of the reflection texture bound to the bind group, `None`
+    /// if none is.
+    reflection_generation: Option<u64>,
+    /// The vertex buffer of the water plane.
+    vertices: wgpu::Buffer,
+    /// The index buffer of the water plane.
+    indices: wgpu::Buffer,
+    /// The format of the index buffer.
+    index_format: wgpu::IndexFormat,
+    /// Number of indices of the water plane.
+    n_indices: u32,
+    /// The vertex buffer storing the water instances.
+    instances: wgpu::Buffer,
+    /// Maximum number of instances in the instance buffer.
+    capacity: u32,
+    /// Number of water surfaces uploaded by the last call to `prepare`.
+    n_instances: u32,
+}
+
+impl WaterRenderPass {
+    /// Initial instance capacity for water surfaces.
+    pub const INITIAL_INSTANCE_CAPACITY: u32 = 4;
+
+    /// Creates a new water render pass.
+    pub fn new(
+        device: &wgpu::Device,
+        globals_layout: &wgpu::BindGroupLayout,
+        format: wgpu::TextureFormat,
+        depth: DepthSettings,
+    ) -> Self {
+        let frame_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("water_frame_bind_group_layout"),
+                entries: &[
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: WaterFrame::BUFFER_SIZE,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                        count: None,
+                    },
+                ],
+            });
+        let frame_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("water_frame_buffer"),
+            size: WaterFrame::SIZE as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let no_reflection = device
+            .create_texture(&wgpu::TextureDescriptor {
+                label: Some("water_no_reflection_texture"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            })
+            .create_view(&Default::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("water_reflection_sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let frame_bind_group = Self::create_frame_bind_group(
+            device,
+            &frame_bind_group_layout,
+            &frame_buffer,
+            &no_reflection,
+            &sampler,
+        );
+
+        // The unit plane is scaled by the node of each water surface.
+        let plane = Mesh::water_plane(1.0);
+        let vertices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("water_vertex_buffer"),
+            contents: plane.attributes.0[&VertexAttribute::POSITION].as_bytes(),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let plane_indices = plane.indices.as_ref().unwrap();
+        let indices = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("water_index_buffer"),
+            contents: plane_indices.as_bytes(),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("water_shader_module"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("water.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("water_pipeline_layout"),
+            bind_group_layouts: &[globals_layout, &frame_bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("water_pipeline"),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader_module,
+                entry_point: Some("vs_main"),
+                compilation_options: Default::default(),
+                buffers: &[
+                    wgpu::VertexBufferLayout {
+                        array_stride: VertexAttribute::POSITION.size as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Vertex,
+                        attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                    },
+                    wgpu::VertexBufferLayout {
+                        array_stride: WaterInstance::SIZE as wgpu::BufferAddress,
+                        step_mode: wgpu::VertexStepMode::Instance,
+                        attributes: &wgpu::vertex_attr_array![
+                            1 => Float32x4,
+                            2 => Float32x4,
+                            3 => Float32x4,
+                            4 => Float32x4,
+                            5 => Float32x4,
+                            6 => Float32x4,
+                            7 => Float32x4,
+                        ],
+                    },
+                ],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader_module,
+                entry_point: Some("fs_main"),
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                polygon_mode: wgpu::PolygonMode::Fill,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: depth.texture_format(),
+                depth_write_enabled: false,
+                depth_compare: depth.compare(),
+                stencil: Default::default(),
+                bias: Default::default(),
+            }),
+            multisample: depth.multisample(),
+            multiview: None,
+            cache: None,
+        });
+        let instances = Self::create_instance_buffer(device, Self::INITIAL_INSTANCE_CAPACITY);
+
+        Self {
+            pipeline,
+            frame_bind_group_layout,
+            frame_bind_group,
+            frame_buffer,
+            no_reflection,
+            sampler,
+            reflection_generation: None,
+            vertices,
+            indices,
+            index_format: plane_indices.format(),
+            n_indices: plane_indices.len() as u32,
+            instances,
+            capacity: Self::INITIAL_INSTANCE_CAPACITY,
+            n_instances: 0,
+        }
+    }
+
+    fn create_frame_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        buffer: &wgpu::Buffer,
+        reflection: &wgpu::TextureView,
+        sampler: &wgpu::Sampler,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("water_frame_bind_group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(reflection),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+            ],
+        })
+    }
+
+    /// Returns the node of the reflective water surface the scene is
+    /// mirrored about, the first visible one, and its plane in world space.
+    /// The xyz of the plane is its unit normal, and w the opposite of its
+    /// distance to the origin along the normal.
+    pub fn reflection_plane(scene: &Scene) -> Option<(NodeIdx, Vec4)> {
+        let node = <(&Water, &NodeIdx)>::query()
+            .iter(&scene.world)
+            .filter(|(water, node)| water.reflection && scene.nodes[**node].is_visible())
+            .map(|(_, node)| *node)
+            .min()?;
+        let world = scene.nodes.world(node);
+        let normal = (world.rotation * Vec3::Y).normalize();
+        Some((node, normal.extend(-normal.dot(world.translation))))
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: u32) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("water_instance_buffer"),
+            size: WaterInstance::SIZE as u64 * capacity as u64,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Collects the visible water surfaces on the layers of the layer mask
+    /// and uploads them to the instance buffer. If `reflect` is true, the
+    /// reflective surface samples the reflection rendered by the renderer
+    /// for the main camera.
+    pub fn prepare(
+        &mut self,
+        scene: &Scene,
+        renderer: &Renderer,
+        staging: &mut StagingRing,
+        encoder: &mut wgpu::CommandEncoder,
+        layer_mask: u32,
+        reflect: bool,
+    ) {
+        profiling::scope!("WaterRenderPass::prepare");
+        let reflection = renderer.water_reflection().filter(|_| reflect);
+        let generation = reflection.map(|(_, generation)| generation);
+        if self.reflection_generation != generation {
+            let view = reflection.map_or(&self.no_reflection, |(texture, _)| &texture.view);
+            self.frame_bind_group = Self::create_frame_bind_group(
+                &renderer.device,
+                &self.frame_bind_group_layout,
+                &self.frame_buffer,
+                view,
+                &self.sampler,
+            );
+            self.reflection_generation = generation;
+        }
+        let reflective = reflection
+            .and_then(|_| Self::reflection_plane(scene))
+            .map(|(node, _)| node);
+
+        let instances = <(&Water, &NodeIdx, Option<&RenderLayer>)>::query()
+            .iter(&scene.world)
+            .filter(|(_, node, layer)| {
+                scene.nodes[**node].is_visible()
+                    && layer.copied().unwrap_or_default().is_in(layer_mask)
+            })
+            .map(|(water, node, _)| WaterInstance {
+                model: scene.nodes.world(*node).to_mat4().to_cols_array(),
+                color: water.color.into(),
+                sky_color: water.sky_color.into(),
+                wave: [
+                    water.wave_amplitude,
+                    water.wave_length.max(f32::EPSILON),
+                    water.wave_speed,
+                    if reflective == Some(*node) { 1.0 } else { 0.0 },
+                ],
+            })
+            .collect::<Vec<_>>();
+
+        self.n_instances = instances.len() as u32;
+        if instances.is_empty() {
+            return;
+        }
+
+        if self.n_instances > self.capacity {
+            self.capacity = self.n_instances.next_power_of_two();
+            self.instances = Self::create_instance_buffer(&renderer.device, self.capacity);
+        }
+        staging.write(
+            &renderer.device,
+            encoder,
+            &self.instances,
+            0,
+            bytemuck::cast_slice(&instances),
+        );
+
+        let (sun_dir, sun_color) = <(&Light, &NodeIdx)>::query()
+            .iter(&scene.world)
+            .filter(|(_, node)| scene.nodes[**node].is_active())
+            .find_map(|(light, node)| match light {
+                Light::Directional { color, .. } => light
+                    .world_direction(&scene.nodes.world(*node))
+                    .map(|direction| (-direction, *color)),
+                Light::Point { .. } => None,
+            })
+            .unwrap_or((Vec3::Y, Color::WHITE));
+        let frame = WaterFrame {
+            sun_dir: [sun_dir.x, sun_dir.y, sun_dir.z, renderer.time().elapsed],
+            sun_color: sun_color.into(),
+        };
+        staging.write(
+            &renderer.device,
+            encoder,
+            &self.frame_buffer,
+            0,
+            bytemuck::bytes_of(&frame),
+        );
+    }
+
+    /// Records the water pass. The globals must be already updated by the
+    /// main pass, and the depth buffer must contain the opaque geometry.
+    pub fn record(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &RenderTarget,
+        msaa_view: Option<&wgpu::TextureView>,
+        globals: &GlobalsBindGroup,
+        depth_view: &wgpu::TextureView,
+        viewport: &Viewport,
+    ) {
+        profiling::scope!("WaterRenderPass::record");
+        if self.n_instances == 0 {
+            return;
+        }
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("water_render_pass"),
+            color_attachments: &[Some(scene_color_attachment(
+                target,
+                msaa_view,
+                wgpu::LoadOp::Load,
+            ))],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        viewport.apply(&mut render_pass);
+        render_pass.set_bind_group(0, globals, &[]);
+        render_pass.set_bind_group(1, &self.frame_bind_group, &[]);
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_vertex_buffer(1, self.instances.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), self.index_format);
+        render_pass.draw_indexed(0..self.n_indices, 0, 0..self.n_instances);
+        drop(render_pass);
+        self.n_instances = 0;
+    }
+}
+
+/// Returns the matrix mirroring the points about the plane, whose xyz is its
+/// unit normal and w the opposite of its distance to the origin.
+pub fn reflection_matrix(plane: Vec4) -> Mat4 {
+    let n = plane.truncate();
+    Mat4::from_cols(
+        (Vec3::X - 2.0 * n.x * n).extend(0.0),
+        (Vec3::Y - 2.0 * n.y * n).extend(0.0),
+        (Vec3::Z - 2.0 * n.z * n).extend(0.0),
+        (-2.0 * plane.w * n).extend(1.0),
+    )
+}
+
+/// Returns the projection matrix whose near plane is replaced by the given
+/// plane in view space, clipping what lies behind it without clip distances.
+///
+/// The far plane is tilted to go through the corner of the frustum the
+/// farthest from the plane, which keeps the depth range as tight as
+/// possible, see Lengyel, "Oblique View Frustum Depth Projection and
+/// Clipping". The camera must be behind the plane.
+pub fn oblique_projection(proj: Mat4, plane: Vec4, reversed_z: bool) -> Mat4 {
+    let far_z = if reversed_z { 0.0 } else { 1.0 };
+    let corner = proj.inverse() * Vec4::new(plane.x.signum(), plane.y.signum(), far_z, 1.0);
+    let near = plane / plane.dot(corner);
+    // The near plane is at z = 0 in clip space, or at z = w if reversed.
+    let z = if reversed_z { proj.row(3) - near } else { near };
+    Mat4::from_cols(proj.row(0), proj.row(1), z, proj.row(3)).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oblique_projection_clips_below_plane() {
+        // Camera above water at y = 1, mirrored below it.
+        let plane = Vec4::new(0.0, 1.0, 0.0, -1.0);
+        let view = Mat4::look_at_rh(Vec3::new(0.0, 5.0, 10.0), Vec3::Y, Vec3::Y)
+            * reflection_matrix(plane);
+        assert!(view
+            .inverse()
+            .transform_point3(Vec3::ZERO)
+            .abs_diff_eq(Vec3::new(0.0, -3.0, 10.0), 1e-4));
+
+        let view_plane = view.inverse().transpose() * plane;
+        for reversed_z in [false, true] {
+            let proj = if reversed_z {
+                Mat4::perspective_rh(1.0, 1.5, 100.0, 0.1)
+            } else {
+                Mat4::perspective_rh(1.0, 1.5, 0.1, 100.0)
+            };
+            let proj = oblique_projection(proj, view_plane, reversed_z);
+            let depth = |p: Vec3| {
+                let clip = proj * view * p.extend(1.0);
+                let depth = clip.z / clip.w;
+                if reversed_z {
+                    1.0 - depth
+                } else {
+                    depth
+                }
+            };
+            // On the plane at the near plane, above it inside the depth
+            // range, below it clipped.
+            assert!(depth(Vec3::new(1.0, 1.0, -3.0)).abs() < 1e-4);
+            assert!((0.0..1.0).contains(&depth(Vec3::new(0.0, 3.0, 0.0))));
+            assert!(depth(Vec3::new(0.0, 0.5, 0.0)) < 0.0);
+        }
+    }
+}
@@ -0,0 +1,119 @@
+/// Camera data.
+struct Globals {
+    view: mat4x4<f32>,
+    proj: mat4x4<f32>,
+    camera_pos: vec3<f32>,
+    time: f32,
+    delta_time: f32,
+}
+
+struct WaterFrame {
+    /// Direction towards the sun in world space, w is the elapsed time.
+    sun_dir: vec4<f32>,
+    sun_color: vec4<f32>,
+}
+
+struct VSInput {
+    @location(0) position: vec3<f32>,
+    @location(1) model_0: vec4<f32>,
+    @location(2) model_1: vec4<f32>,
+    @location(3) model_2: vec4<f32>,
+    @location(4) model_3: vec4<f32>,
+    @location(5) color: vec4<f32>,
+    @location(6) sky_color: vec4<f32>,
+    /// Amplitude, length and speed of the waves, w is 1 if the surface
+    /// samples the reflection texture.
+    @location(7) wave: vec4<f32>,
+}
+
+struct VSOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) sky_color: vec4<f32>,
+    @location(3) wave: vec4<f32>,
+}
+
+@group(0) @binding(0) var<uniform> globals: Globals;
+@group(1) @binding(0) var<uniform> frame: WaterFrame;
+/// Scene rendered mirrored below the reflective surface, of the size of the
+/// render target.
+@group(1) @binding(1) var reflection_texture: texture_2d<f32>;
+@group(1) @binding(2) var reflection_sampler: sampler;
+
+const PI: f32 = 3.14159265;
+const N_WAVES: u32 = 4u;
+// Directions of the waves.
+const WAVE_DIRS = array<vec2<f32>, 4>(
+    vec2<f32>(1.0, 0.0),
+    vec2<f32>(0.6, 0.8),
+    vec2<f32>(-0.7, 0.7),
+    vec2<f32>(0.2, -0.98),
+);
+// Relative lengths of the waves, the amplitude scales accordingly.
+const WAVE_SCALES = array<f32, 4>(1.0, 0.61, 0.37, 0.23);
+
+/// Returns the height of the surface and its partial derivatives along x and
+/// z at the given position.
+fn waves(p: vec2<f32>, wave: vec4<f32>, time: f32) -> vec3<f32> {
+    var dirs = WAVE_DIRS;
+    var scales = WAVE_SCALES;
+    var result = vec3<f32>(0.0);
+    for (var i = 0u; i < N_WAVES; i++) {
+        let k = 2.0 * PI / (wave.y * scales[i]);
+        let amplitude = wave.x * scales[i];
+        let phase = k * (dot(dirs[i], p) - wave.z * time);
+        result.x += amplitude * sin(phase);
+        result.y += amplitude * k * dirs[i].x * cos(phase);
+        result.z += amplitude * k * dirs[i].y * cos(phase);
+    }
+    return result;
+}
+
+@vertex
+fn vs_main(vin: VSInput) -> VSOutput {
+    let model = mat4x4<f32>(vin.model_0, vin.model_1, vin.model_2, vin.model_3);
+    var world_position = (model * vec4<f32>(vin.position, 1.0)).xyz;
+    world_position.y += waves(world_position.xz, vin.wave, frame.sun_dir.w).x;
+
+    var vout: VSOutput;
+    vout.position = globals.proj * globals.view * vec4<f32>(world_position, 1.0);
+    vout.world_position = world_position;
+    vout.color = vin.color;
+    vout.sky_color = vin.sky_color;
+    vout.wave = vin.wave;
+    return vout;
+}
+
+@fragment
+fn fs_main(vout: VSOutput) -> @location(0) vec4<f32> {
+    let time = frame.sun_dir.w;
+    // Normals are evaluated per pixel, with an extra set of small ripples.
+    let large = waves(vout.world_position.xz, vout.wave, time);
+    let ripples = waves(vout.world_position.xz, vout.wave * vec4<f32>(0.1, 0.1, 0.5, 1.0), time);
+    let n = normalize(vec3<f32>(-(large.y + ripples.y), 1.0, -(large.z + ripples.z)));
+
+    // Camera position is the translation of the inverse view matrix.
+    let rot = mat3x3<f32>(globals.view[0].xyz, globals.view[1].xyz, globals.view[2].xyz);
+    let camera_position = -(transpose(rot) * globals.view[3].xyz);
+    let v = normalize(camera_position - vout.world_position);
+
+    // Schlick's approximation of the Fresnel term, with the reflectance of
+    // water at normal incidence.
+    let f0 = 0.02;
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(n, v), 0.0), 5.0);
+
+    let l = normalize(frame.sun_dir.xyz);
+    let h = normalize(l + v);
+    let specular = pow(max(dot(n, h), 0.0), 256.0) * step(0.0, l.y);
+
+    // The reflection is seen at the same position on the screen, shifted by
+    // the waves.
+    let uv = vout.position.xy / vec2<f32>(textureDimensions(reflection_texture)) + n.xz * 0.03;
+    let reflection = textureSample(reflection_texture, reflection_sampler, uv).rgb;
+    let sky_color = select(vout.sky_color.rgb, reflection, vout.wave.w > 0.5);
+
+    let color = mix(vout.color.rgb, sky_color, fresnel) + specular * frame.sun_color.rgb;
+    let alpha = mix(vout.color.a, 1.0, fresnel);
+    return vec4<f32>(color, alpha);
+}
@@ -1,3 +1,5 @@
+use crate::core::{FxHashMap, SmlString};
+use pyo3::pymethods;
 use std::default::Default;
 use std::ops::Deref;
 
@@ -144,3 +146,328 @@ impl Sampler {
         Self { sampler, id }
     }
 }
+
+/// Wrapping of the texture coordinates outside of the `[0, 1]` range.
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WrapMode {
+    /// The texture repeats.
+    Repeat,
+    /// The texels at the edges are stretched.
+    Clamp,
+    /// The texture repeats, mirrored every other time.
+    Mirror,
+}
+
+impl From<WrapMode> for wgpu::AddressMode {
+    fn from(mode: WrapMode) -> Self {
+        match mode {
+            WrapMode::Repeat => wgpu::AddressMode::Repeat,
+            WrapMode::Clamp => wgpu::AddressMode::ClampToEdge,
+            WrapMode::Mirror => wgpu::AddressMode::MirrorRepeat,
+        }
+    }
+}
+
+/// Filtering of the texels.
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FilterMode {
+    /// The nearest texel is sampled.
+    Nearest,
+    /// The nearest texels are interpolated.
+    Linear,
+}
+
+impl From<FilterMode> for wgpu::FilterMode {
+    fn from(mode: FilterMode) -> Self {
+        match mode {
+            FilterMode::Nearest => wgpu::FilterMode::Nearest,
+            FilterMode::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// Settings of a sampler, registered under a name in the [`SamplerRegistry`].
+#[pyo3::pyclass]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SamplerConfig {
+    /// Wrapping along U.
+    #[pyo3(get, set)]
+    pub wrap_u: WrapMode,
+    /// Wrapping along V.
+    #[pyo3(get, set)]
+    pub wrap_v: WrapMode,
+    /// Filtering of the magnified and minified texels.
+    #[pyo3(get, set)]
+    pub filter: FilterMode,
+    /// Filtering between the mip levels.
+    #[pyo3(get, set)]
+    pub mipmap_filter: FilterMode,
+    /// Maximum anisotropy, from 1 (disabled) to 16. Only applied if all the
+    /// filters are linear.
+    #[pyo3(get, set)]
+    pub anisotropy: u16,
+    /// Lowest mip level sampled.
+    #[pyo3(get, set)]
+    pub lod_min: f32,
+    /// Highest mip level sampled.
+    #[pyo3(get, set)]
+    pub lod_max: f32,
+    /// Comparison function of the depth samplers.
+    pub compare: Option<wgpu::CompareFunction>,
+}
+
+impl Default for SamplerConfig {
+    fn default() -> Self {
+        Self {
+            wrap_u: WrapMode::Repeat,
+            wrap_v: WrapMode::Repeat,
+            filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            anisotropy: 1,
+            lod_min: 0.0,
+            lod_max: 32.0,
+            compare: None,
+        }
+    }
+}
+
+impl SamplerConfig {
+    /// Settings of the comparison sampler of the shadow maps.
+    pub const DEPTH: Self = Self {
+        wrap_u: WrapMode::Clamp,
+        wrap_v: WrapMode::Clamp,
+        filter: FilterMode::Nearest,
+        mipmap_filter: FilterMode::Nearest,
+        anisotropy: 1,
+        lod_min: 0.0,
+        lod_max: 32.0,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+    };
+
+    /// Parses the settings described by a sampler name, made of an optional
+    /// wrap mode (`repeat`, `clamp` or `mirror`, repeat by default), a filter
+    /// (`linear` or `nearest`) and an optional anisotropy (`aniso2` to
+    /// `aniso16`) separated by underscores, e.g. `clamp_nearest` or
+    /// `linear_aniso8`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let mut config = Self::default();
+        let mut parts = name.split('_').peekable();
+        match parts.peek().copied()? {
+            "repeat" | "clamp" | "mirror" => {
+                let wrap = match parts.next()? {
+                    "repeat" => WrapMode::Repeat,
+                    "clamp" => WrapMode::Clamp,
+                    _ => WrapMode::Mirror,
+                };
+                config.wrap_u = wrap;
+                config.wrap_v = wrap;
+            }
+            _ => {}
+        }
+        let filter = match parts.next()? {
+            "linear" => FilterMode::Linear,
+            "nearest" => FilterMode::Nearest,
+            _ => return None,
+        };
+        config.filter = filter;
+        config.mipmap_filter = filter;
+        if let Some(part) = parts.next() {
+            config.anisotropy = part
+                .strip_prefix("aniso")?
+                .parse()
+                .ok()
+                .filter(|n| (1..=16).contains(n))?;
+        }
+        parts.next().is_none().then_some(config)
+    }
+
+    /// Returns the descriptor of the sampler.
+    pub fn descriptor<'a>(&self, label: &'a str) -> wgpu::SamplerDescriptor<'a> {
+        let linear = self.filter == FilterMode::Linear && self.mipmap_filter == FilterMode::Linear;
+        let anisotropy = self.anisotropy.clamp(1, 16);
+        if anisotropy > 1 && !linear {
+            log::warn!("Sampler {label} requires linear filters for anisotropy, ignored.");
+        }
+        wgpu::SamplerDescriptor {
+            label: Some(label),
+            address_mode_u: self.wrap_u.into(),
+            address_mode_v: self.wrap_v.into(),
+            address_mode_w: self.wrap_u.into(),
+            mag_filter: self.filter.into(),
+            min_filter: self.filter.into(),
+            mipmap_filter: self.mipmap_filter.into(),
+            lod_min_clamp: self.lod_min.max(0.0),
+            lod_max_clamp: self.lod_max.max(self.lod_min.max(0.0)),
+            compare: self.compare,
+            anisotropy_clamp: if linear { anisotropy } else { 1 },
+            border_color: None,
+        }
+    }
+}
+
+#[pymethods]
+impl SamplerConfig {
+    #[new]
+    #[pyo3(signature = (wrap=WrapMode::Repeat, filter=FilterMode::Linear, mipmap_filter=None, anisotropy=1, lod_min=0.0, lod_max=32.0))]
+    pub fn new_py(
+        wrap: WrapMode,
+        filter: FilterMode,
+        mipmap_filter: Option<FilterMode>,
+        anisotropy: u16,
+        lod_min: f32,
+        lod_max: f32,
+    ) -> Self {
+        Self {
+            wrap_u: wrap,
+            wrap_v: wrap,
+            filter,
+            mipmap_filter: mipmap_filter.unwrap_or(filter),
+            anisotropy,
+            lod_min,
+            lod_max,
+            compare: None,
+        }
+    }
+
+    /// Returns the settings described by a sampler name, e.g.
+    /// `clamp_nearest` or `linear_aniso8`, `None` if the name is not valid.
+    #[staticmethod]
+    #[pyo3(name = "from_name")]
+    pub fn from_name_py(name: &str) -> Option<Self> {
+        Self::from_name(name)
+    }
+
+    pub fn __repr__(&self) -> String {
+        format!(
+            "SamplerConfig(wrap_u={:?}, wrap_v={:?}, filter={:?}, mipmap_filter={:?}, anisotropy={}, lod_min={}, lod_max={})",
+            self.wrap_u,
+            self.wrap_v,
+            self.filter,
+            self.mipmap_filter,
+            self.anisotropy,
+            self.lod_min,
+            self.lod_max
+        )
+    }
+}
+
+/// Samplers of the textures by name, created when first requested.
+///
+/// A name refers to a registered [`SamplerConfig`], or else describes the
+/// settings of the sampler, see [`SamplerConfig::from_name`]. `depth` is the
+/// comparison sampler of the shadow maps.
+pub struct SamplerRegistry {
+    configs: FxHashMap<SmlString, SamplerConfig>,
+    samplers: FxHashMap<SmlString, Sampler>,
+}
+
+impl Default for SamplerRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SamplerRegistry {
+    /// Name of the sampler used by the textures without a valid sampler.
+    pub const DEFAULT: &'static str = "linear";
+
+    /// Creates the registry with the `depth` sampler.
+    pub fn new() -> Self {
+        let mut configs = FxHashMap::default();
+        configs.insert(SmlString::from("depth"), SamplerConfig::DEPTH);
+        Self {
+            configs,
+            samplers: FxHashMap::default(),
+        }
+    }
+
+    /// Registers the settings of a sampler, replacing the ones registered
+    /// under the same name. Returns whether the settings changed.
+    pub fn register(&mut self, name: &str, config: SamplerConfig) -> bool {
+        if self.configs.get(name) == Some(&config) {
+            return false;
+        }
+        self.configs.insert(SmlString::from(name), config);
+        self.samplers.remove(name);
+        true
+    }
+
+    /// Returns the settings of the sampler of the given name.
+    pub fn config(&self, name: &str) -> Option<SamplerConfig> {
+        self.configs
+            .get(name)
+            .copied()
+            .or_else(|| SamplerConfig::from_name(name))
+    }
+
+    /// Creates the sampler of the given name if it doesn't exist yet.
+    /// Returns `false` if the name refers to no settings.
+    pub fn prepare(&mut self, device: &wgpu::Device, name: &str) -> bool {
+        if self.samplers.contains_key(name) {
+            return true;
+        }
+        match self.config(name) {
+            Some(config) => {
+                let sampler = Sampler::new(device, config.descriptor(name));
+                self.samplers.insert(SmlString::from(name), sampler);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the sampler of the given name, if it has been created.
+    pub fn get(&self, name: &str) -> Option<&Sampler> {
+        self.samplers.get(name)
+    }
+
+    /// Releases the samplers, created again when requested. The registered
+    /// settings are kept.
+    pub fn clear(&mut self) {
+        self.samplers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sampler_names_are_parsed() {
+        assert_eq!(
+            SamplerConfig::from_name("linear"),
+            Some(SamplerConfig::default())
+        );
+
+        let config = SamplerConfig::from_name("clamp_nearest").unwrap();
+        assert_eq!(config.wrap_u, WrapMode::Clamp);
+        assert_eq!(config.wrap_v, WrapMode::Clamp);
+        assert_eq!(config.filter, FilterMode::Nearest);
+        assert_eq!(config.mipmap_filter, FilterMode::Nearest);
+
+        let config = SamplerConfig::from_name("mirror_linear_aniso8").unwrap();
+        assert_eq!(config.wrap_u, WrapMode::Mirror);
+        assert_eq!(config.anisotropy, 8);
+
+        assert_eq!(SamplerConfig::from_name("clamp"), None);
+        assert_eq!(SamplerConfig::from_name("linear_aniso32"), None);
+        assert_eq!(SamplerConfig::from_name("nearest_linear"), None);
+        assert_eq!(SamplerConfig::from_name(""), None);
+    }
+
+    #[test]
+    fn registered_configs_override_names() {
+        let mut registry = SamplerRegistry::new();
+        let config = SamplerConfig {
+            anisotropy: 4,
+            ..SamplerConfig::default()
+        };
+        assert!(registry.register("linear", config));
+        assert!(!registry.register("linear", config));
+        assert_eq!(registry.config("linear"), Some(config));
+        assert_eq!(registry.config("depth"), Some(SamplerConfig::DEPTH));
+        assert_eq!(registry.config("unknown"), None);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::core::{assets::FileWatcher, FxHashSet};
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+/// Shaders embedded in the library, used when no user-provided version is
+/// available or when the user-provided version fails to compile.
+const EMBEDDED_SHADERS: [(&str, &str); 2] = [
+    ("blph.wgsl", include_str!("rpass/blph.wgsl")),
+    ("shadow.wgsl", include_str!("rpass/shadow.wgsl")),
+];
+
+/// Manages the sources of the shaders used by the rendering passes.
+///
+/// Shaders are loaded from a user-provided directory if set, falling back to
+/// the embedded ones. The directory and the custom shaders of entities are
+/// watched for modifications; each modification increments the generation
+/// of the manager so that rendering passes know when to rebuild their
+/// pipelines.
+#[derive(Default)]
+pub struct ShaderManager {
+    /// Directory containing the shaders overriding the embedded ones.
+    dir: Option<PathBuf>,
+    /// Custom material shaders used by entities.
+    custom: FxHashSet<PathBuf>,
+    /// Watches the user-provided shaders.
+    watcher: Option<FileWatcher>,
+    /// Incremented each time the shaders change.
+    generation: u64,
+}
+
+impl ShaderManager {
+    /// Returns the generation of the shaders.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Sets the directory from which the shaders are loaded. `None` restores
+    /// the embedded shaders.
+    pub fn set_directory(&mut self, dir: Option<PathBuf>) {
+        log::info!("Loading shaders from {:?}", dir);
+        self.dir = dir;
+        self.watcher = None;
+        if let Some(dir) = &self.dir {
+            for (name, _) in EMBEDDED_SHADERS {
+                let path = dir.join(name);
+                if path.exists() {
+                    self.watch(&path);
+                }
+            }
+        }
+        for path in self.custom.clone() {
+            self.watch(&path);
+        }
+        self.generation += 1;
+    }
+
+    /// Registers a custom material shader, does nothing if the shader is
+    /// already registered.
+    pub fn register_custom(&mut self, path: &Path) {
+        if self.custom.insert(path.to_path_buf()) {
+            self.watch(path);
+            self.generation += 1;
+        }
+    }
+
+    /// Returns the registered custom material shaders.
+    pub fn custom_shaders(&self) -> impl Iterator<Item = &Path> {
+        self.custom.iter().map(|path| path.as_path())
+    }
+
+    /// Increments the generation if any of the watched shaders has been
+    /// modified since the last call.
+    pub fn poll(&mut self) {
+        if let Some(watcher) = &self.watcher {
+            let changed = watcher.changed_files();
+            if !changed.is_empty() {
+                log::info!("Shaders modified: {:?}", changed);
+                self.generation += 1;
+            }
+        }
+    }
+
+    /// Returns the source of the shader with the given file name, and
+    /// whether it is the embedded version.
+    pub fn source(&self, name: &str) -> (Cow<'static, str>, bool) {
+        if let Some(dir) = &self.dir {
+            let path = dir.join(name);
+            if path.exists() {
+                match std::fs::read_to_string(&path) {
+                    Ok(source) => return (Cow::Owned(source), false),
+                    Err(err) => log::error!("Failed to read shader {:?}: {}", path, err),
+                }
+            }
+        }
+        (Cow::Borrowed(Self::embedded(name)), true)
+    }
+
+    /// Returns the embedded version of the shader with the given file name.
+    pub fn embedded(name: &str) -> &'static str {
+        EMBEDDED_SHADERS
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, source)| *source)
+            .unwrap_or_else(|| panic!("No embedded shader named {}", name))
+    }
+
+    /// Returns the source of a custom material shader.
+    pub fn custom_source(&self, path: &Path) -> Option<String> {
+        std::fs::read_to_string(path)
+            .map_err(|err| log::error!("Failed to read shader {:?}: {}", path, err))
+            .ok()
+    }
+
+    fn watch(&mut self, path: &Path) {
+        if self.watcher.is_none() {
+            self.watcher = FileWatcher::new()
+                .map_err(|err| log::error!("Failed to watch shaders: {}", err))
+                .ok();
+        }
+        if let Some(watcher) = &mut self.watcher {
+            watcher.watch(path);
+        }
+    }
+}
+
+/// Runs the given function creating GPU objects, returns an error instead of
+/// panicking if wgpu reports a validation error, e.g. when a shader fails to
+/// compile.
+pub fn validated<T>(device: &wgpu::Device, f: impl FnOnce() -> T) -> Result<T, wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let result = f();
+    match pollster::block_on(device.pop_error_scope()) {
+        None => Ok(result),
+        Some(err) => Err(err),
+    }
+}
@@ -0,0 +1,62 @@
+use wgpu::util::StagingBelt;
+
+/// Ring of staging buffers through which the dynamic uniform and storage
+/// buffers are updated every frame.
+///
+/// Each write is recorded as a copy into the command encoder of the frame,
+/// so it must happen before the passes reading the buffer are recorded. The
+/// staging chunks stay mapped and are reused once the GPU finished the frame
+/// they were written in, which avoids the implicit staging allocations of
+/// `Queue::write_buffer`.
+pub struct StagingRing {
+    belt: StagingBelt,
+}
+
+impl StagingRing {
+    /// Size of the staging chunks, writes larger than this get their own
+    /// chunk.
+    pub const CHUNK_SIZE: wgpu::BufferAddress = 1 << 20;
+
+    pub fn new() -> Self {
+        Self {
+            belt: StagingBelt::new(Self::CHUNK_SIZE),
+        }
+    }
+
+    /// Records the copy of `data` into `target` at `offset`. The size of the
+    /// data must be a multiple of `wgpu::COPY_BUFFER_ALIGNMENT`.
+    pub fn write(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        target: &wgpu::Buffer,
+        offset: wgpu::BufferAddress,
+        data: &[u8],
+    ) {
+        let size = match wgpu::BufferSize::new(data.len() as u64) {
+            Some(size) => size,
+            None => return,
+        };
+        self.belt
+            .write_buffer(encoder, target, offset, size, device)
+            .copy_from_slice(data);
+    }
+
+    /// Closes the chunks written during the frame, must be called before
+    /// submitting the command encoder.
+    pub fn finish(&mut self) {
+        self.belt.finish();
+    }
+
+    /// Recycles the chunks of the submitted frames once the GPU is done with
+    /// them, must be called after submitting the command encoder.
+    pub fn recall(&mut self) {
+        self.belt.recall();
+    }
+}
+
+impl Default for StagingRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
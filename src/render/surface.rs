@@ -1,6 +1,5 @@
 use crate::render::context::GpuContext;
 use std::ops::{Deref, DerefMut};
-use winit::window::Window;
 
 /// Surface of the window used to render.
 ///
@@ -60,7 +59,7 @@ impl<'w> Surface<'w> {
 
 impl<'w> Surface<'w> {
     /// Creates a new surface from a window and configures it.
-    pub fn new(context: &GpuContext, window: &'w Window) -> Self {
+    pub fn new(context: &GpuContext, window: impl Into<wgpu::SurfaceTarget<'w>>) -> Self {
         profiling::scope!("Surface::new");
         let surface = context.instance.create_surface(window).unwrap();
         let caps = surface.get_capabilities(&context.adapter);
@@ -74,8 +73,11 @@ impl<'w> Surface<'w> {
             }
         }
 
+        // Frames are copied when recording if the surface allows it.
+        let usage =
+            wgpu::TextureUsages::RENDER_ATTACHMENT | (caps.usages & wgpu::TextureUsages::COPY_SRC);
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage,
             format,
             width: window.inner_size().width,
             height: window.inner_size().height,
@@ -93,6 +95,24 @@ impl<'w> Surface<'w> {
         }
     }
 
+    /// Sets the present mode of the surface and reconfigures it.
+    ///
+    /// Returns `true` if the present mode changed.
+    pub fn set_present_mode(&mut self, device: &wgpu::Device, mode: wgpu::PresentMode) -> bool {
+        if self.config.present_mode == mode {
+            return false;
+        }
+        self.config.present_mode = mode;
+        self.inner.configure(device, &self.config);
+        true
+    }
+
+    /// Configures the surface again with its current configuration, e.g.
+    /// after it was lost or became outdated.
+    pub fn reconfigure(&self, device: &wgpu::Device) {
+        self.inner.configure(device, &self.config);
+    }
+
     /// Resizes the surface and reconfigures it.
     ///
     /// Size is expressed in physical pixels.
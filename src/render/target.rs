@@ -1,3 +1,10 @@
+use crate::core::{
+    assets::{Handle, TextureAssets},
+    camera::Camera,
+    SmlString, Texture,
+};
+use std::path::PathBuf;
+
 /// A render target is a texture that can be rendered to.
 pub struct RenderTarget {
     /// The size of the render target.
@@ -13,3 +20,197 @@ impl RenderTarget {
         self.size.width as f32 / self.size.height as f32
     }
 }
+
+/// Region of a render target the scene is drawn into, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    /// Returns the viewport covering the whole target of the given size.
+    pub fn full(size: wgpu::Extent3d) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: size.width as f32,
+            height: size.height as f32,
+        }
+    }
+
+    /// Returns the largest viewport of the given aspect ratio centered in the
+    /// target of the given size, leaving bars on its sides or above and
+    /// below it.
+    pub fn letterboxed(size: wgpu::Extent3d, aspect: f32) -> Self {
+        let full = Self::full(size);
+        if full.aspect_ratio() > aspect {
+            // Bars on the left and right.
+            let width = full.height * aspect;
+            Self {
+                x: (full.width - width) * 0.5,
+                width,
+                ..full
+            }
+        } else {
+            // Bars above and below.
+            let height = full.width / aspect;
+            Self {
+                y: (full.height - height) * 0.5,
+                height,
+                ..full
+            }
+        }
+    }
+
+    /// Returns the viewport the camera renders to in the target of the given
+    /// size: the whole target, or a letterboxed part of it if the camera has
+    /// a fixed aspect ratio.
+    pub fn for_camera(camera: &Camera, size: wgpu::Extent3d) -> Self {
+        match camera.fixed_aspect {
+            Some(aspect) if aspect > 0.0 => Self::letterboxed(size, aspect),
+            _ => Self::full(size),
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width / self.height
+    }
+
+    /// Restricts the drawing of the render pass to the viewport.
+    pub fn apply(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_viewport(self.x, self.y, self.width, self.height, 0.0, 1.0);
+    }
+}
+
+/// Texture a secondary camera renders the scene into each frame.
+///
+/// The scene is rendered into an attachment which is then copied to the
+/// texture sampled by the materials, so that a camera may see the meshes
+/// showing its own image, one frame late.
+pub struct CameraTarget {
+    /// Name the materials refer to the texture by, in place of a file path.
+    pub name: PathBuf,
+    /// Target the camera renders into.
+    pub target: RenderTarget,
+    /// Texture behind the target.
+    attachment: wgpu::Texture,
+    /// Texture sampled by the materials, in the global texture array.
+    pub texture: Handle<Texture>,
+}
+
+impl CameraTarget {
+    /// Format of the camera textures, the same as the textures loaded from
+    /// files.
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// Creates the target and the sampled texture of the given size. The
+    /// texture replaces the given one in the textures, or is added to them.
+    pub fn new(
+        device: &wgpu::Device,
+        textures: &mut TextureAssets,
+        name: PathBuf,
+        (width, height): (u32, u32),
+        texture: Option<Handle<Texture>>,
+    ) -> Self {
+        let size = wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        };
+        let create = |label, usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some(label),
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: Self::FORMAT,
+                usage,
+                view_formats: &[],
+            })
+        };
+        let attachment = create(
+            "camera_target_attachment",
+            wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        );
+        let raw = create(
+            "camera_target_texture",
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        );
+        let target = RenderTarget {
+            size,
+            view: attachment.create_view(&Default::default()),
+            format: Self::FORMAT,
+        };
+        let sampled = Texture {
+            view: raw.create_view(&Default::default()),
+            raw,
+            size,
+            sampler: SmlString::from("linear"),
+        };
+        let texture = match texture {
+            Some(texture) => {
+                textures.insert(texture, sampled);
+                texture
+            }
+            None => textures.add(sampled),
+        };
+        Self {
+            name,
+            target,
+            attachment,
+            texture,
+        }
+    }
+
+    /// Copies the rendered image to the sampled texture.
+    pub fn copy_to_texture(&self, encoder: &mut wgpu::CommandEncoder, texture: &Texture) {
+        encoder.copy_texture_to_texture(
+            self.attachment.as_image_copy(),
+            texture.raw.as_image_copy(),
+            self.target.size,
+        );
+    }
+}
+
+/// Texture the scene is rendered into mirrored below the reflective water
+/// surface, sampled by the water in the same frame.
+pub struct WaterReflection {
+    /// Target the mirrored camera renders into.
+    pub target: RenderTarget,
+    /// Texture behind the target, sampled by the water pass.
+    pub texture: Texture,
+}
+
+impl WaterReflection {
+    /// Creates the target and the texture of the given size, the size of
+    /// the window so that the water samples it at the position of its
+    /// pixels.
+    pub fn new(device: &wgpu::Device, size: wgpu::Extent3d) -> Self {
+        let raw = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("water_reflection_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: CameraTarget::FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let target = RenderTarget {
+            size,
+            view: raw.create_view(&Default::default()),
+            format: CameraTarget::FORMAT,
+        };
+        let texture = Texture {
+            view: raw.create_view(&Default::default()),
+            raw,
+            size,
+            sampler: SmlString::from("linear"),
+        };
+        Self { target, texture }
+    }
+}
@@ -0,0 +1,894 @@
+use crate::{
+    app::command::Command,
+    core::{
+        camera::Camera,
+        mesh::{LodGroup, MeshBundle},
+        particle::ParticleEmitter,
+        sprite::Sprite,
+        water::Water,
+        FxHashSet, Light, SmlString,
+    },
+    scene::{
+        Baked, Billboard, CustomShader, Entity, Name, Node, RenderLayer, Scene, Scripts, Tags,
+        Transient,
+    },
+};
+use legion::EntityStore;
+use pyo3::PyObject;
+use std::collections::VecDeque;
+
+/// Change of an entity recorded by the journal, with its state before and
+/// after the change.
+#[derive(Clone, Debug)]
+pub(super) enum Change {
+    /// Change of the node of the entity: its transform, visibility, shadows
+    /// or material override.
+    Node {
+        entity: Entity,
+        before: Node,
+        after: Node,
+    },
+    Light {
+        entity: Entity,
+        before: Light,
+        after: Light,
+    },
+    Name {
+        entity: Entity,
+        before: Option<SmlString>,
+        after: Option<SmlString>,
+    },
+    /// Tag added to the entity, or removed from it if not `added`.
+    Tag {
+        entity: Entity,
+        tag: SmlString,
+        added: bool,
+    },
+    /// Entity spawned, with the state of it and its descendants while the
+    /// spawning is undone.
+    Spawn {
+        entity: Entity,
+        removed: Vec<EntityState>,
+    },
+    /// Entity despawned with its descendants, with their state while the
+    /// despawning is done.
+    Despawn {
+        entity: Entity,
+        removed: Vec<EntityState>,
+    },
+}
+
+/// Node and components of a removed entity, to restore it with the same
+/// identifier.
+///
+/// The rigid bodies and colliders are not restored.
+#[derive(Clone, Debug)]
+pub(super) struct EntityState {
+    entity: Entity,
+    node: Node,
+    name: Option<Name>,
+    tags: Option<Tags>,
+    mesh: Option<MeshBundle>,
+    lod: Option<LodGroup>,
+    light: Option<Light>,
+    camera: Option<Camera>,
+    sprite: Option<Sprite>,
+    emitter: Option<ParticleEmitter>,
+    water: Option<Water>,
+    layer: Option<RenderLayer>,
+    shader: Option<CustomShader>,
+    billboard: Option<Billboard>,
+    scripts: Option<Vec<PyObject>>,
+    baked: bool,
+}
+
+impl EntityState {
+    /// Returns the mesh bundles the entity keeps alive.
+    fn meshes(&self) -> Vec<MeshBundle> {
+        Scene::referenced_meshes(self.lod.as_ref(), self.mesh.as_ref())
+    }
+}
+
+impl Change {
+    fn entity(&self) -> Entity {
+        match self {
+            Change::Node { entity, .. }
+            | Change::Light { entity, .. }
+            | Change::Name { entity, .. }
+            | Change::Tag { entity, .. }
+            | Change::Spawn { entity, .. }
+            | Change::Despawn { entity, .. } => *entity,
+        }
+    }
+}
+
+/// Journal of the changes made to the entities of a scene by the commands,
+/// to undo and redo them.
+///
+/// The changes are grouped into transactions, undone and redone at once; a
+/// change made outside of a transaction is a transaction of its own. The
+/// transforms, visibility, material overrides, lights, names and tags of the
+/// entities are recorded, as well as the spawned and despawned entities; the
+/// changes of the cameras, which follow the navigation, and the entities
+/// marked `Transient` are not.
+///
+/// Despawned entities keep their identifier, so that the changes recorded
+/// before apply again once they are restored. The journal keeps the meshes
+/// of the removed entities alive until their transactions are forgotten.
+#[derive(Debug, Default)]
+pub struct Journal {
+    /// Whether the changes are recorded, disabled by default.
+    enabled: bool,
+    /// Transactions to undo, the last one being the most recent.
+    undo: VecDeque<Vec<Change>>,
+    /// Undone transactions to redo, the last one being the last undone.
+    redo: Vec<Vec<Change>>,
+    /// Changes of the open transaction and the number of nested
+    /// transactions opened.
+    transaction: Option<(Vec<Change>, u32)>,
+    /// Meshes of the removed entities of the forgotten transactions, to be
+    /// released by the scene.
+    pub(super) released: Vec<MeshBundle>,
+}
+
+impl Journal {
+    /// Maximum number of transactions kept to be undone.
+    pub const CAPACITY: usize = 256;
+
+    /// Returns whether the changes are recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Returns whether there is a transaction to undo.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+            || self
+                .transaction
+                .as_ref()
+                .is_some_and(|(changes, _)| !changes.is_empty())
+    }
+
+    /// Returns whether there is an undone transaction to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Forgets all the recorded changes.
+    pub fn clear(&mut self) {
+        for changes in std::mem::take(&mut self.undo) {
+            self.forget(changes, false);
+        }
+        self.forget_redo();
+        if let Some((changes, _)) = self.transaction.take() {
+            self.forget(changes, false);
+        }
+    }
+
+    /// Forgets the changes of a transaction, releasing the meshes of the
+    /// removed entities it keeps alive: the despawned ones if it is done, the
+    /// spawned ones if it is `undone`.
+    fn forget(&mut self, changes: Vec<Change>, undone: bool) {
+        for change in changes {
+            match change {
+                Change::Spawn { removed, .. } if undone => {
+                    self.released
+                        .extend(removed.iter().flat_map(EntityState::meshes));
+                }
+                Change::Despawn { removed, .. } if !undone => {
+                    self.released
+                        .extend(removed.iter().flat_map(EntityState::meshes));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn forget_redo(&mut self) {
+        for changes in std::mem::take(&mut self.redo) {
+            self.forget(changes, true);
+        }
+    }
+
+    /// Opens a transaction, nested in the open one if any.
+    fn begin(&mut self) {
+        match &mut self.transaction {
+            Some((_, depth)) => *depth += 1,
+            None => self.transaction = Some((Vec::new(), 1)),
+        }
+    }
+
+    /// Closes the innermost open transaction, committing the changes once the
+    /// outermost one is closed.
+    fn end(&mut self) {
+        if let Some((_, depth)) = &mut self.transaction {
+            *depth -= 1;
+            if *depth == 0 {
+                self.close();
+            }
+        }
+    }
+
+    /// Commits the changes of the open transaction, whatever its depth.
+    fn close(&mut self) {
+        if let Some((changes, _)) = self.transaction.take() {
+            self.commit(changes);
+        }
+    }
+
+    fn commit(&mut self, changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.forget_redo();
+        self.push_undo(changes);
+    }
+
+    fn push_undo(&mut self, changes: Vec<Change>) {
+        if self.undo.len() == Self::CAPACITY {
+            if let Some(oldest) = self.undo.pop_front() {
+                self.forget(oldest, false);
+            }
+        }
+        self.undo.push_back(changes);
+    }
+
+    fn record(&mut self, change: Change) {
+        let Some((changes, _)) = &mut self.transaction else {
+            self.commit(vec![change]);
+            return;
+        };
+        // Successive changes of the node of an entity, e.g. while dragging
+        // it, are merged into one.
+        if let (
+            Change::Node { entity, after, .. },
+            Some(Change::Node {
+                entity: last,
+                after: last_after,
+                ..
+            }),
+        ) = (&change, changes.last_mut())
+        {
+            if entity.raw == last.raw {
+                *last_after = after.clone();
+                return;
+            }
+        }
+        changes.push(change);
+    }
+}
+
+impl Scene {
+    /// Starts or stops recording the changes of the entities to undo them.
+    /// The recorded changes are forgotten when stopping.
+    pub fn set_journal_enabled(&mut self, enabled: bool) {
+        self.journal.enabled = enabled;
+        if !enabled {
+            self.journal.clear();
+            self.release_journal_meshes();
+        }
+    }
+
+    /// Returns the journal of the changes of the entities.
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Reverts the changes of the last transaction, closing the open one
+    /// first. Returns false if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        self.journal.close();
+        let Some(mut changes) = self.journal.undo.pop_back() else {
+            self.release_journal_meshes();
+            return false;
+        };
+        for change in changes.iter_mut().rev() {
+            self.apply_change(change, true);
+        }
+        self.journal.redo.push(changes);
+        self.release_journal_meshes();
+        true
+    }
+
+    /// Applies again the changes of the last undone transaction. Returns
+    /// false if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        self.journal.close();
+        let Some(mut changes) = self.journal.redo.pop() else {
+            self.release_journal_meshes();
+            return false;
+        };
+        for change in &mut changes {
+            self.apply_change(change, false);
+        }
+        self.journal.push_undo(changes);
+        self.release_journal_meshes();
+        true
+    }
+
+    /// Releases the meshes kept alive by the forgotten transactions.
+    pub(super) fn release_journal_meshes(&mut self) {
+        for mesh in self.journal.released.drain(..) {
+            self.mesh_refs.push((mesh, false));
+        }
+    }
+
+    /// Handles the commands opening and closing transactions.
+    pub(super) fn journal_transaction(&mut self, cmd: &Command) {
+        match cmd {
+            Command::BeginTransaction => self.journal.begin(),
+            Command::EndTransaction => self.journal.end(),
+            _ => {}
+        }
+    }
+
+    /// Returns the changes the command may make, with the current state of
+    /// the entities as both their state before and after, to be completed by
+    /// `record_changes` once the command is applied.
+    pub(super) fn snapshot_changes(&self, cmd: &Command) -> Vec<Change> {
+        if !self.journal.enabled {
+            return Vec::new();
+        }
+        match cmd {
+            Command::Translate { entity, .. }
+            | Command::Rotate { entity, .. }
+            | Command::Scale { entity, .. }
+            | Command::SetTransform { entity, .. }
+            | Command::SetPosition { entity, .. }
+            | Command::SetRotation { entity, .. }
+            | Command::LookAt { entity, .. }
+            | Command::SetScale { entity, .. }
+            | Command::SetActive { entity, .. }
+            | Command::SetVisible { entity, .. }
+            | Command::SetCastShadows { entity, .. }
+            | Command::UseMaterial { entity, .. }
+            | Command::ClearMaterialOverride { entity } => {
+                self.node_change(*entity).into_iter().collect()
+            }
+            Command::SetVisibleByTag { tag, .. } => self
+                .entities_with_tag(tag)
+                .iter()
+                .filter_map(|entity| self.node_change(*entity))
+                .collect(),
+            Command::SetDirectionalLight { entity, .. } | Command::SetLightColor { entity, .. } => {
+                self.light(*entity)
+                    .map(|light| Change::Light {
+                        entity: *entity,
+                        before: light,
+                        after: light,
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            Command::SetName { entity, .. } if self.world.contains(entity.raw) => {
+                let name = self.name(*entity).map(SmlString::from);
+                vec![Change::Name {
+                    entity: *entity,
+                    before: name.clone(),
+                    after: name,
+                }]
+            }
+            Command::AddTag { entity, tag } if !self.has_tag(*entity, tag) => vec![Change::Tag {
+                entity: *entity,
+                tag: tag.clone(),
+                added: true,
+            }],
+            Command::RemoveTag { entity, tag } if self.has_tag(*entity, tag) => {
+                vec![Change::Tag {
+                    entity: *entity,
+                    tag: tag.clone(),
+                    added: false,
+                }]
+            }
+            Command::Spawned { entity }
+                if self.world.contains(entity.raw) && !self.is_transient(*entity) =>
+            {
+                vec![Change::Spawn {
+                    entity: *entity,
+                    removed: Vec::new(),
+                }]
+            }
+            Command::Despawn { entity } => self
+                .despawn_change(*entity, &mut FxHashSet::default())
+                .into_iter()
+                .collect(),
+            Command::DespawnByTag { tag } => {
+                // Tagged descendants of tagged entities are restored once.
+                let mut captured = FxHashSet::default();
+                self.entities_with_tag(tag)
+                    .iter()
+                    .filter_map(|entity| self.despawn_change(*entity, &mut captured))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Completes the changes with the state of the entities after the
+    /// command, and records the ones which changed something.
+    pub(super) fn record_changes(&mut self, changes: Vec<Change>) {
+        for mut change in changes {
+            let changed = match &mut change {
+                Change::Node {
+                    entity,
+                    before,
+                    after,
+                } => {
+                    *after = self.nodes[entity.node].clone();
+                    after != before
+                }
+                Change::Light {
+                    entity,
+                    before,
+                    after,
+                } => match self.light(*entity) {
+                    Some(light) => {
+                        *after = light;
+                        after != before
+                    }
+                    None => false,
+                },
+                Change::Name {
+                    entity,
+                    before,
+                    after,
+                } => {
+                    *after = self.name(*entity).map(SmlString::from);
+                    after != before
+                }
+                Change::Tag { entity, tag, added } => self.has_tag(*entity, tag) == *added,
+                Change::Spawn { .. } => true,
+                Change::Despawn { entity, removed } => {
+                    let despawned = !self.world.contains(entity.raw);
+                    if despawned {
+                        self.hold_meshes(removed);
+                    }
+                    despawned
+                }
+            };
+            if changed {
+                self.journal.record(change);
+            }
+        }
+    }
+
+    /// Restores the state of the entity before the change if `undo`, after it
+    /// otherwise. Changes of despawned entities are skipped.
+    fn apply_change(&mut self, change: &mut Change, undo: bool) {
+        match change {
+            Change::Spawn { entity, removed } if undo => {
+                *removed = self.remove_entities(*entity);
+                return;
+            }
+            Change::Despawn { entity, removed } if !undo => {
+                *removed = self.remove_entities(*entity);
+                return;
+            }
+            Change::Spawn { removed, .. } | Change::Despawn { removed, .. } => {
+                self.restore_entities(removed);
+                return;
+            }
+            _ => {}
+        }
+        if !self.world.contains(change.entity().raw) {
+            return;
+        }
+        match change {
+            Change::Node {
+                entity,
+                before,
+                after,
+            } => {
+                self.nodes[entity.node] = if undo { before } else { after }.clone();
+            }
+            Change::Light {
+                entity,
+                before,
+                after,
+            } => {
+                if let Some(mut entry) = self.world.entry(entity.raw) {
+                    entry.add_component(if undo { *before } else { *after });
+                }
+            }
+            Change::Name {
+                entity,
+                before,
+                after,
+            } => match if undo { before } else { after } {
+                Some(name) => self.set_name(*entity, name.clone()),
+                None => self.remove_name(*entity),
+            },
+            Change::Tag { entity, tag, added } => {
+                if *added != undo {
+                    self.add_tag(*entity, tag.clone());
+                } else {
+                    self.remove_tag(*entity, tag);
+                }
+            }
+            Change::Spawn { .. } | Change::Despawn { .. } => {}
+        }
+    }
+
+    /// Returns the despawning of the entity with the state of it and its
+    /// descendants, except the ones already `captured`. `None` if the entity
+    /// doesn't exist or is transient.
+    fn despawn_change(
+        &self,
+        entity: Entity,
+        captured: &mut FxHashSet<legion::Entity>,
+    ) -> Option<Change> {
+        if self.is_transient(entity) {
+            return None;
+        }
+        let mut removed = self.entity_states(entity);
+        removed.retain(|state| captured.insert(state.entity.raw));
+        (!removed.is_empty()).then_some(Change::Despawn { entity, removed })
+    }
+
+    /// Returns whether the entity exists and is marked `Transient`.
+    fn is_transient(&self, entity: Entity) -> bool {
+        self.world
+            .entry_ref(entity.raw)
+            .is_ok_and(|entry| entry.get_component::<Transient>().is_ok())
+    }
+
+    /// Returns the state of the entity and its descendants, parents first.
+    fn entity_states(&self, entity: Entity) -> Vec<EntityState> {
+        if !self.world.contains(entity.raw) {
+            return Vec::new();
+        }
+        let (_, mut entities) = self.subtree(entity);
+        entities.sort_unstable_by_key(|entity| entity.node.0);
+        entities
+            .into_iter()
+            .filter_map(|entity| {
+                let entry = self.world.entry_ref(entity.raw).ok()?;
+                Some(EntityState {
+                    entity,
+                    node: self.nodes[entity.node].clone(),
+                    name: entry.get_component::<Name>().ok().cloned(),
+                    tags: entry.get_component::<Tags>().ok().cloned(),
+                    mesh: entry.get_component::<MeshBundle>().ok().copied(),
+                    lod: entry.get_component::<LodGroup>().ok().cloned(),
+                    light: entry.get_component::<Light>().ok().copied(),
+                    camera: entry.get_component::<Camera>().ok().cloned(),
+                    sprite: entry.get_component::<Sprite>().ok().copied(),
+                    emitter: entry.get_component::<ParticleEmitter>().ok().copied(),
+                    water: entry.get_component::<Water>().ok().copied(),
+                    layer: entry.get_component::<RenderLayer>().ok().copied(),
+                    shader: entry.get_component::<CustomShader>().ok().cloned(),
+                    billboard: entry.get_component::<Billboard>().ok().copied(),
+                    scripts: entry
+                        .get_component::<Scripts>()
+                        .ok()
+                        .map(|scripts| scripts.0.clone()),
+                    baked: entry.get_component::<Baked>().is_ok(),
+                })
+            })
+            .collect()
+    }
+
+    /// Removes the entity and its descendants, returning their state. The
+    /// journal keeps their meshes alive meanwhile.
+    fn remove_entities(&mut self, entity: Entity) -> Vec<EntityState> {
+        let removed = self.entity_states(entity);
+        self.despawn(entity);
+        self.hold_meshes(&removed);
+        removed
+    }
+
+    /// Keeps the meshes of the removed entities alive.
+    fn hold_meshes(&mut self, removed: &[EntityState]) {
+        for state in removed {
+            for mesh in state.meshes() {
+                self.mesh_refs.push((mesh, true));
+            }
+        }
+    }
+
+    /// Spawns again the removed entities with their identifiers, nodes and
+    /// components. The journal's references to their meshes are passed on to
+    /// the entities.
+    fn restore_entities(&mut self, removed: &[EntityState]) {
+        for state in removed {
+            let entity = state.entity;
+            if self.world.contains(entity.raw) {
+                continue;
+            }
+            self.world.push_with_id(entity.raw, (entity.node,));
+            let mut entry = self.world.entry(entity.raw).unwrap();
+            if let Some(name) = &state.name {
+                entry.add_component(name.clone());
+                if let Some(label) = &name.label {
+                    self.names.entry(label.clone()).or_default().push(entity);
+                }
+            }
+            if let Some(tags) = &state.tags {
+                entry.add_component(tags.clone());
+                for tag in tags.0.iter() {
+                    self.tags.entry(tag.clone()).or_default().push(entity);
+                }
+            }
+            if let Some(mesh) = state.mesh {
+                entry.add_component(mesh);
+                self.respawned.push((mesh, entity.node));
+            }
+            if let Some(lod) = &state.lod {
+                entry.add_component(lod.clone());
+            }
+            if let Some(light) = state.light {
+                entry.add_component(light);
+            }
+            if let Some(camera) = &state.camera {
+                entry.add_component(camera.clone());
+            }
+            if let Some(sprite) = state.sprite {
+                entry.add_component(sprite);
+            }
+            if let Some(emitter) = state.emitter {
+                entry.add_component(emitter);
+            }
+            if let Some(water) = state.water {
+                entry.add_component(water);
+            }
+            if let Some(layer) = state.layer {
+                entry.add_component(layer);
+            }
+            if let Some(shader) = &state.shader {
+                entry.add_component(shader.clone());
+            }
+            if let Some(billboard) = state.billboard {
+                entry.add_component(billboard);
+            }
+            if let Some(scripts) = &state.scripts {
+                entry.add_component(Scripts(scripts.clone()));
+            }
+            if state.baked {
+                entry.add_component(Baked);
+            }
+            self.nodes[entity.node] = state.node.clone();
+        }
+    }
+
+    /// Returns the state of the node of the entity, `None` if the entity
+    /// doesn't exist or is a camera.
+    fn node_change(&self, entity: Entity) -> Option<Change> {
+        let entry = self.world.entry_ref(entity.raw).ok()?;
+        if entry.get_component::<Camera>().is_ok() {
+            return None;
+        }
+        let node = self.nodes[entity.node].clone();
+        Some(Change::Node {
+            entity,
+            before: node.clone(),
+            after: node,
+        })
+    }
+
+    fn light(&self, entity: Entity) -> Option<Light> {
+        let entry = self.world.entry_ref(entity.raw).ok()?;
+        entry.get_component::<Light>().ok().copied()
+    }
+
+    fn has_tag(&self, entity: Entity, tag: &str) -> bool {
+        self.entities_with_tag(tag)
+            .iter()
+            .any(|tagged| tagged.raw == entity.raw)
+    }
+
+    /// Removes the name of the entity, if any.
+    fn remove_name(&mut self, entity: Entity) {
+        let Some(mut entry) = self.world.entry(entity.raw) else {
+            return;
+        };
+        if let Ok(name) = entry.get_component::<Name>() {
+            if let Some(name) = &name.label {
+                Self::unindex_name(&mut self.names, name, entity);
+            }
+        }
+        entry.remove_component::<Name>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::{
+            assets::Handle,
+            mesh::{AestheticBundle, MeshBundle},
+            Color,
+        },
+        scene::NodeIdx,
+    };
+    use glam::Vec3;
+    use std::marker::PhantomData;
+
+    fn scene() -> Scene {
+        let (sender, receiver) = crossbeam_channel::unbounded::<Command>();
+        let mut scene = Scene::new(sender, receiver);
+        scene.set_journal_enabled(true);
+        scene
+    }
+
+    fn position(scene: &Scene, entity: Entity) -> Vec3 {
+        scene.nodes[entity.node].transform().translation
+    }
+
+    fn mesh_bundle() -> MeshBundle {
+        fn handle<T: crate::core::assets::Asset>() -> Handle<T> {
+            Handle {
+                generation: 0,
+                index: 1,
+                marker: PhantomData,
+            }
+        }
+        MeshBundle {
+            mesh: handle(),
+            aesthetic: AestheticBundle {
+                textures: handle(),
+                materials: handle(),
+            },
+        }
+    }
+
+    /// Returns the number of references to the mesh taken minus the ones
+    /// released since the last call.
+    fn mesh_ref_count(scene: &mut Scene) -> i32 {
+        scene
+            .take_mesh_refs()
+            .iter()
+            .map(|(_, referenced)| if *referenced { 1 } else { -1 })
+            .sum()
+    }
+
+    #[test]
+    fn changes_are_undone_and_redone() {
+        let mut scene = scene();
+        let entity = scene.spawn(NodeIdx::root(), ());
+        let sender = scene.cmd_sender().clone();
+        sender
+            .send(Command::SetPosition {
+                entity,
+                position: Vec3::X,
+                world: false,
+            })
+            .unwrap();
+        sender
+            .send(Command::SetName {
+                entity,
+                name: SmlString::from("tree"),
+            })
+            .unwrap();
+        scene.prepare(&mut None);
+        assert!(scene.undo());
+        assert!(scene.find_entity("tree").is_none());
+        assert!(scene.undo());
+        assert_eq!(position(&scene, entity), Vec3::ZERO);
+        assert!(!scene.undo());
+
+        assert!(scene.redo());
+        assert_eq!(position(&scene, entity), Vec3::X);
+        assert!(scene.redo());
+        assert!(scene.find_entity("tree").is_some());
+        assert!(!scene.redo());
+    }
+
+    #[test]
+    fn transactions_are_undone_at_once() {
+        let mut scene = scene();
+        let entity = scene.spawn(NodeIdx::root(), ());
+        let sender = scene.cmd_sender().clone();
+        sender.send(Command::BeginTransaction).unwrap();
+        for i in 1..=3 {
+            sender
+                .send(Command::SetPosition {
+                    entity,
+                    position: Vec3::X * i as f32,
+                    world: false,
+                })
+                .unwrap();
+        }
+        sender.send(Command::EndTransaction).unwrap();
+        scene.prepare(&mut None);
+        assert_eq!(scene.journal().undo.back().map(Vec::len), Some(1));
+        assert!(scene.undo());
+        assert_eq!(position(&scene, entity), Vec3::ZERO);
+        assert!(!scene.journal().can_undo());
+    }
+
+    #[test]
+    fn spawns_are_undone_and_redone() {
+        let mut scene = scene();
+        let mesh = mesh_bundle();
+        let entity = scene.spawn(NodeIdx::root(), (mesh,));
+        scene
+            .cmd_sender()
+            .send(Command::SetPosition {
+                entity,
+                position: Vec3::X,
+                world: false,
+            })
+            .unwrap();
+        scene.prepare(&mut None);
+        assert_eq!(mesh_ref_count(&mut scene), 1);
+
+        assert!(scene.undo());
+        assert!(scene.undo());
+        assert!(!scene.world.contains(entity.raw));
+        assert_eq!(scene.take_despawned(), vec![(mesh, entity.node)]);
+        // The journal keeps the mesh alive to redo the spawning.
+        assert_eq!(mesh_ref_count(&mut scene), 0);
+
+        assert!(scene.redo());
+        assert!(scene.world.contains(entity.raw));
+        assert_eq!(scene.take_respawned(), vec![(mesh, entity.node)]);
+        assert_eq!(mesh_ref_count(&mut scene), 0);
+        assert!(scene.redo());
+        assert_eq!(position(&scene, entity), Vec3::X);
+
+        // Spawning again forgets the undone spawning, releasing its mesh.
+        assert!(scene.undo());
+        assert!(scene.undo());
+        scene.take_mesh_refs();
+        scene.spawn(NodeIdx::root(), ());
+        scene.prepare(&mut None);
+        assert!(!scene.journal().can_redo());
+        assert_eq!(mesh_ref_count(&mut scene), -1);
+    }
+
+    #[test]
+    fn despawns_are_undone_and_redone() {
+        let mut scene = scene();
+        let mesh = mesh_bundle();
+        let parent = scene.spawn(NodeIdx::root(), (mesh,));
+        let child = scene.spawn(parent.node, (Light::Point { color: Color::RED },));
+        scene.set_name(parent, SmlString::from("house"));
+        scene.add_tag(child, SmlString::from("lamp"));
+        scene.nodes[parent.node].set_visible(true);
+        scene.prepare(&mut None);
+        scene.journal.clear();
+        scene.take_mesh_refs();
+        scene
+            .cmd_sender()
+            .send(Command::Despawn { entity: parent })
+            .unwrap();
+        scene.prepare(&mut None);
+        assert!(!scene.world.contains(parent.raw));
+        assert!(!scene.world.contains(child.raw));
+        assert_eq!(mesh_ref_count(&mut scene), 0);
+
+        assert!(scene.undo());
+        assert!(scene.world.contains(parent.raw));
+        assert_eq!(scene.find_entity("house").map(|e| e.raw), Some(parent.raw));
+        assert_eq!(scene.entities_with_tag("lamp").len(), 1);
+        assert_eq!(scene.light(child), Some(Light::Point { color: Color::RED }));
+        assert!(scene.nodes[parent.node].is_active());
+        assert!(scene.nodes[parent.node].is_visible());
+        assert_eq!(scene.take_respawned(), vec![(mesh, parent.node)]);
+        assert_eq!(mesh_ref_count(&mut scene), 0);
+
+        assert!(scene.redo());
+        assert!(!scene.world.contains(parent.raw));
+        assert!(scene.find_entity("house").is_none());
+        assert!(scene.entities_with_tag("lamp").is_empty());
+
+        // Forgetting the despawning releases the mesh it keeps alive.
+        scene.set_journal_enabled(false);
+        assert_eq!(mesh_ref_count(&mut scene), -1);
+    }
+
+    #[test]
+    fn transient_entities_are_not_recorded() {
+        let mut scene = scene();
+        let entity = scene.spawn(NodeIdx::root(), ());
+        scene.mark_transient(entity);
+        scene
+            .cmd_sender()
+            .send(Command::Despawn { entity })
+            .unwrap();
+        scene.prepare(&mut None);
+        assert!(!scene.world.contains(entity.raw));
+        assert!(!scene.journal().can_undo());
+    }
+}
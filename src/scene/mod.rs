@@ -1,17 +1,42 @@
+mod journal;
 mod node;
+mod prefab;
+mod script;
+pub use journal::*;
 pub use node::*;
+pub use prefab::*;
+pub use script::*;
 
 use crossbeam_channel::{Receiver, Sender};
 use glam::{Mat4, Quat, Vec3};
-use std::fmt::{Debug, Formatter};
+use std::{
+    collections::BTreeMap,
+    fmt::{Debug, Formatter},
+    hash::{Hash, Hasher},
+    ops::Bound,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
 
+#[cfg(feature = "physics")]
+use crate::physics::{Collider, ColliderShape, Collision, PhysicsWorld, RigidBody};
 use crate::{
     app::command::{Command, CommandReceiver, CommandSender},
-    core::{camera::Camera, ConcatOrder, Light},
+    core::{
+        camera::{AutoExposure, Backdrop, Camera},
+        gizmo::Gizmo,
+        mesh::{LodGroup, MeshBundle},
+        sky::Sky,
+        Color, ConcatOrder, FxHashMap, FxHashSet, FxHasher, Light, LightKind, SmlString,
+    },
+    Labeled,
 };
 use legion::{storage::IntoComponentSource, EntityStore, IntoQuery, World};
 use numpy as np;
-use pyo3::Python;
+use numpy::array;
+use pyo3::{Py, PyObject, PyRef, PyResult, Python};
+#[cfg(feature = "physics")]
+use rapier3d::prelude::SharedShape;
 
 /// Entity in a scene.
 #[derive(Clone, Copy, Debug)]
@@ -22,12 +47,140 @@ pub struct Entity {
     pub(crate) node: NodeIdx,
 }
 
+/// Name of an entity.
+///
+/// Stored as a component of the entity; the inner value is not used.
+pub type Name = Labeled<()>;
+
+/// Tags attached to an entity.
+#[derive(Clone, Debug, Default)]
+pub struct Tags(pub(crate) FxHashSet<SmlString>);
+
+/// Render layer of an entity.
+///
+/// Entities are drawn in ascending layer order, entities without this
+/// component are on layer 0. Cameras only draw the layers enabled in their
+/// layer mask.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RenderLayer(pub u8);
+
+impl RenderLayer {
+    /// Maximum number of render layers.
+    pub const COUNT: u8 = 32;
+
+    /// Returns true if the layer is enabled in the given layer mask.
+    pub fn is_in(&self, mask: u32) -> bool {
+        mask & (1 << self.0) != 0
+    }
+}
+
+/// Custom material shader used to draw an entity instead of the
+/// Blinn-Phong shader.
+///
+/// The shader must expose the same entry points and bindings as `blph.wgsl`.
+/// It may also read the custom vertex attributes of the meshes at their
+/// location, see `Mesh::set_attribute`.
+///
+/// Besides the camera matrices, the globals of the pass give the position of
+/// the camera in world space (`camera_pos`), and the seconds elapsed since
+/// the renderer started (`time`) and since the previous frame
+/// (`delta_time`).
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CustomShader(pub PathBuf);
+
+impl CustomShader {
+    /// Returns the label of the pipelines created from this shader.
+    pub fn label(&self) -> String {
+        format!("custom:{}", self.0.display())
+    }
+}
+
+/// Marker of the entities drawing static geometry baked from other entities,
+/// see `Renderer::bake_static_batch`.
+///
+/// Ray casts and analyses skip these entities and see the original ones.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Baked;
+
+/// Marker of the entities the application spawns for its own use, e.g. the
+/// ghost of a placement or the default light.
+///
+/// The journal doesn't record their spawning and despawning.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Transient;
+
+/// Billboard component making an entity always face the main camera.
+///
+/// The entity is rotated so that its local +Z axis points towards the camera.
+#[pyo3::pyclass]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Billboard {
+    /// The entity rotates freely to face the camera.
+    Spherical,
+    /// The entity only rotates around the world Y axis.
+    Cylindrical,
+}
+
 /// Entity with a command sender.
 #[pyo3::pyclass]
 #[derive(Clone, Debug)]
 pub struct PyEntity {
     pub entity: Entity,
     pub cmd_sender: Sender<Command>,
+    pub scene: Arc<RwLock<Scene>>,
+}
+
+/// Handle to one of the scenes of the application, see `App.create_scene`.
+///
+/// The entities of a scene can only be accessed while it is the active
+/// scene, the commands sent to them in the meantime are applied once it is
+/// active again.
+#[pyo3::pyclass]
+#[pyo3(name = "Scene")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PyScene {
+    pub(crate) id: u32,
+}
+
+#[pyo3::pymethods]
+impl PyScene {
+    /// Identifier of the scene, the first scene of the application is 0.
+    #[getter]
+    pub fn get_id(&self) -> u32 {
+        self.id
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __hash__(&self) -> u64 {
+        self.id as u64
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Scene({})", self.id)
+    }
+}
+
+/// Converts a vector to a 3x1 numpy array.
+pub(crate) fn vec3_to_py(v: Vec3) -> Py<np::PyArray2<f32>> {
+    Python::with_gil(|py| {
+        np::PyArray2::<f32>::from_array(py, &array![[v.x], [v.y], [v.z]]).to_owned()
+    })
+}
+
+/// Converts a matrix to a 4x4 numpy array in row-major order.
+pub(crate) fn mat4_to_py(m: Mat4) -> Py<np::PyArray2<f32>> {
+    Python::with_gil(|py| {
+        let [x, y, z, w] = m.transpose().to_cols_array_2d();
+        np::PyArray2::<f32>::from_array(py, &array![x, y, z, w]).to_owned()
+    })
+}
+
+/// Converts a quaternion to a numpy array in the order of (w, x, y, z).
+fn quat_to_py(q: Quat) -> Py<np::PyArray1<f32>> {
+    Python::with_gil(|py| np::PyArray1::<f32>::from_slice(py, &[q.w, q.x, q.y, q.z]).to_owned())
 }
 
 #[pyo3::pymethods]
@@ -75,6 +228,103 @@ impl PyEntity {
         });
     }
 
+    /// Returns the position of the entity relative to its parent.
+    pub fn get_position(&self) -> Py<np::PyArray2<f32>> {
+        vec3_to_py(self.local_transform().translation)
+    }
+
+    /// Returns the rotation of the entity relative to its parent as a
+    /// quaternion (w, x, y, z).
+    pub fn get_rotation_quat(&self) -> Py<np::PyArray1<f32>> {
+        quat_to_py(self.local_transform().rotation)
+    }
+
+    /// Returns the scale of the entity relative to its parent.
+    pub fn get_scale(&self) -> Py<np::PyArray2<f32>> {
+        vec3_to_py(self.local_transform().scale)
+    }
+
+    /// Returns the position of the entity in world space.
+    pub fn get_world_position(&self) -> Py<np::PyArray2<f32>> {
+        vec3_to_py(self.world_transform().translation)
+    }
+
+    /// Returns the rotation of the entity in world space as a quaternion
+    /// (w, x, y, z).
+    pub fn get_world_rotation_quat(&self) -> Py<np::PyArray1<f32>> {
+        quat_to_py(self.world_transform().rotation)
+    }
+
+    /// Returns the scale of the entity in world space.
+    pub fn get_world_scale(&self) -> Py<np::PyArray2<f32>> {
+        vec3_to_py(self.world_transform().scale)
+    }
+
+    /// Sets the position of the entity relative to its parent.
+    pub fn set_position(&self, position: &np::PyArray2<f32>) {
+        self.send_position(position, false);
+    }
+
+    /// Sets the position of the entity in world space.
+    pub fn set_world_position(&self, position: &np::PyArray2<f32>) {
+        self.send_position(position, true);
+    }
+
+    /// Sets the rotation of the entity relative to its parent from a
+    /// quaternion (w, x, y, z).
+    pub fn set_rotation_quat(&self, quat: [f32; 4]) {
+        self.send_rotation(quat, false);
+    }
+
+    /// Sets the rotation of the entity in world space from a quaternion
+    /// (w, x, y, z).
+    pub fn set_world_rotation_quat(&self, quat: [f32; 4]) {
+        self.send_rotation(quat, true);
+    }
+
+    /// Rotates the entity so that its -Z axis points to the target position
+    /// given in world space.
+    #[pyo3(signature = (target, up=None))]
+    pub fn look_at(&self, target: &np::PyArray2<f32>, up: Option<&np::PyArray2<f32>>) {
+        Python::with_gil(|_py| {
+            let target = Vec3::from_slice(target.readonly().as_slice().unwrap());
+            let up = up
+                .map(|up| Vec3::from_slice(up.readonly().as_slice().unwrap()))
+                .unwrap_or(Vec3::Y);
+            self.cmd_sender
+                .send(Command::LookAt {
+                    entity: self.entity,
+                    target,
+                    up,
+                })
+                .unwrap();
+        });
+    }
+
+    /// Makes the entity always face the main camera. Passing `None` restores
+    /// the normal behaviour.
+    pub fn set_billboard(&self, billboard: Option<Billboard>) {
+        self.cmd_sender
+            .send(Command::SetBillboard {
+                entity: self.entity,
+                billboard,
+            })
+            .unwrap();
+    }
+
+    /// Sets the scale of the entity relative to its parent.
+    pub fn set_scale(&self, scale: &np::PyArray2<f32>) {
+        Python::with_gil(|_py| {
+            let scale = Vec3::from_slice(scale.readonly().as_slice().unwrap());
+            self.cmd_sender
+                .send(Command::SetScale {
+                    entity: self.entity,
+                    scale,
+                })
+                .unwrap();
+        });
+    }
+
     pub fn rotate(&self, rotation: &np::PyArray2<f32>, order: ConcatOrder) {
         Python::with_gil(|_py| {
             let rot = Mat4::from_cols_slice(rotation.readonly().as_slice().unwrap()).transpose();
@@ -138,6 +388,314 @@ impl PyEntity {
             .unwrap();
     }
 
+    /// Sets the name of the entity. The name can be used later to look up
+    /// the entity with `find_entity` or `find_entities`.
+    pub fn set_name(&self, name: &str) {
+        self.cmd_sender
+            .send(Command::SetName {
+                entity: self.entity,
+                name: SmlString::from(name),
+            })
+            .unwrap();
+    }
+
+    /// Sets the render layer of the entity. Entities on higher layers are
+    /// drawn after entities on lower layers. Layers range from 0 to 31.
+    pub fn set_layer(&self, layer: u8) {
+        self.cmd_sender
+            .send(Command::SetRenderLayer {
+                entity: self.entity,
+                layer,
+            })
+            .unwrap();
+    }
+
+    /// Draws the entity with a custom material shader loaded from the given
+    /// WGSL file, or with the default shader if `None`. The shader is
+    /// reloaded when the file is modified.
+    pub fn set_shader(&self, path: Option<&str>) {
+        self.cmd_sender
+            .send(Command::SetCustomShader {
+                entity: self.entity,
+                path: path.map(PathBuf::from),
+            })
+            .unwrap();
+    }
+
+    /// Sets the layers visible to the camera. Bit `i` of the mask enables
+    /// layer `i`. Does nothing if the entity is not a camera.
+    pub fn set_layer_mask(&self, mask: u32) {
+        self.cmd_sender
+            .send(Command::SetLayerMask {
+                entity: self.entity,
+                mask,
+            })
+            .unwrap();
+    }
+
+    /// Adds a rigid body to the entity, simulated from its current world
+    /// transform. The body is fixed if the mass is not positive.
+    ///
+    /// Colliders added afterwards are attached to the body.
+    #[cfg(feature = "physics")]
+    #[pyo3(signature = (mass=1.0))]
+    pub fn add_rigid_body(&self, mass: f32) {
+        self.cmd_sender
+            .send(Command::AddRigidBody {
+                entity: self.entity,
+                mass,
+            })
+            .unwrap();
+    }
+
+    /// Adds a collider enclosing the mesh of the entity.
+    #[cfg(feature = "physics")]
+    #[pyo3(signature = (shape=ColliderShape::Aabb))]
+    pub fn add_collider(&self, shape: ColliderShape) {
+        self.cmd_sender
+            .send(Command::AddCollider {
+                entity: self.entity,
+                shape,
+            })
+            .unwrap();
+    }
+
+    /// Fixes the aspect ratio of the image of the camera, letterboxing it in
+    /// the window, or makes it follow the window if `None`. Does nothing if
+    /// the entity is not a camera.
+    #[pyo3(signature = (aspect=None))]
+    pub fn set_fixed_aspect(&self, aspect: Option<f32>) {
+        self.cmd_sender
+            .send(Command::SetFixedAspect {
+                entity: self.entity,
+                aspect,
+            })
+            .unwrap();
+    }
+
+    /// Sets the vertical field of view of the camera in degrees, from the
+    /// next frame on. Does nothing if the entity is not a camera or if its
+    /// projection is orthographic.
+    pub fn set_fov(&self, fov: f32) -> PyResult<()> {
+        if !(fov > 0.0 && fov < 180.0) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The field of view must be between 0 and 180 degrees.",
+            ));
+        }
+        self.cmd_sender
+            .send(Command::SetCameraParams {
+                entity: self.entity,
+                fov: Some(fov),
+                near: None,
+                far: None,
+            })
+            .unwrap();
+        Ok(())
+    }
+
+    /// Sets the distances of the near and far clipping planes of the camera,
+    /// from the next frame on. The far plane may be infinitely far for
+    /// perspective projections. Does nothing if the entity is not a camera.
+    pub fn set_clip_planes(&self, near: f32, far: f32) -> PyResult<()> {
+        if !(near > 0.0 && far > near) {
+            return Err(pyo3::exceptions::PyValueError::new_err(
+                "The clipping planes must satisfy 0 < near < far.",
+            ));
+        }
+        self.cmd_sender
+            .send(Command::SetCameraParams {
+                entity: self.entity,
+                fov: None,
+                near: Some(near),
+                far: Some(far),
+            })
+            .unwrap();
+        Ok(())
+    }
+
+    /// Sets the background color of the camera and removes its gradient or
+    /// image background. Does nothing if the entity is not a camera.
+    pub fn set_background(&self, color: Color) {
+        self.cmd_sender
+            .send(Command::SetBackground {
+                entity: self.entity,
+                color,
+            })
+            .unwrap();
+        self.cmd_sender
+            .send(Command::SetBackdrop {
+                entity: self.entity,
+                backdrop: None,
+            })
+            .unwrap();
+    }
+
+    /// Draws a vertical gradient behind the scene seen by the camera. Does
+    /// nothing if the entity is not a camera.
+    pub fn set_background_gradient(&self, top: Color, bottom: Color) {
+        self.cmd_sender
+            .send(Command::SetBackdrop {
+                entity: self.entity,
+                backdrop: Some(Backdrop::Gradient { top, bottom }),
+            })
+            .unwrap();
+    }
+
+    /// Draws an image covering the screen behind the scene seen by the
+    /// camera. Does nothing if the entity is not a camera.
+    pub fn set_background_image(&self, path: &str) {
+        self.cmd_sender
+            .send(Command::SetBackdrop {
+                entity: self.entity,
+                backdrop: Some(Backdrop::Image(PathBuf::from(path))),
+            })
+            .unwrap();
+    }
+
+    /// Draws an analytical sky behind the scene seen by the camera, with the
+    /// sun disk and the ground below the horizon. Does nothing if the entity
+    /// is not a camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `turbidity` - Haziness of the atmosphere, from 2 for a clear sky to
+    ///   10 for a hazy one.
+    /// * `exposure` - Scale of the brightness of the sky.
+    /// * `sun_direction` - Direction towards the sun; follows the day/night
+    ///   cycle of the main camera if enabled.
+    /// * `ground` - Color of the ground below the horizon.
+    #[pyo3(signature = (turbidity=3.0, exposure=1.0, sun_direction=None, ground=None))]
+    pub fn set_background_sky(
+        &self,
+        turbidity: f32,
+        exposure: f32,
+        sun_direction: Option<&np::PyArray2<f32>>,
+        ground: Option<Color>,
+    ) {
+        let mut sky = Sky {
+            turbidity,
+            exposure,
+            ..Default::default()
+        };
+        if let Some(sun) = sun_direction {
+            sky.sun = Vec3::from_slice(sun.readonly().as_slice().unwrap()).normalize_or(sky.sun);
+        }
+        if let Some(ground) = ground {
+            sky.ground = ground;
+        }
+        self.cmd_sender
+            .send(Command::SetBackdrop {
+                entity: self.entity,
+                backdrop: Some(Backdrop::Sky(sky)),
+            })
+            .unwrap();
+    }
+
+    /// Sets the exposure of the camera in EV: the lit colors are scaled by
+    /// `2^ev`. With auto-exposure, it compensates the adapted exposure. Does
+    /// nothing if the entity is not a camera.
+    pub fn set_exposure(&self, ev: f32) {
+        self.cmd_sender
+            .send(Command::SetExposure {
+                entity: self.entity,
+                ev,
+            })
+            .unwrap();
+    }
+
+    /// Adapts the exposure of the camera over time to the average luminance
+    /// of the previous frames, only for the main window. Does nothing if the
+    /// entity is not a camera.
+    ///
+    /// # Arguments
+    ///
+    /// * `enabled` - Disables the auto-exposure if false.
+    /// * `key` - Average luminance the frames are brought to.
+    /// * `speed` - Rate of the adaptation per second.
+    /// * `min_ev` - Lowest adapted exposure in EV.
+    /// * `max_ev` - Highest adapted exposure in EV.
+    #[pyo3(signature = (enabled=true, key=0.18, speed=1.5, min_ev=-4.0, max_ev=4.0))]
+    pub fn set_auto_exposure(&self, enabled: bool, key: f32, speed: f32, min_ev: f32, max_ev: f32) {
+        let auto = enabled.then_some(AutoExposure {
+            key,
+            speed,
+            min_ev: min_ev.min(max_ev),
+            max_ev: max_ev.max(min_ev),
+        });
+        self.cmd_sender
+            .send(Command::SetAutoExposure {
+                entity: self.entity,
+                auto,
+            })
+            .unwrap();
+    }
+
+    /// Adds a tag to the entity. An entity can have multiple tags.
+    pub fn add_tag(&self, tag: &str) {
+        self.cmd_sender
+            .send(Command::AddTag {
+                entity: self.entity,
+                tag: SmlString::from(tag),
+            })
+            .unwrap();
+    }
+
+    /// Removes a tag from the entity.
+    pub fn remove_tag(&self, tag: &str) {
+        self.cmd_sender
+            .send(Command::RemoveTag {
+                entity: self.entity,
+                tag: SmlString::from(tag),
+            })
+            .unwrap();
+    }
+
+    /// Attaches a script to the entity: an object whose method
+    /// `update(entity, input, dt)` is called each frame with the entity,
+    /// after the camera controls and before the `on_update` event.
+    ///
+    /// The scripts run by node order, the ones of a parent before the ones
+    /// of its children, and the scripts of an entity in the order they were
+    /// attached. They stop with the entity.
+    pub fn attach_script(&self, py: Python<'_>, script: PyObject) -> PyResult<()> {
+        if !script.as_ref(py).hasattr("update")? {
+            return Err(pyo3::exceptions::PyTypeError::new_err(
+                "A script must have an `update(entity, input, dt)` method.",
+            ));
+        }
+        self.cmd_sender
+            .send(Command::AttachScript {
+                entity: self.entity,
+                script,
+            })
+            .unwrap();
+        Ok(())
+    }
+
+    /// Detaches a script attached with `attach_script`.
+    pub fn detach_script(&self, script: PyObject) {
+        self.cmd_sender
+            .send(Command::DetachScript {
+                entity: self.entity,
+                script,
+            })
+            .unwrap();
+    }
+
+    /// Returns the scripts attached to the entity, in the order they run.
+    pub fn get_scripts(&self, py: Python<'_>) -> Vec<PyObject> {
+        self.scene.read().unwrap().scripts_of(py, self.entity)
+    }
+
+    /// Removes the entity and all its descendants from the scene.
+    pub fn despawn(&self) {
+        self.cmd_sender
+            .send(Command::Despawn {
+                entity: self.entity,
+            })
+            .unwrap();
+    }
+
     pub fn clear_material_override(&self) {
         self.cmd_sender
             .send(Command::ClearMaterialOverride {
@@ -157,6 +715,86 @@ impl PyEntity {
                 .unwrap();
         });
     }
+
+    /// Sets the color of the light attached to the entity.
+    pub fn set_light_color(&self, color: Color) {
+        self.cmd_sender
+            .send(Command::SetLightColor {
+                entity: self.entity,
+                color,
+            })
+            .unwrap();
+    }
+
+    /// Sets whether the light attached to the entity casts shadows. Only
+    /// directional lights cast shadows, each into its own shadow map.
+    pub fn set_casts_shadows(&self, casts_shadows: bool) {
+        self.set_cast_shadows(casts_shadows);
+    }
+
+    /// Returns the kind of the light attached to the entity, `None` if the
+    /// entity has no light.
+    pub fn get_light_kind(&self) -> Option<LightKind> {
+        let scene = self.scene.read().unwrap();
+        let entry = scene.world.entry_ref(self.entity.raw).ok()?;
+        let light = entry.get_component::<Light>().ok()?;
+        Some(light.kind())
+    }
+
+    pub fn __repr__(&self) -> String {
+        let scene = self.scene.read().unwrap();
+        match scene.name(self.entity) {
+            Some(name) => format!("PyEntity({:?}, name='{}')", self.entity.raw, name),
+            None => format!("PyEntity({:?})", self.entity.raw),
+        }
+    }
+
+    /// Entities are equal if they refer to the same entity of the scene.
+    pub fn __eq__(&self, other: PyRef<Self>) -> bool {
+        self.entity.raw == other.entity.raw
+    }
+
+    pub fn __hash__(&self) -> u64 {
+        let mut hasher = FxHasher::default();
+        self.entity.raw.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Implementation of the methods only available to Rust.
+impl PyEntity {
+    /// Returns the local transform of the entity.
+    fn local_transform(&self) -> Transform {
+        *self.scene.read().unwrap().nodes[self.entity.node].transform()
+    }
+
+    /// Returns the world transform of the entity.
+    fn world_transform(&self) -> Transform {
+        self.scene.read().unwrap().nodes.world(self.entity.node)
+    }
+
+    fn send_position(&self, position: &np::PyArray2<f32>, world: bool) {
+        Python::with_gil(|_py| {
+            let position = Vec3::from_slice(position.readonly().as_slice().unwrap());
+            self.cmd_sender
+                .send(Command::SetPosition {
+                    entity: self.entity,
+                    position,
+                    world,
+                })
+                .unwrap();
+        });
+    }
+
+    fn send_rotation(&self, [w, x, y, z]: [f32; 4], world: bool) {
+        self.cmd_sender
+            .send(Command::SetRotation {
+                entity: self.entity,
+                rotation: Quat::from_xyzw(x, y, z, w).normalize(),
+                world,
+            })
+            .unwrap();
+    }
 }
 
 /// Scene graph.
@@ -165,6 +803,36 @@ pub struct Scene {
     pub(crate) world: World,
     /// Scene graph nodes.
     pub(crate) nodes: Nodes,
+    /// Index of entities by name. Names are not required to be unique.
+    names: BTreeMap<SmlString, Vec<Entity>>,
+    /// Index of entities by tag.
+    tags: FxHashMap<SmlString, Vec<Entity>>,
+    /// Mesh instances of despawned entities, to be removed from the renderer.
+    despawned: Vec<(MeshBundle, NodeIdx)>,
+    /// Mesh instances of the entities restored by the journal, to be added
+    /// to the renderer.
+    respawned: Vec<(MeshBundle, NodeIdx)>,
+    /// Mesh instances switched by the LOD groups, as (node, previous mesh,
+    /// new mesh), to be updated in the renderer.
+    lod_switches: Vec<(NodeIdx, MeshBundle, MeshBundle)>,
+    /// Mesh bundles referenced by spawned entities (`true`) or no longer
+    /// referenced by despawned ones (`false`), in order, to update the
+    /// reference counts of the renderer.
+    mesh_refs: Vec<(MeshBundle, bool)>,
+    /// Custom shaders assigned since the last call to `take_custom_shaders`.
+    custom_shaders: Vec<PathBuf>,
+    /// Aspect ratio of the window the cameras render to.
+    viewport_aspect: f32,
+    /// Manipulator gizmo of the selected entity, if any.
+    pub(crate) gizmo: Option<Gizmo>,
+    /// Rigid body simulation of the entities.
+    #[cfg(feature = "physics")]
+    pub(crate) physics: PhysicsWorld,
+    /// Colliders waiting for the mesh of their entity to be shaped.
+    #[cfg(feature = "physics")]
+    pending_colliders: Vec<(Entity, ColliderShape)>,
+    /// Changes of the entities recorded to undo them.
+    journal: Journal,
     /// Command sender for sending commands to the scene.
     cmd_sender: CommandSender,
     /// Command receiver serves as a buffer for commands to be executed.
@@ -187,11 +855,50 @@ impl Scene {
         Self {
             world: World::default(),
             nodes: Nodes::default(),
+            names: BTreeMap::new(),
+            tags: FxHashMap::default(),
+            despawned: Vec::new(),
+            respawned: Vec::new(),
+            lod_switches: Vec::new(),
+            mesh_refs: Vec::new(),
+            custom_shaders: Vec::new(),
+            viewport_aspect: 1.0,
+            gizmo: None,
+            #[cfg(feature = "physics")]
+            physics: PhysicsWorld::new(),
+            #[cfg(feature = "physics")]
+            pending_colliders: Vec::new(),
+            journal: Journal::default(),
             cmd_sender: sender,
             cmd_receiver: receiver,
         }
     }
 
+    /// Removes all the entities of the scene and drops the pending
+    /// commands.
+    pub fn clear(&mut self) {
+        self.world.clear();
+        self.nodes = Nodes::default();
+        self.names.clear();
+        self.tags.clear();
+        self.despawned.clear();
+        self.respawned.clear();
+        self.lod_switches.clear();
+        self.mesh_refs.clear();
+        self.custom_shaders.clear();
+        self.gizmo = None;
+        // The references of the removed entities kept by the journal are
+        // dropped with the others.
+        self.journal.clear();
+        self.journal.released.clear();
+        #[cfg(feature = "physics")]
+        {
+            self.physics = PhysicsWorld::new();
+            self.pending_colliders.clear();
+        }
+        while self.cmd_receiver.try_recv().is_ok() {}
+    }
+
     /// Returns the command sender.
     pub fn cmd_sender(&self) -> &CommandSender {
         &self.cmd_sender
@@ -214,6 +921,9 @@ impl Scene {
     /// * `parent` - The parent node of the new node.
     /// * `components` - The components to add to the entity.
     /// * `instanced` - Whether the entity should be instanced.
+    ///
+    /// The spawning is recorded by the journal, if enabled, once the
+    /// commands sent before are applied.
     pub fn spawn<T>(&mut self, parent: NodeIdx, components: T) -> Entity
     where
         Option<T>: IntoComponentSource,
@@ -231,11 +941,308 @@ impl Scene {
         let node_id = self.nodes.push(Node::new(Some(parent)));
 
         // Add the node ID as a component to the entity.
-        self.world.entry(entity).unwrap().add_component(node_id);
+        let mut entry = self.world.entry(entity).unwrap();
+        entry.add_component(node_id);
+        let lod = entry.get_component::<LodGroup>().ok();
+        let mesh = entry.get_component::<MeshBundle>().ok();
+        for mesh in Self::referenced_meshes(lod, mesh) {
+            self.mesh_refs.push((mesh, true));
+        }
 
-        Entity {
+        let entity = Entity {
             raw: entity,
             node: node_id,
+        };
+        if self.journal.is_enabled() {
+            let _ = self.cmd_sender.send(Command::Spawned { entity });
+        }
+        entity
+    }
+
+    /// Marks the entity as spawned by the application for its own use, see
+    /// `Transient`.
+    pub fn mark_transient(&mut self, entity: Entity) {
+        if let Some(mut entry) = self.world.entry(entity.raw) {
+            entry.add_component(Transient);
+        }
+    }
+
+    /// Sets the name of the entity, replacing the previous one if any.
+    pub fn set_name(&mut self, entity: Entity, name: SmlString) {
+        let mut entry = match self.world.entry(entity.raw) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        // Remove the entity from the index under its previous name.
+        if let Ok(prev) = entry.get_component::<Name>() {
+            if let Some(prev) = &prev.label {
+                Self::unindex_name(&mut self.names, prev, entity);
+            }
+        }
+
+        entry.add_component(Name {
+            label: Some(name.clone()),
+            inner: (),
+        });
+        self.names.entry(name).or_default().push(entity);
+    }
+
+    /// Returns the name of the entity if it has one.
+    pub fn name(&self, entity: Entity) -> Option<&str> {
+        self.world.entry_ref(entity.raw).ok().and_then(|entry| {
+            entry
+                .into_component::<Name>()
+                .ok()
+                .and_then(|name| name.label.as_deref())
+        })
+    }
+
+    /// Returns the first entity spawned with the given name.
+    pub fn find_entity(&self, name: &str) -> Option<Entity> {
+        self.names
+            .get(name)
+            .and_then(|entities| entities.first().copied())
+    }
+
+    /// Returns all entities whose name starts with the given prefix, ordered
+    /// by name.
+    pub fn find_entities<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = Entity> + 'a {
+        self.names
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(name, _)| name.starts_with(prefix))
+            .flat_map(|(_, entities)| entities.iter().copied())
+    }
+
+    /// Adds a tag to the entity.
+    pub fn add_tag(&mut self, entity: Entity, tag: SmlString) {
+        let mut entry = match self.world.entry(entity.raw) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let inserted = match entry.get_component_mut::<Tags>() {
+            Ok(tags) => tags.0.insert(tag.clone()),
+            Err(_) => {
+                let mut tags = Tags::default();
+                tags.0.insert(tag.clone());
+                entry.add_component(tags);
+                true
+            }
+        };
+
+        if inserted {
+            self.tags.entry(tag).or_default().push(entity);
+        }
+    }
+
+    /// Removes a tag from the entity.
+    pub fn remove_tag(&mut self, entity: Entity, tag: &str) {
+        let mut entry = match self.world.entry(entity.raw) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        if let Ok(tags) = entry.get_component_mut::<Tags>() {
+            if tags.0.remove(tag) {
+                Self::unindex(&mut self.tags, tag, entity);
+            }
+        }
+    }
+
+    /// Returns all entities with the given tag.
+    pub fn entities_with_tag(&self, tag: &str) -> &[Entity] {
+        self.tags.get(tag).map(|e| e.as_slice()).unwrap_or(&[])
+    }
+
+    /// Removes the entity and all its descendants from the scene.
+    ///
+    /// The nodes of the removed entities stay in the scene graph but are
+    /// deactivated and hidden, so that the indices of the other nodes remain
+    /// valid. Returns the removed entities.
+    pub fn despawn(&mut self, entity: Entity) -> Vec<Entity> {
+        if !self.world.contains(entity.raw) {
+            return Vec::new();
+        }
+
+        let (removed_nodes, removed) = self.subtree(entity);
+
+        for entity in &removed {
+            if let Some(entry) = self.world.entry(entity.raw) {
+                if let Ok(name) = entry.get_component::<Name>() {
+                    if let Some(name) = &name.label {
+                        Self::unindex_name(&mut self.names, name, *entity);
+                    }
+                }
+                if let Ok(tags) = entry.get_component::<Tags>() {
+                    for tag in tags.0.iter() {
+                        Self::unindex(&mut self.tags, tag, *entity);
+                    }
+                }
+                if let Ok(mesh) = entry.get_component::<MeshBundle>() {
+                    // The instance of an entity restored by the journal and
+                    // removed again before the renderer is updated.
+                    let instance = (*mesh, entity.node);
+                    match self.respawned.iter().position(|r| *r == instance) {
+                        Some(i) => {
+                            self.respawned.swap_remove(i);
+                        }
+                        None => self.despawned.push(instance),
+                    }
+                }
+                let lod = entry.get_component::<LodGroup>().ok();
+                let mesh = entry.get_component::<MeshBundle>().ok();
+                for mesh in Self::referenced_meshes(lod, mesh) {
+                    self.mesh_refs.push((mesh, false));
+                }
+            }
+            #[cfg(feature = "physics")]
+            self.physics.remove_entity(*entity);
+            self.world.remove(entity.raw);
+        }
+
+        for node in removed_nodes {
+            let node = &mut self.nodes[node];
+            node.set_active(false);
+            node.set_visible(false);
+            node.set_cast_shadows(false);
+        }
+        if self
+            .gizmo
+            .is_some_and(|gizmo| removed.iter().any(|e| e.raw == gizmo.target.raw))
+        {
+            self.gizmo = None;
+        }
+
+        removed
+    }
+
+    /// Returns the nodes of the entity and its descendants, with their
+    /// entities.
+    fn subtree(&self, entity: Entity) -> (FxHashSet<NodeIdx>, Vec<Entity>) {
+        // Children are always pushed after their parent, so a single pass
+        // over the following nodes is enough to collect all descendants.
+        let mut nodes = FxHashSet::default();
+        nodes.insert(entity.node);
+        for (idx, node) in self.nodes.iter().enumerate().skip(entity.node.0 + 1) {
+            if node.parent.is_some_and(|p| nodes.contains(&p)) {
+                nodes.insert(NodeIdx(idx));
+            }
+        }
+
+        let entities = <(legion::Entity, &NodeIdx)>::query()
+            .iter(&self.world)
+            .filter(|(_, node)| nodes.contains(node))
+            .map(|(raw, node)| Entity {
+                raw: *raw,
+                node: *node,
+            })
+            .collect::<Vec<_>>();
+        (nodes, entities)
+    }
+
+    /// Takes the colliders added since the last call, to be shaped from the
+    /// meshes of their entities.
+    #[cfg(feature = "physics")]
+    pub fn take_pending_colliders(&mut self) -> Vec<(Entity, ColliderShape)> {
+        std::mem::take(&mut self.pending_colliders)
+    }
+
+    /// Attaches a collider of the given shape to the entity, placed at
+    /// `offset` in its space.
+    #[cfg(feature = "physics")]
+    pub fn add_collider(&mut self, entity: Entity, shape: SharedShape, offset: Vec3) {
+        let world = self.nodes.world(entity.node);
+        let handle = self.physics.add_collider(entity, &world, shape, offset);
+        if let Some(mut entry) = self.world.entry(entity.raw) {
+            match entry.get_component_mut::<Collider>() {
+                Ok(collider) => collider.handles.push(handle),
+                Err(_) => entry.add_component(Collider {
+                    handles: vec![handle],
+                }),
+            }
+        }
+    }
+
+    /// Advances the physics simulation by `dt` seconds, moving the nodes of
+    /// the rigid bodies. Returns the collisions which started or stopped.
+    #[cfg(feature = "physics")]
+    pub fn step_physics(&mut self, dt: f32) -> Vec<Collision> {
+        if self.physics.is_empty() {
+            return Vec::new();
+        }
+        self.physics.step(dt, &mut self.nodes)
+    }
+
+    /// Takes the custom shaders assigned to entities since the last call.
+    pub fn take_custom_shaders(&mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.custom_shaders)
+    }
+
+    /// Takes the mesh instances of the entities despawned since the last call.
+    pub fn take_despawned(&mut self) -> Vec<(MeshBundle, NodeIdx)> {
+        std::mem::take(&mut self.despawned)
+    }
+
+    /// Takes the mesh instances of the entities restored by undoing or
+    /// redoing since the last call.
+    pub fn take_respawned(&mut self) -> Vec<(MeshBundle, NodeIdx)> {
+        std::mem::take(&mut self.respawned)
+    }
+
+    /// Takes the mesh instances switched by the LOD groups since the last
+    /// call, as (node, previous mesh, new mesh).
+    pub fn take_lod_switches(&mut self) -> Vec<(NodeIdx, MeshBundle, MeshBundle)> {
+        std::mem::take(&mut self.lod_switches)
+    }
+
+    /// Takes the mesh bundles referenced (`true`) or no longer referenced
+    /// (`false`) by the entities spawned or despawned since the last call.
+    pub fn take_mesh_refs(&mut self) -> Vec<(MeshBundle, bool)> {
+        std::mem::take(&mut self.mesh_refs)
+    }
+
+    /// Returns the mesh bundles the entity keeps alive: its mesh and the
+    /// meshes of all the levels of its LOD group, each once.
+    pub fn meshes_of(&self, entity: Entity) -> Vec<MeshBundle> {
+        match self.world.entry_ref(entity.raw) {
+            Ok(entry) => Self::referenced_meshes(
+                entry.get_component::<LodGroup>().ok(),
+                entry.get_component::<MeshBundle>().ok(),
+            ),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Returns the meshes of the levels of the LOD group and the mesh of an
+    /// entity, each once.
+    fn referenced_meshes(lod: Option<&LodGroup>, mesh: Option<&MeshBundle>) -> Vec<MeshBundle> {
+        let mut meshes = lod
+            .map(|lod| lod.levels().iter().map(|level| level.mesh).collect())
+            .unwrap_or_else(Vec::new);
+        meshes.extend(mesh.copied());
+        meshes.sort_unstable();
+        meshes.dedup();
+        meshes
+    }
+
+    /// Removes the entity from the index under the given key.
+    fn unindex(index: &mut FxHashMap<SmlString, Vec<Entity>>, key: &str, entity: Entity) {
+        if let Some(entities) = index.get_mut(key) {
+            entities.retain(|e| e.raw != entity.raw);
+            if entities.is_empty() {
+                index.remove(key);
+            }
+        }
+    }
+
+    /// Removes the entity from the name index under the given name.
+    fn unindex_name(index: &mut BTreeMap<SmlString, Vec<Entity>>, name: &str, entity: Entity) {
+        if let Some(entities) = index.get_mut(name) {
+            entities.retain(|e| e.raw != entity.raw);
+            if entities.is_empty() {
+                index.remove(name);
+            }
         }
     }
 
@@ -245,9 +1252,32 @@ impl Scene {
         query.iter(&self.world).next().is_some()
     }
 
+    /// Returns the main camera of the scene, or the first camera if none is
+    /// set as the main camera.
+    pub fn main_camera(&self) -> Option<Entity> {
+        let mut query = <(legion::Entity, &Camera, &NodeIdx)>::query();
+        let cameras = query
+            .iter(&self.world)
+            .map(|(raw, camera, node)| {
+                let entity = Entity {
+                    raw: *raw,
+                    node: *node,
+                };
+                (camera.is_main, entity)
+            })
+            .collect::<Vec<_>>();
+        cameras
+            .iter()
+            .find(|(is_main, _)| *is_main)
+            .or(cameras.first())
+            .map(|(_, entity)| *entity)
+    }
+
     /// Processes all commands in the command receiver.
     pub fn prepare(&mut self, main_camera: &mut Option<Entity>) {
         while let Ok(cmd) = self.cmd_receiver.try_recv() {
+            let changes = self.snapshot_changes(&cmd);
+            self.journal_transaction(&cmd);
             match cmd {
                 Command::Translate {
                     entity,
@@ -300,6 +1330,48 @@ impl Scene {
                         }
                     }
                 }
+                Command::SetPosition {
+                    entity,
+                    position,
+                    world,
+                } => {
+                    let position = match self.nodes[entity.node].parent {
+                        Some(parent) if world => self
+                            .nodes
+                            .inverse_world(parent)
+                            .to_mat4()
+                            .transform_point3(position),
+                        _ => position,
+                    };
+                    self.nodes[entity.node].transform_mut().translation = position;
+                }
+                Command::SetRotation {
+                    entity,
+                    rotation,
+                    world,
+                } => {
+                    let rotation = match self.nodes[entity.node].parent {
+                        Some(parent) if world => {
+                            self.nodes.world(parent).rotation.inverse() * rotation
+                        }
+                        _ => rotation,
+                    };
+                    self.nodes[entity.node].transform_mut().rotation = rotation;
+                }
+                Command::LookAt { entity, target, up } => {
+                    self.look_at(entity.node, target, up);
+                }
+                Command::SetBillboard { entity, billboard } => {
+                    if let Some(mut entry) = self.world.entry(entity.raw) {
+                        match billboard {
+                            Some(billboard) => entry.add_component(billboard),
+                            None => entry.remove_component::<Billboard>(),
+                        }
+                    }
+                }
+                Command::SetScale { entity, scale } => {
+                    self.nodes[entity.node].transform_mut().scale = scale;
+                }
                 Command::SetActive { entity, active } => {
                     self.nodes[entity.node].set_active(active);
                 }
@@ -356,6 +1428,153 @@ impl Scene {
                     let node = &mut self.nodes[entity.node];
                     node.material_override = None;
                 }
+                Command::SetName { entity, name } => {
+                    self.set_name(entity, name);
+                }
+                Command::AddTag { entity, tag } => {
+                    self.add_tag(entity, tag);
+                }
+                Command::RemoveTag { entity, tag } => {
+                    self.remove_tag(entity, &tag);
+                }
+                Command::AttachScript { entity, script } => {
+                    self.attach_script(entity, script);
+                }
+                Command::DetachScript { entity, script } => {
+                    self.detach_script(entity, &script);
+                }
+                Command::SetVisibleByTag { tag, visible } => {
+                    if let Some(entities) = self.tags.get(&tag) {
+                        for entity in entities {
+                            self.nodes[entity.node].set_visible(visible);
+                        }
+                    }
+                }
+                Command::SetRenderLayer { entity, layer } => {
+                    if layer >= RenderLayer::COUNT {
+                        log::warn!(
+                            "Render layer {} out of range, must be less than {}",
+                            layer,
+                            RenderLayer::COUNT
+                        );
+                        continue;
+                    }
+                    if let Some(mut entry) = self.world.entry(entity.raw) {
+                        entry.add_component(RenderLayer(layer));
+                    }
+                }
+                Command::SetCustomShader { entity, path } => {
+                    if let Some(mut entry) = self.world.entry(entity.raw) {
+                        match path {
+                            Some(path) => {
+                                self.custom_shaders.push(path.clone());
+                                entry.add_component(CustomShader(path));
+                            }
+                            None => entry.remove_component::<CustomShader>(),
+                        }
+                    }
+                }
+                Command::SetLayerMask { entity, mask } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            camera.layer_mask = mask;
+                        }
+                    }
+                }
+                Command::SetBackground { entity, color } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            camera.background = color;
+                        }
+                    }
+                }
+                Command::SetFixedAspect { entity, aspect } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            camera.fixed_aspect = aspect.filter(|aspect| *aspect > 0.0);
+                        }
+                    }
+                }
+                Command::SetCameraParams {
+                    entity,
+                    fov,
+                    near,
+                    far,
+                } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            if let Some(fov) = fov {
+                                camera.proj.set_fov(fov);
+                            }
+                            camera.proj.min_depth = near.unwrap_or(camera.proj.min_depth);
+                            camera.proj.max_depth = far.unwrap_or(camera.proj.max_depth);
+                        }
+                    }
+                }
+                Command::ResizeViewport { width, height } => {
+                    if width > 0 && height > 0 {
+                        self.viewport_aspect = width as f32 / height as f32;
+                    }
+                }
+                #[cfg(feature = "physics")]
+                Command::AddRigidBody { entity, mass } => {
+                    if let Some(mut entry) = self.world.entry(entity.raw) {
+                        let world = self.nodes.world(entity.node);
+                        let handle = self.physics.add_rigid_body(entity, &world, mass);
+                        entry.add_component(RigidBody { handle });
+                    }
+                }
+                #[cfg(feature = "physics")]
+                Command::AddCollider { entity, shape } => {
+                    if self.world.contains(entity.raw) {
+                        self.pending_colliders.push((entity, shape));
+                    }
+                }
+                #[cfg(feature = "physics")]
+                Command::SetGravity(gravity) => {
+                    self.physics.set_gravity(gravity);
+                }
+                Command::SetBackdrop { entity, backdrop } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            camera.backdrop = backdrop;
+                        }
+                    }
+                }
+                Command::SetExposure { entity, ev } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            camera.exposure = ev;
+                        }
+                    }
+                }
+                Command::SetAutoExposure { entity, auto } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            camera.auto_exposure = auto;
+                        }
+                    }
+                }
+                Command::SetSkySun { entity, sun } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(camera) = entry.get_component_mut::<Camera>() {
+                            if let Some(Backdrop::Sky(sky)) = camera.backdrop.as_mut() {
+                                sky.sun = sun;
+                            }
+                        }
+                    }
+                }
+                Command::Despawn { entity } => {
+                    let removed = self.despawn(entity);
+                    Self::clear_main_camera(main_camera, &removed);
+                }
+                Command::DespawnByTag { tag } => {
+                    let entities = self.tags.get(&tag).cloned().unwrap_or_default();
+                    for entity in entities {
+                        let removed = self.despawn(entity);
+                        Self::clear_main_camera(main_camera, &removed);
+                    }
+                }
                 Command::SetAsMainCamera { entity } => {
                     // Check if the entity has a camera component.
                     let is_camera_node = self.world.entry(entity.raw).is_some();
@@ -387,8 +1606,112 @@ impl Scene {
                         }
                     }
                 }
+                Command::SetLightColor { entity, color } => {
+                    if let Ok(mut entry) = self.world.entry_mut(entity.raw) {
+                        if let Ok(light) = entry.get_component_mut::<Light>() {
+                            match light {
+                                Light::Directional { color: c, .. } | Light::Point { color: c } => {
+                                    *c = color;
+                                }
+                            }
+                        }
+                    }
+                }
                 _ => {}
             }
+            self.record_changes(changes);
+        }
+        self.release_journal_meshes();
+
+        // The main camera may belong to another scene after switching scenes.
+        if main_camera.is_some_and(|camera| self.world.entry_ref(camera.raw).is_err()) {
+            *main_camera = self.main_camera();
+        }
+
+        self.update_camera_aspects();
+        self.update_billboards(*main_camera);
+        self.update_lods(*main_camera);
+    }
+
+    /// Updates the aspect ratio of the cameras following the window.
+    fn update_camera_aspects(&mut self) {
+        for camera in <&mut Camera>::query().iter_mut(&mut self.world) {
+            camera.aspect = camera.fixed_aspect.unwrap_or(self.viewport_aspect);
+        }
+    }
+
+    /// Rotates the node so that its -Z axis points to the target position
+    /// given in world space.
+    pub fn look_at(&mut self, node: NodeIdx, target: Vec3, up: Vec3) {
+        let world = self.nodes.world(node);
+        let forward = target - world.translation;
+        if forward.length_squared() < f32::EPSILON {
+            return;
+        }
+        // Avoid degenerate rotation when looking along the up vector.
+        let up = if forward.normalize().cross(up.normalize()).length_squared() < 1e-6 {
+            up.normalize().any_orthonormal_vector()
+        } else {
+            up
+        };
+        let mut transform = Transform::from_translation(world.translation);
+        transform.looking_at(target, up);
+        let rotation = match self.nodes[node].parent {
+            Some(parent) => self.nodes.world(parent).rotation.inverse() * transform.rotation,
+            None => transform.rotation,
+        };
+        self.nodes[node].transform_mut().rotation = rotation;
+    }
+
+    /// Orients all billboards towards the main camera.
+    fn update_billboards(&mut self, main_camera: Option<Entity>) {
+        let camera = match main_camera {
+            Some(camera) => self.nodes.world(camera.node),
+            None => return,
+        };
+        let billboards = <(&Billboard, &NodeIdx)>::query()
+            .iter(&self.world)
+            .map(|(billboard, node)| (*billboard, *node))
+            .collect::<Vec<_>>();
+        for (billboard, node) in billboards {
+            let position = self.nodes.world(node).translation;
+            // The -Z axis points away from the camera, so that +Z faces it.
+            let (away, up) = match billboard {
+                Billboard::Spherical => (position - camera.translation, camera.rotation * Vec3::Y),
+                Billboard::Cylindrical => {
+                    let mut away = position - camera.translation;
+                    away.y = 0.0;
+                    (away, Vec3::Y)
+                }
+            };
+            self.look_at(node, position + away, up);
+        }
+    }
+
+    /// Selects the level of detail of the LOD groups from their distance to
+    /// the main camera.
+    fn update_lods(&mut self, main_camera: Option<Entity>) {
+        let camera = match main_camera {
+            Some(camera) => self.nodes.world(camera.node).translation,
+            None => return,
+        };
+        let nodes = &self.nodes;
+        let switches = &mut self.lod_switches;
+        for (lod, mesh, node) in
+            <(&mut LodGroup, &mut MeshBundle, &NodeIdx)>::query().iter_mut(&mut self.world)
+        {
+            let distance = nodes.world(*node).translation.distance(camera);
+            if let Some((previous, current)) = lod.select(distance) {
+                *mesh = current;
+                switches.push((*node, previous, current));
+            }
+        }
+    }
+
+    /// Resets the main camera if it is among the removed entities.
+    fn clear_main_camera(main_camera: &mut Option<Entity>, removed: &[Entity]) {
+        if main_camera.is_some_and(|camera| removed.iter().any(|e| e.raw == camera.raw)) {
+            *main_camera = None;
         }
     }
 
@@ -429,6 +1752,81 @@ mod tests {
         assert_eq!(scene.nodes[NodeIdx(4)].parent, Some(NodeIdx::root()));
     }
 
+    #[test]
+    fn entity_naming() {
+        use super::NodeIdx;
+        use crate::core::SmlString;
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut scene = super::Scene::new(sender, receiver);
+        let a = scene.spawn(NodeIdx::root(), ());
+        let b = scene.spawn(NodeIdx::root(), ());
+        let c = scene.spawn(NodeIdx::root(), ());
+        scene.set_name(a, SmlString::from("house_0"));
+        scene.set_name(b, SmlString::from("house_1"));
+        scene.set_name(c, SmlString::from("tree"));
+
+        assert_eq!(scene.name(a), Some("house_0"));
+        assert_eq!(scene.find_entity("tree").unwrap().raw, c.raw);
+        assert_eq!(scene.find_entities("house").count(), 2);
+
+        // Renaming removes the entity from its previous name.
+        scene.set_name(b, SmlString::from("shed"));
+        assert!(scene.find_entity("house_1").is_none());
+        assert_eq!(scene.find_entities("house").count(), 1);
+        assert_eq!(scene.find_entity("shed").unwrap().raw, b.raw);
+    }
+
+    #[test]
+    fn entity_tagging_and_despawning() {
+        use super::NodeIdx;
+        use crate::core::SmlString;
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut scene = super::Scene::new(sender, receiver);
+        let a = scene.spawn(NodeIdx::root(), ());
+        let b = scene.spawn(a.node, ());
+        let c = scene.spawn(NodeIdx::root(), ());
+        scene.add_tag(a, SmlString::from("tree"));
+        scene.add_tag(a, SmlString::from("tree"));
+        scene.add_tag(b, SmlString::from("tree"));
+        scene.add_tag(c, SmlString::from("lamp"));
+        scene.set_name(b, SmlString::from("leaf"));
+        assert_eq!(scene.entities_with_tag("tree").len(), 2);
+
+        scene.remove_tag(b, "tree");
+        assert_eq!(scene.entities_with_tag("tree").len(), 1);
+
+        // Despawning a parent also removes its children.
+        let removed = scene.despawn(a);
+        assert_eq!(removed.len(), 2);
+        assert!(scene.entities_with_tag("tree").is_empty());
+        assert!(scene.find_entity("leaf").is_none());
+        assert!(!scene.nodes[b.node].is_visible());
+        assert_eq!(scene.entities_with_tag("lamp").len(), 1);
+    }
+
+    #[test]
+    fn entity_look_at() {
+        use super::NodeIdx;
+        use glam::{Quat, Vec3};
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut scene = super::Scene::new(sender, receiver);
+        let parent = scene.spawn(NodeIdx::root(), ());
+        let child = scene.spawn(parent.node, ());
+        scene.nodes[parent.node].transform_mut().rotation =
+            Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        scene.nodes[child.node].transform_mut().translation = Vec3::new(1.0, 0.0, 0.0);
+
+        // The child is at (0, 0, -1) in world space, looking at the origin.
+        scene.look_at(child.node, Vec3::ZERO, Vec3::Y);
+        let world = scene.nodes.world(child.node);
+        let forward = world.rotation * Vec3::NEG_Z;
+        assert!((world.translation - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-5);
+        assert!((forward - Vec3::Z).length() < 1e-5);
+    }
+
     #[test]
     #[should_panic]
     fn entity_spawning_failed() {
@@ -1,5 +1,7 @@
 pub use crate::core::Transform;
 
+use crate::core::Color;
+
 use std::ops::{Deref, DerefMut, Index, IndexMut};
 
 /// A node in the scene graph.
@@ -18,6 +20,8 @@ pub struct Node {
     /// Material override. If set, this material will be used instead of the
     /// material set by the submesh.
     pub(crate) material_override: Option<u32>,
+    /// Color mixed over the shaded color by its alpha, for overlays.
+    pub(crate) tint: Option<Color>,
 }
 
 impl Node {
@@ -28,6 +32,7 @@ impl Node {
             active: true,
             visible: false,
             material_override: None,
+            tint: None,
             cast_shadows: true,
         }
     }
@@ -40,6 +45,7 @@ impl Node {
             active: true,
             visible: false,
             material_override: None,
+            tint: None,
             cast_shadows: false,
         }
     }
@@ -94,7 +100,7 @@ impl Node {
 /// the scene graph, it is the index of the node in the nodes array. The root
 /// node has the ID `0`, see [`NodeIdx::root`].
 #[repr(transparent)]
-#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq, Ord, PartialOrd)]
 pub struct NodeIdx(pub(crate) usize);
 
 impl NodeIdx {
@@ -0,0 +1,199 @@
+use crate::{
+    core::{
+        mesh::{LodGroup, MeshBundle},
+        FxHashMap, FxHashSet, Light, SmlString,
+    },
+    scene::{Entity, Name, Node, NodeIdx, Scene, Tags, Transform},
+};
+use legion::IntoQuery;
+
+/// Template of an entity hierarchy captured from a scene, see
+/// `App.capture_prefab`.
+///
+/// The meshes and materials of the entities are shared by all the instances
+/// of the prefab, only the nodes and the components are duplicated.
+#[pyo3::pyclass]
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    /// Entities of the hierarchy, each one after its parent, the first one
+    /// being the root.
+    entities: Vec<PrefabEntity>,
+}
+
+/// Entity of a prefab with the components duplicated by its instances.
+#[derive(Clone, Debug)]
+struct PrefabEntity {
+    /// Index of the parent entity in the prefab, `None` for the root.
+    parent: Option<usize>,
+    /// Node with the local transform, the visibility, the material override
+    /// and the tint of the entity.
+    node: Node,
+    mesh: Option<MeshBundle>,
+    lod: Option<LodGroup>,
+    light: Option<Light>,
+    name: Option<SmlString>,
+    tags: Vec<SmlString>,
+}
+
+#[pyo3::pymethods]
+impl Prefab {
+    /// Number of entities instantiated by the prefab.
+    fn __len__(&self) -> usize {
+        self.entities.len()
+    }
+
+    fn __repr__(&self) -> String {
+        match self.entities[0].name.as_deref() {
+            Some(name) => format!("Prefab('{}', entities={})", name, self.entities.len()),
+            None => format!("Prefab(entities={})", self.entities.len()),
+        }
+    }
+}
+
+impl Prefab {
+    /// Returns the meshes of the entities of the prefab and of their levels
+    /// of detail, each once.
+    pub fn meshes(&self) -> Vec<MeshBundle> {
+        let mut meshes = self
+            .entities
+            .iter()
+            .flat_map(|entity| Scene::referenced_meshes(entity.lod.as_ref(), entity.mesh.as_ref()))
+            .collect::<Vec<_>>();
+        meshes.sort_unstable();
+        meshes.dedup();
+        meshes
+    }
+}
+
+impl Scene {
+    /// Captures the entity and its descendants into a prefab. Returns `None`
+    /// if the entity doesn't exist.
+    pub fn capture_prefab(&self, root: Entity) -> Option<Prefab> {
+        if !self.world.contains(root.raw) {
+            return None;
+        }
+
+        // Children are always pushed after their parent, so a single pass
+        // over the following nodes is enough to collect all descendants.
+        let mut subtree = FxHashSet::default();
+        subtree.insert(root.node);
+        for (idx, node) in self.nodes.iter().enumerate().skip(root.node.0 + 1) {
+            if node.parent.is_some_and(|p| subtree.contains(&p)) {
+                subtree.insert(NodeIdx(idx));
+            }
+        }
+        let mut entities = <(legion::Entity, &NodeIdx)>::query()
+            .iter(&self.world)
+            .filter(|(_, node)| subtree.contains(node))
+            .map(|(raw, node)| (*node, *raw))
+            .collect::<Vec<_>>();
+        entities.sort_unstable_by_key(|(node, _)| *node);
+
+        let mut indices = FxHashMap::default();
+        let mut captured = Vec::with_capacity(entities.len());
+        for (node_idx, raw) in entities {
+            let entry = self.world.entry_ref(raw).unwrap();
+            let node = &self.nodes[node_idx];
+            let parent = if node_idx == root.node {
+                None
+            } else {
+                node.parent.and_then(|p| indices.get(&p).copied())
+            };
+            indices.insert(node_idx, captured.len());
+            captured.push(PrefabEntity {
+                parent,
+                node: node.clone(),
+                mesh: entry.get_component::<MeshBundle>().ok().copied(),
+                lod: entry.get_component::<LodGroup>().ok().cloned(),
+                light: entry.get_component::<Light>().ok().copied(),
+                name: entry
+                    .get_component::<Name>()
+                    .ok()
+                    .and_then(|name| name.label.clone()),
+                tags: entry
+                    .get_component::<Tags>()
+                    .map(|tags| tags.0.iter().cloned().collect())
+                    .unwrap_or_default(),
+            });
+        }
+        Some(Prefab { entities: captured })
+    }
+
+    /// Spawns the entities of the prefab under the parent node, the root
+    /// being placed at the given local transform, or at the captured one if
+    /// `None`.
+    ///
+    /// Returns the root entity and the instances of the meshes, to be added
+    /// to the renderer.
+    pub fn instantiate(
+        &mut self,
+        prefab: &Prefab,
+        parent: NodeIdx,
+        transform: Option<Transform>,
+    ) -> (Entity, Vec<(MeshBundle, NodeIdx)>) {
+        let mut spawned: Vec<Entity> = Vec::with_capacity(prefab.entities.len());
+        let mut instances = Vec::new();
+        for captured in &prefab.entities {
+            let parent = captured.parent.map_or(parent, |p| spawned[p].node);
+            let entity = match (captured.mesh, &captured.lod) {
+                (Some(mesh), Some(lod)) => self.spawn(parent, (mesh, lod.clone())),
+                (Some(mesh), None) => self.spawn(parent, (mesh,)),
+                (None, _) => self.spawn(parent, ()),
+            };
+            if let Some(light) = captured.light {
+                if let Some(mut entry) = self.world.entry(entity.raw) {
+                    entry.add_component(light);
+                }
+            }
+            if let Some(name) = &captured.name {
+                self.set_name(entity, name.clone());
+            }
+            for tag in &captured.tags {
+                self.add_tag(entity, tag.clone());
+            }
+            let mut node = captured.node.clone();
+            node.parent = Some(parent);
+            self.nodes[entity.node] = node;
+            if let Some(mesh) = captured.mesh {
+                instances.push((mesh, entity.node));
+            }
+            spawned.push(entity);
+        }
+        let root = spawned[0];
+        if let Some(transform) = transform {
+            self.nodes[root.node].set_transform(transform);
+        }
+        (root, instances)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::command::Command, core::Color};
+
+    #[test]
+    fn prefab_duplicates_the_hierarchy() {
+        let (sender, receiver) = crossbeam_channel::unbounded::<Command>();
+        let mut scene = Scene::new(sender, receiver);
+        let root = scene.spawn(NodeIdx::root(), ());
+        scene.set_name(root, SmlString::from("house"));
+        let light = Light::Point {
+            color: Color::WHITE,
+        };
+        let lamp = scene.spawn(root.node, (light,));
+        scene.nodes[lamp.node].transform_mut().translation = glam::Vec3::Y;
+
+        let prefab = scene.capture_prefab(root).unwrap();
+        assert_eq!(prefab.__len__(), 2);
+
+        let transform = Transform::from_translation(glam::Vec3::X);
+        let (copy, instances) = scene.instantiate(&prefab, NodeIdx::root(), Some(transform));
+        assert!(instances.is_empty());
+        assert_eq!(scene.find_entities("house").count(), 2);
+        let lamp_copy = scene.nodes.children(copy.node).collect::<Vec<_>>();
+        assert_eq!(lamp_copy.len(), 1);
+        let world = scene.nodes.world(lamp_copy[0]);
+        assert_eq!(world.translation, glam::Vec3::new(1.0, 1.0, 0.0));
+    }
+}
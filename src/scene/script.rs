@@ -0,0 +1,66 @@
+use crate::scene::{Entity, NodeIdx, Scene};
+use legion::IntoQuery;
+use pyo3::{PyObject, Python};
+
+/// Python objects attached to an entity, whose `update` method is called
+/// each frame, see `PyEntity.attach_script`.
+#[derive(Debug, Default)]
+pub struct Scripts(pub(crate) Vec<PyObject>);
+
+impl Scene {
+    /// Attaches the script to the entity after its other scripts. A script
+    /// already attached to the entity is not attached again.
+    pub fn attach_script(&mut self, entity: Entity, script: PyObject) {
+        let Some(mut entry) = self.world.entry(entity.raw) else {
+            return;
+        };
+        if let Ok(scripts) = entry.get_component_mut::<Scripts>() {
+            if !scripts.0.iter().any(|s| s.is(&script)) {
+                scripts.0.push(script);
+            }
+            return;
+        }
+        entry.add_component(Scripts(vec![script]));
+    }
+
+    /// Detaches the script from the entity.
+    pub fn detach_script(&mut self, entity: Entity, script: &PyObject) {
+        if let Some(mut entry) = self.world.entry(entity.raw) {
+            if let Ok(scripts) = entry.get_component_mut::<Scripts>() {
+                scripts.0.retain(|s| !s.is(script));
+            }
+        }
+    }
+
+    /// Returns the scripts attached to the entity.
+    pub fn scripts_of(&self, py: Python<'_>, entity: Entity) -> Vec<PyObject> {
+        self.world
+            .entry_ref(entity.raw)
+            .ok()
+            .and_then(|entry| {
+                let scripts = entry.get_component::<Scripts>().ok()?;
+                Some(scripts.0.iter().map(|s| s.clone_ref(py)).collect())
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the scripts of the active entities in the order they run:
+    /// by node, so that the scripts of a parent run before the ones of its
+    /// children, then in the order they were attached.
+    pub fn scripts(&self, py: Python<'_>) -> Vec<(Entity, PyObject)> {
+        let mut scripts = <(legion::Entity, &NodeIdx, &Scripts)>::query()
+            .iter(&self.world)
+            .filter(|(_, node, _)| self.nodes[**node].is_active())
+            .flat_map(|(raw, node, scripts)| {
+                let entity = Entity {
+                    raw: *raw,
+                    node: *node,
+                };
+                scripts.0.iter().map(move |s| (entity, s.clone_ref(py)))
+            })
+            .collect::<Vec<_>>();
+        // The sort is stable, keeping the order of the scripts of an entity.
+        scripts.sort_by_key(|(entity, _)| entity.node);
+        scripts
+    }
+}
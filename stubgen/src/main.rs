@@ -0,0 +1,652 @@
+//! Generates the Python type stubs of the `bkfw` module from the pyo3
+//! definitions of the crate.
+//!
+//! The classes and functions registered in `src/lib.rs` are looked up in the
+//! sources, and their methods, properties and documentation are written to
+//! `pypkg/bk7084/bkfw.pyi`, which maturin ships with the wheels.
+//!
+//! Run it with `cargo run -p stubgen` after changing the Python API and
+//! commit the stubs. `cargo run -p stubgen -- --check` fails if the stubs
+//! are out of date.
+
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// Path of the generated stubs, relative to the root of the repository.
+const STUBS_PATH: &str = "pypkg/bk7084/bkfw.pyi";
+
+fn main() -> ExitCode {
+    let check = std::env::args().skip(1).any(|arg| arg == "--check");
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("stubgen is in the repository")
+        .to_path_buf();
+    let stubs = match generate_stubs(&root.join("src")) {
+        Ok(stubs) => stubs,
+        Err(err) => {
+            eprintln!("error: failed to generate the Python stubs: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let path = root.join(STUBS_PATH);
+    let up_to_date = fs::read_to_string(&path).ok().as_deref() == Some(stubs.as_str());
+    if check {
+        if !up_to_date {
+            eprintln!(
+                "error: {} is out of date, run `cargo run -p stubgen`",
+                path.display()
+            );
+            return ExitCode::FAILURE;
+        }
+    } else if !up_to_date {
+        // Only write the stubs if they changed, to keep their timestamp.
+        if let Err(err) = fs::write(&path, stubs) {
+            eprintln!("error: failed to write {}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+        println!("Stubs written to {}", path.display());
+    }
+    ExitCode::SUCCESS
+}
+
+/// A class exposed to Python.
+#[derive(Default)]
+struct Class {
+    /// Name of the class in Python.
+    name: String,
+    doc: String,
+    /// Variants of the enum, with their documentation.
+    variants: Vec<(String, String)>,
+    /// Fields exposed with `#[pyo3(get)]`, as (name, type, settable, doc).
+    fields: Vec<(String, syn::Type, bool, String)>,
+    /// Items of the `#[pymethods]` blocks, in the order of the sources.
+    methods: Vec<syn::ImplItem>,
+}
+
+/// Definitions found in the sources.
+#[derive(Default)]
+struct Definitions {
+    /// Classes by the name of their Rust type.
+    classes: HashMap<String, Class>,
+    /// Functions by their Rust name.
+    functions: HashMap<String, syn::ItemFn>,
+}
+
+fn generate_stubs(src: &Path) -> Result<String, String> {
+    let mut files = Vec::new();
+    collect_sources(src, &mut files).map_err(|err| err.to_string())?;
+    files.sort();
+
+    let mut defs = Definitions::default();
+    for file in &files {
+        let source = fs::read_to_string(file).map_err(|err| err.to_string())?;
+        match syn::parse_file(&source) {
+            Ok(ast) => collect_items(&ast.items, &mut defs),
+            Err(err) => eprintln!("warning: failed to parse {}: {}", file.display(), err),
+        }
+    }
+
+    // Registered classes and functions, in the order of the module.
+    let lib = fs::read_to_string(src.join("lib.rs")).map_err(|err| err.to_string())?;
+    let classes = registered(&lib, "add_class::<", '>');
+    let functions = registered(&lib, "wrap_pyfunction!(", ',');
+    let names = defs
+        .classes
+        .iter()
+        .map(|(ident, class)| (ident.clone(), class.name.clone()))
+        .collect::<HashMap<_, _>>();
+
+    let mut out = String::new();
+    out.push_str("# Type stubs of the bkfw module, generated by stubgen. Do not edit.\n\n");
+    out.push_str("from typing import Any, Callable, ClassVar\n\n");
+    out.push_str("import numpy as np\nimport numpy.typing as npt\n");
+    for ident in &functions {
+        if let Some(function) = defs.functions.get(ident) {
+            out.push_str("\n\n");
+            let context = Context {
+                class: "",
+                names: &names,
+            };
+            write_function(&mut out, &function.attrs, &function.sig, context, "");
+        }
+    }
+    for ident in &classes {
+        if let Some(class) = defs.classes.get(ident) {
+            out.push_str("\n\n");
+            write_class(&mut out, ident, class, &names);
+        }
+    }
+    Ok(out)
+}
+
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Returns the last segment of the paths following each occurrence of
+/// `prefix` in the source, up to `end`.
+fn registered(source: &str, prefix: &str, end: char) -> Vec<String> {
+    source
+        .match_indices(prefix)
+        .filter_map(|(i, _)| {
+            let rest = &source[i + prefix.len()..];
+            let path = &rest[..rest.find(end)?];
+            Some(path.rsplit("::").next()?.trim().to_string())
+        })
+        .collect()
+}
+
+fn collect_items(items: &[syn::Item], defs: &mut Definitions) {
+    for item in items {
+        match item {
+            syn::Item::Struct(item) if has_attr(&item.attrs, "pyclass") => {
+                let class = class_entry(defs, &item.ident, &item.attrs);
+                if let syn::Fields::Named(fields) = &item.fields {
+                    for field in &fields.named {
+                        let Some(args) = attr_args(&field.attrs, "pyo3") else {
+                            continue;
+                        };
+                        let flags = args.split(',').map(str::trim).collect::<Vec<_>>();
+                        if flags.contains(&"get") {
+                            let name = named_arg(&args, "name")
+                                .unwrap_or_else(|| field.ident.as_ref().unwrap().to_string());
+                            let settable = flags.contains(&"set");
+                            let doc = doc(&field.attrs);
+                            class.fields.push((name, field.ty.clone(), settable, doc));
+                        }
+                    }
+                }
+            }
+            syn::Item::Enum(item) if has_attr(&item.attrs, "pyclass") => {
+                let class = class_entry(defs, &item.ident, &item.attrs);
+                class.variants = item
+                    .variants
+                    .iter()
+                    .map(|variant| (variant.ident.to_string(), doc(&variant.attrs)))
+                    .collect();
+            }
+            syn::Item::Impl(item) if has_attr(&item.attrs, "pymethods") => {
+                if let syn::Type::Path(ty) = item.self_ty.as_ref() {
+                    let ident = &ty.path.segments.last().unwrap().ident;
+                    let class = defs.classes.entry(ident.to_string()).or_default();
+                    class.methods.extend(item.items.iter().cloned());
+                }
+            }
+            syn::Item::Fn(item) if has_attr(&item.attrs, "pyfunction") => {
+                defs.functions
+                    .insert(item.sig.ident.to_string(), item.clone());
+            }
+            syn::Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    collect_items(items, defs);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Returns the class of the given Rust type, filling its name and
+/// documentation.
+fn class_entry<'a>(
+    defs: &'a mut Definitions,
+    ident: &syn::Ident,
+    attrs: &[syn::Attribute],
+) -> &'a mut Class {
+    let class = defs.classes.entry(ident.to_string()).or_default();
+    class.name = attr_args(attrs, "pyclass")
+        .and_then(|args| named_arg(&args, "name"))
+        .or_else(|| attr_args(attrs, "pyo3").and_then(|args| named_arg(&args, "name")))
+        .unwrap_or_else(|| ident.to_string());
+    class.doc = doc(attrs);
+    class
+}
+
+fn write_class(out: &mut String, ident: &str, class: &Class, names: &HashMap<String, String>) {
+    let _ = writeln!(out, "class {}:", class.name);
+    let indent = "    ";
+    let mut empty = true;
+    if !class.doc.is_empty() {
+        write_doc(out, &class.doc, indent);
+        empty = false;
+    }
+    let context = Context {
+        class: ident,
+        names,
+    };
+    for (variant, doc) in &class.variants {
+        let _ = writeln!(out, "{}{}: ClassVar[{}]", indent, variant, class.name);
+        if !doc.is_empty() {
+            write_doc(out, doc, indent);
+        }
+        empty = false;
+    }
+    for (name, ty, settable, doc) in &class.fields {
+        let ty = context.py_type(ty);
+        if *settable {
+            let _ = writeln!(out, "{}{}: {}", indent, name, ty);
+            if !doc.is_empty() {
+                write_doc(out, doc, indent);
+            }
+        } else {
+            let _ = writeln!(out, "{}@property", indent);
+            let _ = writeln!(out, "{}def {}(self) -> {}:", indent, name, ty);
+            write_body(out, doc, indent);
+        }
+        empty = false;
+    }
+    for item in &class.methods {
+        match item {
+            syn::ImplItem::Fn(method) => {
+                write_function(out, &method.attrs, &method.sig, context, indent);
+                empty = false;
+            }
+            syn::ImplItem::Const(constant) if has_attr(&constant.attrs, "classattr") => {
+                let name = py_name(&constant.attrs).unwrap_or_else(|| constant.ident.to_string());
+                let ty = context.py_type(&constant.ty);
+                let _ = writeln!(out, "{}{}: ClassVar[{}]", indent, name, ty);
+                empty = false;
+            }
+            _ => {}
+        }
+    }
+    if empty {
+        let _ = writeln!(out, "{}...", indent);
+    }
+}
+
+/// Class whose methods are written, to resolve `Self`, empty for the
+/// functions of the module.
+#[derive(Clone, Copy)]
+struct Context<'a> {
+    class: &'a str,
+    names: &'a HashMap<String, String>,
+}
+
+fn write_function(
+    out: &mut String,
+    attrs: &[syn::Attribute],
+    sig: &syn::Signature,
+    context: Context,
+    indent: &str,
+) {
+    let rust_name = sig.ident.to_string();
+    let is_new = has_attr(attrs, "new");
+    let is_static = has_attr(attrs, "staticmethod");
+    let is_classmethod = has_attr(attrs, "classmethod");
+    let getter = attr_args(attrs, "getter");
+    let setter = attr_args(attrs, "setter");
+    if has_attr(attrs, "classattr") {
+        let name = py_name(attrs).unwrap_or(rust_name);
+        let ty = context.return_type(&sig.output);
+        let _ = writeln!(out, "{}{}: ClassVar[{}]", indent, name, ty);
+        return;
+    }
+
+    // Name of the function in Python.
+    let name = if is_new {
+        "__new__".to_string()
+    } else if let Some(args) = getter.as_ref().filter(|args| !args.is_empty()) {
+        args.trim().to_string()
+    } else if let Some(args) = setter.as_ref().filter(|args| !args.is_empty()) {
+        args.trim().to_string()
+    } else if let Some(name) = py_name(attrs) {
+        name
+    } else if getter.is_some() {
+        rust_name
+            .strip_prefix("get_")
+            .unwrap_or(&rust_name)
+            .to_string()
+    } else if setter.is_some() {
+        rust_name
+            .strip_prefix("set_")
+            .unwrap_or(&rust_name)
+            .to_string()
+    } else {
+        rust_name.clone()
+    };
+
+    // Parameters visible from Python, with their types.
+    let mut params = Vec::new();
+    let mut receiver = false;
+    for (i, input) in sig.inputs.iter().enumerate() {
+        match input {
+            syn::FnArg::Receiver(_) => receiver = true,
+            syn::FnArg::Typed(arg) => {
+                let ident = match arg.pat.as_ref() {
+                    syn::Pat::Ident(pat) => pat.ident.to_string(),
+                    _ => format!("arg{}", i),
+                };
+                let ty_name = last_ident(&arg.ty);
+                if ty_name.as_deref() == Some("Python") {
+                    continue;
+                }
+                // `slf: PyRef<Self>` and the class of the class methods.
+                if i == 0 && (ident == "slf" || ident == "_slf" || is_classmethod) {
+                    receiver = !is_classmethod;
+                    continue;
+                }
+                params.push((ident, context.py_type(&arg.ty)));
+            }
+        }
+    }
+
+    // Defaults and variadic parameters of the signature.
+    let signature = attr_args(attrs, "pyo3")
+        .and_then(|args| signature_params(&args))
+        .unwrap_or_default();
+    let mut rendered = Vec::new();
+    if is_new || is_classmethod {
+        rendered.push("cls".to_string());
+    } else if receiver && !is_static {
+        rendered.push("self".to_string());
+    }
+    if signature.is_empty() {
+        for (ident, ty) in &params {
+            rendered.push(format!("{}: {}", ident, ty));
+        }
+    } else {
+        for param in signature {
+            let param = param.replace(' ', "");
+            let ty_of = |ident: &str| {
+                params
+                    .iter()
+                    .find(|(name, _)| name == ident)
+                    .map_or_else(|| "Any".to_string(), |(_, ty)| ty.clone())
+            };
+            if param == "*" || param == "/" {
+                rendered.push(param);
+            } else if let Some(ident) = param.strip_prefix("**") {
+                rendered.push(format!("**{}: Any", ident));
+            } else if let Some(ident) = param.strip_prefix('*') {
+                rendered.push(format!("*{}: Any", ident));
+            } else if let Some((ident, _)) = param.split_once('=') {
+                rendered.push(format!("{}: {} = ...", ident, ty_of(ident)));
+            } else {
+                rendered.push(format!("{}: {}", param, ty_of(&param)));
+            }
+        }
+    }
+
+    let output = if is_new {
+        context.class_name()
+    } else {
+        context.return_type(&sig.output)
+    };
+    if getter.is_some() {
+        let _ = writeln!(out, "{}@property", indent);
+    } else if setter.is_some() {
+        let _ = writeln!(out, "{}@{}.setter", indent, name);
+    } else if is_static {
+        let _ = writeln!(out, "{}@staticmethod", indent);
+    } else if is_classmethod {
+        let _ = writeln!(out, "{}@classmethod", indent);
+    }
+    let _ = writeln!(
+        out,
+        "{}def {}({}) -> {}:",
+        indent,
+        name,
+        rendered.join(", "),
+        output
+    );
+    write_body(out, &doc(attrs), indent);
+}
+
+impl Context<'_> {
+    /// Returns the name of the class in Python.
+    fn class_name(&self) -> String {
+        self.names
+            .get(self.class)
+            .cloned()
+            .unwrap_or_else(|| self.class.to_string())
+    }
+
+    fn return_type(&self, output: &syn::ReturnType) -> String {
+        match output {
+            syn::ReturnType::Default => "None".to_string(),
+            syn::ReturnType::Type(_, ty) => self.py_type(ty),
+        }
+    }
+
+    /// Returns the Python type of a Rust type converted by pyo3, `Any` if
+    /// it is unknown.
+    fn py_type(&self, ty: &syn::Type) -> String {
+        match ty {
+            syn::Type::Reference(ty) => self.py_type(&ty.elem),
+            syn::Type::Paren(ty) => self.py_type(&ty.elem),
+            syn::Type::Group(ty) => self.py_type(&ty.elem),
+            syn::Type::Tuple(ty) if ty.elems.is_empty() => "None".to_string(),
+            syn::Type::Tuple(ty) => format!(
+                "tuple[{}]",
+                ty.elems
+                    .iter()
+                    .map(|ty| self.py_type(ty))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            syn::Type::Array(ty) => format!("list[{}]", self.py_type(&ty.elem)),
+            syn::Type::Slice(ty) => format!("list[{}]", self.py_type(&ty.elem)),
+            syn::Type::Path(ty) => {
+                let segment = ty.path.segments.last().unwrap();
+                let args = match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(args) => args
+                        .args
+                        .iter()
+                        .filter_map(|arg| match arg {
+                            syn::GenericArgument::Type(ty) => Some(ty),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                    _ => Vec::new(),
+                };
+                let arg = |i: usize| {
+                    args.get(i)
+                        .map_or_else(|| "Any".to_string(), |ty| self.py_type(ty))
+                };
+                let ident = segment.ident.to_string();
+                match ident.as_str() {
+                    "bool" => "bool".to_string(),
+                    "u8" | "u16" | "u32" | "u64" | "u128" | "usize" | "i8" | "i16" | "i32"
+                    | "i64" | "i128" | "isize" => "int".to_string(),
+                    "f32" | "f64" => "float".to_string(),
+                    "String" | "str" | "SmlString" | "PathBuf" | "Path" | "char" | "PyString" => {
+                        "str".to_string()
+                    }
+                    "Option" => format!("{} | None", arg(0)),
+                    "Vec" | "VecDeque" => format!("list[{}]", arg(0)),
+                    "HashMap" | "FxHashMap" | "BTreeMap" => format!("dict[{}, {}]", arg(0), arg(1)),
+                    "HashSet" | "FxHashSet" | "BTreeSet" => format!("set[{}]", arg(0)),
+                    "PyResult" | "Result" | "Py" | "PyRef" | "PyRefMut" | "Box" | "Arc" => arg(0),
+                    "PyArray" | "PyArray1" | "PyArray2" | "PyArray3" | "PyArrayDyn"
+                    | "PyReadonlyArray1" | "PyReadonlyArray2" | "PyReadonlyArrayDyn" => {
+                        let dtype = match args.first().and_then(|ty| last_ident(ty)).as_deref() {
+                            Some("f32") => "np.float32",
+                            Some("f64") => "np.float64",
+                            Some("u8") => "np.uint8",
+                            Some("u16") => "np.uint16",
+                            Some("u32") => "np.uint32",
+                            Some("u64") => "np.uint64",
+                            Some("i32") => "np.int32",
+                            Some("i64") => "np.int64",
+                            Some("bool") => "np.bool_",
+                            _ => "Any",
+                        };
+                        format!("npt.NDArray[{}]", dtype)
+                    }
+                    "PyDict" => "dict[Any, Any]".to_string(),
+                    "PyList" => "list[Any]".to_string(),
+                    "PyTuple" => "tuple[Any, ...]".to_string(),
+                    "PyType" => "type".to_string(),
+                    "PyFunction" | "PyCFunction" => "Callable[..., Any]".to_string(),
+                    "Self" => self.class_name(),
+                    _ => self
+                        .names
+                        .get(&ident)
+                        .cloned()
+                        .unwrap_or_else(|| "Any".to_string()),
+                }
+            }
+            _ => "Any".to_string(),
+        }
+    }
+}
+
+/// Writes the docstring and the ellipsis of the body of a function.
+fn write_body(out: &mut String, doc: &str, indent: &str) {
+    let indent = format!("{}    ", indent);
+    if doc.is_empty() {
+        let _ = writeln!(out, "{}...", indent);
+    } else {
+        write_doc(out, doc, &indent);
+    }
+}
+
+fn write_doc(out: &mut String, doc: &str, indent: &str) {
+    let doc = doc.replace('\\', "\\\\").replace("\"\"\"", "\\\"\\\"\\\"");
+    let mut lines = doc.lines();
+    let _ = write!(out, "{}\"\"\"{}", indent, lines.next().unwrap_or_default());
+    for line in lines {
+        if line.is_empty() {
+            out.push('\n');
+        } else {
+            let _ = write!(out, "\n{}{}", indent, line);
+        }
+    }
+    if doc.contains('\n') {
+        let _ = writeln!(out, "\n{}\"\"\"", indent);
+    } else {
+        out.push_str("\"\"\"\n");
+    }
+}
+
+/// Returns the documentation of an item.
+fn doc(attrs: &[syn::Attribute]) -> String {
+    let lines = attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc"))
+        .filter_map(|attr| match &attr.meta {
+            syn::Meta::NameValue(syn::MetaNameValue {
+                value:
+                    syn::Expr::Lit(syn::ExprLit {
+                        lit: syn::Lit::Str(doc),
+                        ..
+                    }),
+                ..
+            }) => Some(doc.value()),
+            _ => None,
+        })
+        .map(|line| {
+            line.strip_prefix(' ')
+                .unwrap_or(&line)
+                .trim_end()
+                .to_string()
+        })
+        .collect::<Vec<_>>();
+    lines.join("\n").trim().to_string()
+}
+
+/// Returns whether the attributes contain the given attribute, whatever
+/// its path prefix, e.g. `pyclass` for `#[pyo3::pyclass]`.
+fn has_attr(attrs: &[syn::Attribute], name: &str) -> bool {
+    attrs
+        .iter()
+        .any(|attr| attr.path().segments.last().is_some_and(|s| s.ident == name))
+}
+
+/// Returns the arguments of the given attribute as a string, empty if it
+/// has none. The arguments of repeated attributes are joined.
+fn attr_args(attrs: &[syn::Attribute], name: &str) -> Option<String> {
+    let args = attrs
+        .iter()
+        .filter(|attr| attr.path().segments.last().is_some_and(|s| s.ident == name))
+        .map(|attr| match &attr.meta {
+            syn::Meta::List(list) => list.tokens.to_string(),
+            _ => String::new(),
+        })
+        .collect::<Vec<_>>();
+    (!args.is_empty()).then(|| args.join(", "))
+}
+
+/// Returns the text following the `key =` argument, the key being a whole
+/// word, e.g. not the end of `text_signature` for `signature`.
+fn arg_value<'a>(args: &'a str, key: &str) -> Option<&'a str> {
+    let mut start = 0;
+    while let Some(i) = args[start..].find(key).map(|i| start + i) {
+        start = i + key.len();
+        let whole = args[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric() && c != '_');
+        let value = args[start..].trim_start().strip_prefix('=');
+        if let Some(value) = value.filter(|_| whole) {
+            return Some(value.trim_start());
+        }
+    }
+    None
+}
+
+/// Returns the string value of the `key = "value"` argument.
+fn named_arg(args: &str, key: &str) -> Option<String> {
+    let value = arg_value(args, key)?.strip_prefix('"')?;
+    value.split('"').next().map(str::to_string)
+}
+
+/// Returns the Python name set with `#[pyo3(name = "...")]`.
+fn py_name(attrs: &[syn::Attribute]) -> Option<String> {
+    attr_args(attrs, "pyo3").and_then(|args| named_arg(&args, "name"))
+}
+
+/// Returns the parameters of `signature = (...)`, split at the top-level
+/// commas.
+fn signature_params(args: &str) -> Option<Vec<String>> {
+    let value = arg_value(args, "signature")?.strip_prefix('(')?;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut params = Vec::new();
+    let mut current = String::new();
+    for c in value.chars() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string && depth == 0 => break,
+            ')' | ']' | '}' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                params.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    params.push(current);
+    Some(
+        params
+            .into_iter()
+            .map(|param| param.trim().to_string())
+            .filter(|param| !param.is_empty())
+            .collect(),
+    )
+}
+
+/// Returns the last identifier of the path of a type.
+fn last_ident(ty: &syn::Type) -> Option<String> {
+    match ty {
+        syn::Type::Path(ty) => ty.path.segments.last().map(|s| s.ident.to_string()),
+        syn::Type::Reference(ty) => last_ident(&ty.elem),
+        _ => None,
+    }
+}